@@ -197,6 +197,14 @@ export function SettingsPanelRedesignOptimized({ toggleLowerRightPaneDock, isLow
             { key: 'damping', path: 'visualisation.physics.damping', definition: settingsUIDefinition.visualisation.subsections.physics.settings.damping },
             { key: 'boundsSize', path: 'visualisation.physics.boundsSize', definition: settingsUIDefinition.visualisation.subsections.physics.settings.boundsSize },
             { key: 'collisionRadius', path: 'visualisation.physics.collisionRadius', definition: settingsUIDefinition.visualisation.subsections.physics.settings.collisionRadius },
+            { key: 'collisionStiffness', path: 'visualisation.physics.collisionStiffness', definition: settingsUIDefinition.visualisation.subsections.physics.settings.collisionStiffness },
+            { key: 'layoutMode', path: 'visualisation.physics.layoutMode', definition: settingsUIDefinition.visualisation.subsections.physics.settings.layoutMode },
+            { key: 'planeZ', path: 'visualisation.physics.planeZ', definition: settingsUIDefinition.visualisation.subsections.physics.settings.planeZ },
+            { key: 'forceModel', path: 'visualisation.physics.forceModel', definition: settingsUIDefinition.visualisation.subsections.physics.settings.forceModel },
+            { key: 'gravity', path: 'visualisation.physics.gravity', definition: settingsUIDefinition.visualisation.subsections.physics.settings.gravity },
+            { key: 'strongGravity', path: 'visualisation.physics.strongGravity', definition: settingsUIDefinition.visualisation.subsections.physics.settings.strongGravity },
+            { key: 'edgeWeightNormalization', path: 'visualisation.physics.edgeWeightNormalization', definition: settingsUIDefinition.visualisation.subsections.physics.settings.edgeWeightNormalization },
+            { key: 'clusterSeparation', path: 'visualisation.physics.clusterSeparation', definition: settingsUIDefinition.visualisation.subsections.physics.settings.clusterSeparation },
             { key: 'enableBounds', path: 'visualisation.physics.enableBounds', definition: settingsUIDefinition.visualisation.subsections.physics.settings.enableBounds },
             { key: 'maxVelocity', path: 'visualisation.physics.maxVelocity', definition: settingsUIDefinition.visualisation.subsections.physics.settings.maxVelocity },
             { key: 'repulsionDistance', path: 'visualisation.physics.repulsionDistance', definition: settingsUIDefinition.visualisation.subsections.physics.settings.repulsionDistance },
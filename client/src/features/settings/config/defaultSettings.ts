@@ -48,6 +48,14 @@ export const defaultSettings: Settings = {
       attractionStrength: 0.05,
       boundsSize: 15.0, // From JSON (camelCase, was 45)
       collisionRadius: 0.5, // From JSON (camelCase, was 0.9)
+      collisionStiffness: 0.5,
+      layoutMode: '3d',
+      planeZ: 0,
+      forceModel: 'springElectric',
+      gravity: 1.0,
+      strongGravity: false,
+      edgeWeightNormalization: 'none',
+      clusterSeparation: 0,
       damping: 0.95,
       enableBounds: true,
       enabled: true,
@@ -39,6 +39,14 @@ export interface PhysicsSettings {
   attractionStrength: number;
   boundsSize: number;
   collisionRadius: number;
+  collisionStiffness: number;
+  layoutMode: '2d' | '3d';
+  planeZ: number;
+  forceModel: 'springElectric' | 'forceAtlas2' | 'linLog';
+  gravity: number;
+  strongGravity: boolean;
+  edgeWeightNormalization: 'none' | 'max' | 'log';
+  clusterSeparation: number;
   damping: number;
   enableBounds: boolean;
   enabled: boolean;
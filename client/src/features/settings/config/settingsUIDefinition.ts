@@ -81,9 +81,17 @@ export const settingsUIDefinition: Record<string, UICategoryDefinition> = {
         label: 'Physics',
         settings: {
           enabled: { label: 'Enable Physics', type: 'toggle', path: 'visualisation.physics.enabled', description: 'Enable physics simulation for graph layout.' },
-          attractionStrength: { label: 'Attraction Strength', type: 'slider', min: 0, max: 1, step: 0.01, path: 'visualisation.physics.attractionStrength', description: 'Strength of attraction between connected nodes.' },
+          attractionStrength: { label: 'Attraction Strength', type: 'slider', min: 0, max: 1, step: 0.01, path: 'visualisation.physics.attractionStrength', description: 'Pull towards the centre for the best-connected nodes, so hubs settle in the middle. 0 disables it.' },
           boundsSize: { label: 'Bounds Size', type: 'slider', min: 1, max: 50, step: 0.5, path: 'visualisation.physics.boundsSize', description: 'Size of the simulation bounding box.' },
           collisionRadius: { label: 'Collision Radius', type: 'slider', min: 0.1, max: 5, step: 0.1, path: 'visualisation.physics.collisionRadius', description: 'Radius for node collision detection.' },
+          collisionStiffness: { label: 'Collision Stiffness', type: 'slider', min: 0, max: 5, step: 0.1, path: 'visualisation.physics.collisionStiffness', description: 'How hard overlapping nodes push apart. 0 disables collisions.' },
+          layoutMode: { label: 'Layout Mode', type: 'radioGroup', options: [{value: '3d', label: '3D'}, {value: '2d', label: '2D'}], path: 'visualisation.physics.layoutMode', description: 'Lay the graph out in 3D or flat on a plane, e.g. for wall displays.' },
+          planeZ: { label: 'Plane Z', type: 'slider', min: -50, max: 50, step: 0.5, path: 'visualisation.physics.planeZ', description: 'Depth of the plane used by the 2D layout.' },
+          forceModel: { label: 'Force Model', type: 'select', options: [{value: 'springElectric', label: 'Spring-Electric'}, {value: 'forceAtlas2', label: 'ForceAtlas2'}, {value: 'linLog', label: 'LinLog'}], path: 'visualisation.physics.forceModel', description: 'Forces used to lay out the graph. ForceAtlas2 and LinLog separate clusters more clearly.' },
+          gravity: { label: 'Gravity', type: 'slider', min: 0, max: 10, step: 0.1, path: 'visualisation.physics.gravity', description: 'Pull towards the centre in the ForceAtlas2 and LinLog models.' },
+          strongGravity: { label: 'Strong Gravity', type: 'toggle', path: 'visualisation.physics.strongGravity', description: 'Gravity grows with distance, keeping disconnected parts close.' },
+          edgeWeightNormalization: { label: 'Edge Weight Normalization', type: 'select', options: [{value: 'none', label: 'None'}, {value: 'max', label: 'By Heaviest Edge'}, {value: 'log', label: 'Logarithmic'}], path: 'visualisation.physics.edgeWeightNormalization', description: 'Scale edge weights so heavily cross-linked pages do not dominate the layout.' },
+          clusterSeparation: { label: 'Cluster Separation', type: 'slider', min: 0, max: 5, step: 0.1, path: 'visualisation.physics.clusterSeparation', description: 'Lay out communities apart from each other and keep their pages together. 0 turns it off.' },
           damping: { label: 'Damping', type: 'slider', min: 0, max: 1, step: 0.01, path: 'visualisation.physics.damping', description: 'Damping factor to slow down node movement.' },
           enableBounds: { label: 'Enable Bounds', type: 'toggle', path: 'visualisation.physics.enableBounds', description: 'Confine nodes within the bounds size.' },
           iterations: { label: 'Iterations', type: 'slider', min: 10, max: 500, step: 10, path: 'visualisation.physics.iterations', description: 'Number of physics iterations per step.' },
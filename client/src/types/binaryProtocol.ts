@@ -28,6 +28,44 @@ export const BINARY_NODE_ID_OFFSET = 0;
 export const BINARY_POSITION_OFFSET = 4;
 export const BINARY_VELOCITY_OFFSET = 16;
 
+/**
+ * Versioned framing (crates/webxr-core/src/protocol.rs), used when the client sends
+ * `protocolVersion` in requestInitialData. Every binary message then starts with:
+ * - Magic: 4 bytes (uint32, 0xFFFFFFFE)
+ * - Version: 2 bytes (uint16)
+ * - Kind: 1 byte (0 positions, 1 edges)
+ * - Encoding: 1 byte (0 float32, 1 quantized16)
+ */
+export const PROTOCOL_VERSION = 1;
+export const FRAME_MAGIC = 0xFFFFFFFE;
+export const FRAME_HEADER_SIZE = 8;
+
+export interface FrameHeader {
+  version: number;
+  kind: number;
+  encoding: number;
+}
+
+/**
+ * Split a framed message into its header and payload, or return null if it has no
+ * header or is from another protocol version
+ */
+export function splitFrame(buffer: ArrayBuffer): { header: FrameHeader; payload: ArrayBuffer } | null {
+  if (buffer.byteLength < FRAME_HEADER_SIZE) {
+    return null;
+  }
+  const view = new DataView(buffer);
+  if (view.getUint32(0, true) !== FRAME_MAGIC) {
+    return null;
+  }
+  const header = { version: view.getUint16(4, true), kind: view.getUint8(6), encoding: view.getUint8(7) };
+  if (header.version !== PROTOCOL_VERSION) {
+    console.warn(`Ignoring frame for protocol version ${header.version}, expected ${PROTOCOL_VERSION}`);
+    return null;
+  }
+  return { header, payload: buffer.slice(FRAME_HEADER_SIZE) };
+}
+
 /**
  * Parse binary data buffer into an array of BinaryNodeData objects
  */
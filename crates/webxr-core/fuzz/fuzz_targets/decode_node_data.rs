@@ -0,0 +1,11 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use webxr_core::binary_protocol::{decode_node_data, encode_node_data};
+
+fuzz_target!(|data: &[u8]| {
+    // Anything that decodes must re-encode to the same bytes
+    if let Ok(nodes) = decode_node_data(data) {
+        assert_eq!(encode_node_data(&nodes), data);
+    }
+});
@@ -0,0 +1,653 @@
+use crate::node_data::BinaryNodeData;
+use crate::vec3::Vec3Data;
+use bytemuck::{Pod, Zeroable};
+use bytes::{Bytes, BytesMut};
+use log::{trace, debug};
+use serde::{Deserialize, Serialize};
+
+// The float32 record lives with `BinaryNodeData`, which it is converted from
+pub use crate::node_data::{WireNodeDataItem, WIRE_NODE_SIZE};
+
+const WIRE_ITEM_SIZE: usize = WIRE_NODE_SIZE;
+
+/// Compact wire format for bandwidth-constrained clients. Positions are 16-bit fixed
+/// point within the bounds volume and velocities are scaled to ±max velocity.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct QuantizedNodeDataItem {
+    pub id: u32,            // 4 bytes
+    pub position: [u16; 3], // 6 bytes
+    pub velocity: [i16; 3], // 6 bytes
+    // Total: 16 bytes
+}
+
+static_assertions::const_assert_eq!(std::mem::size_of::<QuantizedNodeDataItem>(), 16);
+
+const QUANTIZED_ITEM_SIZE: usize = std::mem::size_of::<QuantizedNodeDataItem>();
+
+/// Why a binary frame from a client could not be decoded
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodeError {
+    /// The frame is not a whole number of records
+    InvalidLength { len: usize, item_size: usize },
+    /// A record has a NaN or infinite position or velocity
+    NonFinite { id: u32 },
+    /// A framed message didn't start with a frame header
+    MissingHeader,
+    /// A framed message from another version of the protocol
+    UnsupportedVersion { version: u16 },
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::InvalidLength { len, item_size } => {
+                write!(f, "Data size {} is not a multiple of wire item size {}", len, item_size)
+            }
+            DecodeError::NonFinite { id } => write!(f, "Node {} has a non-finite position or velocity", id),
+            DecodeError::MissingHeader => write!(f, "Frame has no protocol header"),
+            DecodeError::UnsupportedVersion { version } => {
+                write!(f, "Frame is protocol version {}, expected {}", version, crate::protocol::PROTOCOL_VERSION)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Binary frame format a client negotiates when it requests data
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WireEncoding {
+    #[default]
+    Float32,
+    Quantized16,
+}
+
+/// Range the quantized encoding maps onto. It is sent to the client in the
+/// handshake so frames can be decoded; values outside it are clamped.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QuantizationBounds {
+    /// Positions span `-half_extent..=half_extent` on every axis
+    pub half_extent: f32,
+    pub max_velocity: f32,
+}
+
+impl QuantizationBounds {
+    pub fn is_valid(&self) -> bool {
+        self.half_extent.is_finite() && self.half_extent > 0.0
+            && self.max_velocity.is_finite() && self.max_velocity > 0.0
+    }
+
+    pub fn quantize_position(&self, value: f32) -> u16 {
+        let normalised = ((value + self.half_extent) / (2.0 * self.half_extent)).clamp(0.0, 1.0);
+        (normalised * u16::MAX as f32).round() as u16
+    }
+
+    pub fn dequantize_position(&self, value: u16) -> f32 {
+        (value as f32 / u16::MAX as f32) * 2.0 * self.half_extent - self.half_extent
+    }
+
+    pub fn quantize_velocity(&self, value: f32) -> i16 {
+        let normalised = (value / self.max_velocity).clamp(-1.0, 1.0);
+        (normalised * i16::MAX as f32).round() as i16
+    }
+
+    pub fn dequantize_velocity(&self, value: i16) -> f32 {
+        (value as f32 / i16::MAX as f32) * self.max_velocity
+    }
+
+    fn quantize(&self, item: &WireNodeDataItem) -> QuantizedNodeDataItem {
+        let (p, v) = (item.position, item.velocity);
+        QuantizedNodeDataItem {
+            id: item.id,
+            position: [self.quantize_position(p.x), self.quantize_position(p.y), self.quantize_position(p.z)],
+            velocity: [self.quantize_velocity(v.x), self.quantize_velocity(v.y), self.quantize_velocity(v.z)],
+        }
+    }
+}
+
+// Binary format (explicit):
+// - For each node (28 bytes total):
+//   - Node Index: 4 bytes (u32)
+//   - Position: 3 × 4 bytes = 12 bytes
+//   - Velocity: 3 × 4 bytes = 12 bytes
+// Total: 28 bytes per node
+
+pub fn encode_node_data(nodes: &[(u32, BinaryNodeData)]) -> Vec<u8> {
+    // Only log non-empty node transmissions to reduce spam
+    if !nodes.is_empty() {
+        trace!("Encoding {} nodes for binary transmission", nodes.len());
+    }
+    
+    let mut buffer = Vec::with_capacity(nodes.len() * WIRE_ITEM_SIZE);
+    
+    // Log some samples of the encoded data
+    let sample_size = std::cmp::min(3, nodes.len());
+    if sample_size > 0 {
+        trace!("Sample of nodes being encoded:");
+    }
+    
+    for (node_id, node) in nodes {
+        // Log the first few nodes for debugging
+        if sample_size > 0 && *node_id < sample_size as u32 {
+            trace!("Encoding node {}: pos=[{:.3},{:.3},{:.3}], vel=[{:.3},{:.3},{:.3}]",
+                node_id,
+                node.position.x, node.position.y, node.position.z,
+                node.velocity.x, node.velocity.y, node.velocity.z);
+        }
+        
+        // Create explicit wire format item
+        let wire_item = WireNodeDataItem::new(*node_id, node);
+        
+        // Use bytemuck for safe, direct memory layout conversion
+        let item_bytes = bytemuck::bytes_of(&wire_item);
+        buffer.extend_from_slice(item_bytes);
+        
+        // Mass, flags, and padding are server-side only and not transmitted over wire
+    }
+
+    // Only log non-empty node transmissions to reduce spam
+    if !nodes.is_empty() {
+        trace!("Encoded binary data: {} bytes for {} nodes", buffer.len(), nodes.len());
+    }
+    buffer
+}
+
+/// One tick of node data, encoded once and shared by every session. Cloning only
+/// bumps a reference count; sessions that need a subset of the nodes pick records
+/// out by index instead of encoding them again.
+#[derive(Debug, Clone, Default)]
+pub struct EncodedFrame {
+    bytes: Bytes,
+    sequence: u64,
+}
+
+impl EncodedFrame {
+    /// A frame of float wire records encoded elsewhere, e.g. read back from a
+    /// recording. Fails if `bytes` is not a whole number of records.
+    pub fn from_wire(bytes: Bytes, sequence: u64) -> Result<Self, DecodeError> {
+        if !bytes.len().is_multiple_of(WIRE_ITEM_SIZE) {
+            return Err(DecodeError::InvalidLength { len: bytes.len(), item_size: WIRE_ITEM_SIZE });
+        }
+        Ok(Self { bytes, sequence })
+    }
+
+    /// Increases with every frame from the same encoder, so a session can tell
+    /// whether it has already handled a frame without keeping it alive
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.bytes.len() / WIRE_ITEM_SIZE
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// The whole frame as sent on the wire
+    pub fn bytes(&self) -> Bytes {
+        self.bytes.clone()
+    }
+
+    pub fn item(&self, index: usize) -> WireNodeDataItem {
+        let start = index * WIRE_ITEM_SIZE;
+        bytemuck::pod_read_unaligned(&self.bytes[start..start + WIRE_ITEM_SIZE])
+    }
+
+    pub fn items(&self) -> impl Iterator<Item = WireNodeDataItem> + '_ {
+        self.bytes.chunks_exact(WIRE_ITEM_SIZE).map(bytemuck::pod_read_unaligned)
+    }
+
+    /// Records at `indices`, which must be ascending and unique. A contiguous run,
+    /// including the whole frame, is returned without copying.
+    pub fn select(&self, indices: &[usize]) -> Bytes {
+        let (Some(&first), Some(&last)) = (indices.first(), indices.last()) else {
+            return Bytes::new();
+        };
+        if last - first + 1 == indices.len() {
+            return self.bytes.slice(first * WIRE_ITEM_SIZE..(last + 1) * WIRE_ITEM_SIZE);
+        }
+
+        let mut selected = BytesMut::with_capacity(indices.len() * WIRE_ITEM_SIZE);
+        for &index in indices {
+            let start = index * WIRE_ITEM_SIZE;
+            selected.extend_from_slice(&self.bytes[start..start + WIRE_ITEM_SIZE]);
+        }
+        selected.freeze()
+    }
+
+    /// Records at `indices` converted to the quantized wire format
+    pub fn select_quantized(&self, indices: &[usize], bounds: &QuantizationBounds) -> Bytes {
+        let mut selected = BytesMut::with_capacity(indices.len() * QUANTIZED_ITEM_SIZE);
+        for &index in indices {
+            selected.extend_from_slice(bytemuck::bytes_of(&bounds.quantize(&self.item(index))));
+        }
+        selected.freeze()
+    }
+}
+
+/// Encodes frames into a buffer that is reused from tick to tick. Once every
+/// session has dropped the previous frame, its allocation is reclaimed rather than
+/// a new one being made.
+#[derive(Debug, Default)]
+pub struct FrameEncoder {
+    buffer: BytesMut,
+    sequence: u64,
+}
+
+impl FrameEncoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn encode<I>(&mut self, nodes: I) -> EncodedFrame
+    where
+        I: IntoIterator<Item = (u32, BinaryNodeData)>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let nodes = nodes.into_iter();
+        self.buffer.reserve(nodes.len() * WIRE_ITEM_SIZE);
+        for (id, node) in nodes {
+            let wire_item = WireNodeDataItem::new(id, &node);
+            self.buffer.extend_from_slice(bytemuck::bytes_of(&wire_item));
+        }
+        self.sequence += 1;
+        EncodedFrame { bytes: self.buffer.split().freeze(), sequence: self.sequence }
+    }
+}
+
+/// Decodes a frame of wire records. Frames come from clients, so a malformed one is
+/// an error rather than a panic: the length must be a whole number of records and
+/// every coordinate finite. Records are read unaligned, as socket buffers may be.
+pub fn decode_node_data(data: &[u8]) -> Result<Vec<(u32, BinaryNodeData)>, DecodeError> {
+    // Check if data is properly sized
+    if !data.len().is_multiple_of(WIRE_ITEM_SIZE) {
+        return Err(DecodeError::InvalidLength { len: data.len(), item_size: WIRE_ITEM_SIZE });
+    }
+    
+    if data.is_empty() {
+        return Ok(Vec::new());
+    }
+    
+    let expected_nodes = data.len() / WIRE_ITEM_SIZE;
+    debug!(
+        "Decoding binary data: size={} bytes, expected nodes={}",
+        data.len(),
+        expected_nodes
+    );
+    
+    let mut updates = Vec::with_capacity(expected_nodes);
+    
+    // Set up sample logging
+    let max_samples = 3;
+    let mut samples_logged = 0;
+    
+    debug!("Starting binary data decode, expecting nodes with position and velocity data");
+    
+    // Process data in chunks of WIRE_ITEM_SIZE bytes
+    for chunk in data.chunks_exact(WIRE_ITEM_SIZE) {
+        // Use bytemuck for safe deserialization from bytes
+        let wire_item: WireNodeDataItem = bytemuck::pod_read_unaligned(chunk);
+        if !wire_item.position.is_finite() || !wire_item.velocity.is_finite() {
+            return Err(DecodeError::NonFinite { id: wire_item.id });
+        }
+        
+        // Log the first few decoded items as samples
+        if samples_logged < max_samples {
+            debug!(
+                "Decoded node {}: pos=[{:.3},{:.3},{:.3}], vel=[{:.3},{:.3},{:.3}]",
+                wire_item.id,
+                wire_item.position.x, wire_item.position.y, wire_item.position.z,
+                wire_item.velocity.x, wire_item.velocity.y, wire_item.velocity.z
+            );
+            samples_logged += 1;
+        }
+        
+        // Server-side fields get defaults, replaced with the values from node_map
+        updates.push((wire_item.id, BinaryNodeData::from(&wire_item)));
+    }
+    
+    debug!("Successfully decoded {} nodes from binary data", updates.len());
+    Ok(updates)
+}
+
+/// Appends records that aren't part of a shared frame, such as supernodes, to a
+/// payload already selected from one, in the same encoding
+pub fn append_items(payload: Bytes, items: &[WireNodeDataItem], encoding: WireEncoding, bounds: &QuantizationBounds) -> Bytes {
+    if items.is_empty() {
+        return payload;
+    }
+    let item_size = match encoding {
+        WireEncoding::Float32 => WIRE_ITEM_SIZE,
+        WireEncoding::Quantized16 => QUANTIZED_ITEM_SIZE,
+    };
+    let mut combined = BytesMut::with_capacity(payload.len() + items.len() * item_size);
+    combined.extend_from_slice(&payload);
+    for item in items {
+        match encoding {
+            WireEncoding::Float32 => combined.extend_from_slice(bytemuck::bytes_of(item)),
+            WireEncoding::Quantized16 => combined.extend_from_slice(bytemuck::bytes_of(&bounds.quantize(item))),
+        }
+    }
+    combined.freeze()
+}
+
+/// Decodes a quantized frame; the counterpart of `EncodedFrame::select_quantized`
+pub fn decode_quantized_node_data(data: &[u8], bounds: &QuantizationBounds) -> Result<Vec<(u32, BinaryNodeData)>, DecodeError> {
+    let chunks = data.chunks_exact(QUANTIZED_ITEM_SIZE);
+    if !chunks.remainder().is_empty() {
+        return Err(DecodeError::InvalidLength { len: data.len(), item_size: QUANTIZED_ITEM_SIZE });
+    }
+
+    Ok(chunks
+        .map(|chunk| {
+            let item: QuantizedNodeDataItem = bytemuck::pod_read_unaligned(chunk);
+            let (p, v) = (item.position, item.velocity);
+            (item.id, BinaryNodeData::from_motion(
+                Vec3Data::new(bounds.dequantize_position(p[0]), bounds.dequantize_position(p[1]), bounds.dequantize_position(p[2])),
+                Vec3Data::new(bounds.dequantize_velocity(v[0]), bounds.dequantize_velocity(v[1]), bounds.dequantize_velocity(v[2])),
+            ))
+        })
+        .collect())
+}
+
+/// First word of an edge frame. Position frames start with a node id, and node ids
+/// never reach this value, so clients can tell the two apart.
+pub const EDGE_FRAME_MARKER: u32 = u32::MAX;
+
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeFrameKind {
+    /// Every edge in the graph, replacing what the client had
+    Snapshot = 0,
+    Added = 1,
+    /// Records identify edges by source and target; weight and type are unused
+    Removed = 2,
+}
+
+impl TryFrom<u32> for EdgeFrameKind {
+    type Error = String;
+
+    fn try_from(value: u32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(EdgeFrameKind::Snapshot),
+            1 => Ok(EdgeFrameKind::Added),
+            2 => Ok(EdgeFrameKind::Removed),
+            other => Err(format!("Unknown edge frame kind {}", other)),
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+struct EdgeFrameHeader {
+    marker: u32, // 4 bytes, always EDGE_FRAME_MARKER
+    kind: u32,   // 4 bytes
+}
+
+/// Wire format of one edge in an edge frame
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable)]
+pub struct WireEdgeItem {
+    pub source: u32,    // 4 bytes
+    pub target: u32,    // 4 bytes
+    pub weight: f32,    // 4 bytes
+    pub edge_type: u32, // 4 bytes, code from EdgeTypeTable
+    // Total: 16 bytes
+}
+
+static_assertions::const_assert_eq!(std::mem::size_of::<WireEdgeItem>(), 16);
+
+const EDGE_HEADER_SIZE: usize = std::mem::size_of::<EdgeFrameHeader>();
+const EDGE_ITEM_SIZE: usize = std::mem::size_of::<WireEdgeItem>();
+
+/// Stable numeric codes for edge type names. Code 0 means untyped and the name
+/// with code `n` is `names()[n - 1]`. Codes are only ever added, so a client
+/// keeps a valid table by appending the names it is sent.
+#[derive(Debug, Default, Clone)]
+pub struct EdgeTypeTable {
+    names: Vec<String>,
+}
+
+impl EdgeTypeTable {
+    pub fn code(&mut self, edge_type: Option<&str>) -> u32 {
+        let Some(edge_type) = edge_type else {
+            return 0;
+        };
+        let index = match self.names.iter().position(|name| name == edge_type) {
+            Some(index) => index,
+            None => {
+                self.names.push(edge_type.to_string());
+                self.names.len() - 1
+            }
+        };
+        index as u32 + 1
+    }
+
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+}
+
+pub fn encode_edge_frame(kind: EdgeFrameKind, edges: &[WireEdgeItem]) -> Bytes {
+    let mut buffer = BytesMut::with_capacity(EDGE_HEADER_SIZE + edges.len() * EDGE_ITEM_SIZE);
+    buffer.extend_from_slice(bytemuck::bytes_of(&EdgeFrameHeader { marker: EDGE_FRAME_MARKER, kind: kind as u32 }));
+    buffer.extend_from_slice(bytemuck::cast_slice(edges));
+    buffer.freeze()
+}
+
+pub fn decode_edge_frame(data: &[u8]) -> Result<(EdgeFrameKind, Vec<WireEdgeItem>), String> {
+    if data.len() < EDGE_HEADER_SIZE {
+        return Err(format!("Edge frame of {} bytes is shorter than its header", data.len()));
+    }
+    let header: EdgeFrameHeader = bytemuck::pod_read_unaligned(&data[..EDGE_HEADER_SIZE]);
+    if header.marker != EDGE_FRAME_MARKER {
+        return Err("Missing edge frame marker".to_string());
+    }
+    let kind = EdgeFrameKind::try_from(header.kind)?;
+
+    let chunks = data[EDGE_HEADER_SIZE..].chunks_exact(EDGE_ITEM_SIZE);
+    if !chunks.remainder().is_empty() {
+        return Err(format!(
+            "Edge data size {} is not a multiple of edge item size {}",
+            data.len() - EDGE_HEADER_SIZE,
+            EDGE_ITEM_SIZE
+        ));
+    }
+    Ok((kind, chunks.map(bytemuck::pod_read_unaligned).collect()))
+}
+
+pub fn calculate_message_size(updates: &[(u32, BinaryNodeData)]) -> usize {
+    // Each update uses WireNodeDataItem size
+    updates.len() * std::mem::size_of::<WireNodeDataItem>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wire_format_size() {
+        // Verify that WireNodeDataItem is exactly 28 bytes
+        assert_eq!(std::mem::size_of::<WireNodeDataItem>(), 28);
+    }
+
+    #[test]
+    fn test_encode_decode_roundtrip() {
+        let nodes = vec![
+            (1u32, BinaryNodeData {
+                position: crate::vec3::Vec3Data::new(1.0, 2.0, 3.0),
+                velocity: crate::vec3::Vec3Data::new(0.1, 0.2, 0.3),
+                mass: 100,
+                flags: 1,
+                padding: [0, 0],
+            }),
+            (2u32, BinaryNodeData {
+                position: crate::vec3::Vec3Data::new(4.0, 5.0, 6.0),
+                velocity: crate::vec3::Vec3Data::new(0.4, 0.5, 0.6),
+                mass: 200,
+                flags: 1,
+                padding: [0, 0],
+            }),
+        ];
+
+        let encoded = encode_node_data(&nodes);
+        
+        // Verify encoded size matches expected wire format
+        assert_eq!(encoded.len(), nodes.len() * std::mem::size_of::<WireNodeDataItem>());
+        assert_eq!(encoded.len(), nodes.len() * 28);
+        
+        let decoded = decode_node_data(&encoded).unwrap();
+        assert_eq!(nodes.len(), decoded.len());
+
+        for ((orig_id, orig_data), (dec_id, dec_data)) in nodes.iter().zip(decoded.iter()) {
+            assert_eq!(orig_id, dec_id);
+            assert_eq!(orig_data.position, dec_data.position);
+            assert_eq!(orig_data.velocity, dec_data.velocity);
+            // Note: mass, flags, and padding are not transmitted over the wire,
+            // so decoded values will have defaults (100, 0, [0,0])
+            assert_eq!(dec_data.mass, 100u8);
+            assert_eq!(dec_data.flags, 0u8);
+            assert_eq!(dec_data.padding, [0u8, 0u8]);
+        }
+    }
+
+    #[test]
+    fn test_decode_invalid_data() {
+        // Test with data that's not a multiple of wire item size (28 bytes)
+        let result = decode_node_data(&[0u8; 27]);
+        assert!(result.is_err());
+        assert!(result.unwrap_err().to_string().contains("not a multiple of wire item size"));
+        
+        // Test with data that's too short but multiple of wire size
+        let result = decode_node_data(&[0u8; 0]);
+        assert!(result.is_ok());
+        assert_eq!(result.unwrap().len(), 0);
+
+        // NaN coordinates and misaligned buffers are rejected or handled without panicking
+        let mut nan = WireNodeDataItem { id: 7, position: Vec3Data::zero(), velocity: Vec3Data::zero() };
+        nan.velocity.y = f32::NAN;
+        assert_eq!(decode_node_data(bytemuck::bytes_of(&nan)).unwrap_err(), DecodeError::NonFinite { id: 7 });
+        let words = [0u32; 8];
+        let bytes: &[u8] = bytemuck::cast_slice(&words);
+        assert_eq!(decode_node_data(&bytes[1..WIRE_ITEM_SIZE + 1]).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_frame_select_by_index() {
+        let nodes: Vec<(u32, BinaryNodeData)> = (0..4u32)
+            .map(|i| (i + 10, BinaryNodeData {
+                position: crate::vec3::Vec3Data::new(i as f32, 0.0, 0.0),
+                velocity: crate::vec3::Vec3Data::new(0.0, i as f32, 0.0),
+                mass: 100,
+                flags: 1,
+                padding: [0, 0],
+            }))
+            .collect();
+
+        let mut encoder = FrameEncoder::new();
+        let frame = encoder.encode(nodes.iter().copied());
+        assert_eq!(frame.node_count(), 4);
+        assert_eq!(frame.bytes(), Bytes::from(encode_node_data(&nodes)));
+        assert_eq!(frame.item(2).id, 12);
+
+        // A contiguous run shares the frame's memory
+        let run = frame.select(&[1, 2]);
+        assert_eq!(run.as_ptr(), frame.bytes()[WIRE_ITEM_SIZE..].as_ptr());
+
+        let sparse = decode_node_data(&frame.select(&[0, 3])).unwrap();
+        assert_eq!(sparse.iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![10, 13]);
+        assert_eq!(sparse[1].1.velocity, nodes[3].1.velocity);
+        assert!(frame.select(&[]).is_empty());
+
+        // The next frame is encoded independently of the one still held
+        let next = encoder.encode(nodes[..1].iter().copied());
+        assert_eq!(next.node_count(), 1);
+        assert!(next.sequence() > frame.sequence());
+        assert_eq!(frame.item(0).id, 10);
+    }
+
+    #[test]
+    fn test_quantized_roundtrip() {
+        let bounds = QuantizationBounds { half_extent: 15.0, max_velocity: 0.02 };
+        let nodes = [
+            (7u32, BinaryNodeData {
+                position: Vec3Data::new(-3.25, 0.0, 14.9),
+                velocity: Vec3Data::new(0.01, -0.015, 0.0),
+                mass: 100,
+                flags: 1,
+                padding: [0, 0],
+            }),
+            (8u32, BinaryNodeData {
+                // Outside the bounds, so clamped to the edges
+                position: Vec3Data::new(40.0, -40.0, 0.5),
+                velocity: Vec3Data::new(1.0, 0.0, 0.0),
+                mass: 100,
+                flags: 1,
+                padding: [0, 0],
+            }),
+        ];
+
+        let frame = FrameEncoder::new().encode(nodes.iter().copied());
+        let quantized = frame.select_quantized(&[0, 1], &bounds);
+        assert_eq!(quantized.len(), 2 * QUANTIZED_ITEM_SIZE);
+
+        let decoded = decode_quantized_node_data(&quantized, &bounds).unwrap();
+        let position_step = 2.0 * bounds.half_extent / u16::MAX as f32;
+        let velocity_step = bounds.max_velocity / i16::MAX as f32;
+        let (id, first) = decoded[0];
+        assert_eq!(id, 7);
+        assert!((first.position.x + 3.25).abs() <= position_step);
+        assert!((first.position.z - 14.9).abs() <= position_step);
+        assert!((first.velocity.y + 0.015).abs() <= velocity_step);
+        assert_eq!(decoded[1].1.position.x, 15.0);
+        assert_eq!(decoded[1].1.position.y, -15.0);
+        assert_eq!(decoded[1].1.velocity.x, 0.02);
+    }
+
+    #[test]
+    fn test_edge_frame_roundtrip() {
+        let mut types = EdgeTypeTable::default();
+        let edges = [
+            WireEdgeItem { source: 1, target: 2, weight: 0.5, edge_type: types.code(None) },
+            WireEdgeItem { source: 2, target: 3, weight: 1.0, edge_type: types.code(Some("similarity")) },
+            WireEdgeItem { source: 3, target: 1, weight: 2.0, edge_type: types.code(Some("similarity")) },
+        ];
+        assert_eq!(types.names(), ["similarity".to_string()]);
+        assert_eq!(edges[2].edge_type, 1);
+
+        let frame = encode_edge_frame(EdgeFrameKind::Added, &edges);
+        assert_eq!(frame.len(), 8 + 3 * 16);
+        assert_eq!(&frame[..4], &[0xFF; 4]);
+
+        let (kind, decoded) = decode_edge_frame(&frame).unwrap();
+        assert_eq!(kind, EdgeFrameKind::Added);
+        assert_eq!(decoded, edges);
+
+        assert!(decode_edge_frame(&frame[..frame.len() - 1]).is_err());
+        assert!(decode_edge_frame(&encode_node_data(&[])).is_err());
+    }
+
+    #[test]
+    fn test_message_size_calculation() {
+        let nodes = vec![
+            (1u32, BinaryNodeData {
+                position: crate::vec3::Vec3Data::new(1.0, 2.0, 3.0),
+                velocity: crate::vec3::Vec3Data::new(0.1, 0.2, 0.3),
+                mass: 100,
+                flags: 1,
+                padding: [0, 0],
+            }),
+        ];
+
+        let size = calculate_message_size(&nodes);
+        assert_eq!(size, 28);
+        
+        let encoded = encode_node_data(&nodes);
+        assert_eq!(encoded.len(), size);
+    }
+}
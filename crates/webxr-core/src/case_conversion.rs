@@ -0,0 +1,83 @@
+//! Conversion between the camelCase keys clients use and the snake_case keys of
+//! the server's settings structs
+
+use serde_json::{Map, Value};
+
+/// `nodeSize` and `node-size` become `node_size`
+pub fn to_snake_case(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 4);
+    for c in s.chars() {
+        if c == '-' {
+            result.push('_');
+        } else if c.is_ascii_uppercase() {
+            if !result.is_empty() && !result.ends_with('_') {
+                result.push('_');
+            }
+            result.push(c.to_ascii_lowercase());
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+/// `node_size` becomes `nodeSize`
+pub fn to_camel_case(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut upper_next = false;
+    for c in s.chars() {
+        if c == '_' || c == '-' {
+            upper_next = !result.is_empty();
+        } else if upper_next {
+            result.push(c.to_ascii_uppercase());
+            upper_next = false;
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+/// Renames every object key in `value`, recursing into nested objects and arrays
+pub fn convert_keys(value: Value, convert: fn(&str) -> String) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| (convert(&key), convert_keys(value, convert)))
+                .collect::<Map<String, Value>>(),
+        ),
+        Value::Array(items) => Value::Array(items.into_iter().map(|v| convert_keys(v, convert)).collect()),
+        other => other,
+    }
+}
+
+pub fn keys_to_snake_case(value: Value) -> Value {
+    convert_keys(value, to_snake_case)
+}
+
+pub fn keys_to_camel_case(value: Value) -> Value {
+    convert_keys(value, to_camel_case)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_case_round_trip() {
+        assert_eq!(to_snake_case("enableHologram"), "enable_hologram");
+        assert_eq!(to_snake_case("node-size"), "node_size");
+        assert_eq!(to_snake_case("already_snake"), "already_snake");
+        assert_eq!(to_camel_case("enable_hologram"), "enableHologram");
+        assert_eq!(to_camel_case("xr"), "xr");
+        assert_eq!(
+            keys_to_camel_case(json!({"bloom": {"edge_bloom_strength": 1, "list": [{"a_b": 2}]}})),
+            json!({"bloom": {"edgeBloomStrength": 1, "list": [{"aB": 2}]}})
+        );
+        assert_eq!(
+            keys_to_snake_case(json!({"visualisation": {"nodeSize": 1, "list": [{"aB": 2}]}})),
+            json!({"visualisation": {"node_size": 1, "list": [{"a_b": 2}]}})
+        );
+    }
+}
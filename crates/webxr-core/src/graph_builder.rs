@@ -0,0 +1,236 @@
+//! Building graphs from page metadata
+
+use log::{info, trace};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{HashMap, HashSet};
+
+use crate::models::edge::Edge;
+use crate::models::graph::GraphData;
+use crate::models::metadata::MetadataStore;
+use crate::models::node::Node;
+
+/// Builds a graph with a node per page in `metadata` and an edge for every pair of
+/// pages that reference each other, weighted by how often, laid out from `seed`
+pub fn assemble_graph(metadata: &MetadataStore, seed: u64) -> GraphData {
+    let mut graph = GraphData::new();
+    let mut edge_map = HashMap::new();
+    let mut node_map = HashMap::new();
+
+    // First pass: Create nodes from files in metadata
+    let mut valid_nodes = HashSet::new();
+    trace!("Creating nodes from {} metadata entries", metadata.len());
+    for file_name in metadata.keys() {
+        let node_id = file_name.trim_end_matches(".md").to_string();
+        valid_nodes.insert(node_id);
+    }
+    trace!("Created valid_nodes set with {} nodes", valid_nodes.len());
+
+    // Create nodes for all valid node IDs
+    for node_id in &valid_nodes {
+        // Get metadata for this node, including the node_id if available
+        let metadata_entry = graph.metadata.get(&format!("{}.md", node_id));
+        let stored_node_id = metadata_entry.map(|m| m.node_id.clone());
+        
+        // Create node with stored ID or generate a new one if not available
+        let stored_node_id_u32 = stored_node_id.and_then(|s| s.parse::<u32>().ok());
+        let mut node = Node::new_with_id(node_id.clone(), stored_node_id_u32);
+        graph.id_to_metadata.insert(node.id.to_string(), node_id.clone());
+
+        // Get metadata for this node
+        if let Some(metadata) = metadata.get(&format!("{}.md", node_id)) {
+            // Set file size which also calculates mass
+            node.set_file_size(metadata.file_size as u64);  // This will update both file_size and mass
+            
+            // Set the node label to the file name without extension
+            // This will be used as the display name for the node
+            node.label = metadata.file_name.trim_end_matches(".md").to_string();
+            
+            // Set visual properties from metadata
+            node.size = Some(metadata.node_size as f32);
+            
+            // Add metadata fields to node's metadata map
+            // Add all relevant metadata fields to ensure consistency
+            node.metadata.insert("fileName".to_string(), metadata.file_name.clone());
+            
+            // Add name field (without .md extension) for client-side metadata ID mapping
+            if metadata.file_name.ends_with(".md") {
+                let name = metadata.file_name[..metadata.file_name.len() - 3].to_string();
+                node.metadata.insert("name".to_string(), name.clone());
+                node.metadata.insert("metadataId".to_string(), name);
+            } else {
+                node.metadata.insert("name".to_string(), metadata.file_name.clone());
+                node.metadata.insert("metadataId".to_string(), metadata.file_name.clone());
+            }
+            
+            node.metadata.insert("fileSize".to_string(), metadata.file_size.to_string());
+            node.metadata.insert("nodeSize".to_string(), metadata.node_size.to_string());
+            node.metadata.insert("hyperlinkCount".to_string(), metadata.hyperlink_count.to_string());
+            node.metadata.insert("sha1".to_string(), metadata.sha1.clone());
+            node.metadata.insert("lastModified".to_string(), metadata.last_modified.to_string());
+            
+            if !metadata.perplexity_link.is_empty() {
+                node.metadata.insert("perplexityLink".to_string(), metadata.perplexity_link.clone());
+            }
+            
+            if let Some(last_process) = metadata.last_perplexity_process {
+                node.metadata.insert("lastPerplexityProcess".to_string(), last_process.to_string());
+            }
+            
+            // We don't add topic_counts to metadata as it would create circular references
+            // and is already used to create edges
+            
+            // Ensure flags is set to 1 (default active state)
+            node.data.flags = 1;
+        }
+
+        let node_clone = node.clone();
+        graph.nodes.push(node_clone);
+        // Store nodes in map by numeric ID for efficient lookups
+        node_map.insert(node.id, node);
+    }
+
+    // Store metadata in graph
+    trace!("Storing {} metadata entries in graph", metadata.len());
+    graph.metadata = metadata.clone();
+    trace!("Created {} nodes in graph", graph.nodes.len());
+    // Second pass: Create edges from topic counts
+    for (source_file, metadata) in metadata.iter() {
+        let source_id = source_file.trim_end_matches(".md").to_string();
+        // Find the node with this metadata_id to get its numeric ID
+        let source_node = graph.nodes.iter().find(|n| n.metadata_id == source_id);
+        if source_node.is_none() {
+            continue; // Skip if node not found
+        }
+        let source_numeric_id = source_node.unwrap().id;
+        
+        trace!("Processing edges for source: {} (ID: {})", source_id, source_numeric_id);
+        for (target_file, count) in &metadata.topic_counts {
+            let target_id = target_file.trim_end_matches(".md").to_string();
+            // Find the node with this metadata_id to get its numeric ID
+            let target_node = graph.nodes.iter().find(|n| n.metadata_id == target_id);
+            if target_node.is_none() {
+                continue; // Skip if node not found
+            }
+            let target_numeric_id = target_node.unwrap().id;
+
+            trace!("  Edge: {} -> {} (weight: {})", source_numeric_id, target_numeric_id, count);
+
+            // Only create edge if both nodes exist and they're different
+            if source_numeric_id != target_numeric_id {
+                let edge_key = if source_numeric_id < target_numeric_id {
+                    (source_numeric_id, target_numeric_id)
+                } else {
+                    (target_numeric_id, source_numeric_id)
+                };
+
+                edge_map.entry(edge_key)
+                    .and_modify(|weight| *weight += *count as f32)
+                    .or_insert(*count as f32);
+            }
+        }
+    }
+
+    // Convert edge map to edges
+    trace!("Edge map contains {} unique connections", edge_map.len());
+    for ((source, target), weight) in &edge_map {
+        trace!("Edge map entry: {} -- {} (weight: {})", source, target, weight);
+    }
+
+    trace!("Converting edge map to {} edges", edge_map.len());
+    graph.edges = edge_map.into_iter()
+        .map(|((source, target), weight)| {
+            Edge::new(source, target, weight)
+        })
+        .collect();
+
+    initialize_positions(&mut graph, seed);
+
+    info!("Built graph with {} nodes and {} edges", graph.nodes.len(), graph.edges.len());
+    trace!("Completed graph build: {} nodes, {} edges", graph.nodes.len(), graph.edges.len());
+    graph
+}
+
+/// Spreads nodes over a Fibonacci sphere with a little radial jitter drawn from
+/// `seed`. Nodes are placed in `metadata_id` order, so a page starts in the same
+/// place for the same seed however the graph was assembled.
+pub fn initialize_positions(graph: &mut GraphData, seed: u64) {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let node_count = graph.nodes.len() as f32;
+    let initial_radius = 3.0; // Increasing radius for better visibility
+    let golden_ratio = (1.0 + 5.0_f32.sqrt()) / 2.0;
+    
+    // Log the initialization process
+    info!("Initializing random positions for {} nodes with radius {}", 
+         node_count, initial_radius);
+    info!("First 5 node numeric IDs: {}", graph.nodes.iter().take(5).map(|n| n.id.to_string()).collect::<Vec<_>>().join(", "));
+    info!("First 5 node metadata IDs: {}", graph.nodes.iter().take(5).map(|n| n.metadata_id.clone()).collect::<Vec<_>>().join(", "));
+    
+    let mut order: Vec<usize> = (0..graph.nodes.len()).collect();
+    order.sort_by(|&a, &b| graph.nodes[a].metadata_id.cmp(&graph.nodes[b].metadata_id));
+
+    // Use Fibonacci sphere distribution for more uniform initial positions
+    for (i, index) in order.into_iter().enumerate() {
+        let node = &mut graph.nodes[index];
+        let i_float: f32 = i as f32;
+        
+        // Calculate Fibonacci sphere coordinates
+        let theta = 2.0 * std::f32::consts::PI * i_float / golden_ratio;
+        let phi = (1.0 - 2.0 * (i_float + 0.5) / node_count).acos();
+        
+        // Add slight randomness to prevent exact overlaps
+        let r = initial_radius * (0.9 + rng.gen_range(0.0..0.2));
+        
+        node.set_x(r * phi.sin() * theta.cos());
+        node.set_y(r * phi.sin() * theta.sin());
+        node.set_z(r * phi.cos());
+        
+        // Initialize with zero velocity
+        node.set_vx(0.0);
+        node.set_vy(0.0);
+        node.set_vz(0.0);
+
+        // Log first 5 nodes for debugging
+        if i < 5 {
+            info!("Initialized node {}: id={}, pos=[{:.3},{:.3},{:.3}]", 
+                 i,
+                 node.id,
+                 node.data.position.x, 
+                 node.data.position.y, 
+                 node.data.position.z);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn graph(names: &[&str]) -> GraphData {
+        let mut graph = GraphData::new();
+        graph.nodes = names.iter().enumerate()
+            .map(|(i, name)| Node::new_with_id(name.to_string(), Some(i as u32 + 1)))
+            .collect();
+        graph
+    }
+
+    fn position_of(graph: &GraphData, name: &str) -> [f32; 3] {
+        let p = graph.nodes.iter().find(|n| n.metadata_id == name).unwrap().data.position;
+        [p.x, p.y, p.z]
+    }
+
+    #[test]
+    fn test_initialize_positions_is_seeded() {
+        let mut first = graph(&["a", "b", "c"]);
+        let mut reordered = graph(&["c", "a", "b"]);
+        initialize_positions(&mut first, 7);
+        initialize_positions(&mut reordered, 7);
+        for name in ["a", "b", "c"] {
+            assert_eq!(position_of(&first, name), position_of(&reordered, name));
+        }
+
+        let mut reseeded = graph(&["a", "b", "c"]);
+        initialize_positions(&mut reseeded, 8);
+        assert_ne!(position_of(&first, "a"), position_of(&reseeded, "a"));
+    }
+}
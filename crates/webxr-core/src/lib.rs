@@ -0,0 +1,20 @@
+//! Code shared by the WebXR server and its browser client: the graph model, graph
+//! building from page metadata, reference extraction, and the binary wire protocol
+//! with its conformance vectors.
+//!
+//! The crate has no actix or CUDA dependencies so it also compiles to
+//! `wasm32-unknown-unknown`; the `wasm` feature adds JavaScript bindings for the
+//! protocol codec and case conversion.
+
+pub mod binary_protocol;
+pub mod case_conversion;
+pub mod graph_builder;
+pub mod models;
+pub mod node_data;
+pub mod page;
+pub mod protocol;
+pub mod reference_parser;
+pub mod vec3;
+
+#[cfg(feature = "wasm")]
+pub mod wasm;
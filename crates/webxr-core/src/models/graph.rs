@@ -0,0 +1,72 @@
+use crate::models::node::Node;
+use super::edge::Edge;
+use super::metadata::MetadataStore;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+
+/// Represents the graph data structure containing nodes, edges, and metadata.
+/// All fields use camelCase serialization for client compatibility.
+#[derive(Default, Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphData {
+    /// List of nodes in the graph.
+    pub nodes: Vec<Node>,
+    /// List of edges connecting the nodes.
+    pub edges: Vec<Edge>,
+    /// Metadata associated with the graph, using camelCase keys.
+    pub metadata: MetadataStore,
+    /// Mapping from numeric ID to metadata ID (filename) for lookup
+    #[serde(skip)]
+    pub id_to_metadata: HashMap<String, String>,
+}
+
+impl GraphData {
+    pub fn new() -> Self {
+        Self {
+            nodes: Vec::new(),
+            edges: Vec::new(),
+            metadata: MetadataStore::new(),
+            id_to_metadata: HashMap::new(),
+        }
+    }
+}
+
+/// Optional structure added when the graph is built from metadata
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GraphBuildOptions {
+    /// Add a placeholder node for every link target that matches no page
+    pub ghost_nodes: bool,
+    /// Also add placeholders for links to pages that exist but aren't published.
+    /// Their titles are withheld, so these only preserve the shape of the graph.
+    pub private_ghost_nodes: bool,
+    /// Add a node per tag, linked to every page carrying it
+    pub tag_nodes: bool,
+    /// Join each journal page to the next one by date
+    pub journal_edges: bool,
+    /// Join pages flagged as near duplicates of each other
+    pub duplicate_edges: bool,
+    /// Add a node per open task, linked to its page and the pages it references
+    pub task_nodes: bool,
+}
+
+impl GraphBuildOptions {
+    /// Reads `GRAPH_GHOST_NODES`, `GRAPH_PRIVATE_GHOST_NODES`, `GRAPH_TAG_NODES`,
+    /// `GRAPH_JOURNAL_EDGES`, `GRAPH_DUPLICATE_EDGES` and `GRAPH_TASK_NODES`
+    pub fn from_env() -> Self {
+        Self {
+            ghost_nodes: env_flag("GRAPH_GHOST_NODES"),
+            private_ghost_nodes: env_flag("GRAPH_PRIVATE_GHOST_NODES"),
+            tag_nodes: env_flag("GRAPH_TAG_NODES"),
+            journal_edges: env_flag("GRAPH_JOURNAL_EDGES"),
+            duplicate_edges: env_flag("GRAPH_DUPLICATE_EDGES"),
+            task_nodes: env_flag("GRAPH_TASK_NODES"),
+        }
+    }
+}
+
+fn env_flag(name: &str) -> bool {
+    env::var(name)
+        .map(|value| matches!(value.trim().to_lowercase().as_str(), "1" | "true" | "yes" | "on"))
+        .unwrap_or(false)
+}
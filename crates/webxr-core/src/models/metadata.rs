@@ -0,0 +1,191 @@
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Stores metadata about a processed file.
+/// All fields use camelCase serialization for client compatibility.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct Metadata {
+    #[serde(default)]
+    pub file_name: String,
+    #[serde(default)]
+    pub file_size: usize,
+    #[serde(default)]
+    pub node_size: f64,
+    #[serde(default)]
+    pub hyperlink_count: usize,
+    #[serde(default)]
+    pub sha1: String,
+    #[serde(default = "default_node_id")]
+    pub node_id: String,
+    #[serde(default = "Utc::now")]
+    pub last_modified: DateTime<Utc>,
+    #[serde(default)]
+    pub perplexity_link: String,
+    #[serde(default)]
+    pub last_perplexity_process: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub perplexity_summary: String,
+    #[serde(default)]
+    pub topic_counts: HashMap<String, usize>,
+    /// Link targets that match no page, with how often each is linked
+    #[serde(default)]
+    pub unresolved_links: HashMap<String, usize>,
+    /// Link targets that are pages in the repository but not published, counted
+    /// like `unresolved_links`
+    #[serde(default)]
+    pub private_links: HashMap<String, usize>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Other names references to this page may use, from its `alias::` property
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// Files with nearly the same content, with the Jaccard similarity of their
+    /// word shingles
+    #[serde(default)]
+    pub near_duplicates: HashMap<String, f32>,
+    /// Result of the last check of each external link in this file, by URL
+    #[serde(default)]
+    pub link_checks: HashMap<String, LinkCheck>,
+    /// Previews of the external pages this file links to, in link order
+    #[serde(default)]
+    pub link_previews: Vec<LinkPreview>,
+    /// Word count, reading time and heading outline of the page
+    #[serde(flatten)]
+    pub page_stats: PageStats,
+    /// `TODO`, `DONE` and other task blocks in the page, in document order
+    #[serde(default)]
+    pub tasks: Vec<Task>,
+}
+
+/// A Logseq task block such as `- TODO Write report`, with its schedule
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Task {
+    /// `TODO`, `DOING`, `NOW`, `LATER`, `WAITING`, `DONE` or `CANCELED`
+    pub marker: String,
+    pub text: String,
+    /// `A`, `B` or `C` from `[#A]`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub priority: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scheduled: Option<TaskDate>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deadline: Option<TaskDate>,
+    /// `#tags` in the task's text, as written
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Pages the task's text `[[links]]` to, as written
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub references: Vec<String>,
+    /// Line of the task in the page, from 1
+    pub line: usize,
+}
+
+impl Task {
+    pub fn is_done(&self) -> bool {
+        matches!(self.marker.as_str(), "DONE" | "CANCELED" | "CANCELLED")
+    }
+}
+
+/// A `SCHEDULED:` or `DEADLINE:` date, with its time of day when it has one
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TaskDate {
+    pub date: NaiveDate,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub time: Option<NaiveTime>,
+}
+
+/// Size and structure of a page's prose, for node tooltips
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PageStats {
+    /// Words outside code blocks and front matter
+    #[serde(default)]
+    pub word_count: usize,
+    /// Estimated minutes to read, at 200 words a minute
+    #[serde(default)]
+    pub reading_time_minutes: u32,
+    #[serde(default)]
+    pub outline: Vec<Heading>,
+}
+
+/// A heading in a page, in document order
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Heading {
+    /// 1 for `#`, up to 6 for `######`
+    pub level: u8,
+    pub text: String,
+}
+
+/// Whether an external link answered when last checked
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkCheck {
+    pub checked_at: DateTime<Utc>,
+    /// HTTP status of the final response, if there was one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<u16>,
+    /// Why no response arrived, such as a DNS failure or timeout
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub broken: bool,
+}
+
+/// The Open Graph title and image of an external page, or its `<title>` when it
+/// has no Open Graph tags
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkPreview {
+    pub url: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub image: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub site_name: Option<String>,
+}
+
+// Default function for node_id to ensure backward compatibility
+fn default_node_id() -> String {
+    // Will be replaced with actual ID during processing
+    "0".to_string()
+}
+
+/// Type alias for metadata storage with camelCase keys
+pub type MetadataStore = HashMap<String, Metadata>;
+
+// Implement helper methods directly on HashMap<String, Metadata>
+pub trait MetadataOps {
+    fn validate_files(&self, markdown_dir: &str) -> bool;
+    fn get_max_node_id(&self) -> u32;
+}
+
+impl MetadataOps for MetadataStore {
+    fn get_max_node_id(&self) -> u32 {
+        // Find the maximum node_id in the metadata store
+        self.values()
+            .map(|m| m.node_id.parse::<u32>().unwrap_or(0))
+            .max()
+            .unwrap_or(0)
+    }
+    
+    fn validate_files(&self, markdown_dir: &str) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+
+        // Check if the markdown files referenced in metadata actually exist
+        for filename in self.keys() {
+            let file_path = format!("{}/{}", markdown_dir, filename);
+            if !std::path::Path::new(&file_path).exists() {
+                return false;
+            }
+        }
+        
+        true
+    }
+}
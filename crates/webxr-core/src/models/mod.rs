@@ -0,0 +1,6 @@
+pub mod edge;
+pub mod graph;
+pub mod metadata;
+pub mod node;
+
+pub use metadata::MetadataStore;
@@ -1,8 +1,19 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicU32, Ordering};
-use crate::utils::socket_flow_messages::BinaryNodeData;
-use crate::types::vec3::Vec3Data;
+use crate::node_data::BinaryNodeData;
+use crate::vec3::Vec3Data;
+
+/// `node_type` of placeholder nodes standing in for pages that don't exist
+pub const GHOST_NODE_TYPE: &str = "ghost";
+/// `node_type` of nodes standing for a tag, linked to every page carrying it
+pub const TAG_NODE_TYPE: &str = "tag";
+/// `node_type` of Logseq journal pages
+pub const JOURNAL_NODE_TYPE: &str = "journal";
+/// `node_type` of notes pulled in from Nostr relays
+pub const NOSTR_NODE_TYPE: &str = "nostr";
+/// `node_type` of open tasks shown in the work view
+pub const TASK_NODE_TYPE: &str = "task";
 
 // Static counter for generating unique numeric IDs
 static NEXT_NODE_ID: AtomicU32 = AtomicU32::new(1);  // Start from 1 (0 could be reserved)
@@ -83,7 +94,18 @@ impl Node {
         // Calculate mass using log scale to prevent extremely large masses
         let base_mass = ((size + 1) as f32).log10() / 4.0;
         // Scale to 0-255 range for u8
-        self.data.mass = ((base_mass.max(0.1).min(10.0) * 25.5) as u8).max(1);
+        self.data.mass = ((base_mass.clamp(0.1, 10.0) * 25.5) as u8).max(1);
+    }
+
+    /// Ghost, tag, Nostr and task nodes are added when the graph is built and have no file
+    pub fn is_page(&self) -> bool {
+        !matches!(self.node_type.as_deref(), Some(GHOST_NODE_TYPE | TAG_NODE_TYPE | NOSTR_NODE_TYPE | TASK_NODE_TYPE))
+    }
+
+    /// Tags get heavier the more pages carry them, so popular tags settle as hubs
+    pub fn set_tag_mass(&mut self, page_count: usize) {
+        let base_mass = ((page_count + 1) as f32).log2();
+        self.data.mass = (base_mass.clamp(1.0, 10.0) * 25.5) as u8;
     }
 
     pub fn with_position(mut self, x: f32, y: f32, z: f32) -> Self {
@@ -176,6 +198,17 @@ mod tests {
         assert_eq!(end_value, start_value + 2);
     }
 
+    #[test]
+    fn test_tag_mass() {
+        let mut node = Node::new("tag:rust".to_string());
+        node.set_tag_mass(1);
+        assert_eq!(node.data.mass, 25);
+        node.set_tag_mass(15);
+        assert_eq!(node.data.mass, 102);
+        node.set_tag_mass(100_000);
+        assert_eq!(node.data.mass, 255);
+    }
+
     #[test]
     fn test_node_creation() {
         let node = Node::new("test".to_string())
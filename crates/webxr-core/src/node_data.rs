@@ -0,0 +1,72 @@
+//! Per-node physics state and its layouts
+//!
+//! `BinaryNodeData` is the one definition of a node's state: the server keeps it
+//! on every `Node`, and the CUDA kernels read it from device memory in exactly this
+//! layout. `WireNodeDataItem` is the float32 record sent to clients, which carries
+//! the node id and leaves out the server-only fields. Converting between the two
+//! goes through the functions here, so the wire never sees mass or flags and
+//! decoded records always get the same defaults. The quantized wire record is
+//! built from a `WireNodeDataItem` in `binary_protocol`.
+
+use bytemuck::{Pod, Zeroable};
+use serde::{Deserialize, Serialize};
+
+use crate::vec3::Vec3Data;
+
+/// Mass given to nodes decoded from the wire, until the server's copy replaces it
+pub const WIRE_DEFAULT_MASS: u8 = 100;
+
+/// Server and GPU layout of a node, 28 bytes
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable, Serialize, Deserialize)]
+pub struct BinaryNodeData {
+    pub position: Vec3Data, // 12 bytes
+    pub velocity: Vec3Data, // 12 bytes
+    pub mass: u8,           // 1 byte, server-side only
+    pub flags: u8,          // 1 byte, server-side only
+    pub padding: [u8; 2],   // 2 bytes, read by the kernel as type multipliers
+}
+
+static_assertions::const_assert_eq!(std::mem::size_of::<BinaryNodeData>(), 28);
+
+pub const GPU_NODE_SIZE: usize = std::mem::size_of::<BinaryNodeData>();
+
+// The CUDA kernels read nodes in this layout straight from device memory
+#[cfg(feature = "cuda")]
+unsafe impl cudarc::driver::DeviceRepr for BinaryNodeData {}
+
+#[cfg(feature = "cuda")]
+unsafe impl cudarc::driver::ValidAsZeroBits for BinaryNodeData {}
+
+impl BinaryNodeData {
+    /// State for a node known only by its motion, e.g. from a client's frame. Mass
+    /// and flags are defaults for the server to fill in from its own copy.
+    pub fn from_motion(position: Vec3Data, velocity: Vec3Data) -> Self {
+        Self { position, velocity, mass: WIRE_DEFAULT_MASS, flags: 0, padding: [0, 0] }
+    }
+}
+
+impl From<&WireNodeDataItem> for BinaryNodeData {
+    fn from(item: &WireNodeDataItem) -> Self {
+        Self::from_motion(item.position, item.velocity)
+    }
+}
+
+/// Float32 wire record of a node, 28 bytes, little endian
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Pod, Zeroable)]
+pub struct WireNodeDataItem {
+    pub id: u32,            // 4 bytes
+    pub position: Vec3Data, // 12 bytes
+    pub velocity: Vec3Data, // 12 bytes
+}
+
+static_assertions::const_assert_eq!(std::mem::size_of::<WireNodeDataItem>(), 28);
+
+pub const WIRE_NODE_SIZE: usize = std::mem::size_of::<WireNodeDataItem>();
+
+impl WireNodeDataItem {
+    pub fn new(id: u32, data: &BinaryNodeData) -> Self {
+        Self { id, position: data.position, velocity: data.velocity }
+    }
+}
@@ -0,0 +1,203 @@
+//! Page properties, inline tags and prose statistics, as written by Logseq and
+//! other markdown tools
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use std::collections::HashMap;
+
+use chrono::{NaiveDate, NaiveTime};
+
+use crate::models::metadata::{Heading, PageStats, Task, TaskDate};
+
+// `key:: value` page properties as written by Logseq
+static LOGSEQ_PROPERTY: Lazy<Regex> = Lazy::new(|| Regex::new(r"^([A-Za-z0-9_-]+)::\s*(.*)$").unwrap());
+// `key: value` lines in YAML front matter
+static FRONT_MATTER_PROPERTY: Lazy<Regex> = Lazy::new(|| Regex::new(r"^([A-Za-z0-9_-]+):\s*(.*)$").unwrap());
+// `#tag` and `#[[multi word tag]]`
+static INLINE_TAG: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?:^|\s)#(?:\[\[([^\]]+)\]\]|([^\s#\[\],.;:!?]+))").unwrap());
+// `## Heading`, optionally as a Logseq `- ## Heading` block
+static HEADING: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(?:[-*]\s+)?(#{1,6})\s+(.+?)\s*#*$").unwrap());
+// `- TODO [#A] text`
+static TASK: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^[-*]\s+(TODO|DOING|NOW|LATER|WAITING|DONE|CANCELED|CANCELLED)\s+(?:\[#([A-C])\]\s*)?(.*)$").unwrap()
+});
+// `[[page]]` references in a task's text
+static PAGE_REFERENCE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\[\[([^\[\]]+)\]\]").unwrap());
+// `SCHEDULED: <2024-01-10 Wed 09:30 .+1w>`
+static AGENDA_DATE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(SCHEDULED|DEADLINE):\s*<(\d{4}-\d{2}-\d{2})(?:\s+[A-Za-z]+)?(?:\s+(\d{1,2}:\d{2}))?[^>]*>").unwrap()
+});
+
+const WORDS_PER_MINUTE: usize = 200;
+
+/// Properties at the top of the page, either Logseq `key:: value` lines or YAML
+/// front matter. Keys are lowercased.
+pub fn page_properties(content: &str) -> HashMap<String, String> {
+    let mut properties = HashMap::new();
+    let mut lines = content.trim_start().lines();
+
+    if content.trim_start().starts_with("---") {
+        lines.next();
+        for line in lines.take_while(|line| line.trim() != "---") {
+            if let Some(caps) = FRONT_MATTER_PROPERTY.captures(line.trim()) {
+                properties.insert(caps[1].to_lowercase(), caps[2].trim().trim_matches('"').to_string());
+            }
+        }
+        return properties;
+    }
+
+    for line in lines {
+        match LOGSEQ_PROPERTY.captures(line.trim()) {
+            Some(caps) => {
+                properties.insert(caps[1].to_lowercase(), caps[2].trim().to_string());
+            }
+            None => break,
+        }
+    }
+    properties
+}
+
+/// Other names the page goes by, from a Logseq `alias::` property or an
+/// `aliases` front matter list, as written
+pub fn page_aliases(content: &str) -> Vec<String> {
+    let properties = page_properties(content);
+    let Some(list) = properties.get("alias").or_else(|| properties.get("aliases")) else {
+        return Vec::new();
+    };
+    list.trim_matches(|c| c == '[' || c == ']')
+        .split(',')
+        .map(|alias| alias.trim_matches(|c: char| c.is_whitespace() || matches!(c, '[' | ']' | '"' | '\'')))
+        .filter(|alias| !alias.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Names of the `#tag`s and `#[[multi word tag]]`s in `content`, as written
+pub fn inline_tags(content: &str) -> impl Iterator<Item = &str> {
+    INLINE_TAG.captures_iter(content)
+        .filter_map(|caps| caps.get(1).or_else(|| caps.get(2)).map(|tag| tag.as_str()))
+}
+
+/// Word count, reading time and heading outline of `content`. Front matter and
+/// fenced code blocks are left out of both counts and outline.
+pub fn page_stats(content: &str) -> PageStats {
+    let mut lines = content.lines();
+    if content.trim_start().starts_with("---") {
+        lines.by_ref().find(|line| line.trim() == "---");
+        lines.by_ref().find(|line| line.trim() == "---");
+    }
+
+    let mut word_count = 0;
+    let mut outline = Vec::new();
+    let mut in_code = false;
+    for line in lines {
+        let trimmed = line.trim();
+        if trimmed.trim_start_matches(['-', '*', ' ']).starts_with("```") {
+            in_code = !in_code;
+            continue;
+        }
+        if in_code || LOGSEQ_PROPERTY.is_match(trimmed) {
+            continue;
+        }
+        if let Some(caps) = HEADING.captures(trimmed) {
+            outline.push(Heading { level: caps[1].len() as u8, text: caps[2].to_string() });
+        }
+        word_count += trimmed.split_whitespace()
+            .filter(|word| word.chars().any(char::is_alphanumeric))
+            .count();
+    }
+
+    PageStats {
+        word_count,
+        reading_time_minutes: word_count.div_ceil(WORDS_PER_MINUTE) as u32,
+        outline,
+    }
+}
+
+/// The task blocks in `content` with the `SCHEDULED:` and `DEADLINE:` dates
+/// written on them or on the lines below them. Tasks in code blocks are skipped.
+pub fn page_tasks(content: &str) -> Vec<Task> {
+    let mut tasks: Vec<Task> = Vec::new();
+    // Whether the block being read is the last task found
+    let mut in_task = false;
+    let mut in_code = false;
+    for (index, line) in content.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.trim_start_matches(['-', '*', ' ']).starts_with("```") {
+            in_code = !in_code;
+            continue;
+        }
+        if in_code {
+            continue;
+        }
+        if trimmed.starts_with("- ") || trimmed.starts_with("* ") || trimmed == "-" {
+            in_task = false;
+            if let Some(caps) = TASK.captures(trimmed) {
+                let text = AGENDA_DATE.replace_all(&caps[3], "").trim().to_string();
+                let mut references: Vec<String> = PAGE_REFERENCE.captures_iter(&text)
+                    .map(|reference| reference[1].trim().to_string())
+                    .collect();
+                references.dedup();
+                tasks.push(Task {
+                    marker: caps[1].to_string(),
+                    priority: caps.get(2).map(|priority| priority.as_str().to_string()),
+                    scheduled: None,
+                    deadline: None,
+                    tags: inline_tags(&text).map(str::to_string).collect(),
+                    references,
+                    text,
+                    line: index + 1,
+                });
+                in_task = true;
+            }
+        }
+        let Some(task) = tasks.last_mut().filter(|_| in_task) else { continue };
+        for caps in AGENDA_DATE.captures_iter(trimmed) {
+            let Ok(date) = NaiveDate::parse_from_str(&caps[2], "%Y-%m-%d") else { continue };
+            let time = caps.get(3).and_then(|time| NaiveTime::parse_from_str(time.as_str(), "%H:%M").ok());
+            let slot = if &caps[1] == "SCHEDULED" { &mut task.scheduled } else { &mut task.deadline };
+            *slot = Some(TaskDate { date, time });
+        }
+    }
+    tasks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_page_stats() {
+        let content = "---\ntitle: Rust\n---\n# Rust\n\nA systems language.\n- ## Ownership ##\n  - Each value has one owner\n```rust\nlet x = 1;\n```\n### Borrowing\n";
+        let stats = page_stats(content);
+        assert_eq!(stats.outline, vec![
+            Heading { level: 1, text: "Rust".to_string() },
+            Heading { level: 2, text: "Ownership".to_string() },
+            Heading { level: 3, text: "Borrowing".to_string() },
+        ]);
+        assert_eq!(stats.word_count, 11);
+        assert_eq!(stats.reading_time_minutes, 1);
+        assert_eq!(page_stats("").reading_time_minutes, 0);
+    }
+
+    #[test]
+    fn test_page_tasks() {
+        let content = "- TODO [#A] Write report for [[Acme]] #work\n  SCHEDULED: <2024-01-10 Wed>\n  DEADLINE: <2024-01-12 Fri 10:00 +1w>\n  - notes\n- DONE Ship it SCHEDULED: <2024-01-09 Tue>\n```\n- TODO not a task\n```\n- todo lowercase isn't either";
+        let tasks = page_tasks(content);
+        assert_eq!(tasks.len(), 2);
+        assert_eq!(tasks[0].text, "Write report for [[Acme]] #work");
+        assert_eq!((tasks[0].references.clone(), tasks[0].tags.clone()), (vec!["Acme".to_string()], vec!["work".to_string()]));
+        assert_eq!(tasks[0].priority.as_deref(), Some("A"));
+        assert_eq!(tasks[0].scheduled, Some(TaskDate { date: NaiveDate::from_ymd_opt(2024, 1, 10).unwrap(), time: None }));
+        assert_eq!(tasks[0].deadline.unwrap().time, NaiveTime::from_hms_opt(10, 0, 0));
+        assert_eq!((tasks[1].text.as_str(), tasks[1].line, tasks[1].is_done()), ("Ship it", 5, true));
+        assert!(tasks[1].scheduled.is_some());
+    }
+
+    #[test]
+    fn test_page_aliases() {
+        assert_eq!(page_aliases("alias:: Rust Lang, [[rustlang]]\n- body"), vec!["Rust Lang", "rustlang"]);
+        assert_eq!(page_aliases("---\naliases: [\"Graphs\", networks]\n---\n"), vec!["Graphs", "networks"]);
+        assert!(page_aliases("- alias:: not a page property").is_empty());
+    }
+}
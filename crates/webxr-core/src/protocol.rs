@@ -0,0 +1,202 @@
+//! Versioned framing and conformance vectors for the binary protocol
+//!
+//! A client that sends `protocolVersion` when it requests data gets every binary
+//! frame, positions and edges alike, behind an 8-byte header naming the protocol
+//! version, the kind of frame and its record encoding. Its first word, like the
+//! edge frame marker, is never a node id. The `vectors` are exact encodings the
+//! client's tests can check against, and `tests/golden` holds whole frames, so a
+//! change to a record layout on either side fails CI rather than a live session.
+
+use bytemuck::{Pod, Zeroable};
+use bytes::{Bytes, BytesMut};
+
+use crate::binary_protocol::{DecodeError, WireEncoding};
+
+/// Bumped whenever a record layout or the header changes
+pub const PROTOCOL_VERSION: u16 = 1;
+/// First word of a framed message
+pub const FRAME_MAGIC: u32 = 0xFFFF_FFFE;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Pod, Zeroable)]
+pub struct FrameHeader {
+    pub magic: u32,   // 4 bytes, always FRAME_MAGIC
+    pub version: u16, // 2 bytes
+    pub kind: u8,     // 1 byte, a FrameKind
+    pub encoding: u8, // 1 byte, 0 for float32 and 1 for quantized16 records
+    // Total: 8 bytes
+}
+
+static_assertions::const_assert_eq!(std::mem::size_of::<FrameHeader>(), 8);
+
+pub const FRAME_HEADER_SIZE: usize = std::mem::size_of::<FrameHeader>();
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameKind {
+    Positions = 0,
+    /// An edge frame, itself starting with the edge frame header
+    Edges = 1,
+}
+
+impl FrameHeader {
+    pub fn new(kind: FrameKind, encoding: WireEncoding) -> Self {
+        let encoding = match encoding {
+            WireEncoding::Float32 => 0,
+            WireEncoding::Quantized16 => 1,
+        };
+        Self { magic: FRAME_MAGIC, version: PROTOCOL_VERSION, kind: kind as u8, encoding }
+    }
+
+    pub fn kind(&self) -> Option<FrameKind> {
+        match self.kind {
+            0 => Some(FrameKind::Positions),
+            1 => Some(FrameKind::Edges),
+            _ => None,
+        }
+    }
+
+    pub fn encoding(&self) -> Option<WireEncoding> {
+        match self.encoding {
+            0 => Some(WireEncoding::Float32),
+            1 => Some(WireEncoding::Quantized16),
+            _ => None,
+        }
+    }
+}
+
+/// `payload` behind a header for the current version
+pub fn frame(kind: FrameKind, encoding: WireEncoding, payload: &[u8]) -> Bytes {
+    let mut buffer = BytesMut::with_capacity(FRAME_HEADER_SIZE + payload.len());
+    buffer.extend_from_slice(bytemuck::bytes_of(&FrameHeader::new(kind, encoding)));
+    buffer.extend_from_slice(payload);
+    buffer.freeze()
+}
+
+/// Splits a framed message into its header and payload. Only frames of the current
+/// version are accepted.
+pub fn split_frame(data: &[u8]) -> Result<(FrameHeader, &[u8]), DecodeError> {
+    if data.len() < FRAME_HEADER_SIZE {
+        return Err(DecodeError::MissingHeader);
+    }
+    let header: FrameHeader = bytemuck::pod_read_unaligned(&data[..FRAME_HEADER_SIZE]);
+    if header.magic != FRAME_MAGIC {
+        return Err(DecodeError::MissingHeader);
+    }
+    if header.version != PROTOCOL_VERSION {
+        return Err(DecodeError::UnsupportedVersion { version: header.version });
+    }
+    Ok((header, &data[FRAME_HEADER_SIZE..]))
+}
+
+/// Exact encodings of single records, for checking another implementation of the
+/// protocol against this one. All values are little endian.
+pub mod vectors {
+    use crate::binary_protocol::{QuantizationBounds, WireEdgeItem};
+
+    /// A node record and its encoding
+    #[derive(Debug, Clone, Copy)]
+    pub struct NodeVector {
+        pub id: u32,
+        pub position: [f32; 3],
+        pub velocity: [f32; 3],
+        pub bytes: &'static [u8],
+    }
+
+    pub const FLOAT32_NODE: NodeVector = NodeVector {
+        id: 1,
+        position: [10.0, 20.0, 30.0],
+        velocity: [0.1, 0.2, 0.3],
+        bytes: &[
+            0x01, 0x00, 0x00, 0x00, // id
+            0x00, 0x00, 0x20, 0x41, 0x00, 0x00, 0xA0, 0x41, 0x00, 0x00, 0xF0, 0x41, // position
+            0xCD, 0xCC, 0xCC, 0x3D, 0xCD, 0xCC, 0x4C, 0x3E, 0x9A, 0x99, 0x99, 0x3E, // velocity
+        ],
+    };
+
+    /// Bounds `QUANTIZED_NODE` is encoded against
+    pub const QUANTIZED_BOUNDS: QuantizationBounds = QuantizationBounds { half_extent: 15.0, max_velocity: 0.02 };
+
+    /// Decodes to within one quantization step of its position and velocity
+    pub const QUANTIZED_NODE: NodeVector = NodeVector {
+        id: 7,
+        position: [0.0, 7.5, -15.0],
+        velocity: [0.01, -0.02, 0.0],
+        bytes: &[
+            0x07, 0x00, 0x00, 0x00, // id
+            0x00, 0x80, 0xFF, 0xBF, 0x00, 0x00, // position, u16 × 3
+            0x00, 0x40, 0x01, 0x80, 0x00, 0x00, // velocity, i16 × 3
+        ],
+    };
+
+    /// Header of a framed float32 position frame
+    pub const POSITIONS_HEADER: [u8; 8] = [0xFE, 0xFF, 0xFF, 0xFF, 0x01, 0x00, 0x00, 0x00];
+
+    pub const SNAPSHOT_EDGE: WireEdgeItem = WireEdgeItem { source: 1, target: 2, weight: 1.5, edge_type: 1 };
+
+    /// An edge snapshot frame holding `SNAPSHOT_EDGE`
+    pub const EDGE_SNAPSHOT: [u8; 24] = [
+        0xFF, 0xFF, 0xFF, 0xFF, 0x00, 0x00, 0x00, 0x00, // marker, kind
+        0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, // source, target
+        0x00, 0x00, 0xC0, 0x3F, 0x01, 0x00, 0x00, 0x00, // weight, edge type
+    ];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::vectors::*;
+    use super::*;
+    use crate::binary_protocol::{decode_edge_frame, decode_node_data, decode_quantized_node_data, encode_edge_frame, encode_node_data, EdgeFrameKind, FrameEncoder};
+    use crate::node_data::BinaryNodeData;
+    use crate::vec3::Vec3Data;
+
+    fn node_data(vector: &NodeVector) -> BinaryNodeData {
+        BinaryNodeData {
+            position: Vec3Data::from(vector.position),
+            velocity: Vec3Data::from(vector.velocity),
+            mass: 100,
+            flags: 0,
+            padding: [0, 0],
+        }
+    }
+
+    #[test]
+    fn test_vectors() {
+        assert_eq!(encode_node_data(&[(FLOAT32_NODE.id, node_data(&FLOAT32_NODE))]), FLOAT32_NODE.bytes);
+        let (id, data) = decode_node_data(FLOAT32_NODE.bytes).unwrap()[0];
+        assert_eq!(id, FLOAT32_NODE.id);
+        assert_eq!(<[f32; 3]>::from(data.position), FLOAT32_NODE.position);
+        assert_eq!(<[f32; 3]>::from(data.velocity), FLOAT32_NODE.velocity);
+
+        let frame = FrameEncoder::new().encode(vec![(QUANTIZED_NODE.id, node_data(&QUANTIZED_NODE))]);
+        assert_eq!(&frame.select_quantized(&[0], &QUANTIZED_BOUNDS)[..], QUANTIZED_NODE.bytes);
+        let (id, data) = decode_quantized_node_data(QUANTIZED_NODE.bytes, &QUANTIZED_BOUNDS).unwrap()[0];
+        assert_eq!(id, QUANTIZED_NODE.id);
+        let position_step = 2.0 * QUANTIZED_BOUNDS.half_extent / u16::MAX as f32;
+        let velocity_step = QUANTIZED_BOUNDS.max_velocity / i16::MAX as f32;
+        let (position, velocity) = (<[f32; 3]>::from(data.position), <[f32; 3]>::from(data.velocity));
+        for axis in 0..3 {
+            assert!((position[axis] - QUANTIZED_NODE.position[axis]).abs() <= position_step);
+            assert!((velocity[axis] - QUANTIZED_NODE.velocity[axis]).abs() <= velocity_step);
+        }
+
+        assert_eq!(encode_edge_frame(EdgeFrameKind::Snapshot, &[SNAPSHOT_EDGE]), &EDGE_SNAPSHOT[..]);
+        assert_eq!(decode_edge_frame(&EDGE_SNAPSHOT).unwrap(), (EdgeFrameKind::Snapshot, vec![SNAPSHOT_EDGE]));
+    }
+
+    #[test]
+    fn test_framing() {
+        let framed = frame(FrameKind::Positions, WireEncoding::Float32, FLOAT32_NODE.bytes);
+        assert_eq!(&framed[..FRAME_HEADER_SIZE], &POSITIONS_HEADER);
+        let (header, payload) = split_frame(&framed).unwrap();
+        assert_eq!(header.kind(), Some(FrameKind::Positions));
+        assert_eq!(header.encoding(), Some(WireEncoding::Float32));
+        assert_eq!(payload, FLOAT32_NODE.bytes);
+
+        let mut future = framed.to_vec();
+        future[4] = 2;
+        assert_eq!(split_frame(&future), Err(DecodeError::UnsupportedVersion { version: 2 }));
+        assert_eq!(split_frame(FLOAT32_NODE.bytes), Err(DecodeError::MissingHeader));
+        assert_eq!(split_frame(&POSITIONS_HEADER[..4]), Err(DecodeError::MissingHeader));
+    }
+}
@@ -0,0 +1,386 @@
+//! Reference extraction for different note-taking conventions
+//!
+//! Graph edges come from the references one page makes to another. Logseq vaults are
+//! matched on bare page names anywhere in the text, which is how the graph has always
+//! been built. Vaults from other tools link explicitly instead, so their profiles only
+//! count actual links and resolve them to page names.
+//!
+//! References are resolved through a [`PageIndex`], so a page's aliases resolve to
+//! the page itself, and pages on the `REFERENCE_IGNORE` stop-list get no edges.
+//! Names are compared after NFC normalisation and lowercasing, so `[[AI]]`, `[[ai]]`
+//! and a decomposed `[[Café]]` each find their page.
+
+use log::{debug, warn};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fmt;
+use std::str::FromStr;
+use unicode_normalization::UnicodeNormalization;
+
+use crate::models::metadata::MetadataStore;
+use crate::page::{inline_tags, page_properties};
+
+// `[[page]]`, `[[page|alias]]`, `[[page#heading]]` and `![[embed]]`
+static WIKI_LINK: Lazy<Regex> = Lazy::new(|| Regex::new(r"!?\[\[([^\[\]]+?)\]\]").unwrap());
+// `[text](relative/page.md)`, optionally with angle brackets or a title
+static MARKDOWN_LINK: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r#"!?\[[^\]]*\]\(\s*<?([^)\s>]+)>?(?:\s+"[^"]*")?\s*\)"#).unwrap());
+
+const PAGE_EXTENSIONS: [&str; 2] = [".md", ".markdown"];
+
+/// The pages references can resolve to, with the aliases they declare and the
+/// names whose references are ignored
+#[derive(Debug, Clone, Default)]
+pub struct PageIndex {
+    /// Canonical page names, in the order given
+    names: Vec<String>,
+    /// Canonical page name by normalised name
+    lookup: HashMap<String, String>,
+    /// Canonical page name by normalised alias
+    aliases: HashMap<String, String>,
+    /// Normalised names of pages and aliases to drop references to
+    ignored: HashSet<String>,
+}
+
+impl PageIndex {
+    /// Indexes `names`. Where several normalise to the same name, the first is the
+    /// canonical page every reference resolves to.
+    pub fn new(names: Vec<String>) -> Self {
+        let mut lookup = HashMap::new();
+        let mut canonical = Vec::new();
+        for name in names {
+            if let Entry::Vacant(entry) = lookup.entry(normalize_name(&name)) {
+                entry.insert(name.clone());
+                canonical.push(name);
+            }
+        }
+        Self { names: canonical, lookup, ..Default::default() }
+    }
+
+    /// Indexes the pages in `metadata` with their aliases, ignoring the pages named
+    /// in `REFERENCE_IGNORE`
+    pub fn from_metadata(metadata: &MetadataStore) -> Self {
+        let mut names: Vec<String> = metadata.keys().map(|name| name.trim_end_matches(".md").to_string()).collect();
+        names.sort_unstable();
+        let mut index = Self::new(names);
+        for (file_name, page) in metadata {
+            for alias in &page.aliases {
+                index.add_alias(alias, file_name.trim_end_matches(".md"));
+            }
+        }
+        index.with_ignored(ignored_from_env())
+    }
+
+    /// Resolves `alias` to `page`. Aliases that are page names in their own right,
+    /// or already claimed by another page, are left alone.
+    pub fn add_alias(&mut self, alias: &str, page: &str) {
+        let key = normalize_name(alias);
+        if key.is_empty() || self.lookup.contains_key(&key) {
+            return;
+        }
+        match self.aliases.entry(key) {
+            Entry::Occupied(existing) => {
+                debug!("Alias '{}' of {} is already an alias of {}", alias, page, existing.get());
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(page.to_string());
+            }
+        }
+    }
+
+    pub fn with_ignored(mut self, names: impl IntoIterator<Item = String>) -> Self {
+        self.ignored.extend(names.into_iter().map(|name| normalize_name(&name)));
+        self
+    }
+
+    /// The page `name` refers to, if it is a page or an alias of one
+    fn canonical(&self, name: &str) -> Option<&String> {
+        let key = normalize_name(name);
+        self.lookup.get(&key).or_else(|| self.aliases.get(&key))
+    }
+
+    fn is_ignored(&self, name: &str, page: &str) -> bool {
+        self.ignored.contains(&normalize_name(name)) || self.ignored.contains(&normalize_name(page))
+    }
+}
+
+/// The form page names are compared in: NFC-normalised, trimmed and lowercased
+pub fn normalize_name(name: &str) -> String {
+    name.trim().nfc().collect::<String>().to_lowercase()
+}
+
+/// Page names from the comma-separated `REFERENCE_IGNORE` stop-list, such as the
+/// pages a daily template links to on every journal
+pub fn ignored_from_env() -> Vec<String> {
+    env::var("REFERENCE_IGNORE")
+        .map(|list| list.split(',').map(str::trim).filter(|name| !name.is_empty()).map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ParserProfile {
+    /// Any case-insensitive whole-word mention of a page name
+    #[default]
+    Logseq,
+    /// `[[page]]` wiki links, including `[[page|alias]]` and `[[page#heading]]`
+    Obsidian,
+    /// Standard `[text](page.md)` links to relative paths
+    Markdown,
+}
+
+impl FromStr for ParserProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "logseq" => Ok(ParserProfile::Logseq),
+            "obsidian" => Ok(ParserProfile::Obsidian),
+            "markdown" | "md" => Ok(ParserProfile::Markdown),
+            other => Err(format!("Unknown parser profile '{}'", other)),
+        }
+    }
+}
+
+impl fmt::Display for ParserProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ParserProfile::Logseq => "logseq",
+            ParserProfile::Obsidian => "obsidian",
+            ParserProfile::Markdown => "markdown",
+        };
+        f.write_str(name)
+    }
+}
+
+impl ParserProfile {
+    /// Reads `MARKDOWN_PARSER_PROFILE`, falling back to Logseq when unset or invalid
+    pub fn from_env() -> Self {
+        match env::var("MARKDOWN_PARSER_PROFILE") {
+            Ok(value) if !value.trim().is_empty() => value.parse().unwrap_or_else(|e| {
+                warn!("{}; using the logseq profile", e);
+                ParserProfile::default()
+            }),
+            _ => ParserProfile::default(),
+        }
+    }
+
+    /// Returns one entry per reference to a page in `pages`, using the page's name
+    /// as spelled in the index. References to aliases count for the aliased page.
+    pub fn extract_references(self, content: &str, pages: &PageIndex) -> Vec<String> {
+        match self {
+            ParserProfile::Logseq => extract_mentions(content, pages),
+            _ => resolve_links(self.link_targets(content).into_iter(), pages),
+        }
+    }
+
+    /// Explicit links to neither a page nor an alias in `pages`, counted per target
+    /// page and keyed by its first spelling. Logseq mentions can't dangle, so only
+    /// its `[[links]]` are checked. Ignored names are never reported.
+    pub fn unresolved_links(self, content: &str, pages: &PageIndex) -> HashMap<String, usize> {
+        let mut unresolved: HashMap<String, (String, usize)> = HashMap::new();
+        for target in self.link_targets(content) {
+            let title = page_title(target);
+            let name = normalize_name(&title);
+            if !title.is_empty() && pages.canonical(&title).is_none() && !pages.ignored.contains(&name) {
+                unresolved.entry(name).or_insert((title, 0)).1 += 1;
+            }
+        }
+        unresolved.into_values().collect()
+    }
+
+    /// Tags on the page, deduplicated case-insensitively and keeping their first
+    /// spelling. Plain Markdown has no tag syntax, so that profile finds none.
+    pub fn tags(self, content: &str) -> Vec<String> {
+        let mut tags: Vec<String> = Vec::new();
+        match self {
+            ParserProfile::Logseq | ParserProfile::Obsidian => {
+                // `tags:: a, [[b]]` in Logseq, `tags: [a, b]` in front matter
+                if let Some(value) = page_properties(content).get("tags") {
+                    tags.extend(split_tag_list(value));
+                }
+                tags.extend(inline_tags(content)
+                    .map(|tag| tag.trim().to_string())
+                    // `#1` is an issue number, not a tag
+                    .filter(|tag| !tag.chars().all(|c| c.is_ascii_digit())));
+            }
+            ParserProfile::Markdown => {}
+        }
+
+        let mut seen = HashSet::new();
+        tags.retain(|tag| seen.insert(tag.to_lowercase()));
+        tags
+    }
+
+    fn link_targets(self, content: &str) -> Vec<&str> {
+        match self {
+            ParserProfile::Logseq | ParserProfile::Obsidian => WIKI_LINK.captures_iter(content)
+                .map(|c| {
+                    // Aliases follow `|`, which is escaped inside tables
+                    let inner = c.get(1).map_or("", |m| m.as_str());
+                    inner.split('|').next().unwrap_or_default().trim_end_matches('\\')
+                })
+                .collect(),
+            ParserProfile::Markdown => MARKDOWN_LINK.captures_iter(content)
+                .filter_map(|c| c.get(1).map(|m| m.as_str()))
+                .filter(|target| is_relative_link(target))
+                .collect(),
+        }
+    }
+}
+
+fn extract_mentions(content: &str, pages: &PageIndex) -> Vec<String> {
+    let mut references = Vec::new();
+    let content_lower = content.nfc().collect::<String>().to_lowercase();
+
+    // Pages differing only in case appear once, as their canonical name
+    let mentionable = pages.names.iter().map(|name| (normalize_name(name), name))
+        .chain(pages.aliases.iter().map(|(alias, name)| (alias.clone(), name)));
+    for (node_name_lower, node_name) in mentionable {
+        if pages.is_ignored(&node_name_lower, node_name) {
+            continue;
+        }
+
+        // Create a regex pattern with word boundaries
+        let pattern = format!(r"\b{}\b", regex::escape(&node_name_lower));
+        if let Ok(re) = Regex::new(&pattern) {
+            // Count case-insensitive matches of the filename
+            let count = re.find_iter(&content_lower).count();
+
+            // If we found any references, add them to the map
+            if count > 0 {
+                debug!("Found {} references to {} in content", count, node_name);
+                // Add the reference multiple times based on count
+                for _ in 0..count {
+                    references.push(node_name.clone());
+                }
+            }
+        }
+    }
+
+    references
+}
+
+/// Splits a property value such as `a, [[b c]], #d` into tag names
+fn split_tag_list(list: &str) -> impl Iterator<Item = String> + '_ {
+    list.split(',')
+        .map(|tag| tag.trim_matches(|c: char| c.is_whitespace() || matches!(c, '[' | ']' | '#' | '"' | '\'')))
+        .filter(|tag| !tag.is_empty())
+        .map(str::to_string)
+}
+
+fn is_relative_link(target: &str) -> bool {
+    !(target.starts_with('#') || target.starts_with('/') || target.contains("://") || target.starts_with("mailto:"))
+}
+
+/// Maps link targets onto page names, ignoring links to pages that don't exist
+fn resolve_links<'a>(targets: impl Iterator<Item = &'a str>, pages: &PageIndex) -> Vec<String> {
+    targets
+        .filter_map(|target| {
+            let title = page_title(target);
+            let page = pages.canonical(&title)?;
+            (!pages.is_ignored(&title, page)).then(|| page.clone())
+        })
+        .collect()
+}
+
+/// The page a link target refers to: anchors, block references, directories and
+/// extensions are dropped and percent-encoding is decoded
+fn page_title(target: &str) -> String {
+    let target = target.split(['#', '^']).next().unwrap_or_default();
+    let decoded = urlencoding::decode(target)
+        .map(|d| d.into_owned())
+        .unwrap_or_else(|_| target.to_string());
+    let file_name = decoded.trim().rsplit('/').next().unwrap_or_default();
+    let stem = PAGE_EXTENSIONS.iter().find_map(|ext| {
+        let split = file_name.len().checked_sub(ext.len())?;
+        file_name.get(split..)?.eq_ignore_ascii_case(ext).then(|| &file_name[..split])
+    });
+    stem.unwrap_or(file_name).trim().to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn nodes() -> PageIndex {
+        PageIndex::new(vec!["Rust".to_string(), "Graph Theory".to_string(), "Notes".to_string()])
+    }
+
+    #[test]
+    fn test_logseq_counts_mentions() {
+        let refs = ParserProfile::Logseq.extract_references("rust and [[Rust]], not rusty", &nodes());
+        assert_eq!(refs, vec!["Rust".to_string(), "Rust".to_string()]);
+    }
+
+    #[test]
+    fn test_obsidian_resolves_aliases_and_headings() {
+        let content = "See [[graph theory|graphs]], [[Rust#Ownership]], ![[notes]] and [[Missing]]. Rust alone is ignored.";
+        let refs = ParserProfile::Obsidian.extract_references(content, &nodes());
+        assert_eq!(refs, vec!["Graph Theory".to_string(), "Rust".to_string(), "Notes".to_string()]);
+    }
+
+    #[test]
+    fn test_markdown_relative_links() {
+        let content = "[a](Graph%20Theory.md) [b](./sub/rust.md#intro) [c](<Notes.md>) [d](https://example.com/Rust.md) [e](#Rust)";
+        let refs = ParserProfile::Markdown.extract_references(content, &nodes());
+        assert_eq!(refs, vec!["Graph Theory".to_string(), "Rust".to_string(), "Notes".to_string()]);
+    }
+
+    #[test]
+    fn test_unresolved_links() {
+        let content = "[[Rust]], [[Missing Page|alias]], [[missing page#Heading]] and ![[Other.md]]";
+        let unresolved = ParserProfile::Logseq.unresolved_links(content, &nodes());
+        assert_eq!(unresolved.len(), 2);
+        assert_eq!(unresolved["Missing Page"], 2);
+        assert_eq!(unresolved["Other"], 1);
+        assert!(ParserProfile::Markdown.unresolved_links("[a](https://x.org/y.md) [b](Notes.md)", &nodes()).is_empty());
+    }
+
+    #[test]
+    fn test_aliases_and_ignored_pages() {
+        let mut pages = nodes().with_ignored(vec!["notes".to_string()]);
+        pages.add_alias("rustlang", "Rust");
+        pages.add_alias("graph theory", "Rust");
+
+        let refs = ParserProfile::Logseq.extract_references("rustlang, graph theory and notes", &pages);
+        assert_eq!(refs, vec!["Graph Theory".to_string(), "Rust".to_string()]);
+        let refs = ParserProfile::Obsidian.extract_references("[[RustLang]] [[Notes]] [[Graph Theory]]", &pages);
+        assert_eq!(refs, vec!["Rust".to_string(), "Graph Theory".to_string()]);
+        assert!(ParserProfile::Obsidian.unresolved_links("[[rustlang]] [[Notes]] [[Missing]]", &pages.with_ignored(vec!["Missing".to_string()])).is_empty());
+    }
+
+    #[test]
+    fn test_case_and_unicode_insensitive_names() {
+        // "Café" as a precomposed page name, linked with a decomposed é
+        let pages = PageIndex::new(vec!["AI".to_string(), "ai".to_string(), "Caf\u{e9}".to_string()]);
+        let content = "[[ai]] [[AI]] [[Cafe\u{301}]]";
+        let refs = ParserProfile::Obsidian.extract_references(content, &pages);
+        assert_eq!(refs, vec!["AI".to_string(), "AI".to_string(), "Caf\u{e9}".to_string()]);
+        let refs = ParserProfile::Logseq.extract_references(content, &pages);
+        assert_eq!(refs, vec!["AI".to_string(), "AI".to_string(), "Caf\u{e9}".to_string()]);
+        assert!(ParserProfile::Logseq.unresolved_links(content, &pages).is_empty());
+    }
+
+    #[test]
+    fn test_tags() {
+        let logseq = "tags:: Rust, [[Graph Theory]], #wip\n- notes on #rust and #[[Type Systems]], see issue #42 and a#b";
+        assert_eq!(ParserProfile::Logseq.tags(logseq), vec!["Rust", "Graph Theory", "wip", "Type Systems"]);
+
+        let obsidian = "---\ntitle: x\ntags: [project, \"rust\"]\n---\n# Heading\nBody #inbox #project";
+        assert_eq!(ParserProfile::Obsidian.tags(obsidian), vec!["project", "rust", "inbox"]);
+
+        assert!(ParserProfile::Markdown.tags("#rust").is_empty());
+    }
+
+    #[test]
+    fn test_profile_parsing() {
+        assert_eq!(" Obsidian".parse::<ParserProfile>(), Ok(ParserProfile::Obsidian));
+        assert_eq!("md".parse::<ParserProfile>(), Ok(ParserProfile::Markdown));
+        assert!("wiki".parse::<ParserProfile>().is_err());
+    }
+}
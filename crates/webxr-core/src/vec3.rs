@@ -4,7 +4,7 @@ use serde::{Serialize, Deserialize};
 
 /// A 3D vector type that is compatible with both CUDA and WebSocket binary protocol
 #[repr(C)]
-#[derive(Debug, Clone, Copy, Pod, Zeroable, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Pod, Zeroable, Serialize, Deserialize)]
 pub struct Vec3Data {
     pub x: f32,
     pub y: f32,
@@ -59,6 +59,10 @@ impl Vec3Data {
     pub fn as_vec3(&self) -> Vec3 {
         Vec3::new(self.x, self.y, self.z)
     }
+
+    pub fn is_finite(&self) -> bool {
+        self.x.is_finite() && self.y.is_finite() && self.z.is_finite()
+    }
 }
 
 #[cfg(test)]
@@ -0,0 +1,90 @@
+//! JavaScript bindings for the browser client. Structured values cross the boundary
+//! as JSON strings so the client needs no generated type glue beyond these functions.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use wasm_bindgen::prelude::*;
+
+use crate::binary_protocol::{self, EdgeFrameKind};
+use crate::case_conversion;
+use crate::node_data::BinaryNodeData;
+use crate::vec3::Vec3Data;
+
+/// A node update as the client sees it: physics-only fields are not on the wire
+#[derive(Serialize, Deserialize)]
+struct NodeUpdate {
+    id: u32,
+    position: Vec3Data,
+    velocity: Vec3Data,
+}
+
+fn to_js_error(e: impl std::fmt::Display) -> JsValue {
+    JsValue::from_str(&e.to_string())
+}
+
+/// Decodes a binary position frame into a JSON array of `{id, position, velocity}`
+#[wasm_bindgen(js_name = decodeNodeData)]
+pub fn decode_node_data(data: &[u8]) -> Result<String, JsValue> {
+    let updates: Vec<NodeUpdate> = binary_protocol::decode_node_data(data)
+        .map_err(to_js_error)?
+        .into_iter()
+        .map(|(id, node)| NodeUpdate { id, position: node.position, velocity: node.velocity })
+        .collect();
+    serde_json::to_string(&updates).map_err(to_js_error)
+}
+
+/// Encodes a JSON array of `{id, position, velocity}` into a binary position frame
+#[wasm_bindgen(js_name = encodeNodeData)]
+pub fn encode_node_data(updates: &str) -> Result<Vec<u8>, JsValue> {
+    let updates: Vec<NodeUpdate> = serde_json::from_str(updates).map_err(to_js_error)?;
+    let nodes: Vec<(u32, BinaryNodeData)> = updates
+        .into_iter()
+        .map(|update| (update.id, BinaryNodeData::from_motion(update.position, update.velocity)))
+        .collect();
+    Ok(binary_protocol::encode_node_data(&nodes))
+}
+
+/// Decodes an edge frame into `{kind, edges: [{source, target, weight, edgeType}]}`
+#[wasm_bindgen(js_name = decodeEdgeFrame)]
+pub fn decode_edge_frame(data: &[u8]) -> Result<String, JsValue> {
+    let (kind, edges) = binary_protocol::decode_edge_frame(data).map_err(to_js_error)?;
+    let kind = match kind {
+        EdgeFrameKind::Snapshot => "snapshot",
+        EdgeFrameKind::Added => "added",
+        EdgeFrameKind::Removed => "removed",
+    };
+    let edges: Vec<Value> = edges
+        .iter()
+        .map(|edge| json!({
+            "source": edge.source,
+            "target": edge.target,
+            "weight": edge.weight,
+            "edgeType": edge.edge_type,
+        }))
+        .collect();
+    Ok(json!({ "kind": kind, "edges": edges }).to_string())
+}
+
+#[wasm_bindgen(js_name = toSnakeCase)]
+pub fn to_snake_case(s: &str) -> String {
+    case_conversion::to_snake_case(s)
+}
+
+#[wasm_bindgen(js_name = toCamelCase)]
+pub fn to_camel_case(s: &str) -> String {
+    case_conversion::to_camel_case(s)
+}
+
+/// Converts every object key in a JSON document to camelCase
+#[wasm_bindgen(js_name = keysToCamelCase)]
+pub fn keys_to_camel_case(json: &str) -> Result<String, JsValue> {
+    let value: Value = serde_json::from_str(json).map_err(to_js_error)?;
+    Ok(case_conversion::keys_to_camel_case(value).to_string())
+}
+
+/// Converts every object key in a JSON document to snake_case
+#[wasm_bindgen(js_name = keysToSnakeCase)]
+pub fn keys_to_snake_case(json: &str) -> Result<String, JsValue> {
+    let value: Value = serde_json::from_str(json).map_err(to_js_error)?;
+    Ok(case_conversion::keys_to_snake_case(value).to_string())
+}
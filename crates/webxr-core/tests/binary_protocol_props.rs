@@ -0,0 +1,99 @@
+//! Property tests for decoding client position frames, which arrive from untrusted
+//! sockets and must never panic the server
+
+use proptest::prelude::*;
+use webxr_core::binary_protocol::{decode_node_data, encode_node_data, DecodeError};
+use webxr_core::node_data::BinaryNodeData;
+use webxr_core::vec3::Vec3Data;
+
+const WIRE_ITEM_SIZE: usize = 28;
+
+fn node(position: [f32; 3], velocity: [f32; 3]) -> BinaryNodeData {
+    BinaryNodeData {
+        position: Vec3Data::from(position),
+        velocity: Vec3Data::from(velocity),
+        mass: 100,
+        flags: 0,
+        padding: [0, 0],
+    }
+}
+
+fn finite() -> impl Strategy<Value = f32> {
+    prop_oneof![
+        any::<f32>().prop_filter("finite", |v| v.is_finite()),
+        Just(f32::MAX),
+        Just(f32::MIN),
+        Just(f32::MIN_POSITIVE),
+        Just(-0.0),
+    ]
+}
+
+fn non_finite() -> impl Strategy<Value = f32> {
+    prop_oneof![Just(f32::NAN), Just(f32::INFINITY), Just(f32::NEG_INFINITY)]
+}
+
+fn updates() -> impl Strategy<Value = Vec<(u32, BinaryNodeData)>> {
+    prop::collection::vec(
+        (any::<u32>(), prop::array::uniform3(finite()), prop::array::uniform3(finite()))
+            .prop_map(|(id, position, velocity)| (id, node(position, velocity))),
+        0..64,
+    )
+}
+
+proptest! {
+    #[test]
+    fn decode_never_panics(data in prop::collection::vec(any::<u8>(), 0..512), offset in 0usize..4) {
+        let data = &data[offset.min(data.len())..];
+        match decode_node_data(data) {
+            Ok(nodes) => prop_assert_eq!(nodes.len() * WIRE_ITEM_SIZE, data.len()),
+            Err(DecodeError::InvalidLength { len, .. }) => {
+                prop_assert_eq!(len, data.len());
+                prop_assert!(len % WIRE_ITEM_SIZE != 0);
+            }
+            Err(DecodeError::NonFinite { .. }) => {}
+            Err(e) => prop_assert!(false, "unexpected error for an unframed message: {}", e),
+        }
+    }
+
+    #[test]
+    fn round_trip_preserves_ids_and_vectors(nodes in updates()) {
+        let encoded = encode_node_data(&nodes);
+        prop_assert_eq!(encoded.len(), nodes.len() * WIRE_ITEM_SIZE);
+        let decoded = decode_node_data(&encoded).unwrap();
+        prop_assert_eq!(decoded.len(), nodes.len());
+        for ((id, original), (decoded_id, data)) in nodes.iter().zip(&decoded) {
+            prop_assert_eq!(id, decoded_id);
+            prop_assert_eq!(original.position.as_array().map(f32::to_bits), data.position.as_array().map(f32::to_bits));
+            prop_assert_eq!(original.velocity.as_array().map(f32::to_bits), data.velocity.as_array().map(f32::to_bits));
+        }
+    }
+
+    #[test]
+    fn truncated_frames_are_rejected(nodes in updates().prop_filter("non-empty", |n| !n.is_empty()), cut in 1usize..WIRE_ITEM_SIZE) {
+        let encoded = encode_node_data(&nodes);
+        let truncated = &encoded[..encoded.len() - cut];
+        prop_assert_eq!(
+            decode_node_data(truncated).unwrap_err(),
+            DecodeError::InvalidLength { len: truncated.len(), item_size: WIRE_ITEM_SIZE }
+        );
+    }
+
+    #[test]
+    fn non_finite_values_are_rejected(mut nodes in updates().prop_filter("non-empty", |n| !n.is_empty()), index in any::<prop::sample::Index>(), component in 0usize..6, value in non_finite()) {
+        let index = index.index(nodes.len());
+        let target = &mut nodes[index].1;
+        match component {
+            0 => target.position.x = value,
+            1 => target.position.y = value,
+            2 => target.position.z = value,
+            3 => target.velocity.x = value,
+            4 => target.velocity.y = value,
+            _ => target.velocity.z = value,
+        }
+        let first_bad = nodes.iter()
+            .find(|(_, data)| !data.position.is_finite() || !data.velocity.is_finite())
+            .map(|(id, _)| *id)
+            .unwrap();
+        prop_assert_eq!(decode_node_data(&encode_node_data(&nodes)).unwrap_err(), DecodeError::NonFinite { id: first_bad });
+    }
+}
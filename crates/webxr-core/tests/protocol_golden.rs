@@ -0,0 +1,101 @@
+//! Golden frames for the binary protocol. Each file in `tests/golden` is a whole
+//! frame as sent on the wire; a change that alters one is a wire format change and
+//! needs `PROTOCOL_VERSION` bumping along with the client. Run with
+//! `UPDATE_GOLDEN=1` to rewrite the files after such a change. The client's copies
+//! of the layout constants are checked here too.
+
+use std::path::PathBuf;
+
+use webxr_core::binary_protocol::{
+    decode_edge_frame, decode_node_data, decode_quantized_node_data, encode_edge_frame, EdgeFrameKind,
+    FrameEncoder, QuantizationBounds, WireEdgeItem, WireEncoding,
+};
+use webxr_core::node_data::BinaryNodeData;
+use webxr_core::protocol::{self, FrameKind, PROTOCOL_VERSION};
+use webxr_core::vec3::Vec3Data;
+
+const BOUNDS: QuantizationBounds = QuantizationBounds { half_extent: 50.0, max_velocity: 0.5 };
+
+fn nodes() -> Vec<(u32, BinaryNodeData)> {
+    [(1, [0.0, 0.0, 0.0], [0.0, 0.0, 0.0]), (42, [-12.5, 3.25, 40.0], [0.25, -0.125, 0.0]), (70_000, [49.0, -49.0, 0.5], [-0.5, 0.5, 0.01])]
+        .into_iter()
+        .map(|(id, position, velocity)| {
+            (id, BinaryNodeData {
+                position: Vec3Data::from(position),
+                velocity: Vec3Data::from(velocity),
+                mass: 100,
+                flags: 0,
+                padding: [0, 0],
+            })
+        })
+        .collect()
+}
+
+fn edges() -> Vec<WireEdgeItem> {
+    vec![
+        WireEdgeItem { source: 1, target: 42, weight: 2.0, edge_type: 0 },
+        WireEdgeItem { source: 42, target: 70_000, weight: 0.5, edge_type: 3 },
+    ]
+}
+
+/// Compares `bytes` with the golden file `name`, or rewrites it under `UPDATE_GOLDEN`
+fn check_golden(name: &str, bytes: &[u8]) {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden").join(name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&path, bytes).unwrap();
+        return;
+    }
+    let golden = std::fs::read(&path).unwrap_or_else(|e| panic!("Reading {}: {}", path.display(), e));
+    assert_eq!(bytes, &golden[..], "{} no longer matches; the wire format changed", name);
+}
+
+#[test]
+fn float32_frame() {
+    let frame = FrameEncoder::new().encode(nodes());
+    check_golden("float32_frame.bin", &frame.bytes());
+    let decoded = decode_node_data(&frame.bytes()).unwrap();
+    assert_eq!(decoded.iter().map(|(id, _)| *id).collect::<Vec<_>>(), vec![1, 42, 70_000]);
+}
+
+#[test]
+fn quantized16_frame() {
+    let frame = FrameEncoder::new().encode(nodes());
+    let quantized = frame.select_quantized(&[0, 1, 2], &BOUNDS);
+    check_golden("quantized16_frame.bin", &quantized);
+    assert_eq!(decode_quantized_node_data(&quantized, &BOUNDS).unwrap().len(), 3);
+}
+
+#[test]
+fn edge_snapshot_frame() {
+    let frame = encode_edge_frame(EdgeFrameKind::Snapshot, &edges());
+    check_golden("edge_snapshot.bin", &frame);
+    assert_eq!(decode_edge_frame(&frame).unwrap(), (EdgeFrameKind::Snapshot, edges()));
+}
+
+#[test]
+fn framed_frames() {
+    assert_eq!(PROTOCOL_VERSION, 1, "bump the golden files and the client together with the version");
+
+    let positions = protocol::frame(FrameKind::Positions, WireEncoding::Quantized16, &FrameEncoder::new().encode(nodes()).select_quantized(&[0, 1, 2], &BOUNDS));
+    check_golden("framed_quantized16_frame.bin", &positions);
+    let edges = protocol::frame(FrameKind::Edges, WireEncoding::Float32, &encode_edge_frame(EdgeFrameKind::Snapshot, &edges()));
+    check_golden("framed_edge_snapshot.bin", &edges);
+
+    let (header, payload) = protocol::split_frame(&positions).unwrap();
+    assert_eq!((header.kind(), header.encoding()), (Some(FrameKind::Positions), Some(WireEncoding::Quantized16)));
+    assert_eq!(decode_quantized_node_data(payload, &BOUNDS).unwrap().len(), 3);
+}
+
+#[test]
+fn client_constants_match() {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../client/src/types/binaryProtocol.ts");
+    let client = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("Reading {}: {}", path.display(), e));
+    for constant in [
+        format!("BINARY_NODE_SIZE = {}", std::mem::size_of::<webxr_core::binary_protocol::WireNodeDataItem>()),
+        format!("PROTOCOL_VERSION = {}", PROTOCOL_VERSION),
+        format!("FRAME_MAGIC = 0x{:X}", protocol::FRAME_MAGIC),
+        format!("FRAME_HEADER_SIZE = {}", protocol::FRAME_HEADER_SIZE),
+    ] {
+        assert!(client.contains(&constant), "client/src/types/binaryProtocol.ts should declare {}", constant);
+    }
+}
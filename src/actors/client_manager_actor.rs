@@ -3,14 +3,22 @@
 use actix::prelude::*;
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
 use crate::actors::messages::*;
-use crate::handlers::socket_flow_handler::SocketFlowServer;
+use crate::handlers::socket_flow_handler::{SocketFlowServer, SuspendedSession};
 // WsMessage is no longer needed here as we use custom messages
-use log::{debug, warn};
+use log::{debug, info, warn};
+
+/// How long a disconnected client's session can be resumed
+const RESUME_GRACE_PERIOD: Duration = Duration::from_secs(60);
 
 pub struct ClientManagerActor {
     clients: HashMap<usize, Addr<SocketFlowServer>>,
     next_id: AtomicUsize,
+    // Resume token of each connected client
+    resume_tokens: HashMap<String, usize>,
+    // Sessions of disconnected clients by resume token, with when they expire
+    suspended: HashMap<String, (Instant, SuspendedSession)>,
 }
 
 impl ClientManagerActor {
@@ -18,17 +26,21 @@ impl ClientManagerActor {
         Self {
             clients: HashMap::new(),
             next_id: AtomicUsize::new(1),
+            resume_tokens: HashMap::new(),
+            suspended: HashMap::new(),
         }
     }
 
-    pub fn register_client(&mut self, addr: Addr<SocketFlowServer>) -> usize {
+    pub fn register_client(&mut self, addr: Addr<SocketFlowServer>, resume_token: String) -> usize {
         let client_id = self.next_id.fetch_add(1, Ordering::SeqCst);
         self.clients.insert(client_id, addr);
+        self.resume_tokens.insert(resume_token, client_id);
         debug!("Client {} registered. Total clients: {}", client_id, self.clients.len());
         client_id
     }
 
     pub fn unregister_client(&mut self, client_id: usize) {
+        self.resume_tokens.retain(|_, id| *id != client_id);
         if self.clients.remove(&client_id).is_some() {
             debug!("Client {} unregistered. Total clients: {}", client_id, self.clients.len());
         } else {
@@ -36,15 +48,14 @@ impl ClientManagerActor {
         }
     }
 
-    pub fn broadcast_to_all(&self, data: Vec<u8>) {
-        if self.clients.is_empty() {
-            return;
-        }
-
-        debug!("Broadcasting {} bytes to {} clients", data.len(), self.clients.len());
-        
-        for (_client_id, addr) in &self.clients {
-            addr.do_send(SendToClientBinary(data.clone()));
+    pub fn broadcast_frame(&self, msg: BroadcastPositionFrame) {
+        let update = SendPositionFrame {
+            frame: msg.frame,
+            kinetic_energy: msg.kinetic_energy,
+            detailed_debug: msg.detailed_debug,
+        };
+        for addr in self.clients.values() {
+            addr.do_send(update.clone());
         }
     }
 
@@ -63,6 +74,11 @@ impl ClientManagerActor {
     pub fn get_client_count(&self) -> usize {
         self.clients.len()
     }
+
+    fn purge_expired_sessions(&mut self) {
+        let now = Instant::now();
+        self.suspended.retain(|_, (expires_at, _)| *expires_at > now);
+    }
 }
 
 impl Actor for ClientManagerActor {
@@ -81,7 +97,36 @@ impl Handler<RegisterClient> for ClientManagerActor {
     type Result = Result<usize, String>;
 
     fn handle(&mut self, msg: RegisterClient, _ctx: &mut Self::Context) -> Self::Result {
-        Ok(self.register_client(msg.addr))
+        Ok(self.register_client(msg.addr, msg.resume_token))
+    }
+}
+
+impl Handler<SuspendSession> for ClientManagerActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: SuspendSession, _ctx: &mut Self::Context) -> Self::Result {
+        self.purge_expired_sessions();
+        self.suspended.insert(msg.token, (Instant::now() + RESUME_GRACE_PERIOD, msg.session));
+        debug!("Suspended session kept for {:?}. Suspended sessions: {}", RESUME_GRACE_PERIOD, self.suspended.len());
+    }
+}
+
+impl Handler<ResumeSession> for ClientManagerActor {
+    type Result = ResponseFuture<Option<SuspendedSession>>;
+
+    fn handle(&mut self, msg: ResumeSession, _ctx: &mut Self::Context) -> Self::Result {
+        self.purge_expired_sessions();
+        if let Some((_, session)) = self.suspended.remove(&msg.token) {
+            return Box::pin(async move { Some(session) });
+        }
+
+        // The old connection may still be open if the client dropped without closing it
+        let live = self.resume_tokens.get(&msg.token).and_then(|id| self.clients.get(id)).cloned();
+        Box::pin(async move {
+            let addr = live?;
+            info!("Taking over session from a connection that is still open");
+            addr.send(HandOverSession).await.ok().flatten()
+        })
     }
 }
 
@@ -94,12 +139,22 @@ impl Handler<UnregisterClient> for ClientManagerActor {
     }
 }
 
-impl Handler<BroadcastNodePositions> for ClientManagerActor {
-    type Result = Result<(), String>;
+impl Handler<BroadcastPositionFrame> for ClientManagerActor {
+    type Result = ();
 
-    fn handle(&mut self, msg: BroadcastNodePositions, _ctx: &mut Self::Context) -> Self::Result {
-        self.broadcast_to_all(msg.positions);
-        Ok(())
+    fn handle(&mut self, msg: BroadcastPositionFrame, _ctx: &mut Self::Context) -> Self::Result {
+        self.broadcast_frame(msg);
+    }
+}
+
+impl Handler<BroadcastEdgeFrame> for ClientManagerActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: BroadcastEdgeFrame, _ctx: &mut Self::Context) -> Self::Result {
+        let update = SendEdgeFrame(msg.0);
+        for addr in self.clients.values() {
+            addr.do_send(update.clone());
+        }
     }
 }
 
@@ -1,5 +1,5 @@
 use actix::prelude::*;
-use log::{error, warn, info, trace};
+use log::{debug, error, warn, info, trace};
 use std::io::{Error, ErrorKind};
 use std::collections::HashMap;
 use std::time::{Duration, Instant};
@@ -10,9 +10,11 @@ use cudarc::driver::sys::CUdevice_attribute_enum;
 
 use crate::models::graph::GraphData;
 use crate::models::simulation_params::SimulationParams;
+use crate::utils::gpu_compute::{EdgeCsr, GPULayoutParams, GpuEdges};
 use crate::utils::socket_flow_messages::BinaryNodeData;
 use crate::types::vec3::Vec3Data;
 use crate::actors::messages::*;
+use crate::services::fault_injection::FaultInjector;
 use std::path::Path;
 use std::env;
 use std::sync::Arc;
@@ -39,6 +41,7 @@ pub struct GPUComputeActor {
     device: Option<Arc<CudaDevice>>,
     force_kernel: Option<CudaFunction>,
     node_data: Option<CudaSlice<BinaryNodeData>>,
+    edges: Option<GpuEdges>,
     num_nodes: u32,
     node_indices: HashMap<u32, usize>,
     simulation_params: SimulationParams,
@@ -46,6 +49,8 @@ pub struct GPUComputeActor {
     gpu_failure_count: u32,
     last_failure_reset: Instant,
     cpu_fallback_active: bool,
+    /// Failures and delays to inject, in debug builds only
+    faults: Option<Arc<FaultInjector>>,
 }
 
 // Struct to hold the results of GPU initialization
@@ -53,6 +58,7 @@ struct GpuInitializationResult {
     device: Arc<CudaDevice>,
     force_kernel: CudaFunction,
     node_data: CudaSlice<BinaryNodeData>,
+    edges: GpuEdges,
     num_nodes: u32,
     node_indices: HashMap<u32, usize>,
 }
@@ -63,6 +69,7 @@ impl GPUComputeActor {
             device: None,
             force_kernel: None,
             node_data: None,
+            edges: None,
             num_nodes: 0,
             node_indices: HashMap::new(),
             simulation_params: SimulationParams::default(),
@@ -70,9 +77,16 @@ impl GPUComputeActor {
             gpu_failure_count: 0,
             last_failure_reset: Instant::now(),
             cpu_fallback_active: false,
+            faults: None,
         }
     }
 
+    /// Fails initialisation and force computations as `faults` says
+    pub fn with_faults(mut self, faults: Arc<FaultInjector>) -> Self {
+        self.faults = Some(faults);
+        self
+    }
+
     // --- Static GPU Initialization Logic ---
 
     async fn static_test_gpu_capabilities() -> Result<(), Error> {
@@ -172,11 +186,13 @@ impl GPUComputeActor {
         // Pass graph.nodes which is Vec<Node>
         let (force_kernel, node_data, node_indices) = Self::static_load_compute_kernel(device.clone(), num_nodes, &graph.nodes).await?;
         info!("(Static Logic) Compute kernel loaded and data copied");
+        let edges = GpuEdges::upload(&device, EdgeCsr::new(&graph, &node_indices, &SimulationParams::default()))?;
         
         Ok(GpuInitializationResult {
             device, // No Some() needed, it's Arc<CudaDevice>
             force_kernel, // No Some()
             node_data,    // No Some()
+            edges,
             num_nodes,
             node_indices,
         })
@@ -205,25 +221,33 @@ impl GPUComputeActor {
 
         let mut host_node_data = Vec::with_capacity(graph.nodes.len());
         for node in &graph.nodes {
-            host_node_data.push(BinaryNodeData {
-                position: node.data.position.clone(),
-                velocity: node.data.velocity.clone(),
-                mass: node.data.mass,
-                flags: node.data.flags,
-                padding: node.data.padding,
-            });
+            host_node_data.push(self.simulation_params.gpu_node_data(node));
         }
 
         device.htod_sync_copy_into(&host_node_data, node_data_slice)
             .map_err(|e| Error::new(ErrorKind::Other, format!("Failed to copy node data to GPU: {}", e)))?;
+
+        if self.simulation_params.uses_edges() {
+            let csr = EdgeCsr::new(graph, &self.node_indices, &self.simulation_params);
+            if self.edges.as_ref().is_none_or(|edges| edges.csr != csr) {
+                self.edges = Some(GpuEdges::upload(device, csr)?);
+            }
+        }
         
         Ok(())
     }
 
     fn compute_forces_internal(&mut self) -> Result<(), Error> {
+        if !self.cpu_fallback_active {
+            if let Some(Err(e)) = self.faults.as_ref().map(|faults| faults.inject_blocking("GPU force computation")) {
+                return self.handle_gpu_error(e);
+            }
+        }
+
         let device = self.device.as_ref().ok_or_else(|| Error::new(ErrorKind::Other, "Device not initialized"))?;
         let force_kernel = self.force_kernel.as_ref().ok_or_else(|| Error::new(ErrorKind::Other, "Kernel not initialized"))?;
         let node_data = self.node_data.as_ref().ok_or_else(|| Error::new(ErrorKind::Other, "Node data not initialized"))?;
+        let edges = self.edges.as_ref().ok_or_else(|| Error::other("Edges not initialized"))?;
 
         if self.cpu_fallback_active {
             warn!("GPU compute in CPU fallback mode, skipping GPU kernel");
@@ -265,6 +289,7 @@ impl GPUComputeActor {
                     f32::MAX
                 },
                 self.iteration_count as i32,
+                GPULayoutParams::new(&self.simulation_params, edges),
             ))
         };
 
@@ -336,7 +361,13 @@ impl Handler<InitializeGPU> for GPUComputeActor {
     fn handle(&mut self, msg: InitializeGPU, _ctx: &mut Self::Context) -> Self::Result {
         let graph_data_owned = msg.graph;
 
-        let fut = GPUComputeActor::perform_gpu_initialization(graph_data_owned);
+        let faults = self.faults.clone();
+        let fut = async move {
+            if let Some(faults) = faults {
+                faults.inject("GPU initialisation").await.map_err(Error::other)?;
+            }
+            GPUComputeActor::perform_gpu_initialization(graph_data_owned).await
+        };
         
         // Use FutureActorExt trait's into_actor method
         let actor_fut = fut.into_actor(self);
@@ -348,6 +379,7 @@ impl Handler<InitializeGPU> for GPUComputeActor {
                         actor.device = Some(init_result.device);
                         actor.force_kernel = Some(init_result.force_kernel);
                         actor.node_data = Some(init_result.node_data);
+                        actor.edges = Some(init_result.edges);
                         actor.num_nodes = init_result.num_nodes;
                         actor.node_indices = init_result.node_indices;
                         
@@ -365,6 +397,7 @@ impl Handler<InitializeGPU> for GPUComputeActor {
                         actor.device = None;
                         actor.force_kernel = None;
                         actor.node_data = None;
+                        actor.edges = None;
                         actor.num_nodes = 0;
                         actor.node_indices.clear();
                         actor.cpu_fallback_active = true; // Fallback on init failure
@@ -408,6 +441,16 @@ impl Handler<UpdateSimulationParams> for GPUComputeActor {
     }
 }
 
+impl Handler<UpdateViewportBounds> for GPUComputeActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: UpdateViewportBounds, _ctx: &mut Self::Context) -> Self::Result {
+        debug!("Updating viewport bounds to {} (enabled: {})", msg.half_extent, msg.enabled);
+        self.simulation_params.viewport_bounds = msg.half_extent;
+        self.simulation_params.enable_bounds = msg.enabled;
+    }
+}
+
 impl Handler<ComputeForces> for GPUComputeActor {
     type Result = Result<(), String>;
 
@@ -1,7 +1,7 @@
 //! Graph Service Actor to replace Arc<RwLock<GraphService>>
 
 use actix::prelude::*;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::Arc;
 use tokio::time::Duration;
@@ -10,40 +10,147 @@ use log::{debug, info, warn, error};
  
 use crate::actors::messages::*;
 use crate::actors::client_manager_actor::ClientManagerActor;
-use crate::models::node::Node;
+use crate::models::node::{Node, GHOST_NODE_TYPE, JOURNAL_NODE_TYPE, NOSTR_NODE_TYPE, TAG_NODE_TYPE, TASK_NODE_TYPE};
 use crate::models::edge::Edge;
+use webxr_core::graph_builder;
 use crate::models::metadata::MetadataStore;
-use crate::models::graph::GraphData;
+use crate::models::graph::{GraphBuildOptions, GraphData};
+use crate::models::graph_aggregation::GraphAggregation;
+use crate::models::node_grab::NodeGrabs;
+use crate::types::vec3::Vec3Data;
 use crate::utils::socket_flow_messages::{BinaryNodeData, glam_to_vec3data}; // Added glam_to_vec3data
-use crate::utils::binary_protocol;
+use crate::utils::binary_protocol::{self, EdgeFrameKind, EdgeTypeTable, EncodedFrame, FrameEncoder, WireEdgeItem};
 use crate::actors::gpu_compute_actor::GPUComputeActor;
+use crate::services::event_bus::{EventBus, GraphEvent};
+use crate::services::duplicates;
+use crate::services::timeline;
+use crate::services::graph_service::GraphService;
+use crate::services::graph_validation::{self, RepairOptions, RepairReport};
+use crate::services::node_colors::NodeColorMapper;
+use crate::services::world_bounds::{BoundsConfig, WorldBounds};
+use crate::services::nostr_ingest::ExternalNote;
+use crate::services::reference_parser::{normalize_name, PageIndex, ParserProfile};
+use chrono::{NaiveDate, Utc};
+use rand::{Rng, SeedableRng};
+use rand::rngs::StdRng;
+use sha1::{Digest, Sha1};
+
+const PRIVATE_GHOST_LABEL: &str = "Private page";
+/// `edge_type` joining a task node to a page its text links to
+pub const TASK_REFERENCE_EDGE_TYPE: &str = "taskReference";
+
+/// What moves the nodes between builds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum PhysicsBackend {
+    /// Steps the layout every 16 ms; the position broadcaster sends the results on
+    #[default]
+    Cpu,
+    /// Never runs the simulation, so nodes stay where the build put them. For tests
+    /// that need positions to hold still.
+    Noop,
+}
 
 pub struct GraphServiceActor {
     graph_data: Arc<GraphData>, // Changed to Arc<GraphData>
     node_map: HashMap<u32, Node>,
-    // gpu_compute_addr: Option<Addr<GPUComputeActor>>, // Unused
+    // Only told about bounds changes; the layout itself runs on the CPU
+    gpu_compute_addr: Option<Addr<GPUComputeActor>>,
     client_manager: Addr<ClientManagerActor>,
     simulation_running: AtomicBool,
     shutdown_complete: Arc<AtomicBool>,
     next_node_id: AtomicU32,
+    event_bus: EventBus,
+    frame_encoder: FrameEncoder,
+    // Positions encoded for clients; cleared whenever a node moves or the graph changes
+    position_frame: Option<EncodedFrame>,
+    // Supernode aggregations keyed by cell size bits; cleared when nodes are added or removed
+    aggregations: HashMap<u32, Arc<GraphAggregation>>,
+    // Codes for edge types in binary edge frames; stable for the actor's lifetime
+    edge_types: EdgeTypeTable,
+    // Incremented when nodes or edges change. Moving nodes doesn't count, as clients
+    // follow positions over the WebSocket rather than refetching the graph.
+    revision: u64,
+    build_options: GraphBuildOptions,
+    // Fixes applied to every graph before it replaces the live one
+    repair_options: RepairOptions,
+    // Seed from settings for builds that don't bring their own
+    layout_seed: Option<u64>,
+    // Source of all randomness in the simulation, reseeded on every build
+    rng: StdRng,
+    // Nodes being dragged by clients, which the solver leaves alone
+    grabs: NodeGrabs,
+    physics: PhysicsBackend,
+    node_colors: NodeColorMapper,
+    world_bounds: WorldBounds,
+    // Notes from Nostr relays, added to every build
+    external_notes: Vec<ExternalNote>,
 }
 
 impl GraphServiceActor {
     pub fn new(
         client_manager: Addr<ClientManagerActor>,
-        _gpu_compute_addr: Option<Addr<GPUComputeActor>>, // Marked as unused
+        gpu_compute_addr: Option<Addr<GPUComputeActor>>,
+        event_bus: EventBus,
     ) -> Self {
         Self {
             graph_data: Arc::new(GraphData::new()), // Changed to Arc::new
             node_map: HashMap::new(),
-            // gpu_compute_addr, // Unused
+            gpu_compute_addr,
             client_manager,
             simulation_running: AtomicBool::new(false),
             shutdown_complete: Arc::new(AtomicBool::new(false)),
             next_node_id: AtomicU32::new(1),
+            event_bus,
+            frame_encoder: FrameEncoder::new(),
+            position_frame: None,
+            aggregations: HashMap::new(),
+            edge_types: EdgeTypeTable::default(),
+            revision: 0,
+            build_options: GraphBuildOptions::default(),
+            repair_options: RepairOptions::default(),
+            layout_seed: None,
+            rng: StdRng::from_entropy(),
+            grabs: NodeGrabs::default(),
+            physics: PhysicsBackend::default(),
+            node_colors: NodeColorMapper::default(),
+            world_bounds: WorldBounds { half_extent: BoundsConfig::default().size, config: BoundsConfig::default() },
+            external_notes: Vec::new(),
         }
     }
 
+    pub fn with_build_options(mut self, build_options: GraphBuildOptions) -> Self {
+        self.build_options = build_options;
+        self
+    }
+
+    pub fn with_repair_options(mut self, repair_options: RepairOptions) -> Self {
+        self.repair_options = repair_options;
+        self
+    }
+
+    pub fn with_physics(mut self, physics: PhysicsBackend) -> Self {
+        self.physics = physics;
+        self
+    }
+
+    pub fn with_node_colors(mut self, node_colors: NodeColorMapper) -> Self {
+        self.node_colors = node_colors;
+        self
+    }
+
+    pub fn with_world_bounds(mut self, config: BoundsConfig) -> Self {
+        self.world_bounds = WorldBounds { half_extent: config.fit(0), config };
+        self
+    }
+
+    pub fn with_layout_seed(mut self, seed: Option<u64>) -> Self {
+        self.layout_seed = seed;
+        if let Some(seed) = seed {
+            self.rng = StdRng::seed_from_u64(seed);
+        }
+        self
+    }
+
     pub fn get_graph_data(&self) -> &GraphData { // Returns a reference to the inner GraphData
         &self.graph_data // Dereferences Arc<GraphData> to &GraphData
     }
@@ -52,8 +159,57 @@ impl GraphServiceActor {
         &self.node_map
     }
 
+    /// Returns the current positions as a binary frame, encoding them only if they
+    /// changed since the last call
+    pub fn position_frame(&mut self) -> EncodedFrame {
+        if let Some(frame) = &self.position_frame {
+            return frame.clone();
+        }
+        let frame = self.frame_encoder.encode(self.graph_data.nodes.iter().map(|node| (node.id, node.data)));
+        self.position_frame = Some(frame.clone());
+        frame
+    }
+
+    fn encode_edges<'a>(
+        edge_types: &mut EdgeTypeTable,
+        kind: EdgeFrameKind,
+        edges: impl IntoIterator<Item = &'a Edge>,
+    ) -> EdgeFrameUpdate {
+        let items: Vec<WireEdgeItem> = edges.into_iter()
+            .map(|edge| WireEdgeItem {
+                source: edge.source,
+                target: edge.target,
+                weight: edge.weight,
+                edge_type: edge_types.code(edge.edge_type.as_deref()),
+            })
+            .collect();
+        EdgeFrameUpdate {
+            frame: binary_protocol::encode_edge_frame(kind, &items),
+            edge_types: edge_types.names().to_vec(),
+        }
+    }
+
+    /// Every edge as a snapshot frame
+    pub fn edge_frame(&mut self) -> EdgeFrameUpdate {
+        Self::encode_edges(&mut self.edge_types, EdgeFrameKind::Snapshot, &self.graph_data.edges)
+    }
+
+    fn broadcast_edges<'a>(&mut self, kind: EdgeFrameKind, edges: impl IntoIterator<Item = &'a Edge>) {
+        let update = Self::encode_edges(&mut self.edge_types, kind, edges);
+        self.client_manager.do_send(BroadcastEdgeFrame(update));
+    }
+
+    /// Sends the full edge list to subscribed clients after the graph is replaced
+    fn broadcast_edge_snapshot(&mut self) {
+        let update = self.edge_frame();
+        self.client_manager.do_send(BroadcastEdgeFrame(update));
+    }
+
     pub fn add_node(&mut self, node: Node) {
         let node_id = node.id; // Store the ID before moving node
+        self.position_frame = None;
+        self.revision += 1;
+        self.aggregations.clear();
         
         // Update node_map
         self.node_map.insert(node.id, node.clone());
@@ -73,6 +229,9 @@ impl GraphServiceActor {
     }
 
     pub fn remove_node(&mut self, node_id: u32) {
+        self.position_frame = None;
+        self.revision += 1;
+        self.aggregations.clear();
         // Remove from node_map
         self.node_map.remove(&node_id);
         
@@ -88,6 +247,8 @@ impl GraphServiceActor {
 
     pub fn add_edge(&mut self, edge: Edge) {
         let edge_id = edge.id.clone(); // Store the ID before moving edge
+        self.revision += 1;
+        self.broadcast_edges(EdgeFrameKind::Added, [&edge]);
         
         let graph_data_mut = Arc::make_mut(&mut self.graph_data);
         // Add to graph data if not already present
@@ -104,11 +265,18 @@ impl GraphServiceActor {
     }
 
     pub fn remove_edge(&mut self, edge_id: &str) {
+        let removed: Vec<Edge> = self.graph_data.edges.iter().filter(|e| e.id == edge_id).cloned().collect();
+        if !removed.is_empty() {
+            self.revision += 1;
+            self.broadcast_edges(EdgeFrameKind::Removed, &removed);
+        }
         Arc::make_mut(&mut self.graph_data).edges.retain(|e| e.id != edge_id);
         debug!("Removed edge: {}", edge_id);
     }
 
-    pub fn build_from_metadata(&mut self, metadata: MetadataStore) -> Result<(), String> {
+    /// Rebuilds the graph, laying it out from `seed` if given, otherwise from the
+    /// configured seed or a fresh one
+    pub fn build_from_metadata(&mut self, metadata: MetadataStore, seed: Option<u64>) -> Result<(), String> {
         let mut new_graph_data = GraphData::new(); // Create a new GraphData instance
         self.node_map.clear(); // Clear node_map separately
 
@@ -127,6 +295,8 @@ impl GraphServiceActor {
             node.metadata.insert("fileSize".to_string(), file_meta_data.file_size.to_string());
             node.metadata.insert("nodeSize".to_string(), file_meta_data.node_size.to_string());
             node.metadata.insert("hyperlinkCount".to_string(), file_meta_data.hyperlink_count.to_string());
+            node.metadata.insert("wordCount".to_string(), file_meta_data.page_stats.word_count.to_string());
+            node.metadata.insert("readingTimeMinutes".to_string(), file_meta_data.page_stats.reading_time_minutes.to_string());
             node.metadata.insert("sha1".to_string(), file_meta_data.sha1.clone());
             node.metadata.insert("lastModified".to_string(), file_meta_data.last_modified.to_rfc3339());
             if !file_meta_data.perplexity_link.is_empty() {
@@ -135,6 +305,17 @@ impl GraphServiceActor {
             if let Some(last_process) = file_meta_data.last_perplexity_process {
                 node.metadata.insert("lastPerplexityProcess".to_string(), last_process.to_rfc3339());
             }
+            if !file_meta_data.aliases.is_empty() {
+                node.metadata.insert("aliases".to_string(), file_meta_data.aliases.join(", "));
+            }
+            let broken_links = file_meta_data.link_checks.values().filter(|check| check.broken).count();
+            if broken_links > 0 {
+                node.metadata.insert("brokenLinks".to_string(), broken_links.to_string());
+            }
+            if let Some(date) = timeline::journal_date(&metadata_id_val) {
+                node.node_type = Some(JOURNAL_NODE_TYPE.to_string());
+                node.metadata.insert("journalDate".to_string(), date.to_string());
+            }
             node.metadata.insert("metadataId".to_string(), metadata_id_val);
 
             // Add to new_graph_data and self.node_map
@@ -162,11 +343,42 @@ impl GraphServiceActor {
         for ((source_id, target_id), weight) in edge_map {
             new_graph_data.edges.push(Edge::new(source_id, target_id, weight));
         }
+
+        if self.build_options.journal_edges {
+            Self::add_journal_edges(&mut new_graph_data);
+        }
+        if self.build_options.duplicate_edges {
+            Self::add_duplicate_edges(&metadata, &mut new_graph_data);
+        }
+        // Tags first, so ghosts aren't added for `[[links]]` that became tag nodes
+        if self.build_options.tag_nodes {
+            self.add_tag_nodes(&metadata, &mut new_graph_data);
+        }
+        if self.build_options.ghost_nodes || self.build_options.private_ghost_nodes {
+            self.add_ghost_nodes(&metadata, &mut new_graph_data);
+        }
+        if self.build_options.task_nodes {
+            self.add_task_nodes(&metadata, &mut new_graph_data);
+        }
+        self.add_external_notes(&metadata, &mut new_graph_data, HashMap::new());
         
         // Populate metadata in new_graph_data (assuming metadata is MetadataStore)
         new_graph_data.metadata = metadata.clone(); // Clone the entire store
 
+        let seed = GraphService::layout_seed(seed.or(self.layout_seed));
+        graph_builder::initialize_positions(&mut new_graph_data, seed);
+        self.rng = StdRng::seed_from_u64(seed);
+
+        // Duplicate nodes may have been dropped, so the map is rebuilt from what's left
+        if !graph_validation::sanitize(&mut new_graph_data, &self.repair_options).repaired.is_empty() {
+            self.node_map = new_graph_data.nodes.iter().map(|node| (node.id, node.clone())).collect();
+        }
+
         self.graph_data = Arc::new(new_graph_data); // Replace the old Arc with the new one
+        self.position_frame = None;
+        self.revision += 1;
+        self.aggregations.clear();
+        self.broadcast_edge_snapshot();
         
         info!("Built graph from metadata: {} nodes, {} edges",
               self.graph_data.nodes.len(), self.graph_data.edges.len());
@@ -174,8 +386,334 @@ impl GraphServiceActor {
         Ok(())
     }
 
+    /// Recolours every node with the colour mapper and sends the colours to clients.
+    /// Nothing is sent while colouring is off and no node carries a colour.
+    fn restyle(&mut self) {
+        let colored = self.graph_data.nodes.iter().any(|node| node.color.is_some());
+        if !self.node_colors.is_enabled() && !colored {
+            return;
+        }
+        let colors = self.node_colors.apply(Arc::make_mut(&mut self.graph_data), Utc::now());
+        for node in self.node_map.values_mut() {
+            node.color = colors.get(&node.id).cloned();
+        }
+        debug!("Coloured {} of {} nodes", colors.len(), self.graph_data.nodes.len());
+        self.event_bus.publish(GraphEvent::Restyle { colors });
+    }
+
+    /// Fits the bounds to the node count. When they change, or when `announce` is
+    /// set, the GPU gets the new boundary and clients are told to rescale.
+    fn refit_bounds(&mut self, announce: bool) {
+        let node_count = self.graph_data.nodes.len();
+        match self.world_bounds.config.refit(self.world_bounds.half_extent, node_count) {
+            Some(half_extent) => self.world_bounds.half_extent = half_extent,
+            None if !announce => return,
+            None => {}
+        }
+        info!("World bounds now {:.1} for {} nodes (auto-fit: {})",
+            self.world_bounds.half_extent, node_count, self.world_bounds.config.auto_fit);
+        self.push_bounds_to_gpu();
+        self.event_bus.publish(GraphEvent::Bounds(self.world_bounds));
+    }
+
+    fn push_bounds_to_gpu(&self) {
+        if let Some(gpu_compute_addr) = &self.gpu_compute_addr {
+            gpu_compute_addr.do_send(UpdateViewportBounds {
+                half_extent: self.world_bounds.half_extent,
+                enabled: self.world_bounds.config.enabled,
+            });
+        }
+    }
+
+    /// Joins each journal page to the next journal by date. Days without a journal
+    /// are skipped, so the chain stays connected across gaps.
+    fn add_journal_edges(graph_data: &mut GraphData) {
+        let mut journals: Vec<(NaiveDate, u32)> = graph_data.nodes.iter()
+            .filter_map(|node| timeline::journal_date(&node.metadata_id).map(|date| (date, node.id)))
+            .collect();
+        journals.sort_unstable();
+
+        let linked: HashSet<(u32, u32)> = graph_data.edges.iter()
+            .map(|edge| (edge.source.min(edge.target), edge.source.max(edge.target)))
+            .collect();
+        for pair in journals.windows(2) {
+            let (earlier, later) = (pair[0].1, pair[1].1);
+            if !linked.contains(&(earlier.min(later), earlier.max(later))) {
+                let mut edge = Edge::new(earlier, later, 1.0);
+                edge.edge_type = Some(timeline::JOURNAL_EDGE_TYPE.to_string());
+                graph_data.edges.push(edge);
+            }
+        }
+        debug!("Linked {} journal pages in date order", journals.len());
+    }
+
+    /// Joins pages flagged as near duplicates, weighted by their similarity, unless
+    /// they already link to each other
+    fn add_duplicate_edges(metadata: &MetadataStore, graph_data: &mut GraphData) {
+        let page_ids: HashMap<&str, u32> = graph_data.nodes.iter()
+            .map(|node| (node.metadata_id.as_str(), node.id))
+            .collect();
+        let mut linked: HashSet<(u32, u32)> = graph_data.edges.iter()
+            .map(|edge| (edge.source.min(edge.target), edge.source.max(edge.target)))
+            .collect();
+        let mut added = Vec::new();
+        for (file_name, file_meta) in metadata {
+            let Some(&source) = page_ids.get(file_name.trim_end_matches(".md")) else { continue };
+            for (other, similarity) in &file_meta.near_duplicates {
+                let Some(&target) = page_ids.get(other.trim_end_matches(".md")) else { continue };
+                if source != target && linked.insert((source.min(target), source.max(target))) {
+                    let mut edge = Edge::new(source.min(target), source.max(target), *similarity);
+                    edge.edge_type = Some(duplicates::DUPLICATE_EDGE_TYPE.to_string());
+                    added.push(edge);
+                }
+            }
+        }
+        debug!("Linked {} pairs of near-duplicate pages", added.len());
+        graph_data.edges.extend(added);
+    }
+
+    /// Adds one node per tag with an edge from every page carrying it, so popular
+    /// tags become hubs. As in Logseq, a tag naming an existing page is that page.
+    fn add_tag_nodes(&mut self, metadata: &MetadataStore, graph_data: &mut GraphData) {
+        let page_ids: HashMap<String, u32> = graph_data.nodes.iter()
+            .map(|node| (node.metadata_id.to_lowercase(), node.id))
+            .collect();
+        // Ordered so tag ids don't depend on hash order
+        let mut tagged: BTreeMap<String, (&String, Vec<u32>)> = BTreeMap::new();
+        for (file_name, file_meta) in metadata {
+            if let Some(&page_id) = page_ids.get(&file_name.trim_end_matches(".md").to_lowercase()) {
+                for tag in &file_meta.tags {
+                    tagged.entry(tag.to_lowercase()).or_insert((tag, Vec::new())).1.push(page_id);
+                }
+            }
+        }
+
+        let mut linked: HashSet<(u32, u32)> = graph_data.edges.iter()
+            .map(|edge| (edge.source.min(edge.target), edge.source.max(edge.target)))
+            .collect();
+        let mut tag_nodes = Vec::new();
+        for (key, (tag, mut pages)) in tagged {
+            pages.sort_unstable();
+            let hub_id = match page_ids.get(&key) {
+                Some(&page_id) => page_id,
+                None => {
+                    let id = self.next_node_id.fetch_add(1, Ordering::SeqCst);
+                    let mut node = Node::new_with_id(format!("{}:{}", TAG_NODE_TYPE, tag), Some(id));
+                    node.label = tag.clone();
+                    node.node_type = Some(TAG_NODE_TYPE.to_string());
+                    node.set_tag_mass(pages.len());
+                    node.metadata.insert("pageCount".to_string(), pages.len().to_string());
+                    tag_nodes.push(node);
+                    id
+                }
+            };
+            for page_id in pages {
+                if page_id != hub_id && linked.insert((page_id.min(hub_id), page_id.max(hub_id))) {
+                    let mut edge = Edge::new(page_id, hub_id, 1.0);
+                    edge.edge_type = Some(TAG_NODE_TYPE.to_string());
+                    graph_data.edges.push(edge);
+                }
+            }
+        }
+
+        debug!("Added {} tag nodes", tag_nodes.len());
+        for node in tag_nodes {
+            self.node_map.insert(node.id, node.clone());
+            graph_data.nodes.push(node);
+        }
+    }
+
+    /// Adds one ghost node per unresolved link target, shared by every page linking
+    /// to it, so links to missing pages still shape the layout
+    fn add_ghost_nodes(&mut self, metadata: &MetadataStore, graph_data: &mut GraphData) {
+        let page_ids: HashMap<&str, u32> = graph_data.nodes.iter()
+            .map(|node| (node.metadata_id.as_str(), node.id))
+            .collect();
+        let options = self.build_options;
+        let tags: HashSet<String> = graph_data.nodes.iter()
+            .filter(|node| node.node_type.as_deref() == Some(TAG_NODE_TYPE))
+            .map(|node| normalize_name(&node.label))
+            .collect();
+        // Ordered so ghost ids don't depend on hash order. The flag marks links to
+        // unpublished pages.
+        let mut links: Vec<(u32, bool, &String, usize)> = Vec::new();
+        for (file_name, file_meta) in metadata {
+            if let Some(&source_id) = page_ids.get(file_name.trim_end_matches(".md")) {
+                if options.ghost_nodes {
+                    links.extend(file_meta.unresolved_links.iter()
+                        .filter(|(target, _)| !tags.contains(&normalize_name(target)))
+                        .map(|(target, count)| (source_id, false, target, *count)));
+                }
+                if options.private_ghost_nodes {
+                    links.extend(file_meta.private_links.iter().map(|(target, count)| (source_id, true, target, *count)));
+                }
+            }
+        }
+        links.sort();
+
+        let mut ghosts: HashMap<(bool, String), u32> = HashMap::new();
+        let mut ghost_nodes = Vec::new();
+        for (source_id, private, target, count) in links {
+            let key = normalize_name(target);
+            let ghost_id = *ghosts.entry((private, key.clone())).or_insert_with(|| {
+                let id = self.next_node_id.fetch_add(1, Ordering::SeqCst);
+                let mut node = if private {
+                    // Identified by a digest so the title of the private page isn't served
+                    let digest = format!("{:x}", Sha1::digest(key.as_bytes()));
+                    let mut node = Node::new_with_id(format!("{}:private:{}", GHOST_NODE_TYPE, &digest[..12]), Some(id));
+                    node.label = PRIVATE_GHOST_LABEL.to_string();
+                    node
+                } else {
+                    let mut node = Node::new_with_id(format!("{}:{}", GHOST_NODE_TYPE, target), Some(id));
+                    node.label = target.clone();
+                    node
+                };
+                node.metadata.insert("ghostReason".to_string(), if private { "private" } else { "missing" }.to_string());
+                node.node_type = Some(GHOST_NODE_TYPE.to_string());
+                node.data.flags = 1;
+                ghost_nodes.push(node);
+                id
+            });
+            let mut edge = Edge::new(source_id, ghost_id, count as f32);
+            edge.edge_type = Some(GHOST_NODE_TYPE.to_string());
+            graph_data.edges.push(edge);
+        }
+
+        debug!("Added {} ghost nodes for unresolved links", ghost_nodes.len());
+        for node in ghost_nodes {
+            self.node_map.insert(node.id, node.clone());
+            graph_data.nodes.push(node);
+        }
+    }
+
+    /// Adds a node per open task, with a `task` edge to the page it is on and a
+    /// `taskReference` edge to each page its text links to
+    fn add_task_nodes(&mut self, metadata: &MetadataStore, graph_data: &mut GraphData) {
+        let pages = PageIndex::from_metadata(metadata);
+        let page_ids: HashMap<&str, u32> = graph_data.nodes.iter()
+            .filter(|node| node.is_page())
+            .map(|node| (node.metadata_id.as_str(), node.id))
+            .collect();
+        // Ordered so task ids don't depend on hash order
+        let mut file_names: Vec<&String> = metadata.keys().collect();
+        file_names.sort_unstable();
+        let mut task_nodes = Vec::new();
+        for file_name in file_names {
+            let page = file_name.trim_end_matches(".md");
+            let Some(&page_id) = page_ids.get(page) else { continue };
+            for task in metadata[file_name].tasks.iter().filter(|task| !task.is_done()) {
+                let id = self.next_node_id.fetch_add(1, Ordering::SeqCst);
+                let mut node = Node::new_with_id(format!("{}:{}:{}", TASK_NODE_TYPE, page, task.line), Some(id));
+                node.label = task.text.clone();
+                node.node_type = Some(TASK_NODE_TYPE.to_string());
+                node.data.flags = 1;
+                node.metadata.insert("marker".to_string(), task.marker.clone());
+                node.metadata.insert("page".to_string(), page.to_string());
+                if let Some(due) = task.deadline.or(task.scheduled) {
+                    node.metadata.insert("due".to_string(), due.date.to_string());
+                }
+
+                let mut edge = Edge::new(page_id, id, 1.0);
+                edge.edge_type = Some(TASK_NODE_TYPE.to_string());
+                graph_data.edges.push(edge);
+                let links: String = task.references.iter().map(|reference| format!("[[{}]] ", reference)).collect();
+                for target in ParserProfile::Obsidian.extract_references(&links, &pages) {
+                    if let Some(&target_id) = page_ids.get(target.as_str()).filter(|&&target_id| target_id != page_id) {
+                        let mut edge = Edge::new(id, target_id, 1.0);
+                        edge.edge_type = Some(TASK_REFERENCE_EDGE_TYPE.to_string());
+                        graph_data.edges.push(edge);
+                    }
+                }
+                task_nodes.push(node);
+            }
+        }
+
+        debug!("Added {} task nodes", task_nodes.len());
+        for node in task_nodes {
+            self.node_map.insert(node.id, node.clone());
+            graph_data.nodes.push(node);
+        }
+    }
+
+    /// Adds a node per external note with an edge to each page it links to. Notes
+    /// found in `placed` keep that node's id and position; new ones start next to
+    /// the first page they link to.
+    fn add_external_notes(&mut self, metadata: &MetadataStore, graph_data: &mut GraphData, mut placed: HashMap<String, Node>) {
+        let pages = PageIndex::from_metadata(metadata);
+        let page_nodes: HashMap<&str, (u32, Vec3Data)> = graph_data.nodes.iter()
+            .filter(|node| node.is_page())
+            .map(|node| (node.metadata_id.as_str(), (node.id, node.data.position)))
+            .collect();
+        let mut note_nodes = Vec::new();
+        let mut note_edges = Vec::new();
+        for note in &self.external_notes {
+            let linked: Vec<(u32, Vec3Data)> = note.linked_pages(&pages).iter()
+                .filter_map(|page| page_nodes.get(page.as_str()).copied())
+                .collect();
+            let mut node = match placed.remove(&note.node_id()) {
+                Some(node) => node,
+                None => {
+                    let mut node = Node::new_with_id(note.node_id(), Some(self.next_node_id.fetch_add(1, Ordering::SeqCst)));
+                    let anchor = linked.first().map_or(Vec3Data::zero(), |(_, position)| *position);
+                    node.data.position = Vec3Data::new(
+                        anchor.x + self.rng.gen_range(-1.0..1.0),
+                        anchor.y + self.rng.gen_range(-1.0..1.0),
+                        anchor.z + self.rng.gen_range(-1.0..1.0),
+                    );
+                    node
+                }
+            };
+            node.label = note.title.clone();
+            node.node_type = Some(NOSTR_NODE_TYPE.to_string());
+            node.data.flags = 1;
+            node.metadata.insert("nostrEventId".to_string(), note.event_id.clone());
+            node.metadata.insert("author".to_string(), note.author.clone());
+            node.metadata.insert("kind".to_string(), note.kind.to_string());
+            if let Some(published) = chrono::DateTime::from_timestamp(note.created_at, 0) {
+                node.metadata.insert("publishedAt".to_string(), published.to_rfc3339());
+            }
+            for (page_id, _) in linked {
+                let mut edge = Edge::new(node.id, page_id, 1.0);
+                edge.edge_type = Some(NOSTR_NODE_TYPE.to_string());
+                note_edges.push(edge);
+            }
+            note_nodes.push(node);
+        }
+
+        debug!("Added {} Nostr notes linked to {} pages", note_nodes.len(), note_edges.len());
+        graph_data.edges.extend(note_edges);
+        for node in note_nodes {
+            self.node_map.insert(node.id, node.clone());
+            graph_data.nodes.push(node);
+        }
+    }
+
+    /// Swaps the external notes in the live graph for `notes` without a rebuild
+    fn replace_external_notes(&mut self, notes: Vec<ExternalNote>) {
+        self.external_notes = notes;
+        let mut graph_data = (*self.graph_data).clone();
+        let is_note = |node: &Node| node.node_type.as_deref() == Some(NOSTR_NODE_TYPE);
+        let placed: HashMap<String, Node> = graph_data.nodes.iter()
+            .filter(|node| is_note(node))
+            .map(|node| (node.metadata_id.clone(), node.clone()))
+            .collect();
+        let removed: HashSet<u32> = placed.values().map(|node| node.id).collect();
+        graph_data.nodes.retain(|node| !is_note(node));
+        graph_data.edges.retain(|edge| !removed.contains(&edge.source) && !removed.contains(&edge.target));
+        self.node_map.retain(|id, _| !removed.contains(id));
+
+        let metadata = graph_data.metadata.clone();
+        self.add_external_notes(&metadata, &mut graph_data, placed);
+        self.graph_data = Arc::new(graph_data);
+        self.position_frame = None;
+        self.revision += 1;
+        self.aggregations.clear();
+        self.broadcast_edge_snapshot();
+    }
+
     pub fn update_node_positions(&mut self, positions: Vec<(u32, BinaryNodeData)>) {
         let mut updated_count = 0;
+        self.position_frame = None;
         let graph_data_mut = Arc::make_mut(&mut self.graph_data);
         
         for (node_id, position_data) in positions {
@@ -216,19 +754,19 @@ impl GraphServiceActor {
     }
 
     fn run_simulation_step(&mut self) {
+        for (node_id, client_id) in self.grabs.expire(std::time::Instant::now()) {
+            info!("Drag of node {} by client {} timed out", node_id, client_id);
+            self.broadcast_grab_change(node_id, None);
+        }
+
         // Run physics calculation (GPU or CPU fallback)
         match self.calculate_layout() {
-            Ok(updated_positions) => {
+            Ok(mut updated_positions) => {
+                let node_map = &self.node_map;
+                self.grabs.apply_step(&mut updated_positions, |id| node_map.get(&id).map(|node| node.data));
                 if !updated_positions.is_empty() {
-                    // Update positions
-                    self.update_node_positions(updated_positions.clone());
-                    
-                    // Broadcast to clients
-                    if let Ok(binary_data) = self.encode_node_positions(&updated_positions) {
-                        self.client_manager.do_send(BroadcastNodePositions { 
-                            positions: binary_data 
-                        });
-                    }
+                    // Sessions get these from the next broadcaster frame
+                    self.update_node_positions(updated_positions);
                 }
             }
             Err(e) => {
@@ -237,7 +775,17 @@ impl GraphServiceActor {
         }
     }
 
-    fn calculate_layout(&self) -> Result<Vec<(u32, BinaryNodeData)>, String> {
+    /// Tells every client a node was grabbed by `holder`, or released if `None`, so
+    /// they can stop fighting over it
+    fn broadcast_grab_change(&self, node_id: u32, holder: Option<usize>) {
+        let message = match holder {
+            Some(client_id) => serde_json::json!({ "type": "nodeGrabbed", "nodeId": node_id, "clientId": client_id }),
+            None => serde_json::json!({ "type": "nodeReleased", "nodeId": node_id }),
+        };
+        self.client_manager.do_send(BroadcastMessage { message: message.to_string() });
+    }
+
+    fn calculate_layout(&mut self) -> Result<Vec<(u32, BinaryNodeData)>, String> {
         // For now, always use CPU fallback since GPU actor communication is async
         // TODO: Refactor simulation loop to handle async GPU computation properly
         self.calculate_layout_cpu()
@@ -288,30 +836,30 @@ impl GraphServiceActor {
             // Convert future to ActorFuture and spawn it
             ctx.wait(future.into_actor(self).map(|positions, actor, _ctx| {
                 if !positions.is_empty() {
-                    actor.update_node_positions(positions.clone());
-                    
-                    // Broadcast to clients
-                    if let Ok(binary_data) = actor.encode_node_positions(&positions) {
-                        actor.client_manager.do_send(BroadcastNodePositions {
-                            positions: binary_data
-                        });
-                    }
+                    actor.update_node_positions(positions);
                 }
             }));
         }
     }
     */
 
-    fn calculate_layout_cpu(&self) -> Result<Vec<(u32, BinaryNodeData)>, String> {
+    fn calculate_layout_cpu(&mut self) -> Result<Vec<(u32, BinaryNodeData)>, String> {
         // Simple CPU physics simulation
         let mut updated_positions = Vec::new();
+        let rng = &mut self.rng;
         
         for node in &self.graph_data.nodes {
             // Simple physics: apply some random movement for demo
             let mut new_data = node.data.clone();
-            new_data.position.x += (rand::random::<f32>() - 0.5) * 0.1;
-            new_data.position.y += (rand::random::<f32>() - 0.5) * 0.1;
-            new_data.position.z += (rand::random::<f32>() - 0.5) * 0.1;
+            new_data.position.x += (rng.gen::<f32>() - 0.5) * 0.1;
+            new_data.position.y += (rng.gen::<f32>() - 0.5) * 0.1;
+            new_data.position.z += (rng.gen::<f32>() - 0.5) * 0.1;
+            if self.world_bounds.config.enabled {
+                let limit = self.world_bounds.half_extent;
+                new_data.position.x = new_data.position.x.clamp(-limit, limit);
+                new_data.position.y = new_data.position.y.clamp(-limit, limit);
+                new_data.position.z = new_data.position.z.clamp(-limit, limit);
+            }
             
             updated_positions.push((node.id, new_data));
         }
@@ -319,10 +867,6 @@ impl GraphServiceActor {
         Ok(updated_positions)
     }
 
-    fn encode_node_positions(&self, positions: &[(u32, BinaryNodeData)]) -> Result<Vec<u8>, String> {
-        // Now binary_protocol expects (u32, BinaryNodeData) directly
-        Ok(binary_protocol::encode_node_data(positions))
-    }
 }
 
 impl Actor for GraphServiceActor {
@@ -330,7 +874,11 @@ impl Actor for GraphServiceActor {
 
     fn started(&mut self, ctx: &mut Self::Context) {
         info!("GraphServiceActor started");
-        self.start_simulation_loop(ctx);
+        self.push_bounds_to_gpu();
+        match self.physics {
+            PhysicsBackend::Cpu => self.start_simulation_loop(ctx),
+            PhysicsBackend::Noop => info!("Physics disabled, node positions stay as built"),
+        }
     }
 
     fn stopped(&mut self, _ctx: &mut Self::Context) {
@@ -350,6 +898,49 @@ impl Handler<GetGraphData> for GraphServiceActor {
     }
 }
 
+impl Handler<GetGraphRevision> for GraphServiceActor {
+    type Result = u64;
+
+    fn handle(&mut self, _msg: GetGraphRevision, _ctx: &mut Self::Context) -> Self::Result {
+        self.revision
+    }
+}
+
+impl Handler<GetPositionFrame> for GraphServiceActor {
+    type Result = Result<EncodedFrame, String>;
+
+    fn handle(&mut self, _msg: GetPositionFrame, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(self.position_frame())
+    }
+}
+
+impl Handler<GetEdgeFrame> for GraphServiceActor {
+    type Result = Result<EdgeFrameUpdate, String>;
+
+    fn handle(&mut self, _msg: GetEdgeFrame, _ctx: &mut Self::Context) -> Self::Result {
+        Ok(self.edge_frame())
+    }
+}
+
+impl Handler<GetGraphAggregation> for GraphServiceActor {
+    type Result = Result<Arc<GraphAggregation>, String>;
+
+    fn handle(&mut self, msg: GetGraphAggregation, _ctx: &mut Self::Context) -> Self::Result {
+        if let Some(aggregation) = self.aggregations.get(&msg.cell_size.to_bits()) {
+            return Ok(Arc::clone(aggregation));
+        }
+        let aggregation = Arc::new(GraphAggregation::build(
+            self.graph_data.nodes.iter().map(|node| (node.id, node.data.position)),
+            msg.cell_size,
+        )?);
+        info!("Aggregated {} of {} nodes into {} supernodes (cell size {})",
+            aggregation.aggregated_node_count(), self.graph_data.nodes.len(),
+            aggregation.supernodes.len(), msg.cell_size);
+        self.aggregations.insert(msg.cell_size.to_bits(), Arc::clone(&aggregation));
+        Ok(aggregation)
+    }
+}
+
 impl Handler<UpdateNodePositions> for GraphServiceActor {
     type Result = Result<(), String>;
 
@@ -364,6 +955,7 @@ impl Handler<AddNode> for GraphServiceActor {
 
     fn handle(&mut self, msg: AddNode, _ctx: &mut Self::Context) -> Self::Result {
         self.add_node(msg.node);
+        self.refit_bounds(false);
         Ok(())
     }
 }
@@ -373,6 +965,7 @@ impl Handler<RemoveNode> for GraphServiceActor {
 
     fn handle(&mut self, msg: RemoveNode, _ctx: &mut Self::Context) -> Self::Result {
         self.remove_node(msg.node_id);
+        self.refit_bounds(false);
         Ok(())
     }
 }
@@ -407,7 +1000,57 @@ impl Handler<BuildGraphFromMetadata> for GraphServiceActor {
     type Result = Result<(), String>;
 
     fn handle(&mut self, msg: BuildGraphFromMetadata, _ctx: &mut Self::Context) -> Self::Result {
-        self.build_from_metadata(msg.metadata)
+        self.build_from_metadata(msg.metadata, msg.seed)?;
+        self.event_bus.publish(GraphEvent::Rebuilt {
+            node_count: self.graph_data.nodes.len(),
+            edge_count: self.graph_data.edges.len(),
+        });
+        self.restyle();
+        self.refit_bounds(false);
+        Ok(())
+    }
+}
+
+impl Handler<SetExternalNotes> for GraphServiceActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetExternalNotes, _ctx: &mut Self::Context) -> Self::Result {
+        self.replace_external_notes(msg.notes);
+        self.event_bus.publish(GraphEvent::Updated {
+            node_count: self.graph_data.nodes.len(),
+            edge_count: self.graph_data.edges.len(),
+        });
+        self.restyle();
+        self.refit_bounds(false);
+    }
+}
+
+impl Handler<SetNodeColors> for GraphServiceActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: SetNodeColors, _ctx: &mut Self::Context) -> Self::Result {
+        self.node_colors = msg.mapper;
+        self.restyle();
+    }
+}
+
+impl Handler<GetWorldBounds> for GraphServiceActor {
+    type Result = MessageResult<GetWorldBounds>;
+
+    fn handle(&mut self, _msg: GetWorldBounds, _ctx: &mut Self::Context) -> Self::Result {
+        MessageResult(self.world_bounds)
+    }
+}
+
+impl Handler<SetWorldBounds> for GraphServiceActor {
+    type Result = Result<WorldBounds, String>;
+
+    fn handle(&mut self, msg: SetWorldBounds, _ctx: &mut Self::Context) -> Self::Result {
+        msg.config.validate()?;
+        let changed = msg.config != self.world_bounds.config;
+        self.world_bounds.config = msg.config;
+        self.refit_bounds(changed);
+        Ok(self.world_bounds)
     }
 }
 
@@ -433,6 +1076,10 @@ impl Handler<UpdateNodePosition> for GraphServiceActor {
     type Result = Result<(), String>;
 
     fn handle(&mut self, msg: UpdateNodePosition, _ctx: &mut Self::Context) -> Self::Result {
+        // Untracked updates can't take a node away from the client dragging it
+        if let Some(holder) = self.grabs.holder(msg.node_id) {
+            return Err(format!("Node {} is being dragged by client {}", msg.node_id, holder));
+        }
         // Update node in the node map
         if let Some(node) = self.node_map.get_mut(&msg.node_id) {
             // Preserve existing mass and flags
@@ -449,6 +1096,7 @@ impl Handler<UpdateNodePosition> for GraphServiceActor {
             debug!("Received update for unknown node ID: {}", msg.node_id);
             return Err(format!("Unknown node ID: {}", msg.node_id));
         }
+        self.position_frame = None;
         
         // Update corresponding node in graph
         let graph_data_mut = Arc::make_mut(&mut self.graph_data);
@@ -489,8 +1137,15 @@ impl Handler<UpdateGraphData> for GraphServiceActor {
         info!("Updating graph data with {} nodes, {} edges",
               msg.graph_data.nodes.len(), msg.graph_data.edges.len());
         
+        let mut graph_data = msg.graph_data;
+        graph_validation::sanitize(&mut graph_data, &self.repair_options);
+
         // Update graph data by creating a new Arc
-        self.graph_data = Arc::new(msg.graph_data);
+        self.graph_data = Arc::new(graph_data);
+        self.position_frame = None;
+        self.revision += 1;
+        self.aggregations.clear();
+        self.broadcast_edge_snapshot();
         
         // Rebuild node map
         self.node_map.clear();
@@ -499,6 +1154,85 @@ impl Handler<UpdateGraphData> for GraphServiceActor {
         }
         
         info!("Graph data updated successfully");
+        self.event_bus.publish(GraphEvent::Updated {
+            node_count: self.graph_data.nodes.len(),
+            edge_count: self.graph_data.edges.len(),
+        });
+        self.restyle();
+        self.refit_bounds(false);
+        Ok(())
+    }
+}
+
+impl Handler<RepairGraph> for GraphServiceActor {
+    type Result = Result<RepairReport, String>;
+
+    fn handle(&mut self, msg: RepairGraph, ctx: &mut Self::Context) -> Self::Result {
+        let mut graph_data = (*self.graph_data).clone();
+        let report = graph_validation::sanitize(&mut graph_data, &msg.options);
+        if !report.repaired.is_empty() {
+            Handler::<UpdateGraphData>::handle(self, UpdateGraphData { graph_data }, ctx)?;
+        }
+        Ok(report)
+    }
+}
+
+impl Handler<GrabNode> for GraphServiceActor {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, msg: GrabNode, _ctx: &mut Self::Context) -> Self::Result {
+        let Some(node) = self.node_map.get(&msg.node_id) else {
+            return Err(format!("Unknown node ID: {}", msg.node_id));
+        };
+        let mut data = node.data;
+        let already_held = self.grabs.holder(msg.node_id) == Some(msg.client_id);
+        self.grabs.grab(msg.node_id, msg.client_id, std::time::Instant::now())?;
+        if !already_held {
+            data.velocity = Vec3Data::zero();
+            self.update_node_positions(vec![(msg.node_id, data)]);
+            self.broadcast_grab_change(msg.node_id, Some(msg.client_id));
+        }
+        Ok(())
+    }
+}
+
+impl Handler<MoveGrabbedNode> for GraphServiceActor {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, msg: MoveGrabbedNode, _ctx: &mut Self::Context) -> Self::Result {
+        self.grabs.touch(msg.node_id, msg.client_id, std::time::Instant::now())?;
+        if let Some(node) = self.node_map.get(&msg.node_id) {
+            let mut data = node.data;
+            data.position = msg.position;
+            data.velocity = Vec3Data::zero();
+            self.update_node_positions(vec![(msg.node_id, data)]);
+        }
         Ok(())
     }
 }
+
+impl Handler<ReleaseNode> for GraphServiceActor {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, msg: ReleaseNode, _ctx: &mut Self::Context) -> Self::Result {
+        self.grabs.release(msg.node_id, msg.client_id)?;
+        if let (Some(velocity), Some(node)) = (msg.velocity, self.node_map.get(&msg.node_id)) {
+            let mut data = node.data;
+            data.velocity = velocity;
+            self.update_node_positions(vec![(msg.node_id, data)]);
+        }
+        self.broadcast_grab_change(msg.node_id, None);
+        Ok(())
+    }
+}
+
+impl Handler<ReleaseClientGrabs> for GraphServiceActor {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReleaseClientGrabs, _ctx: &mut Self::Context) -> Self::Result {
+        for node_id in self.grabs.release_client(msg.client_id) {
+            debug!("Released node {} dragged by disconnected client {}", node_id, msg.client_id);
+            self.broadcast_grab_change(node_id, None);
+        }
+    }
+}
@@ -8,9 +8,19 @@ use crate::models::node::Node;
 use crate::models::edge::Edge;
 use crate::models::metadata::MetadataStore;
 use crate::config::AppFullSettings;
+use crate::config::validation::ValidationErrors;
 use crate::models::graph::GraphData as ServiceGraphData;
 use crate::utils::socket_flow_messages::BinaryNodeData;
+use crate::types::vec3::Vec3Data;
+use crate::utils::binary_protocol::EncodedFrame;
+use crate::models::graph_aggregation::GraphAggregation;
+use std::sync::Arc;
+use bytes::Bytes;
 use crate::models::simulation_params::SimulationParams;
+use crate::services::graph_validation::{RepairOptions, RepairReport};
+use crate::services::node_colors::NodeColorMapper;
+use crate::services::nostr_ingest::ExternalNote;
+use crate::services::world_bounds::{BoundsConfig, WorldBounds};
 use crate::models::graph::GraphData as ModelsGraphData;
 
 // Graph Service Actor Messages
@@ -18,6 +28,25 @@ use crate::models::graph::GraphData as ModelsGraphData;
 #[rtype(result = "Result<ServiceGraphData, String>")]
 pub struct GetGraphData;
 
+/// Revision of the graph, incremented whenever nodes or edges change but not when
+/// nodes only move
+#[derive(Message)]
+#[rtype(result = "u64")]
+pub struct GetGraphRevision;
+
+/// Current node positions as a shared binary frame, encoded at most once per change
+#[derive(Message)]
+#[rtype(result = "Result<EncodedFrame, String>")]
+pub struct GetPositionFrame;
+
+/// Supernode aggregation of the graph at the given grid cell size; built on first
+/// request and reused until nodes are added or removed
+#[derive(Message)]
+#[rtype(result = "Result<Arc<GraphAggregation>, String>")]
+pub struct GetGraphAggregation {
+    pub cell_size: f32,
+}
+
 #[derive(Message)]
 #[rtype(result = "Result<(), String>")]
 pub struct UpdateNodePositions {
@@ -48,14 +77,54 @@ pub struct RemoveEdge {
     pub edge_id: String,
 }
 
+/// Binary edge frame together with the edge type names its type codes refer to
+#[derive(Debug, Clone)]
+pub struct EdgeFrameUpdate {
+    pub frame: Bytes,
+    pub edge_types: Vec<String>,
+}
+
+/// Every edge in the graph as a snapshot edge frame
+#[derive(Message)]
+#[rtype(result = "Result<EdgeFrameUpdate, String>")]
+pub struct GetEdgeFrame;
+
 #[derive(Message)]
 #[rtype(result = "Result<HashMap<u32, Node>, String>")]
 pub struct GetNodeMap;
 
+/// Replaces the node colour mapper and recolours the current graph
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SetNodeColors {
+    pub mapper: NodeColorMapper,
+}
+
+#[derive(Message)]
+#[rtype(result = "WorldBounds")]
+pub struct GetWorldBounds;
+
+/// Replaces the bounds configuration and refits the bounds to the current graph
+#[derive(Message)]
+#[rtype(result = "Result<WorldBounds, String>")]
+pub struct SetWorldBounds {
+    pub config: BoundsConfig,
+}
+
+/// Replaces the notes pulled from Nostr relays. Notes already in the graph keep
+/// their node and position.
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct SetExternalNotes {
+    pub notes: Vec<ExternalNote>,
+}
+
 #[derive(Message)]
 #[rtype(result = "Result<(), String>")]
 pub struct BuildGraphFromMetadata {
     pub metadata: MetadataStore,
+    /// Overrides the configured layout seed for this build
+    pub seed: Option<u64>,
 }
 
 #[derive(Message)]
@@ -70,6 +139,37 @@ pub struct UpdateNodePosition {
     pub velocity: Vec3,
 }
 
+/// Starts a drag, giving the client authority over the node until it lets go
+#[derive(Message)]
+#[rtype(result = "Result<(), String>")]
+pub struct GrabNode {
+    pub node_id: u32,
+    pub client_id: usize,
+}
+
+#[derive(Message)]
+#[rtype(result = "Result<(), String>")]
+pub struct MoveGrabbedNode {
+    pub node_id: u32,
+    pub client_id: usize,
+    pub position: Vec3Data,
+}
+
+/// Ends a drag; `velocity` lets the client throw the node back into the layout
+#[derive(Message)]
+#[rtype(result = "Result<(), String>")]
+pub struct ReleaseNode {
+    pub node_id: u32,
+    pub client_id: usize,
+    pub velocity: Option<Vec3Data>,
+}
+
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct ReleaseClientGrabs {
+    pub client_id: usize,
+}
+
 #[derive(Message)]
 #[rtype(result = "Result<(), String>")]
 pub struct SimulationStep;
@@ -84,13 +184,20 @@ pub struct UpdateGraphData {
     pub graph_data: ServiceGraphData,
 }
 
+/// Fixes what `options` allows in the live graph, as `graph_validation::repair`
+#[derive(Message)]
+#[rtype(result = "Result<RepairReport, String>")]
+pub struct RepairGraph {
+    pub options: RepairOptions,
+}
+
 // Settings Actor Messages
 #[derive(Message)]
 #[rtype(result = "Result<AppFullSettings, String>")]
 pub struct GetSettings;
 
 #[derive(Message)]
-#[rtype(result = "Result<(), String>")]
+#[rtype(result = "Result<(), ValidationErrors>")]
 pub struct UpdateSettings {
     pub settings: AppFullSettings,
 }
@@ -128,6 +235,7 @@ pub struct RefreshMetadata;
 #[rtype(result = "Result<usize, String>")]
 pub struct RegisterClient {
     pub addr: actix::Addr<crate::handlers::socket_flow_handler::SocketFlowServer>,
+    pub resume_token: String,
 }
 
 #[derive(Message)]
@@ -136,10 +244,36 @@ pub struct UnregisterClient {
     pub client_id: usize,
 }
 
+/// State of a disconnected client, kept for a grace period so a reconnect presenting
+/// the same resume token can pick up where it left off
 #[derive(Message)]
-#[rtype(result = "Result<(), String>")]
-pub struct BroadcastNodePositions {
-    pub positions: Vec<u8>,
+#[rtype(result = "()")]
+pub struct SuspendSession {
+    pub token: String,
+    pub session: crate::handlers::socket_flow_handler::SuspendedSession,
+}
+
+/// Claims a suspended session, or takes it over from a connection that hasn't yet
+/// noticed its client is gone
+#[derive(Message)]
+#[rtype(result = "Option<crate::handlers::socket_flow_handler::SuspendedSession>")]
+pub struct ResumeSession {
+    pub token: String,
+}
+
+/// Asks a live connection to give its session to a reconnecting client and close
+#[derive(Message)]
+#[rtype(result = "Option<crate::handlers::socket_flow_handler::SuspendedSession>")]
+pub struct HandOverSession;
+
+/// One position frame per broadcaster tick, fanned out to every client
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct BroadcastPositionFrame {
+    pub frame: EncodedFrame,
+    /// Mean kinetic energy per node, used by clients to pick their update rate
+    pub kinetic_energy: f32,
+    pub detailed_debug: bool,
 }
 
 #[derive(Message)]
@@ -155,11 +289,25 @@ pub struct GetClientCount;
 // Messages for ClientManagerActor to send to individual SocketFlowServer clients
 #[derive(Message)]
 #[rtype(result = "()")]
-pub struct SendToClientBinary(pub Vec<u8>);
+pub struct SendToClientText(pub String);
 
+/// Edge changes for clients subscribed to edge updates
 #[derive(Message)]
 #[rtype(result = "()")]
-pub struct SendToClientText(pub String);
+pub struct BroadcastEdgeFrame(pub EdgeFrameUpdate);
+
+#[derive(Message, Clone)]
+#[rtype(result = "()")]
+pub struct SendEdgeFrame(pub EdgeFrameUpdate);
+
+/// The shared position frame; each client filters it down to what it needs
+#[derive(Message, Clone)]
+#[rtype(result = "()")]
+pub struct SendPositionFrame {
+    pub frame: EncodedFrame,
+    pub kinetic_energy: f32,
+    pub detailed_debug: bool,
+}
 
 // GPU Compute Actor Messages
 #[derive(Message)]
@@ -180,6 +328,14 @@ pub struct UpdateSimulationParams {
     pub params: SimulationParams,
 }
 
+/// Moves the boundary the kernel holds nodes inside
+#[derive(Message)]
+#[rtype(result = "()")]
+pub struct UpdateViewportBounds {
+    pub half_extent: f32,
+    pub enabled: bool,
+}
+
 #[derive(Message)]
 #[rtype(result = "Result<(), String>")]
 pub struct ComputeForces;
@@ -2,7 +2,7 @@ use actix::prelude::*;
 use log::info;
 use serde_json::Value;
 
-use crate::models::protected_settings::{ProtectedSettings, NostrUser, ApiKeys};
+use crate::models::protected_settings::{ProtectedSettings, NostrUser, ApiKeys, GitHubConnection, protected_settings_path};
 
 pub struct ProtectedSettingsActor {
     settings: ProtectedSettings,
@@ -144,4 +144,55 @@ impl Handler<GetUser> for ProtectedSettingsActor {
     fn handle(&mut self, msg: GetUser, _ctx: &mut Self::Context) -> Self::Result {
         self.settings.users.get(&msg.pubkey).cloned()
     }
-}
\ No newline at end of file
+}
+
+// Message to connect a user's GitHub account; the settings are saved straight away
+// so the token survives a restart
+#[derive(Message)]
+#[rtype(result = "Result<(), String>")]
+pub struct StoreGitHubConnection {
+    pub pubkey: String,
+    pub connection: GitHubConnection,
+}
+
+impl Handler<StoreGitHubConnection> for ProtectedSettingsActor {
+    type Result = Result<(), String>;
+
+    fn handle(&mut self, msg: StoreGitHubConnection, _ctx: &mut Self::Context) -> Self::Result {
+        self.settings.github_connections.insert(msg.pubkey, msg.connection);
+        self.settings.save(protected_settings_path())
+    }
+}
+
+// Message to get a user's connected GitHub account
+#[derive(Message)]
+#[rtype(result = "Option<GitHubConnection>")]
+pub struct GetGitHubConnection {
+    pub pubkey: String,
+}
+
+impl Handler<GetGitHubConnection> for ProtectedSettingsActor {
+    type Result = Option<GitHubConnection>;
+
+    fn handle(&mut self, msg: GetGitHubConnection, _ctx: &mut Self::Context) -> Self::Result {
+        self.settings.github_connections.get(&msg.pubkey).cloned()
+    }
+}
+
+// Message to disconnect a user's GitHub account; returns whether one was connected
+#[derive(Message)]
+#[rtype(result = "Result<bool, String>")]
+pub struct RemoveGitHubConnection {
+    pub pubkey: String,
+}
+
+impl Handler<RemoveGitHubConnection> for ProtectedSettingsActor {
+    type Result = Result<bool, String>;
+
+    fn handle(&mut self, msg: RemoveGitHubConnection, _ctx: &mut Self::Context) -> Self::Result {
+        if self.settings.github_connections.remove(&msg.pubkey).is_none() {
+            return Ok(false);
+        }
+        self.settings.save(protected_settings_path()).map(|_| true)
+    }
+}
@@ -6,6 +6,7 @@ use log::{debug, info};
 
 use crate::actors::messages::*;
 use crate::config::AppFullSettings;
+use crate::config::validation::{validate_settings, ValidationErrors};
 
 pub struct SettingsActor {
     settings: AppFullSettings,
@@ -20,9 +21,12 @@ impl SettingsActor {
         &self.settings
     }
 
-    pub fn update_settings(&mut self, new_settings: AppFullSettings) {
+    /// Replaces the settings, keeping the current ones if the new settings fail validation
+    pub fn update_settings(&mut self, new_settings: AppFullSettings) -> Result<(), ValidationErrors> {
+        validate_settings(&new_settings)?;
         self.settings = new_settings;
         debug!("Settings updated");
+        Ok(())
     }
 
     pub fn get_setting_by_path(&self, path: &str) -> Result<Value, String> {
@@ -121,8 +125,10 @@ impl SettingsActor {
         }
         
         // Convert back to AppFullSettings
-        self.settings = serde_json::from_value(settings_value)
+        let updated: AppFullSettings = serde_json::from_value(settings_value)
             .map_err(|e| format!("Failed to deserialize updated settings: {}", e))?;
+        validate_settings(&updated).map_err(|e| e.to_string())?;
+        self.settings = updated;
         
         debug!("Setting '{}' updated", path);
         Ok(())
@@ -150,11 +156,10 @@ impl Handler<GetSettings> for SettingsActor {
 }
 
 impl Handler<UpdateSettings> for SettingsActor {
-    type Result = Result<(), String>;
+    type Result = Result<(), ValidationErrors>;
 
     fn handle(&mut self, msg: UpdateSettings, _ctx: &mut Self::Context) -> Self::Result {
-        self.update_settings(msg.settings);
-        Ok(())
+        self.update_settings(msg.settings)
     }
 }
 
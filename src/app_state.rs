@@ -1,19 +1,47 @@
 use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
 use actix::prelude::*;
 use actix_web::web;
-use log::info;
+use log::{debug, info, warn};
 
+use crate::actors::graph_actor::PhysicsBackend;
 use crate::actors::{GraphServiceActor, SettingsActor, MetadataActor, ClientManagerActor, GPUComputeActor, ProtectedSettingsActor};
 use crate::config::AppFullSettings; // Renamed for clarity, ClientFacingSettings removed
 use tokio::time::Duration;
 use crate::config::feature_access::FeatureAccess;
+use crate::config::data_dirs::DataDirs;
+use crate::models::graph::GraphBuildOptions;
 use crate::models::metadata::MetadataStore;
-use crate::models::protected_settings::{ProtectedSettings, ApiKeys, NostrUser};
-use crate::services::github::{GitHubClient, ContentAPI};
+use crate::models::protected_settings::{ProtectedSettings, ApiKeys, NostrUser, GitHubConnection, protected_settings_path};
+use crate::services::github::{ContentAPI, GitHubClient, GitHubConfig, GitHubOAuth, GitHubService};
+use crate::services::graph_validation::RepairOptions;
+use crate::services::node_colors::NodeColorMapper;
 use crate::services::perplexity_service::PerplexityService;
 use crate::services::speech_service::SpeechService;
 use crate::services::ragflow_service::RAGFlowService;
 use crate::services::nostr_service::NostrService;
+use crate::services::activity::ActivityLog;
+use crate::services::nostr_ingest::NostrIngest;
+use crate::services::nostr_publisher::NostrPublisher;
+use crate::services::comments::CommentService;
+use crate::services::label_atlas::LabelAtlasCache;
+use crate::services::recommendations::Recommender;
+use crate::services::ai_usage;
+use crate::services::resilience;
+use crate::services::demo::{DemoConfig, DemoService};
+use crate::services::fault_injection::{FaultInjectionConfig, FaultInjector, FaultyGitHub};
+use crate::services::saved_filters::SavedFilterService;
+use crate::services::conversations::ConversationStore;
+use crate::services::event_bus::EventBus;
+use crate::services::webhook_service::WebhookService;
+use crate::services::world_bounds::BoundsConfig;
+use crate::services::view_links::ViewLinkService;
+use crate::services::shares::ShareService;
+use crate::services::job_queue::JobQueue;
+use crate::services::position_broadcaster;
+use crate::services::replay::ReplayRecorder;
+use crate::services::snapshot;
+use crate::services::telemetry::{TelemetryLimits, TelemetryService};
+use crate::services::tenants::{TenantQuota, TenantRegistry};
 
 #[derive(Clone)]
 pub struct AppState {
@@ -24,70 +52,46 @@ pub struct AppState {
     pub metadata_addr: Addr<MetadataActor>,
     pub client_manager_addr: Addr<ClientManagerActor>,
     pub github_client: Arc<GitHubClient>,
-    pub content_api: Arc<ContentAPI>,
+    pub content_api: Arc<dyn GitHubService>,
     pub perplexity_service: Option<Arc<PerplexityService>>,
     pub ragflow_service: Option<Arc<RAGFlowService>>,
     pub speech_service: Option<Arc<SpeechService>>,
     pub nostr_service: Option<web::Data<NostrService>>,
     pub feature_access: web::Data<FeatureAccess>,
-    pub ragflow_session_id: String,
     pub active_connections: Arc<AtomicUsize>,
+    pub event_bus: EventBus,
+    pub webhook_service: Arc<WebhookService>,
+    pub job_queue: JobQueue,
+    pub view_links: Arc<ViewLinkService>,
+    pub shares: Arc<ShareService>,
+    pub tenants: Arc<TenantRegistry>,
+    pub github_oauth: Option<Arc<GitHubOAuth>>,
+    pub activity: Arc<ActivityLog>,
+    pub comments: Arc<CommentService>,
+    pub saved_filters: Arc<SavedFilterService>,
+    pub conversations: Arc<ConversationStore>,
+    pub label_atlases: Arc<LabelAtlasCache>,
+    pub recommendations: Arc<Recommender>,
+    pub demo: Arc<DemoService>,
+    pub telemetry: Arc<TelemetryService>,
 }
 
 impl AppState {
     pub async fn new(
         settings: AppFullSettings,
         github_client: Arc<GitHubClient>,
-        content_api: Arc<ContentAPI>,
+        content_api: Arc<dyn GitHubService>,
         perplexity_service: Option<Arc<PerplexityService>>,
         ragflow_service: Option<Arc<RAGFlowService>>,
         speech_service: Option<Arc<SpeechService>>,
-        ragflow_session_id: String,
     ) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
-        info!("[AppState::new] Initializing actor system");
-        tokio::time::sleep(Duration::from_millis(50)).await;
-        
-        // Start actors
-        info!("[AppState::new] Starting ClientManagerActor");
-        let client_manager_addr = ClientManagerActor::new().start();
-        
-        info!("[AppState::new] Starting SettingsActor");
-        let settings_addr = SettingsActor::new(settings).start();
-        
-        info!("[AppState::new] Starting MetadataActor");
-        let metadata_addr = MetadataActor::new(MetadataStore::new()).start();
-        
-        info!("[AppState::new] Starting GPUComputeActor");
-        let gpu_compute_addr = Some(GPUComputeActor::new().start());
-        
-        info!("[AppState::new] Starting GraphServiceActor");
-        let graph_service_addr = GraphServiceActor::new(
-            client_manager_addr.clone(),
-            gpu_compute_addr.clone()
-        ).start();
-        
-        info!("[AppState::new] Starting ProtectedSettingsActor");
-        let protected_settings_addr = ProtectedSettingsActor::new(ProtectedSettings::default()).start();
-        
-        info!("[AppState::new] Actor system initialization complete");
-        
-        Ok(Self {
-            graph_service_addr,
-            gpu_compute_addr,
-            settings_addr,
-            protected_settings_addr,
-            metadata_addr,
-            client_manager_addr,
-            github_client,
-            content_api,
-            perplexity_service,
-            ragflow_service,
-            speech_service,
-            nostr_service: None,
-            feature_access: web::Data::new(FeatureAccess::from_env()),
-            ragflow_session_id,
-            active_connections: Arc::new(AtomicUsize::new(0)),
-        })
+        let mut builder = AppStateBuilder::new(settings)
+            .with_github_client(github_client)
+            .with_content_api(content_api);
+        builder.perplexity_service = perplexity_service;
+        builder.ragflow_service = ragflow_service;
+        builder.speech_service = speech_service;
+        builder.build().await
     }
 
     pub fn increment_connections(&self) -> usize {
@@ -131,6 +135,25 @@ impl AppState {
         }
     }
 
+    /// The user's GitHub account connected through OAuth, if any
+    pub async fn get_github_connection(&self, pubkey: &str) -> Option<GitHubConnection> {
+        use crate::actors::protected_settings_actor::GetGitHubConnection;
+        self.protected_settings_addr.send(GetGitHubConnection {
+            pubkey: pubkey.to_string(),
+        }).await.ok().flatten()
+    }
+
+    /// The OAuth token of the user's connected GitHub account, if any
+    pub async fn get_github_token(&self, pubkey: &str) -> Option<String> {
+        match self.get_github_connection(pubkey).await?.token() {
+            Ok(token) => Some(token),
+            Err(e) => {
+                warn!("Ignoring GitHub connection of {}: {}", pubkey, e);
+                None
+            }
+        }
+    }
+
     pub fn set_nostr_service(&mut self, service: NostrService) {
         self.nostr_service = Some(web::Data::new(service));
     }
@@ -167,3 +190,246 @@ impl AppState {
         &self.metadata_addr
     }
 }
+
+/// Assembles an `AppState`. The server goes through `AppState::new`; tests use the
+/// builder to swap in an in-memory repository, leave out the GPU and background
+/// loops, and keep node positions fixed (see `crate::test_support`).
+pub struct AppStateBuilder {
+    settings: AppFullSettings,
+    github_client: Option<Arc<GitHubClient>>,
+    content_api: Option<Arc<dyn GitHubService>>,
+    perplexity_service: Option<Arc<PerplexityService>>,
+    ragflow_service: Option<Arc<RAGFlowService>>,
+    speech_service: Option<Arc<SpeechService>>,
+    metadata: MetadataStore,
+    feature_access: Option<FeatureAccess>,
+    faults: Option<FaultInjectionConfig>,
+    physics: PhysicsBackend,
+    gpu: bool,
+    background_tasks: bool,
+}
+
+impl AppStateBuilder {
+    pub fn new(settings: AppFullSettings) -> Self {
+        Self {
+            settings,
+            github_client: None,
+            content_api: None,
+            perplexity_service: None,
+            ragflow_service: None,
+            speech_service: None,
+            metadata: MetadataStore::new(),
+            feature_access: None,
+            faults: None,
+            physics: PhysicsBackend::default(),
+            gpu: true,
+            background_tasks: true,
+        }
+    }
+
+    /// Without one, a client with no credentials is used, which fails every request
+    pub fn with_github_client(mut self, github_client: Arc<GitHubClient>) -> Self {
+        self.github_client = Some(github_client);
+        self
+    }
+
+    /// Defaults to a `ContentAPI` over the GitHub client
+    pub fn with_content_api(mut self, content_api: Arc<dyn GitHubService>) -> Self {
+        self.content_api = Some(content_api);
+        self
+    }
+
+    /// Metadata the `MetadataActor` starts with
+    pub fn with_metadata(mut self, metadata: MetadataStore) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Defaults to `FeatureAccess::from_env`
+    pub fn with_feature_access(mut self, feature_access: FeatureAccess) -> Self {
+        self.feature_access = Some(feature_access);
+        self
+    }
+
+    /// Defaults to `FaultInjectionConfig::from_env`, which injects nothing outside
+    /// debug builds
+    pub fn with_faults(mut self, faults: FaultInjectionConfig) -> Self {
+        self.faults = Some(faults);
+        self
+    }
+
+    pub fn with_physics(mut self, physics: PhysicsBackend) -> Self {
+        self.physics = physics;
+        self
+    }
+
+    /// Leaves out the `GPUComputeActor`
+    pub fn without_gpu(mut self) -> Self {
+        self.gpu = false;
+        self
+    }
+
+    /// Skips the position broadcaster and snapshot history loops
+    pub fn without_background_tasks(mut self) -> Self {
+        self.background_tasks = false;
+        self
+    }
+
+    pub async fn build(self) -> Result<AppState, Box<dyn std::error::Error + Send + Sync>> {
+        let settings = self.settings;
+        resilience::configure(&settings);
+        ai_usage::configure(settings.ai_budget.as_ref());
+        info!("[AppState::new] Initializing actor system");
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        let github_client = match self.github_client {
+            Some(github_client) => github_client,
+            None => Arc::new(GitHubClient::new(GitHubConfig::unconfigured(), Arc::new(tokio::sync::RwLock::new(settings.clone()))).await?),
+        };
+        let content_api = self.content_api
+            .unwrap_or_else(|| Arc::new(ContentAPI::new(github_client.clone())));
+        let faults = self.faults.unwrap_or_else(FaultInjectionConfig::from_env);
+        let content_api: Arc<dyn GitHubService> = if faults.github.is_active() {
+            Arc::new(FaultyGitHub::new(content_api, Arc::new(FaultInjector::new(faults.github, faults.seed))))
+        } else {
+            content_api
+        };
+        
+        // Start actors
+        info!("[AppState::new] Starting ClientManagerActor");
+        let client_manager_addr = ClientManagerActor::new().start();
+        
+        let broadcast_rate = settings.system.websocket.max_update_rate;
+        let layout_seed = settings.visualisation.physics.seed;
+        let node_colors = NodeColorMapper::from_settings(&settings.visualisation.nodes);
+        let world_bounds = BoundsConfig::from_settings(&settings.visualisation.physics);
+        // Tenant GitHub clients only read the debug flag, so a snapshot will do
+        let tenants = Arc::new(TenantRegistry::new(
+            TenantQuota::from_env(),
+            Arc::new(tokio::sync::RwLock::new(settings.clone())),
+        ));
+
+        info!("[AppState::new] Starting SettingsActor");
+        let settings_addr = SettingsActor::new(settings).start();
+        
+        info!("[AppState::new] Starting MetadataActor");
+        let metadata_addr = MetadataActor::new(self.metadata).start();
+        
+        let gpu_compute_addr = if self.gpu {
+            info!("[AppState::new] Starting GPUComputeActor");
+            let mut gpu_compute = GPUComputeActor::new();
+            if faults.gpu.is_active() {
+                gpu_compute = gpu_compute.with_faults(Arc::new(FaultInjector::new(faults.gpu, faults.seed)));
+            }
+            Some(gpu_compute.start())
+        } else {
+            None
+        };
+        
+        // Created before the actors so services can publish from the start
+        let event_bus = EventBus::default();
+
+        info!("[AppState::new] Starting GraphServiceActor");
+        let graph_service_addr = GraphServiceActor::new(
+            client_manager_addr.clone(),
+            gpu_compute_addr.clone(),
+            event_bus.clone(),
+        )
+        .with_build_options(GraphBuildOptions::from_env())
+        .with_repair_options(RepairOptions::from_env())
+        .with_layout_seed(layout_seed)
+        .with_node_colors(node_colors)
+        .with_world_bounds(world_bounds)
+        .with_physics(self.physics)
+        .start();
+        
+        info!("[AppState::new] Starting ProtectedSettingsActor");
+        // Holds connected GitHub accounts, so load what an earlier run saved
+        let protected_settings = ProtectedSettings::load(protected_settings_path()).unwrap_or_else(|e| {
+            debug!("[AppState::new] Using default protected settings: {}", e);
+            ProtectedSettings::default()
+        });
+        let nostr_publisher = NostrPublisher::from_settings(&protected_settings.nostr_publishing);
+        let nostr_ingest = NostrIngest::from_settings(&protected_settings.nostr_ingest);
+        let protected_settings_addr = ProtectedSettingsActor::new(protected_settings).start();
+        
+        info!("[AppState::new] Actor system initialization complete");
+
+        if self.background_tasks {
+            info!("[AppState::new] Starting position broadcaster");
+            position_broadcaster::start(
+                graph_service_addr.clone(),
+                settings_addr.clone(),
+                client_manager_addr.clone(),
+                broadcast_rate,
+                ReplayRecorder::from_env(),
+            );
+
+            snapshot::start_history(graph_service_addr.clone(), settings_addr.clone());
+
+            if let Some(nostr_ingest) = nostr_ingest {
+                info!("[AppState::new] Starting Nostr ingest");
+                nostr_ingest.start(graph_service_addr.clone());
+            }
+        }
+
+        let telemetry = Arc::new(TelemetryService::new(TelemetryLimits::from_env()));
+        if self.background_tasks {
+            telemetry.start();
+        }
+
+        info!("[AppState::new] Starting webhook dispatcher");
+        let webhook_service = Arc::new(WebhookService::new());
+        webhook_service.start(&event_bus);
+
+        info!("[AppState::new] Starting activity log");
+        let activity = Arc::new(ActivityLog::new(event_bus.clone()));
+        activity.start(&event_bus);
+
+        if let Some(publisher) = nostr_publisher {
+            info!("[AppState::new] Starting Nostr publisher");
+            publisher.start(&event_bus);
+        }
+
+        info!("[AppState::new] Starting job queue");
+        let job_queue = JobQueue::new(event_bus.clone());
+
+        let comments = Arc::new(CommentService::new(event_bus.clone()));
+
+        info!("[AppState::new] Starting saved filters");
+        let saved_filters = Arc::new(SavedFilterService::new(event_bus.clone()));
+        saved_filters.start(&event_bus, graph_service_addr.clone());
+        
+        Ok(AppState {
+            graph_service_addr,
+            gpu_compute_addr,
+            settings_addr,
+            protected_settings_addr,
+            metadata_addr,
+            client_manager_addr,
+            github_client,
+            content_api,
+            perplexity_service: self.perplexity_service,
+            ragflow_service: self.ragflow_service,
+            speech_service: self.speech_service,
+            nostr_service: None,
+            feature_access: web::Data::new(self.feature_access.unwrap_or_else(FeatureAccess::from_env)),
+            active_connections: Arc::new(AtomicUsize::new(0)),
+            event_bus,
+            webhook_service,
+            job_queue,
+            view_links: Arc::new(ViewLinkService::new()),
+            shares: Arc::new(ShareService::new()),
+            tenants,
+            github_oauth: GitHubOAuth::from_env().map(Arc::new),
+            activity,
+            comments,
+            saved_filters,
+            conversations: Arc::new(ConversationStore::new(DataDirs::global().conversations())),
+            label_atlases: Arc::new(LabelAtlasCache::default()),
+            recommendations: Arc::new(Recommender::default()),
+            demo: Arc::new(DemoService::new(DemoConfig::from_env())),
+            telemetry,
+        })
+    }
+}
@@ -0,0 +1,17 @@
+use clap::Parser;
+use webxr::ws_bench::{self, BenchArgs};
+
+#[tokio::main]
+async fn main() {
+    let args = BenchArgs::parse();
+    let json = args.json;
+    let report = ws_bench::run(args).await;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report).unwrap_or_default());
+    } else {
+        print!("{}", report);
+    }
+    if report.connected == 0 {
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,278 @@
+//! Offline subcommands
+//!
+//! `webxr` with no subcommand starts the server. The subcommands work on the vault
+//! and the stored metadata directly through `FileService` and `GraphService`, so they
+//! can run in scripts and CI without the HTTP server, actors or GPU.
+
+use clap::{Parser, Subcommand, ValueEnum};
+use log::info;
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::RwLock;
+
+use crate::config::data_dirs::DataDirs;
+use crate::config::{AppFullSettings, PhysicsSettings};
+use crate::models::graph::GraphData;
+use crate::models::metadata::MetadataStore;
+use crate::models::simulation_params::{SimulationParams, SimulationPhase};
+use crate::services::file_service::FileService;
+use crate::services::github::{ContentAPI, GitHubClient, GitHubConfig};
+use crate::services::gltf_export::{self, ExportStyle};
+use crate::services::graph_service::GraphService;
+use crate::services::graphml_export;
+use crate::services::world_bounds::BoundsConfig;
+
+#[derive(Debug, Parser)]
+#[command(name = "webxr", about = "WebXR graph visualisation server")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Fetch changed markdown from GitHub and update the stored metadata
+    Sync,
+    /// Build the graph from the stored metadata and write it to a file
+    Export {
+        #[arg(long, value_enum, default_value_t = ExportFormat::Graphml)]
+        format: ExportFormat,
+        /// Defaults to `graph.<format>` in the working directory
+        #[arg(long)]
+        output: Option<PathBuf>,
+        /// Layout seed; defaults to the configured physics seed
+        #[arg(long)]
+        seed: Option<u64>,
+        /// CPU layout iterations to run before exporting positions
+        #[arg(long, default_value_t = 0)]
+        iterations: u32,
+    },
+    /// Check the stored metadata against the markdown on disk
+    ValidateMetadata,
+    /// Time CPU layout iterations on the graph built from the stored metadata
+    BenchLayout {
+        #[arg(long, default_value_t = 100)]
+        iterations: u32,
+        #[arg(long)]
+        seed: Option<u64>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    Graphml,
+    Glb,
+    Json,
+}
+
+impl ExportFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Graphml => "graphml",
+            ExportFormat::Glb => "glb",
+            ExportFormat::Json => "json",
+        }
+    }
+}
+
+pub async fn run(command: Command, settings: Arc<RwLock<AppFullSettings>>) -> io::Result<()> {
+    crate::services::vault_crypto::init()
+        .map_err(|e| io::Error::other(format!("Failed to initialize vault encryption: {}", e)))?;
+
+    match command {
+        Command::Sync => sync(settings).await,
+        Command::Export { format, output, seed, iterations } => {
+            let settings = settings.read().await.clone();
+            let output = output.unwrap_or_else(|| PathBuf::from(format!("graph.{}", format.extension())));
+            export(&settings, format, &output, seed, iterations)
+        }
+        Command::ValidateMetadata => validate_metadata(),
+        Command::BenchLayout { iterations, seed } => {
+            let settings = settings.read().await.clone();
+            bench_layout(&settings, iterations, seed)
+        }
+    }
+}
+
+async fn sync(settings: Arc<RwLock<AppFullSettings>>) -> io::Result<()> {
+    let github_config = GitHubConfig::from_env()
+        .map_err(|e| io::Error::other(format!("Failed to load GitHub config: {}", e)))?;
+    let github_client = GitHubClient::new(github_config, settings.clone()).await
+        .map_err(|e| io::Error::other(format!("Failed to initialize GitHub client: {}", e)))?;
+    let content_api = Arc::new(ContentAPI::new(Arc::new(github_client)));
+
+    let mut metadata = load_metadata()?;
+    let processed = FileService::new(settings.clone())
+        .fetch_and_process_files(content_api, settings, &mut metadata)
+        .await
+        .map_err(|e| io::Error::other(format!("Error processing files: {}", e)))?;
+    FileService::save_metadata(&metadata)?;
+
+    println!("Synced {} changed files; {} pages in metadata", processed.len(), metadata.len());
+    Ok(())
+}
+
+fn export(settings: &AppFullSettings, format: ExportFormat, output: &Path, seed: Option<u64>, iterations: u32) -> io::Result<()> {
+    let metadata = load_metadata()?;
+    let physics = &settings.visualisation.physics;
+    let mut graph = GraphService::assemble_graph(&metadata, seed.or(physics.seed));
+    if iterations > 0 {
+        let params = simulation_params(physics, graph.nodes.len());
+        run_layout(&mut graph, &params, iterations)?;
+    }
+
+    let bytes = match format {
+        ExportFormat::Graphml => graphml_export::export_graphml(&graph).into_bytes(),
+        ExportFormat::Glb => gltf_export::export_glb(&graph, &ExportStyle::from_settings(&settings.visualisation)),
+        ExportFormat::Json => serde_json::to_vec_pretty(&graph)?,
+    };
+    std::fs::write(output, bytes)?;
+    println!("Wrote {} nodes and {} edges to {}", graph.nodes.len(), graph.edges.len(), output.display());
+    Ok(())
+}
+
+fn validate_metadata() -> io::Result<()> {
+    let metadata = load_metadata()?;
+    let problems = metadata_problems(&metadata, |file_name| DataDirs::global().markdown_file(file_name).exists());
+    for problem in &problems {
+        println!("{}", problem);
+    }
+    if problems.is_empty() {
+        println!("Metadata for {} pages is valid", metadata.len());
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("{} problems found in metadata", problems.len())))
+    }
+}
+
+fn bench_layout(settings: &AppFullSettings, iterations: u32, seed: Option<u64>) -> io::Result<()> {
+    let metadata = load_metadata()?;
+    let physics = &settings.visualisation.physics;
+    let mut graph = GraphService::assemble_graph(&metadata, seed.or(physics.seed));
+    let params = simulation_params(physics, graph.nodes.len());
+    let started = Instant::now();
+    run_layout(&mut graph, &params, iterations)?;
+    let elapsed = started.elapsed();
+
+    println!("nodes: {}", graph.nodes.len());
+    println!("edges: {}", graph.edges.len());
+    println!("iterations: {}", iterations);
+    println!("total: {:.1} ms", elapsed.as_secs_f64() * 1000.0);
+    println!("per iteration: {:.3} ms", elapsed.as_secs_f64() * 1000.0 / iterations.max(1) as f64);
+    Ok(())
+}
+
+fn load_metadata() -> io::Result<MetadataStore> {
+    let metadata = FileService::load_or_create_metadata().map_err(io::Error::other)?;
+    info!("Loaded {} items from metadata store", metadata.len());
+    Ok(metadata)
+}
+
+/// Parameters the server would use for a graph of `node_count` nodes
+fn simulation_params(physics: &PhysicsSettings, node_count: usize) -> SimulationParams {
+    SimulationParams {
+        iterations: physics.iterations,
+        spring_strength: physics.spring_strength,
+        repulsion: physics.repulsion_strength,
+        damping: physics.damping,
+        max_repulsion_distance: physics.repulsion_distance,
+        viewport_bounds: BoundsConfig::from_settings(physics).fit(node_count),
+        mass_scale: physics.mass_scale,
+        boundary_damping: physics.boundary_damping,
+        enable_bounds: physics.enable_bounds,
+        collision_radius: physics.collision_radius,
+        collision_stiffness: physics.collision_stiffness,
+        plane_z: physics.layout_plane(),
+        time_step: 0.016,
+        phase: SimulationPhase::Dynamic,
+        mode: physics.force_model,
+        gravity: physics.gravity,
+        strong_gravity: physics.strong_gravity,
+        edge_weight_normalization: physics.edge_weight_normalization,
+        degree_gravity: physics.attraction_strength,
+        cluster_separation: physics.cluster_separation,
+        node_types: physics.node_types.clone(),
+    }
+}
+
+fn run_layout(graph: &mut GraphData, params: &SimulationParams, iterations: u32) -> io::Result<()> {
+    let mut node_map = HashMap::new();
+    let mut compound = None;
+    for _ in 0..iterations {
+        GraphService::calculate_layout_cpu(graph, &mut node_map, params)?;
+        GraphService::apply_compound_layout(&mut compound, graph, &mut node_map, params);
+        GraphService::recover_divergence(graph, &mut node_map, params);
+    }
+    Ok(())
+}
+
+/// Describes every inconsistency in `metadata`: entries filed under another name,
+/// node ids that aren't numbers or are shared, markdown files missing on disk, and
+/// references to pages that aren't in the store
+pub fn metadata_problems(metadata: &MetadataStore, file_exists: impl Fn(&str) -> bool) -> Vec<String> {
+    let mut problems = Vec::new();
+    let mut node_ids: HashMap<&str, &str> = HashMap::new();
+    let pages: HashSet<&str> = metadata.keys().map(|name| name.trim_end_matches(".md")).collect();
+
+    let mut names: Vec<&String> = metadata.keys().collect();
+    names.sort();
+    for name in names {
+        let entry = &metadata[name];
+        if entry.file_name != *name {
+            problems.push(format!("{}: stored under file name {}", name, entry.file_name));
+        }
+        if entry.node_id.parse::<u32>().is_err() {
+            problems.push(format!("{}: node id '{}' is not a number", name, entry.node_id));
+        } else if let Some(other) = node_ids.insert(&entry.node_id, name) {
+            problems.push(format!("{}: node id {} is also used by {}", name, entry.node_id, other));
+        }
+        if !file_exists(name) {
+            problems.push(format!("{}: markdown file is missing", name));
+        }
+        let mut missing: Vec<&String> = entry.topic_counts.keys()
+            .filter(|target| !pages.contains(target.trim_end_matches(".md")))
+            .collect();
+        missing.sort();
+        for target in missing {
+            problems.push(format!("{}: references {} which is not in the metadata", name, target));
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::metadata::Metadata;
+
+    fn entry(file_name: &str, node_id: &str, references: &[&str]) -> Metadata {
+        Metadata {
+            file_name: file_name.to_string(),
+            node_id: node_id.to_string(),
+            topic_counts: references.iter().map(|target| (target.to_string(), 1)).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_metadata_problems() {
+        let mut metadata = MetadataStore::new();
+        metadata.insert("A.md".to_string(), entry("A.md", "1", &["B"]));
+        metadata.insert("B.md".to_string(), entry("B.md", "2", &["A", "Gone"]));
+        assert_eq!(metadata_problems(&metadata, |_| true), vec!["B.md: references Gone which is not in the metadata"]);
+
+        metadata.insert("C.md".to_string(), entry("Old C.md", "2", &[]));
+        metadata.insert("D.md".to_string(), entry("D.md", "x", &[]));
+        let problems = metadata_problems(&metadata, |name| name != "D.md");
+        assert_eq!(problems, vec![
+            "B.md: references Gone which is not in the metadata",
+            "C.md: stored under file name Old C.md",
+            "C.md: node id 2 is also used by B.md",
+            "D.md: node id 'x' is not a number",
+            "D.md: markdown file is missing",
+        ]);
+    }
+}
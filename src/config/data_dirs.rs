@@ -0,0 +1,142 @@
+//! Filesystem locations of the server's data
+//!
+//! Everything the server persists lives under one data root, `/app/data` in the
+//! Docker image. `DATA_DIR` moves the whole tree, while `MARKDOWN_DIR`,
+//! `METADATA_DIR`, `USER_SETTINGS_DIR` and `CLIENT_DIR` move single parts of it, so
+//! the server can run from a checkout and tests can point it at temporary
+//! directories.
+
+use once_cell::sync::OnceCell;
+use std::env;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_DATA_DIR: &str = "/app/data";
+const DEFAULT_USER_SETTINGS_DIR: &str = "/app/user_settings";
+
+static DATA_DIRS: OnceCell<DataDirs> = OnceCell::new();
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DataDirs {
+    pub root: PathBuf,
+    pub markdown: PathBuf,
+    pub metadata: PathBuf,
+    pub user_settings: PathBuf,
+    /// Built client to serve from `/`; unset when a reverse proxy serves it
+    pub client: Option<PathBuf>,
+}
+
+impl DataDirs {
+    /// Every directory under `root`, with no client directory
+    pub fn under(root: impl Into<PathBuf>) -> Self {
+        let root = root.into();
+        Self {
+            markdown: root.join("markdown"),
+            metadata: root.join("metadata"),
+            user_settings: root.join("user_settings"),
+            client: None,
+            root,
+        }
+    }
+
+    pub fn from_env() -> Self {
+        Self::from_vars(|name| env::var(name).ok().filter(|value| !value.is_empty()))
+    }
+
+    fn from_vars(var: impl Fn(&str) -> Option<String>) -> Self {
+        let data_dir = var("DATA_DIR");
+        let mut dirs = Self::under(data_dir.as_deref().unwrap_or(DEFAULT_DATA_DIR));
+        // User settings sit beside the data root in the image, not inside it
+        if data_dir.is_none() {
+            dirs.user_settings = PathBuf::from(DEFAULT_USER_SETTINGS_DIR);
+        }
+        if let Some(markdown) = var("MARKDOWN_DIR") {
+            dirs.markdown = markdown.into();
+        }
+        if let Some(metadata) = var("METADATA_DIR") {
+            dirs.metadata = metadata.into();
+        }
+        if let Some(user_settings) = var("USER_SETTINGS_DIR") {
+            dirs.user_settings = user_settings.into();
+        }
+        dirs.client = var("CLIENT_DIR").map(PathBuf::from);
+        dirs
+    }
+
+    /// Sets the directories used for the rest of the process. Fails, returning
+    /// `dirs`, if they were already set or already read.
+    pub fn init(dirs: DataDirs) -> Result<(), DataDirs> {
+        DATA_DIRS.set(dirs)
+    }
+
+    /// The directories passed to `init`, or those from the environment if it
+    /// hasn't been called
+    pub fn global() -> &'static DataDirs {
+        DATA_DIRS.get_or_init(Self::from_env)
+    }
+
+    pub fn markdown_file(&self, file_name: impl AsRef<Path>) -> PathBuf {
+        self.markdown.join(file_name)
+    }
+
+    pub fn metadata_file(&self, file_name: impl AsRef<Path>) -> PathBuf {
+        self.metadata.join(file_name)
+    }
+
+    pub fn blobs(&self) -> PathBuf {
+        self.root.join("blobs")
+    }
+
+    pub fn snapshots(&self) -> PathBuf {
+        self.root.join("snapshots")
+    }
+
+    pub fn tenants(&self) -> PathBuf {
+        self.root.join("tenants")
+    }
+
+    pub fn ai_cache(&self) -> PathBuf {
+        self.root.join("ai_cache")
+    }
+
+    pub fn audio_cache(&self) -> PathBuf {
+        self.root.join("audio_cache")
+    }
+
+    pub fn conversations(&self) -> PathBuf {
+        self.root.join("conversations")
+    }
+
+    pub fn replays(&self) -> PathBuf {
+        self.root.join("replays")
+    }
+
+    pub fn templates(&self) -> PathBuf {
+        self.root.join("templates")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn dirs_from(vars: &[(&str, &str)]) -> DataDirs {
+        let vars: HashMap<&str, &str> = vars.iter().copied().collect();
+        DataDirs::from_vars(|name| vars.get(name).map(|value| value.to_string()))
+    }
+
+    #[test]
+    fn test_data_dirs_from_env() {
+        let defaults = dirs_from(&[]);
+        assert_eq!(defaults.markdown, PathBuf::from("/app/data/markdown"));
+        assert_eq!(defaults.metadata_file("metadata.json"), PathBuf::from("/app/data/metadata/metadata.json"));
+        assert_eq!(defaults.user_settings, PathBuf::from("/app/user_settings"));
+        assert_eq!(defaults.client, None);
+
+        let local = dirs_from(&[("DATA_DIR", "/tmp/vault"), ("MARKDOWN_DIR", "/notes"), ("CLIENT_DIR", "client/dist")]);
+        assert_eq!(local.markdown, PathBuf::from("/notes"));
+        assert_eq!(local.blobs(), PathBuf::from("/tmp/vault/blobs"));
+        assert_eq!(local.user_settings, PathBuf::from("/tmp/vault/user_settings"));
+        assert_eq!(local.client, Some(PathBuf::from("client/dist")));
+    }
+}
@@ -4,60 +4,13 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use serde_yaml;
 use std::path::PathBuf;
-// use std::collections::BTreeMap; // For ordered map during serialization - Removed as unused
+use std::collections::BTreeMap;
+use crate::models::simulation_params::SimulationMode;
+use crate::utils::case_conversion::keys_to_snake_case;
 
+pub mod data_dirs;
 pub mod feature_access;
-
-// Recursive function to convert JSON Value keys to snake_case
-fn keys_to_snake_case(value: Value) -> Value {
-    match value {
-        Value::Object(map) => {
-            let new_map = map.into_iter().map(|(k, v)| {
-                let snake_key = k.chars().fold(String::new(), |mut acc, c| {
-                    if c.is_ascii_uppercase() {
-                        if !acc.is_empty() {
-                            acc.push('_');
-                        }
-                        acc.push(c.to_ascii_lowercase());
-                    } else {
-                        acc.push(c);
-                    }
-                    acc
-                });
-                (snake_key, keys_to_snake_case(v))
-            }).collect();
-            Value::Object(new_map)
-        }
-        Value::Array(arr) => {
-            Value::Array(arr.into_iter().map(keys_to_snake_case).collect())
-        }
-        _ => value,
-    }
-}
-
-// Recursive function to convert JSON Value keys to camelCase (if needed for comparison/debugging)
-fn _keys_to_camel_case(value: Value) -> Value {
-     match value {
-         Value::Object(map) => {
-             let new_map = map.into_iter().map(|(k, v)| {
-                 let camel_key = k.split('_').enumerate().map(|(i, part)| {
-                     if i == 0 {
-                         part.to_string()
-                     } else {
-                         part.chars().next().map_or(String::new(), |c| c.to_uppercase().collect::<String>() + &part[1..])
-                     }
-                 }).collect::<String>();
-                 (camel_key, _keys_to_camel_case(v))
-             }).collect();
-             Value::Object(new_map)
-         }
-         Value::Array(arr) => {
-             Value::Array(arr.into_iter().map(_keys_to_camel_case).collect())
-         }
-         _ => value,
-     }
- }
-
+pub mod validation;
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 // #[serde(rename_all = "camelCase")] // Reverted
@@ -79,6 +32,56 @@ pub struct NodeSettings {
     pub enable_hologram: bool,
     pub enable_metadata_shape: bool,
     pub enable_metadata_visualisation: bool,
+    /// What the server colours nodes by; `base` leaves every node at `base_color`
+    #[serde(default)]
+    pub color_mode: NodeColorMode,
+    /// Age buckets in increasing `max_age_days`; pages older than the last bucket
+    /// take its colour
+    #[serde(default = "default_age_colors")]
+    pub age_colors: Vec<AgeColor>,
+    /// Colours for `node_type`s such as `tag`, `ghost` and `journal`
+    #[serde(default = "default_type_colors")]
+    pub type_colors: BTreeMap<String, String>,
+    /// Colours for connected clusters, largest cluster first
+    #[serde(default = "default_cluster_palette")]
+    pub cluster_palette: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum NodeColorMode {
+    #[default]
+    Base,
+    Age,
+    Type,
+    Cluster,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct AgeColor {
+    pub max_age_days: u32,
+    pub color: String,
+}
+
+fn default_age_colors() -> Vec<AgeColor> {
+    [(7, "#a6e22e"), (30, "#e6db74"), (180, "#fd971f"), (365, "#75715e")]
+        .into_iter()
+        .map(|(max_age_days, color)| AgeColor { max_age_days, color: color.to_string() })
+        .collect()
+}
+
+fn default_type_colors() -> BTreeMap<String, String> {
+    [("tag", "#ae81ff"), ("ghost", "#75715e"), ("journal", "#e6db74")]
+        .into_iter()
+        .map(|(node_type, color)| (node_type.to_string(), color.to_string()))
+        .collect()
+}
+
+fn default_cluster_palette() -> Vec<String> {
+    ["#66d9ef", "#a6e22e", "#f92672", "#fd971f", "#ae81ff", "#e6db74", "#f8f8f2", "#75715e"]
+        .into_iter()
+        .map(String::from)
+        .collect()
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
@@ -98,7 +101,15 @@ pub struct EdgeSettings {
 pub struct PhysicsSettings {
     pub attraction_strength: f32,
     pub bounds_size: f32,
+    /// Grow the bounds with the node count, from `bounds_size` up to `max_bounds_size`
+    #[serde(default)]
+    pub auto_fit_bounds: bool,
+    #[serde(default = "default_max_bounds_size")]
+    pub max_bounds_size: f32,
     pub collision_radius: f32,
+    /// Force per unit of overlap pushing colliding nodes apart; 0 disables collisions
+    #[serde(default = "default_collision_stiffness")]
+    pub collision_stiffness: f32,
     pub damping: f32,
     pub enable_bounds: bool,
     pub enabled: bool,
@@ -109,6 +120,113 @@ pub struct PhysicsSettings {
     pub repulsion_distance: f32,
     pub mass_scale: f32,
     pub boundary_damping: f32,
+    /// Multipliers for nodes of a given `node_type`, e.g. `tag` or `ghost`
+    #[serde(default)]
+    pub node_types: BTreeMap<String, NodeTypePhysics>,
+    #[serde(default)]
+    pub layout_mode: LayoutMode,
+    /// z of the plane nodes are held to in 2D mode
+    #[serde(default)]
+    pub plane_z: f32,
+    /// Seed for initial positions and any randomness in the physics, so a layout can
+    /// be reproduced. Without one a fresh seed is picked on every build.
+    #[serde(default)]
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub force_model: SimulationMode,
+    /// Pull towards the origin in the ForceAtlas2 models, per unit of degree
+    #[serde(default = "default_gravity")]
+    pub gravity: f32,
+    /// Gravity grows with distance from the origin, keeping disconnected parts close
+    #[serde(default)]
+    pub strong_gravity: bool,
+    /// How edge weights are scaled before they reach the solvers
+    #[serde(default)]
+    pub edge_weight_normalization: EdgeWeightNormalization,
+    /// Gap kept between communities, as a multiple of their combined radii, with
+    /// members held near their community; 0 lays the graph out in one level
+    #[serde(default)]
+    pub cluster_separation: f32,
+}
+
+impl PhysicsSettings {
+    /// The plane the solver holds nodes to, if laying out in 2D
+    pub fn layout_plane(&self) -> Option<f32> {
+        match self.layout_mode {
+            LayoutMode::ThreeD => None,
+            LayoutMode::TwoD => Some(self.plane_z),
+        }
+    }
+}
+
+/// Whether the layout uses all three axes or is flattened onto a plane, e.g. for
+/// wall displays and 2D clients
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LayoutMode {
+    #[default]
+    #[serde(rename = "3d")]
+    ThreeD,
+    #[serde(rename = "2d")]
+    TwoD,
+}
+
+/// Scales edge weights, which count the references between two pages, so a few
+/// heavily cross-linked pages don't dominate the springs
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum EdgeWeightNormalization {
+    /// Weights as counted
+    #[default]
+    None,
+    /// Divided by the heaviest weight in the graph, so they lie in (0, 1]
+    Max,
+    /// log(1 + weight), divided by the same for the heaviest weight
+    Log,
+}
+
+impl EdgeWeightNormalization {
+    /// `weight` scaled for a graph whose heaviest edge weighs `max_weight`
+    pub fn apply(self, weight: f32, max_weight: f32) -> f32 {
+        match self {
+            Self::None => weight,
+            _ if max_weight <= 0.0 => weight,
+            Self::Max => weight / max_weight,
+            Self::Log => weight.ln_1p() / max_weight.ln_1p(),
+        }
+    }
+}
+
+/// Scales the physics of one node type relative to ordinary pages
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub struct NodeTypePhysics {
+    /// Multiplies the node's mass, which scales both spring and repulsion forces
+    #[serde(default = "default_multiplier")]
+    pub mass: f32,
+    /// Multiplies only the repulsion between this node and others
+    #[serde(default = "default_multiplier")]
+    pub charge: f32,
+}
+
+fn default_multiplier() -> f32 {
+    1.0
+}
+
+fn default_collision_stiffness() -> f32 {
+    0.5
+}
+
+fn default_max_bounds_size() -> f32 {
+    250.0
+}
+
+fn default_gravity() -> f32 {
+    1.0
+}
+
+impl Default for NodeTypePhysics {
+    fn default() -> Self {
+        Self { mass: 1.0, charge: 1.0 }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
@@ -147,6 +265,9 @@ pub struct LabelSettings {
     pub text_resolution: u32,
     pub text_padding: f32,
     pub billboard_mode: String,
+    /// TrueType font for server-rendered label atlases; a system font when unset
+    #[serde(default)]
+    pub font_path: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
@@ -435,6 +556,9 @@ pub struct OpenAISettings { // Client-facing
     #[serde(default)] pub base_url: Option<String>,
     #[serde(default)] pub timeout: Option<u64>,
     #[serde(default)] pub rate_limit: Option<u32>,
+    #[serde(default)] pub tts_model: Option<String>,
+    #[serde(default)] pub tts_voice: Option<String>,
+    #[serde(default)] pub tts_speed: Option<f32>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
@@ -464,6 +588,69 @@ pub struct WhisperSettings { // Client-facing
     #[serde(default)] pub initial_prompt: Option<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+// #[serde(rename_all = "camelCase")] // Reverted
+pub struct ElevenLabsSettings { // Client-facing
+    #[serde(default)] pub api_key: Option<String>,
+    #[serde(default)] pub api_url: Option<String>,
+    #[serde(default)] pub default_voice_id: Option<String>,
+    #[serde(default)] pub model_id: Option<String>,
+    #[serde(default)] pub default_speed: Option<f32>,
+    #[serde(default)] pub stability: Option<f32>,
+    #[serde(default)] pub similarity_boost: Option<f32>,
+    #[serde(default)] pub timeout: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+// #[serde(rename_all = "camelCase")] // Reverted
+pub struct SonataSettings { // Client-facing
+    #[serde(default)] pub api_url: Option<String>,
+    #[serde(default)] pub default_voice: Option<String>,
+    #[serde(default)] pub default_format: Option<String>,
+    #[serde(default)] pub default_speed: Option<f32>,
+    #[serde(default)] pub timeout: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+// #[serde(rename_all = "camelCase")] // Reverted
+pub struct AudioCacheSettings { // On-disk TTS audio cache
+    #[serde(default)] pub enabled: bool,
+    #[serde(default)] pub directory: Option<String>,
+    #[serde(default)] pub max_size_mb: Option<u64>,
+    #[serde(default)] pub eviction_policy: Option<String>, // "lru" (default) or "fifo"
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+// #[serde(rename_all = "camelCase")] // Reverted
+pub struct AiCacheSettings { // On-disk cache of Perplexity/RAGFlow answers
+    #[serde(default)] pub enabled: bool,
+    #[serde(default)] pub directory: Option<String>,
+    #[serde(default)] pub max_size_mb: Option<u64>,
+    #[serde(default)] pub ttl_hours: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+// #[serde(rename_all = "camelCase")] // Reverted
+pub struct AiBudgetSettings { // Daily spend caps for the paid AI providers
+    #[serde(default)] pub daily_limit_usd: Option<f64>, // All providers together
+    #[serde(default)] pub provider_limits_usd: BTreeMap<String, f64>, // Keyed by provider: perplexity, ragflow, openai
+    #[serde(default)] pub prices: BTreeMap<String, AiPriceSettings>, // Overrides the built-in prices
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq)]
+// #[serde(rename_all = "camelCase")] // Reverted
+pub struct AiPriceSettings { // USD per million tokens (characters for speech)
+    #[serde(default)] pub input_per_million: f64,
+    #[serde(default)] pub output_per_million: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+// #[serde(rename_all = "camelCase")] // Reverted
+pub struct SyncScheduleSettings { // Periodic GitHub sync
+    #[serde(default)] pub enabled: bool,
+    #[serde(default)] pub schedule: String, // cron expression, 5 fields or 6 with seconds first (UTC)
+}
+
 // --- Client-Facing Settings Struct (for JSON deserialization) ---
 #[derive(Debug, Serialize, Deserialize, Clone, Default)]
 #[serde(rename_all = "camelCase")]
@@ -477,6 +664,9 @@ pub struct Settings { // Renamed to ClientFacingSettings conceptually
     #[serde(default)] pub openai: Option<OpenAISettings>,
     #[serde(default)] pub kokoro: Option<KokoroSettings>,
     #[serde(default)] pub whisper: Option<WhisperSettings>,
+    #[serde(default)] pub elevenlabs: Option<ElevenLabsSettings>,
+    #[serde(default)] pub sonata: Option<SonataSettings>,
+    #[serde(default)] pub audio_cache: Option<AudioCacheSettings>,
 }
 
 // --- Full App Settings Struct (for server state, loaded from YAML) ---
@@ -492,6 +682,12 @@ pub struct AppFullSettings {
     #[serde(default)] pub openai: Option<OpenAISettings>,
     #[serde(default)] pub kokoro: Option<KokoroSettings>,
     #[serde(default)] pub whisper: Option<WhisperSettings>,
+    #[serde(default)] pub elevenlabs: Option<ElevenLabsSettings>,
+    #[serde(default)] pub sonata: Option<SonataSettings>,
+    #[serde(default)] pub audio_cache: Option<AudioCacheSettings>,
+    #[serde(default)] pub ai_cache: Option<AiCacheSettings>,
+    #[serde(default)] pub ai_budget: Option<AiBudgetSettings>,
+    #[serde(default)] pub sync: Option<SyncScheduleSettings>,
 }
 
 // Manual Serialize implementation for AppFullSettings to ensure snake_case YAML output
@@ -512,6 +708,12 @@ impl Serialize for AppFullSettings {
             openai: &'a Option<OpenAISettings>,
             kokoro: &'a Option<KokoroSettings>,
             whisper: &'a Option<WhisperSettings>,
+            elevenlabs: &'a Option<ElevenLabsSettings>,
+            sonata: &'a Option<SonataSettings>,
+            audio_cache: &'a Option<AudioCacheSettings>,
+            ai_cache: &'a Option<AiCacheSettings>,
+            ai_budget: &'a Option<AiBudgetSettings>,
+            sync: &'a Option<SyncScheduleSettings>,
         }
 
         let helper = AppFullSettingsHelper {
@@ -524,6 +726,12 @@ impl Serialize for AppFullSettings {
             openai: &self.openai,
             kokoro: &self.kokoro,
             whisper: &self.whisper,
+            elevenlabs: &self.elevenlabs,
+            sonata: &self.sonata,
+            audio_cache: &self.audio_cache,
+            ai_cache: &self.ai_cache,
+            ai_budget: &self.ai_budget,
+            sync: &self.sync,
         };
 
         // Convert the helper to a serde_json::Value. This avoids recursive serialization.
@@ -0,0 +1,302 @@
+//! Validation of settings before they are applied
+//!
+//! Settings arrive from clients as loosely typed JSON, so a value can deserialize
+//! fine and still stall or explode the simulation loop (a zero bounds size, damping
+//! above one, a NaN spring strength). `validate_settings` collects every problem
+//! with the path of the offending field so the client can point at it.
+
+use serde::Serialize;
+use std::fmt;
+
+use super::{AppFullSettings, LayoutMode, PhysicsSettings, ServerFullWebSocketSettings};
+use crate::models::simulation_params::SimulationMode;
+
+// The GPU kernel runs this many iterations per tick, so large values stall the loop
+const MAX_PHYSICS_ITERATIONS: u32 = 1000;
+const MAX_UPDATE_RATE: u32 = 120;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldError {
+    /// Dotted settings path, e.g. `visualisation.physics.damping`
+    pub field: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct ValidationErrors(pub Vec<FieldError>);
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Invalid settings: ")?;
+        for (i, error) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, "; ")?;
+            }
+            write!(f, "{} {}", error.field, error.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+#[derive(Default)]
+struct Validator {
+    errors: Vec<FieldError>,
+}
+
+impl Validator {
+    fn fail(&mut self, field: &str, message: String) {
+        self.errors.push(FieldError { field: field.to_string(), message });
+    }
+
+    fn range(&mut self, field: &str, value: f32, min: f32, max: f32) {
+        if !(value.is_finite() && (min..=max).contains(&value)) {
+            self.fail(field, format!("must be between {} and {}, got {}", min, max, value));
+        }
+    }
+
+    fn non_negative(&mut self, field: &str, value: f32) {
+        if !(value.is_finite() && value >= 0.0) {
+            self.fail(field, format!("must be a finite non-negative number, got {}", value));
+        }
+    }
+
+    fn positive(&mut self, field: &str, value: f32) {
+        if !(value.is_finite() && value > 0.0) {
+            self.fail(field, format!("must be a finite positive number, got {}", value));
+        }
+    }
+
+    fn color(&mut self, field: &str, value: &str) {
+        if !is_hex_color(value) {
+            self.fail(field, format!("must be a hex color like #rrggbb, got '{}'", value));
+        }
+    }
+
+    fn port(&mut self, field: &str, value: u16) {
+        if value == 0 {
+            self.fail(field, "must be a port between 1 and 65535".to_string());
+        }
+    }
+}
+
+/// `#rgb`, `#rrggbb` or `#rrggbbaa`
+fn is_hex_color(value: &str) -> bool {
+    value.strip_prefix('#')
+        .map(|hex| matches!(hex.len(), 3 | 6 | 8) && hex.chars().all(|c| c.is_ascii_hexdigit()))
+        .unwrap_or(false)
+}
+
+pub fn validate_settings(settings: &AppFullSettings) -> Result<(), ValidationErrors> {
+    let mut v = Validator::default();
+    let vis = &settings.visualisation;
+
+    validate_physics(&mut v, &vis.physics);
+
+    v.color("visualisation.nodes.base_color", &vis.nodes.base_color);
+    v.range("visualisation.nodes.opacity", vis.nodes.opacity, 0.0, 1.0);
+    v.range("visualisation.nodes.metalness", vis.nodes.metalness, 0.0, 1.0);
+    v.range("visualisation.nodes.roughness", vis.nodes.roughness, 0.0, 1.0);
+    v.positive("visualisation.nodes.node_size", vis.nodes.node_size);
+    for (i, bucket) in vis.nodes.age_colors.iter().enumerate() {
+        v.color(&format!("visualisation.nodes.age_colors[{}].color", i), &bucket.color);
+    }
+    for (node_type, color) in &vis.nodes.type_colors {
+        v.color(&format!("visualisation.nodes.type_colors.{}", node_type), color);
+    }
+    for (i, color) in vis.nodes.cluster_palette.iter().enumerate() {
+        v.color(&format!("visualisation.nodes.cluster_palette[{}]", i), color);
+    }
+    v.color("visualisation.edges.color", &vis.edges.color);
+    v.range("visualisation.edges.opacity", vis.edges.opacity, 0.0, 1.0);
+    v.non_negative("visualisation.edges.base_width", vis.edges.base_width);
+    v.color("visualisation.rendering.background_color", &vis.rendering.background_color);
+    v.color("visualisation.labels.text_color", &vis.labels.text_color);
+    v.color("visualisation.labels.text_outline_color", &vis.labels.text_outline_color);
+    v.color("visualisation.hologram.ring_color", &vis.hologram.ring_color);
+    v.range("visualisation.hologram.ring_opacity", vis.hologram.ring_opacity, 0.0, 1.0);
+
+    let xr = &settings.xr;
+    v.color("xr.hand_mesh_color", &xr.hand_mesh_color);
+    v.color("xr.hand_ray_color", &xr.hand_ray_color);
+    v.color("xr.plane_color", &xr.plane_color);
+    v.color("xr.portal_edge_color", &xr.portal_edge_color);
+    if let Some(color) = &xr.teleport_ray_color {
+        v.color("xr.teleport_ray_color", color);
+    }
+    if let Some(color) = &xr.controller_ray_color {
+        v.color("xr.controller_ray_color", color);
+    }
+
+    let network = &settings.system.network;
+    v.port("system.network.port", network.port);
+    if network.enable_metrics {
+        v.port("system.network.metrics_port", network.metrics_port);
+        if network.metrics_port == network.port {
+            v.fail("system.network.metrics_port", "must differ from system.network.port".to_string());
+        }
+    }
+
+    validate_websocket(&mut v, &settings.system.websocket);
+
+    if v.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(ValidationErrors(v.errors))
+    }
+}
+
+fn validate_physics(v: &mut Validator, physics: &PhysicsSettings) {
+    v.non_negative("visualisation.physics.attraction_strength", physics.attraction_strength);
+    v.non_negative("visualisation.physics.spring_strength", physics.spring_strength);
+    v.non_negative("visualisation.physics.repulsion_strength", physics.repulsion_strength);
+    v.positive("visualisation.physics.repulsion_distance", physics.repulsion_distance);
+    v.non_negative("visualisation.physics.collision_radius", physics.collision_radius);
+    v.non_negative("visualisation.physics.collision_stiffness", physics.collision_stiffness);
+    v.non_negative("visualisation.physics.cluster_separation", physics.cluster_separation);
+    v.range("visualisation.physics.damping", physics.damping, 0.0, 1.0);
+    v.range("visualisation.physics.boundary_damping", physics.boundary_damping, 0.0, 1.0);
+    v.positive("visualisation.physics.max_velocity", physics.max_velocity);
+    v.positive("visualisation.physics.bounds_size", physics.bounds_size);
+    if physics.auto_fit_bounds && (physics.max_bounds_size.is_nan() || physics.max_bounds_size < physics.bounds_size) {
+        v.fail("visualisation.physics.max_bounds_size",
+            format!("must be at least bounds_size ({}), got {}", physics.bounds_size, physics.max_bounds_size));
+    }
+    v.positive("visualisation.physics.mass_scale", physics.mass_scale);
+    if !(1..=MAX_PHYSICS_ITERATIONS).contains(&physics.iterations) {
+        v.fail("visualisation.physics.iterations",
+            format!("must be between 1 and {}, got {}", MAX_PHYSICS_ITERATIONS, physics.iterations));
+    }
+    if physics.layout_mode == LayoutMode::TwoD {
+        v.range("visualisation.physics.plane_z", physics.plane_z, -physics.bounds_size, physics.bounds_size);
+    }
+    if physics.force_model != SimulationMode::SpringElectric {
+        v.non_negative("visualisation.physics.gravity", physics.gravity);
+    }
+    for (node_type, multipliers) in &physics.node_types {
+        v.positive(&format!("visualisation.physics.node_types.{}.mass", node_type), multipliers.mass);
+        v.positive(&format!("visualisation.physics.node_types.{}.charge", node_type), multipliers.charge);
+    }
+}
+
+fn validate_websocket(v: &mut Validator, ws: &ServerFullWebSocketSettings) {
+    for (field, rate) in [
+        ("system.websocket.update_rate", ws.update_rate),
+        ("system.websocket.binary_update_rate", ws.binary_update_rate),
+        ("system.websocket.min_update_rate", ws.min_update_rate),
+        ("system.websocket.max_update_rate", ws.max_update_rate),
+    ] {
+        if !(1..=MAX_UPDATE_RATE).contains(&rate) {
+            v.fail(field, format!("must be between 1 and {} updates per second, got {}", MAX_UPDATE_RATE, rate));
+        }
+    }
+    if ws.min_update_rate > ws.max_update_rate {
+        v.fail("system.websocket.min_update_rate", "must not exceed system.websocket.max_update_rate".to_string());
+    }
+    v.range("system.websocket.motion_damping", ws.motion_damping, 0.0, 1.0);
+    v.non_negative("system.websocket.motion_threshold", ws.motion_threshold);
+    if ws.binary_chunk_size == 0 {
+        v.fail("system.websocket.binary_chunk_size", "must be positive".to_string());
+    }
+    if ws.heartbeat_interval == 0 || ws.heartbeat_interval >= ws.heartbeat_timeout {
+        v.fail("system.websocket.heartbeat_interval",
+            "must be positive and shorter than system.websocket.heartbeat_timeout".to_string());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{EdgeWeightNormalization, NodeTypePhysics};
+
+    fn physics() -> PhysicsSettings {
+        PhysicsSettings {
+            attraction_strength: 0.05,
+            bounds_size: 15.0,
+            auto_fit_bounds: true,
+            max_bounds_size: 250.0,
+            collision_radius: 0.5,
+            collision_stiffness: 0.5,
+            damping: 0.95,
+            enable_bounds: true,
+            enabled: true,
+            iterations: 100,
+            max_velocity: 0.02,
+            repulsion_strength: 0.1,
+            spring_strength: 0.2,
+            repulsion_distance: 2.0,
+            mass_scale: 1.0,
+            boundary_damping: 0.95,
+            node_types: [("ghost".to_string(), NodeTypePhysics { mass: 0.3, charge: 0.5 })].into(),
+            layout_mode: LayoutMode::TwoD,
+            plane_z: 0.0,
+            seed: Some(42),
+            force_model: SimulationMode::ForceAtlas2,
+            gravity: 1.0,
+            strong_gravity: false,
+            edge_weight_normalization: EdgeWeightNormalization::Log,
+            cluster_separation: 1.5,
+        }
+    }
+
+    #[test]
+    fn test_physics_ranges() {
+        let mut v = Validator::default();
+        validate_physics(&mut v, &physics());
+        assert!(v.errors.is_empty());
+
+        let mut v = Validator::default();
+        validate_physics(&mut v, &PhysicsSettings {
+            damping: 1.5,
+            bounds_size: 0.0,
+            max_bounds_size: f32::NAN,
+            spring_strength: f32::NAN,
+            iterations: 0,
+            node_types: [("tag".to_string(), NodeTypePhysics { mass: 2.0, charge: 0.0 })].into(),
+            plane_z: 20.0,
+            gravity: -1.0,
+            ..physics()
+        });
+        let fields: Vec<&str> = v.errors.iter().map(|e| e.field.as_str()).collect();
+        assert_eq!(fields, vec![
+            "visualisation.physics.spring_strength",
+            "visualisation.physics.damping",
+            "visualisation.physics.bounds_size",
+            "visualisation.physics.max_bounds_size",
+            "visualisation.physics.iterations",
+            "visualisation.physics.plane_z",
+            "visualisation.physics.gravity",
+            "visualisation.physics.node_types.tag.charge",
+        ]);
+    }
+
+    #[test]
+    fn test_websocket_rates() {
+        let mut v = Validator::default();
+        validate_websocket(&mut v, &ServerFullWebSocketSettings::default());
+        assert!(v.errors.is_empty());
+
+        let mut v = Validator::default();
+        validate_websocket(&mut v, &ServerFullWebSocketSettings {
+            min_update_rate: 90,
+            max_update_rate: 60,
+            heartbeat_interval: 0,
+            ..Default::default()
+        });
+        assert_eq!(v.errors.len(), 2);
+    }
+
+    #[test]
+    fn test_hex_colors() {
+        assert!(is_hex_color("#fff"));
+        assert!(is_hex_color("#66d9ef"));
+        assert!(is_hex_color("#66D9EF80"));
+        assert!(!is_hex_color("66d9ef"));
+        assert!(!is_hex_color("#66d9e"));
+        assert!(!is_hex_color("#gggggg"));
+        assert!(!is_hex_color("red"));
+    }
+}
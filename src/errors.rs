@@ -0,0 +1,206 @@
+//! Errors handlers return to HTTP clients
+//!
+//! Every failure a handler surfaces becomes an `AppError`, which answers with a
+//! status fitting its kind and a JSON body of `status`, `code`, `message` and
+//! `correlationId`. The correlation id is taken from the request's
+//! `X-Correlation-Id` header, or made up when the client sent none, echoed on every
+//! response and logged with the error, so a report from a user can be matched to
+//! the server log line.
+
+use actix_web::http::header::HeaderMap;
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use log::{debug, error};
+use serde_json::json;
+use std::future::Future;
+use thiserror::Error;
+
+use crate::services::page_templates::TemplateError;
+use crate::services::shares::ShareError;
+
+pub const CORRELATION_HEADER: &str = "X-Correlation-Id";
+// Longer or odd-looking ids from clients are replaced rather than logged
+const MAX_CORRELATION_ID_LEN: usize = 64;
+
+tokio::task_local! {
+    static CORRELATION_ID: String;
+}
+
+#[derive(Debug, Error)]
+pub enum AppError {
+    #[error("{0}")]
+    NotFound(String),
+    #[error("{0}")]
+    BadRequest(String),
+    #[error("{0}")]
+    Forbidden(String),
+    /// The resource already exists or has changed underneath the request
+    #[error("{0}")]
+    Conflict(String),
+    /// A service that isn't configured or is still starting
+    #[error("{0}")]
+    Unavailable(String),
+    #[error("File service error: {0}")]
+    File(String),
+    #[error("Graph service error: {0}")]
+    Graph(String),
+    #[error("GPU error: {0}")]
+    Gpu(String),
+    #[error("GitHub error: {0}")]
+    GitHub(String),
+    /// An actor didn't answer, usually because it has stopped
+    #[error("Actor unavailable: {0}")]
+    Mailbox(#[from] actix::MailboxError),
+    #[error("{0}")]
+    Internal(String),
+}
+
+impl AppError {
+    pub fn file(e: impl std::fmt::Display) -> Self {
+        Self::File(e.to_string())
+    }
+
+    pub fn graph(e: impl std::fmt::Display) -> Self {
+        Self::Graph(e.to_string())
+    }
+
+    pub fn gpu(e: impl std::fmt::Display) -> Self {
+        Self::Gpu(e.to_string())
+    }
+
+    pub fn github(e: impl std::fmt::Display) -> Self {
+        Self::GitHub(e.to_string())
+    }
+
+    /// Stable machine-readable name of the kind of error
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::NotFound(_) => "not_found",
+            Self::BadRequest(_) => "bad_request",
+            Self::Forbidden(_) => "forbidden",
+            Self::Conflict(_) => "conflict",
+            Self::Unavailable(_) => "unavailable",
+            Self::File(_) => "file_error",
+            Self::Graph(_) => "graph_error",
+            Self::Gpu(_) => "gpu_error",
+            Self::GitHub(_) => "github_error",
+            Self::Mailbox(_) => "actor_unavailable",
+            Self::Internal(_) => "internal",
+        }
+    }
+}
+
+impl From<std::io::Error> for AppError {
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            std::io::ErrorKind::NotFound => Self::NotFound(e.to_string()),
+            std::io::ErrorKind::AlreadyExists => Self::Conflict(e.to_string()),
+            std::io::ErrorKind::InvalidInput | std::io::ErrorKind::InvalidData => Self::BadRequest(e.to_string()),
+            _ => Self::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<TemplateError> for AppError {
+    fn from(e: TemplateError) -> Self {
+        match e {
+            TemplateError::NotFound(_) => Self::NotFound(e.to_string()),
+            TemplateError::InvalidName(_) | TemplateError::Render { .. } => Self::BadRequest(e.to_string()),
+        }
+    }
+}
+
+impl From<ShareError> for AppError {
+    fn from(e: ShareError) -> Self {
+        match e {
+            ShareError::Invalid(_) => Self::BadRequest(e.to_string()),
+            ShareError::NotFound(_) => Self::NotFound(e.to_string()),
+            ShareError::Storage(_) => Self::Internal(e.to_string()),
+        }
+    }
+}
+
+impl ResponseError for AppError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::NotFound(_) => StatusCode::NOT_FOUND,
+            Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::Conflict(_) => StatusCode::CONFLICT,
+            Self::Unavailable(_) | Self::Mailbox(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::GitHub(_) => StatusCode::BAD_GATEWAY,
+            Self::File(_) | Self::Graph(_) | Self::Gpu(_) | Self::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let correlation_id = current_correlation_id().unwrap_or_else(new_correlation_id);
+        let status = self.status_code();
+        if status.is_server_error() {
+            error!("[{}] {} ({})", correlation_id, self, self.code());
+        } else {
+            debug!("[{}] {} ({})", correlation_id, self, self.code());
+        }
+        HttpResponse::build(status)
+            .insert_header((CORRELATION_HEADER, correlation_id.clone()))
+            .json(json!({
+                "status": "error",
+                "code": self.code(),
+                "message": self.to_string(),
+                "correlationId": correlation_id,
+            }))
+    }
+}
+
+fn new_correlation_id() -> String {
+    uuid::Uuid::new_v4().to_string()
+}
+
+/// The id the client sent, if it's a reasonable one, or a new one
+pub fn correlation_id(headers: &HeaderMap) -> String {
+    headers.get(CORRELATION_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| !id.is_empty() && id.len() <= MAX_CORRELATION_ID_LEN)
+        .filter(|id| id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+        .map(str::to_string)
+        .unwrap_or_else(new_correlation_id)
+}
+
+/// Runs `fut` with `id` as the correlation id of any error it responds with
+pub async fn with_correlation_id<F: Future>(id: String, fut: F) -> F::Output {
+    CORRELATION_ID.scope(id, fut).await
+}
+
+pub fn current_correlation_id() -> Option<String> {
+    CORRELATION_ID.try_with(|id| id.clone()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::{HeaderName, HeaderValue};
+
+    #[tokio::test]
+    async fn test_error_response() {
+        let not_found = AppError::from(std::io::Error::new(std::io::ErrorKind::NotFound, "no such page"));
+        assert_eq!((not_found.status_code(), not_found.code()), (StatusCode::NOT_FOUND, "not_found"));
+        assert_eq!(AppError::gpu("device lost").status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+
+        let response = with_correlation_id("req-1".to_string(), async { AppError::graph("no nodes").error_response() }).await;
+        assert_eq!(response.headers().get(CORRELATION_HEADER).unwrap(), "req-1");
+        let body = actix_web::body::to_bytes(response.into_body()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body, json!({
+            "status": "error",
+            "code": "graph_error",
+            "message": "Graph service error: no nodes",
+            "correlationId": "req-1",
+        }));
+
+        let mut headers = HeaderMap::new();
+        headers.insert(HeaderName::from_static("x-correlation-id"), HeaderValue::from_static("abc-123"));
+        assert_eq!(correlation_id(&headers), "abc-123");
+        headers.insert(HeaderName::from_static("x-correlation-id"), HeaderValue::from_static("not ok; id"));
+        assert_ne!(correlation_id(&headers), "not ok; id");
+    }
+}
@@ -0,0 +1,51 @@
+use actix_web::{web, HttpResponse, Result};
+use serde::Deserialize;
+use serde_json::json;
+use crate::AppState;
+use crate::services::activity::{ActivityCursor, ActivityKind};
+
+const DEFAULT_LIMIT: usize = 100;
+const MAX_LIMIT: usize = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct ActivityQuery {
+    /// Entry id or RFC 3339 timestamp to read after
+    pub since: Option<String>,
+    pub limit: Option<usize>,
+    /// Comma-separated kinds to keep, e.g. `nodeAdded,pullRequestMerged`
+    pub kind: Option<String>,
+}
+
+fn parse_kinds(value: &str) -> std::result::Result<Vec<ActivityKind>, String> {
+    value.split(',')
+        .map(str::trim)
+        .filter(|kind| !kind.is_empty())
+        .map(|kind| serde_json::from_value(json!(kind)).map_err(|_| format!("Unknown activity kind '{}'", kind)))
+        .collect()
+}
+
+/// Activity entries after `since`, oldest first, or the latest ones without it.
+/// Clients poll with the last id they saw or follow `activity` server events.
+pub async fn get_activity(app_state: web::Data<AppState>, query: web::Query<ActivityQuery>) -> Result<HttpResponse> {
+    let cursor = match query.since.as_deref().map(ActivityCursor::parse).transpose() {
+        Ok(cursor) => cursor,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(json!({ "status": "error", "message": e }))),
+    };
+    let kinds = match query.kind.as_deref().map(parse_kinds).transpose() {
+        Ok(kinds) => kinds.unwrap_or_default(),
+        Err(e) => return Ok(HttpResponse::BadRequest().json(json!({ "status": "error", "message": e }))),
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+    Ok(HttpResponse::Ok().json(json!({
+        "entries": app_state.activity.query(cursor, &kinds, limit),
+        "latestId": app_state.activity.latest_id(),
+    })))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/activity")
+            .route(web::get().to(get_activity))
+    );
+}
@@ -0,0 +1,60 @@
+use crate::actors::messages::GetMetadata;
+use crate::app_state::AppState;
+use crate::errors::AppError;
+use crate::services::agenda::{self, TaskFilter, TaskStatus};
+use actix_web::{web, HttpResponse};
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct TaskQuery {
+    /// `open` (default), `done` or `all`
+    #[serde(default)]
+    pub status: TaskStatus,
+    /// Markers to keep, comma separated: `TODO,DOING`
+    pub state: Option<String>,
+    /// Only tasks tagged with this, in the task or on its page
+    pub tag: Option<String>,
+    /// Only the tasks on this page
+    pub page: Option<String>,
+}
+
+impl TaskQuery {
+    fn filter(&self) -> TaskFilter {
+        TaskFilter {
+            status: self.status,
+            states: self.state.iter()
+                .flat_map(|states| states.split(','))
+                .map(|state| state.trim().to_string())
+                .filter(|state| !state.is_empty())
+                .collect(),
+            tag: self.tag.clone().filter(|tag| !tag.trim().is_empty()),
+        }
+    }
+}
+
+/// Tasks across published pages, by due day with undated tasks last
+async fn list_tasks(state: web::Data<AppState>, query: web::Query<TaskQuery>) -> Result<HttpResponse, AppError> {
+    let metadata = state.metadata_addr.send(GetMetadata).await?.map_err(AppError::file)?;
+    let mut tasks = agenda::collect(&metadata, &query.filter());
+    if let Some(page) = &query.page {
+        let page = page.trim_end_matches(".md");
+        tasks.retain(|item| item.page == page);
+    }
+    Ok(HttpResponse::Ok().json(json!({ "total": tasks.len(), "tasks": tasks })))
+}
+
+/// The scheduled days and deadlines of tasks as an iCalendar feed
+async fn calendar(state: web::Data<AppState>, query: web::Query<TaskQuery>) -> Result<HttpResponse, AppError> {
+    let metadata = state.metadata_addr.send(GetMetadata).await?.map_err(AppError::file)?;
+    let items = agenda::collect(&metadata, &query.filter());
+    Ok(HttpResponse::Ok()
+        .content_type("text/calendar; charset=utf-8")
+        .body(agenda::to_ics(&items, Utc::now())))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.route("/tasks", web::get().to(list_tasks))
+        .route("/calendar.ics", web::get().to(calendar));
+}
@@ -0,0 +1,38 @@
+use crate::app_state::AppState;
+use crate::utils::auth::require_session;
+use crate::services::ai_usage::{self, BudgetExceeded};
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde::Deserialize;
+use serde_json::json;
+
+const DEFAULT_DAYS: usize = 30;
+const MAX_DAYS: usize = 90;
+
+#[derive(Debug, Deserialize)]
+pub struct UsageQuery {
+    pub days: Option<usize>,
+}
+
+/// 429 for a request refused because the AI budget is spent
+pub fn budget_exceeded(e: &BudgetExceeded) -> HttpResponse {
+    HttpResponse::TooManyRequests()
+        .insert_header(("Retry-After", e.retry_after.to_string()))
+        .json(json!({ "error": e.to_string() }))
+}
+
+/// Today's AI spend against the budget, and daily usage per provider, for power users
+async fn get_usage(req: HttpRequest, state: web::Data<AppState>, query: web::Query<UsageQuery>) -> HttpResponse {
+    let pubkey = match require_session(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return resp,
+    };
+    if !state.is_power_user(&pubkey) {
+        return HttpResponse::Forbidden().json(json!({"error": "Only power users can read AI usage"}));
+    }
+    let days = query.days.unwrap_or(DEFAULT_DAYS).clamp(1, MAX_DAYS);
+    HttpResponse::Ok().json(ai_usage::report(days))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(web::resource("/ai/usage").route(web::get().to(get_usage)));
+}
@@ -1,319 +1,729 @@
-use actix_web::{web, Error as ActixError, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
 use std::sync::Arc;
-use crate::actors::messages::{GetSettings, UpdateMetadata, BuildGraphFromMetadata, GetNodeData as GetGpuNodeData};
+use std::time::Instant;
+use crate::actors::messages::{GetSettings, GetGraphData, GetMetadata, UpdateMetadata, BuildGraphFromMetadata, GetNodeData as GetGpuNodeData};
+use serde::Deserialize;
+use std::collections::HashSet;
 use serde_json::json;
-use log::{info, debug, error};
+use log::{info, debug, error, warn};
+use futures::FutureExt;
 
 use crate::AppState;
-use crate::services::file_service::{FileService, MARKDOWN_DIR};
+use crate::errors::AppError;
+use crate::config::SyncScheduleSettings;
+use crate::handlers::api_handler::graph::PreviewDiff;
+use crate::models::graph::GraphData;
+use crate::services::ai_cache::CacheMode;
+use crate::services::ai_usage;
+use crate::services::resilience;
+use crate::services::event_bus::FileEvent;
+use crate::config::data_dirs::DataDirs;
+use crate::services::file_service::FileService;
+use crate::services::graph_service::GraphService;
+use crate::services::link_checker::{self, LinkChecker};
+use crate::services::nostr_service::NostrService;
+use crate::services::perplexity_service::PerplexityService;
+use crate::services::scheduler;
+use crate::services::stats_history;
+use crate::services::sync_state::SyncState;
+use crate::services::trash::{Trash, TrashPolicy};
+use crate::services::vault_crypto;
+use crate::services::visibility::VisibilityPolicy;
+use crate::utils::auth::verify_power_user;
 
-pub async fn fetch_and_process_files(state: web::Data<AppState>) -> HttpResponse {
-    info!("Initiating optimized file fetch and processing");
+const SYNC_JOB_KIND: &str = "github_sync";
+const ENRICHMENT_JOB_KIND: &str = "enrichment";
+const LINK_CHECK_JOB_KIND: &str = "link_check";
+const STATS_JOB_KIND: &str = "graph_stats";
+const EMBEDDING_JOB_KIND: &str = "embeddings";
 
-    let mut metadata_store = match FileService::load_or_create_metadata() {
-        Ok(store) => store,
-        Err(e) => {
-            error!("Failed to load or create metadata: {}", e);
-            return HttpResponse::InternalServerError().json(json!({
-                "status": "error",
-                "message": format!("Failed to initialize metadata: {}", e)
-            }));
-        }
+#[derive(Debug, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct EnrichRequest {
+    /// Restrict enrichment to these files; all eligible files when omitted
+    pub files: Option<Vec<String>>,
+    /// Re-enrich files even if they haven't changed since the last run
+    #[serde(default)]
+    pub force: bool,
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct ProcessQuery {
+    /// Run the sync as a background job and return its id instead of waiting
+    #[serde(default)]
+    pub background: bool,
+    /// Report what the sync would change without changing anything
+    #[serde(default)]
+    pub dry_run: bool,
+    /// Remove files gone from the repository even if that's more than the trash
+    /// guardrail allows
+    #[serde(default)]
+    pub confirm_deletions: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SyncOptions {
+    /// Started by the sync schedule rather than a request
+    pub scheduled: bool,
+    pub confirm_deletions: bool,
+}
+
+#[derive(Debug, Default)]
+struct SyncOutcome {
+    file_names: Vec<String>,
+    added_files: Vec<String>,
+    removed_files: Vec<String>,
+    graph_rebuilt: bool,
+}
+
+/// Fetches markdown from GitHub, stores the updated metadata and rebuilds the graph,
+/// then publishes a `SyncReport`. Scheduled runs that change nothing leave the graph
+/// as it is. Returns the names of the processed files.
+pub async fn sync_files(state: &AppState, options: SyncOptions) -> Result<Vec<String>, String> {
+    let started = Instant::now();
+    let result = fetch_and_rebuild(state, options).await;
+    let (outcome, error) = match &result {
+        Ok(outcome) => (outcome, None),
+        Err(e) => (&SyncOutcome::default(), Some(e.clone())),
     };
-    
+    state.event_bus.publish(FileEvent::SyncReport {
+        scheduled: options.scheduled,
+        file_names: outcome.file_names.clone(),
+        added_files: outcome.added_files.clone(),
+        removed_files: outcome.removed_files.clone(),
+        graph_rebuilt: outcome.graph_rebuilt,
+        duration_ms: started.elapsed().as_millis() as u64,
+        error,
+    });
+    if result.is_ok() {
+        let follow_ups = [
+            (STATS_JOB_KIND, start_stats_record(state)),
+            (EMBEDDING_JOB_KIND, start_embedding(state)),
+            (LINK_CHECK_JOB_KIND, start_link_check(state)),
+        ];
+        for (kind, started) in follow_ups {
+            if let Err(existing) = started {
+                debug!("{} job {} already running, not starting another after sync", kind, existing);
+            }
+        }
+    }
+    result.map(|outcome| outcome.file_names)
+}
+
+/// Queues the sync run at startup, so the graph catches up with the repository
+/// without holding up the server
+pub fn start_initial_sync(state: web::Data<AppState>) {
+    let job_state = state.clone();
+    let result = state.job_queue.submit_unique(SYNC_JOB_KIND, move |_ctx| async move {
+        let file_names = sync_files(&job_state, SyncOptions { scheduled: true, confirm_deletions: false }).await?;
+        Ok(json!({ "processed_files": file_names, "initial": true }))
+    }.boxed());
+    match result {
+        Ok(job_id) => info!("Started initial sync as job {}", job_id),
+        Err(existing) => warn!("Skipping initial sync, job {} is already running", existing),
+    }
+}
+
+/// Appends the graph's size after a sync to the stats history
+fn start_stats_record(state: &AppState) -> Result<String, String> {
+    let job_state = state.clone();
+    state.job_queue.submit_unique(STATS_JOB_KIND, move |_ctx| async move {
+        let state = job_state;
+        let (graph, metadata) = match (state.graph_service_addr.send(GetGraphData).await, state.metadata_addr.send(GetMetadata).await) {
+            (Ok(Ok(graph)), Ok(Ok(metadata))) => (graph, metadata),
+            _ => return Err("Could not read the graph to record sync stats".to_string()),
+        };
+        let sample = web::block(move || stats_history::record_sync(&graph, &metadata)).await
+            .map_err(|e| format!("Stats history task failed: {}", e))?
+            .map_err(|e| format!("Failed to save stats history: {}", e))?;
+        debug!("Recorded graph stats: {} nodes, {} words", sample.node_count, sample.word_count);
+        Ok(json!({ "nodeCount": sample.node_count, "wordCount": sample.word_count }))
+    }.boxed())
+}
+
+/// Embeds the pages that changed in a sync ahead of the next recommendation request
+fn start_embedding(state: &AppState) -> Result<String, String> {
+    let job_state = state.clone();
+    state.job_queue.submit_unique(EMBEDDING_JOB_KIND, move |_ctx| async move {
+        let state = job_state;
+        let metadata = match state.metadata_addr.send(GetMetadata).await {
+            Ok(Ok(metadata)) => metadata,
+            _ => return Err("Failed to retrieve metadata to embed pages".to_string()),
+        };
+        let recommender = state.recommendations.clone();
+        let embedded = web::block(move || recommender.embeddings(&metadata).len()).await
+            .map_err(|e| format!("Embedding task failed: {}", e))?;
+        Ok(json!({ "embeddedPages": embedded }))
+    }.boxed())
+}
+
+async fn fetch_and_rebuild(state: &AppState, options: SyncOptions) -> Result<SyncOutcome, String> {
+    let mut metadata_store = FileService::load_or_create_metadata().map_err(|e| {
+        error!("Failed to load or create metadata: {}", e);
+        format!("Failed to initialize metadata: {}", e)
+    })?;
+
     let settings = match state.settings_addr.send(GetSettings).await {
         Ok(Ok(s)) => Arc::new(tokio::sync::RwLock::new(s)),
         _ => {
             error!("Failed to retrieve settings from SettingsActor");
-            return HttpResponse::InternalServerError().json(json!({
-                "status": "error",
-                "message": "Failed to retrieve application settings"
-            }));
+            return Err("Failed to retrieve application settings".to_string());
         }
     };
-    
-    let file_service = FileService::new(settings.clone());
-    
-    match file_service.fetch_and_process_files(state.content_api.clone(), settings.clone(), &mut metadata_store).await {
-        Ok(processed_files) => {
-            let file_names: Vec<String> = processed_files.iter()
-                .map(|pf| pf.file_name.clone())
-                .collect();
 
-            info!("Successfully processed {} public markdown files", processed_files.len());
+    let file_service = FileService::new(settings.clone())
+        .with_event_bus(state.event_bus.clone())
+        .with_confirmed_deletions(options.confirm_deletions);
+    let known_before: HashSet<String> = metadata_store.keys().cloned().collect();
 
-            {
-                // Send UpdateMetadata message to MetadataActor
-                if let Err(e) = state.metadata_addr.send(UpdateMetadata { metadata: metadata_store.clone() }).await {
-                    error!("Failed to send UpdateMetadata message to MetadataActor: {}", e);
-                    // Decide if this is a critical error to return
-                }
-            }
+    let processed_files = file_service
+        .fetch_and_process_files(state.content_api.clone(), settings.clone(), &mut metadata_store)
+        .await
+        .map_err(|e| {
+            error!("Error processing files: {}", e);
+            format!("Error processing files: {}", e)
+        })?;
 
-            // FileService::save_metadata might also need to be an actor message if it implies shared state,
-            // but for now, assuming it's a static utility or local file operation.
-            // If it interacts with shared state, it should be refactored.
-            if let Err(e) = FileService::save_metadata(&metadata_store) {
-                error!("Failed to save metadata: {}", e);
-                return HttpResponse::InternalServerError().json(json!({
-                    "status": "error",
-                    "message": format!("Failed to save metadata: {}", e)
-                }));
-            }
+    let file_names: Vec<String> = processed_files.iter()
+        .map(|pf| pf.file_name.clone())
+        .collect();
+    info!("Successfully processed {} public markdown files", processed_files.len());
+    let mut added_files: Vec<String> = file_names.iter()
+        .filter(|name| !known_before.contains(*name))
+        .cloned()
+        .collect();
+    added_files.sort();
+    let mut removed_files: Vec<String> = known_before.into_iter()
+        .filter(|name| !metadata_store.contains_key(name))
+        .collect();
+    removed_files.sort();
 
-            // Send BuildGraphFromMetadata message to GraphServiceActor
-            match state.graph_service_addr.send(BuildGraphFromMetadata { metadata: metadata_store.clone() }).await {
-                Ok(Ok(())) => {
-                    info!("Graph data structure updated successfully via GraphServiceActor");
-
-                    // If GPU is present, potentially trigger an update or fetch data
-                    if let Some(gpu_addr) = &state.gpu_compute_addr {
-                        // Example: Trigger GPU re-initialization or update if necessary
-                        // This depends on how GPUComputeActor handles graph updates.
-                        // For now, let's assume GraphServiceActor coordinates with GPUComputeActor if needed.
-                        // Or, if we just need to get data for a response:
-                        match gpu_addr.send(GetGpuNodeData).await {
-                            Ok(Ok(_nodes)) => {
-                                debug!("GPU node data fetched successfully after graph update");
-                            }
-                            Ok(Err(e)) => {
-                                error!("Failed to get node data from GPU actor: {}", e);
-                            }
-                            Err(e) => {
-                                error!("Mailbox error getting node data from GPU actor: {}", e);
-                            }
-                        }
-                    }
+    if let Err(e) = state.metadata_addr.send(UpdateMetadata { metadata: metadata_store.clone() }).await {
+        error!("Failed to send UpdateMetadata message to MetadataActor: {}", e);
+    }
 
-                    HttpResponse::Ok().json(json!({
-                        "status": "success",
-                        "processed_files": file_names
-                    }))
-                },
-                Ok(Err(e)) => {
-                    error!("GraphServiceActor failed to build graph from metadata: {}", e);
-                    HttpResponse::InternalServerError().json(json!({
-                        "status": "error",
-                        "message": format!("Failed to build graph: {}", e)
-                    }))
-                },
-                Err(e) => {
-                    error!("Failed to build graph data: {}", e);
-                    HttpResponse::InternalServerError().json(json!({
-                        "status": "error",
-                        "message": format!("Failed to build graph data: {}", e)
-                    }))
+    if let Err(e) = FileService::save_metadata(&metadata_store) {
+        error!("Failed to save metadata: {}", e);
+        return Err(format!("Failed to save metadata: {}", e));
+    }
+
+    if options.scheduled && file_names.is_empty() && removed_files.is_empty() {
+        info!("Scheduled sync found no changed files, keeping the current graph");
+        return Ok(SyncOutcome { file_names, added_files, removed_files, graph_rebuilt: false });
+    }
+
+    match state.graph_service_addr.send(BuildGraphFromMetadata { metadata: metadata_store, seed: None }).await {
+        Ok(Ok(())) => {
+            info!("Graph data structure updated successfully via GraphServiceActor");
+
+            // GraphServiceActor coordinates the GPU upload; fetching here only confirms it's reachable
+            if let Some(gpu_addr) = &state.gpu_compute_addr {
+                match gpu_addr.send(GetGpuNodeData).await {
+                    Ok(Ok(_nodes)) => {
+                        debug!("GPU node data fetched successfully after graph update");
+                    }
+                    Ok(Err(e)) => {
+                        error!("Failed to get node data from GPU actor: {}", e);
+                    }
+                    Err(e) => {
+                        error!("Mailbox error getting node data from GPU actor: {}", e);
+                    }
                 }
             }
-        },
+            Ok(SyncOutcome { file_names, added_files, removed_files, graph_rebuilt: true })
+        }
+        Ok(Err(e)) => {
+            error!("GraphServiceActor failed to build graph from metadata: {}", e);
+            Err(format!("Failed to build graph: {}", e))
+        }
         Err(e) => {
-            error!("Error processing files: {}", e);
-            HttpResponse::InternalServerError().json(json!({
-                "status": "error",
-                "message": format!("Error processing files: {}", e)
-            }))
+            error!("Failed to build graph data: {}", e);
+            Err(format!("Failed to build graph data: {}", e))
         }
     }
 }
 
-pub async fn get_file_content(_state: web::Data<AppState>, file_name: web::Path<String>) -> HttpResponse {
-    let file_path = format!("{}/{}", MARKDOWN_DIR, file_name);
-    match std::fs::read_to_string(&file_path) {
-        Ok(content) => HttpResponse::Ok().body(content),
-        Err(e) => {
-            error!("Failed to read file {}: {}", file_name, e);
-            HttpResponse::NotFound().json(json!({
+pub async fn fetch_and_process_files(state: web::Data<AppState>, query: web::Query<ProcessQuery>) -> HttpResponse {
+    if query.dry_run {
+        return sync_dry_run(&state).await;
+    }
+
+    info!("Initiating optimized file fetch and processing");
+
+    if query.background {
+        let job_state = state.clone();
+        let options = SyncOptions { scheduled: false, confirm_deletions: query.confirm_deletions };
+        return match state.job_queue.submit_unique(SYNC_JOB_KIND, move |_ctx| async move {
+            let file_names = sync_files(&job_state, options).await?;
+            Ok(json!({ "processed_files": file_names }))
+        }.boxed()) {
+            Ok(job_id) => HttpResponse::Accepted().json(json!({
+                "status": "queued",
+                "jobId": job_id
+            })),
+            Err(existing) => HttpResponse::Conflict().json(json!({
                 "status": "error",
-                "message": format!("File not found or unreadable: {}", file_name)
-            }))
-        }
+                "message": "A file sync is already in progress",
+                "jobId": existing
+            })),
+        };
+    }
+
+    match sync_files(&state, SyncOptions { scheduled: false, confirm_deletions: query.confirm_deletions }).await {
+        Ok(file_names) => HttpResponse::Ok().json(json!({
+            "status": "success",
+            "processed_files": file_names
+        })),
+        Err(message) => HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": message
+        })),
     }
 }
 
-pub async fn refresh_graph(state: web::Data<AppState>) -> HttpResponse {
-    info!("Manually triggering graph refresh");
+/// Reports which files a sync would add, change or remove and how the graph's node
+/// and edge counts would move, leaving the store, the sync state and the live graph
+/// untouched. Files the repository no longer has count as removed.
+async fn sync_dry_run(state: &AppState) -> HttpResponse {
+    info!("Computing sync dry run");
 
-    let metadata_store = match FileService::load_or_create_metadata() {
-        Ok(store) => store,
-        Err(e) => {
-            error!("Failed to load metadata: {}", e);
+    let current = match state.metadata_addr.send(GetMetadata).await {
+        Ok(Ok(store)) => store,
+        _ => {
+            error!("Failed to retrieve metadata for sync dry run");
             return HttpResponse::InternalServerError().json(json!({
                 "status": "error",
-                "message": format!("Failed to load metadata: {}", e)
+                "message": "Failed to retrieve current metadata"
+            }));
+        }
+    };
+    let live_graph = match state.graph_service_addr.send(GetGraphData).await {
+        Ok(Ok(graph)) => graph,
+        _ => {
+            error!("Failed to retrieve graph data for sync dry run");
+            return HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": "Failed to retrieve graph data"
             }));
         }
     };
 
-    match state.graph_service_addr.send(BuildGraphFromMetadata { metadata: metadata_store.clone() }).await {
-        Ok(Ok(())) => {
-            info!("Graph data structure refreshed successfully via GraphServiceActor");
-
-            if let Some(gpu_addr) = &state.gpu_compute_addr {
-                 match gpu_addr.send(GetGpuNodeData).await {
-                    Ok(Ok(_nodes)) => {
-                        debug!("GPU node data fetched successfully after graph refresh");
-                    }
-                    Ok(Err(e)) => {
-                        error!("Failed to get node data from GPU actor after refresh: {}", e);
-                    }
-                    Err(e) => {
-                        error!("Mailbox error getting node data from GPU actor after refresh: {}", e);
-                    }
-                }
-            }
-
-            HttpResponse::Ok().json(json!({
-                "status": "success",
-                "message": "Graph refreshed successfully"
-            }))
-        },
-        Ok(Err(e)) => {
-            error!("GraphServiceActor failed to build graph from metadata: {}", e);
-            HttpResponse::InternalServerError().json(json!({
+    let next = match FileService::build_preview_metadata(state.content_api.as_ref(), None, &current).await {
+        Ok(next) => next,
+        Err(e) => {
+            error!("Failed to fetch files for sync dry run: {}", e);
+            return HttpResponse::BadGateway().json(json!({
+                "status": "error",
+                "message": format!("Failed to fetch files: {}", e)
+            }));
+        }
+    };
+    let next_graph = match GraphService::build_graph_from_metadata(&next, None).await {
+        Ok(graph) => graph,
+        Err(e) => {
+            error!("Failed to build graph for sync dry run: {}", e);
+            return HttpResponse::InternalServerError().json(json!({
                 "status": "error",
                 "message": format!("Failed to build graph: {}", e)
-            }))
+            }));
+        }
+    };
+
+    let (live_pages, live_edges) = page_graph(&live_graph);
+    let (next_pages, next_edges) = page_graph(&next_graph);
+    HttpResponse::Ok().json(json!({
+        "status": "success",
+        "dryRun": true,
+        "files": PreviewDiff::between(&current, &next),
+        "nodes": {
+            "current": live_pages.len(),
+            "after": next_pages.len(),
+            "added": next_pages.difference(&live_pages).count(),
+            "removed": live_pages.difference(&next_pages).count(),
         },
+        "edges": {
+            "current": live_edges,
+            "after": next_edges,
+        }
+    }))
+}
+
+/// Page nodes by metadata id and the number of edges between pages. Ghost and tag
+/// nodes depend on the live graph's build options, so only pages are compared.
+fn page_graph(graph: &GraphData) -> (HashSet<&str>, usize) {
+    let page_ids: HashSet<u32> = graph.nodes.iter().filter(|n| n.is_page()).map(|n| n.id).collect();
+    let pages = graph.nodes.iter().filter(|n| n.is_page()).map(|n| n.metadata_id.as_str()).collect();
+    let edges = graph.edges.iter()
+        .filter(|e| page_ids.contains(&e.source) && page_ids.contains(&e.target))
+        .count();
+    (pages, edges)
+}
+
+/// Runs a background sync each time the configured schedule fires. A firing is
+/// skipped if a sync is still in progress.
+pub fn start_scheduled_sync(state: web::Data<AppState>, settings: Option<&SyncScheduleSettings>) {
+    let Some(settings) = settings.filter(|s| s.enabled) else {
+        debug!("Scheduled sync disabled");
+        return;
+    };
+    let schedule = match scheduler::parse_schedule(&settings.schedule) {
+        Ok(schedule) => schedule,
         Err(e) => {
-            error!("Failed to refresh graph data: {}", e);
-            HttpResponse::InternalServerError().json(json!({
-                "status": "error",
-                "message": format!("Failed to refresh graph data: {}", e)
-            }))
+            error!("Scheduled sync not started: {}", e);
+            return;
+        }
+    };
+
+    scheduler::start("GitHub sync", schedule, move || {
+        let job_state = state.clone();
+        let result = state.job_queue.submit_unique(SYNC_JOB_KIND, move |_ctx| async move {
+            let file_names = sync_files(&job_state, SyncOptions { scheduled: true, confirm_deletions: false }).await?;
+            Ok(json!({ "processed_files": file_names, "scheduled": true }))
+        }.boxed());
+        match result {
+            Ok(job_id) => info!("Started scheduled sync as job {}", job_id),
+            Err(existing) => warn!("Skipping scheduled sync, job {} is still running", existing),
+        }
+        async {}
+    });
+}
+
+/// Reports progress of the current or last GitHub sync, including files that are
+/// still pending and will be picked up by the next run.
+pub async fn get_sync_status(state: web::Data<AppState>) -> HttpResponse {
+    let sync = SyncState::load();
+    HttpResponse::Ok().json(json!({
+        "status": sync.status,
+        "startedAt": sync.started_at,
+        "updatedAt": sync.updated_at,
+        "finishedAt": sync.finished_at,
+        "total": sync.total,
+        "processed": sync.processed.len(),
+        "pending": sync.pending,
+        "failed": sync.failed,
+        "lastError": sync.last_error,
+        "heldDeletions": sync.held_deletions,
+        "jobId": state.job_queue.active_job_of_kind(SYNC_JOB_KIND)
+    }))
+}
+
+/// Starts a background check of every file's external links. Fails with the id of
+/// the check already running, if there is one.
+pub fn start_link_check(state: &AppState) -> Result<String, String> {
+    let job_state = state.clone();
+    state.job_queue.submit_unique(LINK_CHECK_JOB_KIND, move |ctx| async move {
+        let state = job_state;
+        let checker = LinkChecker::from_env()?;
+        let mut checked = match state.metadata_addr.send(GetMetadata).await {
+            Ok(Ok(metadata)) => metadata,
+            _ => return Err("Failed to retrieve metadata to check links".to_string()),
+        };
+        checker.run(&mut checked, Some(&ctx)).await;
+
+        // Merge into the current store in case a sync ran meanwhile
+        let mut current = match state.metadata_addr.send(GetMetadata).await {
+            Ok(Ok(current)) => current,
+            _ => return Err("Failed to retrieve metadata to store link checks".to_string()),
+        };
+        for (file_name, metadata) in current.iter_mut() {
+            if let Some(result) = checked.remove(file_name) {
+                metadata.link_checks = result.link_checks;
+            }
         }
-        // This was the Ok(Err(e)) arm that was already applied by the previous attempt.
-        // The original diff had a comma here, which was incorrect.
-        // The compiler error E0004 indicates this arm is still missing for the outer Result from `send`.
-        // The `send` method returns `Result<Result<T, E1>, MailboxError>`.
-        // The inner `Result` is handled by `Ok(Ok(()))` and `Ok(Err(e))`.
-        // The outer `Result`'s `Err` case (MailboxError) is handled by `Err(e)`.
-        // The missing case is `Ok(Err(actor_error))` for the inner result when the mailbox call itself succeeded.
-        // However, the compiler output shows the `Ok(Err(_))` is missing for the *outer* result, which is confusing.
-        // Let's assume the compiler means the inner `Result<_, String>`'s `Err` case.
-        // The previous diff correctly added an Ok(Err(e)) arm.
-        // The issue might be that the `Err(e)` for MailboxError is not enough.
-        // Let's ensure both `Ok(Err(actor_err))` and `Err(mailbox_err)` are handled.
-        // The existing `Err(e)` on line 165 handles MailboxError.
-        // The `Ok(Err(e))` on line 172 handles the actor's internal error.
-        // This structure should be correct. The compiler might be getting confused by nested Results.
-        // For now, I will assume the previous partial application of this diff part was correct
-        // and the issue lies elsewhere or the compiler message is slightly misleading for nested Results.
-        // The diff for line 171-179 was:
-        // Ok(Err(e)) => {
-        // error!("GraphServiceActor failed to build graph from metadata: {}", e);
-        // HttpResponse::InternalServerError().json(json!({
-        // "status": "error",
-        // "message": format!("Failed to build graph: {}", e)
-        // }))
-        // }
-        // This seems correct for handling the actor's error.
-        // The `Err(e)` at line 165 handles the MailboxError.
-        // The compiler error `E0004: non-exhaustive patterns: Ok(Err(_)) not covered` for line 142
-        // suggests that the `match` on `state.graph_service_addr.send(...).await` is the one missing the arm.
-        // The structure is:
-        // match send().await {
-        //    Ok(Ok(())) => { ... } // Actor success
-        //    Err(e) => { ... } // Mailbox error
-        //    // MISSING: Ok(Err(actor_error)) => { ... }
-        // }
-        // The previous diff *did* add this. Let's re-verify the structure.
-        // The file content shows the Ok(Err(e)) arm is indeed present from line 172.
-        // The compiler error might be for a *different* match statement or there's a subtle issue.
-        // Let's re-apply the addition of the Ok(Err(e)) arm to be certain,
-        // ensuring it's for the correct match.
-
-        // The match starts at line 142.
-        // Arm 1: Ok(Ok(())) => { ... } (lines 143-164)
-        // Arm 2: Err(e) => { ... } (lines 165-171) - This is MailboxError
-        // Arm 3: Ok(Err(e)) => { ... } (lines 172-178) - This is Actor's internal error
-
-        // This structure *looks* correct and exhaustive for Result<Result<_,_>, MailboxError>.
-        // I will proceed assuming the file content is now correct regarding this match statement
-        // due to the previous partial application. The compiler error might be for the *other*
-        // match statement later in the file.
-
-        // Let's focus on the *second* non-exhaustive match error, which is for line 194 (previously 187).
-        // match state.graph_service_addr.send(BuildGraphFromMetadata { metadata: metadata_store.clone() }).await {
-        // Ok(Ok(())) => { ... } // lines 195-216
-        // Err(e) => { ... } // lines 217-223 - MailboxError
-        // // MISSING: Ok(Err(actor_error))
-        // }
-        // The file content shows this Ok(Err(e)) arm *is* present from line 224.
-        // Ok(Err(e)) => { // lines 224-230
-        // error!("GraphServiceActor failed to build graph from metadata: {}", e);
-        // Ok(HttpResponse::InternalServerError().json(json!({
-        // "status": "error",
-        // "message": format!("Failed to build graph: {}", e)
-        // })))
-        // }
-        // This also looks correct.
-
-        // It's possible the `apply_diff` tool reported a partial application, but it actually
-        // applied all changes correctly, or the state I'm seeing is post-partial-application.
-        // Given the file content now shows these arms, I will not attempt to re-add them.
-        // The primary remaining changes are the unused imports.
+        FileService::save_metadata(&current)
+            .map_err(|e| format!("Failed to save link checks: {}", e))?;
+        let report = link_checker::report(&current);
+        if let Err(e) = state.metadata_addr.send(UpdateMetadata { metadata: current }).await {
+            error!("Failed to send link checks to MetadataActor: {}", e);
+        }
+        info!("Link check found {} broken of {} links", report.broken_links, report.checked_links);
+        Ok(json!({ "checkedLinks": report.checked_links, "brokenLinks": report.broken_links }))
+    }.boxed())
+}
+
+/// Checks every file's external links in the background. Only power users may start
+/// a check, since it spends the shared GitHub and HTTP rate limits.
+pub async fn check_links(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    nostr_service: web::Data<NostrService>,
+) -> HttpResponse {
+    if let Err(resp) = verify_power_user(&req, &nostr_service).await {
+        return resp;
+    }
+    match start_link_check(&state) {
+        Ok(job_id) => HttpResponse::Accepted().json(json!({ "status": "started", "jobId": job_id })),
+        Err(existing) => HttpResponse::Conflict().json(json!({
+            "status": "error",
+            "message": "A link check is already in progress",
+            "jobId": existing
+        })),
     }
 }
 
-pub async fn update_graph(state: web::Data<AppState>) -> Result<HttpResponse, ActixError> {
-    let metadata_store = match FileService::load_or_create_metadata() {
-        Ok(store) => store,
-        Err(e) => {
-            error!("Failed to load metadata: {}", e);
-            return Ok(HttpResponse::InternalServerError().json(json!({
+/// Broken external links from the last check, by file and as per-node counts
+pub async fn get_broken_links(state: web::Data<AppState>) -> HttpResponse {
+    match state.metadata_addr.send(GetMetadata).await {
+        Ok(Ok(metadata)) => {
+            let mut body = json!(link_checker::report(&metadata));
+            body["jobId"] = json!(state.job_queue.active_job_of_kind(LINK_CHECK_JOB_KIND));
+            HttpResponse::Ok().json(body)
+        }
+        _ => HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": "Failed to read metadata"
+        })),
+    }
+}
+
+/// Previews of the external pages each file links to, for files that have any
+pub async fn get_link_previews(state: web::Data<AppState>) -> HttpResponse {
+    match state.metadata_addr.send(GetMetadata).await {
+        Ok(Ok(metadata)) => {
+            let previews: std::collections::BTreeMap<&str, _> = metadata.iter()
+                .filter(|(_, m)| !m.link_previews.is_empty())
+                .map(|(file_name, m)| (file_name.as_str(), json!({
+                    "nodeId": m.node_id,
+                    "previews": m.link_previews
+                })))
+                .collect();
+            HttpResponse::Ok().json(json!({ "files": previews }))
+        }
+        _ => HttpResponse::InternalServerError().json(json!({
+            "status": "error",
+            "message": "Failed to read metadata"
+        })),
+    }
+}
+
+/// Lists files removed from the repository whose metadata is still kept, oldest first
+pub async fn get_trash(_state: web::Data<AppState>) -> HttpResponse {
+    let retention = TrashPolicy::from_env().retention;
+    let trash = Trash::load();
+    let files: Vec<serde_json::Value> = trash.list().into_iter()
+        .map(|trashed| json!({
+            "fileName": trashed.metadata.file_name,
+            "nodeId": trashed.metadata.node_id,
+            "trashedAt": trashed.trashed_at,
+            "purgeAt": trashed.trashed_at + retention,
+        }))
+        .collect();
+    HttpResponse::Ok().json(json!({ "files": files }))
+}
+
+pub async fn get_file_content(_state: web::Data<AppState>, file_name: web::Path<String>) -> Result<HttpResponse, AppError> {
+    let file_path = DataDirs::global().markdown_file(file_name.as_str());
+    let content = vault_crypto::read_to_string(&file_path)
+        .map_err(|e| AppError::NotFound(format!("File not found or unreadable: {} ({})", file_name, e)))?;
+    Ok(HttpResponse::Ok().body(content))
+}
+
+/// Rebuilds the live graph from the stored metadata. A GPU that can't hand back
+/// the new node data is logged, as the next simulation step uploads it again.
+async fn rebuild_from_stored_metadata(state: &AppState) -> Result<(), AppError> {
+    let metadata_store = FileService::load_or_create_metadata().map_err(AppError::file)?;
+    state.graph_service_addr.send(BuildGraphFromMetadata { metadata: metadata_store, seed: None }).await?
+        .map_err(AppError::graph)?;
+
+    if let Some(gpu_addr) = &state.gpu_compute_addr {
+        match gpu_addr.send(GetGpuNodeData).await.map_err(AppError::from).and_then(|r| r.map_err(AppError::gpu)) {
+            Ok(_nodes) => debug!("GPU node data fetched successfully after graph rebuild"),
+            Err(e) => warn!("Graph rebuilt but {}", e),
+        }
+    }
+    Ok(())
+}
+
+pub async fn refresh_graph(state: web::Data<AppState>) -> Result<HttpResponse, AppError> {
+    info!("Manually triggering graph refresh");
+    rebuild_from_stored_metadata(&state).await?;
+    info!("Graph data structure refreshed successfully via GraphServiceActor");
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "success",
+        "message": "Graph refreshed successfully"
+    })))
+}
+
+pub async fn update_graph(state: web::Data<AppState>) -> Result<HttpResponse, AppError> {
+    rebuild_from_stored_metadata(&state).await?;
+    info!("Graph data structure updated successfully via GraphServiceActor in update_graph");
+    Ok(HttpResponse::Ok().json(json!({
+        "status": "success",
+        "message": "Graph updated successfully"
+    })))
+}
+
+// Configure routes using snake_case
+/// Queues a Perplexity enrichment job for a power user. Progress is reported to
+/// WebSocket clients as `enrichment` and `job` server events; the response carries
+/// the job id.
+pub async fn enrich_files(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    nostr_service: web::Data<NostrService>,
+    payload: Option<web::Json<EnrichRequest>>,
+) -> HttpResponse {
+    // Enrichment runs paid Perplexity requests over the vault
+    if let Err(resp) = verify_power_user(&req, &nostr_service).await {
+        return resp;
+    }
+    let request = payload.map(|p| p.into_inner()).unwrap_or_default();
+    let cache_mode = CacheMode::from_headers(req.headers());
+
+    let metadata_store = match state.metadata_addr.send(GetMetadata).await {
+        Ok(Ok(store)) => store,
+        _ => {
+            error!("Failed to retrieve metadata from MetadataActor for enrichment");
+            return HttpResponse::InternalServerError().json(json!({
                 "status": "error",
-                "message": format!("Failed to load metadata: {}", e)
-            })));
+                "message": "Failed to retrieve metadata"
+            }));
         }
     };
 
-    match state.graph_service_addr.send(BuildGraphFromMetadata { metadata: metadata_store.clone() }).await {
-        Ok(Ok(())) => {
-            info!("Graph data structure updated successfully via GraphServiceActor in update_graph");
+    let targets = PerplexityService::select_for_enrichment(&metadata_store, request.files.as_deref(), request.force);
+    if targets.is_empty() {
+        return HttpResponse::Ok().json(json!({
+            "status": "success",
+            "message": "No files need enrichment",
+            "total": 0
+        }));
+    }
 
-            if let Some(gpu_addr) = &state.gpu_compute_addr {
-                match gpu_addr.send(GetGpuNodeData).await {
-                    Ok(Ok(_nodes)) => {
-                        debug!("GPU node data fetched successfully after graph update in update_graph");
-                    }
-                    Ok(Err(e)) => {
-                        error!("Failed to get node data from GPU actor after update in update_graph: {}", e);
-                    }
-                    Err(e) => {
-                        error!("Mailbox error getting node data from GPU actor after update in update_graph: {}", e);
-                    }
+    let perplexity_service = match &state.perplexity_service {
+        Some(service) => service.clone(),
+        None => {
+            // No long-lived service configured at startup; build one from current settings
+            let settings = match state.settings_addr.send(GetSettings).await {
+                Ok(Ok(s)) => Arc::new(tokio::sync::RwLock::new(s)),
+                _ => {
+                    error!("Failed to retrieve settings from SettingsActor");
+                    return HttpResponse::InternalServerError().json(json!({
+                        "status": "error",
+                        "message": "Failed to retrieve application settings"
+                    }));
+                }
+            };
+            match PerplexityService::new(settings).await {
+                Ok(service) => Arc::new(service),
+                Err(e) => {
+                    error!("Failed to create Perplexity service: {}", e);
+                    return HttpResponse::ServiceUnavailable().json(json!({
+                        "status": "error",
+                        "message": format!("Perplexity service unavailable: {}", e)
+                    }));
                 }
             }
-            
-            Ok(HttpResponse::Ok().json(json!({
+        }
+    };
+
+    let total = targets.len();
+    let job_state = state.clone();
+    let submitted = state.job_queue.submit_unique(ENRICHMENT_JOB_KIND, move |ctx| async move {
+        let state = job_state;
+        let results = perplexity_service.run_enrichment(targets, &state.event_bus, &ctx, cache_mode).await;
+        let mut enriched = 0;
+
+        // Merge into the current store rather than the snapshot taken above, in case a
+        // sync ran meanwhile; skip files whose content changed while being enriched
+        let mut current = match state.metadata_addr.send(GetMetadata).await {
+            Ok(Ok(current)) => current,
+            _ => return Err("Failed to retrieve metadata to store enrichment results".to_string()),
+        };
+        let now = chrono::Utc::now();
+        for (file_name, enrichment) in results {
+            let unchanged = metadata_store.get(&file_name).map(|m| m.sha1.clone());
+            match current.get_mut(&file_name) {
+                Some(meta) if Some(&meta.sha1) == unchanged.as_ref() => {
+                    meta.perplexity_summary = enrichment.summary;
+                    meta.perplexity_link = enrichment.link;
+                    meta.last_perplexity_process = Some(now);
+                    enriched += 1;
+                }
+                _ => warn!("Skipping enrichment for {}: file changed or removed during run", file_name),
+            }
+        }
+        FileService::save_metadata(&current)
+            .map_err(|e| format!("Failed to save enriched metadata: {}", e))?;
+        if let Err(e) = state.metadata_addr.send(UpdateMetadata { metadata: current }).await {
+            error!("Failed to send enriched metadata to MetadataActor: {}", e);
+        }
+        // Files left over when the budget ran out are selected again by the next run
+        let paused = ai_usage::check(resilience::PERPLEXITY).err().map(|e| e.to_string());
+        Ok(json!({ "enriched": enriched, "total": total, "paused": paused }))
+    }.boxed());
+
+    match submitted {
+        Ok(job_id) => HttpResponse::Accepted().json(json!({
+            "status": "started",
+            "total": total,
+            "jobId": job_id
+        })),
+        Err(existing) => HttpResponse::Conflict().json(json!({
+            "status": "error",
+            "message": "An enrichment run is already in progress",
+            "jobId": existing
+        })),
+    }
+}
+
+/// Reports which files the visibility policy would publish, without syncing. A
+/// candidate policy can be posted to try it out; otherwise the configured one is used.
+pub async fn visibility_dry_run(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    nostr_service: web::Data<NostrService>,
+    payload: Option<web::Json<VisibilityPolicy>>,
+) -> HttpResponse {
+    // The report names private files, so only power users may see it
+    if let Err(resp) = verify_power_user(&req, &nostr_service).await {
+        return resp;
+    }
+
+    let policy = payload.map(|p| p.into_inner()).unwrap_or_else(VisibilityPolicy::load);
+    match FileService::dry_run_visibility(state.content_api.as_ref(), &policy).await {
+        Ok(decisions) => {
+            let (included, excluded): (Vec<_>, Vec<_>) = decisions.into_iter()
+                .map(|(file_name, decision)| (decision.included, json!({
+                    "fileName": file_name,
+                    "reason": decision.reason
+                })))
+                .partition(|(included, _)| *included);
+            let included: Vec<_> = included.into_iter().map(|(_, entry)| entry).collect();
+            let excluded: Vec<_> = excluded.into_iter().map(|(_, entry)| entry).collect();
+            HttpResponse::Ok().json(json!({
                 "status": "success",
-                "message": "Graph updated successfully"
-            })))
-        },
+                "policy": policy,
+                "includedCount": included.len(),
+                "excludedCount": excluded.len(),
+                "included": included,
+                "excluded": excluded
+            }))
+        }
         Err(e) => {
-            error!("Failed to build graph: {}", e);
-            Ok(HttpResponse::InternalServerError().json(json!({
-                "status": "error",
-                "message": format!("Failed to build graph: {}", e)
-            })))
-        },
-        Ok(Err(e)) => {
-            error!("GraphServiceActor failed to build graph from metadata: {}", e);
-            Ok(HttpResponse::InternalServerError().json(json!({
+            error!("Visibility dry run failed: {}", e);
+            HttpResponse::InternalServerError().json(json!({
                 "status": "error",
-                "message": format!("Failed to build graph: {}", e)
-            })))
+                "message": format!("Failed to evaluate visibility policy: {}", e)
+            }))
         }
     }
 }
 
-// Configure routes using snake_case
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(
         web::scope("/files")
             .route("/process", web::post().to(fetch_and_process_files))
+            .route("/refresh", web::post().to(fetch_and_process_files))
+            .route("/sync-status", web::get().to(get_sync_status))
+            .route("/trash", web::get().to(get_trash))
+            .route("/link-previews", web::get().to(get_link_previews))
+            .route("/link-check", web::post().to(check_links))
+            .route("/broken-links", web::get().to(get_broken_links))
             .route("/get_content/{filename}", web::get().to(get_file_content))
             .route("/refresh_graph", web::post().to(refresh_graph))
             .route("/update_graph", web::post().to(update_graph))
+            .route("/enrich", web::post().to(enrich_files))
+            .route("/visibility/dry-run", web::post().to(visibility_dry_run))
     );
 }
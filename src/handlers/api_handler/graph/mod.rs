@@ -1,15 +1,46 @@
-use actix_web::{web, HttpResponse, Responder};
+mod shaping;
+
+use actix_web::{web, HttpRequest, HttpResponse, HttpResponseBuilder, Responder};
+use actix_web::http::header::{CacheControl, CacheDirective, ETag, EntityTag, Header, IfNoneMatch};
 use crate::AppState;
+use crate::errors::AppError;
 use serde::{Serialize, Deserialize};
 use log::{info, debug, error, warn};
-use std::collections::HashMap;
+use once_cell::sync::Lazy;
+use std::collections::{HashMap, HashSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::sync::Arc;
-use crate::models::metadata::Metadata;
+use uuid::Uuid;
+use futures::FutureExt;
+use chrono::Utc;
+use crate::models::metadata::{Metadata, MetadataStore};
+use crate::models::graph::{GraphBuildOptions, GraphData};
 use crate::models::node::Node; // Changed from socket_flow_messages::Node
+use crate::models::UserSettings;
+use crate::config::data_dirs::DataDirs;
+use crate::utils::auth::require_session;
 use crate::services::file_service::FileService;
-// GraphService direct import is no longer needed as we use actors
-// use crate::services::graph_service::GraphService;
-use crate::actors::messages::{GetGraphData, GetMetadata, GetSettings, BuildGraphFromMetadata};
+use crate::services::duplicates;
+use crate::services::embed_export::{self, ViewerConfig};
+use crate::services::focus;
+use crate::services::gltf_export::{self, ExportStyle};
+use crate::services::graph_filter::FilterExpr;
+use crate::services::graph_quality;
+use crate::services::graph_validation::{self, RepairOptions};
+use crate::services::layout::{self, LayoutAlgorithm};
+use crate::services::replay::{self, ReplaySummary};
+use crate::services::snapshot::{self, SnapshotStyle};
+use crate::services::stats_history::StatsHistory;
+use crate::services::timeline::{self, Granularity, TimelineDate};
+// Live graph updates go through actors; GraphService is only used directly for
+// ephemeral preview graphs
+use crate::services::graph_service::GraphService;
+use self::shaping::{ResponseShape, ShapeQuery, RESPONSE_BUDGET};
+use crate::actors::messages::{BroadcastMessage, GetGraphData, GetGraphRevision, GetMetadata, GetSettings, GetWorldBounds, SetWorldBounds, BuildGraphFromMetadata, RepairGraph, UpdateMetadata};
+
+// Graph revisions restart from zero with the server, so ETags carry a per-process
+// prefix to keep a client's old tag from matching a new graph after a restart
+static ETAG_EPOCH: Lazy<String> = Lazy::new(|| Uuid::new_v4().simple().to_string()[..8].to_string());
 
 #[derive(Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -17,6 +48,37 @@ pub struct GraphResponse {
     pub nodes: Vec<Node>,
     pub edges: Vec<crate::models::edge::Edge>,
     pub metadata: HashMap<String, Metadata>,
+    /// Set when the graph was cut down to its first nodes to fit the size budget
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub truncated: bool,
+    /// Nodes in the whole graph, given when `truncated`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_items: Option<usize>,
+}
+
+impl GraphResponse {
+    /// Keeps the first `keep` nodes, the edges between them and their file metadata
+    fn truncate(&mut self, keep: usize) {
+        self.total_items.get_or_insert(self.nodes.len());
+        self.truncated = true;
+        self.nodes.truncate(keep);
+        let ids: HashSet<u32> = self.nodes.iter().map(|node| node.id).collect();
+        self.edges.retain(|edge| ids.contains(&edge.source) && ids.contains(&edge.target));
+        let members: HashSet<&str> = self.nodes.iter().map(|node| node.metadata_id.as_str()).collect();
+        self.metadata.retain(|file_name, _| members.contains(file_name.trim_end_matches(".md")));
+    }
+
+    /// Serializes the response as shaped by the request, dropping nodes from the end
+    /// until it fits in `budget` bytes
+    fn encode_within(&mut self, shape: &ResponseShape, budget: usize) -> serde_json::Result<Vec<u8>> {
+        let mut body = shape.encode(self)?;
+        while body.len() > budget && self.nodes.len() > 1 {
+            let keep = shaping::suggested_page_size(self.nodes.len(), body.len(), budget).min(self.nodes.len() - 1);
+            self.truncate(keep);
+            body = shape.encode(self)?;
+        }
+        Ok(body)
+    }
 }
 
 #[derive(Serialize)]
@@ -31,6 +93,57 @@ pub struct PaginatedGraphResponse {
     pub page_size: usize,
 }
 
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PreviewGraphResponse {
+    pub branch: String,
+    pub nodes: Vec<Node>,
+    pub edges: Vec<crate::models::edge::Edge>,
+    pub metadata: HashMap<String, Metadata>,
+    pub diff: PreviewDiff,
+}
+
+/// File-level differences between a preview branch and the live graph
+#[derive(Serialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct PreviewDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<String>,
+}
+
+impl PreviewDiff {
+    /// Files `next` adds to, removes from or changes in `current`, each sorted by name
+    pub fn between(current: &MetadataStore, next: &MetadataStore) -> Self {
+        let mut diff = PreviewDiff::default();
+        for (name, meta) in next.iter() {
+            match current.get(name) {
+                None => diff.added.push(name.clone()),
+                Some(existing) if existing.sha1 != meta.sha1 => diff.changed.push(name.clone()),
+                Some(_) => {}
+            }
+        }
+        diff.removed = current.keys().filter(|name| !next.contains_key(*name)).cloned().collect();
+        diff.added.sort();
+        diff.changed.sort();
+        diff.removed.sort();
+        diff
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PreviewQuery {
+    pub branch: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct GraphFilterQuery {
+    /// Filter expression to narrow the graph to
+    pub filter: Option<String>,
+    /// Id of a saved filter to narrow the graph to
+    pub filter_id: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct GraphQuery {
     pub query: Option<String>,
@@ -38,26 +151,200 @@ pub struct GraphQuery {
     pub page_size: Option<usize>,
     pub sort: Option<String>,
     pub filter: Option<String>,
+    pub filter_id: Option<String>,
+}
+
+/// Metadata ids the requesting user has bookmarked. They are only looked up for a
+/// valid Nostr session; other requests get the graph without flags.
+async fn request_bookmarks(state: &AppState, req: &HttpRequest) -> Vec<String> {
+    if req.headers().get("Authorization").is_none() {
+        return Vec::new();
+    }
+    match require_session(req, state).await {
+        Ok(pubkey) => UserSettings::load(&pubkey)
+            .map(|user| user.bookmarks.into_iter().map(|bookmark| bookmark.node).collect())
+            .unwrap_or_default(),
+        Err(_) => Vec::new(),
+    }
 }
 
-pub async fn get_graph_data(state: web::Data<AppState>) -> impl Responder {
+/// Metadata ids of the nodes picked by a `filter` expression, a saved `filter_id`
+/// or both, or `None` when neither is given
+async fn requested_members(
+    state: &AppState,
+    graph: &GraphData,
+    filter: Option<&str>,
+    filter_id: Option<&str>,
+) -> Result<Option<HashSet<String>>, HttpResponse> {
+    let mut members = match filter.map(str::trim).filter(|f| !f.is_empty()) {
+        Some(filter) => match FilterExpr::parse(filter) {
+            Ok(expr) => Some(expr.select(graph, Utc::now())),
+            Err(e) => return Err(HttpResponse::BadRequest().json(serde_json::json!({"error": format!("Invalid filter: {}", e)}))),
+        },
+        None => None,
+    };
+    if let Some(filter_id) = filter_id {
+        let Some(saved) = state.saved_filters.select(filter_id, graph).await else {
+            return Err(HttpResponse::NotFound().json(serde_json::json!({"error": format!("Filter {} not found", filter_id)})));
+        };
+        members = Some(match members {
+            Some(members) => members.intersection(&saved).cloned().collect(),
+            None => saved,
+        });
+    }
+    Ok(members)
+}
+
+/// Keeps the nodes whose metadata ids are in `members`, the edges between them and
+/// their file metadata
+fn restrict_to_members(graph: &mut GraphData, members: &HashSet<String>) {
+    graph.nodes.retain(|node| members.contains(&node.metadata_id));
+    let ids: HashSet<u32> = graph.nodes.iter().map(|node| node.id).collect();
+    graph.edges.retain(|edge| ids.contains(&edge.source) && ids.contains(&edge.target));
+    graph.metadata.retain(|file_name, _| members.contains(file_name.trim_end_matches(".md")));
+}
+
+/// Flags bookmarked nodes with `userData.bookmarked = "true"`
+fn mark_bookmarks(nodes: &mut [Node], bookmarks: &[String]) {
+    if bookmarks.is_empty() {
+        return;
+    }
+    let bookmarks: HashSet<&str> = bookmarks.iter().map(String::as_str).collect();
+    for node in nodes.iter_mut().filter(|node| bookmarks.contains(node.metadata_id.as_str())) {
+        node.user_data.get_or_insert_with(HashMap::new).insert("bookmarked".to_string(), "true".to_string());
+    }
+}
+
+/// ETag for the current graph revision, or the 304 response if the client's
+/// `If-None-Match` already names it. The revision is read before the graph data, so
+/// a tag can only ever be older than the data it's sent with, never newer. Bookmark
+/// flags differ per user, so the user's bookmarks are part of the tag.
+async fn graph_etag(state: &AppState, req: &HttpRequest, bookmarks: &[String]) -> Result<Option<EntityTag>, HttpResponse> {
+    let revision = match state.graph_service_addr.send(GetGraphRevision).await {
+        Ok(revision) => revision,
+        Err(e) => {
+            warn!("Could not read graph revision: {}", e);
+            return Ok(None);
+        }
+    };
+    let etag = if bookmarks.is_empty() {
+        EntityTag::new_strong(format!("{}-{}", *ETAG_EPOCH, revision))
+    } else {
+        let mut hasher = DefaultHasher::new();
+        bookmarks.hash(&mut hasher);
+        EntityTag::new_strong(format!("{}-{}-{:x}", *ETAG_EPOCH, revision, hasher.finish()))
+    };
+
+    let unchanged = match IfNoneMatch::parse(req) {
+        Ok(IfNoneMatch::Any) => true,
+        Ok(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(&etag)),
+        Err(_) => false,
+    };
+    if unchanged {
+        debug!("Graph unchanged at revision {}", revision);
+        return Err(HttpResponse::NotModified().insert_header(ETag(etag)).finish());
+    }
+    Ok(Some(etag))
+}
+
+/// 200 response carrying the ETag, asking caches to revalidate rather than serve a
+/// stale graph
+fn graph_response(etag: Option<EntityTag>) -> HttpResponseBuilder {
+    let mut builder = HttpResponse::Ok();
+    if let Some(etag) = etag {
+        builder.insert_header(ETag(etag))
+            .insert_header(CacheControl(vec![CacheDirective::NoCache]));
+    }
+    builder
+}
+
+/// Serializes `response` as shaped by the request. Responses over the size budget are
+/// refused with 413 and a page size that should fit.
+fn shaped_graph_response<T: Serialize>(
+    etag: Option<EntityTag>,
+    shape: &ResponseShape,
+    response: &T,
+    node_count: usize,
+) -> HttpResponse {
+    match shape.encode(response) {
+        Ok(body) => budgeted_graph_response(etag, body, node_count),
+        Err(e) => {
+            error!("Failed to serialize graph response: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({"error": "Failed to serialize graph data"}))
+        }
+    }
+}
+
+/// Sends a serialized graph `body`, or refuses it with 413 if it's over the budget
+fn budgeted_graph_response(etag: Option<EntityTag>, body: Vec<u8>, node_count: usize) -> HttpResponse {
+    if body.len() > *RESPONSE_BUDGET {
+        warn!("Graph response of {} bytes exceeds the {} byte budget", body.len(), *RESPONSE_BUDGET);
+        return HttpResponse::PayloadTooLarge().json(serde_json::json!({
+            "error": "Graph response exceeds the size budget",
+            "size": body.len(),
+            "budget": *RESPONSE_BUDGET,
+            "paginatedEndpoint": "/api/graph/data/paginated",
+            "suggestedPageSize": shaping::suggested_page_size(node_count, body.len(), *RESPONSE_BUDGET),
+            "hint": "Request pages, or reduce the response with fields= or include_metadata=false"
+        }));
+    }
+    graph_response(etag).content_type("application/json").body(body)
+}
+
+pub async fn get_graph_data(
+    state: web::Data<AppState>,
+    req: HttpRequest,
+    shape_query: web::Query<ShapeQuery>,
+    filter_query: web::Query<GraphFilterQuery>,
+) -> impl Responder {
     info!("Received request for graph data");
+    let shape = match ResponseShape::from_query(&shape_query) {
+        Ok(shape) => shape,
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({"error": e})),
+    };
+    let bookmarks = request_bookmarks(&state, &req).await;
+    let etag = match graph_etag(&state, &req, &bookmarks).await {
+        Ok(etag) => etag,
+        Err(not_modified) => return not_modified,
+    };
     let graph_data_result = state.graph_service_addr.send(GetGraphData).await;
 
     match graph_data_result {
-        Ok(Ok(graph_data_owned)) => { // graph_data_owned is now GraphData
+        Ok(Ok(mut graph_data_owned)) => { // graph_data_owned is now GraphData
+            match requested_members(&state, &graph_data_owned, filter_query.filter.as_deref(), filter_query.filter_id.as_deref()).await {
+                Ok(Some(members)) => restrict_to_members(&mut graph_data_owned, &members),
+                Ok(None) => {}
+                Err(resp) => return resp,
+            }
             debug!("Preparing graph response with {} nodes and {} edges",
                 graph_data_owned.nodes.len(),
                 graph_data_owned.edges.len()
             );
  
             // Clone data from the owned GraphData for the response
-            let response = GraphResponse {
+            let mut response = GraphResponse {
                 nodes: graph_data_owned.nodes.clone(),
                 edges: graph_data_owned.edges.clone(),
                 metadata: graph_data_owned.metadata.clone(),
+                truncated: false,
+                total_items: None,
             };
-            HttpResponse::Ok().json(response)
+            mark_bookmarks(&mut response.nodes, &bookmarks);
+            // Clients that don't page still get the start of an oversized graph,
+            // flagged as truncated, rather than nothing
+            match response.encode_within(&shape, *RESPONSE_BUDGET) {
+                Ok(body) => {
+                    if response.truncated {
+                        warn!("Graph response truncated to {} of {} nodes to fit the {} byte budget",
+                            response.nodes.len(), graph_data_owned.nodes.len(), *RESPONSE_BUDGET);
+                    }
+                    budgeted_graph_response(etag, body, response.nodes.len())
+                }
+                Err(e) => {
+                    error!("Failed to serialize graph response: {}", e);
+                    HttpResponse::InternalServerError().json(serde_json::json!({"error": "Failed to serialize graph data"}))
+                }
+            }
         }
         Ok(Err(e)) => {
             error!("Failed to get graph data from actor: {}", e);
@@ -72,9 +359,15 @@ pub async fn get_graph_data(state: web::Data<AppState>) -> impl Responder {
 
 pub async fn get_paginated_graph_data(
     state: web::Data<AppState>,
+    req: HttpRequest,
     query: web::Query<GraphQuery>,
+    shape_query: web::Query<ShapeQuery>,
 ) -> impl Responder {
     info!("Received request for paginated graph data with params: {:?}", query);
+    let shape = match ResponseShape::from_query(&shape_query) {
+        Ok(shape) => shape,
+        Err(e) => return HttpResponse::BadRequest().json(serde_json::json!({"error": e})),
+    };
 
     let page = query.page.map(|p| p.saturating_sub(1)).unwrap_or(0);
     let page_size = query.page_size.unwrap_or(100);
@@ -86,22 +379,34 @@ pub async fn get_paginated_graph_data(
         }));
     }
 
+    let bookmarks = request_bookmarks(&state, &req).await;
+    let etag = match graph_etag(&state, &req, &bookmarks).await {
+        Ok(etag) => etag,
+        Err(not_modified) => return not_modified,
+    };
+
     // This part is complex due to mutable access.
     // For now, let's assume get_graph_data_mut was for reading and we use GetGraphData.
     // If mutable access is truly needed, specific messages for modifications are required.
     let graph_result = state.graph_service_addr.send(GetGraphData).await;
-    let graph_data_owned = match graph_result { // graph_data_owned is GraphData
+    let mut graph_data_owned = match graph_result { // graph_data_owned is GraphData
         Ok(Ok(g_owned)) => g_owned,
         _ => {
             error!("Failed to get graph data for pagination");
             return HttpResponse::InternalServerError().json(serde_json::json!({"error": "Failed to retrieve graph data"}));
         }
     };
+    // Filtering comes before paging, so pages and totals count matching nodes only
+    match requested_members(&state, &graph_data_owned, query.filter.as_deref(), query.filter_id.as_deref()).await {
+        Ok(Some(members)) => restrict_to_members(&mut graph_data_owned, &members),
+        Ok(None) => {}
+        Err(resp) => return resp,
+    }
     let total_items = graph_data_owned.nodes.len();
     
     if total_items == 0 {
         debug!("Graph is empty");
-        return HttpResponse::Ok().json(PaginatedGraphResponse {
+        return graph_response(etag).json(PaginatedGraphResponse {
             nodes: Vec::new(),
             edges: Vec::new(),
             metadata: HashMap::new(),
@@ -126,7 +431,8 @@ pub async fn get_paginated_graph_data(
 
     debug!("Calculating slice from {} to {} out of {} total items", start, end, total_items);
  
-    let page_nodes = graph_data_owned.nodes[start..end].to_vec();
+    let mut page_nodes = graph_data_owned.nodes[start..end].to_vec();
+    mark_bookmarks(&mut page_nodes, &bookmarks);
  
     let node_ids: std::collections::HashSet<_> = page_nodes.iter()
         .map(|node| node.id)
@@ -151,147 +457,581 @@ pub async fn get_paginated_graph_data(
         page_size,
     };
 
-    HttpResponse::Ok().json(response)
+    shaped_graph_response(etag, &shape, &response, response.nodes.len())
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct RefreshQuery {
+    /// Lays the rebuilt graph out from this seed instead of the configured one
+    pub seed: Option<u64>,
 }
 
-pub async fn refresh_graph(state: web::Data<AppState>) -> impl Responder {
+pub async fn refresh_graph(state: web::Data<AppState>, query: web::Query<RefreshQuery>) -> Result<HttpResponse, AppError> {
     info!("Received request to refresh graph");
-    
-    let metadata_result = state.metadata_addr.send(GetMetadata).await;
-    
-    match metadata_result {
-        Ok(Ok(metadata_store)) => {
-            debug!("Building graph from {} metadata entries", metadata_store.len());
-            
-            // Send BuildGraphFromMetadata message to GraphServiceActor
-            match state.graph_service_addr.send(BuildGraphFromMetadata { metadata: metadata_store }).await {
-                Ok(Ok(())) => {
-                    // Optionally, if we need to preserve old positions, that logic would need to be
-                    // part of the GraphServiceActor's BuildGraphFromMetadata handler or a subsequent message.
-                    // For simplicity here, we assume the actor handles the build correctly.
-                    info!("Graph refreshed successfully via GraphServiceActor");
-                    HttpResponse::Ok().json(serde_json::json!({
-                        "success": true,
-                        "message": "Graph refreshed successfully"
-                    }))
-                }
-                Ok(Err(e)) => {
-                    error!("GraphServiceActor failed to build graph from metadata: {}", e);
-                    HttpResponse::InternalServerError().json(serde_json::json!({
-                        "success": false,
-                        "error": format!("Failed to build graph: {}", e)
-                    }))
-                }
-                Err(e) => {
-                    error!("Mailbox error sending BuildGraphFromMetadata: {}", e);
-                    HttpResponse::InternalServerError().json(serde_json::json!({
-                        "success": false,
-                        "error": "Graph service unavailable during refresh"
-                    }))
-                }
+
+    let metadata_store = state.metadata_addr.send(GetMetadata).await?.map_err(AppError::file)?;
+    debug!("Building graph from {} metadata entries", metadata_store.len());
+    state.graph_service_addr.send(BuildGraphFromMetadata { metadata: metadata_store, seed: query.seed }).await?
+        .map_err(AppError::graph)?;
+
+    info!("Graph refreshed successfully via GraphServiceActor");
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "message": "Graph refreshed successfully"
+    })))
+}
+
+pub async fn update_graph(state: web::Data<AppState>) -> Result<HttpResponse, AppError> {
+    info!("Received request to update graph");
+
+    let mut metadata = FileService::load_or_create_metadata().map_err(AppError::file)?;
+    let settings = state.settings_addr.send(GetSettings).await?.map_err(AppError::Internal)?;
+    let settings = Arc::new(tokio::sync::RwLock::new(settings));
+
+    let file_service = FileService::new(settings.clone()).with_event_bus(state.event_bus.clone());
+    let processed_files = file_service.fetch_and_process_files(state.content_api.clone(), settings.clone(), &mut metadata).await
+        .map_err(AppError::file)?;
+    if processed_files.is_empty() {
+        debug!("No new files to process");
+        return Ok(HttpResponse::Ok().json(serde_json::json!({
+            "success": true,
+            "message": "No updates needed"
+        })));
+    }
+
+    debug!("Processing {} new files", processed_files.len());
+    if let Err(e) = state.metadata_addr.send(UpdateMetadata { metadata: metadata.clone() }).await {
+        error!("Failed to send UpdateMetadata to MetadataActor: {}", e);
+    }
+    state.graph_service_addr.send(BuildGraphFromMetadata { metadata, seed: None }).await?
+        .map_err(AppError::graph)?;
+
+    debug!("Graph updated successfully via GraphServiceActor after file processing");
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "success": true,
+        "message": format!("Graph updated with {} new files", processed_files.len())
+    })))
+}
+
+fn is_valid_branch_name(branch: &str) -> bool {
+    !branch.is_empty()
+        && branch.len() <= 255
+        && !branch.starts_with('-')
+        && !branch.starts_with('/')
+        && !branch.contains("..")
+        && !branch.chars().any(|c| c.is_whitespace() || c.is_control() || matches!(c, '~' | '^' | ':' | '?' | '*' | '[' | '\\'))
+}
+
+/// Builds an ephemeral graph from another branch (usually a pending PR) without
+/// touching the live graph, and reports which files it adds, removes or changes.
+pub async fn preview_graph(state: web::Data<AppState>, query: web::Query<PreviewQuery>) -> HttpResponse {
+    let branch = query.branch.trim();
+    if !is_valid_branch_name(branch) {
+        return HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Invalid branch name: {}", query.branch)
+        }));
+    }
+    match build_preview(&state, branch).await {
+        Ok(preview) => HttpResponse::Ok().json(preview),
+        Err(response) => response,
+    }
+}
+
+/// Builds the preview graph for `git_ref` (a branch name or commit SHA). On failure
+/// the error is returned as a ready-to-send response.
+pub async fn build_preview(state: &AppState, git_ref: &str) -> Result<PreviewGraphResponse, HttpResponse> {
+    info!("Building preview graph for '{}'", git_ref);
+
+    let current = match state.metadata_addr.send(GetMetadata).await {
+        Ok(Ok(store)) => store,
+        _ => {
+            error!("Failed to retrieve metadata for preview of '{}'", git_ref);
+            return Err(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": "Failed to retrieve current metadata"
+            })));
+        }
+    };
+
+    let preview = match FileService::build_preview_metadata(state.content_api.as_ref(), Some(git_ref), &current).await {
+        Ok(preview) => preview,
+        Err(e) => {
+            error!("Failed to fetch '{}' for preview: {}", git_ref, e);
+            return Err(HttpResponse::BadGateway().json(serde_json::json!({
+                "error": format!("Failed to fetch '{}': {}", git_ref, e)
+            })));
+        }
+    };
+
+    let graph = match GraphService::build_graph_from_metadata(&preview, None).await {
+        Ok(graph) => graph,
+        Err(e) => {
+            error!("Failed to build preview graph for '{}': {}", git_ref, e);
+            return Err(HttpResponse::InternalServerError().json(serde_json::json!({
+                "error": format!("Failed to build preview graph: {}", e)
+            })));
+        }
+    };
+
+    let diff = PreviewDiff::between(&current, &preview);
+
+    Ok(PreviewGraphResponse {
+        branch: git_ref.to_string(),
+        nodes: graph.nodes,
+        edges: graph.edges,
+        metadata: graph.metadata,
+        diff,
+    })
+}
+
+/// The live graph, with actor failures as `AppError`s
+async fn live_graph(state: &AppState) -> Result<GraphData, AppError> {
+    state.graph_service_addr.send(GetGraphData).await?.map_err(AppError::graph)
+}
+
+/// Orphaned pages, dangling links and duplicate titles in the live graph
+pub async fn get_graph_quality(state: web::Data<AppState>) -> Result<HttpResponse, AppError> {
+    let graph_data = live_graph(&state).await?;
+    let report = graph_quality::analyse(&graph_data);
+    debug!("Graph quality: {} orphans, {} dangling links, {} duplicate titles",
+        report.orphans.len(), report.dangling_links.len(), report.duplicate_titles.len());
+    Ok(HttpResponse::Ok().json(report))
+}
+
+/// Duplicate ids, dangling edges, non-finite positions and massless nodes in the
+/// live graph
+pub async fn get_graph_validation(state: web::Data<AppState>) -> Result<HttpResponse, AppError> {
+    Ok(HttpResponse::Ok().json(graph_validation::validate(&live_graph(&state).await?)))
+}
+
+/// Repairs the live graph; without a body every repair is applied
+pub async fn repair_graph(state: web::Data<AppState>, options: Option<web::Json<RepairOptions>>) -> Result<HttpResponse, AppError> {
+    let options = options.map(|options| options.into_inner()).unwrap_or_default();
+    let report = state.graph_service_addr.send(RepairGraph { options }).await?.map_err(AppError::graph)?;
+    info!("Repaired {} graph issues on request, {} remain", report.repaired.len(), report.validation.issues.len());
+    Ok(HttpResponse::Ok().json(report))
+}
+
+const DUPLICATE_SCAN_JOB_KIND: &str = "duplicate_scan";
+
+/// Near-duplicate pages flagged by the last scan, with their node ids
+pub async fn get_duplicates(state: web::Data<AppState>) -> impl Responder {
+    match state.metadata_addr.send(GetMetadata).await {
+        Ok(Ok(metadata)) => {
+            let node_id = |file_name: &str| metadata.get(file_name).map(|m| m.node_id.clone());
+            let pairs: Vec<serde_json::Value> = duplicates::recorded_pairs(&metadata).into_iter()
+                .map(|pair| serde_json::json!({
+                    "first": pair.first,
+                    "firstNodeId": node_id(&pair.first),
+                    "second": pair.second,
+                    "secondNodeId": node_id(&pair.second),
+                    "similarity": pair.similarity,
+                }))
+                .collect();
+            HttpResponse::Ok().json(serde_json::json!({
+                "threshold": duplicates::threshold_from_env(),
+                "pairs": pairs,
+                "jobId": state.job_queue.active_job_of_kind(DUPLICATE_SCAN_JOB_KIND),
+            }))
+        }
+        _ => {
+            error!("Failed to get metadata for duplicates");
+            HttpResponse::InternalServerError().json(serde_json::json!({"error": "Failed to retrieve metadata"}))
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct DuplicateScanQuery {
+    /// Overrides `DUPLICATE_SIMILARITY_THRESHOLD` for this scan
+    pub threshold: Option<f32>,
+}
+
+/// Rescans every page for near duplicates as a background job. The graph is
+/// rebuilt afterwards when duplicate edges are on, so they reflect the scan.
+pub async fn scan_duplicates(state: web::Data<AppState>, query: web::Query<DuplicateScanQuery>) -> impl Responder {
+    let threshold = query.threshold.filter(|t| (0.0..=1.0).contains(t)).unwrap_or_else(duplicates::threshold_from_env);
+    let job_state = state.clone();
+    let submitted = state.job_queue.submit_unique(DUPLICATE_SCAN_JOB_KIND, move |_ctx| async move {
+        let state = job_state;
+        let mut metadata = match state.metadata_addr.send(GetMetadata).await {
+            Ok(Ok(metadata)) => metadata,
+            _ => return Err("Failed to retrieve metadata".to_string()),
+        };
+        let (metadata, pairs) = web::block(move || {
+            let pairs = duplicates::detect(&mut metadata, threshold);
+            (metadata, pairs)
+        })
+        .await
+        .map_err(|e| format!("Duplicate scan failed: {}", e))?;
+
+        FileService::save_metadata(&metadata).map_err(|e| format!("Failed to save metadata: {}", e))?;
+        if let Err(e) = state.metadata_addr.send(UpdateMetadata { metadata: metadata.clone() }).await {
+            error!("Failed to send scanned metadata to MetadataActor: {}", e);
+        }
+        if GraphBuildOptions::from_env().duplicate_edges {
+            if let Ok(Err(e)) = state.graph_service_addr.send(BuildGraphFromMetadata { metadata, seed: None }).await {
+                warn!("Failed to rebuild graph after duplicate scan: {}", e);
             }
         }
-        Ok(Err(e)) => {
-            error!("Failed to get metadata from MetadataActor: {}", e);
-            HttpResponse::InternalServerError().json(serde_json::json!({
-                "success": false,
-                "error": "Failed to retrieve metadata for graph refresh"
+        Ok(serde_json::json!({ "pairs": pairs.len(), "threshold": threshold }))
+    }.boxed());
+
+    match submitted {
+        Ok(job_id) => HttpResponse::Accepted().json(serde_json::json!({ "status": "started", "jobId": job_id })),
+        Err(existing) => HttpResponse::Conflict().json(serde_json::json!({
+            "status": "error",
+            "message": "A duplicate scan is already in progress",
+            "jobId": existing
+        })),
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct TimelineQuery {
+    #[serde(default)]
+    pub by: TimelineDate,
+    #[serde(default)]
+    pub granularity: Granularity,
+}
+
+/// Pages grouped by date for the chronological view
+pub async fn get_graph_timeline(state: web::Data<AppState>, query: web::Query<TimelineQuery>) -> impl Responder {
+    match state.graph_service_addr.send(GetGraphData).await {
+        Ok(Ok(graph_data)) => {
+            let buckets = timeline::build(&graph_data, query.by, query.granularity);
+            HttpResponse::Ok().json(serde_json::json!({
+                "by": query.by,
+                "granularity": query.granularity,
+                "buckets": buckets,
             }))
         }
+        Ok(Err(e)) => {
+            error!("Failed to get graph data for timeline: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({"error": "Failed to retrieve graph data"}))
+        }
         Err(e) => {
-            error!("Mailbox error getting metadata: {}", e);
-            HttpResponse::InternalServerError().json(serde_json::json!({
-                "success": false,
-                "error": "Metadata service unavailable"
-            }))
+            error!("Mailbox error getting graph data: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({"error": "Graph service unavailable"}))
         }
-// Removed duplicate success block from here
     }
 }
 
-pub async fn update_graph(state: web::Data<AppState>) -> impl Responder {
-    info!("Received request to update graph");
-    
-    let mut metadata = match FileService::load_or_create_metadata() {
-        Ok(m) => m,
+#[derive(Debug, Deserialize)]
+pub struct StatsHistoryQuery {
+    pub since: Option<chrono::DateTime<Utc>>,
+    #[serde(default)]
+    pub granularity: Granularity,
+}
+
+/// Graph size, word count and orphans as recorded at each sync, one sample per bucket
+pub async fn get_stats_history(query: web::Query<StatsHistoryQuery>) -> impl Responder {
+    let StatsHistoryQuery { since, granularity } = query.into_inner();
+    match web::block(move || StatsHistory::load().trend(since, granularity)).await {
+        Ok(samples) => HttpResponse::Ok().json(serde_json::json!({
+            "granularity": granularity,
+            "samples": samples,
+        })),
+        Err(e) => {
+            error!("Failed to read stats history: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({"error": "Failed to read stats history"}))
+        }
+    }
+}
+
+fn default_layout_spacing() -> f32 {
+    2.0
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LayoutQuery {
+    pub algorithm: LayoutAlgorithm,
+    /// Centre node of a radial layout; defaults to the best-connected node
+    pub root: Option<u32>,
+    #[serde(default = "default_layout_spacing")]
+    pub spacing: f32,
+}
+
+/// Target positions from a deterministic layout, for the client to tween towards.
+/// The physics simulation keeps running from wherever the nodes currently are.
+pub async fn get_graph_layout(state: web::Data<AppState>, query: web::Query<LayoutQuery>) -> impl Responder {
+    match state.graph_service_addr.send(GetGraphData).await {
+        Ok(Ok(graph_data)) => match layout::compute(&graph_data, query.algorithm, query.root, query.spacing) {
+            Ok(positions) => HttpResponse::Ok().json(serde_json::json!({
+                "algorithm": query.algorithm,
+                "positions": positions,
+            })),
+            Err(e) => HttpResponse::BadRequest().json(serde_json::json!({"error": e})),
+        },
+        Ok(Err(e)) => {
+            error!("Failed to get graph data for layout: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({"error": "Failed to retrieve graph data"}))
+        }
         Err(e) => {
-            error!("Failed to load metadata: {}", e);
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "success": false,
-                "error": format!("Failed to load metadata: {}", e)
-            }));
+            error!("Mailbox error getting graph data: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({"error": "Graph service unavailable"}))
+        }
+    }
+}
+
+/// A node with the metadata of its page, such as word count, reading time and
+/// heading outline, for tooltips and detail panels
+pub async fn get_node_detail(state: web::Data<AppState>, path: web::Path<u32>) -> Result<HttpResponse, AppError> {
+    let node_id = path.into_inner();
+    let graph_data = live_graph(&state).await?;
+
+    let node = graph_data.nodes.iter().find(|node| node.id == node_id)
+        .ok_or_else(|| AppError::NotFound(format!("Node {} not found", node_id)))?;
+    // Ghost and tag nodes have no page behind them
+    let page = node.metadata.get("fileName").and_then(|file_name| graph_data.metadata.get(file_name));
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "node": node,
+        "page": page,
+    })))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct FocusQuery {
+    /// Vertical field of view of the camera, in degrees
+    pub fov: Option<f32>,
+    /// Set to false to only compute the camera, without sending it to clients
+    pub broadcast: Option<bool>,
+}
+
+/// Suggests a camera framing a node and its neighbours, and sends it to every
+/// connected client as a `focusNode` message so they can fly there together
+pub async fn focus_node(
+    state: web::Data<AppState>,
+    path: web::Path<u32>,
+    query: web::Query<FocusQuery>,
+) -> impl Responder {
+    let node_id = path.into_inner();
+    let graph_data = match state.graph_service_addr.send(GetGraphData).await {
+        Ok(Ok(graph_data)) => graph_data,
+        Ok(Err(e)) => {
+            error!("Failed to get graph data for focus: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({"error": "Failed to retrieve graph data"}));
+        }
+        Err(e) => {
+            error!("Mailbox error getting graph data: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({"error": "Graph service unavailable"}));
         }
     };
-    
-    let settings_result = state.settings_addr.send(GetSettings).await;
-    let settings = match settings_result {
-        Ok(Ok(s)) => Arc::new(tokio::sync::RwLock::new(s)),
+
+    let fov = query.fov.unwrap_or(focus::DEFAULT_FOV_DEGREES);
+    let Some(camera) = focus::suggest(&graph_data, node_id, fov) else {
+        return HttpResponse::NotFound().json(serde_json::json!({"error": format!("Node {} not found", node_id)}));
+    };
+
+    if query.broadcast.unwrap_or(true) {
+        let mut message = serde_json::json!(camera);
+        message["type"] = serde_json::json!("focusNode");
+        state.client_manager_addr.do_send(BroadcastMessage { message: message.to_string() });
+    }
+    HttpResponse::Ok().json(camera)
+}
+
+/// The world bounds in effect and how they are configured
+pub async fn get_world_bounds(state: web::Data<AppState>) -> impl Responder {
+    match state.graph_service_addr.send(GetWorldBounds).await {
+        Ok(bounds) => HttpResponse::Ok().json(bounds),
+        Err(e) => {
+            error!("Mailbox error getting world bounds: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({"error": "Graph service unavailable"}))
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorldBoundsUpdate {
+    pub enabled: Option<bool>,
+    pub size: Option<f32>,
+    pub auto_fit: Option<bool>,
+    pub max_size: Option<f32>,
+}
+
+/// Changes the bounds until the next settings change or restart. Clients are told
+/// to rescale when the half extent or configuration changes.
+pub async fn update_world_bounds(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    update: web::Json<WorldBoundsUpdate>,
+) -> impl Responder {
+    let pubkey = match require_session(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return resp,
+    };
+    if !state.is_power_user(&pubkey) {
+        return HttpResponse::Forbidden().json(serde_json::json!({"error": "Only power users can change the world bounds"}));
+    }
+
+    let mut config = match state.graph_service_addr.send(GetWorldBounds).await {
+        Ok(bounds) => bounds.config,
+        Err(e) => {
+            error!("Mailbox error getting world bounds: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({"error": "Graph service unavailable"}));
+        }
+    };
+    config.enabled = update.enabled.unwrap_or(config.enabled);
+    config.size = update.size.unwrap_or(config.size);
+    config.auto_fit = update.auto_fit.unwrap_or(config.auto_fit);
+    config.max_size = update.max_size.unwrap_or(config.max_size);
+
+    match state.graph_service_addr.send(SetWorldBounds { config }).await {
+        Ok(Ok(bounds)) => {
+            info!("{} set world bounds to {:?}", pubkey, bounds);
+            HttpResponse::Ok().json(bounds)
+        }
+        Ok(Err(e)) => HttpResponse::BadRequest().json(serde_json::json!({"error": e})),
+        Err(e) => {
+            error!("Mailbox error setting world bounds: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({"error": "Graph service unavailable"}))
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct SnapshotQuery {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+}
+
+/// Renders the graph's current positions as a PNG, e.g. for link previews
+pub async fn get_graph_snapshot(state: web::Data<AppState>, query: web::Query<SnapshotQuery>) -> impl Responder {
+    let graph_data = match state.graph_service_addr.send(GetGraphData).await {
+        Ok(Ok(graph_data)) => graph_data,
+        Ok(Err(e)) => {
+            error!("Failed to get graph data for snapshot: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({"error": "Failed to retrieve graph data"}));
+        }
+        Err(e) => {
+            error!("Mailbox error getting graph data: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({"error": "Graph service unavailable"}));
+        }
+    };
+    let style = match state.settings_addr.send(GetSettings).await {
+        Ok(Ok(settings)) => SnapshotStyle::from_settings(&settings.visualisation),
         _ => {
-            error!("Failed to retrieve settings for FileService in update_graph");
-            return HttpResponse::InternalServerError().json(serde_json::json!({
-                "success": false,
-                "error": "Failed to retrieve application settings"
-            }));
+            warn!("Settings unavailable for snapshot, using default colours");
+            SnapshotStyle::default()
         }
     };
-    
-    let file_service = FileService::new(settings.clone());
-    match file_service.fetch_and_process_files(state.content_api.clone(), settings.clone(), &mut metadata).await {
-        Ok(processed_files) => {
-            if processed_files.is_empty() {
-                debug!("No new files to process");
-                return HttpResponse::Ok().json(serde_json::json!({
-                    "success": true,
-                    "message": "No updates needed"
-                }));
-            }
-            
-            debug!("Processing {} new files", processed_files.len());
-            
-            {
-                // Send UpdateMetadata message to MetadataActor
-                if let Err(e) = state.metadata_addr.send(crate::actors::messages::UpdateMetadata { metadata: metadata.clone() }).await {
-                     error!("Failed to send UpdateMetadata to MetadataActor: {}", e);
-                     // Potentially return error if this is critical
-                }
-            }
-            
-            // Send BuildGraphFromMetadata message to GraphServiceActor
-            match state.graph_service_addr.send(BuildGraphFromMetadata { metadata }).await {
-                Ok(Ok(())) => {
-                    // Position preservation logic would need to be handled by the actor or subsequent messages.
-                    debug!("Graph updated successfully via GraphServiceActor after file processing");
-                    HttpResponse::Ok().json(serde_json::json!({
-                        "success": true,
-                        "message": format!("Graph updated with {} new files", processed_files.len())
-                    }))
-                },
-                Ok(Err(e)) => {
-                    error!("GraphServiceActor failed to build graph from metadata: {}", e);
-                    HttpResponse::InternalServerError().json(serde_json::json!({
-                        "success": false,
-                        "error": format!("Failed to build graph: {}", e)
-                    }))
-                },
-                Err(e) => {
-                    error!("Failed to build new graph: {}", e);
-                    HttpResponse::InternalServerError().json(serde_json::json!({
-                        "success": false,
-                        "error": format!("Failed to build new graph: {}", e)
-                    }))
-                }
-            }
-        },
+
+    let width = query.width.unwrap_or(snapshot::DEFAULT_WIDTH);
+    let height = query.height.unwrap_or(snapshot::DEFAULT_HEIGHT);
+    match snapshot::render_png(&graph_data, width, height, &style) {
+        Ok(png) => HttpResponse::Ok()
+            .content_type("image/png")
+            .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+            .body(png),
         Err(e) => {
-            error!("Failed to fetch and process files: {}", e);
-            HttpResponse::InternalServerError().json(serde_json::json!({
-                "success": false,
-                "error": format!("Failed to fetch and process files: {}", e)
-            }))
+            error!("Failed to encode graph snapshot: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({"error": "Failed to render snapshot"}))
         }
     }
 }
 
+/// Exports the graph at its current positions as a binary glTF scene
+pub async fn export_graph_glb(state: web::Data<AppState>) -> impl Responder {
+    let graph_data = match state.graph_service_addr.send(GetGraphData).await {
+        Ok(Ok(graph_data)) => graph_data,
+        Ok(Err(e)) => {
+            error!("Failed to get graph data for export: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({"error": "Failed to retrieve graph data"}));
+        }
+        Err(e) => {
+            error!("Mailbox error getting graph data: {}", e);
+            return HttpResponse::InternalServerError().json(serde_json::json!({"error": "Graph service unavailable"}));
+        }
+    };
+    let style = match state.settings_addr.send(GetSettings).await {
+        Ok(Ok(settings)) => ExportStyle::from_settings(&settings.visualisation),
+        _ => {
+            warn!("Settings unavailable for export, using default style");
+            ExportStyle::default()
+        }
+    };
+
+    HttpResponse::Ok()
+        .content_type("model/gltf-binary")
+        .insert_header(("Content-Disposition", "attachment; filename=\"graph.glb\""))
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .body(gltf_export::export_glb(&graph_data, &style))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct EmbedQuery {
+    /// Shown as the viewer's title
+    pub title: Option<String>,
+    #[serde(flatten)]
+    pub filter: GraphFilterQuery,
+}
+
+/// A zip of the graph at its current positions and a static viewer for it, to
+/// embed a snapshot of the graph somewhere else
+pub async fn export_graph_embed(state: web::Data<AppState>, query: web::Query<EmbedQuery>) -> Result<HttpResponse, AppError> {
+    let mut graph_data = live_graph(&state).await?;
+    match requested_members(&state, &graph_data, query.filter.filter.as_deref(), query.filter.filter_id.as_deref()).await {
+        Ok(Some(members)) => restrict_to_members(&mut graph_data, &members),
+        Ok(None) => {}
+        Err(response) => return Ok(response),
+    }
+    let visualisation = match state.settings_addr.send(GetSettings).await {
+        Ok(Ok(settings)) => settings.visualisation,
+        _ => {
+            warn!("Settings unavailable for embed export, using default style");
+            Default::default()
+        }
+    };
+    let viewer = ViewerConfig::from_settings(&visualisation, query.title.as_deref());
+
+    let bundle = web::block(move || embed_export::export_bundle(&graph_data, &viewer)).await
+        .map_err(|e| AppError::Internal(e.to_string()))?
+        .map_err(|e| AppError::Internal(format!("Failed to build embed bundle: {}", e)))?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/zip")
+        .insert_header(("Content-Disposition", "attachment; filename=\"graph-embed.zip\""))
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .body(bundle))
+}
+
+async fn read_recording() -> Result<Vec<replay::RecordedFrame>, HttpResponse> {
+    let dir = DataDirs::global().replays();
+    match web::block(move || replay::read_recording(&dir)).await {
+        Ok(Ok(frames)) => Ok(frames),
+        Ok(Err(e)) => {
+            error!("Failed to read replay recording: {}", e);
+            Err(HttpResponse::InternalServerError().json(serde_json::json!({"error": "Failed to read recording"})))
+        }
+        Err(e) => {
+            error!("Replay read task failed: {}", e);
+            Err(HttpResponse::InternalServerError().json(serde_json::json!({"error": "Failed to read recording"})))
+        }
+    }
+}
+
+/// Describes the recorded position frames, if any
+pub async fn get_replay_summary() -> impl Responder {
+    match read_recording().await {
+        Ok(frames) => HttpResponse::Ok().json(ReplaySummary::new(&frames)),
+        Err(response) => response,
+    }
+}
+
+/// Downloads the recorded position frames, each a u64 timestamp and u32 length
+/// followed by its wire records
+pub async fn download_replay() -> impl Responder {
+    match read_recording().await {
+        Ok(frames) if frames.is_empty() => HttpResponse::NotFound().json(serde_json::json!({"error": "Nothing has been recorded"})),
+        Ok(frames) => HttpResponse::Ok()
+            .content_type("application/octet-stream")
+            .insert_header(("Content-Disposition", "attachment; filename=\"replay.bin\""))
+            .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+            .body(replay::encode_recording(&frames)),
+        Err(response) => response,
+    }
+}
+
 // Configure routes using snake_case
 pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(
@@ -299,8 +1039,62 @@ pub fn config(cfg: &mut web::ServiceConfig) {
             // Match client's endpoint pattern exactly
             .route("/data", web::get().to(get_graph_data))
             .route("/data/paginated", web::get().to(get_paginated_graph_data))
+            .route("/quality", web::get().to(get_graph_quality))
+            .route("/validation", web::get().to(get_graph_validation))
+            .route("/validation/repair", web::post().to(repair_graph))
+            .route("/duplicates", web::get().to(get_duplicates))
+            .route("/duplicates/scan", web::post().to(scan_duplicates))
+            .route("/timeline", web::get().to(get_graph_timeline))
+            .route("/stats/history", web::get().to(get_stats_history))
+            .route("/layout", web::get().to(get_graph_layout))
+            .route("/nodes/{node_id}", web::get().to(get_node_detail))
+            .route("/focus/{node_id}", web::post().to(focus_node))
+            .route("/bounds", web::get().to(get_world_bounds))
+            .route("/bounds", web::put().to(update_world_bounds))
+            .route("/snapshot.png", web::get().to(get_graph_snapshot))
+            .route("/export.glb", web::get().to(export_graph_glb))
+            .route("/export/embed.zip", web::get().to(export_graph_embed))
+            .route("/replay", web::get().to(get_replay_summary))
+            .route("/replay.bin", web::get().to(download_replay))
             .route("/update", web::post().to(update_graph))
             // Keep refresh endpoint for admin/maintenance
             .route("/refresh", web::post().to(refresh_graph))
+            .route("/preview", web::get().to(preview_graph))
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::edge::Edge;
+
+    #[test]
+    fn test_oversized_graph_is_truncated() {
+        let nodes: Vec<Node> = (1..=20).map(|id| Node::new_with_id(format!("Page {}", id), Some(id))).collect();
+        let mut response = GraphResponse {
+            edges: (1..20).map(|id| Edge::new(id, id + 1, 1.0)).collect(),
+            metadata: nodes.iter()
+                .map(|node| (format!("{}.md", node.metadata_id), Metadata::default()))
+                .collect(),
+            nodes,
+            truncated: false,
+            total_items: None,
+        };
+        let shape = ResponseShape::from_query(&ShapeQuery::default()).unwrap();
+        let full = shape.encode(&response).unwrap().len();
+
+        let body = response.encode_within(&shape, full / 2).unwrap();
+        assert!(body.len() <= full / 2);
+        assert!(response.truncated);
+        assert_eq!(response.total_items, Some(20));
+        let kept = response.nodes.len();
+        assert!(kept > 0 && kept < 20);
+        assert!(response.nodes.iter().map(|node| node.id).eq(1..=kept as u32));
+        assert_eq!(response.edges.len(), kept - 1);
+        assert_eq!(response.metadata.len(), kept);
+
+        let value: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(value["truncated"], true);
+        assert_eq!(value["totalItems"], 20);
+    }
+}
@@ -0,0 +1,149 @@
+//! Response shaping and size budgets for the graph endpoints
+//!
+//! Most of a graph response is per-node metadata that a renderer doesn't need.
+//! `fields=id,label,data` keeps only the listed node fields and
+//! `include_metadata=false` drops node, edge and file metadata. Whatever remains is
+//! checked against `GRAPH_RESPONSE_BUDGET_BYTES` (default 32 MiB, uncompressed).
+//! An oversized full graph is cut down to its first nodes and flagged as truncated;
+//! an oversized page is refused with a page size that should fit.
+
+use log::warn;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::env;
+
+const DEFAULT_RESPONSE_BUDGET: usize = 32 * 1024 * 1024;
+
+// Serialized names of `Node` fields; `id` is always included
+const NODE_FIELDS: [&str; 11] = [
+    "id", "metadataId", "label", "data", "metadata", "type", "size", "color", "weight", "group", "userData",
+];
+
+pub static RESPONSE_BUDGET: Lazy<usize> = Lazy::new(|| {
+    match env::var("GRAPH_RESPONSE_BUDGET_BYTES") {
+        Ok(value) => value.trim().parse().unwrap_or_else(|_| {
+            warn!("Invalid GRAPH_RESPONSE_BUDGET_BYTES '{}', using {}", value, DEFAULT_RESPONSE_BUDGET);
+            DEFAULT_RESPONSE_BUDGET
+        }),
+        Err(_) => DEFAULT_RESPONSE_BUDGET,
+    }
+});
+
+#[derive(Debug, Default, Deserialize)]
+pub struct ShapeQuery {
+    /// Comma-separated node fields to return
+    pub fields: Option<String>,
+    pub include_metadata: Option<bool>,
+}
+
+#[derive(Debug)]
+pub struct ResponseShape {
+    fields: Option<HashSet<String>>,
+    include_metadata: bool,
+}
+
+impl ResponseShape {
+    pub fn from_query(query: &ShapeQuery) -> Result<Self, String> {
+        let fields = match &query.fields {
+            Some(list) => {
+                let mut fields = HashSet::from(["id".to_string()]);
+                for field in list.split(',').map(str::trim).filter(|f| !f.is_empty()) {
+                    if !NODE_FIELDS.contains(&field) {
+                        return Err(format!("Unknown node field '{}'; expected one of {}", field, NODE_FIELDS.join(", ")));
+                    }
+                    fields.insert(field.to_string());
+                }
+                Some(fields)
+            }
+            None => None,
+        };
+        Ok(Self { fields, include_metadata: query.include_metadata.unwrap_or(true) })
+    }
+
+    fn is_full(&self) -> bool {
+        self.fields.is_none() && self.include_metadata
+    }
+
+    /// Serializes a graph response with `nodes`, `edges` and `metadata` members,
+    /// shaped as requested
+    pub fn encode<T: Serialize>(&self, response: &T) -> serde_json::Result<Vec<u8>> {
+        if self.is_full() {
+            return serde_json::to_vec(response);
+        }
+        let mut value = serde_json::to_value(response)?;
+        self.shape(&mut value);
+        serde_json::to_vec(&value)
+    }
+
+    fn shape(&self, response: &mut Value) {
+        let Some(object) = response.as_object_mut() else {
+            return;
+        };
+        if !self.include_metadata {
+            object.remove("metadata");
+        }
+        if let Some(Value::Array(nodes)) = object.get_mut("nodes") {
+            for node in nodes.iter_mut().filter_map(Value::as_object_mut) {
+                if let Some(fields) = &self.fields {
+                    node.retain(|key, _| fields.contains(key));
+                }
+                if !self.include_metadata {
+                    node.remove("metadata");
+                }
+            }
+        }
+        if !self.include_metadata {
+            if let Some(Value::Array(edges)) = object.get_mut("edges") {
+                for edge in edges.iter_mut().filter_map(Value::as_object_mut) {
+                    edge.remove("metadata");
+                }
+            }
+        }
+    }
+}
+
+/// Page size expected to keep a response of `node_count` nodes, which serialized to
+/// `size` bytes, within `budget`. Leaves some headroom since nodes vary in size.
+pub fn suggested_page_size(node_count: usize, size: usize, budget: usize) -> usize {
+    let per_node = size / node_count.max(1) + 1;
+    (budget * 4 / 5 / per_node).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn graph() -> Value {
+        json!({
+            "nodes": [{"id": 1, "label": "a", "data": {}, "metadata": {"sha1": "x"}, "color": "#fff"}],
+            "edges": [{"id": "1_2", "source": 1, "target": 2, "metadata": {"k": "v"}}],
+            "metadata": {"a.md": {}}
+        })
+    }
+
+    #[test]
+    fn test_fields_and_metadata_shaping() {
+        let shape = ResponseShape::from_query(&ShapeQuery {
+            fields: Some("label, metadata".to_string()),
+            include_metadata: Some(false),
+        }).unwrap();
+        let mut value = graph();
+        shape.shape(&mut value);
+        assert_eq!(value, json!({
+            "nodes": [{"id": 1, "label": "a"}],
+            "edges": [{"id": "1_2", "source": 1, "target": 2}]
+        }));
+
+        let unshaped = ResponseShape::from_query(&ShapeQuery::default()).unwrap();
+        assert_eq!(unshaped.encode(&graph()).unwrap(), serde_json::to_vec(&graph()).unwrap());
+    }
+
+    #[test]
+    fn test_rejects_unknown_fields() {
+        let query = ShapeQuery { fields: Some("id,position".to_string()), include_metadata: None };
+        assert!(ResponseShape::from_query(&query).is_err());
+    }
+}
@@ -30,5 +30,27 @@ pub fn config(cfg: &mut web::ServiceConfig) {
             .configure(crate::handlers::nostr_handler::config)
             .configure(crate::handlers::settings_handler::config)
             .configure(crate::handlers::ragflow_handler::config) // Add this line
+            .configure(crate::handlers::webhook_handler::config)
+            .configure(crate::handlers::view_link_handler::config)
+            .configure(crate::handlers::share_handler::config)
+            .configure(crate::handlers::speech_handler::config)
+            .configure(crate::handlers::job_handler::config)
+            .configure(crate::handlers::storage_handler::config)
+            .configure(crate::handlers::label_atlas_handler::config)
+            .configure(crate::handlers::activity_handler::config)
+            .configure(crate::handlers::comment_handler::config)
+            .configure(crate::handlers::bookmark_handler::config)
+            .configure(crate::handlers::visit_handler::config)
+            .configure(crate::handlers::saved_filter_handler::config)
+            .configure(crate::handlers::pr_handler::config)
+            .configure(crate::handlers::tenant_handler::config)
+            .configure(crate::handlers::github_auth_handler::config)
+            .configure(crate::handlers::telemetry_handler::config)
+            .configure(crate::handlers::journal_handler::config)
+            .configure(crate::handlers::agenda_handler::config)
+            .configure(crate::handlers::recommendation_handler::config)
+            .configure(crate::handlers::demo_handler::config)
+            .configure(crate::handlers::ai_usage_handler::config)
+            .configure(crate::handlers::conversation_handler::config)
     );
 }
@@ -2,7 +2,7 @@ use crate::config::{Settings, SystemSettings, ClientWebSocketSettings};
 use crate::AppState;
 use crate::actors::messages::{GetSettings, UpdateSettings};
 use actix_web::{error::ErrorInternalServerError, web, Error, HttpResponse, Result};
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
@@ -287,6 +287,9 @@ pub async fn get_setting(
         openai: settings.openai.clone(),
         kokoro: settings.kokoro.clone(),
         whisper: settings.whisper.clone(),
+        elevenlabs: settings.elevenlabs.clone(),
+        sonata: settings.sonata.clone(),
+        audio_cache: settings.audio_cache.clone(),
     };
 
     match get_setting_value(&converted_settings, &category, &setting) {
@@ -371,6 +374,9 @@ pub async fn update_setting(
         openai: settings.openai.clone(),
         kokoro: settings.kokoro.clone(),
         whisper: settings.whisper.clone(),
+        elevenlabs: settings.elevenlabs.clone(),
+        sonata: settings.sonata.clone(),
+        audio_cache: settings.audio_cache.clone(),
     };
 
     match update_setting_value(&mut converted_settings, &category, &setting, &value) {
@@ -407,14 +413,14 @@ pub async fn update_setting(
                         error: None,
                     })
                 }
-                Ok(Err(e)) => {
-                    error!("Failed to update settings: {}", e);
-                    HttpResponse::InternalServerError().json(SettingResponse {
+                Ok(Err(errors)) => {
+                    warn!("Rejected setting {}.{}: {}", category, setting, errors);
+                    HttpResponse::BadRequest().json(SettingResponse {
                         category,
                         setting,
                         value: value.into_inner(),
                         success: false,
-                        error: Some("Failed to update settings".to_string()),
+                        error: Some(errors.to_string()),
                     })
                 }
                 Err(e) => {
@@ -492,6 +498,9 @@ pub async fn get_category_settings(
         openai: settings.openai.clone(),
         kokoro: settings.kokoro.clone(),
         whisper: settings.whisper.clone(),
+        elevenlabs: settings.elevenlabs.clone(),
+        sonata: settings.sonata.clone(),
+        audio_cache: settings.audio_cache.clone(),
     };
 
     let _settings_value = serde_json::to_value(&converted_settings)
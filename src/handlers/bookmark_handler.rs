@@ -0,0 +1,99 @@
+use crate::actors::messages::GetMetadata;
+use crate::app_state::AppState;
+use crate::utils::auth::require_session;
+use crate::models::user_settings::UserSettings;
+use crate::models::UISettings;
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use log::{debug, error};
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Debug, Deserialize)]
+pub struct NewBookmark {
+    pub node: String,
+}
+
+fn load_or_default(pubkey: &str) -> UserSettings {
+    UserSettings::load(pubkey).unwrap_or_else(|| UserSettings::new(pubkey, UISettings::default()))
+}
+
+fn save(user_settings: &mut UserSettings) -> Result<(), HttpResponse> {
+    user_settings.last_modified = Utc::now().timestamp();
+    user_settings.save().map_err(|e| {
+        error!("Failed to save bookmarks for {}: {}", user_settings.pubkey, e);
+        HttpResponse::InternalServerError().json(json!({"error": "Failed to save bookmarks"}))
+    })
+}
+
+async fn list_bookmarks(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
+    let pubkey = match require_session(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return resp,
+    };
+    let bookmarks = UserSettings::load(&pubkey).map(|user| user.bookmarks).unwrap_or_default();
+    HttpResponse::Ok().json(json!({ "bookmarks": bookmarks }))
+}
+
+/// Bookmarks a node of the live graph; bookmarking it again is a no-op
+async fn add_bookmark(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    body: web::Json<NewBookmark>,
+) -> HttpResponse {
+    let pubkey = match require_session(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return resp,
+    };
+    let node = body.into_inner().node;
+
+    match state.metadata_addr.send(GetMetadata).await {
+        Ok(Ok(metadata)) if metadata.contains_key(&format!("{}.md", node)) => {}
+        Ok(Ok(_)) => return HttpResponse::NotFound().json(json!({"error": format!("Node {} not found", node)})),
+        _ => {
+            error!("Failed to get metadata to check bookmark of {}", node);
+            return HttpResponse::InternalServerError().json(json!({"error": "Failed to look up node"}));
+        }
+    }
+
+    let mut user_settings = load_or_default(&pubkey);
+    match user_settings.add_bookmark(&node) {
+        Ok(true) => {
+            if let Err(resp) = save(&mut user_settings) {
+                return resp;
+            }
+            debug!("User {} bookmarked {}", pubkey, node);
+            HttpResponse::Created().json(json!({ "bookmarks": user_settings.bookmarks }))
+        }
+        Ok(false) => HttpResponse::Ok().json(json!({ "bookmarks": user_settings.bookmarks })),
+        Err(e) => HttpResponse::UnprocessableEntity().json(json!({ "error": e })),
+    }
+}
+
+async fn remove_bookmark(req: HttpRequest, state: web::Data<AppState>, path: web::Path<String>) -> HttpResponse {
+    let pubkey = match require_session(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return resp,
+    };
+    let Some(mut user_settings) = UserSettings::load(&pubkey) else {
+        return HttpResponse::NoContent().finish();
+    };
+    if user_settings.remove_bookmark(&path) {
+        if let Err(resp) = save(&mut user_settings) {
+            return resp;
+        }
+        debug!("User {} removed the bookmark on {}", pubkey, path);
+    }
+    HttpResponse::NoContent().finish()
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/users/me/bookmarks")
+            .route(web::get().to(list_bookmarks))
+            .route(web::post().to(add_bookmark))
+    ).service(
+        web::resource("/users/me/bookmarks/{node}")
+            .route(web::delete().to(remove_bookmark))
+    );
+}
@@ -0,0 +1,127 @@
+use crate::actors::messages::GetMetadata;
+use crate::app_state::AppState;
+use crate::utils::auth::require_session;
+use crate::services::comments::CommentError;
+use actix_web::{web, HttpRequest, HttpResponse};
+use log::error;
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Debug, Deserialize)]
+pub struct CommentQuery {
+    pub node: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewComment {
+    pub node: String,
+    pub body: String,
+    pub parent_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CommentEdit {
+    pub body: String,
+}
+
+fn error_response(e: CommentError) -> HttpResponse {
+    let body = json!({ "error": e.to_string() });
+    match e {
+        CommentError::Invalid(_) => HttpResponse::BadRequest().json(body),
+        CommentError::NotFound(_) => HttpResponse::NotFound().json(body),
+        CommentError::Forbidden(_) => HttpResponse::Forbidden().json(body),
+        CommentError::Storage(_) => {
+            error!("Comment storage error: {}", body["error"]);
+            HttpResponse::InternalServerError().json(body)
+        }
+    }
+}
+
+/// The thread of a node, oldest first; anyone can read it
+async fn list_comments(state: web::Data<AppState>, query: web::Query<CommentQuery>) -> HttpResponse {
+    HttpResponse::Ok().json(json!({
+        "node": query.node,
+        "comments": state.comments.list(&query.node).await,
+    }))
+}
+
+/// Live comment counts per node, so the client can badge discussed nodes
+async fn comment_counts(state: web::Data<AppState>) -> HttpResponse {
+    HttpResponse::Ok().json(json!({ "counts": state.comments.counts().await }))
+}
+
+async fn add_comment(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    body: web::Json<NewComment>,
+) -> HttpResponse {
+    let pubkey = match require_session(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return resp,
+    };
+    let body = body.into_inner();
+
+    // Comments hang off pages that exist, not arbitrary ids
+    match state.metadata_addr.send(GetMetadata).await {
+        Ok(Ok(metadata)) if metadata.contains_key(&format!("{}.md", body.node)) => {}
+        Ok(Ok(_)) => return error_response(CommentError::NotFound(format!("Node {} not found", body.node))),
+        Ok(Err(e)) => {
+            error!("Failed to get metadata for comment: {}", e);
+            return HttpResponse::InternalServerError().json(json!({"error": "Failed to look up node"}));
+        }
+        Err(e) => {
+            error!("Metadata actor mailbox error: {}", e);
+            return HttpResponse::InternalServerError().json(json!({"error": "Failed to look up node"}));
+        }
+    }
+
+    match state.comments.add(&body.node, body.parent_id, &pubkey, &body.body).await {
+        Ok(comment) => HttpResponse::Created().json(json!({ "comment": comment })),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn edit_comment(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    path: web::Path<String>,
+    body: web::Json<CommentEdit>,
+) -> HttpResponse {
+    let pubkey = match require_session(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return resp,
+    };
+    match state.comments.edit(&path.into_inner(), &pubkey, &body.body).await {
+        Ok(comment) => HttpResponse::Ok().json(json!({ "comment": comment })),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Authors delete their own comments; power users can delete any
+async fn delete_comment(req: HttpRequest, state: web::Data<AppState>, path: web::Path<String>) -> HttpResponse {
+    let pubkey = match require_session(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return resp,
+    };
+    let moderator = state.is_power_user(&pubkey);
+    match state.comments.delete(&path.into_inner(), &pubkey, moderator).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => error_response(e),
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/comments")
+            .route(web::get().to(list_comments))
+            .route(web::post().to(add_comment))
+    ).service(
+        web::resource("/comments/counts")
+            .route(web::get().to(comment_counts))
+    ).service(
+        web::resource("/comments/{id}")
+            .route(web::patch().to(edit_comment))
+            .route(web::delete().to(delete_comment))
+    );
+}
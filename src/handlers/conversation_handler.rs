@@ -0,0 +1,87 @@
+use crate::app_state::AppState;
+use crate::utils::auth::require_session;
+use crate::services::conversations::ConversationError;
+use actix_web::{web, HttpRequest, HttpResponse};
+use log::error;
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Debug, Default, Deserialize)]
+pub struct NewConversation {
+    pub title: Option<String>,
+}
+
+pub fn conversation_error(e: ConversationError) -> HttpResponse {
+    let body = json!({ "error": e.to_string() });
+    match e {
+        ConversationError::NotFound(_) => HttpResponse::NotFound().json(body),
+        ConversationError::LimitReached => HttpResponse::UnprocessableEntity().json(body),
+        ConversationError::Storage(_) => {
+            error!("Conversation storage error: {}", body["error"]);
+            HttpResponse::InternalServerError().json(body)
+        }
+    }
+}
+
+async fn list_conversations(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
+    let pubkey = match require_session(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return resp,
+    };
+    match state.conversations.list(&pubkey).await {
+        Ok(conversations) => HttpResponse::Ok().json(json!({ "conversations": conversations })),
+        Err(e) => conversation_error(e),
+    }
+}
+
+/// Starts an empty conversation to chat in with `conversationId`
+async fn create_conversation(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    body: Option<web::Json<NewConversation>>,
+) -> HttpResponse {
+    let pubkey = match require_session(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return resp,
+    };
+    let title = body.and_then(|body| body.into_inner().title);
+    match state.conversations.create(&pubkey, title.as_deref()).await {
+        Ok(conversation) => HttpResponse::Created().json(conversation),
+        Err(e) => conversation_error(e),
+    }
+}
+
+/// A conversation with its messages and their citations
+async fn get_conversation(req: HttpRequest, state: web::Data<AppState>, path: web::Path<String>) -> HttpResponse {
+    let pubkey = match require_session(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return resp,
+    };
+    match state.conversations.get(&pubkey, &path.into_inner()).await {
+        Ok(conversation) => HttpResponse::Ok().json(conversation),
+        Err(e) => conversation_error(e),
+    }
+}
+
+async fn delete_conversation(req: HttpRequest, state: web::Data<AppState>, path: web::Path<String>) -> HttpResponse {
+    let pubkey = match require_session(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return resp,
+    };
+    match state.conversations.delete(&pubkey, &path.into_inner()).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => conversation_error(e),
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/conversations")
+            .route(web::get().to(list_conversations))
+            .route(web::post().to(create_conversation))
+    ).service(
+        web::resource("/conversations/{id}")
+            .route(web::get().to(get_conversation))
+            .route(web::delete().to(delete_conversation))
+    );
+}
@@ -0,0 +1,54 @@
+use crate::actors::messages::{BuildGraphFromMetadata, GetGraphData, UpdateMetadata};
+use crate::app_state::AppState;
+use crate::errors::AppError;
+use crate::services::demo::{self, DemoError};
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use log::info;
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Debug, Deserialize)]
+pub struct RegenerateQuery {
+    /// Pages to generate, `DEMO_NODES` when not given
+    pub nodes: Option<usize>,
+    /// Same seed, same graph; random when not given
+    pub seed: Option<u64>,
+}
+
+/// Replaces the demo vault with a freshly generated one
+async fn regenerate(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    query: web::Query<RegenerateQuery>,
+) -> Result<HttpResponse, AppError> {
+    // Behind nginx the peer is the proxy, so prefer the forwarded address
+    let client = req.connection_info().realip_remote_addr().unwrap_or("unknown").to_string();
+    let nodes = match state.demo.allow_regenerate(&client, query.nodes) {
+        Ok(nodes) => nodes,
+        Err(e @ DemoError::Disabled) => return Err(AppError::NotFound(e.to_string())),
+        Err(e @ DemoError::Invalid(_)) => return Err(AppError::BadRequest(e.to_string())),
+        Err(e @ DemoError::RateLimited(seconds)) => return Ok(HttpResponse::TooManyRequests()
+            .insert_header(("Retry-After", seconds.to_string()))
+            .json(json!({ "error": e.to_string() }))),
+    };
+    let seed = query.seed.unwrap_or_else(rand::random);
+
+    let metadata = web::block(move || demo::generate(nodes, seed, Utc::now()))
+        .await
+        .map_err(|e| AppError::Internal(format!("Generating the demo graph failed: {}", e)))?;
+    state.metadata_addr.send(UpdateMetadata { metadata: metadata.clone() }).await?.map_err(AppError::file)?;
+    state.graph_service_addr.send(BuildGraphFromMetadata { metadata, seed: Some(seed) }).await?.map_err(AppError::graph)?;
+    let graph = state.graph_service_addr.send(GetGraphData).await?.map_err(AppError::graph)?;
+
+    info!("Regenerated the demo graph with {} nodes and {} edges for {}", graph.nodes.len(), graph.edges.len(), client);
+    Ok(HttpResponse::Ok().json(json!({
+        "seed": seed,
+        "nodes": graph.nodes.len(),
+        "edges": graph.edges.len(),
+    })))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.route("/demo/regenerate", web::post().to(regenerate));
+}
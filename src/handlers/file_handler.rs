@@ -3,7 +3,8 @@ use serde_json::json;
 use log::{info, debug, error};
 
 use crate::AppState;
-use crate::services::file_service::{FileService, MARKDOWN_DIR};
+use crate::config::data_dirs::DataDirs;
+use crate::services::file_service::FileService;
 use crate::services::graph_service::GraphService;
 
 pub async fn fetch_and_process_files(state: web::Data<AppState>) -> HttpResponse {
@@ -94,8 +95,8 @@ pub async fn fetch_and_process_files(state: web::Data<AppState>) -> HttpResponse
 
 pub async fn get_file_content(_state: web::Data<AppState>, file_name: web::Path<String>) -> HttpResponse {
     // Read file directly from disk
-    let file_path = format!("{}/{}", MARKDOWN_DIR, file_name);
-    match std::fs::read_to_string(&file_path) {
+    let file_path = DataDirs::global().markdown_file(file_name.as_str());
+    match crate::services::vault_crypto::read_to_string(&file_path) {
         Ok(content) => HttpResponse::Ok().body(content),
         Err(e) => {
             error!("Failed to read file {}: {}", file_name, e);
@@ -123,7 +124,7 @@ pub async fn refresh_graph(state: web::Data<AppState>) -> HttpResponse {
     };
 
     // Build graph directly from metadata
-    match GraphService::build_graph_from_metadata(&metadata_store).await {
+    match GraphService::build_graph_from_metadata(&metadata_store, None).await {
         Ok(graph_data) => {
             let mut graph = state.graph_service.graph_data.write().await;
             *graph = graph_data.clone();
@@ -168,7 +169,7 @@ pub async fn update_graph(state: web::Data<AppState>) -> Result<HttpResponse, Ac
     };
 
     // Build graph directly from metadata
-    match GraphService::build_graph_from_metadata(&metadata_store).await {
+    match GraphService::build_graph_from_metadata(&metadata_store, None).await {
         Ok(graph) => {
             // Update graph data
             *state.graph_service.graph_data.write().await = graph.clone();
@@ -0,0 +1,131 @@
+use crate::actors::protected_settings_actor::{RemoveGitHubConnection, StoreGitHubConnection};
+use crate::app_state::AppState;
+use crate::utils::auth::require_session;
+use crate::models::protected_settings::GitHubConnection;
+use crate::services::github::{DevicePoll, GitHubOAuth};
+use crate::services::vault_crypto::{VaultCipher, MISSING_KEY};
+use actix_web::{web, HttpRequest, HttpResponse};
+use log::{error, info};
+use serde_json::json;
+use std::sync::Arc;
+
+fn oauth(state: &AppState) -> Result<&Arc<GitHubOAuth>, HttpResponse> {
+    state.github_oauth.as_ref().ok_or_else(|| {
+        HttpResponse::NotFound().json(json!({"error": "GitHub OAuth is not configured"}))
+    })
+}
+
+async fn get_connection(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
+    let pubkey = match require_session(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return resp,
+    };
+    let connection = state.get_github_connection(&pubkey).await;
+    HttpResponse::Ok().json(json!({
+        "enabled": state.github_oauth.is_some(),
+        "connected": connection.is_some(),
+        "login": connection.as_ref().and_then(|c| c.login.clone()),
+        "scopes": connection.as_ref().map(|c| c.scopes.clone()).unwrap_or_default(),
+        "connectedAt": connection.as_ref().map(|c| c.connected_at),
+    }))
+}
+
+/// Starts the device flow; the user enters `userCode` at `verificationUri`
+async fn start_device_flow(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
+    let pubkey = match require_session(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return resp,
+    };
+    let oauth = match oauth(&state) {
+        Ok(oauth) => oauth,
+        Err(resp) => return resp,
+    };
+    // Don't have the user authorize a token that can't be stored
+    if !VaultCipher::global().is_ok_and(|cipher| cipher.is_enabled()) {
+        return HttpResponse::ServiceUnavailable().json(json!({"error": MISSING_KEY}));
+    }
+    match oauth.start_device_flow(&pubkey).await {
+        Ok(authorization) => HttpResponse::Ok().json(authorization),
+        Err(e) => {
+            error!("Failed to start GitHub device flow: {}", e);
+            HttpResponse::BadGateway().json(json!({"error": e}))
+        }
+    }
+}
+
+/// Checks the pending flow once, storing the token when the user has authorized it
+async fn poll_device_flow(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
+    let pubkey = match require_session(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return resp,
+    };
+    let oauth = match oauth(&state) {
+        Ok(oauth) => oauth,
+        Err(resp) => return resp,
+    };
+    let (token, scopes) = match oauth.poll(&pubkey).await {
+        Ok(DevicePoll::Authorized { token, scopes }) => (token, scopes),
+        Ok(DevicePoll::Pending { interval }) => {
+            return HttpResponse::Ok().json(json!({"status": "pending", "interval": interval}));
+        }
+        Ok(DevicePoll::Expired) => return HttpResponse::Ok().json(json!({"status": "expired"})),
+        Ok(DevicePoll::Denied) => return HttpResponse::Ok().json(json!({"status": "denied"})),
+        Err(e) => return HttpResponse::BadRequest().json(json!({"error": e})),
+    };
+
+    let login = oauth.login_for(&token).await;
+    let connection = match GitHubConnection::new(&token, login.clone(), scopes.clone()) {
+        Ok(connection) => connection,
+        Err(e) => {
+            error!("{}", e);
+            return HttpResponse::InternalServerError().json(json!({"error": e}));
+        }
+    };
+    match state.protected_settings_addr.send(StoreGitHubConnection { pubkey: pubkey.clone(), connection }).await {
+        Ok(Ok(())) => {
+            info!("Connected GitHub account {:?} for {}", login, pubkey);
+            HttpResponse::Ok().json(json!({"status": "connected", "login": login, "scopes": scopes}))
+        }
+        Ok(Err(e)) => {
+            error!("Failed to store GitHub connection: {}", e);
+            HttpResponse::InternalServerError().json(json!({"error": e}))
+        }
+        Err(e) => {
+            error!("Protected settings unavailable: {}", e);
+            HttpResponse::InternalServerError().json(json!({"error": "Protected settings unavailable"}))
+        }
+    }
+}
+
+async fn disconnect(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
+    let pubkey = match require_session(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return resp,
+    };
+    match state.protected_settings_addr.send(RemoveGitHubConnection { pubkey }).await {
+        Ok(Ok(true)) => HttpResponse::NoContent().finish(),
+        Ok(Ok(false)) => HttpResponse::NotFound().json(json!({"error": "No GitHub account is connected"})),
+        Ok(Err(e)) => {
+            error!("Failed to remove GitHub connection: {}", e);
+            HttpResponse::InternalServerError().json(json!({"error": e}))
+        }
+        Err(e) => {
+            error!("Protected settings unavailable: {}", e);
+            HttpResponse::InternalServerError().json(json!({"error": "Protected settings unavailable"}))
+        }
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/auth/github")
+            .route(web::get().to(get_connection))
+            .route(web::delete().to(disconnect))
+    ).service(
+        web::resource("/auth/github/device")
+            .route(web::post().to(start_device_flow))
+    ).service(
+        web::resource("/auth/github/device/poll")
+            .route(web::post().to(poll_device_flow))
+    );
+}
@@ -6,7 +6,7 @@ use log::{info, debug, error, warn};
 use std::collections::HashMap;
 use std::sync::Arc;
 use crate::models::metadata::Metadata;
-use crate::utils::socket_flow_messages::Node;
+use crate::models::node::Node;
 use crate::services::file_service::FileService;
 use crate::services::graph_service::GraphService;
 
@@ -77,9 +77,18 @@ pub async fn get_graph_data(state: web::Data<AppState>) -> impl Responder {
             mass_scale: physics_settings.mass_scale,
             boundary_damping: physics_settings.boundary_damping,
             enable_bounds: physics_settings.enable_bounds,
+            collision_radius: physics_settings.collision_radius,
+            collision_stiffness: physics_settings.collision_stiffness,
+            plane_z: physics_settings.layout_plane(),
             time_step: 0.016,
             phase: crate::models::simulation_params::SimulationPhase::Dynamic,
-            mode: crate::models::simulation_params::SimulationMode::Remote,
+            mode: physics_settings.force_model,
+            gravity: physics_settings.gravity,
+            strong_gravity: physics_settings.strong_gravity,
+            edge_weight_normalization: physics_settings.edge_weight_normalization,
+            degree_gravity: physics_settings.attraction_strength,
+            cluster_separation: physics_settings.cluster_separation,
+            node_types: physics_settings.node_types.clone(),
         };
         
         // Calculate graph layout using GPU
@@ -171,9 +180,18 @@ pub async fn get_paginated_graph_data(
                 mass_scale: physics_settings.mass_scale,
                 boundary_damping: physics_settings.boundary_damping,
                 enable_bounds: physics_settings.enable_bounds,
+                collision_radius: physics_settings.collision_radius,
+                collision_stiffness: physics_settings.collision_stiffness,
+                plane_z: physics_settings.layout_plane(),
                 time_step: 0.016,
                 phase: crate::models::simulation_params::SimulationPhase::Dynamic,
-                mode: crate::models::simulation_params::SimulationMode::Remote,
+                mode: physics_settings.force_model,
+                gravity: physics_settings.gravity,
+                strong_gravity: physics_settings.strong_gravity,
+                edge_weight_normalization: physics_settings.edge_weight_normalization,
+                degree_gravity: physics_settings.attraction_strength,
+                cluster_separation: physics_settings.cluster_separation,
+                node_types: physics_settings.node_types.clone(),
             };
             
             // Calculate graph layout using GPU
@@ -270,7 +288,7 @@ pub async fn refresh_graph(state: web::Data<AppState>) -> impl Responder {
     let metadata = state.metadata.read().await.clone();
     debug!("Building graph from {} metadata entries", metadata.len());
     
-    match GraphService::build_graph_from_metadata(&metadata).await {
+    match GraphService::build_graph_from_metadata(&metadata, None).await {
         Ok(mut new_graph) => {
             let mut graph = state.graph_service.get_graph_data_mut().await;
             let mut node_map = state.graph_service.get_node_map_mut().await;
@@ -358,7 +376,7 @@ pub async fn update_graph(state: web::Data<AppState>) -> impl Responder {
             }
             
             // Build new graph
-            match GraphService::build_graph_from_metadata(&metadata).await {
+            match GraphService::build_graph_from_metadata(&metadata, None).await {
                 Ok(mut new_graph) => {
                     let mut graph = state.graph_service.get_graph_data_mut().await;
                     let mut node_map = state.graph_service.get_node_map_mut().await;
@@ -1,6 +1,7 @@
 use actix_web::{web, HttpResponse, Result, get};
 use serde::{Deserialize, Serialize};
 use crate::AppState;
+use crate::services::resilience::{self, CircuitState};
 use log::{info, error};
 use chrono::Utc;
 use crate::actors::messages::{GetMetadata, GetGraphData}; // Assuming GetGraphData returns the necessary counts or the GraphData struct
@@ -34,11 +35,16 @@ pub async fn health_check(app_state: web::Data<AppState>) -> Result<HttpResponse
         }
     };
     
+    // An external service being down degrades the server rather than failing it
+    let dependencies = resilience::health();
+    let status = if dependencies.iter().any(|d| d.state != CircuitState::Closed) { "degraded" } else { "healthy" };
+
     Ok(HttpResponse::Ok().json(serde_json::json!({
-        "status": "healthy",
+        "status": status,
         "metadata_count": metadata_count,
         "nodes_count": nodes_count,
-        "edges_count": edges_count
+        "edges_count": edges_count,
+        "dependencies": dependencies
     })))
 }
 
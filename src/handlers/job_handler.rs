@@ -0,0 +1,64 @@
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use serde_json::json;
+use crate::AppState;
+use crate::handlers::tenant_handler::sync_job_kind;
+use crate::utils::auth::require_session;
+
+/// Lists known jobs, newest first. Finished jobs are kept for a while so their
+/// outcome can still be read after the WebSocket event was missed.
+pub async fn list_jobs(app_state: web::Data<AppState>) -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(json!({
+        "jobs": app_state.job_queue.list()
+    })))
+}
+
+pub async fn get_job(app_state: web::Data<AppState>, id: web::Path<String>) -> Result<HttpResponse> {
+    match app_state.job_queue.get(&id) {
+        Some(job) => Ok(HttpResponse::Ok().json(job)),
+        None => Ok(HttpResponse::NotFound().json(json!({
+            "status": "error",
+            "message": format!("Job not found: {}", id)
+        }))),
+    }
+}
+
+/// Cancels a job. Power users may cancel any job, other users only the sync of
+/// their own vault.
+pub async fn cancel_job(req: HttpRequest, app_state: web::Data<AppState>, id: web::Path<String>) -> Result<HttpResponse> {
+    let pubkey = match require_session(&req, &app_state).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return Ok(resp),
+    };
+    let Some(job) = app_state.job_queue.get(&id) else {
+        return Ok(HttpResponse::NotFound().json(json!({
+            "status": "error",
+            "message": format!("Job not found: {}", id)
+        })));
+    };
+    if !app_state.is_power_user(&pubkey) && job.kind != sync_job_kind(&pubkey) {
+        return Ok(HttpResponse::Forbidden().json(json!({
+            "status": "error",
+            "message": "Only power users can cancel this job"
+        })));
+    }
+
+    if app_state.job_queue.cancel(&id) {
+        return Ok(HttpResponse::Accepted().json(json!({
+            "status": "cancelling",
+            "jobId": id.into_inner()
+        })));
+    }
+    Ok(HttpResponse::Conflict().json(json!({
+        "status": "error",
+        "message": format!("Job {} has already finished", job.id)
+    })))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/jobs")
+            .route("", web::get().to(list_jobs))
+            .route("/{id}", web::get().to(get_job))
+            .route("/{id}", web::delete().to(cancel_job))
+    );
+}
@@ -0,0 +1,107 @@
+use crate::actors::messages::{BuildGraphFromMetadata, GetGraphData, GetMetadata, GetSettings, UpdateMetadata};
+use crate::app_state::AppState;
+use crate::errors::AppError;
+use crate::utils::auth::require_session;
+use crate::services::activity::ActivityKind;
+use crate::services::file_service::FileService;
+use crate::services::github::{ContentAPI, GitHubError};
+use crate::services::journal;
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::{Local, NaiveDate, NaiveTime};
+use log::{info, warn};
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+
+const MAX_NOTE_CHARS: usize = 4000;
+// Someone else committing to the same page between our read and write
+const MAX_WRITE_ATTEMPTS: u32 = 3;
+
+#[derive(Debug, Deserialize)]
+pub struct AppendRequest {
+    pub text: String,
+    /// Journal day, today in the server's time zone if not given
+    #[serde(default)]
+    pub date: Option<NaiveDate>,
+    /// Time stamped on the bullet (`14:30`), now if not given
+    #[serde(default)]
+    pub time: Option<NaiveTime>,
+}
+
+async fn content_api(state: &AppState, pubkey: &str) -> ContentAPI {
+    match state.get_github_token(pubkey).await {
+        Some(token) => ContentAPI::new(Arc::new(state.github_client.with_token(token))),
+        None => ContentAPI::new(state.github_client.clone()),
+    }
+}
+
+/// Appends a timestamped bullet to a journal page, creating the page if needed.
+/// The page is committed to the repository first and then written to the vault,
+/// so the next sync agrees with it. Power users only.
+async fn append_to_journal(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    request: web::Json<AppendRequest>,
+) -> Result<HttpResponse, AppError> {
+    let pubkey = match require_session(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(response) => return Ok(response),
+    };
+    if !state.is_power_user(&pubkey) {
+        return Err(AppError::Forbidden("Only power users can write to the journal".to_string()));
+    }
+    let request = request.into_inner();
+    if request.text.trim().is_empty() {
+        return Err(AppError::BadRequest("Note is empty".to_string()));
+    }
+    if request.text.chars().count() > MAX_NOTE_CHARS {
+        return Err(AppError::BadRequest(format!("Note is longer than {} characters", MAX_NOTE_CHARS)));
+    }
+
+    let now = Local::now();
+    let date = request.date.unwrap_or_else(|| now.date_naive());
+    let file_name = journal::journal_file_name(date);
+    let bullet = journal::bullet(request.time.unwrap_or_else(|| now.time()), &request.text);
+
+    let github = content_api(&state, &pubkey).await;
+    let mut attempt = 1;
+    let (content, created, commit_sha) = loop {
+        let existing = github.get_file_with_sha(&file_name).await.map_err(AppError::github)?;
+        let created = existing.is_none();
+        let (page, sha) = existing.unwrap_or_default();
+        let content = journal::append(&page, &bullet);
+        let message = format!("Journal: {}", date.format("%Y-%m-%d"));
+        match github.put_file(&file_name, &content, (!created).then_some(sha.as_str()), &message).await {
+            Ok(commit_sha) => break (content, created, commit_sha),
+            Err(e) if attempt < MAX_WRITE_ATTEMPTS && matches!(e.downcast_ref::<GitHubError>(), Some(GitHubError::Conflict(_))) => {
+                warn!("{}, retrying ({}/{})", e, attempt, MAX_WRITE_ATTEMPTS);
+                attempt += 1;
+            }
+            Err(e) => return Err(AppError::github(e)),
+        }
+    };
+
+    let mut metadata = state.metadata_addr.send(GetMetadata).await?.map_err(AppError::file)?;
+    let settings = state.settings_addr.send(GetSettings).await?.map_err(AppError::Internal)?;
+    let file_service = FileService::new(Arc::new(tokio::sync::RwLock::new(settings))).with_event_bus(state.event_bus.clone());
+    let page = file_service.write_page(&file_name, &content, &mut metadata)?;
+    state.metadata_addr.send(UpdateMetadata { metadata: metadata.clone() }).await?.map_err(AppError::file)?;
+    state.graph_service_addr.send(BuildGraphFromMetadata { metadata, seed: None }).await?.map_err(AppError::graph)?;
+    let graph = state.graph_service_addr.send(GetGraphData).await?.map_err(AppError::graph)?;
+    let node = graph.nodes.iter().find(|node| node.id.to_string() == page.node_id);
+
+    info!("Appended a note to journal {} in commit {}", file_name, commit_sha);
+    let kind = if created { ActivityKind::NodeAdded } else { ActivityKind::FileUpdated };
+    state.activity.record(kind, &file_name, Some(pubkey), json!({ "commitSha": commit_sha }));
+    Ok(HttpResponse::Ok().json(json!({
+        "fileName": file_name,
+        "bullet": bullet,
+        "created": created,
+        "commitSha": commit_sha,
+        "node": node,
+    })))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.route("/journal/append", web::post().to(append_to_journal));
+}
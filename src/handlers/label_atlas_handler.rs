@@ -0,0 +1,110 @@
+use actix_web::http::header::{CacheControl, CacheDirective, ContentType, ETag, EntityTag, Header, IfNoneMatch};
+use actix_web::{web, HttpRequest, HttpResponse, Result};
+use log::error;
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::actors::messages::{GetGraphData, GetSettings};
+use crate::services::label_atlas::{AtlasFormat, LabelAtlas, LabelAtlasStyle};
+use crate::services::snapshot::encode_grayscale_png;
+use crate::AppState;
+
+#[derive(Debug, Deserialize)]
+pub struct AtlasQuery {
+    #[serde(default)]
+    pub format: AtlasFormat,
+}
+
+/// Builds or reuses the atlas of the current graph's labels, or the error response
+async fn current_atlas(state: &AppState, format: AtlasFormat) -> std::result::Result<(Arc<LabelAtlas>, serde_json::Value), HttpResponse> {
+    let settings = match state.settings_addr.send(GetSettings).await {
+        Ok(Ok(settings)) => settings,
+        _ => return Err(HttpResponse::InternalServerError().json(json!({"error": "Failed to read settings"}))),
+    };
+    let graph = match state.graph_service_addr.send(GetGraphData).await {
+        Ok(Ok(graph)) => graph,
+        _ => return Err(HttpResponse::InternalServerError().json(json!({"error": "Failed to get graph data"}))),
+    };
+
+    let labels_settings = &settings.visualisation.labels;
+    let style = LabelAtlasStyle::from_settings(labels_settings);
+    let colors = json!({
+        "textColor": labels_settings.text_color,
+        "outlineColor": labels_settings.text_outline_color,
+        "outlineWidth": labels_settings.text_outline_width,
+    });
+    let mut labels: Vec<(u32, String)> = graph.nodes.iter().map(|node| (node.id, node.label.clone())).collect();
+    labels.sort_unstable();
+
+    let cache = Arc::clone(&state.label_atlases);
+    match web::block(move || cache.get_or_build(&labels, &style, format)).await {
+        Ok(Ok(atlas)) => Ok((atlas, colors)),
+        Ok(Err(e)) => {
+            error!("Failed to build label atlas: {}", e);
+            Err(HttpResponse::ServiceUnavailable().json(json!({"error": e})))
+        }
+        Err(e) => {
+            error!("Label atlas task failed: {}", e);
+            Err(HttpResponse::InternalServerError().json(json!({"error": "Failed to build label atlas"})))
+        }
+    }
+}
+
+/// The 304 response when the client already holds this atlas version
+fn not_modified(req: &HttpRequest, etag: &EntityTag) -> Option<HttpResponse> {
+    let unchanged = match IfNoneMatch::parse(req) {
+        Ok(IfNoneMatch::Any) => true,
+        Ok(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(etag)),
+        Err(_) => false,
+    };
+    unchanged.then(|| HttpResponse::NotModified().insert_header(ETag(etag.clone())).finish())
+}
+
+/// Where each node's label sits in the atlas texture, with the style to draw it in
+pub async fn get_atlas_metadata(req: HttpRequest, state: web::Data<AppState>, query: web::Query<AtlasQuery>) -> Result<HttpResponse> {
+    let (atlas, colors) = match current_atlas(&state, query.format).await {
+        Ok(found) => found,
+        Err(response) => return Ok(response),
+    };
+    let etag = EntityTag::new_strong(atlas.version.clone());
+    if let Some(response) = not_modified(&req, &etag) {
+        return Ok(response);
+    }
+
+    let mut body = serde_json::to_value(atlas.as_ref())?;
+    if let (Some(body), Some(colors)) = (body.as_object_mut(), colors.as_object()) {
+        body.extend(colors.clone());
+    }
+    Ok(HttpResponse::Ok()
+        .insert_header(ETag(etag))
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .json(body))
+}
+
+/// The atlas texture as a greyscale PNG
+pub async fn get_atlas_texture(req: HttpRequest, state: web::Data<AppState>, query: web::Query<AtlasQuery>) -> Result<HttpResponse> {
+    let (atlas, _) = match current_atlas(&state, query.format).await {
+        Ok(found) => found,
+        Err(response) => return Ok(response),
+    };
+    let etag = EntityTag::new_strong(atlas.version.clone());
+    if let Some(response) = not_modified(&req, &etag) {
+        return Ok(response);
+    }
+
+    let png = web::block(move || encode_grayscale_png(&atlas.pixels, atlas.width, atlas.height)).await??;
+    Ok(HttpResponse::Ok()
+        .insert_header(ETag(etag))
+        .insert_header(CacheControl(vec![CacheDirective::NoCache]))
+        .content_type(ContentType::png())
+        .body(png))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/labels")
+            .route("/atlas", web::get().to(get_atlas_metadata))
+            .route("/atlas.png", web::get().to(get_atlas_texture))
+    );
+}
@@ -1,9 +1,31 @@
+pub mod activity_handler;
+pub mod agenda_handler;
+pub mod ai_usage_handler;
 pub mod api_handler;
+pub mod bookmark_handler;
+pub mod comment_handler;
+pub mod conversation_handler;
+pub mod demo_handler;
+pub mod github_auth_handler;
 pub mod health_handler;
+pub mod job_handler;
+pub mod journal_handler;
+pub mod label_atlas_handler;
 pub mod pages_handler;
 pub mod perplexity_handler;
+pub mod pr_handler;
 pub mod ragflow_handler;
+pub mod recommendation_handler;
+pub mod saved_filter_handler;
 pub mod settings_handler;
+pub mod share_handler;
 pub mod socket_flow_handler;
+pub mod speech_handler;
 pub mod speech_socket_handler;
+pub mod storage_handler;
+pub mod telemetry_handler;
+pub mod tenant_handler;
+pub mod view_link_handler;
+pub mod visit_handler;
 pub mod nostr_handler;
+pub mod webhook_handler;
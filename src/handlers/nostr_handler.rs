@@ -1,5 +1,6 @@
 use crate::app_state::AppState;
 use crate::models::protected_settings::ApiKeys;
+use crate::services::activity::ActivityKind;
 use crate::services::nostr_service::{NostrService, AuthEvent, NostrError};
 use crate::config::feature_access::FeatureAccess;
 use actix_web::{web, Error, HttpRequest, HttpResponse};
@@ -128,9 +129,12 @@ async fn login(
     event: web::Json<AuthEvent>,
     nostr_service: web::Data<NostrService>,
     feature_access: web::Data<FeatureAccess>,
+    state: web::Data<AppState>,
 ) -> Result<HttpResponse, Error> {
     match nostr_service.verify_auth_event(event.into_inner()).await {
         Ok(user) => {
+            state.activity.record(ActivityKind::UserJoined, &user.npub, Some(user.pubkey.clone()), serde_json::Value::Null);
+
             let token = user.session_token.clone().unwrap_or_default();
             let expires_at = user.last_seen + std::env::var("AUTH_TOKEN_EXPIRY")
                 .unwrap_or_else(|_| "3600".to_string())
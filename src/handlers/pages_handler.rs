@@ -1,135 +1,317 @@
-use actix_web::{web, HttpResponse, Result};
+use actix_web::{web, HttpRequest, HttpResponse};
 use crate::AppState;
-use crate::actors::messages::{GetSettings, GetMetadata};
-use serde::Serialize;
-use futures::future::join_all;
-use crate::models::metadata::Metadata;
-use crate::services::github::GitHubFileMetadata;
+use crate::config::data_dirs::DataDirs;
+use crate::actors::messages::{BuildGraphFromMetadata, GetGraphData, GetMetadata, GetSettings, UpdateMetadata};
+use crate::errors::AppError;
+use crate::handlers::pr_handler::pull_request_api;
+use crate::utils::auth::require_session;
+use crate::models::metadata::MetadataStore;
+use crate::services::activity::ActivityKind;
+use crate::services::feed::{self, FeedConfig, FeedEntry};
+use crate::services::file_service::FileService;
+use crate::services::page_templates::PageTemplates;
+use crate::services::pages::{self, Search};
+use crate::services::vault_crypto;
+use log::{error, info};
+use serde::Deserialize;
+use std::sync::Arc;
 
-#[derive(Serialize)]
-#[serde(rename_all = "camelCase")]
-pub struct PageInfo {
-    id: String,
-    title: String,
-    path: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    parent: Option<String>,
-    modified: i64,
-}
-
-pub async fn get_pages(app_state: web::Data<AppState>) -> Result<HttpResponse> {
-    let settings = app_state.settings_addr.send(GetSettings).await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Settings actor mailbox error: {}", e)))?
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
-    let debug_enabled = settings.system.debug.enabled;
-
-    if debug_enabled {
-        log::debug!("Starting pages retrieval");
+const DEFAULT_PAGE_SIZE: usize = 50;
+const DEFAULT_SEARCH_LIMIT: usize = 20;
+const DEFAULT_FEED_SIZE: usize = 50;
+const MAX_FEED_SIZE: usize = 200;
+
+async fn load_metadata(app_state: &AppState) -> Result<MetadataStore, HttpResponse> {
+    match app_state.metadata_addr.send(GetMetadata).await {
+        Ok(Ok(metadata)) => Ok(metadata),
+        Ok(Err(e)) => {
+            error!("Failed to get metadata for pages: {}", e);
+            Err(HttpResponse::InternalServerError().json(serde_json::json!({"error": "Failed to retrieve metadata"})))
+        }
+        Err(e) => {
+            error!("Metadata actor mailbox error: {}", e);
+            Err(HttpResponse::InternalServerError().json(serde_json::json!({"error": "Metadata service unavailable"})))
+        }
+    }
+}
+
+/// The metadata key of the page called `name`, if it is published
+fn page_file(metadata: &MetadataStore, name: &str) -> Result<String, HttpResponse> {
+    let file_name = format!("{}.md", name.trim_end_matches(".md"));
+    if metadata.contains_key(&file_name) {
+        Ok(file_name)
+    } else {
+        Err(HttpResponse::NotFound().json(serde_json::json!({"error": format!("Page {} not found", name)})))
     }
+}
 
-    let metadata = app_state.metadata_addr.send(GetMetadata).await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(format!("Metadata actor mailbox error: {}", e)))?
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
-        
-    if debug_enabled {
-        log::debug!("Found {} metadata entries to process", metadata.len());
+/// Every published page, sorted by title
+pub async fn get_pages(app_state: web::Data<AppState>) -> HttpResponse {
+    match load_metadata(&app_state).await {
+        Ok(metadata) => HttpResponse::Ok().json(pages::list(&metadata)),
+        Err(response) => response,
     }
+}
 
-    let futures: Vec<_> = metadata.iter()
-        .map(|(id, meta)| {
-            let content_api = app_state.content_api.clone();
-            let file_name = meta.file_name.clone();
-            let id = id.clone();
-            let meta = meta.clone();
-            let debug_enabled = debug_enabled;
-
-            async move {
-                if debug_enabled {
-                    log::debug!("Processing file: {} (ID: {})", file_name, id);
-                }
-
-                let github_meta = content_api
-                    .list_markdown_files("")  // Empty string since base path is already configured
-                    .await;
-
-                match github_meta {
-                    Ok(files) => {
-                        if debug_enabled {
-                            log::debug!("Found {} GitHub files for {}", files.len(), file_name);
-                        }
-
-                        let matching_file = files.into_iter()
-                            .find(|f| f.name == file_name);
-
-                        if debug_enabled {
-                            if let Some(ref file) = matching_file {
-                                log::debug!("Found matching GitHub file for {}: {:?}", file_name, file);
-                            } else {
-                                log::debug!("No matching GitHub file found for {}", file_name);
-                            }
-                        }
-
-                        Ok((id, meta, matching_file))
-                    },
-                    Err(e) => {
-                        log::error!("Failed to fetch GitHub metadata for {}: {}", file_name, e);
-                        Ok((id, meta, None))
-                    }
-                }
-            }
-        })
-        .collect();
-    
-    if debug_enabled {
-        log::debug!("Created {} futures for parallel processing", futures.len());
+/// Pages nested by namespace
+pub async fn get_page_tree(app_state: web::Data<AppState>) -> HttpResponse {
+    match load_metadata(&app_state).await {
+        Ok(metadata) => HttpResponse::Ok().json(pages::tree(&metadata)),
+        Err(response) => response,
     }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct RecentQuery {
+    pub page: Option<usize>,
+    pub page_size: Option<usize>,
+}
 
-    let results = join_all(futures).await;
-    
-    let pages: Vec<PageInfo> = results.into_iter()
-        .filter_map(|result: Result<(String, Metadata, Option<GitHubFileMetadata>), actix_web::Error>| {
-            match result {
-                Ok((id, meta, github_meta)) => {
-                    if debug_enabled {
-                        log::debug!("Building page info for {} (ID: {})", meta.file_name, id);
-                    }
-
-                    let modified = github_meta
-                        .and_then(|gm| gm.last_modified)
-                        .map(|dt| dt.timestamp())
-                        .unwrap_or_else(|| {
-                            if debug_enabled {
-                                log::debug!("No modification time found for {}, using 0", meta.file_name);
-                            }
-                            0
-                        });
-
-                    Some(PageInfo {
-                        id,
-                        title: meta.file_name.clone(),
-                        path: format!("/app/data/markdown/{}", meta.file_name),
-                        parent: None,
-                        modified,
-                    })
-                },
-                Err(e) => {
-                    log::error!("Failed to process page: {}", e);
-                    None
-                }
-            }
-        })
-        .collect();
-
-    if debug_enabled {
-        log::debug!("Returning {} processed pages", pages.len());
+/// Pages by last modification, newest first, a page at a time
+pub async fn get_recent_pages(app_state: web::Data<AppState>, query: web::Query<RecentQuery>) -> HttpResponse {
+    let page_size = query.page_size.unwrap_or(DEFAULT_PAGE_SIZE);
+    if page_size == 0 {
+        return HttpResponse::BadRequest().json(serde_json::json!({"error": "Page size must be greater than 0"}));
     }
+    let metadata = match load_metadata(&app_state).await {
+        Ok(metadata) => metadata,
+        Err(response) => return response,
+    };
+    let recent = pages::recent(&metadata);
+    let total_items = recent.len();
+    let page = query.page.unwrap_or(1).max(1);
+    let items: Vec<_> = recent.into_iter().skip((page - 1) * page_size).take(page_size).collect();
+    HttpResponse::Ok().json(serde_json::json!({
+        "pages": items,
+        "currentPage": page,
+        "pageSize": page_size,
+        "totalItems": total_items,
+        "totalPages": total_items.div_ceil(page_size),
+    }))
+}
 
-    Ok(HttpResponse::Ok().json(pages))
+#[derive(Debug, Default, Deserialize)]
+pub struct FeedQuery {
+    pub limit: Option<usize>,
 }
 
-pub fn config(cfg: &mut web::ServiceConfig) {
-    cfg.service(
-        web::resource("")
-            .route(web::get().to(get_pages))
+/// Atom feed of the most recently changed pages, with rendered summaries
+pub async fn get_feed(req: HttpRequest, app_state: web::Data<AppState>, query: web::Query<FeedQuery>) -> HttpResponse {
+    let limit = query.limit.unwrap_or(DEFAULT_FEED_SIZE).clamp(1, MAX_FEED_SIZE);
+    let metadata = match load_metadata(&app_state).await {
+        Ok(metadata) => metadata,
+        Err(response) => return response,
+    };
+    let base_url = {
+        let connection = req.connection_info();
+        format!("{}://{}", connection.scheme(), connection.host())
+    };
+    let recent: Vec<_> = pages::recent(&metadata).into_iter().take(limit).collect();
+
+    // Reads the pages for their summaries, so it runs off the async workers
+    let xml = web::block(move || {
+        let dirs = DataDirs::global();
+        let entries: Vec<FeedEntry> = recent.into_iter()
+            .map(|page| {
+                let content = vault_crypto::read_to_string(dirs.markdown_file(format!("{}.md", page.id))).unwrap_or_default();
+                FeedEntry { summary_html: feed::summary(&content), page }
+            })
+            .collect();
+        feed::atom(&FeedConfig::from_env(), &base_url, &entries)
+    }).await;
+
+    match xml {
+        Ok(xml) => HttpResponse::Ok().content_type("application/atom+xml; charset=utf-8").body(xml),
+        Err(e) => {
+            error!("Building the page feed failed: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({"error": "Feed unavailable"}))
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct SearchQuery {
+    pub q: Option<String>,
+    pub limit: Option<usize>,
+}
+
+/// Pages whose title, aliases or text contain the query, best matches first
+pub async fn search_pages(app_state: web::Data<AppState>, query: web::Query<SearchQuery>) -> HttpResponse {
+    let Some(search) = query.q.as_deref().and_then(Search::new) else {
+        return HttpResponse::BadRequest().json(serde_json::json!({"error": "Query parameter q is required"}));
+    };
+    let limit = query.limit.unwrap_or(DEFAULT_SEARCH_LIMIT).max(1);
+    let metadata = match load_metadata(&app_state).await {
+        Ok(metadata) => metadata,
+        Err(response) => return response,
+    };
+
+    // Reads every page, so it runs off the async workers
+    let hits = web::block(move || {
+        let dirs = DataDirs::global();
+        let mut hits: Vec<_> = metadata.iter()
+            .filter_map(|(file_name, page)| {
+                let content = vault_crypto::read_to_string(dirs.markdown_file(file_name)).ok();
+                search.score(file_name, page, content.as_deref())
+            })
+            .collect();
+        hits.sort_by(|a, b| b.score.cmp(&a.score).then_with(|| a.page.title.cmp(&b.page.title)));
+        hits.truncate(limit);
+        hits
+    }).await;
+
+    match hits {
+        Ok(hits) => HttpResponse::Ok().json(hits),
+        Err(e) => {
+            error!("Page search failed: {}", e);
+            HttpResponse::InternalServerError().json(serde_json::json!({"error": "Search failed"}))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentFormat {
+    Raw,
+    Html,
+    #[default]
+    Both,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct PageQuery {
+    pub format: Option<ContentFormat>,
+}
+
+/// A page's markdown and rendered HTML, with its metadata and backlink count
+pub async fn get_page(
+    app_state: web::Data<AppState>,
+    path: web::Path<String>,
+    query: web::Query<PageQuery>,
+) -> HttpResponse {
+    let metadata = match load_metadata(&app_state).await {
+        Ok(metadata) => metadata,
+        Err(response) => return response,
+    };
+    let file_name = match page_file(&metadata, &path) {
+        Ok(file_name) => file_name,
+        Err(response) => return response,
+    };
+    let content = match vault_crypto::read_to_string(DataDirs::global().markdown_file(&file_name)) {
+        Ok(content) => content,
+        Err(e) => {
+            error!("Failed to read page {}: {}", file_name, e);
+            return HttpResponse::NotFound().json(serde_json::json!({"error": format!("Page {} has no content", path)}));
+        }
+    };
+
+    let format = query.format.unwrap_or_default();
+    let page = &metadata[&file_name];
+    HttpResponse::Ok().json(serde_json::json!({
+        "page": pages::PageSummary::of(&file_name, page),
+        "raw": (format != ContentFormat::Html).then_some(&content),
+        "html": (format != ContentFormat::Raw).then(|| pages::render_html(&content)),
+        "tags": page.tags,
+        "aliases": page.aliases,
+        "outline": page.page_stats.outline,
+        "readingTimeMinutes": page.page_stats.reading_time_minutes,
+        "backlinkCount": pages::backlinks(&metadata, &file_name).len(),
+    }))
+}
+
+/// Pages that reference this one, most references first
+pub async fn get_backlinks(app_state: web::Data<AppState>, path: web::Path<String>) -> HttpResponse {
+    let metadata = match load_metadata(&app_state).await {
+        Ok(metadata) => metadata,
+        Err(response) => return response,
+    };
+    match page_file(&metadata, &path) {
+        Ok(file_name) => HttpResponse::Ok().json(pages::backlinks(&metadata, &file_name)),
+        Err(response) => response,
+    }
+}
+
+/// Names of the templates new pages can be created from
+pub async fn get_templates() -> HttpResponse {
+    let templates = PageTemplates::new(DataDirs::global().templates());
+    HttpResponse::Ok().json(serde_json::json!({ "templates": templates.list() }))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateFromTemplateRequest {
+    pub template: String,
+    pub title: String,
+    #[serde(default)]
+    pub variables: serde_json::Map<String, serde_json::Value>,
+    /// Also open a pull request adding the page to the repository, so the next
+    /// sync keeps it once merged
+    #[serde(default)]
+    pub pull_request: bool,
+}
+
+/// Renders a template into a new page, adds it to the vault and the graph, and
+/// returns the page and its node. Power users only, as it writes to the vault.
+pub async fn create_from_template(
+    req: HttpRequest,
+    app_state: web::Data<AppState>,
+    request: web::Json<CreateFromTemplateRequest>,
+) -> Result<HttpResponse, AppError> {
+    let pubkey = match require_session(&req, &app_state).await {
+        Ok(pubkey) => pubkey,
+        Err(response) => return Ok(response),
+    };
+    if !app_state.is_power_user(&pubkey) {
+        return Err(AppError::Forbidden("Only power users can create pages".to_string()));
+    }
+    let request = request.into_inner();
+    let title = request.title.trim();
+    let file_name = pages::page_file_name(title)
+        .ok_or_else(|| AppError::BadRequest(format!("{:?} can't be a page title", request.title)))?;
+    let content = PageTemplates::new(DataDirs::global().templates()).render(&request.template, title, &request.variables)?;
+
+    let mut metadata = app_state.metadata_addr.send(GetMetadata).await?.map_err(AppError::file)?;
+    if metadata.contains_key(&file_name) {
+        return Err(AppError::Conflict(format!("Page {} already exists", title)));
+    }
+    // Proposed first, so a failed pull request leaves nothing half done
+    let pull_request_url = if request.pull_request {
+        let prs = pull_request_api(&app_state, &pubkey).await;
+        Some(prs.create_page_pull_request(&file_name, &content).await.map_err(AppError::github)?)
+    } else {
+        None
+    };
+
+    let settings = app_state.settings_addr.send(GetSettings).await?.map_err(AppError::Internal)?;
+    let file_service = FileService::new(Arc::new(tokio::sync::RwLock::new(settings))).with_event_bus(app_state.event_bus.clone());
+    let page = file_service.add_page(&file_name, &content, &mut metadata)?;
+    app_state.metadata_addr.send(UpdateMetadata { metadata: metadata.clone() }).await?.map_err(AppError::file)?;
+    app_state.graph_service_addr.send(BuildGraphFromMetadata { metadata, seed: None }).await?.map_err(AppError::graph)?;
+    let graph = app_state.graph_service_addr.send(GetGraphData).await?.map_err(AppError::graph)?;
+    let node = graph.nodes.iter().find(|node| node.id.to_string() == page.node_id);
+
+    info!("Created page {} from template {}", file_name, request.template);
+    app_state.activity.record(
+        ActivityKind::NodeAdded,
+        &file_name,
+        Some(pubkey),
+        serde_json::json!({ "template": request.template, "pullRequestUrl": pull_request_url }),
     );
-} 
\ No newline at end of file
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "page": pages::PageSummary::of(&file_name, &page),
+        "node": node,
+        "pullRequestUrl": pull_request_url,
+    })))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.route("", web::get().to(get_pages))
+        .route("/templates", web::get().to(get_templates))
+        .route("/create-from-template", web::post().to(create_from_template))
+        .route("/tree", web::get().to(get_page_tree))
+        .route("/recent", web::get().to(get_recent_pages))
+        .route("/feed.atom", web::get().to(get_feed))
+        .route("/search", web::get().to(search_pages))
+        .route("/{name}", web::get().to(get_page))
+        .route("/{name}/backlinks", web::get().to(get_backlinks));
+}
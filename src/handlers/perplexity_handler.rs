@@ -1,5 +1,8 @@
 use crate::AppState;
-use actix_web::{post, web, HttpResponse, Responder};
+use crate::services::ai_cache::CacheMode;
+use crate::services::ai_usage::BudgetExceeded;
+use crate::handlers::ai_usage_handler::budget_exceeded;
+use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use log::{error, info};
@@ -20,6 +23,7 @@ pub struct PerplexityResponse {
 
 #[post("")]
 pub async fn handle_perplexity(
+    req: HttpRequest,
     state: web::Data<AppState>,
     request: web::Json<PerplexityRequest>,
 ) -> impl Responder {
@@ -32,8 +36,9 @@ pub async fn handle_perplexity(
         }))
     };
 
-    let conversation_id = state.ragflow_session_id.clone();
-    match perplexity_service.query(&request.query, &conversation_id).await {
+    // Each request without an id starts its own conversation rather than sharing one
+    let conversation_id = request.conversation_id.clone().unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    match perplexity_service.query(&request.query, &conversation_id, CacheMode::from_headers(req.headers())).await {
         Ok(answer) => {
             let response = PerplexityResponse {
                 answer,
@@ -42,6 +47,9 @@ pub async fn handle_perplexity(
             HttpResponse::Ok().json(response)
         }
         Err(e) => {
+            if let Some(exceeded) = e.downcast_ref::<BudgetExceeded>() {
+                return budget_exceeded(exceeded);
+            }
             error!("Error processing perplexity request: {}", e);
             HttpResponse::InternalServerError().json(format!("Error: {}", e))
         }
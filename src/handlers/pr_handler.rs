@@ -0,0 +1,155 @@
+use crate::app_state::AppState;
+use crate::handlers::api_handler::graph::build_preview;
+use crate::services::activity::ActivityKind;
+use crate::services::github::{MergeMethod, PullRequestAPI};
+use crate::services::nostr_service::NostrService;
+use crate::utils::auth::verify_power_user;
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use log::error;
+use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
+
+#[derive(Debug, Deserialize, Default)]
+pub struct ApproveRequest {
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct MergeRequest {
+    #[serde(default)]
+    pub method: MergeMethod,
+    /// Head commit the reviewer saw; the merge is refused if the PR moved since
+    pub expected_head_sha: Option<String>,
+}
+
+/// Acts as the reviewer's own GitHub account when they've connected one, so reviews
+/// and merges are attributed to them instead of the server's token
+pub(crate) async fn pull_request_api(state: &AppState, pubkey: &str) -> PullRequestAPI {
+    match state.get_github_token(pubkey).await {
+        Some(token) => PullRequestAPI::new(Arc::new(state.github_client.with_token(token))),
+        None => PullRequestAPI::new(state.github_client.clone()),
+    }
+}
+
+fn github_error(action: &str, e: impl std::fmt::Display) -> HttpResponse {
+    error!("Failed to {}: {}", action, e);
+    HttpResponse::BadGateway().json(json!({
+        "status": "error",
+        "message": format!("Failed to {}: {}", action, e)
+    }))
+}
+
+// PR titles and file lists can reveal private pages, and approving/merging acts on
+// the repository with the server's token, so the whole workflow needs a signed-in
+// power user
+async fn list_pull_requests(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    nostr_service: web::Data<NostrService>,
+) -> Result<HttpResponse, Error> {
+    let pubkey = match verify_power_user(&req, &nostr_service).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return Ok(resp),
+    };
+
+    let prs = pull_request_api(&state, &pubkey).await;
+    match prs.list_open_pull_requests().await {
+        Ok(pulls) => Ok(HttpResponse::Ok().json(json!({ "pullRequests": pulls }))),
+        Err(e) => Ok(github_error("list pull requests", e)),
+    }
+}
+
+/// Preview graph of the PR's head commit plus the files it adds, changes or removes
+async fn pull_request_diff(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    nostr_service: web::Data<NostrService>,
+    number: web::Path<u32>,
+) -> Result<HttpResponse, Error> {
+    let pubkey = match verify_power_user(&req, &nostr_service).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return Ok(resp),
+    };
+
+    let prs = pull_request_api(&state, &pubkey).await;
+    let pr = match prs.get_pull_request(*number).await {
+        Ok(pr) => pr,
+        Err(e) => return Ok(github_error(&format!("get pull request #{}", number), e)),
+    };
+
+    // Preview by commit SHA so PRs from forks work too
+    match build_preview(&state, &pr.head_sha).await {
+        Ok(preview) => Ok(HttpResponse::Ok().json(json!({
+            "pullRequest": pr,
+            "preview": preview
+        }))),
+        Err(response) => Ok(response),
+    }
+}
+
+async fn approve_pull_request(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    nostr_service: web::Data<NostrService>,
+    number: web::Path<u32>,
+    payload: Option<web::Json<ApproveRequest>>,
+) -> Result<HttpResponse, Error> {
+    let pubkey = match verify_power_user(&req, &nostr_service).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return Ok(resp),
+    };
+
+    let payload = payload.map(|p| p.into_inner()).unwrap_or_default();
+    let prs = pull_request_api(&state, &pubkey).await;
+    match prs.approve_pull_request(*number, payload.comment.as_deref()).await {
+        Ok(()) => Ok(HttpResponse::Ok().json(json!({
+            "status": "success",
+            "number": *number
+        }))),
+        Err(e) => Ok(github_error(&format!("approve pull request #{}", number), e)),
+    }
+}
+
+async fn merge_pull_request(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    nostr_service: web::Data<NostrService>,
+    number: web::Path<u32>,
+    payload: Option<web::Json<MergeRequest>>,
+) -> Result<HttpResponse, Error> {
+    let pubkey = match verify_power_user(&req, &nostr_service).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return Ok(resp),
+    };
+
+    let payload = payload.map(|p| p.into_inner()).unwrap_or_default();
+    let prs = pull_request_api(&state, &pubkey).await;
+    match prs.merge_pull_request(*number, payload.method, payload.expected_head_sha.as_deref()).await {
+        Ok(sha) => {
+            state.activity.record(
+                ActivityKind::PullRequestMerged,
+                &format!("#{}", number),
+                Some(pubkey),
+                json!({ "mergeCommitSha": sha }),
+            );
+            Ok(HttpResponse::Ok().json(json!({
+                "status": "success",
+                "number": *number,
+                "mergeCommitSha": sha
+            })))
+        }
+        Err(e) => Ok(github_error(&format!("merge pull request #{}", number), e)),
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/prs")
+            .route("", web::get().to(list_pull_requests))
+            .route("/{number}/diff", web::get().to(pull_request_diff))
+            .route("/{number}/approve", web::post().to(approve_pull_request))
+            .route("/{number}/merge", web::post().to(merge_pull_request))
+    );
+}
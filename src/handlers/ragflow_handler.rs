@@ -5,6 +5,15 @@ use log::{error, info};
 use serde_json::json;
 use futures::StreamExt;
 use actix_web::web::Bytes;
+use crate::actors::messages::{GetGraphData, GetSettings};
+use crate::config::data_dirs::DataDirs;
+use crate::models::node::Node;
+use crate::services::chat_context::{self, ContextBudget};
+use crate::services::conversations::Citation;
+use crate::services::vault_crypto;
+use crate::handlers::ai_usage_handler::budget_exceeded;
+use crate::handlers::conversation_handler::conversation_error;
+use crate::services::ai_cache::{AiCache, CacheMode};
 use crate::services::ragflow_service::RAGFlowError;
 use actix_web::web::ServiceConfig;
 use crate::types::speech::SpeechOptions;
@@ -37,6 +46,9 @@ pub struct SendMessageRequest {
 // Implement ResponseError for RAGFlowError
 impl ResponseError for RAGFlowError {
     fn error_response(&self) -> HttpResponse {
+        if let RAGFlowError::OverBudget(e) = self {
+            return budget_exceeded(e);
+        }
         HttpResponse::InternalServerError()
             .json(json!({"error": self.to_string()}))
     }
@@ -54,10 +66,18 @@ pub async fn send_message(
         }))
     };
 
-    // Get session ID from request or use the default one from app state if not provided
+    // Without a session the message starts a new one, so callers never share history
     let session_id = match &request.session_id {
         Some(id) => id.clone(),
-        None => state.ragflow_session_id.clone(),
+        None => match ragflow_service.create_session("anonymous".to_string()).await {
+            Ok(id) => id,
+            Err(e) => {
+                error!("Failed to create RAGFlow session: {}", e);
+                return HttpResponse::InternalServerError().json(json!({
+                    "error": format!("Failed to create RAGFlow session: {}", e)
+                }));
+            }
+        },
     };
 
     let enable_tts = request.enable_tts.unwrap_or(false);
@@ -120,6 +140,7 @@ pub async fn send_message(
             });
             HttpResponse::Ok().streaming(mapped_stream)
         },
+        Err(RAGFlowError::OverBudget(e)) => budget_exceeded(&e),
         Err(e) => {
             error!("Error sending message: {}", e);
             HttpResponse::InternalServerError().json(json!({
@@ -192,6 +213,28 @@ pub async fn get_session_history(
     }
 }
 
+/// `question` preceded by the notes around `focus`, and those notes as citations
+async fn with_graph_context(state: &AppState, question: &str, focus: u32) -> Result<(String, Vec<Citation>), HttpResponse> {
+    let graph = match state.graph_service_addr.send(GetGraphData).await {
+        Ok(Ok(graph)) => graph,
+        _ => {
+            error!("Failed to get graph data for chat context");
+            return Err(HttpResponse::InternalServerError().json(json!({"error": "Failed to retrieve graph data"})));
+        }
+    };
+    let read = |node: &Node| vault_crypto::read_to_string(DataDirs::global().markdown_file(format!("{}.md", node.metadata_id))).ok();
+    match chat_context::build(&graph, focus, &ContextBudget::from_env(), read) {
+        Some(context) => {
+            info!("Chat context for node {}: {} notes, ~{} tokens, {} omitted", focus, context.notes.len(), context.tokens, context.omitted);
+            let citations = context.notes.iter()
+                .map(|note| Citation { node_id: note.node_id, title: note.title.clone() })
+                .collect();
+            Ok((context.prompt(question), citations))
+        }
+        None => Err(HttpResponse::NotFound().json(json!({"error": format!("Node {} not found", focus)}))),
+    }
+}
+
 /// Configure RAGFlow API routes
 async fn handle_ragflow_chat(
     state: web::Data<AppState>,
@@ -238,7 +281,25 @@ async fn handle_ragflow_chat(
 
     info!("[handle_ragflow_chat] RAGFlow service is Some. Proceeding."); // ADDED LOG
 
-    let mut session_id = payload.session_id.clone();
+    let (question, citations) = match payload.focus_node_id {
+        Some(focus) => match with_graph_context(&state, &payload.question, focus).await {
+            Ok(prompt) => prompt,
+            Err(response) => return response,
+        },
+        None => (payload.question.clone(), Vec::new()),
+    };
+
+    // A conversation carries its RAGFlow session, so the chat resumes where it left off
+    let conversation = match &payload.conversation_id {
+        Some(id) => match state.conversations.get(&pubkey, id).await {
+            Ok(conversation) => Some(conversation),
+            Err(e) => return conversation_error(e),
+        },
+        None => None,
+    };
+
+    let mut session_id = payload.session_id.clone()
+        .or_else(|| conversation.as_ref().and_then(|conversation| conversation.session_id.clone()));
     if session_id.is_none() {
         // Create a new session if none provided. Using pubkey as user_id for RAGFlow session.
         match ragflow_service.create_session(pubkey.clone()).await {
@@ -257,13 +318,43 @@ async fn handle_ragflow_chat(
     let current_session_id = session_id.expect("Session ID should be Some at this point");
 
     let stream_preference = payload.stream.unwrap_or(false); // Default to false if not provided
-    match ragflow_service.send_chat_message(current_session_id.clone(), payload.question.clone(), stream_preference).await {
+    let cache = match state.settings_addr.send(GetSettings).await {
+        Ok(Ok(settings)) => AiCache::from_settings(settings.ai_cache.as_ref()),
+        _ => None,
+    };
+    match ragflow_service.send_cached_chat_message(
+        current_session_id.clone(),
+        question,
+        stream_preference,
+        cache.as_ref(),
+        CacheMode::from_headers(req.headers()),
+    ).await {
         Ok((answer, final_session_id)) => {
+            // Chats that name neither a session nor a conversation start a new conversation
+            let conversation_id = match conversation {
+                Some(conversation) => Some(conversation.id),
+                None if payload.session_id.is_none() => match state.conversations.create(&pubkey, None).await {
+                    Ok(conversation) => Some(conversation.id),
+                    Err(e) => {
+                        error!("Failed to start a conversation for {}: {}", pubkey, e);
+                        None
+                    }
+                },
+                None => None,
+            };
+            if let Some(id) = &conversation_id {
+                if let Err(e) = state.conversations.record_exchange(&pubkey, id, &final_session_id, &payload.question, &answer, citations.clone()).await {
+                    error!("Failed to record chat in conversation {}: {}", id, e);
+                }
+            }
             HttpResponse::Ok().json(RagflowChatResponse {
                 answer,
                 session_id: final_session_id, // RAGFlow service send_chat_message returns the session_id it used
+                conversation_id,
+                context_node_ids: citations.iter().map(|citation| citation.node_id).collect(),
             })
         }
+        Err(RAGFlowError::OverBudget(e)) => budget_exceeded(&e),
         Err(e) => {
             error!("Error communicating with RAGFlow for session {}: {}", current_session_id, e);
             HttpResponse::InternalServerError().json(json!({"error": format!("RAGFlow communication error: {}", e)}))
@@ -0,0 +1,84 @@
+use crate::actors::messages::GetGraphData;
+use crate::app_state::AppState;
+use crate::errors::AppError;
+use crate::services::recommendations;
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use serde::Deserialize;
+use serde_json::json;
+
+const DEFAULT_LIMIT: usize = 10;
+const MAX_LIMIT: usize = 100;
+const MAX_SESSION_ID_LEN: usize = 128;
+
+#[derive(Debug, Deserialize)]
+pub struct RecommendationQuery {
+    /// Recommend from this node instead of the session's selections
+    pub node: Option<u32>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Selection {
+    pub node_id: u32,
+}
+
+/// The `X-Session-Id` a client picked to have its selections remembered under
+fn session_id(req: &HttpRequest) -> Result<Option<String>, AppError> {
+    match req.headers().get("X-Session-Id").map(|value| value.to_str().map(str::trim)) {
+        None => Ok(None),
+        Some(Ok(session)) if !session.is_empty() && session.len() <= MAX_SESSION_ID_LEN => Ok(Some(session.to_string())),
+        Some(_) => Err(AppError::BadRequest(format!("X-Session-Id must be 1 to {} characters", MAX_SESSION_ID_LEN))),
+    }
+}
+
+/// Pages to read next, from a node or from the session's recent selections
+async fn recommend(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    query: web::Query<RecommendationQuery>,
+) -> Result<HttpResponse, AppError> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let graph = state.graph_service_addr.send(GetGraphData).await?.map_err(AppError::graph)?;
+    let seeds = match query.node {
+        Some(node) if graph.nodes.iter().any(|other| other.id == node) => vec![node],
+        Some(node) => return Err(AppError::NotFound(format!("Node {} not found", node))),
+        None => session_id(&req)?
+            .map(|session| state.recommendations.selections(&session))
+            .unwrap_or_default(),
+    };
+
+    // Embedding pages reads the ones that changed, so it runs off the async workers
+    let recommender = state.recommendations.clone();
+    let ranked = web::block({
+        let seeds = seeds.clone();
+        move || {
+            let ranks = recommender.ranks(&graph);
+            let embeddings = recommender.embeddings(&graph.metadata);
+            recommendations::rank(&graph, &ranks, &embeddings, &seeds, Utc::now(), limit)
+        }
+    }).await.map_err(|e| AppError::Internal(format!("Ranking recommendations failed: {}", e)))?;
+
+    Ok(HttpResponse::Ok().json(json!({ "seeds": seeds, "recommendations": ranked })))
+}
+
+/// Records that the session selected a node, to recommend from later
+async fn record_selection(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    body: web::Json<Selection>,
+) -> Result<HttpResponse, AppError> {
+    let session = session_id(&req)?.ok_or_else(|| AppError::BadRequest("Missing X-Session-Id header".to_string()))?;
+    let graph = state.graph_service_addr.send(GetGraphData).await?.map_err(AppError::graph)?;
+    if !graph.nodes.iter().any(|node| node.id == body.node_id) {
+        return Err(AppError::NotFound(format!("Node {} not found", body.node_id)));
+    }
+    let selections = state.recommendations.select(&session, body.node_id);
+    Ok(HttpResponse::Ok().json(json!({ "selections": selections })))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.route("/recommendations", web::get().to(recommend))
+        .route("/recommendations/selections", web::post().to(record_selection));
+}
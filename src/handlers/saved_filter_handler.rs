@@ -0,0 +1,93 @@
+use crate::actors::messages::GetGraphData;
+use crate::app_state::AppState;
+use crate::utils::auth::require_session;
+use crate::services::saved_filters::SavedFilterError;
+use actix_web::{web, HttpRequest, HttpResponse};
+use log::error;
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Debug, Deserialize)]
+pub struct NewFilter {
+    pub name: String,
+    pub expression: String,
+}
+
+fn error_response(e: SavedFilterError) -> HttpResponse {
+    let body = json!({ "error": e.to_string() });
+    match e {
+        SavedFilterError::Invalid(_) => HttpResponse::BadRequest().json(body),
+        SavedFilterError::NotFound(_) => HttpResponse::NotFound().json(body),
+        SavedFilterError::LimitReached => HttpResponse::UnprocessableEntity().json(body),
+        SavedFilterError::Storage(_) => {
+            error!("Saved filter storage error: {}", body["error"]);
+            HttpResponse::InternalServerError().json(body)
+        }
+    }
+}
+
+async fn list_filters(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
+    let pubkey = match require_session(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return resp,
+    };
+    HttpResponse::Ok().json(json!({ "filters": state.saved_filters.list(&pubkey).await }))
+}
+
+async fn create_filter(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    body: web::Json<NewFilter>,
+) -> HttpResponse {
+    let pubkey = match require_session(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return resp,
+    };
+    let graph = match state.graph_service_addr.send(GetGraphData).await {
+        Ok(Ok(graph)) => graph,
+        _ => {
+            error!("Failed to get graph data for a new saved filter");
+            return HttpResponse::InternalServerError().json(json!({"error": "Failed to retrieve graph data"}));
+        }
+    };
+    match state.saved_filters.create(&pubkey, &body.name, &body.expression, &graph).await {
+        Ok(filter) => HttpResponse::Created().json(json!({
+            "filter": filter,
+            "members": state.saved_filters.members(&filter.id).await.unwrap_or_default(),
+        })),
+        Err(e) => error_response(e),
+    }
+}
+
+/// A filter with the metadata ids of its current members. Filter ids are
+/// unguessable, so anyone holding one can read it, like `filter_id=` on the graph.
+async fn get_filter(state: web::Data<AppState>, path: web::Path<String>) -> HttpResponse {
+    let id = path.into_inner();
+    match (state.saved_filters.get(&id).await, state.saved_filters.members(&id).await) {
+        (Some(filter), Some(members)) => HttpResponse::Ok().json(json!({ "filter": filter, "members": members })),
+        _ => error_response(SavedFilterError::NotFound(format!("Filter {} not found", id))),
+    }
+}
+
+async fn delete_filter(req: HttpRequest, state: web::Data<AppState>, path: web::Path<String>) -> HttpResponse {
+    let pubkey = match require_session(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return resp,
+    };
+    match state.saved_filters.delete(&path.into_inner(), &pubkey).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => error_response(e),
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/filters")
+            .route(web::get().to(list_filters))
+            .route(web::post().to(create_filter))
+    ).service(
+        web::resource("/filters/{id}")
+            .route(web::get().to(get_filter))
+            .route(web::delete().to(delete_filter))
+    );
+}
@@ -1,12 +1,20 @@
 use crate::app_state::AppState;
 use crate::models::{UISettings, UserSettings};
+use crate::models::user_settings::{self, DeviceProfile};
 use crate::config::AppFullSettings; // Removed ClientFacingSettings alias
+use crate::config::validation::ValidationErrors;
 use crate::models::client_settings_payload::*; // Import all DTOs
-use crate::actors::messages::{GetSettings, UpdateSettings};
+use crate::actors::messages::{GetSettings, SetNodeColors, SetWorldBounds, UpdateSettings};
+use crate::services::event_bus::SettingsEvent;
+use crate::services::node_colors::NodeColorMapper;
+use crate::services::world_bounds::BoundsConfig;
 // use crate::handlers::socket_flow_handler::ClientManager;
 use actix_web::{web, Error, HttpResponse, HttpRequest};
 use chrono::Utc;
-use serde_json::json;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{json, Value};
+use crate::utils::{case_conversion, merge_patch};
 use crate::config::feature_access::FeatureAccess;
 use log::{info, error, warn, debug};
 use std::time::Instant;
@@ -17,6 +25,44 @@ fn convert_to_ui_settings(full_settings: &AppFullSettings) -> UISettings {
     UISettings::from(full_settings) // Rely on the From trait implementation
 }
 
+/// Device class from the `X-Device-Profile` header. Unknown profiles are logged and
+/// treated as absent so an older or newer client still gets its settings.
+fn device_profile(req: &HttpRequest) -> Option<DeviceProfile> {
+    let value = req.headers().get("X-Device-Profile")?.to_str().ok()?;
+    value.parse().map_err(|e| warn!("{}; ignoring device profile", e)).ok()
+}
+
+/// Layers the caller's override for their device over `settings`, if they have one
+fn with_device_override(req: &HttpRequest, pubkey: &str, settings: UISettings) -> UISettings {
+    match (device_profile(req), UserSettings::load(pubkey)) {
+        (Some(device), Some(user)) => user.apply_device_override(settings, device),
+        _ => settings,
+    }
+}
+
+fn invalid_settings_response(errors: &ValidationErrors) -> HttpResponse {
+    HttpResponse::BadRequest().json(json!({
+        "status": "error",
+        "message": "Invalid settings",
+        "errors": errors
+    }))
+}
+
+/// Hands the graph the node colouring from newly saved global settings
+fn recolor_graph(state: &AppState, settings: &AppFullSettings) {
+    state.graph_service_addr.do_send(SetNodeColors {
+        mapper: NodeColorMapper::from_settings(&settings.visualisation.nodes),
+    });
+}
+
+/// Hands the graph the world bounds from newly saved global settings, replacing
+/// any set through `/api/graph/bounds`
+fn resize_world(state: &AppState, settings: &AppFullSettings) {
+    state.graph_service_addr.do_send(SetWorldBounds {
+        config: BoundsConfig::from_settings(&settings.visualisation.physics),
+    });
+}
+
 // --- Helper Macros for Merging Settings ---
 
 // Helper macro for merging Option fields
@@ -90,6 +136,14 @@ pub fn config(cfg: &mut web::ServiceConfig) {
         web::resource("/user-settings/sync")
             .route(web::get().to(get_user_settings))
             .route(web::post().to(update_user_settings)) // This now points to the updated function
+    ).service(
+        web::resource("/settings")
+            .route(web::patch().to(patch_settings))
+    ).service(
+        web::resource("/user-settings/devices/{profile}")
+            .route(web::get().to(get_device_override))
+            .route(web::put().to(put_device_override))
+            .route(web::delete().to(delete_device_override))
     ).service(
         web::resource("/user-settings/clear-cache")
             .route(web::post().to(clear_user_settings_cache))
@@ -160,7 +214,7 @@ async fn get_user_settings(
                 })));
             }
         };
-        let ui_settings = convert_to_ui_settings(&settings);
+        let ui_settings = with_device_override(&req, &pubkey, convert_to_ui_settings(&settings));
         debug!("Returning global UI settings for power user {}", pubkey);
         result = Ok(HttpResponse::Ok().json(ui_settings));
     } else {
@@ -168,7 +222,11 @@ async fn get_user_settings(
             debug!("Creating new user settings for {} with default settings", pubkey);
             UserSettings::new(&pubkey, UISettings::default())
         });
-        result = Ok(HttpResponse::Ok().json(&user_settings.settings));
+        let ui_settings = match device_profile(&req) {
+            Some(device) => user_settings.apply_device_override(user_settings.settings.clone(), device),
+            None => user_settings.settings,
+        };
+        result = Ok(HttpResponse::Ok().json(ui_settings));
     }
 
     let elapsed = start_time.elapsed();
@@ -239,6 +297,7 @@ async fn update_user_settings(
                 merge_copy_option!(target_vis.nodes.enable_hologram, nodes_dto.enable_hologram);
                 merge_copy_option!(target_vis.nodes.enable_metadata_shape, nodes_dto.enable_metadata_shape);
                 merge_copy_option!(target_vis.nodes.enable_metadata_visualisation, nodes_dto.enable_metadata_visualisation);
+                merge_copy_option!(target_vis.nodes.color_mode, nodes_dto.color_mode);
             }
             if let Some(edges_dto) = vis_dto.edges {
                 let target_edges = &mut target_vis.edges;
@@ -256,7 +315,17 @@ async fn update_user_settings(
                 let target_physics = &mut target_vis.physics; // Type: config::PhysicsSettings
                 merge_copy_option!(target_physics.attraction_strength, physics_dto.attraction_strength);
                 merge_copy_option!(target_physics.bounds_size, physics_dto.bounds_size);
+                merge_copy_option!(target_physics.auto_fit_bounds, physics_dto.auto_fit_bounds);
+                merge_copy_option!(target_physics.max_bounds_size, physics_dto.max_bounds_size);
                 merge_copy_option!(target_physics.collision_radius, physics_dto.collision_radius);
+                merge_copy_option!(target_physics.collision_stiffness, physics_dto.collision_stiffness);
+                merge_copy_option!(target_physics.layout_mode, physics_dto.layout_mode);
+                merge_copy_option!(target_physics.plane_z, physics_dto.plane_z);
+                merge_copy_option!(target_physics.force_model, physics_dto.force_model);
+                merge_copy_option!(target_physics.gravity, physics_dto.gravity);
+                merge_copy_option!(target_physics.strong_gravity, physics_dto.strong_gravity);
+                merge_copy_option!(target_physics.edge_weight_normalization, physics_dto.edge_weight_normalization);
+                merge_copy_option!(target_physics.cluster_separation, physics_dto.cluster_separation);
                 merge_copy_option!(target_physics.damping, physics_dto.damping);
                 merge_copy_option!(target_physics.enable_bounds, physics_dto.enable_bounds);
                 merge_copy_option!(target_physics.enabled, physics_dto.enabled);
@@ -428,6 +497,7 @@ async fn update_user_settings(
         })};
         if client_payload.openai.is_some() { settings.openai = client_payload.openai.map(|dto| crate::config::OpenAISettings {
             api_key: dto.api_key, base_url: dto.base_url, timeout: dto.timeout, rate_limit: dto.rate_limit,
+            tts_model: dto.tts_model, tts_voice: dto.tts_voice, tts_speed: dto.tts_speed,
         })};
         if client_payload.kokoro.is_some() { settings.kokoro = client_payload.kokoro.map(|dto| crate::config::KokoroSettings {
             api_url: dto.api_url, default_voice: dto.default_voice, default_format: dto.default_format,
@@ -440,12 +510,15 @@ async fn update_user_settings(
         match state.settings_addr.send(UpdateSettings { settings: settings.clone() }).await {
             Ok(Ok(())) => {
                 info!("Power user {} updated global settings", pubkey);
-                let updated_ui_settings = convert_to_ui_settings(&settings);
+                state.event_bus.publish(SettingsEvent::GlobalUpdated { pubkey: pubkey.clone() });
+                recolor_graph(&state, &settings);
+                resize_world(&state, &settings);
+                let updated_ui_settings = with_device_override(&req, &pubkey, convert_to_ui_settings(&settings));
                 Ok(HttpResponse::Ok().json(updated_ui_settings))
             }
-            Ok(Err(e)) => {
-                error!("Failed to update settings for power user {}: {}", pubkey, e);
-                Ok(HttpResponse::InternalServerError().body(format!("Failed to save settings: {}", e)))
+            Ok(Err(errors)) => {
+                warn!("Rejected settings update from power user {}: {}", pubkey, errors);
+                Ok(invalid_settings_response(&errors))
             }
             Err(e) => {
                 error!("Settings actor mailbox error for power user {}: {}", pubkey, e);
@@ -476,6 +549,7 @@ async fn update_user_settings(
                 merge_copy_option!(target_nodes.enable_hologram, nodes_dto.enable_hologram);
                 merge_copy_option!(target_nodes.enable_metadata_shape, nodes_dto.enable_metadata_shape);
                 merge_copy_option!(target_nodes.enable_metadata_visualisation, nodes_dto.enable_metadata_visualisation);
+                merge_copy_option!(target_nodes.color_mode, nodes_dto.color_mode);
             }
             if let Some(edges_dto) = vis_dto.edges { // edges_dto is ClientEdgeSettings
                 let target_edges = &mut target_vis.edges;
@@ -492,7 +566,17 @@ async fn update_user_settings(
                 let target_physics = &mut target_vis.physics; // Type: config::PhysicsSettings
                 merge_copy_option!(target_physics.attraction_strength, physics_dto.attraction_strength);
                 merge_copy_option!(target_physics.bounds_size, physics_dto.bounds_size);
+                merge_copy_option!(target_physics.auto_fit_bounds, physics_dto.auto_fit_bounds);
+                merge_copy_option!(target_physics.max_bounds_size, physics_dto.max_bounds_size);
                 merge_copy_option!(target_physics.collision_radius, physics_dto.collision_radius);
+                merge_copy_option!(target_physics.collision_stiffness, physics_dto.collision_stiffness);
+                merge_copy_option!(target_physics.layout_mode, physics_dto.layout_mode);
+                merge_copy_option!(target_physics.plane_z, physics_dto.plane_z);
+                merge_copy_option!(target_physics.force_model, physics_dto.force_model);
+                merge_copy_option!(target_physics.gravity, physics_dto.gravity);
+                merge_copy_option!(target_physics.strong_gravity, physics_dto.strong_gravity);
+                merge_copy_option!(target_physics.edge_weight_normalization, physics_dto.edge_weight_normalization);
+                merge_copy_option!(target_physics.cluster_separation, physics_dto.cluster_separation);
                 merge_copy_option!(target_physics.damping, physics_dto.damping);
                 merge_copy_option!(target_physics.enable_bounds, physics_dto.enable_bounds);
                 merge_copy_option!(target_physics.enabled, physics_dto.enabled);
@@ -653,8 +737,223 @@ async fn update_user_settings(
         }
 
         debug!("User {} updated their settings", pubkey);
-        Ok(HttpResponse::Ok().json(&user_settings.settings))
+        state.event_bus.publish(SettingsEvent::UserUpdated { pubkey: pubkey.clone() });
+        let ui_settings = match device_profile(&req) {
+            Some(device) => user_settings.apply_device_override(user_settings.settings.clone(), device),
+            None => user_settings.settings,
+        };
+        Ok(HttpResponse::Ok().json(ui_settings))
+    }
+}
+
+// Applies a JSON Merge Patch (RFC 7386) to the caller's settings: the global settings
+// for power users, their own UISettings otherwise. Responds with only what changed.
+async fn patch_settings(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    feature_access: web::Data<FeatureAccess>,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    // Read from raw bytes so `application/merge-patch+json` is accepted as well as JSON
+    let patch: Value = match serde_json::from_slice(&body) {
+        Ok(patch @ Value::Object(_)) => patch,
+        Ok(_) => return Ok(HttpResponse::BadRequest().json(json!({
+            "status": "error",
+            "message": "Settings patch must be a JSON object"
+        }))),
+        Err(e) => return Ok(HttpResponse::BadRequest().json(json!({
+            "status": "error",
+            "message": format!("Invalid JSON: {}", e)
+        }))),
+    };
+
+    let pubkey = match req.headers().get("X-Nostr-Pubkey") {
+        Some(value) => value.to_str().unwrap_or("").to_string(),
+        None => {
+            warn!("Settings patch received without Nostr pubkey.");
+            return Ok(HttpResponse::BadRequest().body("Missing Nostr pubkey for settings update"));
+        }
+    };
+    if !feature_access.can_sync_settings(&pubkey) {
+        warn!("User {} attempted patch_settings without permission", pubkey);
+        return Ok(HttpResponse::Forbidden().body("Settings sync not enabled for this user"));
+    }
+
+    if feature_access.is_power_user(&pubkey) {
+        let current = match state.settings_addr.send(GetSettings).await {
+            Ok(Ok(settings)) => settings,
+            Ok(Err(e)) => {
+                error!("Failed to get settings for patch: {}", e);
+                return Ok(HttpResponse::InternalServerError().body("Failed to get settings"));
+            }
+            Err(e) => {
+                error!("Settings actor mailbox error: {}", e);
+                return Ok(HttpResponse::InternalServerError().body("Settings service unavailable"));
+            }
+        };
+        let (settings, changed) = match apply_settings_patch(&current, &patch) {
+            Ok(patched) => patched,
+            Err(response) => return Ok(response),
+        };
+
+        match state.settings_addr.send(UpdateSettings { settings: settings.clone() }).await {
+            Ok(Ok(())) => {
+                info!("Power user {} patched global settings", pubkey);
+                state.event_bus.publish(SettingsEvent::GlobalUpdated { pubkey: pubkey.clone() });
+                recolor_graph(&state, &settings);
+                resize_world(&state, &settings);
+                Ok(HttpResponse::Ok().json(changed))
+            }
+            Ok(Err(errors)) => {
+                warn!("Rejected settings patch from power user {}: {}", pubkey, errors);
+                Ok(invalid_settings_response(&errors))
+            }
+            Err(e) => {
+                error!("Settings actor mailbox error for power user {}: {}", pubkey, e);
+                Ok(HttpResponse::InternalServerError().body("Settings service unavailable"))
+            }
+        }
+    } else {
+        let mut user_settings = UserSettings::load(&pubkey).unwrap_or_else(|| {
+            debug!("Creating new user settings for {}", pubkey);
+            UserSettings::new(&pubkey, UISettings::default())
+        });
+        let (settings, changed) = match apply_settings_patch(&user_settings.settings, &patch) {
+            Ok(patched) => patched,
+            Err(response) => return Ok(response),
+        };
+
+        user_settings.settings = settings;
+        user_settings.last_modified = Utc::now().timestamp();
+        if let Err(e) = user_settings.save() {
+            error!("Failed to save user settings for {}: {}", pubkey, e);
+            return Ok(HttpResponse::InternalServerError().body(format!("Failed to save user settings: {}", e)));
+        }
+
+        debug!("User {} patched their settings", pubkey);
+        state.event_bus.publish(SettingsEvent::UserUpdated { pubkey: pubkey.clone() });
+        Ok(HttpResponse::Ok().json(changed))
+    }
+}
+
+/// Patches `current` through its JSON form, returning the patched settings and the
+/// changed subtree with camelCase keys
+fn apply_settings_patch<T>(current: &T, patch: &Value) -> Result<(T, Value), HttpResponse>
+where
+    T: Serialize + DeserializeOwned,
+{
+    let bad_request = |message: String| HttpResponse::BadRequest().json(json!({
+        "status": "error",
+        "message": message
+    }));
+    let before = serde_json::to_value(current)
+        .map_err(|e| HttpResponse::InternalServerError().body(format!("Failed to serialize settings: {}", e)))?;
+
+    let mut patched = before.clone();
+    merge_patch::apply(&mut patched, patch).map_err(bad_request)?;
+    let settings: T = serde_json::from_value(patched)
+        .map_err(|e| bad_request(format!("Invalid settings patch: {}", e)))?;
+
+    // Diff the re-serialized settings so only values that actually took effect are reported
+    let after = serde_json::to_value(&settings)
+        .map_err(|e| HttpResponse::InternalServerError().body(format!("Failed to serialize settings: {}", e)))?;
+    let changed = merge_patch::diff(&before, &after).unwrap_or_else(|| json!({}));
+    Ok((settings, case_conversion::keys_to_camel_case(changed)))
+}
+
+// --- Per-device overrides ---
+
+/// Pubkey and device profile for the override endpoints, or the error response
+fn device_override_target(
+    req: &HttpRequest,
+    feature_access: &FeatureAccess,
+    profile: &str,
+) -> Result<(String, DeviceProfile), HttpResponse> {
+    let pubkey = match req.headers().get("X-Nostr-Pubkey") {
+        Some(value) => value.to_str().unwrap_or("").to_string(),
+        None => return Err(HttpResponse::BadRequest().body("Missing Nostr pubkey")),
+    };
+    if !feature_access.can_sync_settings(&pubkey) {
+        warn!("User {} attempted to manage device overrides without permission", pubkey);
+        return Err(HttpResponse::Forbidden().body("Settings sync not enabled for this user"));
+    }
+    let device = profile.parse::<DeviceProfile>()
+        .map_err(|e| HttpResponse::BadRequest().json(json!({ "status": "error", "message": e })))?;
+    Ok((pubkey, device))
+}
+
+async fn get_device_override(
+    req: HttpRequest,
+    feature_access: web::Data<FeatureAccess>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let (pubkey, device) = match device_override_target(&req, &feature_access, &path) {
+        Ok(target) => target,
+        Err(response) => return Ok(response),
+    };
+    let patch = UserSettings::load(&pubkey)
+        .and_then(|user| user.device_overrides.get(&device).cloned())
+        .unwrap_or_else(|| json!({}));
+    Ok(HttpResponse::Ok().json(patch))
+}
+
+// Replaces the override for one device. The patch is checked against the settings
+// structure so a typo is reported now rather than ignored on every sync.
+async fn put_device_override(
+    req: HttpRequest,
+    feature_access: web::Data<FeatureAccess>,
+    path: web::Path<String>,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let (pubkey, device) = match device_override_target(&req, &feature_access, &path) {
+        Ok(target) => target,
+        Err(response) => return Ok(response),
+    };
+    let patch: Value = match serde_json::from_slice(&body) {
+        Ok(patch @ Value::Object(_)) => patch,
+        _ => return Ok(HttpResponse::BadRequest().json(json!({
+            "status": "error",
+            "message": "Device override must be a JSON object"
+        }))),
+    };
+    if let Err(e) = user_settings::apply_override(&UISettings::default(), &patch) {
+        return Ok(HttpResponse::BadRequest().json(json!({ "status": "error", "message": e })));
     }
+
+    let mut user_settings = UserSettings::load(&pubkey)
+        .unwrap_or_else(|| UserSettings::new(&pubkey, UISettings::default()));
+    user_settings.device_overrides.insert(device, patch.clone());
+    user_settings.last_modified = Utc::now().timestamp();
+    if let Err(e) = user_settings.save() {
+        error!("Failed to save device override for {}: {}", pubkey, e);
+        return Ok(HttpResponse::InternalServerError().body(format!("Failed to save user settings: {}", e)));
+    }
+
+    debug!("User {} set their {} settings override", pubkey, device);
+    Ok(HttpResponse::Ok().json(patch))
+}
+
+async fn delete_device_override(
+    req: HttpRequest,
+    feature_access: web::Data<FeatureAccess>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let (pubkey, device) = match device_override_target(&req, &feature_access, &path) {
+        Ok(target) => target,
+        Err(response) => return Ok(response),
+    };
+    let Some(mut user_settings) = UserSettings::load(&pubkey) else {
+        return Ok(HttpResponse::NoContent().finish());
+    };
+    if user_settings.device_overrides.remove(&device).is_some() {
+        user_settings.last_modified = Utc::now().timestamp();
+        if let Err(e) = user_settings.save() {
+            error!("Failed to remove device override for {}: {}", pubkey, e);
+            return Ok(HttpResponse::InternalServerError().body(format!("Failed to save user settings: {}", e)));
+        }
+        debug!("User {} removed their {} settings override", pubkey, device);
+    }
+    Ok(HttpResponse::NoContent().finish())
 }
 
 // Handles updates from the older /user-settings endpoint (needs review/deprecation?)
@@ -720,6 +1019,7 @@ async fn update_settings( // This is the deprecated endpoint
             merge_copy_option!(target_vis.nodes.enable_hologram, nodes_dto.enable_hologram);
             merge_copy_option!(target_vis.nodes.enable_metadata_shape, nodes_dto.enable_metadata_shape);
             merge_copy_option!(target_vis.nodes.enable_metadata_visualisation, nodes_dto.enable_metadata_visualisation);
+            merge_copy_option!(target_vis.nodes.color_mode, nodes_dto.color_mode);
         }
         if let Some(edges_dto) = vis_dto.edges {
             let target_edges = &mut target_vis.edges;
@@ -735,7 +1035,17 @@ async fn update_settings( // This is the deprecated endpoint
             let target_physics = &mut target_vis.physics; // Type: config::PhysicsSettings
             merge_copy_option!(target_physics.attraction_strength, physics_dto.attraction_strength);
             merge_copy_option!(target_physics.bounds_size, physics_dto.bounds_size);
+            merge_copy_option!(target_physics.auto_fit_bounds, physics_dto.auto_fit_bounds);
+            merge_copy_option!(target_physics.max_bounds_size, physics_dto.max_bounds_size);
             merge_copy_option!(target_physics.collision_radius, physics_dto.collision_radius);
+            merge_copy_option!(target_physics.collision_stiffness, physics_dto.collision_stiffness);
+            merge_copy_option!(target_physics.layout_mode, physics_dto.layout_mode);
+            merge_copy_option!(target_physics.plane_z, physics_dto.plane_z);
+            merge_copy_option!(target_physics.force_model, physics_dto.force_model);
+            merge_copy_option!(target_physics.gravity, physics_dto.gravity);
+            merge_copy_option!(target_physics.strong_gravity, physics_dto.strong_gravity);
+            merge_copy_option!(target_physics.edge_weight_normalization, physics_dto.edge_weight_normalization);
+            merge_copy_option!(target_physics.cluster_separation, physics_dto.cluster_separation);
             merge_copy_option!(target_physics.damping, physics_dto.damping);
             merge_copy_option!(target_physics.enable_bounds, physics_dto.enable_bounds);
             merge_copy_option!(target_physics.enabled, physics_dto.enabled);
@@ -901,6 +1211,7 @@ if let Some(auth_dto) = client_payload.auth {
     })};
     if client_payload.openai.is_some() { settings.openai = client_payload.openai.map(|dto| crate::config::OpenAISettings {
         api_key: dto.api_key, base_url: dto.base_url, timeout: dto.timeout, rate_limit: dto.rate_limit,
+        tts_model: dto.tts_model, tts_voice: dto.tts_voice, tts_speed: dto.tts_speed,
     })};
     if client_payload.kokoro.is_some() { settings.kokoro = client_payload.kokoro.map(|dto| crate::config::KokoroSettings {
         api_url: dto.api_url, default_voice: dto.default_voice, default_format: dto.default_format,
@@ -913,12 +1224,15 @@ if let Some(auth_dto) = client_payload.auth {
     match state.settings_addr.send(UpdateSettings { settings: settings.clone() }).await {
         Ok(Ok(())) => {
             info!("Power user {} updated global settings via deprecated /user-settings endpoint", pubkey);
+            state.event_bus.publish(SettingsEvent::GlobalUpdated { pubkey: pubkey.clone() });
+            recolor_graph(&state, &settings);
+            resize_world(&state, &settings);
             let updated_ui_settings = convert_to_ui_settings(&settings);
             Ok(HttpResponse::Ok().json(updated_ui_settings))
         }
-        Ok(Err(e)) => {
-            error!("Failed to update settings in deprecated update: {}", e);
-            Ok(HttpResponse::InternalServerError().body(format!("Failed to save settings: {}", e)))
+        Ok(Err(errors)) => {
+            warn!("Rejected settings update in deprecated update: {}", errors);
+            Ok(invalid_settings_response(&errors))
         }
         Err(e) => {
             error!("Settings actor mailbox error in deprecated update: {}", e);
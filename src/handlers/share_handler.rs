@@ -0,0 +1,120 @@
+use crate::actors::messages::{GetGraphData, GetGraphRevision};
+use crate::app_state::AppState;
+use crate::errors::AppError;
+use crate::handlers::socket_flow_handler::{PreReadSocketSettings, SocketFlowServer};
+use crate::utils::auth::require_session;
+use crate::services::shares::{Share, ShareScope};
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use actix_web_actors::ws;
+use log::info;
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Debug, Deserialize)]
+pub struct CreateShareRequest {
+    #[serde(default)]
+    pub label: Option<String>,
+    #[serde(flatten)]
+    pub scope: ShareScope,
+}
+
+async fn require_power_user(req: &HttpRequest, state: &AppState) -> Result<String, HttpResponse> {
+    let pubkey = require_session(req, state).await?;
+    if !state.is_power_user(&pubkey) {
+        return Err(AppError::Forbidden("Only power users can manage shares".to_string()).error_response());
+    }
+    Ok(pubkey)
+}
+
+fn share_json(share: &Share) -> serde_json::Value {
+    json!({
+        "share": share,
+        "path": format!("/share/{}", share.token),
+    })
+}
+
+async fn create_share(req: HttpRequest, state: web::Data<AppState>, request: web::Json<CreateShareRequest>) -> Result<HttpResponse, AppError> {
+    let pubkey = match require_power_user(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(response) => return Ok(response),
+    };
+    let request = request.into_inner();
+    let share = state.shares.create(&pubkey, request.label, request.scope).await?;
+    Ok(HttpResponse::Created().json(share_json(&share)))
+}
+
+async fn list_shares(req: HttpRequest, state: web::Data<AppState>) -> Result<HttpResponse, AppError> {
+    let pubkey = match require_power_user(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(response) => return Ok(response),
+    };
+    let shares: Vec<_> = state.shares.list(&pubkey).await.iter().map(share_json).collect();
+    Ok(HttpResponse::Ok().json(json!({ "shares": shares })))
+}
+
+async fn revoke_share(req: HttpRequest, state: web::Data<AppState>, token: web::Path<String>) -> Result<HttpResponse, AppError> {
+    let pubkey = match require_power_user(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(response) => return Ok(response),
+    };
+    state.shares.revoke(&pubkey, &token).await?;
+    Ok(HttpResponse::NoContent().finish())
+}
+
+async fn resolve(state: &AppState, token: &str) -> Result<Share, AppError> {
+    state.shares.resolve(token).await.ok_or_else(|| AppError::NotFound(format!("Share {} not found", token)))
+}
+
+/// The shared part of the graph, for anyone holding the link
+async fn get_shared_graph(state: web::Data<AppState>, token: web::Path<String>) -> Result<HttpResponse, AppError> {
+    let share = resolve(&state, &token).await?;
+    let graph = state.graph_service_addr.send(GetGraphData).await?.map_err(AppError::graph)?;
+    Ok(HttpResponse::Ok().json(json!({
+        "label": share.label,
+        "scope": share.scope,
+        "graph": share.scope.apply(&graph),
+    })))
+}
+
+/// Position updates for the shared nodes. The connection is read-only: drags,
+/// position uploads and requests reaching beyond the share are refused.
+async fn shared_socket(
+    req: HttpRequest,
+    stream: web::Payload,
+    state: web::Data<AppState>,
+    pre_read_ws_settings: web::Data<PreReadSocketSettings>,
+    token: web::Path<String>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let share = resolve(&state, &token).await?;
+    let revision = state.graph_service_addr.send(GetGraphRevision).await.map_err(AppError::from)?;
+    let graph = state.graph_service_addr.send(GetGraphData).await.map_err(AppError::from)?.map_err(AppError::graph)?;
+    let node_ids = share.scope.node_ids(&graph);
+    info!("[WebSocket] Shared view connecting with {} nodes", node_ids.len());
+
+    let state = state.into_inner();
+    let client_manager_addr = state.client_manager_addr.clone();
+    let server = SocketFlowServer::new(state, pre_read_ws_settings.get_ref().clone(), client_manager_addr)
+        .read_only(share.token, node_ids, revision);
+    ws::WsResponseBuilder::new(server, &req, stream)
+        .protocols(&["permessage-deflate"])
+        .start()
+}
+
+/// Managing shares, under `/api`
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/shares")
+            .route(web::post().to(create_share))
+            .route(web::get().to(list_shares))
+    ).service(
+        web::resource("/shares/{token}")
+            .route(web::delete().to(revoke_share))
+    );
+}
+
+/// The public side of shares, under `/share`. Nothing here changes the vault,
+/// the graph or settings.
+pub fn public_config(cfg: &mut web::ServiceConfig) {
+    cfg.route("/{token}", web::get().to(get_shared_graph))
+        .route("/{token}/wss", web::get().to(shared_socket));
+}
@@ -1,15 +1,28 @@
 use actix::{prelude::*, Actor, Handler, Message};
 use actix_web::{web, Error, HttpRequest, HttpResponse};
 use actix_web_actors::ws;
+use glam::Vec3;
 use log::{trace, debug, error, info, warn};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::time::Instant;
+use bytes::Bytes;
 
+use crate::actors::messages::{
+    EdgeFrameUpdate, GetEdgeFrame, GetGraphAggregation, GetGraphData, GetGraphRevision, GrabNode, HandOverSession,
+    MoveGrabbedNode, ReleaseClientGrabs, GetWorldBounds, ReleaseNode, ResumeSession, SuspendSession,
+};
 use crate::app_state::AppState;
-use crate::utils::binary_protocol;
+use crate::config::data_dirs::DataDirs;
+use crate::services::replay::{self, RecordedFrame};
+use crate::services::event_bus::{AppEvent, GraphEvent};
+use crate::services::world_bounds::WorldBounds;
+use crate::models::graph_aggregation::{is_supernode_id, GraphAggregation};
+use crate::utils::binary_protocol::{self, DecodeError, EncodedFrame, QuantizationBounds, WireEncoding, WireNodeDataItem, WIRE_NODE_SIZE};
+use crate::utils::protocol::{self, FrameKind, PROTOCOL_VERSION};
 use crate::types::vec3::Vec3Data;
 use crate::utils::socket_flow_messages::{BinaryNodeData, PingMessage, PongMessage};
+use crate::utils::view_culling::{CameraPose, ViewCuller};
 
 // Constants for throttling debug logs
 const DEBUG_LOG_SAMPLE_RATE: usize = 10; // Only log 1 in 10 updates
@@ -17,8 +30,19 @@ const DEBUG_LOG_SAMPLE_RATE: usize = 10; // Only log 1 in 10 updates
 // Default values for deadbands if not provided in settings
 const DEFAULT_POSITION_DEADBAND: f32 = 0.01; // 1cm deadband
 const DEFAULT_VELOCITY_DEADBAND: f32 = 0.005; // 5mm/s deadband
-// Default values for dynamic update rate
-const BATCH_UPDATE_WINDOW_MS: u64 = 200;  // Check motion every 200ms
+// Dynamic update rate
+const INTERACTION_HOLD_MS: u64 = 500; // Stay at the max rate this long after a client drag
+const RTT_SMOOTHING: f64 = 0.2; // Weight of the newest heartbeat RTT sample
+const FRAMES_PER_RTT: f64 = 2.0; // Frames allowed in flight per round trip
+const DEFAULT_SUPERNODE_CELL_SIZE: f32 = 5.0; // Grid cell size when enableLevelOfDetail gives none
+const MAX_REPLAY_SPEED: f32 = 10.0; // Fastest a recording is played back, as a multiple of its pace
+const REPLAY_LOOP_PAUSE_MS: u64 = 1000; // Gap before a looping replay starts over
+// Requests a shared, read-only connection refuses: they change the graph or reveal
+// more of it than the share covers
+const READ_ONLY_REFUSED: &[&str] = &[
+    "grabStart", "grabMove", "grabEnd", "subscribeEdges", "enableLevelOfDetail",
+    "expandSupernode", "collapseSupernode", "startReplay",
+];
 
 // Note: Now using u32 node IDs throughout the system
 
@@ -31,10 +55,57 @@ pub struct PreReadSocketSettings {
     pub motion_damping: f32,
     pub heartbeat_interval_ms: u64, // Added for heartbeat
     pub heartbeat_timeout_ms: u64,  // Added for heartbeat
+    // Volume offered to clients that negotiate quantized positions
+    pub bounds_size: f32,
+    pub max_velocity: f32,
 }
 
 // Old ClientManager struct removed - now using ClientManagerActor
 
+/// Supernode aggregation a client has switched to, and the supernodes it expanded
+struct LevelOfDetail {
+    aggregation: Arc<GraphAggregation>,
+    expanded: HashSet<usize>,
+}
+
+/// The share a read-only connection was opened through, and the nodes it covers
+/// in graph `revision`
+struct SharedView {
+    token: String,
+    node_ids: HashSet<u32>,
+    revision: u64,
+}
+
+/// A recording being streamed in place of the live frames
+struct ReplayPlayback {
+    frames: Vec<RecordedFrame>,
+    next: usize,
+    speed: f32,
+    looping: bool,
+    timer: Option<SpawnHandle>,
+}
+
+/// What a client had set up on a connection that dropped; restored when it reconnects
+/// with the connection's resume token
+pub struct SuspendedSession {
+    encoding: WireEncoding,
+    framed: bool,
+    view_culler: Option<ViewCuller>,
+    level_of_detail: Option<LevelOfDetail>,
+    edge_updates_enabled: bool,
+    // Deadband state for the nodes the client is known to have received
+    last_sent_positions: HashMap<u32, Vec3Data>,
+    last_sent_velocities: HashMap<u32, Vec3Data>,
+    rtt: Option<std::time::Duration>,
+}
+
+impl LevelOfDetail {
+    /// Supernode standing in for the node, unless the node is shown on its own
+    fn collapsed_supernode(&self, node_id: u32) -> Option<usize> {
+        self.aggregation.supernode_of(node_id).filter(|index| !self.expanded.contains(index))
+    }
+}
+
 // Message to set client ID after registration
 #[derive(Message)]
 #[rtype(result = "()")]
@@ -50,38 +121,73 @@ impl Handler<SetClientId> for SocketFlowServer {
     }
 }
 
-// Implement handler for BroadcastPositionUpdate message
-impl Handler<BroadcastPositionUpdate> for SocketFlowServer {
+/// The world bounds changed. Quantized frames are encoded against the new extent
+/// from here on, so the notification goes out before any of them.
+#[derive(Message)]
+#[rtype(result = "()")]
+struct RescaleWorld(WorldBounds);
+
+impl Handler<RescaleWorld> for SocketFlowServer {
     type Result = ();
 
-    fn handle(&mut self, msg: BroadcastPositionUpdate, ctx: &mut Self::Context) -> Self::Result {
-        if !msg.0.is_empty() {
-            // Encode the binary message
-            let binary_data = binary_protocol::encode_node_data(&msg.0);
-            
-            // Send to client directly (permessage-deflate handles compression)
-            ctx.binary(binary_data);
-            
-            // Debug logging - limit to avoid spamming logs
-            if self.should_log_update() {
-                trace!("[WebSocket] Position update sent: {} nodes", msg.0.len());
-            }
-        }
+    fn handle(&mut self, msg: RescaleWorld, ctx: &mut Self::Context) {
+        self.quantization_bounds.half_extent = msg.0.half_extent;
+        let event = serde_json::json!({
+            "type": "serverEvent",
+            "payload": AppEvent::from(GraphEvent::Bounds(msg.0))
+        });
+        ctx.text(event.to_string());
     }
 }
-/// Message type for broadcasting position updates to clients
-#[derive(Message, Clone)]
-#[rtype(result = "()")]
-pub struct BroadcastPositionUpdate(pub Vec<(u32, BinaryNodeData)>);
 
 // Import the new messages
-use crate::actors::messages::{SendToClientBinary, SendToClientText};
+use crate::actors::messages::{SendEdgeFrame, SendPositionFrame, SendToClientText};
 
-impl Handler<SendToClientBinary> for SocketFlowServer {
+impl Handler<SendPositionFrame> for SocketFlowServer {
     type Result = ();
 
-    fn handle(&mut self, msg: SendToClientBinary, ctx: &mut Self::Context) {
-        ctx.binary(msg.0);
+    fn handle(&mut self, msg: SendPositionFrame, ctx: &mut Self::Context) {
+        if !self.position_updates_enabled || self.replay.is_some() {
+            return;
+        }
+        self.update_dynamic_rate(msg.kinetic_energy);
+        if self.last_frame_sequence == Some(msg.frame.sequence()) {
+            return;
+        }
+        // Frames arriving faster than this client's rate are skipped without being
+        // marked as handled, so the latest positions still go out on the next tick
+        if let Some(last_sent) = self.last_position_send {
+            if last_sent.elapsed() < self.get_current_update_interval() {
+                return;
+            }
+        }
+        self.last_frame_sequence = Some(msg.frame.sequence());
+        self.send_position_frame(&msg.frame, msg.detailed_debug, ctx);
+    }
+}
+
+impl Handler<SendEdgeFrame> for SocketFlowServer {
+    type Result = ();
+
+    fn handle(&mut self, msg: SendEdgeFrame, ctx: &mut Self::Context) {
+        if self.edge_updates_enabled {
+            self.send_edge_frame(msg.0, ctx);
+        }
+    }
+}
+
+impl Handler<HandOverSession> for SocketFlowServer {
+    type Result = Option<SuspendedSession>;
+
+    fn handle(&mut self, _msg: HandOverSession, ctx: &mut Self::Context) -> Self::Result {
+        if !self.position_updates_enabled || self.session_handed_over {
+            return None;
+        }
+        info!("[WebSocket] Handing session over to reconnected client");
+        self.session_handed_over = true;
+        let session = self.suspend();
+        ctx.stop();
+        Some(session)
     }
 }
 
@@ -89,7 +195,10 @@ impl Handler<SendToClientText> for SocketFlowServer {
     type Result = ();
 
     fn handle(&mut self, msg: SendToClientText, ctx: &mut Self::Context) {
-        ctx.text(msg.0);
+        // Broadcast notices name pages and nodes outside the share
+        if self.shared.is_none() {
+            ctx.text(msg.0);
+        }
     }
 }
 
@@ -103,10 +212,40 @@ pub struct SocketFlowServer {
     heartbeat_timer_set: bool, // Flag to track if heartbeat timer is set
     // Fields for batched updates and deadband filtering
     _node_position_cache: HashMap<String, BinaryNodeData>, // Dead Code: Field is never read
-    last_sent_positions: HashMap<String, Vec3Data>,
-    last_sent_velocities: HashMap<String, Vec3Data>,
+    last_sent_positions: HashMap<u32, Vec3Data>,
+    last_sent_velocities: HashMap<u32, Vec3Data>,
+    // Frame indices of nodes to send this tick; kept to reuse its allocation
+    changed_indices: Vec<usize>,
+    // Set once the client requests data; until then broadcaster frames are ignored
+    position_updates_enabled: bool,
+    last_frame_sequence: Option<u64>,
+    last_position_send: Option<Instant>,
     position_deadband: f32, // Minimum position change to trigger an update
     velocity_deadband: f32, // Minimum velocity change to trigger an update
+    // Frame format negotiated in requestInitialData
+    encoding: WireEncoding,
+    // Set when the client asked for versioned frames; every binary frame then starts with a header
+    framed: bool,
+    quantization_bounds: QuantizationBounds,
+    // Set from the client's cameraPose messages; without one every node is streamed
+    view_culler: Option<ViewCuller>,
+    // Set once the client enables level of detail; collapsed members are streamed as supernodes
+    level_of_detail: Option<LevelOfDetail>,
+    // Set by startReplay; live frames are ignored until it finishes or is stopped
+    replay: Option<ReplayPlayback>,
+    // Set for connections opened through a share link; only these nodes are streamed
+    // and nothing the client sends changes the graph
+    shared: Option<SharedView>,
+    // Set by subscribeEdges once the edge snapshot has been sent
+    edge_updates_enabled: bool,
+    sent_edge_type_count: usize,
+    // Presented by the client on reconnect to resume this session
+    resume_token: String,
+    session_handed_over: bool,
+    // Heartbeat pings carry a sequence number; a pong confirms everything sent before it
+    pings_sent: u64,
+    // Nodes sent since the last acknowledged ping, with the ping count when sent
+    unacked_nodes: HashMap<u32, u64>,
     // Performance metrics
     last_transfer_size: usize,
     last_transfer_time: Instant,
@@ -115,18 +254,17 @@ pub struct SocketFlowServer {
     nodes_sent_count: usize,
     
     // Dynamic update rate fields
-    last_batch_time: Instant, // Last time we sent a batch of updates
     current_update_rate: u32,  // Current rate in updates per second
+    last_interaction: Option<Instant>, // Last binary position update (drag) from the client
+    heartbeat_sent_at: Option<Instant>, // Outstanding server ping, for measuring RTT
+    rtt: Option<std::time::Duration>,   // Smoothed heartbeat round-trip time
     // Store pre-read settings directly
     min_update_rate: u32,
     max_update_rate: u32,
-    motion_threshold: f32,
+    motion_threshold: f32, // Mean kinetic energy per node at which the max rate is used
     motion_damping: f32,
     // heartbeat_interval_ms: u64, // Unused
     // heartbeat_timeout_ms: u64, // Unused
-    nodes_in_motion: usize,    // Counter for nodes currently in motion
-    total_node_count: usize,   // Total node count for percentage calculation
-    last_motion_check: Instant, // Last time we checked motion percentage,
 }
 
 impl SocketFlowServer {
@@ -156,27 +294,55 @@ impl SocketFlowServer {
             _node_position_cache: HashMap::new(), // Dead Code: Field is never read
             last_sent_positions: HashMap::new(),
             last_sent_velocities: HashMap::new(),
+            changed_indices: Vec::new(),
+            position_updates_enabled: false,
+            last_frame_sequence: None,
+            last_position_send: None,
             position_deadband,
             velocity_deadband,
+            encoding: WireEncoding::Float32,
+            framed: false,
+            quantization_bounds: QuantizationBounds {
+                half_extent: pre_read_settings.bounds_size,
+                max_velocity: pre_read_settings.max_velocity,
+            },
+            view_culler: None,
+            level_of_detail: None,
+            replay: None,
+            shared: None,
+            edge_updates_enabled: false,
+            sent_edge_type_count: 0,
+            resume_token: uuid::Uuid::new_v4().to_string(),
+            session_handed_over: false,
+            pings_sent: 0,
+            unacked_nodes: HashMap::new(),
             last_transfer_size: 0,
             last_transfer_time: Instant::now(),
             total_bytes_sent: 0,
-            last_batch_time: Instant::now(),
             update_count: 0,
             nodes_sent_count: 0,
             current_update_rate,
+            last_interaction: None,
+            heartbeat_sent_at: None,
+            rtt: None,
             min_update_rate,
             max_update_rate,
             motion_threshold,
             motion_damping,
             // heartbeat_interval_ms, // Unused
             // heartbeat_timeout_ms, // Unused
-            nodes_in_motion: 0,
-            total_node_count: 0,
-            last_motion_check: Instant::now()
         }
     }
 
+    /// Makes this a read-only connection for the share `token`, streaming only
+    /// `node_ids`, the shared nodes of graph `revision`. The nodes are looked up
+    /// again when the graph changes, and the connection is closed once the share
+    /// is revoked.
+    pub fn read_only(mut self, token: String, node_ids: HashSet<u32>, revision: u64) -> Self {
+        self.shared = Some(SharedView { token, node_ids, revision });
+        self
+    }
+
     fn handle_ping(&mut self, msg: PingMessage) -> PongMessage {
         self.last_ping = Some(msg.timestamp);
         PongMessage {
@@ -193,8 +359,8 @@ impl SocketFlowServer {
     }
     
     // Check if a node's position or velocity has changed enough to warrant an update
-    fn has_node_changed_significantly(&mut self, node_id: &str, new_position: Vec3Data, new_velocity: Vec3Data) -> bool {
-        let position_changed = if let Some(last_position) = self.last_sent_positions.get(node_id) {
+    fn has_node_changed_significantly(&self, node_id: u32, new_position: Vec3Data, new_velocity: Vec3Data) -> bool {
+        let position_changed = if let Some(last_position) = self.last_sent_positions.get(&node_id) {
             // Calculate Euclidean distance between last sent position and new position
             let dx = new_position.x - last_position.x;
             let dy = new_position.y - last_position.y;
@@ -208,7 +374,7 @@ impl SocketFlowServer {
             true
         };
         
-        let velocity_changed = if let Some(last_velocity) = self.last_sent_velocities.get(node_id) {
+        let velocity_changed = if let Some(last_velocity) = self.last_sent_velocities.get(&node_id) {
             // Calculate velocity change magnitude
             let dvx = new_velocity.x - last_velocity.x;
             let dvy = new_velocity.y - last_velocity.y;
@@ -222,14 +388,98 @@ impl SocketFlowServer {
             true
         };
         
-        // Update stored values if changed
-        if position_changed || velocity_changed {
-            self.last_sent_positions.insert(node_id.to_string(), new_position);
-            self.last_sent_velocities.insert(node_id.to_string(), new_velocity);
-            return true;
+        position_changed || velocity_changed
+    }
+
+    // Remember what the client was last sent for a node
+    fn record_sent_node(&mut self, node_id: u32, position: Vec3Data, velocity: Vec3Data) {
+        self.last_sent_positions.insert(node_id, position);
+        self.last_sent_velocities.insert(node_id, velocity);
+        self.unacked_nodes.insert(node_id, self.pings_sent);
+    }
+
+    // A pong for ping `n` means every frame sent before that ping was received
+    fn acknowledge_ping(&mut self, payload: &[u8]) {
+        let Ok(bytes) = <[u8; 8]>::try_from(payload) else {
+            return;
+        };
+        let acknowledged = u64::from_le_bytes(bytes);
+        self.unacked_nodes.retain(|_, sent_after| *sent_after >= acknowledged);
+    }
+
+    /// Packs up this connection's state for a later resume. Nodes the client hasn't
+    /// acknowledged are dropped from the deadband state so they are sent again.
+    fn suspend(&mut self) -> SuspendedSession {
+        for node_id in self.unacked_nodes.keys() {
+            self.last_sent_positions.remove(node_id);
+            self.last_sent_velocities.remove(node_id);
+        }
+        self.unacked_nodes.clear();
+        self.position_updates_enabled = false;
+
+        SuspendedSession {
+            encoding: self.encoding,
+            framed: self.framed,
+            view_culler: self.view_culler.take(),
+            level_of_detail: self.level_of_detail.take(),
+            edge_updates_enabled: self.edge_updates_enabled,
+            last_sent_positions: std::mem::take(&mut self.last_sent_positions),
+            last_sent_velocities: std::mem::take(&mut self.last_sent_velocities),
+            rtt: self.rtt,
+        }
+    }
+
+    /// Continues a suspended session; the next frame carries only what changed since
+    /// the client's last acknowledged state
+    fn restore_session(&mut self, session: SuspendedSession, ctx: &mut <Self as Actor>::Context) {
+        self.encoding = session.encoding;
+        self.framed = session.framed;
+        self.view_culler = session.view_culler;
+        self.level_of_detail = session.level_of_detail;
+        self.last_sent_positions = session.last_sent_positions;
+        self.last_sent_velocities = session.last_sent_velocities;
+        self.rtt = self.rtt.or(session.rtt);
+        self.last_frame_sequence = None;
+        self.last_position_send = None;
+        self.position_updates_enabled = true;
+        info!("[WebSocket] Resumed session with {} acknowledged nodes", self.last_sent_positions.len());
+
+        let mut response = serde_json::json!({
+            "type": "resumed",
+            "resumeToken": self.resume_token,
+            "encoding": self.encoding,
+            "levelOfDetail": self.level_of_detail.is_some(),
+            "edges": session.edge_updates_enabled,
+            "timestamp": chrono::Utc::now().timestamp_millis()
+        });
+        if self.encoding == WireEncoding::Quantized16 {
+            response["bounds"] = serde_json::json!(self.quantization_bounds);
+        }
+        if self.framed {
+            response["protocolVersion"] = serde_json::json!(PROTOCOL_VERSION);
+        }
+        ctx.text(response.to_string());
+
+        // Edge changes while disconnected aren't tracked, so start from a fresh snapshot
+        if session.edge_updates_enabled {
+            self.subscribe_edges(ctx);
         }
-        
-        false
+    }
+
+    fn subscribe_edges(&mut self, ctx: &mut <Self as Actor>::Context) {
+        let graph_addr = self.app_state.graph_service_addr.clone();
+        let fut = async move { graph_addr.send(GetEdgeFrame).await }
+            .into_actor(self)
+            .map(|result, act, ctx| match result {
+                Ok(Ok(snapshot)) => {
+                    act.edge_updates_enabled = true;
+                    act.sent_edge_type_count = 0;
+                    act.send_edge_frame(snapshot, ctx);
+                }
+                Ok(Err(e)) => error!("[WebSocket] Failed to get edge frame: {}", e),
+                Err(e) => error!("[WebSocket] GraphServiceActor unavailable for edges: {}", e),
+            });
+        ctx.spawn(fut);
     }
 
     // Calculate the current update interval based on the dynamic rate
@@ -238,43 +488,446 @@ impl SocketFlowServer {
         std::time::Duration::from_millis(millis)
     }
     
-    // Calculate the percentage of nodes in motion
-    fn calculate_motion_percentage(&self) -> f32 {
-        if self.total_node_count == 0 {
-            return 0.0;
+    // Update the dynamic rate from the graph's activity, recent drags and the client's RTT
+    fn update_dynamic_rate(&mut self, kinetic_energy: f32) {
+        let interacting = self.last_interaction
+            .map(|at| at.elapsed() < std::time::Duration::from_millis(INTERACTION_HOLD_MS))
+            .unwrap_or(false);
+        let target = target_update_rate(
+            self.min_update_rate,
+            self.max_update_rate,
+            kinetic_energy / self.motion_threshold.max(f32::EPSILON),
+            interacting,
+            self.rtt,
+        );
+
+        if target >= self.current_update_rate {
+            // Speed up straight away so drags and new motion feel responsive
+            self.current_update_rate = target;
+        } else {
+            // Slow down gradually as the graph settles
+            self.current_update_rate = ((self.current_update_rate as f32) * self.motion_damping +
+                                       (target as f32) * (1.0 - self.motion_damping)) as u32;
         }
-        
-        (self.nodes_in_motion as f32) / (self.total_node_count as f32)
+
+        // Ensure rate stays within min and max bounds
+        self.current_update_rate = self.current_update_rate.clamp(self.min_update_rate, self.max_update_rate.max(self.min_update_rate));
     }
-    
-    // Update the dynamic rate based on current motion
-    fn update_dynamic_rate(&mut self) {
-        // Only recalculate periodically to avoid rapid changes
-        let now = Instant::now();
-        let batch_window = std::time::Duration::from_millis(BATCH_UPDATE_WINDOW_MS);
-        let elapsed = now.duration_since(self.last_batch_time);
-        
-        // If we've waited at least the batch window time, or this is the first update
-        if elapsed >= batch_window {
-            // Calculate the current motion percentage
-            let motion_pct = self.calculate_motion_percentage();
-            
-            // Adjust the update rate based on the motion percentage
-            if motion_pct > self.motion_threshold {
-                // Gradually increase rate for high motion scenarios
-                self.current_update_rate = ((self.current_update_rate as f32) * self.motion_damping + 
-                                           (self.max_update_rate as f32) * (1.0 - self.motion_damping)) as u32;
-            } else {
-                // Gradually decrease rate for low motion scenarios
-                self.current_update_rate = ((self.current_update_rate as f32) * self.motion_damping + 
-                                           (self.min_update_rate as f32) * (1.0 - self.motion_damping)) as u32;
+
+    fn record_heartbeat_rtt(&mut self) {
+        if let Some(sent_at) = self.heartbeat_sent_at.take() {
+            let sample = sent_at.elapsed();
+            self.rtt = Some(match self.rtt {
+                Some(rtt) => rtt.mul_f64(1.0 - RTT_SMOOTHING) + sample.mul_f64(RTT_SMOOTHING),
+                None => sample,
+            });
+        }
+    }
+
+    /// Picks the frame format for a client's requested encoding. Unknown values, and
+    /// quantization without usable bounds, fall back to full-precision floats.
+    fn negotiate_encoding(&self, requested: Option<&serde_json::Value>) -> WireEncoding {
+        let Some(requested) = requested else {
+            return WireEncoding::Float32;
+        };
+        match serde_json::from_value::<WireEncoding>(requested.clone()) {
+            Ok(WireEncoding::Quantized16) if !self.quantization_bounds.is_valid() => {
+                warn!("[WebSocket] Quantized positions requested but bounds {:?} are unusable; sending float32",
+                    self.quantization_bounds);
+                WireEncoding::Float32
             }
-            
-            // Ensure rate stays within min and max bounds
-            self.current_update_rate = self.current_update_rate.clamp(self.min_update_rate, self.max_update_rate);
-            
-            // Update the last motion check time
-            self.last_motion_check = now;
+            Ok(encoding) => {
+                info!("[WebSocket] Client using {:?} position encoding", encoding);
+                encoding
+            }
+            Err(_) => {
+                warn!("[WebSocket] Unknown position encoding {}; sending float32", requested);
+                WireEncoding::Float32
+            }
+        }
+    }
+
+    /// Supernode records to send this frame, at their members' current centroid and
+    /// mean velocity
+    fn changed_supernodes(&mut self, sums: &[(Vec3, Vec3, u32)]) -> Vec<WireNodeDataItem> {
+        let Some(lod) = &self.level_of_detail else {
+            return Vec::new();
+        };
+        let candidates: Vec<WireNodeDataItem> = sums.iter()
+            .zip(&lod.aggregation.supernodes)
+            .filter(|((_, _, count), _)| *count > 0)
+            .map(|((position, velocity, count), supernode)| WireNodeDataItem {
+                id: supernode.id,
+                position: (*position / *count as f32).into(),
+                velocity: (*velocity / *count as f32).into(),
+            })
+            .collect();
+
+        let mut items = Vec::new();
+        for item in candidates {
+            if let Some(culler) = &self.view_culler {
+                if !culler.is_visible(item.position) {
+                    continue;
+                }
+            }
+            if self.has_node_changed_significantly(item.id, item.position, item.velocity) {
+                self.record_sent_node(item.id, item.position, item.velocity);
+                items.push(item);
+            }
+        }
+        items
+    }
+
+    /// Switches the client to a new aggregation with every supernode collapsed
+    fn enable_level_of_detail(&mut self, aggregation: Arc<GraphAggregation>, ctx: &mut <Self as Actor>::Context) {
+        // Supernode ids from an earlier aggregation may now mean different nodes
+        self.last_sent_positions.retain(|id, _| !is_supernode_id(*id));
+        self.last_sent_velocities.retain(|id, _| !is_supernode_id(*id));
+
+        let response = serde_json::json!({
+            "type": "supernodes",
+            "cellSize": aggregation.cell_size,
+            "supernodes": aggregation.supernodes,
+        });
+        info!("[WebSocket] Level of detail enabled with {} supernodes", aggregation.supernodes.len());
+        self.level_of_detail = Some(LevelOfDetail { aggregation, expanded: HashSet::new() });
+        ctx.text(response.to_string());
+    }
+
+    /// Expands or collapses the supernode with the given id, replying with the outcome
+    fn set_supernode_expanded(&mut self, supernode_id: Option<u32>, expanded: bool, ctx: &mut <Self as Actor>::Context) {
+        let Some(lod) = &mut self.level_of_detail else {
+            ctx.text(serde_json::json!({
+                "type": "error",
+                "message": "Level of detail is not enabled"
+            }).to_string());
+            return;
+        };
+        let Some((id, index)) = supernode_id.and_then(|id| lod.aggregation.index_of(id).map(|index| (id, index))) else {
+            ctx.text(serde_json::json!({
+                "type": "error",
+                "message": format!("Unknown supernode {:?}", supernode_id)
+            }).to_string());
+            return;
+        };
+
+        let response = if expanded {
+            lod.expanded.insert(index);
+            serde_json::json!({
+                "type": "supernodeExpanded",
+                "id": id,
+                "members": lod.aggregation.supernodes[index].members,
+            })
+        } else {
+            lod.expanded.remove(&index);
+            // Send the supernode again even if its members haven't moved
+            self.last_sent_positions.remove(&id);
+            self.last_sent_velocities.remove(&id);
+            serde_json::json!({
+                "type": "supernodeCollapsed",
+                "id": id,
+            })
+        };
+        ctx.text(response.to_string());
+    }
+
+    /// Runs one step of the grabStart/grabMove/grabEnd drag lifecycle. Starts and
+    /// ends are answered so the client knows whether it holds the node; moves are
+    /// only answered when the client has lost its grab.
+    fn handle_drag(&mut self, msg: &serde_json::Value, ctx: &mut <Self as Actor>::Context) {
+        let node_id = msg.get("nodeId").and_then(|v| v.as_u64()).and_then(|id| u32::try_from(id).ok());
+        let (Some(node_id), Some(client_id)) = (node_id, self.client_id) else {
+            ctx.text(serde_json::json!({
+                "type": "error",
+                "message": "Drags need a numeric nodeId on a registered connection"
+            }).to_string());
+            return;
+        };
+        // Dragging counts as interaction, so positions stream at the max rate
+        self.last_interaction = Some(Instant::now());
+        let vector = |key: &str| msg.get(key).and_then(|v| serde_json::from_value::<Vec3Data>(v.clone()).ok());
+
+        let graph_addr = self.app_state.graph_service_addr.clone();
+        match msg.get("type").and_then(|t| t.as_str()) {
+            Some("grabStart") => {
+                let request = graph_addr.send(GrabNode { node_id, client_id });
+                self.reply_to_drag(request, node_id, Some("grabStarted"), "grabDenied", ctx);
+            }
+            Some("grabMove") => {
+                let Some(position) = vector("position") else {
+                    warn!("[WebSocket] grabMove for node {} without a valid position", node_id);
+                    return;
+                };
+                let request = graph_addr.send(MoveGrabbedNode { node_id, client_id, position });
+                self.reply_to_drag(request, node_id, None, "grabLost", ctx);
+            }
+            _ => {
+                let request = graph_addr.send(ReleaseNode { node_id, client_id, velocity: vector("velocity") });
+                self.reply_to_drag(request, node_id, Some("grabEnded"), "grabLost", ctx);
+            }
+        }
+    }
+
+    fn reply_to_drag<F>(&self, request: F, node_id: u32, ok: Option<&'static str>, failed: &'static str, ctx: &mut <Self as Actor>::Context)
+    where
+        F: std::future::Future<Output = Result<Result<(), String>, MailboxError>> + 'static,
+    {
+        let fut = request.into_actor(self).map(move |result, _act, ctx: &mut <Self as Actor>::Context| {
+            let reply = match result {
+                Ok(Ok(())) => ok.map(|kind| serde_json::json!({ "type": kind, "nodeId": node_id })),
+                Ok(Err(message)) => Some(serde_json::json!({ "type": failed, "nodeId": node_id, "message": message })),
+                Err(e) => {
+                    error!("[WebSocket] GraphServiceActor unavailable for drag: {}", e);
+                    Some(serde_json::json!({ "type": failed, "nodeId": node_id, "message": "Graph service unavailable" }))
+                }
+            };
+            if let Some(reply) = reply {
+                ctx.text(reply.to_string());
+            }
+        });
+        ctx.spawn(fut);
+    }
+
+    /// Loads the recording and streams it in place of the live frames, at `speed`
+    /// times its recorded pace and starting over at the end if `loop` is set
+    fn start_replay(&mut self, msg: &serde_json::Value, ctx: &mut <Self as Actor>::Context) {
+        let speed = msg.get("speed").and_then(|v| v.as_f64()).map_or(1.0, |v| v as f32);
+        if !(speed > 0.0 && speed <= MAX_REPLAY_SPEED) {
+            ctx.text(serde_json::json!({
+                "type": "replayFailed",
+                "message": format!("speed must be above 0 and at most {}", MAX_REPLAY_SPEED)
+            }).to_string());
+            return;
+        }
+        let looping = msg.get("loop").and_then(|v| v.as_bool()).unwrap_or(false);
+        let dir = DataDirs::global().replays();
+        let fut = async move { web::block(move || replay::read_recording(&dir)).await }
+            .into_actor(self)
+            .map(move |result, act, ctx| {
+                let failure = match result {
+                    Ok(Ok(frames)) if !frames.is_empty() => {
+                        act.begin_replay(frames, speed, looping, ctx);
+                        return;
+                    }
+                    Ok(Ok(_)) => "Nothing has been recorded".to_string(),
+                    Ok(Err(e)) => format!("Failed to read recording: {}", e),
+                    Err(e) => format!("Failed to read recording: {}", e),
+                };
+                ctx.text(serde_json::json!({ "type": "replayFailed", "message": failure }).to_string());
+            });
+        ctx.spawn(fut);
+    }
+
+    fn begin_replay(&mut self, frames: Vec<RecordedFrame>, speed: f32, looping: bool, ctx: &mut <Self as Actor>::Context) {
+        self.cancel_replay(ctx);
+        let summary = replay::ReplaySummary::new(&frames);
+        info!("[WebSocket] Replaying {} frames at {}x", summary.frame_count, speed);
+        // The first replayed frame carries every node
+        self.last_sent_positions.clear();
+        self.last_sent_velocities.clear();
+        self.replay = Some(ReplayPlayback { frames, next: 0, speed, looping, timer: None });
+        ctx.text(serde_json::json!({
+            "type": "replayStarted",
+            "frameCount": summary.frame_count,
+            "durationMs": summary.duration_ms,
+            "speed": speed,
+            "loop": looping
+        }).to_string());
+        self.play_replay_frame(ctx);
+    }
+
+    /// Sends the next recorded frame and schedules the one after it
+    fn play_replay_frame(&mut self, ctx: &mut <Self as Actor>::Context) {
+        let Some(playback) = self.replay.as_mut() else { return };
+        let index = playback.next;
+        let frame = playback.frames[index].clone();
+        playback.next += 1;
+        let delay = match playback.frames.get(playback.next) {
+            Some(next) => Some(replay::frame_delay(&frame, next, playback.speed)),
+            None if playback.looping => {
+                playback.next = 0;
+                Some(std::time::Duration::from_millis(REPLAY_LOOP_PAUSE_MS))
+            }
+            None => None,
+        };
+
+        match EncodedFrame::from_wire(frame.bytes, index as u64 + 1) {
+            Ok(encoded) => self.send_position_frame(&encoded, false, ctx),
+            Err(e) => warn!("[WebSocket] Skipping recorded frame {}: {}", index, e),
+        }
+
+        match delay {
+            Some(delay) => {
+                let timer = ctx.run_later(delay, |act, ctx| act.play_replay_frame(ctx));
+                if let Some(playback) = self.replay.as_mut() {
+                    playback.timer = Some(timer);
+                }
+            }
+            None => {
+                self.cancel_replay(ctx);
+                ctx.text(serde_json::json!({ "type": "replayFinished" }).to_string());
+            }
+        }
+    }
+
+    /// Stops any replay. Live frames resume with every node, since the client was
+    /// last sent recorded positions.
+    fn cancel_replay(&mut self, ctx: &mut <Self as Actor>::Context) {
+        let Some(playback) = self.replay.take() else { return };
+        if let Some(timer) = playback.timer {
+            ctx.cancel_future(timer);
+        }
+        self.last_sent_positions.clear();
+        self.last_sent_velocities.clear();
+        self.last_frame_sequence = None;
+        self.last_position_send = None;
+    }
+
+    /// Sends an edge frame, preceded by the edge type names if new ones appeared
+    fn send_edge_frame(&mut self, update: EdgeFrameUpdate, ctx: &mut <Self as Actor>::Context) {
+        if update.edge_types.len() > self.sent_edge_type_count {
+            self.sent_edge_type_count = update.edge_types.len();
+            ctx.text(serde_json::json!({
+                "type": "edgeTypes",
+                "types": update.edge_types,
+            }).to_string());
+        }
+        ctx.binary(self.frame_for_client(FrameKind::Edges, WireEncoding::Float32, update.frame));
+    }
+
+    // Filter the shared frame down to the nodes that changed for this client and send them
+    fn send_position_frame(&mut self, frame: &EncodedFrame, detailed_debug: bool, ctx: &mut <Self as Actor>::Context) {
+        let should_log = self.should_log_update();
+
+        // Pick out the visible nodes that have changed significantly by their index in the frame
+        let mut changed = std::mem::take(&mut self.changed_indices);
+        changed.clear();
+        // Position and velocity sums and member counts of each collapsed supernode
+        let mut supernode_sums = match &self.level_of_detail {
+            Some(lod) => vec![(Vec3::ZERO, Vec3::ZERO, 0u32); lod.aggregation.supernodes.len()],
+            None => Vec::new(),
+        };
+        for (index, item) in frame.items().enumerate() {
+            if self.shared.as_ref().is_some_and(|shared| !shared.node_ids.contains(&item.id)) {
+                continue;
+            }
+            if let Some(supernode) = self.level_of_detail.as_ref().and_then(|lod| lod.collapsed_supernode(item.id)) {
+                let sum = &mut supernode_sums[supernode];
+                sum.0 += Vec3::from(item.position);
+                sum.1 += Vec3::from(item.velocity);
+                sum.2 += 1;
+                continue;
+            }
+            if let Some(culler) = &self.view_culler {
+                if !culler.is_visible(item.position) {
+                    continue;
+                }
+            }
+            if self.has_node_changed_significantly(item.id, item.position, item.velocity) {
+                changed.push(index);
+
+                if detailed_debug && changed.len() <= 5 {
+                    debug!("Including node {} in update", item.id);
+                }
+            }
+        }
+
+        // Nodes over the budget aren't recorded as sent, so they go out on a later frame
+        if let Some(culler) = &self.view_culler {
+            culler.apply_budget(frame, &mut changed);
+        }
+        for &index in &changed {
+            let item = frame.item(index);
+            self.record_sent_node(item.id, item.position, item.velocity);
+        }
+        let supernode_items = self.changed_supernodes(&supernode_sums);
+
+        // If no nodes have changed significantly, don't send an update
+        if changed.is_empty() && supernode_items.is_empty() {
+            self.changed_indices = changed;
+            if detailed_debug && should_log {
+                debug!("[WebSocket] No position changes for this client");
+            }
+            return;
+        }
+
+        // Copy out only the changed records; float frames are never re-encoded
+        let binary_data = match self.encoding {
+            WireEncoding::Float32 => frame.select(&changed),
+            WireEncoding::Quantized16 => frame.select_quantized(&changed, &self.quantization_bounds),
+        };
+        let binary_data = binary_protocol::append_items(binary_data, &supernode_items, self.encoding, &self.quantization_bounds);
+        let sent_count = changed.len() + supernode_items.len();
+
+        if detailed_debug && should_log {
+            debug!("[WebSocket] Sending {} nodes, Rate: {} updates/sec, Interval: {:?}, RTT: {:?}",
+                sent_count, self.current_update_rate, self.get_current_update_interval(), self.rtt);
+
+            // Log details about a sample node to track position changes
+            let node = changed.first().map(|&index| frame.item(index)).unwrap_or_else(|| supernode_items[0]);
+            debug!(
+                "Sample node: id={}, pos=[{:.2},{:.2},{:.2}], vel=[{:.2},{:.2},{:.2}]",
+                node.id,
+                node.position.x, node.position.y, node.position.z,
+                node.velocity.x, node.velocity.y, node.velocity.z
+            );
+        }
+        self.changed_indices = changed;
+
+        // Update performance metrics
+        self.last_transfer_size = binary_data.len();
+        self.total_bytes_sent += binary_data.len();
+        self.update_count += 1;
+        self.nodes_sent_count += sent_count;
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_transfer_time);
+        self.last_transfer_time = now;
+        self.last_position_send = Some(now);
+
+        // Log performance metrics periodically
+        if detailed_debug && should_log {
+            let avg_bytes_per_update = self.total_bytes_sent / self.update_count;
+            debug!("[WebSocket] Transfer: {} bytes, {} nodes, {:?} since last, avg {} bytes/update",
+                binary_data.len(), sent_count, elapsed, avg_bytes_per_update);
+        }
+
+        // Send binary data directly (permessage-deflate handles compression)
+        ctx.binary(self.frame_for_client(FrameKind::Positions, self.encoding, binary_data));
+    }
+
+    /// `payload` behind a protocol header if the client negotiated versioned frames
+    fn frame_for_client(&self, kind: FrameKind, encoding: WireEncoding, payload: Bytes) -> Bytes {
+        if self.framed {
+            protocol::frame(kind, encoding, &payload)
+        } else {
+            payload
+        }
+    }
+
+    /// Position records from a client frame, which must carry a header if the client
+    /// negotiated versioned frames
+    fn decode_client_frame(&self, data: &[u8]) -> Result<Vec<(u32, BinaryNodeData)>, DecodeError> {
+        if self.framed {
+            protocol::split_frame(data).and_then(|(_, payload)| binary_protocol::decode_node_data(payload))
+        } else {
+            binary_protocol::decode_node_data(data)
+        }
+    }
+
+    /// Whether to put a header on every binary frame. Only the current version is
+    /// spoken; a client asking for another gets unframed messages, and can tell
+    /// from `protocolVersion` missing in the reply.
+    fn negotiate_framing(&self, requested: Option<&serde_json::Value>) -> bool {
+        let Some(requested) = requested else {
+            return false;
+        };
+        if requested.as_u64() == Some(u64::from(PROTOCOL_VERSION)) {
+            info!("[WebSocket] Client using versioned frames, protocol version {}", PROTOCOL_VERSION);
+            true
+        } else {
+            warn!("[WebSocket] Client asked for protocol version {}, only {} is supported; sending unframed messages",
+                requested, PROTOCOL_VERSION);
+            false
         }
     }
 
@@ -305,9 +958,10 @@ impl Actor for SocketFlowServer {
         
         // Use actix's runtime to avoid blocking in the actor's started method
         let cm_addr = self.client_manager_addr.clone();
+        let resume_token = self.resume_token.clone();
         actix::spawn(async move {
             use crate::actors::messages::RegisterClient;
-            match cm_addr.send(RegisterClient { addr: addr_clone }).await {
+            match cm_addr.send(RegisterClient { addr: addr_clone, resume_token }).await {
                 Ok(Ok(id)) => {
                     // Send a message back to the actor with its client ID
                     addr.do_send(SetClientId(id));
@@ -332,16 +986,95 @@ impl Actor for SocketFlowServer {
             ctx.run_interval(std::time::Duration::from_secs(5), |act, ctx| {
                 // Send a heartbeat ping every 5 seconds
                 trace!("[WebSocket] Sending server heartbeat ping");
-                ctx.ping(b"");
+                act.heartbeat_sent_at = Some(std::time::Instant::now());
+                act.pings_sent += 1;
+                ctx.ping(&act.pings_sent.to_le_bytes());
                 
                 // Update last activity timestamp to prevent client-side timeout
                 act.last_activity = std::time::Instant::now();
+
+                if let Some((token, revision)) = act.shared.as_ref().map(|shared| (shared.token.clone(), shared.revision)) {
+                    let shares = act.app_state.shares.clone();
+                    let graph_service_addr = act.app_state.graph_service_addr.clone();
+                    ctx.spawn(async move {
+                        let share = shares.resolve(&token).await?;
+                        // Pages join and leave the share as the graph is rebuilt
+                        let current = graph_service_addr.send(GetGraphRevision).await.unwrap_or(revision);
+                        if current == revision {
+                            return Some(None);
+                        }
+                        let graph = graph_service_addr.send(GetGraphData).await.ok().and_then(Result::ok);
+                        Some(graph.map(|graph| (current, share.scope.node_ids(&graph))))
+                    }
+                        .into_actor(act)
+                        .map(|refreshed, act, ctx| match refreshed {
+                            None => {
+                                info!("[WebSocket] Closing connection to a revoked share");
+                                ctx.close(Some(ws::CloseReason {
+                                    code: ws::CloseCode::Policy,
+                                    description: Some("Share revoked".to_string()),
+                                }));
+                                ctx.stop();
+                            }
+                            Some(Some((revision, node_ids))) => {
+                                if let Some(shared) = act.shared.as_mut() {
+                                    shared.revision = revision;
+                                    shared.node_ids = node_ids;
+                                }
+                            }
+                            Some(None) => {}
+                        }));
+                }
             });
         }
 
+        // Quantize against the bounds in effect, which auto-fit may have grown past
+        // the configured size. Other messages wait until they are known.
+        let graph_service_addr = self.app_state.graph_service_addr.clone();
+        ctx.wait(async move { graph_service_addr.send(GetWorldBounds).await }
+            .into_actor(self)
+            .map(|result, act, _ctx| match result {
+                Ok(bounds) => act.quantization_bounds.half_extent = bounds.half_extent,
+                Err(e) => warn!("[WebSocket] Couldn't get world bounds, quantizing with configured size: {}", e),
+            }));
+
+        // Forward internal lifecycle events (graph rebuilt, files processed, settings changed)
+        // to this client. The future lives in the actor context, so it is dropped on disconnect.
+        // Shared connections only get the bounds, as events name files outside the share.
+        let mut events = self.app_state.event_bus.subscribe();
+        let weak_addr = ctx.address().downgrade();
+        let read_only = self.shared.is_some();
+        ctx.spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(AppEvent::Graph(GraphEvent::Bounds(bounds))) => {
+                        let Some(addr) = weak_addr.upgrade() else { break };
+                        addr.do_send(RescaleWorld(bounds));
+                    }
+                    Ok(_) if read_only => {}
+                    Ok(event) => {
+                        let Some(addr) = weak_addr.upgrade() else { break };
+                        let msg = serde_json::json!({
+                            "type": "serverEvent",
+                            "payload": event
+                        });
+                        addr.do_send(SendToClientText(msg.to_string()));
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("[WebSocket] Client lagged behind event bus, skipped {} events", skipped);
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        }.into_actor(self));
+
         // Send simple connection established message
         let response = serde_json::json!({
             "type": "connection_established",
+            "encodings": [WireEncoding::Float32, WireEncoding::Quantized16],
+            "protocolVersion": PROTOCOL_VERSION,
+            "resumeToken": self.resume_token,
+            "readOnly": self.shared.is_some(),
             "timestamp": chrono::Utc::now().timestamp_millis()
         });
 
@@ -360,8 +1093,19 @@ impl Actor for SocketFlowServer {
     }
 
     fn stopped(&mut self, _ctx: &mut Self::Context) {
+        // Keep the session around in case the client reconnects
+        if self.position_updates_enabled && !self.session_handed_over {
+            let session = self.suspend();
+            self.client_manager_addr.do_send(SuspendSession {
+                token: self.resume_token.clone(),
+                session,
+            });
+        }
+
         // Unregister this client when it disconnects
         if let Some(client_id) = self.client_id {
+            // A drag can't survive the connection, even if the session is resumed
+            self.app_state.graph_service_addr.do_send(ReleaseClientGrabs { client_id });
             let cm_addr = self.client_manager_addr.clone();
             actix::spawn(async move {
                 use crate::actors::messages::UnregisterClient;
@@ -374,73 +1118,6 @@ impl Actor for SocketFlowServer {
     }
 }
 
-// Helper function to fetch nodes without borrowing from the actor
-// Update signature to work with actor system
-async fn fetch_nodes(
-    app_state: Arc<AppState>,
-    settings_addr: actix::Addr<crate::actors::settings_actor::SettingsActor>
-) -> Option<(Vec<(u32, BinaryNodeData)>, bool)> {
-    // Fetch raw nodes asynchronously from GraphServiceActor
-    use crate::actors::messages::GetGraphData;
-    let graph_data = match app_state.graph_service_addr.send(GetGraphData).await {
-        Ok(Ok(data)) => data,
-        Ok(Err(e)) => {
-            error!("[WebSocket] Failed to get graph data: {}", e);
-            return None;
-        },
-        Err(e) => {
-            error!("[WebSocket] Failed to send message to GraphServiceActor: {}", e);
-            return None;
-        }
-    };
-    
-    if graph_data.nodes.is_empty() {
-        debug!("[WebSocket] No nodes to send! Empty graph data.");
-        return None;
-    }
-
-    // Get debug settings from SettingsActor
-    use crate::actors::messages::GetSettingByPath;
-    let debug_enabled = match settings_addr.send(GetSettingByPath { path: "system.debug.enabled".to_string() }).await {
-        Ok(Ok(value)) => value.as_bool().unwrap_or(false),
-        _ => false,
-    };
-    let debug_websocket = match settings_addr.send(GetSettingByPath { path: "system.debug.enable_websocket_debug".to_string() }).await {
-        Ok(Ok(value)) => value.as_bool().unwrap_or(false),
-        _ => false,
-    };
-    let detailed_debug = debug_enabled && debug_websocket;
-
-    if detailed_debug {
-        debug!("Raw nodes count: {}, showing first 5 nodes IDs:", graph_data.nodes.len());
-        for (i, node) in graph_data.nodes.iter().take(5).enumerate() {
-            debug!("  Node {}: id={} (numeric), metadata_id={} (filename)",
-                i, node.id, node.metadata_id);
-        }
-    }
-    
-    let mut nodes = Vec::with_capacity(graph_data.nodes.len());
-    for node in &graph_data.nodes { // Iterate over a slice
-        // node.id is already a u32, no need to parse
-        let node_id = node.id;
-        let node_data = BinaryNodeData {
-            position: node.data.position,
-            velocity: node.data.velocity,
-            mass: node.data.mass,
-            flags: node.data.flags,
-            padding: node.data.padding,
-        };
-        nodes.push((node_id, node_data));
-    }
-    
-    if nodes.is_empty() {
-        return None;
-    }
-    
-    // Return nodes and debug flag
-    Some((nodes, detailed_debug))
-}
-
 impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for SocketFlowServer {
     fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
         match msg {
@@ -449,17 +1126,28 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for SocketFlowServer
                 ctx.pong(&msg);
                 self.last_activity = std::time::Instant::now();
             }
-            Ok(ws::Message::Pong(_)) => {
+            Ok(ws::Message::Pong(payload)) => {
                 // Logging every pong creates too much noise, only log in detailed debug mode
                 // Note: We'll skip the debug check here to avoid blocking the actor
                 self.last_activity = std::time::Instant::now();
+                self.record_heartbeat_rtt();
+                self.acknowledge_ping(&payload);
             }
             Ok(ws::Message::Text(text)) => {
-                info!("Received text message: {}", text);
+                // Camera poses arrive every frame, so this is too noisy for info
+                debug!("Received text message: {}", text);
                 self.last_activity = std::time::Instant::now();
                 match serde_json::from_str::<serde_json::Value>(&text) {
                     Ok(msg) => {
-                        match msg.get("type").and_then(|t| t.as_str()) {
+                        let kind = msg.get("type").and_then(|t| t.as_str());
+                        if self.shared.is_some() && kind.is_some_and(|kind| READ_ONLY_REFUSED.contains(&kind)) {
+                            ctx.text(serde_json::json!({
+                                "type": "error",
+                                "message": format!("{} isn't available on a shared view", kind.unwrap_or_default())
+                            }).to_string());
+                            return;
+                        }
+                        match kind {
                             Some("ping") => {
                                 if let Ok(ping_msg) =
                                     serde_json::from_value::<PingMessage>(msg.clone())
@@ -474,142 +1162,115 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for SocketFlowServer
                             Some("requestInitialData") => {
                                 info!("Client requested initial data - sending authoritative server state");
 
-                                // Use a smaller initial interval to start updates quickly
-                                let initial_interval = std::time::Duration::from_millis(10);
-                                let app_state = self.app_state.clone();
-                                let settings_addr = self.app_state.settings_addr.clone();
-                                
-                                // First check if we should log this update
-                                let should_log = self.should_log_update();
-                                
-                                ctx.run_later(initial_interval, move |_act, ctx| {
-                                    // Wrap the async function in an actor future
-                                    let fut = fetch_nodes(app_state.clone(), settings_addr.clone());
-                                    let fut = actix::fut::wrap_future::<_, Self>(fut);
-                                    
-                                    ctx.spawn(fut.map(move |result, act, ctx| {
-                                        if let Some((nodes, detailed_debug)) = result {
-                                            // Now that we're back in the actor context, we can filter the nodes
-                                            // Filter nodes to only include those that have changed significantly
-                                            let mut filtered_nodes = Vec::new();
-                                            for (node_id, node_data) in &nodes {
-                                                let node_id_str = node_id.to_string();
-                                                let position = node_data.position.clone();
-                                                let velocity = node_data.velocity.clone();
-                                                
-                                                // Apply filtering before adding to filtered nodes
-                                                if act.has_node_changed_significantly(
-                                                    &node_id_str,
-                                                    position.clone(),
-                                                    velocity.clone()
-                                                ) {
-                                                    filtered_nodes.push((*node_id, node_data.clone()));
-                                                }
-                                                
-                                                if detailed_debug && filtered_nodes.len() <= 5 {
-                                                    debug!("Including node {} in update", node_id_str);
-                                                }
-                                            }
-                                            
-                                            // If no nodes have changed significantly, don't send an update
-                                            if filtered_nodes.is_empty() {
-                                                return;
-                                            }
-                                            
-                                            // Encode only the nodes that have changed significantly
-                                            let binary_data = binary_protocol::encode_node_data(&filtered_nodes);
-                                            
-                                            // Update motion metrics for dynamic rate adjustment
-                                            act.total_node_count = filtered_nodes.len();
-                                              
-                                            // Count nodes in motion (with non-zero velocity)
-                                            let moving_nodes = filtered_nodes.iter()
-                                                .filter(|(_, node_data)| {
-                                                    let vel = &node_data.velocity;
-                                                    vel.x.abs() > 0.001 || vel.y.abs() > 0.001 || vel.z.abs() > 0.001
-                                                })
-                                                .count();
-                                            
-                                            act.nodes_in_motion = moving_nodes;
-                                            
-                                            // Update the dynamic rate based on current motion
-                                            act.update_dynamic_rate();
-                                            
-                                            // Get the current update interval for the next update
-                                            let update_interval = act.get_current_update_interval();
-                                            
-                                            if detailed_debug && should_log {
-                                                debug!("[WebSocket] Motion: {}/{} nodes, Rate: {} updates/sec, Interval: {:?}",
-                                                    moving_nodes, filtered_nodes.len(), act.current_update_rate, update_interval);
-                                            }
-                                            
-                                            if detailed_debug && should_log && !binary_data.is_empty() {
-                                                trace!("[WebSocket] Encoded binary data: {} bytes for {} nodes", binary_data.len(), filtered_nodes.len());
-                                                
-                                                // Log details about a sample node to track position changes
-                                                if !filtered_nodes.is_empty() {
-                                                    let node = &filtered_nodes[0];
-                                                    debug!(
-                                                        "Sample node: id={}, pos=[{:.2},{:.2},{:.2}], vel=[{:.2},{:.2},{:.2}]",
-                                                        node.0, 
-                                                        node.1.position.x, node.1.position.y, node.1.position.z,
-                                                        node.1.velocity.x, node.1.velocity.y, node.1.velocity.z
-                                                    );
-                                                }
-                                            }
-
-                                            // Only send data if we have nodes to update
-                                            if !filtered_nodes.is_empty() {
-                                                // Send binary data directly (permessage-deflate handles compression)
-                                                
-                                                // Update performance metrics
-                                                act.last_transfer_size = binary_data.len();
-                                                act.total_bytes_sent += binary_data.len();
-                                                act.update_count += 1;
-                                                act.nodes_sent_count += filtered_nodes.len();
-                                                let now = Instant::now();
-                                                let elapsed = now.duration_since(act.last_transfer_time);
-                                                act.last_transfer_time = now;
-                                                
-                                                // Schedule the next update using the dynamic rate
-                                                let next_interval = act.get_current_update_interval();
-                                                
-                                                // Use a simple recursive approach to restart the cycle
-                                                let _app_state = act.app_state.clone();
-                                                let _settings_addr = act.app_state.settings_addr.clone();
-                                                                ctx.run_later(next_interval, move |act, ctx| {
-                                                                    // Recursively call the handler to restart the cycle
-                                                                    <SocketFlowServer as StreamHandler<Result<ws::Message, ws::ProtocolError>>>::handle(act, Ok(ws::Message::Text("{\"type\":\"requestPositionUpdates\"}".to_string().into())), ctx);
-                                                                });
-                                                
-                                                // Log performance metrics periodically
-                                                if detailed_debug && should_log {
-                                                    let avg_bytes_per_update = if act.update_count > 0 {
-                                                        act.total_bytes_sent / act.update_count
-                                                    } else { 0 };
-                                                    
-                                                    debug!("[WebSocket] Transfer: {} bytes, {} nodes, {:?} since last, avg {} bytes/update",
-                                                        binary_data.len(), filtered_nodes.len(), elapsed, avg_bytes_per_update);
-                                                }
-                                                
-                                                ctx.binary(binary_data);
-                                            } else if detailed_debug && should_log {
-                                                // Log keepalive
-                                                debug!("[WebSocket] Sending keepalive (no position changes)");
-                                            }
-                                        }
-                                    }));
-                                });
+                                // Forget what was sent before so the next broadcaster frame
+                                // carries every node
+                                self.last_sent_positions.clear();
+                                self.last_sent_velocities.clear();
+                                self.last_frame_sequence = None;
+                                self.last_position_send = None;
+                                self.position_updates_enabled = true;
+                                self.encoding = self.negotiate_encoding(msg.get("encoding"));
+                                self.framed = self.negotiate_framing(msg.get("protocolVersion"));
 
-                                let response = serde_json::json!({
+                                let mut response = serde_json::json!({
                                     "type": "updatesStarted",
+                                    "encoding": self.encoding,
                                     "timestamp": chrono::Utc::now().timestamp_millis()
                                 });
+                                if self.encoding == WireEncoding::Quantized16 {
+                                    response["bounds"] = serde_json::json!(self.quantization_bounds);
+                                }
+                                if self.framed {
+                                    response["protocolVersion"] = serde_json::json!(PROTOCOL_VERSION);
+                                }
                                 if let Ok(msg_str) = serde_json::to_string(&response) {
                                     self.last_activity = std::time::Instant::now();
                                     ctx.text(msg_str);
                                 }
                             }
+                            Some("cameraPose") => {
+                                match serde_json::from_value::<CameraPose>(msg.clone())
+                                    .map_err(|e| e.to_string())
+                                    .and_then(|pose| ViewCuller::new(&pose))
+                                {
+                                    Ok(culler) => self.view_culler = Some(culler),
+                                    Err(e) => warn!("[WebSocket] Ignoring invalid camera pose: {}", e),
+                                }
+                            }
+                            Some("subscribeEdges") => {
+                                self.subscribe_edges(ctx);
+                            }
+                            Some("resume") => {
+                                let token = msg.get("token").and_then(|t| t.as_str()).unwrap_or_default().to_string();
+                                if token.is_empty() || token == self.resume_token {
+                                    ctx.text(serde_json::json!({
+                                        "type": "resumeFailed",
+                                        "message": "A resume token from an earlier connection is required"
+                                    }).to_string());
+                                    return;
+                                }
+                                let cm_addr = self.client_manager_addr.clone();
+                                let fut = async move { cm_addr.send(ResumeSession { token }).await }
+                                    .into_actor(self)
+                                    .map(|result, act, ctx| match result {
+                                        Ok(Some(session)) => act.restore_session(session, ctx),
+                                        Ok(None) => ctx.text(serde_json::json!({
+                                            "type": "resumeFailed",
+                                            "message": "Session expired or unknown; request initial data instead"
+                                        }).to_string()),
+                                        Err(e) => error!("[WebSocket] ClientManagerActor unavailable for resume: {}", e),
+                                    });
+                                ctx.spawn(fut);
+                            }
+                            Some("unsubscribeEdges") => {
+                                self.edge_updates_enabled = false;
+                            }
+                            Some("enableLevelOfDetail") => {
+                                let cell_size = msg.get("cellSize")
+                                    .and_then(|v| v.as_f64())
+                                    .map(|v| v as f32)
+                                    .unwrap_or(DEFAULT_SUPERNODE_CELL_SIZE);
+                                let graph_addr = self.app_state.graph_service_addr.clone();
+                                let fut = async move { graph_addr.send(GetGraphAggregation { cell_size }).await }
+                                    .into_actor(self)
+                                    .map(|result, act, ctx| match result {
+                                        Ok(Ok(aggregation)) => act.enable_level_of_detail(aggregation, ctx),
+                                        Ok(Err(e)) => ctx.text(serde_json::json!({
+                                            "type": "error",
+                                            "message": format!("Failed to aggregate graph: {}", e)
+                                        }).to_string()),
+                                        Err(e) => error!("[WebSocket] GraphServiceActor unavailable for aggregation: {}", e),
+                                    });
+                                ctx.spawn(fut);
+                            }
+                            Some("disableLevelOfDetail") => {
+                                // Hidden members were never recorded as sent, so the deadband
+                                // catches them up on the next frame
+                                self.level_of_detail = None;
+                                ctx.text(serde_json::json!({ "type": "levelOfDetailDisabled" }).to_string());
+                            }
+                            Some("expandSupernode") | Some("collapseSupernode") => {
+                                let supernode_id = msg.get("id").and_then(|v| v.as_u64()).and_then(|id| u32::try_from(id).ok());
+                                let expanded = msg.get("type").and_then(|t| t.as_str()) == Some("expandSupernode");
+                                self.set_supernode_expanded(supernode_id, expanded, ctx);
+                            }
+                            Some("grabStart") | Some("grabMove") | Some("grabEnd") => {
+                                self.handle_drag(&msg, ctx);
+                            }
+                            Some("startReplay") => {
+                                self.start_replay(&msg, ctx);
+                            }
+                            Some("stopReplay") => {
+                                if self.replay.is_some() {
+                                    self.cancel_replay(ctx);
+                                    ctx.text(serde_json::json!({ "type": "replayStopped" }).to_string());
+                                }
+                            }
+                            Some("clearCameraPose") => {
+                                // Culled nodes were never recorded as sent, so the deadband
+                                // catches them up on the next frame
+                                self.view_culler = None;
+                            }
                             Some("enableRandomization") => {
                                 if let Ok(enable_msg) = serde_json::from_value::<serde_json::Value>(msg.clone()) {
                                     let enabled = enable_msg.get("enabled").and_then(|e| e.as_bool()).unwrap_or(false);
@@ -646,17 +1307,26 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for SocketFlowServer
                 // Enhanced logging for binary message reception
                 info!("Received binary message, length: {}", data.len());
                 self.last_activity = std::time::Instant::now();
+                if self.shared.is_some() {
+                    ctx.text(serde_json::json!({
+                        "type": "error",
+                        "message": "Shared views are read-only"
+                    }).to_string());
+                    return;
+                }
+                // Binary messages are node drags; stream at the max rate while they last
+                self.last_interaction = Some(self.last_activity);
                 
-                // Enhanced logging for binary messages (28 bytes per node now with u32 IDs)
-                if data.len() % 28 != 0 {
+                if !self.framed && data.len() % WIRE_NODE_SIZE != 0 {
                     warn!(
-                        "Binary message size mismatch: {} bytes (not a multiple of 28, remainder: {})",
+                        "Binary message size mismatch: {} bytes (not a multiple of {}, remainder: {})",
                         data.len(),
-                        data.len() % 28
+                        WIRE_NODE_SIZE,
+                        data.len() % WIRE_NODE_SIZE
                     );
                 }
                 
-                match binary_protocol::decode_node_data(&data) {
+                match self.decode_client_frame(&data) {
                     Ok(nodes) => {
                         info!("Decoded {} nodes from binary message", nodes.len());
                         let _nodes_vec: Vec<_> = nodes.clone().into_iter().collect();
@@ -755,6 +1425,27 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for SocketFlowServer
     }
 }
 
+/// Update rate a client should be moving towards. `activity` is the graph's kinetic
+/// energy relative to the level that warrants the max rate; a settled graph drops to
+/// `min_rate`. Slow links are capped so frames don't queue up behind the RTT.
+fn target_update_rate(min_rate: u32, max_rate: u32, activity: f32, interacting: bool, rtt: Option<std::time::Duration>) -> u32 {
+    let max_rate = max_rate.max(min_rate);
+    let mut rate = if interacting {
+        max_rate
+    } else {
+        let fraction = activity.clamp(0.0, 1.0);
+        min_rate + ((max_rate - min_rate) as f32 * fraction).round() as u32
+    };
+
+    if let Some(rtt) = rtt {
+        let rtt_secs = rtt.as_secs_f64();
+        if rtt_secs > 0.0 {
+            rate = rate.min((FRAMES_PER_RTT / rtt_secs) as u32);
+        }
+    }
+    rate.clamp(min_rate, max_rate)
+}
+
 pub async fn socket_flow_handler(
     req: HttpRequest,
     stream: web::Payload,
@@ -808,3 +1499,22 @@ pub async fn socket_flow_handler(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_target_update_rate() {
+        // Settled graph drops to the minimum, an active one climbs to the maximum
+        assert_eq!(target_update_rate(5, 60, 0.0, false, None), 5);
+        assert_eq!(target_update_rate(5, 60, 0.5, false, None), 33);
+        assert_eq!(target_update_rate(5, 60, 4.0, false, None), 60);
+
+        // Dragging always streams at the maximum unless the link is slow
+        assert_eq!(target_update_rate(5, 60, 0.0, true, Some(Duration::from_millis(10))), 60);
+        assert_eq!(target_update_rate(5, 60, 0.0, true, Some(Duration::from_millis(200))), 10);
+        assert_eq!(target_update_rate(5, 60, 1.0, false, Some(Duration::from_secs(2))), 5);
+    }
+}
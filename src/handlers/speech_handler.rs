@@ -0,0 +1,27 @@
+use actix_web::{web, HttpResponse, Result};
+use serde_json::json;
+use crate::AppState;
+
+/// Lists the TTS providers the server knows about, whether each one is configured,
+/// and which one is currently active.
+pub async fn list_providers(app_state: web::Data<AppState>) -> Result<HttpResponse> {
+    match &app_state.speech_service {
+        Some(speech_service) => {
+            let providers = speech_service.list_tts_providers().await;
+            Ok(HttpResponse::Ok().json(json!({
+                "providers": providers
+            })))
+        }
+        None => Ok(HttpResponse::ServiceUnavailable().json(json!({
+            "status": "error",
+            "message": "Speech service is not available"
+        }))),
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/speech")
+            .route("/providers", web::get().to(list_providers))
+    );
+}
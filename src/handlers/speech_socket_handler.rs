@@ -6,9 +6,16 @@ use std::sync::Arc;
 use std::time::{Duration, Instant};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use crate::actors::messages::{BuildGraphFromMetadata, GetGraphData, GetMetadata, GetSettings, UpdateMetadata};
 use crate::app_state::AppState;
-use crate::actors::messages::GetSettings;
-use crate::types::speech::SpeechOptions;
+use crate::config::data_dirs::DataDirs;
+use crate::errors::AppError;
+use crate::utils::auth::require_session;
+use crate::services::activity::ActivityKind;
+use crate::services::file_service::FileService;
+use crate::services::pages;
+use crate::services::transcripts::{self, TranscriptLine};
+use crate::types::speech::{SpeechOptions, TTSProvider};
 use tokio::sync::broadcast;
 use futures::FutureExt;
 
@@ -40,21 +47,42 @@ struct STTActionRequest {
     model: Option<String>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TranscriptRequest {
+    action: String, // "start", "save" or "discard"
+    /// Nodes under discussion, linked from the saved page
+    #[serde(default)]
+    node_ids: Vec<u32>,
+}
+
+fn add_node_ids(node_ids: &mut Vec<u32>, new_ids: Vec<u32>) {
+    for id in new_ids {
+        if !node_ids.contains(&id) {
+            node_ids.push(id);
+        }
+    }
+}
+
 pub struct SpeechSocket {
     id: String,
     app_state: Arc<AppState>,
+    /// Signed-in user, who must be a power user to save transcripts
+    pubkey: Option<String>,
+    /// Nodes to link while a transcript is being recorded
+    transcript_node_ids: Option<Vec<u32>>,
     heartbeat: Instant,
     audio_rx: Option<broadcast::Receiver<Vec<u8>>>,
     transcription_rx: Option<broadcast::Receiver<String>>,
 }
 
 impl SpeechSocket {
-    pub fn new(id: String, app_state: Arc<AppState>) -> Self {
+    pub fn new(id: String, app_state: Arc<AppState>, pubkey: Option<String>) -> Self {
+        // Each connection gets its own session so audio and transcripts aren't
+        // broadcast to every other connected client
         let (audio_rx, transcription_rx) = if let Some(speech_service) = &app_state.speech_service {
-            (
-                Some(speech_service.subscribe_to_audio()),
-                Some(speech_service.subscribe_to_transcriptions())
-            )
+            let session = speech_service.open_session(&id);
+            (Some(session.audio_rx), Some(session.transcription_rx))
         } else {
             (None, None)
         };
@@ -62,6 +90,8 @@ impl SpeechSocket {
         Self {
             id,
             app_state,
+            pubkey,
+            transcript_node_ids: None,
             heartbeat: Instant::now(),
             audio_rx,
             transcription_rx,
@@ -81,28 +111,20 @@ impl SpeechSocket {
     }
 
     // Process text-to-speech request
-    async fn process_tts_request(app_state: Arc<AppState>, req: TextToSpeechRequest) -> Result<(), String> {
+    async fn process_tts_request(app_state: Arc<AppState>, session_id: String, req: TextToSpeechRequest) -> Result<(), String> {
         if let Some(speech_service) = &app_state.speech_service {
-            // Get default settings from app state, handling optional Kokoro settings
-            let settings = app_state.settings_addr.send(GetSettings).await
-                .map_err(|e| format!("Settings actor mailbox error: {}", e))?
-                .map_err(|e| format!("Failed to get settings: {}", e))?;
-            let kokoro_config = settings.kokoro.as_ref(); // Get Option<&KokoroSettings>
-
-            // Provide defaults if Kokoro config or specific fields are None
-            let default_voice = kokoro_config.and_then(|k| k.default_voice.clone()).unwrap_or_else(|| "default_voice_placeholder".to_string()); // Provide a sensible default
-            let default_speed = kokoro_config.and_then(|k| k.default_speed).unwrap_or(1.0);
-            let default_stream = kokoro_config.and_then(|k| k.stream).unwrap_or(true); // Default to streaming?
+            // Defaults come from whichever TTS provider is currently active
+            let defaults = speech_service.default_speech_options().await;
 
             // Create options with defaults or provided values
             let options = SpeechOptions {
-                voice: req.voice.unwrap_or(default_voice),
-                speed: req.speed.unwrap_or(default_speed),
-                stream: req.stream.unwrap_or(default_stream),
+                voice: req.voice.unwrap_or(defaults.voice),
+                speed: req.speed.unwrap_or(defaults.speed),
+                stream: req.stream.unwrap_or(defaults.stream),
             };
 
             // Send request to TTS service
-            match speech_service.text_to_speech(req.text, options).await {
+            match speech_service.session_text_to_speech(&session_id, req.text, options).await {
                 Ok(_) => Ok(()),
                 Err(e) => Err(format!("Failed to process TTS request: {}", e)),
             }
@@ -110,6 +132,109 @@ impl SpeechSocket {
             Err("Speech service is not available".to_string())
         }
     }
+
+    /// Adds a recorded transcript to the vault as a new page linking `node_ids`,
+    /// and rebuilds the graph so it shows up as a node
+    async fn save_transcript(
+        app_state: Arc<AppState>,
+        pubkey: String,
+        lines: Vec<TranscriptLine>,
+        node_ids: Vec<u32>,
+    ) -> Result<serde_json::Value, AppError> {
+        let started_at = lines.first().map(|line| line.at)
+            .ok_or_else(|| AppError::BadRequest("Nothing was said to save".to_string()))?;
+        let mut metadata = app_state.metadata_addr.send(GetMetadata).await?.map_err(AppError::file)?;
+        let related: Vec<String> = node_ids.iter()
+            .filter_map(|id| metadata.iter().find(|(_, page)| page.node_id == id.to_string()))
+            .map(|(file_name, _)| pages::page_title(file_name))
+            .collect();
+        let title = transcripts::page_title(started_at, |title| {
+            pages::page_file_name(title).is_some_and(|file_name| {
+                metadata.contains_key(&file_name) || DataDirs::global().markdown_file(&file_name).exists()
+            })
+        });
+        let file_name = pages::page_file_name(&title)
+            .ok_or_else(|| AppError::Internal(format!("{:?} can't be a page title", title)))?;
+        let content = transcripts::render(&lines, &related);
+
+        let settings = app_state.settings_addr.send(GetSettings).await?.map_err(AppError::Internal)?;
+        let file_service = FileService::new(Arc::new(tokio::sync::RwLock::new(settings))).with_event_bus(app_state.event_bus.clone());
+        let page = file_service.add_page(&file_name, &content, &mut metadata)?;
+        app_state.metadata_addr.send(UpdateMetadata { metadata: metadata.clone() }).await?.map_err(AppError::file)?;
+        app_state.graph_service_addr.send(BuildGraphFromMetadata { metadata, seed: None }).await?.map_err(AppError::graph)?;
+        let graph = app_state.graph_service_addr.send(GetGraphData).await?.map_err(AppError::graph)?;
+        let node = graph.nodes.iter().find(|node| node.id.to_string() == page.node_id);
+
+        info!("Saved a transcript of {} lines as {}", lines.len(), file_name);
+        app_state.activity.record(
+            ActivityKind::NodeAdded,
+            &file_name,
+            Some(pubkey),
+            json!({ "transcriptLines": lines.len(), "nodeIds": node_ids }),
+        );
+        Ok(json!({
+            "type": "transcriptSaved",
+            "page": pages::PageSummary::of(&file_name, &page),
+            "node": node,
+        }))
+    }
+
+    fn handle_transcript_request(&mut self, req: TranscriptRequest, ctx: &mut ws::WebsocketContext<Self>) {
+        let speech_service = match &self.app_state.speech_service {
+            Some(speech_service) => speech_service.clone(),
+            None => {
+                ctx.text(json!({"type": "error", "message": "Speech service is not available"}).to_string());
+                return;
+            }
+        };
+        match req.action.as_str() {
+            "start" => {
+                let pubkey = self.pubkey.as_deref().unwrap_or_default();
+                if !self.app_state.is_power_user(pubkey) {
+                    ctx.text(json!({"type": "error", "message": "Only power users can save transcripts"}).to_string());
+                    return;
+                }
+                if !speech_service.start_transcript(&self.id) {
+                    ctx.text(json!({"type": "error", "message": "No speech session to record"}).to_string());
+                    return;
+                }
+                // Sending start again while recording adds the nodes now being discussed
+                let node_ids = self.transcript_node_ids.get_or_insert_with(Vec::new);
+                add_node_ids(node_ids, req.node_ids);
+                ctx.text(json!({"type": "transcriptStarted", "nodeIds": node_ids}).to_string());
+            }
+            "save" => {
+                let (Some(mut node_ids), Some(pubkey)) = (self.transcript_node_ids.take(), self.pubkey.clone()) else {
+                    ctx.text(json!({"type": "error", "message": "No transcript is being recorded"}).to_string());
+                    return;
+                };
+                add_node_ids(&mut node_ids, req.node_ids);
+                let lines = speech_service.take_transcript(&self.id).unwrap_or_default();
+                let app_state = self.app_state.clone();
+                let addr = ctx.address();
+                let fut = async move {
+                    match Self::save_transcript(app_state, pubkey, lines, node_ids).await {
+                        Ok(saved) => {
+                            let _ = addr.try_send(ReplyMessage(saved.to_string()));
+                        }
+                        Err(e) => {
+                            let msg = json!({"type": "error", "message": format!("Failed to save transcript: {}", e)});
+                            let _ = addr.try_send(ErrorMessage(msg.to_string()));
+                        }
+                    }
+                };
+                ctx.spawn(fut.into_actor(self));
+            }
+            "discard" => {
+                self.transcript_node_ids = None;
+                speech_service.take_transcript(&self.id);
+                ctx.text(json!({"type": "transcriptDiscarded"}).to_string());
+            }
+            _ => {
+                ctx.text(json!({"type": "error", "message": "Invalid transcript action"}).to_string());
+            }
+        }
+    }
 }
 
 impl Actor for SpeechSocket {
@@ -157,6 +282,23 @@ impl Actor for SpeechSocket {
             }.into_actor(self)));
         }
     }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        if let Some(speech_service) = &self.app_state.speech_service {
+            // A recording still under way when the client goes is saved, not lost
+            let lines = speech_service.take_transcript(&self.id).filter(|lines| !lines.is_empty());
+            if let (Some(lines), Some(node_ids), Some(pubkey)) = (lines, self.transcript_node_ids.take(), self.pubkey.clone()) {
+                let app_state = self.app_state.clone();
+                actix::spawn(async move {
+                    if let Err(e) = Self::save_transcript(app_state, pubkey, lines, node_ids).await {
+                        error!("Failed to save transcript on disconnect: {}", e);
+                    }
+                });
+            }
+            speech_service.close_session(&self.id);
+        }
+        info!("[SpeechSocket] Session closed: {}", self.id);
+    }
 }
 
 // Message type for audio data
@@ -202,6 +344,21 @@ impl Handler<TranscriptionMessage> for SpeechSocket {
     }
 }
 
+// Message type for replies to requests handled in a spawned future
+struct ReplyMessage(String);
+
+impl Message for ReplyMessage {
+    type Result = ();
+}
+
+impl Handler<ReplyMessage> for SpeechSocket {
+    type Result = ();
+
+    fn handle(&mut self, msg: ReplyMessage, ctx: &mut Self::Context) -> Self::Result {
+        ctx.text(msg.0);
+    }
+}
+
 // Message type for error data
 struct ErrorMessage(String);
 
@@ -243,9 +400,10 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for SpeechSocket {
                                 if let Ok(tts_req) = serde_json::from_value::<TextToSpeechRequest>(msg) {
                                     // Process TTS request
                                     let app_state = self.app_state.clone();
+                                    let session_id = self.id.clone();
                                     let addr = ctx.address();
                                     let fut = async move {
-                                        if let Err(e) = Self::process_tts_request(app_state, tts_req).await {
+                                        if let Err(e) = Self::process_tts_request(app_state, session_id, tts_req).await {
                                             let error_msg = json!({
                                                 "type": "error",
                                                 "message": e
@@ -258,6 +416,44 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for SpeechSocket {
                                     ctx.text(json!({"type": "error", "message": "Invalid TTS request format"}).to_string());
                                 }
                             }
+                            Some("setProvider") => {
+                                // The provider is shared by every session on the server
+                                if !self.app_state.is_power_user(self.pubkey.as_deref().unwrap_or_default()) {
+                                    ctx.text(json!({"type": "error", "message": "Only power users can change the TTS provider"}).to_string());
+                                    return;
+                                }
+                                match serde_json::from_value::<SetProviderRequest>(msg) {
+                                    Ok(req) => match (TTSProvider::from_id(&req.provider), &self.app_state.speech_service) {
+                                        (Some(provider), Some(speech_service)) => {
+                                            let speech_service = speech_service.clone();
+                                            let addr = ctx.address();
+                                            let fut = async move {
+                                                let provider_id = provider.id();
+                                                match speech_service.set_tts_provider(provider).await {
+                                                    Ok(_) => {
+                                                        let reply = json!({"type": "providerChanged", "provider": provider_id});
+                                                        let _ = addr.try_send(ReplyMessage(reply.to_string()));
+                                                    }
+                                                    Err(e) => {
+                                                        let error_msg = json!({"type": "error", "message": format!("Failed to set TTS provider: {}", e)});
+                                                        let _ = addr.try_send(ErrorMessage(error_msg.to_string()));
+                                                    }
+                                                }
+                                            };
+                                            ctx.spawn(fut.into_actor(self));
+                                        }
+                                        (None, _) => {
+                                            ctx.text(json!({"type": "error", "message": format!("Unknown TTS provider: {}", req.provider)}).to_string());
+                                        }
+                                        (_, None) => {
+                                            ctx.text(json!({"type": "error", "message": "Speech service is not available"}).to_string());
+                                        }
+                                    },
+                                    Err(_) => {
+                                        ctx.text(json!({"type": "error", "message": "Invalid setProvider request format"}).to_string());
+                                    }
+                                }
+                            }
                             Some("stt") => {
                                 // Parse as STT action request
                                 if let Ok(stt_req) = serde_json::from_value::<STTActionRequest>(msg) {
@@ -328,6 +524,14 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for SpeechSocket {
                                     ctx.text(json!({"type": "error", "message": "Invalid STT request format"}).to_string());
                                 }
                             }
+                            Some("transcript") => {
+                                match serde_json::from_value::<TranscriptRequest>(msg) {
+                                    Ok(req) => self.handle_transcript_request(req, ctx),
+                                    Err(_) => {
+                                        ctx.text(json!({"type": "error", "message": "Invalid transcript request format"}).to_string());
+                                    }
+                                }
+                            }
                             _ => {
                                 ctx.text(json!({"type": "error", "message": "Unknown message type"}).to_string());
                             }
@@ -348,8 +552,9 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for SpeechSocket {
 
                     // Clone the speech service Arc to move into the future
                     let speech_service = speech_service.clone();
+                    let session_id = self.id.clone();
                     let fut = async move {
-                        if let Err(e) = speech_service.process_audio_chunk(audio_data).await {
+                        if let Err(e) = speech_service.session_process_audio_chunk(&session_id, audio_data).await {
                             error!("Failed to process audio chunk: {}", e);
                         }
                     }.boxed().into_actor(self);
@@ -374,7 +579,10 @@ pub async fn speech_socket_handler(
     app_state: web::Data<AppState>,
 ) -> Result<HttpResponse, Error> {
     let socket_id = format!("speech_{}", uuid::Uuid::new_v4());
-    let socket = SpeechSocket::new(socket_id, app_state.into_inner());
+    // Anyone may talk; saving transcripts and changing the TTS provider need a signed-in
+    // power user
+    let pubkey = require_session(&req, &app_state).await.ok();
+    let socket = SpeechSocket::new(socket_id, app_state.into_inner(), pubkey);
 
     match ws::start(socket, &req, stream) {
         Ok(response) => {
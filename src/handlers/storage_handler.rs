@@ -0,0 +1,24 @@
+use actix_web::{web, HttpResponse, Result};
+use crate::actors::messages::GetSettings;
+use crate::services::audio_cache::AudioCache;
+use crate::services::storage_quota::StorageQuota;
+use crate::AppState;
+
+/// Disk usage of the markdown mirror and the caches, with their configured limits
+pub async fn get_storage_stats(app_state: web::Data<AppState>) -> Result<HttpResponse> {
+    let mut quota = StorageQuota::from_env();
+    if let Ok(Ok(settings)) = app_state.settings_addr.send(GetSettings).await {
+        if let Some(cache) = AudioCache::from_settings(settings.audio_cache.as_ref()) {
+            quota = quota.with_audio_dir(cache.dir().to_path_buf());
+        }
+    }
+    let stats = web::block(move || quota.stats()).await?;
+    Ok(HttpResponse::Ok().json(stats))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::scope("/storage")
+            .route("/stats", web::get().to(get_storage_stats))
+    );
+}
@@ -0,0 +1,48 @@
+use crate::app_state::AppState;
+use crate::utils::auth::require_session;
+use crate::services::telemetry::{TelemetryBatch, TelemetryError};
+use actix_web::{web, HttpRequest, HttpResponse};
+use serde_json::json;
+
+/// Accepts a batch of client events. Clients are told how long to wait when they
+/// post too often, and should drop the batch rather than queue it.
+async fn post_telemetry(req: HttpRequest, state: web::Data<AppState>, batch: web::Json<TelemetryBatch>) -> HttpResponse {
+    // Behind nginx the peer is the proxy, so prefer the forwarded address
+    let client = req.connection_info().realip_remote_addr().unwrap_or("unknown").to_string();
+    match state.telemetry.ingest(&client, batch.into_inner()) {
+        Ok(accepted) => HttpResponse::Accepted().json(json!({ "accepted": accepted })),
+        Err(e @ TelemetryError::Disabled) => HttpResponse::NotFound().json(json!({ "error": e.to_string() })),
+        Err(e @ TelemetryError::Invalid(_)) => HttpResponse::BadRequest().json(json!({ "error": e.to_string() })),
+        Err(e @ TelemetryError::RateLimited(seconds)) => HttpResponse::TooManyRequests()
+            .insert_header(("Retry-After", seconds.to_string()))
+            .json(json!({ "error": e.to_string() })),
+    }
+}
+
+/// Per-minute summaries of the last hour, for power users
+async fn get_telemetry(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
+    let pubkey = match require_session(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return resp,
+    };
+    if !state.is_power_user(&pubkey) {
+        return HttpResponse::Forbidden().json(json!({"error": "Only power users can read telemetry"}));
+    }
+    let limits = state.telemetry.limits();
+    HttpResponse::Ok().json(json!({
+        "enabled": limits.enabled,
+        "limits": {
+            "batchesPerMinute": limits.batches_per_minute,
+            "maxEvents": limits.max_events,
+        },
+        "minutes": state.telemetry.summaries(),
+    }))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/telemetry")
+            .route(web::post().to(post_telemetry))
+            .route(web::get().to(get_telemetry))
+    );
+}
@@ -0,0 +1,164 @@
+use crate::app_state::AppState;
+use crate::services::tenants::{RegisterVault, TenantError, TenantVault};
+use crate::utils::auth::require_session;
+use actix_web::{web, HttpRequest, HttpResponse};
+use futures::FutureExt;
+use log::error;
+use serde_json::json;
+
+fn error_response(e: TenantError) -> HttpResponse {
+    let body = json!({ "error": e.to_string() });
+    match e {
+        TenantError::Disabled | TenantError::NotFound => HttpResponse::NotFound().json(body),
+        TenantError::Invalid(_) => HttpResponse::BadRequest().json(body),
+        TenantError::QuotaExceeded(_) => HttpResponse::UnprocessableEntity().json(body),
+        TenantError::Upstream(_) => HttpResponse::BadGateway().json(body),
+        TenantError::Storage(_) => {
+            error!("Tenant storage error: {}", body["error"]);
+            HttpResponse::InternalServerError().json(body)
+        }
+    }
+}
+
+// The sealed token never leaves the server
+fn vault_json(vault: &TenantVault) -> serde_json::Value {
+    json!({
+        "pubkey": vault.pubkey,
+        "owner": vault.connection.owner,
+        "repo": vault.connection.repo,
+        "basePath": vault.connection.base_path,
+        "branch": vault.connection.branch,
+        "usesOAuth": !vault.has_token(),
+        "registeredAt": vault.registered_at,
+        "lastSync": vault.last_sync,
+    })
+}
+
+async fn get_vault(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
+    let pubkey = match require_session(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return resp,
+    };
+    match state.tenants.get(&pubkey).await {
+        Some(vault) => HttpResponse::Ok().json(json!({
+            "vault": vault_json(&vault),
+            "syncJobId": state.job_queue.active_job_of_kind(&sync_job_kind(&pubkey)),
+        })),
+        None => error_response(TenantError::NotFound),
+    }
+}
+
+async fn register_vault(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    body: web::Json<RegisterVault>,
+) -> HttpResponse {
+    let pubkey = match require_session(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return resp,
+    };
+    let oauth_token = state.get_github_token(&pubkey).await;
+    match state.tenants.register(&pubkey, body.into_inner(), oauth_token).await {
+        Ok(vault) => HttpResponse::Ok().json(json!({ "vault": vault_json(&vault) })),
+        Err(e) => error_response(e),
+    }
+}
+
+async fn remove_vault(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
+    let pubkey = match require_session(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return resp,
+    };
+    match state.tenants.remove(&pubkey).await {
+        Ok(()) => HttpResponse::NoContent().finish(),
+        Err(e) => error_response(e),
+    }
+}
+
+pub(crate) fn sync_job_kind(pubkey: &str) -> String {
+    format!("tenant_sync:{}", pubkey)
+}
+
+/// Queues a sync of the caller's vault; one sync per tenant runs at a time
+async fn sync_vault(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
+    let pubkey = match require_session(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return resp,
+    };
+    if state.tenants.get(&pubkey).await.is_none() {
+        return error_response(if state.tenants.quota().enabled { TenantError::NotFound } else { TenantError::Disabled });
+    }
+
+    let tenants = state.tenants.clone();
+    let job_pubkey = pubkey.clone();
+    let oauth_token = state.get_github_token(&pubkey).await;
+    match state.job_queue.submit_unique(&sync_job_kind(&pubkey), move |_ctx| async move {
+        let status = tenants.sync(&job_pubkey, oauth_token).await.map_err(|e| e.to_string())?;
+        serde_json::to_value(status).map_err(|e| e.to_string())
+    }.boxed()) {
+        Ok(job_id) => HttpResponse::Accepted().json(json!({
+            "status": "queued",
+            "jobId": job_id
+        })),
+        Err(existing) => HttpResponse::Conflict().json(json!({
+            "status": "error",
+            "message": "A sync of this vault is already in progress",
+            "jobId": existing
+        })),
+    }
+}
+
+async fn get_vault_graph(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
+    let pubkey = match require_session(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return resp,
+    };
+    match state.tenants.graph(&pubkey).await {
+        Ok(graph) => HttpResponse::Ok().json(json!({
+            "nodes": graph.nodes,
+            "edges": graph.edges,
+            "metadata": graph.metadata,
+        })),
+        Err(e) => error_response(e),
+    }
+}
+
+/// Every hosted vault with its usage against the quota; power users only
+async fn list_vaults(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
+    let pubkey = match require_session(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return resp,
+    };
+    if !state.is_power_user(&pubkey) {
+        return HttpResponse::Forbidden().json(json!({"error": "Only power users can list vaults"}));
+    }
+    let quota = state.tenants.quota();
+    let vaults: Vec<_> = state.tenants.list().await.iter().map(vault_json).collect();
+    HttpResponse::Ok().json(json!({
+        "enabled": quota.enabled,
+        "quota": {
+            "maxVaults": quota.max_tenants,
+            "maxFiles": quota.max_files,
+            "maxBytes": quota.max_bytes,
+        },
+        "vaults": vaults,
+    }))
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/tenants")
+            .route(web::get().to(list_vaults))
+    ).service(
+        web::resource("/tenants/vault")
+            .route(web::get().to(get_vault))
+            .route(web::put().to(register_vault))
+            .route(web::delete().to(remove_vault))
+    ).service(
+        web::resource("/tenants/vault/sync")
+            .route(web::post().to(sync_vault))
+    ).service(
+        web::resource("/tenants/vault/graph")
+            .route(web::get().to(get_vault_graph))
+    );
+}
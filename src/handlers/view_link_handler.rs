@@ -0,0 +1,37 @@
+use crate::app_state::AppState;
+use crate::services::view_links::{ViewLinkError, ViewState};
+use actix_web::{web, HttpResponse};
+use log::error;
+use serde_json::json;
+
+/// Stores the posted view and returns the token for its `/g/<token>` link
+async fn create_view_link(state: web::Data<AppState>, view: web::Json<ViewState>) -> HttpResponse {
+    match state.view_links.create(view.into_inner()).await {
+        Ok(link) => HttpResponse::Created().json(json!({
+            "token": link.token,
+            "path": format!("/g/{}", link.token),
+        })),
+        Err(ViewLinkError::Invalid(e)) => HttpResponse::BadRequest().json(json!({ "error": e })),
+        Err(ViewLinkError::Storage(e)) => {
+            error!("Failed to create view link: {}", e);
+            HttpResponse::InternalServerError().json(json!({ "error": e }))
+        }
+    }
+}
+
+async fn resolve_view_link(state: web::Data<AppState>, token: web::Path<String>) -> HttpResponse {
+    match state.view_links.resolve(&token).await {
+        Some(link) => HttpResponse::Ok().json(link),
+        None => HttpResponse::NotFound().json(json!({ "error": format!("View link {} not found", token) })),
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/views")
+            .route(web::post().to(create_view_link))
+    ).service(
+        web::resource("/views/{token}")
+            .route(web::get().to(resolve_view_link))
+    );
+}
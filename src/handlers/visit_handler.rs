@@ -0,0 +1,145 @@
+use crate::actors::messages::GetGraphData;
+use crate::app_state::AppState;
+use crate::utils::auth::require_session;
+use crate::models::user_settings::UserSettings;
+use crate::models::UISettings;
+use crate::services::visit_heatmap;
+use actix_web::{web, HttpRequest, HttpResponse};
+use chrono::Utc;
+use log::{debug, error};
+use serde::Deserialize;
+use serde_json::json;
+
+const MAX_BATCH: usize = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct Tracking {
+    pub enabled: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Visit {
+    /// Metadata id of the node
+    pub node: String,
+    /// How long the node stayed selected
+    #[serde(default)]
+    pub dwell_ms: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VisitBatch {
+    pub visits: Vec<Visit>,
+}
+
+fn load_or_default(pubkey: &str) -> UserSettings {
+    UserSettings::load(pubkey).unwrap_or_else(|| UserSettings::new(pubkey, UISettings::default()))
+}
+
+fn save(user_settings: &mut UserSettings) -> Result<(), HttpResponse> {
+    user_settings.last_modified = Utc::now().timestamp();
+    user_settings.save().map_err(|e| {
+        error!("Failed to save visits for {}: {}", user_settings.pubkey, e);
+        HttpResponse::InternalServerError().json(json!({"error": "Failed to save visits"}))
+    })
+}
+
+async fn list_visits(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
+    let pubkey = match require_session(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return resp,
+    };
+    let user_settings = load_or_default(&pubkey);
+    HttpResponse::Ok().json(json!({ "tracking": user_settings.visit_tracking, "visits": user_settings.visits }))
+}
+
+/// Opts in to or out of visit tracking; opting out forgets the recorded visits
+async fn set_tracking(req: HttpRequest, state: web::Data<AppState>, body: web::Json<Tracking>) -> HttpResponse {
+    let pubkey = match require_session(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return resp,
+    };
+    let mut user_settings = load_or_default(&pubkey);
+    user_settings.set_visit_tracking(body.enabled);
+    if let Err(resp) = save(&mut user_settings) {
+        return resp;
+    }
+    debug!("User {} turned visit tracking {}", pubkey, if body.enabled { "on" } else { "off" });
+    HttpResponse::Ok().json(json!({ "tracking": user_settings.visit_tracking }))
+}
+
+/// Records a batch of visits, which clients gather while the user moves around
+async fn record_visits(req: HttpRequest, state: web::Data<AppState>, body: web::Json<VisitBatch>) -> HttpResponse {
+    let pubkey = match require_session(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return resp,
+    };
+    let batch = body.into_inner();
+    if batch.visits.len() > MAX_BATCH {
+        return HttpResponse::BadRequest().json(json!({"error": format!("At most {} visits per batch", MAX_BATCH)}));
+    }
+    let mut user_settings = load_or_default(&pubkey);
+    if !user_settings.visit_tracking {
+        return HttpResponse::Conflict().json(json!({"error": "Visit tracking is off"}));
+    }
+    let now = Utc::now().timestamp();
+    for visit in &batch.visits {
+        user_settings.record_visit(&visit.node, visit.dwell_ms, now);
+    }
+    if let Err(resp) = save(&mut user_settings) {
+        return resp;
+    }
+    HttpResponse::Accepted().json(json!({ "accepted": batch.visits.len() }))
+}
+
+async fn clear_visits(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
+    let pubkey = match require_session(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return resp,
+    };
+    let Some(mut user_settings) = UserSettings::load(&pubkey) else {
+        return HttpResponse::NoContent().finish();
+    };
+    if !user_settings.visits.is_empty() {
+        user_settings.visits.clear();
+        if let Err(resp) = save(&mut user_settings) {
+            return resp;
+        }
+        debug!("User {} cleared their visits", pubkey);
+    }
+    HttpResponse::NoContent().finish()
+}
+
+/// Heat of the live graph's nodes from the user's visits, for tinting
+async fn get_heatmap(req: HttpRequest, state: web::Data<AppState>) -> HttpResponse {
+    let pubkey = match require_session(&req, &state).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return resp,
+    };
+    let user_settings = load_or_default(&pubkey);
+    match state.graph_service_addr.send(GetGraphData).await {
+        Ok(Ok(graph)) => HttpResponse::Ok().json(json!({
+            "tracking": user_settings.visit_tracking,
+            "nodes": visit_heatmap::heatmap(&graph, &user_settings.visits),
+        })),
+        _ => {
+            error!("Failed to get graph data for the heatmap of {}", pubkey);
+            HttpResponse::InternalServerError().json(json!({"error": "Failed to get graph data"}))
+        }
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/users/me/visits")
+            .route(web::get().to(list_visits))
+            .route(web::post().to(record_visits))
+            .route(web::delete().to(clear_visits))
+    ).service(
+        web::resource("/users/me/visits/tracking")
+            .route(web::put().to(set_tracking))
+    ).service(
+        web::resource("/users/me/visits/heatmap")
+            .route(web::get().to(get_heatmap))
+    );
+}
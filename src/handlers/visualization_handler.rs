@@ -2,7 +2,7 @@ use crate::config::Settings;
 use crate::AppState;
 use crate::actors::messages::{GetSettings, UpdateSettings};
 use actix_web::{web, HttpResponse};
-use log::{debug, error, info};
+use log::{debug, error, info, warn};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
@@ -414,14 +414,14 @@ pub async fn update_setting(
                         error: None,
                     })
                 }
-                Ok(Err(e)) => {
-                    error!("Failed to update settings: {}", e);
-                    HttpResponse::InternalServerError().json(SettingResponse {
+                Ok(Err(errors)) => {
+                    warn!("Rejected setting {}.{}: {}", category, setting, errors);
+                    HttpResponse::BadRequest().json(SettingResponse {
                         category,
                         setting,
                         value: value.into_inner(),
                         success: false,
-                        error: Some("Failed to update settings".to_string()),
+                        error: Some(errors.to_string()),
                     })
                 }
                 Err(e) => {
@@ -0,0 +1,109 @@
+use crate::app_state::AppState;
+use crate::services::nostr_service::NostrService;
+use crate::utils::auth::verify_power_user;
+use actix_web::{web, Error, HttpRequest, HttpResponse};
+use log::error;
+use serde::Deserialize;
+use serde_json::json;
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterWebhookRequest {
+    pub url: String,
+    pub secret: String,
+    #[serde(default)]
+    pub events: Vec<String>,
+}
+
+// Webhooks can trigger external automation, so only signed-in power users may
+// manage them
+async fn list_webhooks(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    nostr_service: web::Data<NostrService>,
+) -> Result<HttpResponse, Error> {
+    if let Err(resp) = verify_power_user(&req, &nostr_service).await {
+        return Ok(resp);
+    }
+
+    // Never echo secrets back to the client
+    let hooks: Vec<_> = state.webhook_service.list().await
+        .into_iter()
+        .map(|h| json!({
+            "id": h.id,
+            "url": h.url,
+            "events": h.events,
+            "createdBy": h.created_by,
+            "createdAt": h.created_at,
+        }))
+        .collect();
+
+    Ok(HttpResponse::Ok().json(json!({
+        "webhooks": hooks,
+        "supportedEvents": crate::services::webhook_service::SUPPORTED_EVENTS,
+    })))
+}
+
+async fn register_webhook(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    nostr_service: web::Data<NostrService>,
+    payload: web::Json<RegisterWebhookRequest>,
+) -> Result<HttpResponse, Error> {
+    let pubkey = match verify_power_user(&req, &nostr_service).await {
+        Ok(pubkey) => pubkey,
+        Err(resp) => return Ok(resp),
+    };
+
+    let payload = payload.into_inner();
+    match state.webhook_service.register(payload.url, payload.secret, payload.events, &pubkey).await {
+        Ok(hook) => Ok(HttpResponse::Created().json(json!({
+            "status": "success",
+            "id": hook.id,
+        }))),
+        Err(e) => {
+            error!("Failed to register webhook: {}", e);
+            Ok(HttpResponse::BadRequest().json(json!({
+                "status": "error",
+                "message": e
+            })))
+        }
+    }
+}
+
+async fn delete_webhook(
+    req: HttpRequest,
+    state: web::Data<AppState>,
+    nostr_service: web::Data<NostrService>,
+    id: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    if let Err(resp) = verify_power_user(&req, &nostr_service).await {
+        return Ok(resp);
+    }
+
+    match state.webhook_service.remove(&id).await {
+        Ok(true) => Ok(HttpResponse::Ok().json(json!({ "status": "success" }))),
+        Ok(false) => Ok(HttpResponse::NotFound().json(json!({
+            "status": "error",
+            "message": format!("Webhook {} not found", id)
+        }))),
+        Err(e) => {
+            error!("Failed to remove webhook {}: {}", id, e);
+            Ok(HttpResponse::InternalServerError().json(json!({
+                "status": "error",
+                "message": e
+            })))
+        }
+    }
+}
+
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(
+        web::resource("/admin/webhooks")
+            .route(web::get().to(list_webhooks))
+            .route(web::post().to(register_webhook))
+    ).service(
+        web::resource("/admin/webhooks/{id}")
+            .route(web::delete().to(delete_webhook))
+    );
+}
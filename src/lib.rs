@@ -1,11 +1,15 @@
 pub mod actors;
 pub mod app_state;
+pub mod cli;
 pub mod config;
+pub mod errors;
 pub mod handlers;
 pub mod models;
 pub mod services;
+pub mod test_support;
 pub mod types;
 pub mod utils;
+pub mod ws_bench;
 
 pub use app_state::AppState;
 pub use actors::{GraphServiceActor, SettingsActor, MetadataActor, ClientManagerActor};
@@ -2,15 +2,18 @@ use webxr::services::nostr_service::NostrService;
 use webxr::{
     AppState,
     config::AppFullSettings, // Import AppFullSettings only
+    config::data_dirs::DataDirs,
     handlers::{
         api_handler,
         health_handler,
         pages_handler,
+        share_handler,
         socket_flow_handler::{socket_flow_handler, PreReadSocketSettings}, // Import PreReadSocketSettings
         speech_socket_handler::speech_socket_handler,
         nostr_handler,
     },
     services::{
+        demo::{self, DemoConfig},
         file_service::FileService,
         graph_service::GraphService,
         github::{GitHubClient, ContentAPI, GitHubConfig},
@@ -19,9 +22,16 @@ use webxr::{
     services::speech_service::SpeechService,
 };
 
+use webxr::cli::Cli;
+
 use actix_web::{web, App, HttpServer, middleware};
+use actix_web::dev::Service;
+use actix_web::http::header::{HeaderName, HeaderValue};
+use futures::FutureExt;
+use webxr::errors;
+use clap::Parser;
 use actix_cors::Cors;
-// use actix_files::Files; // Removed unused import
+use actix_files::Files;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use tokio::time::Duration;
@@ -32,6 +42,8 @@ use tokio::signal::unix::{signal, SignalKind};
 
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
+    let cli = Cli::parse();
+
     // Make dotenv optional since env vars can come from Docker
     dotenv().ok();
 
@@ -75,15 +87,41 @@ async fn main() -> std::io::Result<()> {
 
     debug!("Successfully loaded AppFullSettings"); // Updated log message
 
+    // Fix the data locations before any service reads or writes under them
+    let data_dirs = DataDirs::from_env();
+    info!("Using data directory {}", data_dirs.root.display());
+    let client_dir = data_dirs.client.clone();
+    if DataDirs::init(data_dirs).is_err() {
+        warn!("Data directories were read before startup configured them");
+    }
+
+    if let Some(command) = cli.command {
+        return webxr::cli::run(command, settings).await;
+    }
+
     info!("Starting WebXR application...");
 
+    // Check the vault key before anything reads stored markdown
+    webxr::services::vault_crypto::init()
+        .map_err(|e| std::io::Error::other(format!("Failed to initialize vault encryption: {}", e)))?;
+
     // Create web::Data instances first
     // This now holds Data<Arc<RwLock<AppFullSettings>>>
     let settings_data = web::Data::new(settings.clone());
 
+    let demo_config = DemoConfig::from_env();
+    if demo_config.enabled {
+        info!("Demo mode: serving a synthetic vault of {} pages", demo_config.nodes);
+    }
+
     // Initialize services
     let github_config = match GitHubConfig::from_env() {
         Ok(config) => config,
+        // The demo vault is generated, so it runs without credentials
+        Err(e) if demo_config.enabled => {
+            info!("Demo mode without GitHub: {}", e);
+            GitHubConfig::unconfigured()
+        }
         Err(e) => return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to load GitHub config: {}", e)))
     };
 
@@ -128,6 +166,7 @@ async fn main() -> std::io::Result<()> {
         let settings_read = settings.read().await;
         settings_read.clone()
     };
+    let settings_seed = settings_value.visualisation.physics.seed;
 
     let mut app_state = match AppState::new(
             settings_value,
@@ -136,7 +175,6 @@ async fn main() -> std::io::Result<()> {
             None, // Perplexity placeholder
             ragflow_service_option, // Pass the initialized RAGFlow service
             speech_service,
-            "default_session".to_string() // RAGFlow session ID placeholder
         ).await {
             Ok(state) => state,
             Err(e) => return Err(std::io::Error::new(std::io::ErrorKind::Other, format!("Failed to initialize app state: {}", e)))
@@ -146,14 +184,16 @@ async fn main() -> std::io::Result<()> {
     nostr_handler::init_nostr_service(&mut app_state);
 
     // First, try to load existing metadata without waiting for GitHub download
-    info!("Loading existing metadata for quick initialization");
-    let metadata_store = FileService::load_or_create_metadata()
-        .map_err(|e| {
-            error!("Failed to load existing metadata: {}", e);
-            std::io::Error::new(std::io::ErrorKind::Other, e.to_string())
-        })?;
-
-    info!("Note: Background GitHub data fetch is disabled to resolve compilation issues");
+    let metadata_store = if demo_config.enabled {
+        demo::generate(demo_config.nodes, settings_seed.unwrap_or_default(), chrono::Utc::now())
+    } else {
+        info!("Loading existing metadata for quick initialization");
+        FileService::load_or_create_metadata()
+            .map_err(|e| {
+                error!("Failed to load existing metadata: {}", e);
+                std::io::Error::other(e.to_string())
+            })?
+    };
 
     if metadata_store.is_empty() {
         error!("No metadata found and could not create empty store");
@@ -174,7 +214,7 @@ async fn main() -> std::io::Result<()> {
     // Build initial graph from metadata and initialize GPU compute
     info!("Building initial graph from existing metadata for physics simulation");
 
-    match GraphService::build_graph_from_metadata(&metadata_store).await {
+    match GraphService::build_graph_from_metadata(&metadata_store, settings_seed).await {
         Ok(graph_data) => {
             // Update graph data in the GraphServiceActor
             use webxr::actors::messages::{UpdateGraphData, InitializeGPU};
@@ -232,6 +272,13 @@ async fn main() -> std::io::Result<()> {
     // Create web::Data after all initialization is complete
     let app_state_data = web::Data::new(app_state);
 
+    // There is no repository to sync the demo vault from
+    if !demo_config.enabled {
+        api_handler::files::start_initial_sync(app_state_data.clone());
+        let sync_schedule = settings.read().await.sync.clone();
+        api_handler::files::start_scheduled_sync(app_state_data.clone(), sync_schedule.as_ref());
+    }
+
     // Start the server
     let bind_address = {
         let settings_read = settings.read().await; // Reads AppFullSettings
@@ -249,6 +296,8 @@ async fn main() -> std::io::Result<()> {
             motion_damping: s.system.websocket.motion_damping,
             heartbeat_interval_ms: s.system.websocket.heartbeat_interval, // Assuming these exist
             heartbeat_timeout_ms: s.system.websocket.heartbeat_timeout,   // Assuming these exist
+            bounds_size: s.visualisation.physics.bounds_size,
+            max_velocity: s.visualisation.physics.max_velocity,
         }
     };
     let pre_read_ws_settings_data = web::Data::new(pre_read_ws_settings);
@@ -265,6 +314,19 @@ async fn main() -> std::io::Result<()> {
 
         let mut app = App::new()
             .wrap(middleware::Logger::default())
+            .wrap_fn(|req, srv| {
+                // Errors answered for this request, and its response, carry the id
+                let id = errors::correlation_id(req.headers());
+                let header = HeaderValue::from_str(&id).ok();
+                errors::with_correlation_id(id, srv.call(req)).map(move |res| {
+                    res.map(|mut res| {
+                        if let Some(header) = header {
+                            res.headers_mut().insert(HeaderName::from_static("x-correlation-id"), header);
+                        }
+                        res
+                    })
+                })
+            })
             .wrap(cors)
             .wrap(middleware::Compress::default())
             // Pass AppFullSettings wrapped in Data
@@ -282,6 +344,7 @@ async fn main() -> std::io::Result<()> {
             .app_data(app_state_data.feature_access.clone())
             .route("/wss", web::get().to(socket_flow_handler)) // Changed from /ws to /wss
             .route("/ws/speech", web::get().to(speech_socket_handler))
+            .service(web::scope("/share").configure(share_handler::public_config))
             .service(
                 web::scope("/api") // Add /api prefix for these routes
                     .configure(api_handler::config) // This will now serve /api/user-settings etc.
@@ -289,6 +352,11 @@ async fn main() -> std::io::Result<()> {
                     .service(web::scope("/pages").configure(pages_handler::config))
             );
 
+        // Serve the built client ourselves when no reverse proxy is in front
+        if let Some(client_dir) = &client_dir {
+            app = app.service(Files::new("/", client_dir).index_file("index.html"));
+        }
+
         app
     })
     .bind(&bind_address)?
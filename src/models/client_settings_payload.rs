@@ -1,4 +1,6 @@
 use serde::Deserialize;
+use crate::config::{EdgeWeightNormalization, LayoutMode, NodeColorMode};
+use crate::models::simulation_params::SimulationMode;
 
 // Consistent camelCase for client JSON interaction
 
@@ -78,6 +80,7 @@ pub struct ClientNodeSettings {
     pub enable_hologram: Option<bool>,
     pub enable_metadata_shape: Option<bool>,
     pub enable_metadata_visualisation: Option<bool>,
+    pub color_mode: Option<NodeColorMode>,
 }
 
 // --- Edge Settings DTO ---
@@ -107,7 +110,10 @@ pub struct ClientEdgeSettings {
 pub struct ClientPhysicsSettings {
     pub attraction_strength: Option<f32>,
     pub bounds_size: Option<f32>,
+    pub auto_fit_bounds: Option<bool>,
+    pub max_bounds_size: Option<f32>,
     pub collision_radius: Option<f32>,
+    pub collision_stiffness: Option<f32>,
     pub damping: Option<f32>,
     pub enable_bounds: Option<bool>,
     pub enabled: Option<bool>,
@@ -118,6 +124,13 @@ pub struct ClientPhysicsSettings {
     pub repulsion_distance: Option<f32>,
     pub mass_scale: Option<f32>,
     pub boundary_damping: Option<f32>,
+    pub layout_mode: Option<LayoutMode>,
+    pub plane_z: Option<f32>,
+    pub force_model: Option<SimulationMode>,
+    pub gravity: Option<f32>,
+    pub strong_gravity: Option<bool>,
+    pub edge_weight_normalization: Option<EdgeWeightNormalization>,
+    pub cluster_separation: Option<f32>,
 }
 
 // --- Rendering Settings DTO ---
@@ -304,6 +317,9 @@ pub struct ClientOpenAISettings {
     pub base_url: Option<String>,
     pub timeout: Option<u64>,
     pub rate_limit: Option<u32>,
+    pub tts_model: Option<String>,
+    pub tts_voice: Option<String>,
+    pub tts_speed: Option<f32>,
 }
 
 #[derive(Deserialize, Debug, Default, Clone)]
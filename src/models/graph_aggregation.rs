@@ -0,0 +1,123 @@
+//! Level-of-detail aggregation of the graph into supernodes
+//!
+//! Nodes are grouped by the cell of a uniform grid they fall into when the
+//! aggregation is built. Every cell holding at least `MIN_SUPERNODE_MEMBERS` nodes
+//! becomes a supernode that stands in for its members while collapsed. Membership is
+//! fixed once built so supernodes don't flicker as the layout moves; only their
+//! positions follow the members.
+
+use glam::Vec3;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::types::vec3::Vec3Data;
+
+/// Supernode ids have the top bit set so they can't collide with node ids
+pub const SUPERNODE_ID_BASE: u32 = 0x8000_0000;
+pub const MIN_SUPERNODE_MEMBERS: usize = 2;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Supernode {
+    pub id: u32,
+    /// Aggregate size, for scaling the supernode on the client
+    pub member_count: usize,
+    pub members: Vec<u32>,
+    /// Members' centroid when the aggregation was built
+    pub position: Vec3Data,
+}
+
+#[derive(Debug, Clone)]
+pub struct GraphAggregation {
+    pub cell_size: f32,
+    pub supernodes: Vec<Supernode>,
+    // Node id to index in `supernodes`
+    membership: HashMap<u32, usize>,
+}
+
+impl GraphAggregation {
+    pub fn build<I>(nodes: I, cell_size: f32) -> Result<Self, String>
+    where
+        I: IntoIterator<Item = (u32, Vec3Data)>,
+    {
+        if !(cell_size.is_finite() && cell_size > 0.0) {
+            return Err(format!("Cell size {} must be positive", cell_size));
+        }
+
+        // Ordered by cell so the same layout always yields the same supernode ids
+        let mut cells: BTreeMap<(i32, i32, i32), Vec<(u32, Vec3)>> = BTreeMap::new();
+        for (id, position) in nodes {
+            let position = Vec3::from(position);
+            let cell = (position / cell_size).floor();
+            cells.entry((cell.x as i32, cell.y as i32, cell.z as i32))
+                .or_default()
+                .push((id, position));
+        }
+
+        let mut supernodes = Vec::new();
+        let mut membership = HashMap::new();
+        for members in cells.into_values().filter(|m| m.len() >= MIN_SUPERNODE_MEMBERS) {
+            let index = supernodes.len();
+            let centroid = members.iter().map(|(_, p)| *p).sum::<Vec3>() / members.len() as f32;
+            for (id, _) in &members {
+                membership.insert(*id, index);
+            }
+            supernodes.push(Supernode {
+                id: SUPERNODE_ID_BASE + index as u32,
+                member_count: members.len(),
+                members: members.into_iter().map(|(id, _)| id).collect(),
+                position: centroid.into(),
+            });
+        }
+
+        Ok(Self { cell_size, supernodes, membership })
+    }
+
+    /// Index of the supernode `node_id` belongs to, if it was aggregated
+    pub fn supernode_of(&self, node_id: u32) -> Option<usize> {
+        self.membership.get(&node_id).copied()
+    }
+
+    /// Index of the supernode with the given wire id
+    pub fn index_of(&self, supernode_id: u32) -> Option<usize> {
+        let index = supernode_id.checked_sub(SUPERNODE_ID_BASE)? as usize;
+        (index < self.supernodes.len()).then_some(index)
+    }
+
+    pub fn aggregated_node_count(&self) -> usize {
+        self.membership.len()
+    }
+}
+
+pub fn is_supernode_id(id: u32) -> bool {
+    id >= SUPERNODE_ID_BASE
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_groups_nodes_by_cell() {
+        let nodes = [
+            (1, Vec3Data::new(0.5, 0.5, 0.5)),
+            (2, Vec3Data::new(1.5, 0.5, 0.5)),
+            (3, Vec3Data::new(12.0, 0.0, 0.0)),
+            (4, Vec3Data::new(-3.0, -3.0, -3.0)),
+            (5, Vec3Data::new(-4.0, -2.0, -1.0)),
+        ];
+        let aggregation = GraphAggregation::build(nodes, 5.0).unwrap();
+
+        assert_eq!(aggregation.supernodes.len(), 2);
+        assert_eq!(aggregation.aggregated_node_count(), 4);
+        // Cells are ordered, so the negative cell comes first
+        assert_eq!(aggregation.supernodes[0].members, vec![4, 5]);
+        assert_eq!(aggregation.supernodes[1].position, Vec3Data::new(1.0, 0.5, 0.5));
+        assert_eq!(aggregation.supernode_of(2), Some(1));
+        assert_eq!(aggregation.supernode_of(3), None);
+        assert_eq!(aggregation.index_of(SUPERNODE_ID_BASE + 1), Some(1));
+        assert_eq!(aggregation.index_of(SUPERNODE_ID_BASE + 2), None);
+        assert_eq!(aggregation.index_of(7), None);
+        assert!(GraphAggregation::build(Vec::new(), 0.0).is_err());
+    }
+}
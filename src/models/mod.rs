@@ -1,7 +1,6 @@
-pub mod edge;
-pub mod graph;
-pub mod metadata;
-pub mod node;
+pub use webxr_core::models::{edge, graph, metadata, node};
+pub mod graph_aggregation;
+pub mod node_grab;
 pub mod pagination;
 pub mod protected_settings;
 pub mod simulation_params;
@@ -0,0 +1,190 @@
+//! Client authority over dragged nodes
+//!
+//! A client that starts dragging a node holds it until it lets go, disconnects or
+//! stops sending moves for `GRAB_TIMEOUT`. While a node is held the solver leaves it
+//! where the client put it and other clients can't move it. On release the solver's
+//! steps are blended in over `RELEASE_BLEND_STEPS`, so the node eases back into the
+//! layout instead of snapping.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::utils::socket_flow_messages::BinaryNodeData;
+
+/// Idle time after which a grab is released on the client's behalf
+pub const GRAB_TIMEOUT: Duration = Duration::from_secs(10);
+/// Simulation steps over which a released node returns to full physics
+pub const RELEASE_BLEND_STEPS: u32 = 30;
+
+#[derive(Debug, Clone, Copy)]
+struct Grab {
+    client_id: usize,
+    last_move: Instant,
+}
+
+#[derive(Debug, Default)]
+pub struct NodeGrabs {
+    held: HashMap<u32, Grab>,
+    // Steps taken since release, for nodes still blending back in
+    releasing: HashMap<u32, u32>,
+}
+
+impl NodeGrabs {
+    pub fn holder(&self, node_id: u32) -> Option<usize> {
+        self.held.get(&node_id).map(|grab| grab.client_id)
+    }
+
+    pub fn is_held(&self, node_id: u32) -> bool {
+        self.held.contains_key(&node_id)
+    }
+
+    /// Gives `client_id` authority over the node. Grabbing a node the client
+    /// already holds just refreshes it.
+    pub fn grab(&mut self, node_id: u32, client_id: usize, now: Instant) -> Result<(), String> {
+        match self.holder(node_id) {
+            Some(holder) if holder != client_id => {
+                Err(format!("Node {} is being dragged by client {}", node_id, holder))
+            }
+            _ => {
+                self.releasing.remove(&node_id);
+                self.held.insert(node_id, Grab { client_id, last_move: now });
+                Ok(())
+            }
+        }
+    }
+
+    /// Checks a move from `client_id` is allowed and keeps its grab alive
+    pub fn touch(&mut self, node_id: u32, client_id: usize, now: Instant) -> Result<(), String> {
+        match self.held.get_mut(&node_id) {
+            Some(grab) if grab.client_id == client_id => {
+                grab.last_move = now;
+                Ok(())
+            }
+            Some(grab) => Err(format!("Node {} is being dragged by client {}", node_id, grab.client_id)),
+            None => Err(format!("Node {} is not grabbed", node_id)),
+        }
+    }
+
+    pub fn release(&mut self, node_id: u32, client_id: usize) -> Result<(), String> {
+        self.touch(node_id, client_id, Instant::now())?;
+        self.held.remove(&node_id);
+        self.releasing.insert(node_id, 0);
+        Ok(())
+    }
+
+    /// Releases everything `client_id` holds, e.g. when it disconnects
+    pub fn release_client(&mut self, client_id: usize) -> Vec<u32> {
+        let released: Vec<u32> = self.held.iter()
+            .filter(|(_, grab)| grab.client_id == client_id)
+            .map(|(&node_id, _)| node_id)
+            .collect();
+        self.finish(&released);
+        released
+    }
+
+    /// Releases grabs whose client hasn't moved them for `GRAB_TIMEOUT`
+    pub fn expire(&mut self, now: Instant) -> Vec<(u32, usize)> {
+        let expired: Vec<(u32, usize)> = self.held.iter()
+            .filter(|(_, grab)| now.duration_since(grab.last_move) >= GRAB_TIMEOUT)
+            .map(|(&node_id, grab)| (node_id, grab.client_id))
+            .collect();
+        self.finish(&expired.iter().map(|&(node_id, _)| node_id).collect::<Vec<_>>());
+        expired
+    }
+
+    fn finish(&mut self, node_ids: &[u32]) {
+        for node_id in node_ids {
+            self.held.remove(node_id);
+            self.releasing.insert(*node_id, 0);
+        }
+    }
+
+    /// Adjusts one solver step. Held nodes are dropped from `updates` so they stay
+    /// where their client put them; released nodes move a growing fraction of the
+    /// way from `current` towards the solver's result.
+    pub fn apply_step(
+        &mut self,
+        updates: &mut Vec<(u32, BinaryNodeData)>,
+        current: impl Fn(u32) -> Option<BinaryNodeData>,
+    ) {
+        if self.held.is_empty() && self.releasing.is_empty() {
+            return;
+        }
+        updates.retain(|(node_id, _)| !self.held.contains_key(node_id));
+        for (node_id, data) in updates.iter_mut() {
+            let (Some(steps), Some(before)) = (self.releasing.get(node_id), current(*node_id)) else {
+                continue;
+            };
+            let weight = (*steps + 1) as f32 / RELEASE_BLEND_STEPS as f32;
+            data.position.x = before.position.x + (data.position.x - before.position.x) * weight;
+            data.position.y = before.position.y + (data.position.y - before.position.y) * weight;
+            data.position.z = before.position.z + (data.position.z - before.position.z) * weight;
+            data.velocity.x *= weight;
+            data.velocity.y *= weight;
+            data.velocity.z *= weight;
+        }
+        self.releasing.retain(|_, steps| {
+            *steps += 1;
+            *steps < RELEASE_BLEND_STEPS
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::vec3::Vec3Data;
+
+    fn at(x: f32) -> BinaryNodeData {
+        BinaryNodeData {
+            position: Vec3Data::new(x, 0.0, 0.0),
+            velocity: Vec3Data::zero(),
+            mass: 1,
+            flags: 1,
+            padding: [0, 0],
+        }
+    }
+
+    #[test]
+    fn test_grab_authority() {
+        let now = Instant::now();
+        let mut grabs = NodeGrabs::default();
+        grabs.grab(1, 7, now).unwrap();
+        assert!(grabs.grab(1, 8, now).is_err());
+        assert!(grabs.touch(1, 8, now).is_err());
+        assert!(grabs.release(1, 8).is_err());
+        grabs.touch(1, 7, now).unwrap();
+
+        assert_eq!(grabs.expire(now + GRAB_TIMEOUT / 2), vec![]);
+        assert_eq!(grabs.expire(now + GRAB_TIMEOUT), vec![(1, 7)]);
+        grabs.grab(1, 8, now).unwrap();
+        grabs.grab(2, 8, now).unwrap();
+        let mut released = grabs.release_client(8);
+        released.sort_unstable();
+        assert_eq!(released, vec![1, 2]);
+        assert!(!grabs.is_held(1));
+    }
+
+    #[test]
+    fn test_step_holds_then_blends() {
+        let mut grabs = NodeGrabs::default();
+        grabs.grab(1, 7, Instant::now()).unwrap();
+        let mut updates = vec![(1, at(10.0)), (2, at(10.0))];
+        grabs.apply_step(&mut updates, |_| Some(at(0.0)));
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].0, 2);
+        assert_eq!(updates[0].1.position.x, 10.0);
+
+        grabs.release(1, 7).unwrap();
+        let mut updates = vec![(1, at(10.0))];
+        grabs.apply_step(&mut updates, |_| Some(at(0.0)));
+        assert!((updates[0].1.position.x - 10.0 / RELEASE_BLEND_STEPS as f32).abs() < 1e-5);
+
+        for _ in 1..RELEASE_BLEND_STEPS {
+            grabs.apply_step(&mut vec![(1, at(10.0))], |_| Some(at(0.0)));
+        }
+        let mut updates = vec![(1, at(10.0))];
+        grabs.apply_step(&mut updates, |_| Some(at(0.0)));
+        assert_eq!(updates[0].1.position.x, 10.0);
+    }
+}
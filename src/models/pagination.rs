@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 use crate::models::edge::Edge;
-use crate::utils::socket_flow_messages::Node;
+use crate::models::node::Node;
 
 #[derive(Debug, Deserialize)]
 pub struct PaginationParams {
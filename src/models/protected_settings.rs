@@ -1,5 +1,13 @@
 use serde::{Deserialize, Serialize};
 use chrono::Utc;
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use std::path::{Path, PathBuf};
+use crate::config::data_dirs::DataDirs;
+use crate::services::vault_crypto::VaultCipher;
+
+pub fn protected_settings_path() -> PathBuf {
+    DataDirs::global().metadata_file("protected_settings.json")
+}
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -28,6 +36,98 @@ pub struct ProtectedSettings {
     pub websocket_server: WebSocketServerSettings,
     pub users: std::collections::HashMap<String, NostrUser>,
     pub default_api_keys: ApiKeys,
+    /// GitHub accounts connected through OAuth, by Nostr pubkey. Kept apart from
+    /// `users` so expiring a session doesn't disconnect the account.
+    #[serde(default)]
+    pub github_connections: std::collections::HashMap<String, GitHubConnection>,
+    #[serde(default)]
+    pub nostr_publishing: NostrPublishingSettings,
+    #[serde(default)]
+    pub nostr_ingest: NostrIngestSettings,
+}
+
+/// Announcing added and updated pages as Nostr events, signed with the key in
+/// `NOSTR_PUBLISH_SECRET_KEY`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct NostrPublishingSettings {
+    pub enabled: bool,
+    /// Relay URLs, e.g. `wss://relay.damus.io`
+    pub relays: Vec<String>,
+    /// Event kind; 1, a text note, unless followers expect another
+    pub kind: u16,
+}
+
+impl Default for NostrPublishingSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            relays: Vec::new(),
+            kind: 1,
+        }
+    }
+}
+
+/// Pulling notes from Nostr relays into the graph. Events must match every
+/// non-empty filter.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct NostrIngestSettings {
+    pub enabled: bool,
+    pub relays: Vec<String>,
+    /// Public keys, hex or `npub`
+    pub authors: Vec<String>,
+    /// Event kinds; long-form posts (30023) unless set
+    pub kinds: Vec<u16>,
+    pub hashtags: Vec<String>,
+    /// Notes kept in the graph and the offline cache, newest first
+    pub max_notes: usize,
+}
+
+impl Default for NostrIngestSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            relays: Vec::new(),
+            authors: Vec::new(),
+            kinds: vec![30023],
+            hashtags: Vec::new(),
+            max_notes: 500,
+        }
+    }
+}
+
+/// A user's GitHub OAuth token, sealed with `VAULT_ENCRYPTION_KEY`. Without a key
+/// there is no connection to store.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GitHubConnection {
+    pub login: Option<String>,
+    pub scopes: Vec<String>,
+    pub connected_at: i64,
+    sealed_token: String,
+}
+
+impl GitHubConnection {
+    pub fn new(token: &str, login: Option<String>, scopes: Vec<String>) -> Result<Self, String> {
+        let sealed = VaultCipher::global()
+            .and_then(|cipher| cipher.seal_secret(token.as_bytes()))
+            .map_err(|e| format!("Failed to seal GitHub token: {}", e))?;
+        Ok(Self {
+            login,
+            scopes,
+            connected_at: Utc::now().timestamp(),
+            sealed_token: BASE64.encode(sealed),
+        })
+    }
+
+    pub fn token(&self) -> Result<String, String> {
+        let sealed = BASE64.decode(&self.sealed_token)
+            .map_err(|e| format!("Stored GitHub token is corrupt: {}", e))?;
+        VaultCipher::global()
+            .and_then(|cipher| cipher.decode_string(&sealed))
+            .map_err(|e| format!("Failed to read stored GitHub token: {}", e))
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -112,6 +212,9 @@ impl Default for ProtectedSettings {
             },
             users: std::collections::HashMap::new(),
             default_api_keys: ApiKeys::default(),
+            github_connections: std::collections::HashMap::new(),
+            nostr_publishing: NostrPublishingSettings::default(),
+            nostr_ingest: NostrIngestSettings::default(),
         }
     }
 }
@@ -148,6 +251,18 @@ impl ProtectedSettings {
             }
         }
 
+        if let Some(publishing) = other.get("nostrPublishing") {
+            if let Ok(publishing_settings) = serde_json::from_value(publishing.clone()) {
+                self.nostr_publishing = publishing_settings;
+            }
+        }
+
+        if let Some(ingest) = other.get("nostrIngest") {
+            if let Ok(ingest_settings) = serde_json::from_value(ingest.clone()) {
+                self.nostr_ingest = ingest_settings;
+            }
+        }
+
         Ok(())
     }
 
@@ -209,7 +324,7 @@ impl ProtectedSettings {
         }
     }
 
-    pub fn load(path: &str) -> Result<Self, String> {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, String> {
         let content = std::fs::read_to_string(path)
             .map_err(|e| format!("Failed to read protected settings: {}", e))?;
         
@@ -217,7 +332,12 @@ impl ProtectedSettings {
             .map_err(|e| format!("Failed to parse protected settings: {}", e))
     }
 
-    pub fn save(&self, path: &str) -> Result<(), String> {
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| format!("Failed to create protected settings directory: {}", e))?;
+        }
         let content = serde_json::to_string_pretty(self)
             .map_err(|e| format!("Failed to serialize protected settings: {}", e))?;
         
@@ -7,6 +7,8 @@ pub struct RagflowChatRequest {
     pub question: String,
     pub session_id: Option<String>, // Client might send existing session ID
     pub stream: Option<bool>,       // Add stream parameter, optional
+    pub focus_node_id: Option<u32>, // Answer with the notes around this node as context
+    pub conversation_id: Option<String>, // Continue a stored conversation
     // Add any other RAGFlow specific params client might send
 }
 
@@ -15,5 +17,9 @@ pub struct RagflowChatRequest {
 pub struct RagflowChatResponse {
     pub answer: String,
     pub session_id: String, // Server returns session_id for future requests
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conversation_id: Option<String>, // Conversation the exchange was stored in
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub context_node_ids: Vec<u32>, // Notes sent along with the question, focus first
     // Add any other RAGFlow specific response fields
 }
\ No newline at end of file
@@ -1,17 +1,44 @@
 use serde::{Deserialize, Serialize};
 use bytemuck::{Pod, Zeroable};
+use std::collections::BTreeMap;
+use crate::config::{EdgeWeightNormalization, NodeTypePhysics};
+use crate::models::edge::Edge;
+use crate::models::node::Node;
+use crate::utils::socket_flow_messages::BinaryNodeData;
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+// The kernel reads a node's charge multiplier from `padding[0]` and its collision
+// radius multiplier from `padding[1]`, both in sixteenths with 0 meaning 1
+const PACKED_UNITS: f32 = 16.0;
+// Size of the smallest file-based node, which collides at `collision_radius`
+const REFERENCE_NODE_SIZE: f32 = 5.0;
+
+fn pack_multiplier(value: f32) -> u8 {
+    if value == 1.0 {
+        0
+    } else {
+        (value * PACKED_UNITS).round().clamp(1.0, 255.0) as u8
+    }
+}
+
+/// Force model used by both solvers. The discriminants are the values the kernel
+/// switches on.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub enum SimulationMode {
-    Remote,  // GPU-accelerated remote computation (default)
-    GPU,     // Local GPU computation (deprecated)
-    Local,   // CPU-based computation (disabled)
+    /// Springs and inverse-square repulsion, scaled by node mass. Older configs named
+    /// the compute backend here, which now always means this model.
+    #[serde(alias = "remote", alias = "gpu", alias = "local")]
+    SpringElectric = 0,
+    /// ForceAtlas2: repulsion by degree falling off with distance, attraction along
+    /// edges growing linearly with it, and gravity towards the origin
+    ForceAtlas2 = 1,
+    /// ForceAtlas2 with logarithmic attraction, which pulls clusters tighter
+    LinLog = 2,
 }
 
 impl Default for SimulationMode {
     fn default() -> Self {
-        SimulationMode::Remote
+        SimulationMode::SpringElectric
     }
 }
 
@@ -63,10 +90,41 @@ pub struct SimulationParams {
     // Boundary control
     pub viewport_bounds: f32,     // Range: 100-5000, Default: 1000
     pub enable_bounds: bool,      // Default: true
+
+    // Collision
+    #[serde(default)]
+    pub collision_radius: f32,    // Default: 0.5, scaled per node by its size
+    #[serde(default)]
+    pub collision_stiffness: f32, // Default: 0.5, 0 disables collisions
+
+    // 2D layouts
+    #[serde(default)]
+    pub plane_z: Option<f32>,     // z nodes are held to; None lays out in 3D
     
     // Simulation state
     pub phase: SimulationPhase,   // Current simulation phase
-    pub mode: SimulationMode,     // Computation mode
+    #[serde(default)]
+    pub mode: SimulationMode,     // Force model
+
+    // ForceAtlas2 models
+    #[serde(default)]
+    pub gravity: f32,             // Default: 1.0, pull towards the origin per unit of degree
+    #[serde(default)]
+    pub strong_gravity: bool,     // Gravity grows with distance from the origin
+
+    // Edges and hubs
+    #[serde(default)]
+    pub edge_weight_normalization: EdgeWeightNormalization,
+    #[serde(default)]
+    pub degree_gravity: f32,      // Pull towards the origin for the busiest node, scaled by relative degree
+
+    // Compound layout
+    #[serde(default)]
+    pub cluster_separation: f32,  // Gap between communities over their combined radii; 0 disables
+
+    // Mass and charge multipliers by node type
+    #[serde(default)]
+    pub node_types: BTreeMap<String, NodeTypePhysics>,
 }
 
 impl SimulationParams {
@@ -82,8 +140,17 @@ impl SimulationParams {
             boundary_damping: 0.9,
             viewport_bounds: 1000.0,
             enable_bounds: true,
+            collision_radius: 0.5,
+            collision_stiffness: 0.5,
+            plane_z: None,
             phase: SimulationPhase::Initial,
-            mode: SimulationMode::Remote,
+            mode: SimulationMode::SpringElectric,
+            gravity: 1.0,
+            strong_gravity: false,
+            edge_weight_normalization: EdgeWeightNormalization::None,
+            degree_gravity: 0.0,
+            cluster_separation: 0.0,
+            node_types: BTreeMap::new(),
         }
     }
 
@@ -100,8 +167,17 @@ impl SimulationParams {
                 boundary_damping: 0.95,
                 viewport_bounds: 1000.0,
                 enable_bounds: true,
+                collision_radius: 0.5,
+                collision_stiffness: 0.5,
+                plane_z: None,
                 phase,
-                mode: SimulationMode::Remote,
+                mode: SimulationMode::SpringElectric,
+                gravity: 1.0,
+                strong_gravity: false,
+                edge_weight_normalization: EdgeWeightNormalization::None,
+                degree_gravity: 0.0,
+                cluster_separation: 0.0,
+                node_types: BTreeMap::new(),
             },
             SimulationPhase::Dynamic => Self {
                 iterations: 50,
@@ -114,8 +190,17 @@ impl SimulationParams {
                 boundary_damping: 0.9,
                 viewport_bounds: 1000.0,
                 enable_bounds: true,
+                collision_radius: 0.5,
+                collision_stiffness: 0.5,
+                plane_z: None,
                 phase,
-                mode: SimulationMode::Remote,
+                mode: SimulationMode::SpringElectric,
+                gravity: 1.0,
+                strong_gravity: false,
+                edge_weight_normalization: EdgeWeightNormalization::None,
+                degree_gravity: 0.0,
+                cluster_separation: 0.0,
+                node_types: BTreeMap::new(),
             },
             SimulationPhase::Finalize => Self {
                 iterations: 200,
@@ -128,12 +213,67 @@ impl SimulationParams {
                 boundary_damping: 0.95,
                 viewport_bounds: 1000.0,
                 enable_bounds: true,
+                collision_radius: 0.5,
+                collision_stiffness: 0.5,
+                plane_z: None,
                 phase,
-                mode: SimulationMode::Remote,
+                mode: SimulationMode::SpringElectric,
+                gravity: 1.0,
+                strong_gravity: false,
+                edge_weight_normalization: EdgeWeightNormalization::None,
+                degree_gravity: 0.0,
+                cluster_separation: 0.0,
+                node_types: BTreeMap::new(),
             },
         }
     }
 
+    /// Multipliers for a node of `node_type`; untyped nodes and unlisted types get 1
+    pub fn node_physics(&self, node_type: Option<&str>) -> NodeTypePhysics {
+        node_type.and_then(|t| self.node_types.get(t)).copied().unwrap_or_default()
+    }
+
+    /// Edge weights as the solvers see them, in the order of `edges`. The heaviest
+    /// weight is taken afresh each time, so it follows the graph as it's rebuilt.
+    pub fn edge_weights(&self, edges: &[Edge]) -> Vec<f32> {
+        let max_weight = edges.iter().map(|e| e.weight).fold(0.0f32, f32::max);
+        edges.iter().map(|e| self.edge_weight_normalization.apply(e.weight, max_weight)).collect()
+    }
+
+    /// Whether the solvers need the graph's edges, beyond the spring-electric model
+    /// on the GPU, which springs every pair of nodes
+    pub fn uses_edges(&self) -> bool {
+        self.mode != SimulationMode::SpringElectric || self.degree_gravity > 0.0
+    }
+
+    /// Holds a node to the layout plane, if there is one
+    pub fn constrain(&self, data: &mut BinaryNodeData) {
+        if let Some(z) = self.plane_z {
+            data.position.z = z;
+            data.velocity.z = 0.0;
+        }
+    }
+
+    /// `collision_radius` scales with the node's size; unsized nodes use it as is
+    pub fn radius_scale(node: &Node) -> f32 {
+        node.size.map_or(1.0, |size| (size / REFERENCE_NODE_SIZE).clamp(1.0 / PACKED_UNITS, 255.0 / PACKED_UNITS))
+    }
+
+    /// A node's data as uploaded to the GPU kernel. The mass byte is scaled by the
+    /// type's mass multiplier and the charge and collision radius multipliers are
+    /// packed into the padding.
+    pub fn gpu_node_data(&self, node: &Node) -> BinaryNodeData {
+        let physics = self.node_physics(node.node_type.as_deref());
+        let mut data = node.data;
+        if physics.mass != 1.0 {
+            // The kernel treats a mass of 0 as mid-range
+            let mass = if data.mass == 0 { 127.0 } else { f32::from(data.mass) };
+            data.mass = (mass * physics.mass).round().clamp(1.0, 255.0) as u8;
+        }
+        data.padding = [pack_multiplier(physics.charge), pack_multiplier(Self::radius_scale(node))];
+        data
+    }
+
     // Convert to GPU-compatible parameters
     pub fn to_gpu_params(&self) -> GPUSimulationParams {
         GPUSimulationParams {
@@ -148,3 +288,50 @@ impl SimulationParams {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gpu_node_data_applies_type_multipliers() {
+        let mut params = SimulationParams::new();
+        params.node_types.insert("tag".to_string(), NodeTypePhysics { mass: 2.0, charge: 4.0 });
+
+        let mut tag = Node::new("tag:rust".to_string()).with_type("tag".to_string());
+        tag.data.mass = 50;
+        let data = params.gpu_node_data(&tag);
+        assert_eq!(data.mass, 100);
+        assert_eq!(data.padding[0], 64);
+
+        let mut page = Node::new("Rust".to_string()).with_size(20.0);
+        page.data.mass = 50;
+        let data = params.gpu_node_data(&page);
+        assert_eq!(data.mass, 50);
+        assert_eq!(data.padding, [0, 64]);
+    }
+
+    #[test]
+    fn test_edge_weights_normalization() {
+        let edges = vec![Edge::new(1, 2, 1.0), Edge::new(2, 3, 4.0)];
+        let mut params = SimulationParams::new();
+        assert_eq!(params.edge_weights(&edges), vec![1.0, 4.0]);
+        params.edge_weight_normalization = EdgeWeightNormalization::Max;
+        assert_eq!(params.edge_weights(&edges), vec![0.25, 1.0]);
+        params.edge_weight_normalization = EdgeWeightNormalization::Log;
+        let weights = params.edge_weights(&edges);
+        assert!((weights[0] - 2.0f32.ln() / 5.0f32.ln()).abs() < 1e-6);
+        assert_eq!(weights[1], 1.0);
+    }
+
+    #[test]
+    fn test_constrain_to_plane() {
+        let mut data = Node::new("Rust".to_string()).with_position(1.0, 2.0, 3.0).with_velocity(0.1, 0.1, 0.1).data;
+        SimulationParams::new().constrain(&mut data);
+        assert_eq!(data.position.z, 3.0);
+
+        let params = SimulationParams { plane_z: Some(-1.0), ..SimulationParams::new() };
+        params.constrain(&mut data);
+        assert_eq!((data.position.x, data.position.z, data.velocity.z), (1.0, -1.0, 0.0));
+    }
+}
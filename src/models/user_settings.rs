@@ -2,12 +2,16 @@ use serde::{Deserialize, Serialize};
 use std::fs;
 use std::path::PathBuf;
 use std::sync::{Arc, RwLock};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
 use std::time::{Duration, Instant};
 use log::{info, error, debug, warn};
 use once_cell::sync::Lazy;
 
+use crate::config::data_dirs::DataDirs;
 use crate::models::UISettings;
+use crate::utils::merge_patch;
 
 // Global cache for user settings
 static USER_SETTINGS_CACHE: Lazy<Arc<RwLock<HashMap<String, CachedUserSettings>>>> = 
@@ -16,17 +20,89 @@ static USER_SETTINGS_CACHE: Lazy<Arc<RwLock<HashMap<String, CachedUserSettings>>
 // Cache expiration time (10 minutes)
 const CACHE_EXPIRATION: Duration = Duration::from_secs(10 * 60);
 
+pub const MAX_BOOKMARKS: usize = 500;
+/// Nodes whose visits are kept; the least recently visited are dropped beyond this
+pub const MAX_VISITED_NODES: usize = 2000;
+// A single visit counts for at most an hour, however long the tab stayed open
+const MAX_DWELL_MS: u64 = 60 * 60 * 1000;
+
 // Cache entry with timestamp
 struct CachedUserSettings {
     settings: UserSettings,
     timestamp: Instant,
 }
 
+/// Class of device a client runs on, reported in the `X-Device-Profile` header
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DeviceProfile {
+    Desktop,
+    Mobile,
+    /// Standalone headsets such as Meta Quest
+    Quest,
+    VisionPro,
+}
+
+impl FromStr for DeviceProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "desktop" => Ok(DeviceProfile::Desktop),
+            "mobile" => Ok(DeviceProfile::Mobile),
+            "quest" => Ok(DeviceProfile::Quest),
+            "vision-pro" | "visionpro" => Ok(DeviceProfile::VisionPro),
+            other => Err(format!("Unknown device profile '{}'", other)),
+        }
+    }
+}
+
+impl fmt::Display for DeviceProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DeviceProfile::Desktop => "desktop",
+            DeviceProfile::Mobile => "mobile",
+            DeviceProfile::Quest => "quest",
+            DeviceProfile::VisionPro => "vision-pro",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A favorite node, by its metadata id
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Bookmark {
+    pub node: String,
+    pub added_at: i64,
+}
+
+/// How often and how long a user has looked at a node
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NodeVisits {
+    pub count: u64,
+    pub dwell_ms: u64,
+    pub last_visited: i64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserSettings {
     pub pubkey: String,
     pub settings: UISettings,
     pub last_modified: i64,
+    /// Merge patches applied on top of the user's settings for each device class
+    #[serde(default)]
+    pub device_overrides: BTreeMap<DeviceProfile, serde_json::Value>,
+    /// Favorite nodes in the order they were added
+    #[serde(default)]
+    pub bookmarks: Vec<Bookmark>,
+    /// Whether the user opted in to having their visits recorded
+    #[serde(default)]
+    pub visit_tracking: bool,
+    /// Visits by node metadata id, only while `visit_tracking` is on
+    #[serde(default)]
+    pub visits: BTreeMap<String, NodeVisits>,
 }
 
 impl UserSettings {
@@ -35,6 +111,75 @@ impl UserSettings {
             pubkey: pubkey.to_string(),
             settings,
             last_modified: chrono::Utc::now().timestamp(),
+            device_overrides: BTreeMap::new(),
+            bookmarks: Vec::new(),
+            visit_tracking: false,
+            visits: BTreeMap::new(),
+        }
+    }
+
+    pub fn is_bookmarked(&self, node: &str) -> bool {
+        self.bookmarks.iter().any(|bookmark| bookmark.node == node)
+    }
+
+    /// Bookmarks `node`; returns false if it already was. Fails once the user has
+    /// `MAX_BOOKMARKS` bookmarks.
+    pub fn add_bookmark(&mut self, node: &str) -> Result<bool, String> {
+        if self.is_bookmarked(node) {
+            return Ok(false);
+        }
+        if self.bookmarks.len() >= MAX_BOOKMARKS {
+            return Err(format!("At most {} nodes can be bookmarked", MAX_BOOKMARKS));
+        }
+        self.bookmarks.push(Bookmark { node: node.to_string(), added_at: chrono::Utc::now().timestamp() });
+        Ok(true)
+    }
+
+    /// Removes the bookmark on `node`; returns false if there was none
+    pub fn remove_bookmark(&mut self, node: &str) -> bool {
+        let before = self.bookmarks.len();
+        self.bookmarks.retain(|bookmark| bookmark.node != node);
+        self.bookmarks.len() != before
+    }
+
+    /// Turns visit tracking on or off. Turning it off forgets the recorded visits.
+    pub fn set_visit_tracking(&mut self, enabled: bool) {
+        self.visit_tracking = enabled;
+        if !enabled {
+            self.visits.clear();
+        }
+    }
+
+    /// Records a visit of `node` lasting `dwell_ms`; returns false, recording
+    /// nothing, unless the user opted in
+    pub fn record_visit(&mut self, node: &str, dwell_ms: u64, at: i64) -> bool {
+        if !self.visit_tracking {
+            return false;
+        }
+        let visits = self.visits.entry(node.to_string()).or_default();
+        visits.count += 1;
+        visits.dwell_ms = visits.dwell_ms.saturating_add(dwell_ms.min(MAX_DWELL_MS));
+        visits.last_visited = visits.last_visited.max(at);
+        if self.visits.len() > MAX_VISITED_NODES {
+            if let Some(stalest) = self.visits.iter().min_by_key(|(_, visits)| visits.last_visited).map(|(node, _)| node.clone()) {
+                self.visits.remove(&stalest);
+            }
+        }
+        true
+    }
+
+    /// Resolves `settings` for `device` by applying that device's override. An override
+    /// that no longer fits the settings structure is logged and skipped.
+    pub fn apply_device_override(&self, settings: UISettings, device: DeviceProfile) -> UISettings {
+        let Some(patch) = self.device_overrides.get(&device) else {
+            return settings;
+        };
+        match apply_override(&settings, patch) {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                warn!("Ignoring {} override for user {}: {}", device, self.pubkey, e);
+                settings
+            }
         }
     }
 
@@ -128,7 +273,7 @@ impl UserSettings {
     }
 
     fn get_settings_path(pubkey: &str) -> PathBuf {
-        PathBuf::from("/app/user_settings").join(format!("{}.yaml", pubkey))
+        DataDirs::global().user_settings.join(format!("{}.yaml", pubkey))
     }
     
     // Clear the cache entry for a specific user
@@ -146,4 +291,80 @@ impl UserSettings {
         cache.clear();
         debug!("Cleared all cached settings ({} entries)", count);
     }
-}
\ No newline at end of file
+}
+
+/// Applies a device override patch to `settings`
+pub fn apply_override(settings: &UISettings, patch: &serde_json::Value) -> Result<UISettings, String> {
+    let mut value = serde_json::to_value(settings)
+        .map_err(|e| format!("Failed to serialize settings: {}", e))?;
+    merge_patch::apply(&mut value, patch)?;
+    serde_json::from_value(value).map_err(|e| format!("Invalid override: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_device_override_applies_only_to_its_device() {
+        let mut user = UserSettings::new("pubkey", UISettings::default());
+        user.device_overrides.insert(
+            DeviceProfile::Quest,
+            json!({"visualisation": {"bloom": {"enabled": false, "strength": 0.1}}}),
+        );
+        user.settings.visualisation.bloom.enabled = true;
+        user.settings.visualisation.bloom.strength = 1.5;
+
+        let quest = user.apply_device_override(user.settings.clone(), DeviceProfile::Quest);
+        assert!(!quest.visualisation.bloom.enabled);
+        assert_eq!(quest.visualisation.bloom.strength, 0.1);
+
+        let desktop = user.apply_device_override(user.settings.clone(), DeviceProfile::Desktop);
+        assert!(desktop.visualisation.bloom.enabled);
+        assert_eq!(desktop.visualisation.bloom.strength, 1.5);
+    }
+
+    #[test]
+    fn test_bookmarks() {
+        let mut user = UserSettings::new("pubkey", UISettings::default());
+        assert_eq!(user.add_bookmark("Graph Theory"), Ok(true));
+        assert_eq!(user.add_bookmark("Graph Theory"), Ok(false));
+        assert!(user.is_bookmarked("Graph Theory"));
+        assert!(user.remove_bookmark("Graph Theory"));
+        assert!(!user.remove_bookmark("Graph Theory"));
+
+        for i in 0..MAX_BOOKMARKS {
+            user.add_bookmark(&format!("Page {}", i)).unwrap();
+        }
+        assert!(user.add_bookmark("One too many").is_err());
+    }
+
+    #[test]
+    fn test_visits() {
+        let mut user = UserSettings::new("pubkey", UISettings::default());
+        assert!(!user.record_visit("Graph Theory", 1000, 10));
+        assert!(user.visits.is_empty());
+
+        user.set_visit_tracking(true);
+        assert!(user.record_visit("Graph Theory", 1000, 10));
+        assert!(user.record_visit("Graph Theory", u64::MAX, 5));
+        assert_eq!(user.visits["Graph Theory"], NodeVisits { count: 2, dwell_ms: 1000 + MAX_DWELL_MS, last_visited: 10 });
+
+        for i in 0..MAX_VISITED_NODES {
+            user.record_visit(&format!("Page {}", i), 0, 20 + i as i64);
+        }
+        assert_eq!(user.visits.len(), MAX_VISITED_NODES);
+        assert!(!user.visits.contains_key("Graph Theory"));
+
+        user.set_visit_tracking(false);
+        assert!(user.visits.is_empty());
+    }
+
+    #[test]
+    fn test_device_profile_parsing() {
+        assert_eq!("Vision-Pro".parse::<DeviceProfile>(), Ok(DeviceProfile::VisionPro));
+        assert_eq!(DeviceProfile::VisionPro.to_string(), "vision-pro");
+        assert!("toaster".parse::<DeviceProfile>().is_err());
+    }
+}
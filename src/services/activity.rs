@@ -0,0 +1,267 @@
+//! Activity feed of vault and graph changes
+//!
+//! A running log of what happened to the vault: pages added, updated or removed by
+//! a sync, pull requests merged, users signing in. Entries are appended to
+//! `/app/data/metadata/activity.jsonl` and the newest `ACTIVITY_LOG_LIMIT` (default
+//! 5000) are kept for `/api/activity`. Each new entry is also published on the
+//! event bus, so WebSocket clients get it as a `serverEvent` as it happens.
+
+use chrono::{DateTime, Utc};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::config::data_dirs::DataDirs;
+use crate::services::event_bus::{AppEvent, EventBus, FileEvent};
+
+fn activity_path() -> PathBuf {
+    DataDirs::global().metadata_file("activity.jsonl")
+}
+
+const DEFAULT_LIMIT: usize = 5000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ActivityKind {
+    FileUpdated,
+    NodeAdded,
+    NodeRemoved,
+    PullRequestMerged,
+    UserJoined,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActivityEntry {
+    /// Increases with every entry, so clients can ask for what came after one
+    pub id: u64,
+    pub at: DateTime<Utc>,
+    pub kind: ActivityKind,
+    /// What the entry is about, e.g. a file name or `#12` for a pull request
+    pub subject: String,
+    /// Pubkey of the user behind the change, when there is one
+    pub actor: Option<String>,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub detail: Value,
+}
+
+/// Where to start reading the feed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ActivityCursor {
+    AfterId(u64),
+    After(DateTime<Utc>),
+}
+
+impl ActivityCursor {
+    /// Accepts an entry id or an RFC 3339 timestamp
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let value = value.trim();
+        if let Ok(id) = value.parse::<u64>() {
+            return Ok(ActivityCursor::AfterId(id));
+        }
+        DateTime::parse_from_rfc3339(value)
+            .map(|at| ActivityCursor::After(at.with_timezone(&Utc)))
+            .map_err(|_| format!("'{}' is neither an entry id nor an RFC 3339 timestamp", value))
+    }
+
+    fn includes(&self, entry: &ActivityEntry) -> bool {
+        match self {
+            ActivityCursor::AfterId(id) => entry.id > *id,
+            ActivityCursor::After(at) => entry.at > *at,
+        }
+    }
+}
+
+struct ActivityState {
+    entries: VecDeque<ActivityEntry>,
+    next_id: u64,
+    /// Lines in the log file, which may hold more than `entries` until compacted
+    persisted: usize,
+}
+
+pub struct ActivityLog {
+    state: Mutex<ActivityState>,
+    event_bus: EventBus,
+    limit: usize,
+}
+
+impl ActivityLog {
+    pub fn new(event_bus: EventBus) -> Self {
+        let limit = std::env::var("ACTIVITY_LOG_LIMIT").ok()
+            .and_then(|v| v.trim().parse::<usize>().ok())
+            .filter(|&limit| limit > 0)
+            .unwrap_or(DEFAULT_LIMIT);
+        let (entries, persisted) = Self::load(limit).unwrap_or_else(|e| {
+            debug!("[Activity] No stored activity loaded: {}", e);
+            (VecDeque::new(), 0)
+        });
+        let next_id = entries.back().map_or(1, |entry| entry.id + 1);
+        info!("[Activity] Loaded {} activity entries", entries.len());
+        Self {
+            state: Mutex::new(ActivityState { entries, next_id, persisted }),
+            event_bus,
+            limit,
+        }
+    }
+
+    /// Turns sync reports from the event bus into entries
+    pub fn start(self: &Arc<Self>, event_bus: &EventBus) {
+        let log = self.clone();
+        let mut events = event_bus.subscribe();
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(AppEvent::File(FileEvent::SyncReport { file_names, added_files, removed_files, error: None, .. })) => {
+                        for file_name in &added_files {
+                            log.record(ActivityKind::NodeAdded, file_name, None, Value::Null);
+                        }
+                        for file_name in file_names.iter().filter(|name| !added_files.contains(name)) {
+                            log.record(ActivityKind::FileUpdated, file_name, None, Value::Null);
+                        }
+                        for file_name in &removed_files {
+                            log.record(ActivityKind::NodeRemoved, file_name, None, Value::Null);
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("[Activity] Lagged behind event bus, skipped {} events", skipped);
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    /// Adds an entry, persists it and publishes it on the event bus
+    pub fn record(&self, kind: ActivityKind, subject: &str, actor: Option<String>, detail: Value) -> ActivityEntry {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let entry = ActivityEntry {
+            id: state.next_id,
+            at: Utc::now(),
+            kind,
+            subject: subject.to_string(),
+            actor,
+            detail,
+        };
+        state.next_id += 1;
+        state.entries.push_back(entry.clone());
+        while state.entries.len() > self.limit {
+            state.entries.pop_front();
+        }
+        if let Err(e) = self.persist(&mut state, &entry) {
+            warn!("[Activity] Failed to persist activity entry: {}", e);
+        }
+        drop(state);
+
+        self.event_bus.publish(entry.clone());
+        entry
+    }
+
+    /// Entries after `cursor`, oldest first, or the latest ones without a cursor.
+    /// At most `limit` entries are returned, the earliest when a cursor is given.
+    pub fn query(&self, cursor: Option<ActivityCursor>, kinds: &[ActivityKind], limit: usize) -> Vec<ActivityEntry> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        select(&state.entries, cursor, kinds, limit)
+    }
+
+    /// Id of the newest entry, 0 when the log is empty
+    pub fn latest_id(&self) -> u64 {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.next_id - 1
+    }
+
+    fn persist(&self, state: &mut ActivityState, entry: &ActivityEntry) -> io::Result<()> {
+        if let Some(parent) = activity_path().parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // Rewrite the file once it holds twice what is kept, so it stays bounded
+        if state.persisted >= self.limit * 2 {
+            let mut content = String::new();
+            for kept in &state.entries {
+                content.push_str(&serde_json::to_string(kept)?);
+                content.push('\n');
+            }
+            fs::write(activity_path(), content)?;
+            state.persisted = state.entries.len();
+            return Ok(());
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(activity_path())?;
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+        state.persisted += 1;
+        Ok(())
+    }
+
+    fn load(limit: usize) -> io::Result<(VecDeque<ActivityEntry>, usize)> {
+        let content = fs::read_to_string(activity_path())?;
+        let mut entries = VecDeque::new();
+        let mut lines = 0;
+        for line in content.lines().filter(|line| !line.trim().is_empty()) {
+            lines += 1;
+            match serde_json::from_str::<ActivityEntry>(line) {
+                Ok(entry) => {
+                    entries.push_back(entry);
+                    if entries.len() > limit {
+                        entries.pop_front();
+                    }
+                }
+                Err(e) => warn!("[Activity] Skipping unreadable activity entry: {}", e),
+            }
+        }
+        Ok((entries, lines))
+    }
+}
+
+fn select(
+    entries: &VecDeque<ActivityEntry>,
+    cursor: Option<ActivityCursor>,
+    kinds: &[ActivityKind],
+    limit: usize,
+) -> Vec<ActivityEntry> {
+    let matching = entries.iter()
+        .filter(|entry| cursor.is_none_or(|cursor| cursor.includes(entry)))
+        .filter(|entry| kinds.is_empty() || kinds.contains(&entry.kind));
+    match cursor {
+        Some(_) => matching.take(limit).cloned().collect(),
+        None => {
+            let mut latest: Vec<ActivityEntry> = matching.rev().take(limit).cloned().collect();
+            latest.reverse();
+            latest
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_select() {
+        let start = Utc::now();
+        let entries: VecDeque<ActivityEntry> = (1..=5)
+            .map(|id| ActivityEntry {
+                id,
+                at: start + Duration::seconds(id as i64),
+                kind: if id % 2 == 1 { ActivityKind::FileUpdated } else { ActivityKind::NodeAdded },
+                subject: format!("Page {}.md", id),
+                actor: None,
+                detail: Value::Null,
+            })
+            .collect();
+        let ids = |selected: Vec<ActivityEntry>| selected.iter().map(|e| e.id).collect::<Vec<_>>();
+
+        assert_eq!(ids(select(&entries, None, &[], 2)), vec![4, 5]);
+        assert_eq!(ids(select(&entries, Some(ActivityCursor::AfterId(2)), &[], 2)), vec![3, 4]);
+        assert_eq!(ids(select(&entries, Some(ActivityCursor::After(start + Duration::seconds(3))), &[], 10)), vec![4, 5]);
+        assert_eq!(ids(select(&entries, None, &[ActivityKind::NodeAdded], 10)), vec![2, 4]);
+
+        assert_eq!(ActivityCursor::parse("17"), Ok(ActivityCursor::AfterId(17)));
+        assert!(matches!(ActivityCursor::parse("2024-03-01T10:00:00Z"), Ok(ActivityCursor::After(_))));
+        assert!(ActivityCursor::parse("yesterday").is_err());
+    }
+}
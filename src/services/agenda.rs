@@ -0,0 +1,206 @@
+//! Tasks and deadlines across the vault
+//!
+//! Task blocks (`- TODO ...`) and their `SCHEDULED:` and `DEADLINE:` dates are
+//! parsed into each page's metadata when it is synced. This gathers them into a
+//! task list and an iCalendar feed calendar apps can subscribe to. A task on a
+//! journal page without dates of its own falls on the journal's day.
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+
+use crate::models::metadata::{MetadataStore, Task, TaskDate};
+use crate::services::pages::page_title;
+use crate::services::timeline::journal_date;
+
+pub const CALENDAR_NAME: &str = "Logseq agenda";
+// RFC 5545 lines are folded at 75 octets
+const MAX_LINE_OCTETS: usize = 75;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskStatus {
+    #[default]
+    Open,
+    Done,
+    All,
+}
+
+impl TaskStatus {
+    fn includes(self, task: &Task) -> bool {
+        match self {
+            TaskStatus::Open => !task.is_done(),
+            TaskStatus::Done => task.is_done(),
+            TaskStatus::All => true,
+        }
+    }
+}
+
+/// Which tasks to list
+#[derive(Debug, Clone, Default)]
+pub struct TaskFilter {
+    pub status: TaskStatus,
+    /// Markers to keep, such as `TODO` and `DOING`; any when empty
+    pub states: Vec<String>,
+    /// Keep only tasks with this tag in their text or on their page
+    pub tag: Option<String>,
+}
+
+impl TaskFilter {
+    fn matches(&self, task: &Task, page_tags: &[String]) -> bool {
+        let tagged = |tag: &str| {
+            let tag = tag.trim_start_matches('#');
+            task.tags.iter().chain(page_tags).any(|other| other.eq_ignore_ascii_case(tag))
+        };
+        self.status.includes(task)
+            && (self.states.is_empty() || self.states.iter().any(|state| state.eq_ignore_ascii_case(&task.marker)))
+            && self.tag.as_deref().is_none_or(tagged)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgendaItem {
+    /// Page name, the file name without `.md`
+    pub page: String,
+    pub page_title: String,
+    #[serde(flatten)]
+    pub task: Task,
+    /// Day of the journal page the task is on, if it is on one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub journal_date: Option<NaiveDate>,
+}
+
+impl AgendaItem {
+    /// The day the task is due: its deadline, else its scheduled day, else its journal's day
+    pub fn due(&self) -> Option<NaiveDate> {
+        self.task.deadline.or(self.task.scheduled).map(|date| date.date).or(self.journal_date)
+    }
+}
+
+/// Tasks passing `filter` in every page, by due day with undated tasks last
+pub fn collect(metadata: &MetadataStore, filter: &TaskFilter) -> Vec<AgendaItem> {
+    let mut items: Vec<AgendaItem> = metadata.iter()
+        .flat_map(|(file_name, page)| {
+            let name = file_name.trim_end_matches(".md");
+            page.tasks.iter()
+                .filter(move |task| filter.matches(task, &page.tags))
+                .map(move |task| AgendaItem {
+                    page: name.to_string(),
+                    page_title: page_title(file_name),
+                    task: task.clone(),
+                    journal_date: journal_date(name),
+                })
+        })
+        .collect();
+    items.sort_by(|a, b| {
+        (a.due().is_none(), a.due(), &a.page_title, a.task.line)
+            .cmp(&(b.due().is_none(), b.due(), &b.page_title, b.task.line))
+    });
+    items
+}
+
+fn escape_text(value: &str) -> String {
+    value.replace('\\', "\\\\").replace(';', "\\;").replace(',', "\\,").replace('\n', "\\n")
+}
+
+/// Appends `line` folded into 75-octet lines, each ending in CRLF
+fn push_line(ics: &mut String, line: &str) {
+    let mut octets = 0;
+    for c in line.chars() {
+        if octets + c.len_utf8() > MAX_LINE_OCTETS {
+            ics.push_str("\r\n ");
+            octets = 1;
+        }
+        ics.push(c);
+        octets += c.len_utf8();
+    }
+    ics.push_str("\r\n");
+}
+
+fn push_event(ics: &mut String, item: &AgendaItem, kind: &str, date: TaskDate, summary: String, stamp: &str) {
+    let uid = format!("{:x}", Sha1::digest(format!("{}:{}:{}", item.page, item.task.line, kind).as_bytes()));
+    push_line(ics, "BEGIN:VEVENT");
+    push_line(ics, &format!("UID:{}@agenda", uid));
+    push_line(ics, &format!("DTSTAMP:{}", stamp));
+    match date.time {
+        // Floating time, read in the calendar's own time zone as Logseq does
+        Some(time) => push_line(ics, &format!("DTSTART:{}", date.date.and_time(time).format("%Y%m%dT%H%M%S"))),
+        None => push_line(ics, &format!("DTSTART;VALUE=DATE:{}", date.date.format("%Y%m%d"))),
+    }
+    push_line(ics, &format!("SUMMARY:{}", escape_text(&summary)));
+    push_line(ics, &format!("DESCRIPTION:{}", escape_text(&format!("{} in {}", item.task.marker, item.page_title))));
+    push_line(ics, &format!("CATEGORIES:{}", item.task.marker));
+    if let Some(priority) = &item.task.priority {
+        // iCalendar ranks 1 (highest) to 9
+        let rank = match priority.as_str() { "A" => 1, "B" => 5, _ => 9 };
+        push_line(ics, &format!("PRIORITY:{}", rank));
+    }
+    if matches!(item.task.marker.as_str(), "CANCELED" | "CANCELLED") {
+        push_line(ics, "STATUS:CANCELLED");
+    }
+    push_line(ics, "END:VEVENT");
+}
+
+/// An iCalendar document with an event for each scheduled day and deadline of
+/// `items`. Journal tasks without dates get one on their journal's day; other
+/// undated tasks are left out.
+pub fn to_ics(items: &[AgendaItem], now: DateTime<Utc>) -> String {
+    let stamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let mut ics = String::new();
+    push_line(&mut ics, "BEGIN:VCALENDAR");
+    push_line(&mut ics, "VERSION:2.0");
+    push_line(&mut ics, "PRODID:-//logseqSpringThing//Agenda//EN");
+    push_line(&mut ics, "CALSCALE:GREGORIAN");
+    push_line(&mut ics, &format!("X-WR-CALNAME:{}", CALENDAR_NAME));
+    for item in items {
+        let task = &item.task;
+        if let Some(scheduled) = task.scheduled {
+            push_event(&mut ics, item, "scheduled", scheduled, task.text.clone(), &stamp);
+        }
+        if let Some(deadline) = task.deadline {
+            push_event(&mut ics, item, "deadline", deadline, format!("Deadline: {}", task.text), &stamp);
+        }
+        if let (None, None, Some(date)) = (task.scheduled, task.deadline, item.journal_date) {
+            push_event(&mut ics, item, "journal", TaskDate { date, time: None }, task.text.clone(), &stamp);
+        }
+    }
+    push_line(&mut ics, "END:VCALENDAR");
+    ics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::metadata::Metadata;
+    use webxr_core::page::page_tasks;
+
+    #[test]
+    fn test_agenda() {
+        let mut metadata = MetadataStore::new();
+        for (name, content) in [
+            ("2024_01_08.md", "- TODO Call Sam, about the talk\n- DONE Book room"),
+            ("Projects.md", "- TODO [#A] Ship release\n  DEADLINE: <2024-01-12 Fri 10:00>\n- LATER Someday #ideas"),
+        ] {
+            let tags = if name == "Projects.md" { vec!["work".to_string()] } else { Vec::new() };
+            metadata.insert(name.to_string(), Metadata { tasks: page_tasks(content), tags, ..Default::default() });
+        }
+
+        let open = collect(&metadata, &TaskFilter::default());
+        let order: Vec<&str> = open.iter().map(|item| item.task.text.as_str()).collect();
+        assert_eq!(order, vec!["Call Sam, about the talk", "Ship release", "Someday #ideas"]);
+        assert_eq!(collect(&metadata, &TaskFilter { status: TaskStatus::Done, ..Default::default() }).len(), 1);
+        let later = TaskFilter { states: vec!["later".to_string()], ..Default::default() };
+        assert_eq!(collect(&metadata, &later).len(), 1);
+        // Page tags count as well as tags in the task
+        assert_eq!(collect(&metadata, &TaskFilter { tag: Some("#Work".to_string()), ..Default::default() }).len(), 2);
+        assert_eq!(collect(&metadata, &TaskFilter { tag: Some("ideas".to_string()), ..Default::default() }).len(), 1);
+
+        let ics = to_ics(&open, Utc::now());
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 2);
+        assert!(ics.contains("DTSTART;VALUE=DATE:20240108\r\nSUMMARY:Call Sam\\, about the talk\r\n"));
+        assert!(ics.contains("DTSTART:20240112T100000\r\nSUMMARY:Deadline: Ship release\r\n"));
+        assert!(ics.contains("PRIORITY:1"));
+        assert!(ics.lines().all(|line| line.len() <= MAX_LINE_OCTETS));
+    }
+}
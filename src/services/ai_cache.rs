@@ -0,0 +1,233 @@
+//! On-disk cache for AI service responses
+//!
+//! Perplexity and RAGFlow answers are stored under a SHA-256 of provider, model and
+//! prompt, so re-running enrichment over unchanged notes or asking the same
+//! question again doesn't spend API credits. Entries expire after `ttl_hours`; the
+//! total size is bounded and the least recently used entries are evicted first.
+//! OpenAI is only used for speech, which `AudioCache` already caches.
+//!
+//! Callers can skip the cache per request with `Cache-Control: no-cache` (ask the
+//! provider, then store the fresh answer) or `no-store` (neither read nor write).
+
+use crate::config::data_dirs::DataDirs;
+use crate::config::AiCacheSettings;
+use actix_web::http::header::{HeaderMap, CACHE_CONTROL};
+use chrono::{DateTime, Duration, Utc};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+const DEFAULT_MAX_SIZE_MB: u64 = 64;
+const DEFAULT_TTL_HOURS: u64 = 24 * 30;
+const CACHE_FILE_EXTENSION: &str = "json";
+
+/// How one request uses the cache
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum CacheMode {
+    #[default]
+    Use,
+    /// Ask the provider and store its answer
+    Refresh,
+    /// Leave the cache alone
+    Bypass,
+}
+
+impl CacheMode {
+    /// From the request's `Cache-Control` header
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let directives: Vec<String> = headers.get_all(CACHE_CONTROL)
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .map(|directive| directive.trim().to_ascii_lowercase())
+            .collect();
+        if directives.iter().any(|d| d == "no-store") {
+            CacheMode::Bypass
+        } else if directives.iter().any(|d| d == "no-cache") {
+            CacheMode::Refresh
+        } else {
+            CacheMode::Use
+        }
+    }
+
+    fn reads(self) -> bool {
+        self == CacheMode::Use
+    }
+
+    fn writes(self) -> bool {
+        self != CacheMode::Bypass
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Entry {
+    provider: String,
+    model: String,
+    created_at: DateTime<Utc>,
+    response: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct AiCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    ttl: Duration,
+}
+
+impl AiCache {
+    /// Returns `None` when caching is disabled or not configured
+    pub fn from_settings(settings: Option<&AiCacheSettings>) -> Option<Self> {
+        let settings = settings?;
+        if !settings.enabled {
+            return None;
+        }
+        Some(Self::new(
+            settings.directory.as_ref().map(PathBuf::from).unwrap_or_else(|| DataDirs::global().ai_cache()),
+            settings.max_size_mb.unwrap_or(DEFAULT_MAX_SIZE_MB) * 1024 * 1024,
+            Duration::hours(settings.ttl_hours.unwrap_or(DEFAULT_TTL_HOURS) as i64),
+        ))
+    }
+
+    pub fn new(dir: PathBuf, max_bytes: u64, ttl: Duration) -> Self {
+        Self { dir, max_bytes, ttl }
+    }
+
+    pub fn key(provider: &str, model: &str, prompt: &str) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(provider.as_bytes());
+        hasher.update([0]);
+        hasher.update(model.as_bytes());
+        hasher.update([0]);
+        hasher.update(prompt.as_bytes());
+        hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn path_for(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", key, CACHE_FILE_EXTENSION))
+    }
+
+    /// The stored response, unless it expired or `mode` skips reading
+    pub fn get(&self, key: &str, mode: CacheMode) -> Option<String> {
+        if !mode.reads() {
+            return None;
+        }
+        let path = self.path_for(key);
+        let entry: Entry = serde_json::from_slice(&fs::read(&path).ok()?).ok()?;
+        if Utc::now() - entry.created_at > self.ttl {
+            debug!("[AiCache] Expired {} for {}", key, entry.provider);
+            let _ = fs::remove_file(&path);
+            return None;
+        }
+        // Bump mtime so recently used entries survive eviction
+        if let Err(e) = fs::File::options().write(true).open(&path).and_then(|f| f.set_modified(SystemTime::now())) {
+            debug!("[AiCache] Failed to touch {:?}: {}", path, e);
+        }
+        debug!("[AiCache] Hit for {} ({} {})", key, entry.provider, entry.model);
+        Some(entry.response)
+    }
+
+    pub fn put(&self, key: &str, provider: &str, model: &str, response: &str, mode: CacheMode) -> io::Result<()> {
+        if !mode.writes() || response.is_empty() {
+            return Ok(());
+        }
+        let entry = Entry {
+            provider: provider.to_string(),
+            model: model.to_string(),
+            created_at: Utc::now(),
+            response: response.to_string(),
+        };
+        let data = serde_json::to_vec(&entry)?;
+        if data.len() as u64 > self.max_bytes {
+            return Ok(());
+        }
+        fs::create_dir_all(&self.dir)?;
+        // Write to a temp file first so a concurrent reader never sees a partial entry
+        let tmp = self.dir.join(format!("{}.tmp", key));
+        fs::write(&tmp, data)?;
+        fs::rename(&tmp, self.path_for(key))?;
+        self.evict()
+    }
+
+    /// Removes entries, least recently used first, until the cache fits in `max_bytes`
+    pub fn evict(&self) -> io::Result<()> {
+        let mut entries = Vec::new();
+        let mut total: u64 = 0;
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(CACHE_FILE_EXTENSION) {
+                continue;
+            }
+            let meta = entry.metadata()?;
+            total += meta.len();
+            entries.push((meta.modified().unwrap_or(SystemTime::UNIX_EPOCH), meta.len(), path));
+        }
+
+        if total <= self.max_bytes {
+            return Ok(());
+        }
+
+        entries.sort_by_key(|(stamp, _, _)| *stamp);
+        for (_, len, path) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            match fs::remove_file(&path) {
+                Ok(()) => {
+                    total = total.saturating_sub(len);
+                    debug!("[AiCache] Evicted {:?}", path);
+                }
+                Err(e) => warn!("[AiCache] Failed to evict {:?}: {}", path, e),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::HeaderValue;
+
+    #[test]
+    fn test_ai_cache() {
+        let dir = std::env::temp_dir().join(format!("ai_cache_test_{}", uuid::Uuid::new_v4()));
+        let cache = AiCache::new(dir, 400, Duration::hours(1));
+        let key = AiCache::key("perplexity", "sonar", "What is a graph?");
+        assert_ne!(key, AiCache::key("perplexity", "sonar-pro", "What is a graph?"));
+        assert_ne!(key, AiCache::key("ragflow", "sonar", "What is a graph?"));
+
+        cache.put(&key, "perplexity", "sonar", "Nodes and edges", CacheMode::Use).unwrap();
+        assert_eq!(cache.get(&key, CacheMode::Use).as_deref(), Some("Nodes and edges"));
+        assert_eq!(cache.get(&key, CacheMode::Refresh), None);
+        cache.put(&key, "perplexity", "sonar", "Not stored", CacheMode::Bypass).unwrap();
+        assert_eq!(cache.get(&key, CacheMode::Use).as_deref(), Some("Nodes and edges"));
+
+        // Make sure the second entry is strictly newer on coarse-mtime filesystems
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        let other = AiCache::key("perplexity", "sonar", "What is a tree?");
+        cache.put(&other, "perplexity", "sonar", &"x".repeat(200), CacheMode::Use).unwrap();
+        assert_eq!(cache.get(&key, CacheMode::Use), None);
+        assert!(cache.get(&other, CacheMode::Use).is_some());
+
+        let expired = AiCache::new(cache.dir().to_path_buf(), 400, Duration::zero() - Duration::seconds(1));
+        assert_eq!(expired.get(&other, CacheMode::Use), None);
+        assert!(cache.get(&other, CacheMode::Use).is_none());
+        let _ = fs::remove_dir_all(cache.dir());
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(CacheMode::from_headers(&headers), CacheMode::Use);
+        headers.insert(CACHE_CONTROL, HeaderValue::from_static("max-age=0, No-Cache"));
+        assert_eq!(CacheMode::from_headers(&headers), CacheMode::Refresh);
+        headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+        assert_eq!(CacheMode::from_headers(&headers), CacheMode::Bypass);
+    }
+}
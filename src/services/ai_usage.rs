@@ -0,0 +1,338 @@
+//! Token usage, cost and daily budgets for the AI providers
+//!
+//! Every request that reaches Perplexity, RAGFlow or OpenAI (AI cache hits don't)
+//! is recorded against its UTC day and provider in `ai_usage.json`: the request
+//! count, input and output tokens, and the cost at the configured prices.
+//! Perplexity reports its token counts; for RAGFlow and speech they are estimated
+//! from the text.
+//!
+//! With `ai_budget` limits set, [`check`] refuses new requests once the day's spend
+//! reaches the overall or the provider's limit, until midnight UTC. Chat endpoints
+//! answer 429 and enrichment jobs pause; the notes a paused job didn't reach are
+//! picked up by the next run.
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+use crate::config::data_dirs::DataDirs;
+use crate::config::{AiBudgetSettings, AiPriceSettings};
+use crate::services::resilience;
+
+const RETAINED_DAYS: i64 = 90;
+pub const CHARS_PER_TOKEN: usize = 4;
+
+static TRACKER: OnceLock<Mutex<UsageTracker>> = OnceLock::new();
+
+fn ai_usage_path() -> PathBuf {
+    DataDirs::global().metadata_file("ai_usage.json")
+}
+
+/// USD per million tokens when `ai_budget.prices` doesn't say otherwise. OpenAI
+/// speech is billed per character; RAGFlow is self-hosted, so its cost depends on
+/// the models it is set up with.
+fn default_price(provider: &str) -> AiPriceSettings {
+    match provider {
+        resilience::PERPLEXITY => AiPriceSettings { input_per_million: 1.0, output_per_million: 1.0 },
+        resilience::OPENAI => AiPriceSettings { input_per_million: 15.0, output_per_million: 0.0 },
+        _ => AiPriceSettings::default(),
+    }
+}
+
+/// Rough token count for providers that don't report one
+pub fn estimate_tokens(text: &str) -> u64 {
+    text.chars().count().div_ceil(CHARS_PER_TOKEN) as u64
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderUsage {
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost_usd: f64,
+}
+
+/// Usage per UTC day and provider
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct UsageLedger {
+    pub days: BTreeMap<NaiveDate, BTreeMap<String, ProviderUsage>>,
+}
+
+impl UsageLedger {
+    pub fn load() -> Self {
+        match fs::read_to_string(ai_usage_path()) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable AI usage ledger: {}", e);
+                UsageLedger::default()
+            }),
+            Err(_) => UsageLedger::default(),
+        }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = ai_usage_path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_string(self).map_err(io::Error::from)?;
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, path)
+    }
+
+    /// Spend on `date`, for one provider or all of them
+    pub fn spent(&self, date: NaiveDate, provider: Option<&str>) -> f64 {
+        self.days.get(&date).map_or(0.0, |providers| {
+            providers.iter()
+                .filter(|(name, _)| provider.is_none_or(|p| p == name.as_str()))
+                .map(|(_, usage)| usage.cost_usd)
+                .sum()
+        })
+    }
+}
+
+/// A request refused because a daily limit is spent
+#[derive(Debug, Clone, PartialEq)]
+pub struct BudgetExceeded {
+    /// The provider whose limit is spent, or `None` for the overall limit
+    pub provider: Option<String>,
+    pub spent_usd: f64,
+    pub limit_usd: f64,
+    /// Seconds until the budget resets at midnight UTC
+    pub retry_after: u64,
+}
+
+impl fmt::Display for BudgetExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Daily AI budget reached for {}: ${:.2} of ${:.2} spent; resets in {}s",
+            self.provider.as_deref().unwrap_or("all providers"),
+            self.spent_usd,
+            self.limit_usd,
+            self.retry_after
+        )
+    }
+}
+
+impl std::error::Error for BudgetExceeded {}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProviderBudget {
+    pub provider: String,
+    pub today: ProviderUsage,
+    pub limit_usd: Option<f64>,
+    pub remaining_usd: Option<f64>,
+    /// New requests are refused until tomorrow
+    pub paused: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DayUsage {
+    pub date: NaiveDate,
+    pub cost_usd: f64,
+    pub providers: BTreeMap<String, ProviderUsage>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageReport {
+    pub date: NaiveDate,
+    pub spent_usd: f64,
+    pub daily_limit_usd: Option<f64>,
+    pub providers: Vec<ProviderBudget>,
+    /// Oldest first
+    pub days: Vec<DayUsage>,
+}
+
+/// The ledger together with the limits and prices it is checked against
+#[derive(Debug, Default)]
+pub struct UsageTracker {
+    budget: AiBudgetSettings,
+    ledger: UsageLedger,
+}
+
+impl UsageTracker {
+    pub fn new(budget: AiBudgetSettings, ledger: UsageLedger) -> Self {
+        Self { budget, ledger }
+    }
+
+    fn price(&self, provider: &str) -> AiPriceSettings {
+        self.budget.prices.get(provider).copied().unwrap_or_else(|| default_price(provider))
+    }
+
+    fn provider_limit(&self, provider: &str) -> Option<f64> {
+        self.budget.provider_limits_usd.get(provider).copied()
+    }
+
+    pub fn check_at(&self, provider: &str, now: DateTime<Utc>) -> Result<(), BudgetExceeded> {
+        let today = now.date_naive();
+        let retry_after = today.succ_opt()
+            .and_then(|tomorrow| tomorrow.and_hms_opt(0, 0, 0))
+            .map_or(1, |midnight| (midnight.and_utc() - now).num_seconds().max(1) as u64);
+        let limits = [
+            (Some(provider), self.provider_limit(provider)),
+            (None, self.budget.daily_limit_usd),
+        ];
+        for (scope, limit) in limits {
+            let Some(limit_usd) = limit else { continue };
+            let spent_usd = self.ledger.spent(today, scope);
+            if spent_usd >= limit_usd {
+                return Err(BudgetExceeded { provider: scope.map(str::to_string), spent_usd, limit_usd, retry_after });
+            }
+        }
+        Ok(())
+    }
+
+    /// Adds one request to today's usage and returns the provider's total for the day
+    pub fn record_at(&mut self, provider: &str, input_tokens: u64, output_tokens: u64, now: DateTime<Utc>) -> ProviderUsage {
+        let price = self.price(provider);
+        let today = now.date_naive();
+        let usage = self.ledger.days.entry(today).or_default().entry(provider.to_string()).or_default();
+        usage.requests += 1;
+        usage.input_tokens += input_tokens;
+        usage.output_tokens += output_tokens;
+        usage.cost_usd += (input_tokens as f64 * price.input_per_million + output_tokens as f64 * price.output_per_million) / 1_000_000.0;
+        let total = *usage;
+
+        let oldest = today - Duration::days(RETAINED_DAYS - 1);
+        self.ledger.days.retain(|date, _| *date >= oldest);
+        total
+    }
+
+    /// Today's spend against the limits, and usage over the last `days` days
+    pub fn report_at(&self, days: usize, now: DateTime<Utc>) -> UsageReport {
+        let today = now.date_naive();
+        let mut names: Vec<String> = [resilience::PERPLEXITY, resilience::RAGFLOW, resilience::OPENAI]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        for name in self.ledger.days.get(&today).into_iter().flat_map(|providers| providers.keys()) {
+            if !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+
+        let providers = names.into_iter().map(|provider| {
+            let today_usage = self.ledger.days.get(&today)
+                .and_then(|providers| providers.get(&provider))
+                .copied()
+                .unwrap_or_default();
+            let limit_usd = self.provider_limit(&provider);
+            ProviderBudget {
+                remaining_usd: limit_usd.map(|limit| (limit - today_usage.cost_usd).max(0.0)),
+                paused: self.check_at(&provider, now).is_err(),
+                provider,
+                today: today_usage,
+                limit_usd,
+            }
+        }).collect();
+
+        let oldest = today - Duration::days(days.saturating_sub(1) as i64);
+        let days = self.ledger.days.range(oldest..=today)
+            .map(|(date, providers)| DayUsage {
+                date: *date,
+                cost_usd: providers.values().map(|usage| usage.cost_usd).sum(),
+                providers: providers.clone(),
+            })
+            .collect();
+
+        UsageReport {
+            date: today,
+            spent_usd: self.ledger.spent(today, None),
+            daily_limit_usd: self.budget.daily_limit_usd,
+            providers,
+            days,
+        }
+    }
+}
+
+fn tracker() -> &'static Mutex<UsageTracker> {
+    TRACKER.get_or_init(|| Mutex::new(UsageTracker::new(AiBudgetSettings::default(), UsageLedger::load())))
+}
+
+/// Applies the limits and prices from the settings
+pub fn configure(settings: Option<&AiBudgetSettings>) {
+    let budget = settings.cloned().unwrap_or_default();
+    if budget.daily_limit_usd.is_some() || !budget.provider_limits_usd.is_empty() {
+        info!("AI budget: {:?} a day overall, {:?} per provider", budget.daily_limit_usd, budget.provider_limits_usd);
+    }
+    tracker().lock().unwrap_or_else(|e| e.into_inner()).budget = budget;
+}
+
+/// Whether a new request to `provider` fits in today's budget
+pub fn check(provider: &str) -> Result<(), BudgetExceeded> {
+    tracker().lock().unwrap_or_else(|e| e.into_inner()).check_at(provider, Utc::now())
+}
+
+/// Records one request that reached `provider`
+pub fn record(provider: &str, input_tokens: u64, output_tokens: u64) {
+    let mut tracker = tracker().lock().unwrap_or_else(|e| e.into_inner());
+    tracker.record_at(provider, input_tokens, output_tokens, Utc::now());
+    if let Err(e) = tracker.ledger.save() {
+        warn!("Failed to save AI usage: {}", e);
+    }
+}
+
+pub fn report(days: usize) -> UsageReport {
+    tracker().lock().unwrap_or_else(|e| e.into_inner()).report_at(days, Utc::now())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_usage_tracker() {
+        let budget = AiBudgetSettings {
+            daily_limit_usd: Some(1.0),
+            provider_limits_usd: BTreeMap::from([(resilience::PERPLEXITY.to_string(), 0.5)]),
+            prices: BTreeMap::from([(resilience::RAGFLOW.to_string(), AiPriceSettings { input_per_million: 2.0, output_per_million: 4.0 })]),
+        };
+        let mut tracker = UsageTracker::new(budget, UsageLedger::default());
+        let now = Utc.with_ymd_and_hms(2026, 3, 14, 18, 0, 0).unwrap();
+
+        let usage = tracker.record_at(resilience::PERPLEXITY, 200_000, 100_000, now);
+        assert_eq!(usage.requests, 1);
+        assert!((usage.cost_usd - 0.3).abs() < 1e-9);
+        assert!(tracker.check_at(resilience::PERPLEXITY, now).is_ok());
+        tracker.record_at(resilience::PERPLEXITY, 100_000, 100_000, now);
+        let exceeded = tracker.check_at(resilience::PERPLEXITY, now).unwrap_err();
+        assert_eq!(exceeded.provider.as_deref(), Some(resilience::PERPLEXITY));
+        assert_eq!(exceeded.retry_after, 6 * 3600);
+
+        // RAGFlow has no limit of its own, but counts towards the overall one
+        assert!(tracker.check_at(resilience::RAGFLOW, now).is_ok());
+        tracker.record_at(resilience::RAGFLOW, 100_000, 100_000, now);
+        let exceeded = tracker.check_at(resilience::RAGFLOW, now).unwrap_err();
+        assert_eq!(exceeded.provider, None);
+        assert!((exceeded.spent_usd - 1.1).abs() < 1e-9);
+
+        // A new day starts with nothing spent
+        let tomorrow = now + Duration::days(1);
+        assert!(tracker.check_at(resilience::PERPLEXITY, tomorrow).is_ok());
+        tracker.record_at(resilience::OPENAI, estimate_tokens("Hello there"), 0, tomorrow);
+        let report = tracker.report_at(7, tomorrow);
+        assert_eq!(report.days.len(), 2);
+        assert!((report.days[0].cost_usd - 1.1).abs() < 1e-9);
+        let openai = report.providers.iter().find(|p| p.provider == resilience::OPENAI).unwrap();
+        assert_eq!(openai.today.input_tokens, 3);
+        let perplexity = report.providers.iter().find(|p| p.provider == resilience::PERPLEXITY).unwrap();
+        assert_eq!(perplexity.remaining_usd, Some(0.5));
+        assert!(!perplexity.paused);
+
+        // Old days are dropped
+        tracker.record_at(resilience::OPENAI, 1, 0, now + Duration::days(RETAINED_DAYS));
+        assert!(!tracker.ledger.days.contains_key(&now.date_naive()));
+    }
+}
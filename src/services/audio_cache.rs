@@ -0,0 +1,181 @@
+//! On-disk cache for synthesized speech
+//!
+//! Entries are keyed by a SHA-256 of provider, voice, speed and text, so repeated
+//! TTS of the same node summary or UI prompt is served from disk instead of hitting
+//! the provider again. The key also covers the provider's model and output format,
+//! so changing those settings stops older audio from being served. Total size is bounded; the oldest entries are evicted first,
+//! where "oldest" is last access (LRU) or creation time (FIFO).
+
+use crate::config::data_dirs::DataDirs;
+use crate::config::AudioCacheSettings;
+use crate::types::speech::{SpeechOptions, TTSProvider};
+use log::{debug, warn};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+const DEFAULT_MAX_SIZE_MB: u64 = 256;
+const CACHE_FILE_EXTENSION: &str = "audio";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EvictionPolicy {
+    Lru,
+    Fifo,
+}
+
+#[derive(Debug, Clone)]
+pub struct AudioCache {
+    dir: PathBuf,
+    max_bytes: u64,
+    policy: EvictionPolicy,
+}
+
+impl AudioCache {
+    /// Returns `None` when caching is disabled or not configured
+    pub fn from_settings(settings: Option<&AudioCacheSettings>) -> Option<Self> {
+        let settings = settings?;
+        if !settings.enabled {
+            return None;
+        }
+        let policy = match settings.eviction_policy.as_deref() {
+            Some(p) if p.eq_ignore_ascii_case("fifo") => EvictionPolicy::Fifo,
+            _ => EvictionPolicy::Lru,
+        };
+        Some(Self::new(
+            settings.directory.as_ref().map(PathBuf::from).unwrap_or_else(|| DataDirs::global().audio_cache()),
+            settings.max_size_mb.unwrap_or(DEFAULT_MAX_SIZE_MB) * 1024 * 1024,
+            policy,
+        ))
+    }
+
+    pub fn new(dir: PathBuf, max_bytes: u64, policy: EvictionPolicy) -> Self {
+        Self { dir, max_bytes, policy }
+    }
+
+    /// Cache key for `text` spoken by `provider` with `options`. `fingerprint` names
+    /// the backend's other audio settings, as given by `TtsProvider::audio_fingerprint`.
+    pub fn key(provider: &TTSProvider, fingerprint: &str, text: &str, options: &SpeechOptions) -> String {
+        let mut hasher = Sha256::new();
+        hasher.update(provider.id().as_bytes());
+        hasher.update([0]);
+        hasher.update(fingerprint.as_bytes());
+        hasher.update([0]);
+        hasher.update(options.voice.as_bytes());
+        hasher.update([0]);
+        hasher.update(options.speed.to_le_bytes());
+        hasher.update([0]);
+        hasher.update(text.as_bytes());
+        hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    fn path_for(&self, key: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", key, CACHE_FILE_EXTENSION))
+    }
+
+    pub fn get(&self, key: &str) -> Option<Vec<u8>> {
+        let path = self.path_for(key);
+        let data = fs::read(&path).ok()?;
+        if self.policy == EvictionPolicy::Lru {
+            // Bump mtime so recently used entries survive eviction
+            if let Err(e) = fs::File::options().write(true).open(&path).and_then(|f| f.set_modified(SystemTime::now())) {
+                debug!("[AudioCache] Failed to touch {:?}: {}", path, e);
+            }
+        }
+        debug!("[AudioCache] Hit for {}", key);
+        Some(data)
+    }
+
+    pub fn put(&self, key: &str, data: &[u8]) -> io::Result<()> {
+        if data.is_empty() || data.len() as u64 > self.max_bytes {
+            return Ok(());
+        }
+        fs::create_dir_all(&self.dir)?;
+        // Write to a temp file first so a concurrent reader never sees a partial entry
+        let tmp = self.dir.join(format!("{}.tmp", key));
+        fs::write(&tmp, data)?;
+        fs::rename(&tmp, self.path_for(key))?;
+        self.evict()
+    }
+
+    /// Removes entries, oldest first, until the cache fits in `max_bytes`
+    pub fn evict(&self) -> io::Result<()> {
+        let mut entries = Vec::new();
+        let mut total: u64 = 0;
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(CACHE_FILE_EXTENSION) {
+                continue;
+            }
+            let meta = entry.metadata()?;
+            let stamp = match self.policy {
+                EvictionPolicy::Lru => meta.modified(),
+                EvictionPolicy::Fifo => meta.created().or_else(|_| meta.modified()),
+            }.unwrap_or(SystemTime::UNIX_EPOCH);
+            total += meta.len();
+            entries.push((stamp, meta.len(), path));
+        }
+
+        if total <= self.max_bytes {
+            return Ok(());
+        }
+
+        entries.sort_by_key(|(stamp, _, _)| *stamp);
+        for (_, len, path) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            match fs::remove_file(&path) {
+                Ok(()) => {
+                    total = total.saturating_sub(len);
+                    debug!("[AudioCache] Evicted {:?}", path);
+                }
+                Err(e) => warn!("[AudioCache] Failed to evict {:?}: {}", path, e),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_cache(max_bytes: u64) -> AudioCache {
+        let dir = std::env::temp_dir().join(format!("audio_cache_test_{}", uuid::Uuid::new_v4()));
+        AudioCache::new(dir, max_bytes, EvictionPolicy::Lru)
+    }
+
+    #[test]
+    fn test_key_depends_on_voice_text_and_audio_settings() {
+        let options = SpeechOptions::default();
+        let other_voice = SpeechOptions { voice: "other".to_string(), ..SpeechOptions::default() };
+        let a = AudioCache::key(&TTSProvider::Kokoro, "mp3", "hello", &options);
+        assert_eq!(a, AudioCache::key(&TTSProvider::Kokoro, "mp3", "hello", &options));
+        assert_ne!(a, AudioCache::key(&TTSProvider::Kokoro, "mp3", "hello", &other_voice));
+        assert_ne!(a, AudioCache::key(&TTSProvider::Kokoro, "mp3", "goodbye", &options));
+        assert_ne!(a, AudioCache::key(&TTSProvider::Kokoro, "wav", "hello", &options));
+        assert_ne!(a, AudioCache::key(&TTSProvider::OpenAI, "mp3", "hello", &options));
+    }
+
+    #[test]
+    fn test_put_get_and_evict() {
+        let cache = temp_cache(10);
+        cache.put("first", b"123456").unwrap();
+        assert_eq!(cache.get("first").as_deref(), Some(&b"123456"[..]));
+
+        // Make sure the second entry is strictly newer on coarse-mtime filesystems
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        cache.put("second", b"abcdef").unwrap();
+
+        assert!(cache.get("first").is_none());
+        assert!(cache.get("second").is_some());
+        let _ = fs::remove_dir_all(cache.dir());
+    }
+}
@@ -0,0 +1,140 @@
+//! Content-addressed store for downloaded markdown
+//!
+//! Files are stored under their git blob SHA, the same hash the GitHub contents API
+//! reports, so a sync can tell from the listing alone whether it already has a file's
+//! content. The ETag of each file's last download is kept alongside, letting changed
+//! files be fetched with `If-None-Match`.
+
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::config::data_dirs::DataDirs;
+use crate::services::vault_crypto;
+
+const ETAG_INDEX_FILE: &str = "etags.json";
+
+/// ETag and blob SHA from the last successful download of a file
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EtagEntry {
+    pub etag: String,
+    pub sha: String,
+}
+
+pub struct BlobCache {
+    dir: PathBuf,
+    etags: Mutex<HashMap<String, EtagEntry>>,
+}
+
+/// SHA-1 of the content as a git blob (`blob <len>\0<content>`)
+pub fn git_blob_sha(content: &str) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(format!("blob {}\0", content.len()).as_bytes());
+    hasher.update(content.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+impl BlobCache {
+    pub fn open() -> Self {
+        Self::open_at(DataDirs::global().blobs())
+    }
+
+    pub fn open_at(dir: PathBuf) -> Self {
+        let etags = fs::read_to_string(dir.join(ETAG_INDEX_FILE))
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        Self { dir, etags: Mutex::new(etags) }
+    }
+
+    fn path_for(&self, sha: &str) -> Option<PathBuf> {
+        // Only accept hex SHAs so a bad listing can't escape the cache directory
+        if sha.len() < 4 || !sha.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        Some(self.dir.join(&sha[..2]).join(sha))
+    }
+
+    /// Returns the content stored under `sha`; corrupt entries are dropped
+    pub fn get(&self, sha: &str) -> Option<String> {
+        let path = self.path_for(sha)?;
+        let content = vault_crypto::read_to_string(&path).ok()?;
+        if git_blob_sha(&content) != sha {
+            warn!("Dropping corrupt blob {}", sha);
+            let _ = fs::remove_file(&path);
+            return None;
+        }
+        // Bump mtime so blobs still in use survive storage quota eviction
+        if let Err(e) = fs::File::options().write(true).open(&path).and_then(|f| f.set_modified(SystemTime::now())) {
+            debug!("Failed to touch blob {}: {}", sha, e);
+        }
+        Some(content)
+    }
+
+    /// Stores content under its own blob SHA and returns that SHA
+    pub fn put(&self, content: &str) -> io::Result<String> {
+        let sha = git_blob_sha(content);
+        let path = self.path_for(&sha)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid blob sha"))?;
+        if path.exists() {
+            return Ok(sha);
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let tmp = path.with_extension("tmp");
+        vault_crypto::write(&tmp, content)?;
+        fs::rename(&tmp, &path)?;
+        debug!("Stored blob {}", sha);
+        Ok(sha)
+    }
+
+    pub fn etag(&self, file_name: &str) -> Option<EtagEntry> {
+        self.etags.lock().unwrap().get(file_name).cloned()
+    }
+
+    pub fn set_etag(&self, file_name: &str, etag: String, sha: String) {
+        self.etags.lock().unwrap().insert(file_name.to_string(), EtagEntry { etag, sha });
+    }
+
+    /// Persists the ETag index; blobs themselves are written by `put`
+    pub fn save_index(&self) -> io::Result<()> {
+        let json = serde_json::to_string_pretty(&*self.etags.lock().unwrap()).map_err(io::Error::from)?;
+        fs::create_dir_all(&self.dir)?;
+        let tmp = self.dir.join(format!("{}.tmp", ETAG_INDEX_FILE));
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, self.dir.join(ETAG_INDEX_FILE))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_git_blob_sha_matches_git() {
+        // `printf 'hello world\n' | git hash-object --stdin`
+        assert_eq!(git_blob_sha("hello world\n"), "3b18e512dba79e4c8300dd08aeb37f8e728b8dad");
+    }
+
+    #[test]
+    fn test_put_then_get_by_sha() {
+        let dir = std::env::temp_dir().join(format!("blob_cache_test_{}", uuid::Uuid::new_v4()));
+        let cache = BlobCache::open_at(dir.clone());
+        let sha = cache.put("public:: true\n# Page").unwrap();
+        assert_eq!(cache.get(&sha).as_deref(), Some("public:: true\n# Page"));
+        assert!(cache.get("0000000000000000000000000000000000000000").is_none());
+        assert!(cache.get("../etc").is_none());
+
+        cache.set_etag("Page.md", "\"abc\"".to_string(), sha.clone());
+        cache.save_index().unwrap();
+        assert_eq!(BlobCache::open_at(dir.clone()).etag("Page.md").map(|e| e.sha), Some(sha));
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,263 @@
+//! Graph-aware context for chat questions
+//!
+//! Given a focus node, the neighbourhood is walked breadth first, up to
+//! `max_depth` links away and `max_nodes` notes, following the strongest links
+//! first. Each note contributes its title, its Perplexity summary and the opening
+//! of its text for as long as the token budget lasts. Nearer notes are added in
+//! full before further ones get anything, so a tight budget still keeps the focus
+//! and its direct links. The chat endpoint puts the rendered context before the
+//! question, so answers can follow the links between notes. The budget is set
+//! with `CHAT_CONTEXT_DEPTH`, `CHAT_CONTEXT_NODES` and `CHAT_CONTEXT_TOKENS`.
+
+use serde::Serialize;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Write;
+
+use crate::models::graph::GraphData;
+use crate::models::node::Node;
+use crate::services::ai_usage::{estimate_tokens, CHARS_PER_TOKEN};
+
+const EXCERPT_CHARS: usize = 600;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContextBudget {
+    /// Links followed away from the focus
+    pub max_depth: usize,
+    /// Notes included, the focus among them
+    pub max_nodes: usize,
+    pub max_tokens: u64,
+}
+
+impl Default for ContextBudget {
+    fn default() -> Self {
+        Self { max_depth: 2, max_nodes: 12, max_tokens: 1500 }
+    }
+}
+
+impl ContextBudget {
+    /// `CHAT_CONTEXT_DEPTH`, `CHAT_CONTEXT_NODES` and `CHAT_CONTEXT_TOKENS`, each
+    /// falling back to the default
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let var = |name: &str| std::env::var(name).ok().and_then(|v| v.trim().parse::<u64>().ok());
+        Self {
+            max_depth: var("CHAT_CONTEXT_DEPTH").map_or(defaults.max_depth, |v| v as usize),
+            max_nodes: var("CHAT_CONTEXT_NODES").map_or(defaults.max_nodes, |v| v.max(1) as usize),
+            max_tokens: var("CHAT_CONTEXT_TOKENS").unwrap_or(defaults.max_tokens),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContextNote {
+    pub node_id: u32,
+    pub title: String,
+    /// Links away from the focus
+    pub depth: usize,
+    /// Title of the note this one was reached from
+    pub via: Option<String>,
+    pub summary: Option<String>,
+    pub excerpt: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PromptContext {
+    pub focus_id: u32,
+    pub notes: Vec<ContextNote>,
+    /// Estimated size of the rendered notes
+    pub tokens: u64,
+    /// Notes within reach that didn't fit the budget
+    pub omitted: usize,
+}
+
+impl PromptContext {
+    pub fn node_ids(&self) -> Vec<u32> {
+        self.notes.iter().map(|note| note.node_id).collect()
+    }
+
+    /// The notes as plain text, nearest first
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        for note in &self.notes {
+            text.push_str(&note_header(note));
+            if let Some(summary) = &note.summary {
+                let _ = writeln!(text, "Summary: {}", summary);
+            }
+            if let Some(excerpt) = &note.excerpt {
+                let _ = writeln!(text, "{}", excerpt);
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    /// `question` preceded by the notes it is asked about
+    pub fn prompt(&self, question: &str) -> String {
+        let focus = self.notes.first().map_or("", |note| note.title.as_str());
+        format!(
+            "Answer using these notes from my knowledge graph, starting at \"{}\". \
+Links between notes are shown as \"via\".\n\n{}Question: {}",
+            focus,
+            self.render(),
+            question
+        )
+    }
+}
+
+fn note_header(note: &ContextNote) -> String {
+    match &note.via {
+        Some(via) => format!("## {} (via {})\n", note.title, via),
+        None => format!("## {}\n", note.title),
+    }
+}
+
+/// The opening of a page, leaving out Logseq properties (`key:: value`)
+pub fn excerpt(content: &str, max_chars: usize) -> Option<String> {
+    let mut excerpt = String::new();
+    for line in content.lines() {
+        let text = line.trim_start().trim_start_matches("- ");
+        let is_property = text.split_once("::").is_some_and(|(key, _)| {
+            !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        });
+        if is_property || text.trim().is_empty() {
+            continue;
+        }
+        if !excerpt.is_empty() {
+            excerpt.push('\n');
+        }
+        excerpt.push_str(line.trim_end());
+        if excerpt.chars().count() >= max_chars {
+            excerpt = excerpt.chars().take(max_chars).collect();
+            excerpt.push('…');
+            break;
+        }
+    }
+    Some(excerpt).filter(|excerpt| !excerpt.is_empty())
+}
+
+/// Nodes within `max_depth` links of `focus`, as `(node id, depth, reached from)`,
+/// nearest first and strongest link first within a depth
+pub fn neighbourhood(graph: &GraphData, focus: u32, max_depth: usize, max_nodes: usize) -> Vec<(u32, usize, Option<u32>)> {
+    let mut links: HashMap<u32, Vec<(u32, f32)>> = HashMap::new();
+    for edge in &graph.edges {
+        if edge.source == edge.target {
+            continue;
+        }
+        links.entry(edge.source).or_default().push((edge.target, edge.weight));
+        links.entry(edge.target).or_default().push((edge.source, edge.weight));
+    }
+    for neighbours in links.values_mut() {
+        neighbours.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+    }
+
+    let mut seen = HashSet::from([focus]);
+    let mut found = Vec::new();
+    let mut queue = VecDeque::from([(focus, 0, None)]);
+    while let Some((id, depth, via)) = queue.pop_front() {
+        if found.len() >= max_nodes {
+            break;
+        }
+        found.push((id, depth, via));
+        if depth >= max_depth {
+            continue;
+        }
+        for &(neighbour, _) in links.get(&id).into_iter().flatten() {
+            if seen.insert(neighbour) {
+                queue.push_back((neighbour, depth + 1, Some(id)));
+            }
+        }
+    }
+    found
+}
+
+/// The context for questions about `focus`, or `None` if it isn't in the graph.
+/// `read` returns the text of a node's page.
+pub fn build(graph: &GraphData, focus: u32, budget: &ContextBudget, read: impl Fn(&Node) -> Option<String>) -> Option<PromptContext> {
+    let nodes: HashMap<u32, &Node> = graph.nodes.iter().map(|node| (node.id, node)).collect();
+    nodes.get(&focus)?;
+    let title = |id: u32| nodes.get(&id).map_or_else(|| id.to_string(), |node| node.label.clone());
+
+    let reachable = neighbourhood(graph, focus, budget.max_depth, budget.max_nodes);
+    let mut context = PromptContext { focus_id: focus, notes: Vec::new(), tokens: 0, omitted: 0 };
+    for (id, depth, via) in reachable {
+        let Some(node) = nodes.get(&id) else { continue };
+        let mut note = ContextNote {
+            node_id: id,
+            title: title(id),
+            depth,
+            via: via.map(title),
+            summary: None,
+            excerpt: None,
+        };
+        let header = estimate_tokens(&note_header(&note));
+        if context.tokens + header > budget.max_tokens {
+            context.omitted += 1;
+            continue;
+        }
+        context.tokens += header;
+
+        let summary = graph.metadata.get(&format!("{}.md", node.metadata_id))
+            .map(|meta| meta.perplexity_summary.trim().to_string())
+            .filter(|summary| !summary.is_empty());
+        if let Some(summary) = summary {
+            let tokens = estimate_tokens(&format!("Summary: {}\n", summary));
+            if context.tokens + tokens <= budget.max_tokens {
+                context.tokens += tokens;
+                note.summary = Some(summary);
+            }
+        }
+
+        let remaining = budget.max_tokens - context.tokens;
+        let max_chars = (remaining as usize * CHARS_PER_TOKEN).min(EXCERPT_CHARS);
+        if max_chars > 0 {
+            if let Some(text) = read(node).and_then(|content| excerpt(&content, max_chars)) {
+                context.tokens += estimate_tokens(&text).min(remaining);
+                note.excerpt = Some(text);
+            }
+        }
+        context.notes.push(note);
+    }
+    Some(context)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::edge::Edge;
+
+    fn node(id: u32, name: &str) -> Node {
+        Node::new_with_id(name.to_string(), Some(id)).with_label(name.to_string())
+    }
+
+    #[test]
+    fn test_build_context() {
+        let mut graph = GraphData::new();
+        graph.nodes = vec![node(1, "Graphs"), node(2, "Trees"), node(3, "Edges"), node(4, "Forests"), node(5, "Elsewhere")];
+        graph.edges = vec![Edge::new(1, 2, 1.0), Edge::new(3, 1, 5.0), Edge::new(2, 4, 1.0), Edge::new(4, 5, 1.0)];
+        let read = |node: &Node| Some(format!("public:: true\n\n- About {}.\n- More detail.", node.label));
+
+        let order: Vec<u32> = neighbourhood(&graph, 1, 2, 10).iter().map(|(id, _, _)| *id).collect();
+        assert_eq!(order, vec![1, 3, 2, 4]);
+
+        let context = build(&graph, 1, &ContextBudget::default(), read).unwrap();
+        assert_eq!(context.node_ids(), vec![1, 3, 2, 4]);
+        assert_eq!(context.notes[3].via.as_deref(), Some("Trees"));
+        assert_eq!(context.notes[0].excerpt.as_deref(), Some("- About Graphs.\n- More detail."));
+        assert_eq!(context.omitted, 0);
+        let prompt = context.prompt("How do trees relate to graphs?");
+        assert!(prompt.contains("## Forests (via Trees)\n- About Forests."));
+        assert!(prompt.ends_with("Question: How do trees relate to graphs?"));
+
+        // A tight budget keeps the nearest notes and drops the rest
+        let tight = ContextBudget { max_depth: 2, max_nodes: 10, max_tokens: 12 };
+        let context = build(&graph, 1, &tight, read).unwrap();
+        assert!(context.tokens <= 12);
+        assert_eq!(context.notes[0].title, "Graphs");
+        assert!(context.omitted > 0);
+
+        assert!(build(&graph, 9, &ContextBudget::default(), read).is_none());
+        assert_eq!(excerpt("alias:: G\n- abcdef", 3).as_deref(), Some("- a…"));
+    }
+}
@@ -0,0 +1,257 @@
+//! Threaded comments on graph nodes
+//!
+//! Collaborators can discuss a page from inside the 3D view without editing it.
+//! Comments are attached to a node by its metadata id (the page name), so they
+//! follow the page across rebuilds, and reply to another comment through
+//! `parent_id`. Bodies are markdown, stored as given. Comments are kept in
+//! `/app/data/metadata/comments.json`; every change is published on the event bus.
+
+use chrono::{DateTime, Utc};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::config::data_dirs::DataDirs;
+use crate::services::event_bus::{CommentEvent, EventBus};
+
+fn comments_path() -> PathBuf {
+    DataDirs::global().metadata_file("comments.json")
+}
+
+const MAX_BODY_BYTES: usize = 10 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Comment {
+    pub id: String,
+    /// Metadata id of the node, i.e. the page name without `.md`
+    pub node: String,
+    pub parent_id: Option<String>,
+    /// Pubkey of the author
+    pub author: String,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+    pub edited_at: Option<DateTime<Utc>>,
+    /// Deleted comments with replies stay as placeholders so the thread holds together
+    #[serde(default)]
+    pub deleted: bool,
+}
+
+#[derive(Debug)]
+pub enum CommentError {
+    Invalid(String),
+    NotFound(String),
+    /// The requester may not change this comment
+    Forbidden(String),
+    Storage(String),
+}
+
+impl std::fmt::Display for CommentError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CommentError::Invalid(message)
+            | CommentError::NotFound(message)
+            | CommentError::Forbidden(message)
+            | CommentError::Storage(message) => f.write_str(message),
+        }
+    }
+}
+
+pub struct CommentService {
+    comments: RwLock<HashMap<String, Comment>>,
+    event_bus: EventBus,
+}
+
+impl CommentService {
+    pub fn new(event_bus: EventBus) -> Self {
+        let comments = Self::load_comments().unwrap_or_else(|e| {
+            debug!("[Comments] No stored comments loaded: {}", e);
+            HashMap::new()
+        });
+        info!("[Comments] Loaded {} comments", comments.len());
+        Self { comments: RwLock::new(comments), event_bus }
+    }
+
+    /// Comments on `node`, oldest first. Replies carry their parent's id, so
+    /// clients can nest them.
+    pub async fn list(&self, node: &str) -> Vec<Comment> {
+        let comments = self.comments.read().await;
+        let mut thread: Vec<Comment> = comments.values().filter(|c| c.node == node).cloned().collect();
+        thread.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)));
+        thread
+    }
+
+    /// Number of live comments per node, for showing which nodes are discussed
+    pub async fn counts(&self) -> HashMap<String, usize> {
+        let mut counts = HashMap::new();
+        for comment in self.comments.read().await.values().filter(|c| !c.deleted) {
+            *counts.entry(comment.node.clone()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    pub async fn add(&self, node: &str, parent_id: Option<String>, author: &str, body: &str) -> Result<Comment, CommentError> {
+        let body = validate_body(body)?;
+        let mut comments = self.comments.write().await;
+        if let Some(parent_id) = &parent_id {
+            match comments.get(parent_id) {
+                Some(parent) if parent.node == node => {}
+                Some(_) => return Err(CommentError::Invalid("A reply must be on the same node as its parent".to_string())),
+                None => return Err(CommentError::NotFound(format!("Comment {} not found", parent_id))),
+            }
+        }
+        let comment = Comment {
+            id: Uuid::new_v4().simple().to_string(),
+            node: node.to_string(),
+            parent_id,
+            author: author.to_string(),
+            body,
+            created_at: Utc::now(),
+            edited_at: None,
+            deleted: false,
+        };
+        comments.insert(comment.id.clone(), comment.clone());
+        Self::save_comments(&comments)?;
+        drop(comments);
+
+        self.event_bus.publish(CommentEvent::Added { comment: comment.clone() });
+        Ok(comment)
+    }
+
+    /// Replaces the body of a comment; only its author may edit it
+    pub async fn edit(&self, id: &str, requester: &str, body: &str) -> Result<Comment, CommentError> {
+        let body = validate_body(body)?;
+        let mut comments = self.comments.write().await;
+        let comment = comments.get_mut(id)
+            .filter(|c| !c.deleted)
+            .ok_or_else(|| CommentError::NotFound(format!("Comment {} not found", id)))?;
+        if comment.author != requester {
+            return Err(CommentError::Forbidden("Only the author can edit a comment".to_string()));
+        }
+        comment.body = body;
+        comment.edited_at = Some(Utc::now());
+        let comment = comment.clone();
+        Self::save_comments(&comments)?;
+        drop(comments);
+
+        self.event_bus.publish(CommentEvent::Edited { comment: comment.clone() });
+        Ok(comment)
+    }
+
+    /// Deletes a comment as its author or a moderator
+    pub async fn delete(&self, id: &str, requester: &str, moderator: bool) -> Result<(), CommentError> {
+        let mut comments = self.comments.write().await;
+        let node = remove_comment(&mut comments, id, requester, moderator)?;
+        Self::save_comments(&comments)?;
+        drop(comments);
+
+        self.event_bus.publish(CommentEvent::Deleted { id: id.to_string(), node });
+        Ok(())
+    }
+
+    fn load_comments() -> Result<HashMap<String, Comment>, Box<dyn std::error::Error>> {
+        let content = fs::read_to_string(comments_path())?;
+        let comments: Vec<Comment> = serde_json::from_str(&content)?;
+        Ok(comments.into_iter().map(|comment| (comment.id.clone(), comment)).collect())
+    }
+
+    fn save_comments(comments: &HashMap<String, Comment>) -> Result<(), CommentError> {
+        let save = || -> std::io::Result<()> {
+            if let Some(parent) = comments_path().parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut sorted: Vec<&Comment> = comments.values().collect();
+            sorted.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)));
+            fs::write(comments_path(), serde_json::to_string_pretty(&sorted)?)
+        };
+        save().map_err(|e| CommentError::Storage(format!("Failed to persist comments: {}", e)))
+    }
+}
+
+fn validate_body(body: &str) -> Result<String, CommentError> {
+    let body = body.trim();
+    if body.is_empty() {
+        return Err(CommentError::Invalid("Comment body is empty".to_string()));
+    }
+    if body.len() > MAX_BODY_BYTES {
+        return Err(CommentError::Invalid(format!("Comment is {} bytes; the limit is {}", body.len(), MAX_BODY_BYTES)));
+    }
+    Ok(body.to_string())
+}
+
+/// Removes the comment, or blanks it when it has replies, and drops placeholders
+/// left without replies. Returns the comment's node.
+fn remove_comment(
+    comments: &mut HashMap<String, Comment>,
+    id: &str,
+    requester: &str,
+    moderator: bool,
+) -> Result<String, CommentError> {
+    let comment = comments.get(id)
+        .filter(|c| !c.deleted)
+        .ok_or_else(|| CommentError::NotFound(format!("Comment {} not found", id)))?;
+    if comment.author != requester && !moderator {
+        return Err(CommentError::Forbidden("Only the author or a power user can delete a comment".to_string()));
+    }
+    let node = comment.node.clone();
+
+    let mut current = Some(id.to_string());
+    while let Some(id) = current.take() {
+        let has_replies = comments.values().any(|c| c.parent_id.as_deref() == Some(id.as_str()));
+        if has_replies {
+            if let Some(comment) = comments.get_mut(&id) {
+                comment.deleted = true;
+                comment.body.clear();
+            }
+            break;
+        }
+        // Without replies the comment goes, and so may a deleted parent it was the last reply to
+        current = comments.remove(&id)
+            .and_then(|removed| removed.parent_id)
+            .filter(|parent| comments.get(parent).is_some_and(|p| p.deleted));
+    }
+    Ok(node)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn comment(id: &str, parent_id: Option<&str>, author: &str) -> Comment {
+        Comment {
+            id: id.to_string(),
+            node: "Page".to_string(),
+            parent_id: parent_id.map(str::to_string),
+            author: author.to_string(),
+            body: "Looks good".to_string(),
+            created_at: Utc::now(),
+            edited_at: None,
+            deleted: false,
+        }
+    }
+
+    #[test]
+    fn test_remove_keeps_threads_together() {
+        let mut comments: HashMap<String, Comment> = [
+            comment("a", None, "alice"),
+            comment("b", Some("a"), "bob"),
+        ].into_iter().map(|c| (c.id.clone(), c)).collect();
+
+        assert!(matches!(remove_comment(&mut comments, "a", "bob", false), Err(CommentError::Forbidden(_))));
+
+        // The root has a reply, so it stays as a placeholder
+        assert_eq!(remove_comment(&mut comments, "a", "alice", false).unwrap(), "Page");
+        assert!(comments["a"].deleted && comments["a"].body.is_empty());
+
+        // Removing the last reply takes the placeholder with it
+        remove_comment(&mut comments, "b", "carol", true).unwrap();
+        assert!(comments.is_empty());
+
+        assert!(validate_body("  ").is_err());
+        assert!(validate_body(&"x".repeat(MAX_BODY_BYTES + 1)).is_err());
+    }
+}
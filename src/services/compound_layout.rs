@@ -0,0 +1,315 @@
+//! Two-level layout for graphs with communities
+//!
+//! On a large vault a single force-directed pass lets communities drift through
+//! each other. The compound layout finds communities by modularity, lays
+//! them out as a meta-graph whose nodes are the communities and whose edges count
+//! the links between them, and then holds each member within a disc around its
+//! community's position. The physics still arranges nodes inside a community.
+
+use std::collections::HashMap;
+
+use crate::models::graph::GraphData;
+use crate::models::node::Node;
+use crate::types::vec3::Vec3Data;
+
+// Local moving usually settles in a handful of rounds
+const MAX_MOVING_ROUNDS: usize = 20;
+const META_ITERATIONS: usize = 100;
+// Fraction of the remaining distance to the ideal meta-edge length covered per iteration
+const META_ATTRACTION: f32 = 0.1;
+// Fraction of the overshoot a member is pulled back by on each step
+const MEMBER_PULL: f32 = 0.1;
+// Smallest distance allowed per member when sizing a community's disc
+const MIN_MEMBER_SPACING: f32 = 1.0;
+
+/// Communities of linked nodes, largest first, each with its members in id order.
+/// Nodes without edges, and communities of one, are left out.
+pub fn detect_communities(graph: &GraphData) -> Vec<Vec<u32>> {
+    let mut ids: Vec<u32> = graph.nodes.iter().map(|node| node.id).collect();
+    ids.sort_unstable();
+    ids.dedup();
+    let index: HashMap<u32, usize> = ids.iter().enumerate().map(|(i, &id)| (id, i)).collect();
+
+    let mut neighbours: Vec<Vec<(usize, f32)>> = vec![Vec::new(); ids.len()];
+    for edge in &graph.edges {
+        if let (Some(&i), Some(&j)) = (index.get(&edge.source), index.get(&edge.target)) {
+            if i != j {
+                neighbours[i].push((j, edge.weight.max(0.0)));
+                neighbours[j].push((i, edge.weight.max(0.0)));
+            }
+        }
+    }
+
+    // Louvain's local moving phase: each node joins the neighbouring community that
+    // raises modularity the most, staying put on ties and otherwise preferring the
+    // lowest label, visiting nodes in id order so runs agree
+    let degree: Vec<f32> = neighbours.iter().map(|list| list.iter().map(|&(_, w)| w).sum()).collect();
+    let total_weight: f32 = degree.iter().sum();
+    let mut labels: Vec<usize> = (0..ids.len()).collect();
+    if total_weight > 0.0 {
+        let mut community_degree = degree.clone();
+        for _ in 0..MAX_MOVING_ROUNDS {
+            let mut changed = false;
+            for i in 0..ids.len() {
+                if neighbours[i].is_empty() {
+                    continue;
+                }
+                let current = labels[i];
+                community_degree[current] -= degree[i];
+                let mut links: HashMap<usize, f32> = HashMap::from([(current, 0.0)]);
+                for &(j, weight) in &neighbours[i] {
+                    *links.entry(labels[j]).or_default() += weight;
+                }
+                let gain = |label: usize, weight: f32| weight - community_degree[label] * degree[i] / total_weight;
+                let mut best = (current, gain(current, links[&current]));
+                let mut candidates: Vec<(usize, f32)> = links.into_iter().collect();
+                candidates.sort_unstable_by_key(|&(label, _)| label);
+                for (label, weight) in candidates {
+                    let value = gain(label, weight);
+                    if value > best.1 {
+                        best = (label, value);
+                    }
+                }
+                labels[i] = best.0;
+                community_degree[best.0] += degree[i];
+                changed |= best.0 != current;
+            }
+            if !changed {
+                break;
+            }
+        }
+    }
+
+    let mut by_label: HashMap<usize, Vec<u32>> = HashMap::new();
+    for (i, &label) in labels.iter().enumerate() {
+        by_label.entry(label).or_default().push(ids[i]);
+    }
+    let mut communities: Vec<Vec<u32>> = by_label.into_values().filter(|members| members.len() > 1).collect();
+    communities.sort_by(|a, b| b.len().cmp(&a.len()).then(a[0].cmp(&b[0])));
+    communities
+}
+
+/// Where each community sits and how far its members may stray
+#[derive(Debug, Clone)]
+pub struct CompoundLayout {
+    community: HashMap<u32, usize>,
+    centres: Vec<Vec3Data>,
+    radii: Vec<f32>,
+    separation: f32,
+    // Node and edge counts of the graph it was computed for
+    shape: (usize, usize),
+}
+
+impl CompoundLayout {
+    /// Lays out the communities of `graph`, starting from where their members are
+    /// now. Each community gets a disc sized by its members' spread, and discs are
+    /// kept `separation` times the sum of their radii apart, with linked communities
+    /// pulled to that distance.
+    pub fn new(graph: &GraphData, separation: f32) -> Self {
+        let communities = detect_communities(graph);
+        let positions: HashMap<u32, Vec3Data> = graph.nodes.iter().map(|node| (node.id, node.data.position)).collect();
+
+        let mut community = HashMap::new();
+        let mut centres = Vec::with_capacity(communities.len());
+        let mut radii = Vec::with_capacity(communities.len());
+        for (c, members) in communities.iter().enumerate() {
+            let points: Vec<Vec3Data> = members.iter().filter_map(|id| positions.get(id).copied()).collect();
+            let centre = mean(&points);
+            let spread = (points.iter().map(|&p| distance_squared(p, centre)).sum::<f32>() / points.len() as f32).sqrt();
+            centres.push(centre);
+            radii.push(spread.max(MIN_MEMBER_SPACING * (members.len() as f32).sqrt()));
+            for &id in members {
+                community.insert(id, c);
+            }
+        }
+
+        let mut links: HashMap<(usize, usize), f32> = HashMap::new();
+        for edge in &graph.edges {
+            if let (Some(&a), Some(&b)) = (community.get(&edge.source), community.get(&edge.target)) {
+                if a != b {
+                    *links.entry((a.min(b), a.max(b))).or_default() += edge.weight.max(0.0);
+                }
+            }
+        }
+        let mut links: Vec<((usize, usize), f32)> = links.into_iter().collect();
+        links.sort_by_key(|&(pair, _)| pair);
+
+        solve_meta_graph(&mut centres, &radii, &links, separation);
+        Self { community, centres, radii, separation, shape: (graph.nodes.len(), graph.edges.len()) }
+    }
+
+    /// Whether this layout still fits `graph` laid out with `separation`
+    pub fn is_current(&self, graph: &GraphData, separation: f32) -> bool {
+        self.separation == separation && self.shape == (graph.nodes.len(), graph.edges.len())
+    }
+
+    /// The community a node belongs to, if any
+    pub fn community_of(&self, id: u32) -> Option<usize> {
+        self.community.get(&id).copied()
+    }
+
+    /// Position and disc radius of community `c`
+    pub fn cluster(&self, c: usize) -> Option<(Vec3Data, f32)> {
+        Some((*self.centres.get(c)?, *self.radii.get(c)?))
+    }
+
+    /// Pulls a node outside its community's disc part of the way back towards it.
+    /// Returns whether it moved.
+    pub fn constrain(&self, node: &mut Node) -> bool {
+        let Some((centre, radius)) = self.community_of(node.id).and_then(|c| self.cluster(c)) else {
+            return false;
+        };
+        let position = node.data.position;
+        let offset = Vec3Data::new(position.x - centre.x, position.y - centre.y, position.z - centre.z);
+        let distance = distance_squared(position, centre).sqrt();
+        if distance <= radius {
+            return false;
+        }
+        let pull = MEMBER_PULL * (distance - radius) / distance;
+        node.data.position = Vec3Data::new(
+            position.x - offset.x * pull,
+            position.y - offset.y * pull,
+            position.z - offset.z * pull,
+        );
+        true
+    }
+}
+
+/// Moves community centres so no two discs come closer than `separation` times
+/// their combined radii and linked ones settle at that distance. The mean
+/// position is kept, so the layout doesn't jump when clustering is turned on.
+fn solve_meta_graph(centres: &mut [Vec3Data], radii: &[f32], links: &[((usize, usize), f32)], separation: f32) {
+    let n = centres.len();
+    if n < 2 {
+        return;
+    }
+    let anchor = mean(centres);
+    let max_link = links.iter().map(|&(_, weight)| weight).fold(0.0f32, f32::max);
+
+    // Coincident centres have no direction to separate along, so spread them on a circle
+    for i in 0..n {
+        if (0..i).any(|j| distance_squared(centres[i], centres[j]) < 1e-6) {
+            let angle = std::f32::consts::TAU * i as f32 / n as f32;
+            centres[i] = Vec3Data::new(
+                centres[i].x + radii[i] * angle.cos(),
+                centres[i].y + radii[i] * angle.sin(),
+                centres[i].z,
+            );
+        }
+    }
+
+    for _ in 0..META_ITERATIONS {
+        let mut shifts = vec![Vec3Data::zero(); n];
+        for i in 0..n {
+            for j in (i + 1)..n {
+                let gap = separation * (radii[i] + radii[j]);
+                let distance = distance_squared(centres[i], centres[j]).sqrt();
+                if distance < gap && distance > 0.0 {
+                    push(&mut shifts, centres, i, j, (gap - distance) / 2.0 / distance);
+                }
+            }
+        }
+        for &((i, j), weight) in links {
+            let gap = separation * (radii[i] + radii[j]);
+            let distance = distance_squared(centres[i], centres[j]).sqrt();
+            if distance > gap && max_link > 0.0 {
+                let pull = META_ATTRACTION * (weight / max_link) * (distance - gap) / 2.0 / distance;
+                push(&mut shifts, centres, i, j, -pull);
+            }
+        }
+        for (centre, shift) in centres.iter_mut().zip(&shifts) {
+            *centre = Vec3Data::new(centre.x + shift.x, centre.y + shift.y, centre.z + shift.z);
+        }
+    }
+
+    let drift = mean(centres);
+    for centre in centres.iter_mut() {
+        *centre = Vec3Data::new(
+            centre.x - drift.x + anchor.x,
+            centre.y - drift.y + anchor.y,
+            centre.z - drift.z + anchor.z,
+        );
+    }
+}
+
+/// Moves `i` and `j` apart along the line between them by `amount` of their
+/// distance each, or together when `amount` is negative
+fn push(shifts: &mut [Vec3Data], centres: &[Vec3Data], i: usize, j: usize, amount: f32) {
+    let dx = (centres[i].x - centres[j].x) * amount;
+    let dy = (centres[i].y - centres[j].y) * amount;
+    let dz = (centres[i].z - centres[j].z) * amount;
+    shifts[i] = Vec3Data::new(shifts[i].x + dx, shifts[i].y + dy, shifts[i].z + dz);
+    shifts[j] = Vec3Data::new(shifts[j].x - dx, shifts[j].y - dy, shifts[j].z - dz);
+}
+
+fn mean(points: &[Vec3Data]) -> Vec3Data {
+    if points.is_empty() {
+        return Vec3Data::zero();
+    }
+    let n = points.len() as f32;
+    Vec3Data::new(
+        points.iter().map(|p| p.x).sum::<f32>() / n,
+        points.iter().map(|p| p.y).sum::<f32>() / n,
+        points.iter().map(|p| p.z).sum::<f32>() / n,
+    )
+}
+
+fn distance_squared(a: Vec3Data, b: Vec3Data) -> f32 {
+    (a.x - b.x).powi(2) + (a.y - b.y).powi(2) + (a.z - b.z).powi(2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::edge::Edge;
+
+    /// Two triangles joined by one edge, plus an unlinked node, all near the origin
+    fn two_triangles() -> GraphData {
+        let mut graph = GraphData::new();
+        graph.nodes = (1..=7).map(|id| {
+            let mut node = Node::new_with_id(id.to_string(), Some(id));
+            node.data.position = Vec3Data::new(id as f32 * 0.1, 0.0, 0.0);
+            node
+        }).collect();
+        graph.edges = [(1, 2), (2, 3), (3, 1), (4, 5), (5, 6), (6, 4), (3, 4)]
+            .iter()
+            .map(|&(s, t)| Edge::new(s, t, 1.0))
+            .collect();
+        graph
+    }
+
+    #[test]
+    fn test_detect_communities() {
+        let graph = two_triangles();
+        let communities = detect_communities(&graph);
+        assert_eq!(communities, vec![vec![1, 2, 3], vec![4, 5, 6]]);
+
+        let mut shuffled = graph.clone();
+        shuffled.nodes.reverse();
+        shuffled.edges.reverse();
+        assert_eq!(detect_communities(&shuffled), communities);
+    }
+
+    #[test]
+    fn test_compound_layout_separates_and_constrains() {
+        let graph = two_triangles();
+        let layout = CompoundLayout::new(&graph, 1.5);
+        assert!(layout.is_current(&graph, 1.5));
+        assert!(!layout.is_current(&graph, 2.0));
+        assert_eq!(layout.community_of(7), None);
+
+        let (a, radius_a) = layout.cluster(layout.community_of(1).unwrap()).unwrap();
+        let (b, radius_b) = layout.cluster(layout.community_of(4).unwrap()).unwrap();
+        assert!(distance_squared(a, b).sqrt() >= 1.5 * (radius_a + radius_b) * 0.99);
+
+        // A member far outside its disc moves back towards it, one inside stays put
+        let mut node = graph.nodes[0].clone();
+        node.data.position = Vec3Data::new(a.x + 100.0, a.y, a.z);
+        assert!(layout.constrain(&mut node));
+        assert!(node.data.position.x < a.x + 100.0);
+        node.data.position = a;
+        assert!(!layout.constrain(&mut node));
+        let mut loner = graph.nodes[6].clone();
+        assert!(!layout.constrain(&mut loner));
+    }
+}
@@ -0,0 +1,280 @@
+//! Chat conversations kept per user
+//!
+//! Each conversation remembers its RAGFlow session and the messages exchanged in
+//! it, with the graph notes an answer was given from as citations, so a chat can
+//! be picked up again after a reconnect and users never share a session. A user's
+//! conversations are stored together in `conversations/<pubkey>.json` under the
+//! data root. Only the newest `MAX_MESSAGES` messages of a conversation are kept.
+
+use chrono::{DateTime, Utc};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+const MAX_CONVERSATIONS_PER_USER: usize = 100;
+const MAX_MESSAGES: usize = 500;
+const TITLE_CHARS: usize = 80;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Role {
+    User,
+    Assistant,
+}
+
+/// A graph note an answer drew on
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Citation {
+    pub node_id: u32,
+    pub title: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChatMessage {
+    pub role: Role,
+    pub content: String,
+    pub created_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub citations: Vec<Citation>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Conversation {
+    pub id: String,
+    pub title: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// RAGFlow session holding the conversation's history on the agent's side
+    #[serde(default)]
+    pub session_id: Option<String>,
+    #[serde(default)]
+    pub messages: Vec<ChatMessage>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversationSummary {
+    pub id: String,
+    pub title: String,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub message_count: usize,
+}
+
+impl From<&Conversation> for ConversationSummary {
+    fn from(conversation: &Conversation) -> Self {
+        Self {
+            id: conversation.id.clone(),
+            title: conversation.title.clone(),
+            created_at: conversation.created_at,
+            updated_at: conversation.updated_at,
+            message_count: conversation.messages.len(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConversationError {
+    NotFound(String),
+    LimitReached,
+    Storage(String),
+}
+
+impl std::fmt::Display for ConversationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConversationError::NotFound(message) | ConversationError::Storage(message) => f.write_str(message),
+            ConversationError::LimitReached => {
+                write!(f, "At most {} conversations can be kept per user", MAX_CONVERSATIONS_PER_USER)
+            }
+        }
+    }
+}
+
+impl From<io::Error> for ConversationError {
+    fn from(e: io::Error) -> Self {
+        ConversationError::Storage(format!("Failed to store conversations: {}", e))
+    }
+}
+
+/// A short title from the first question
+fn title_from(question: &str) -> String {
+    let line = question.lines().map(str::trim).find(|line| !line.is_empty()).unwrap_or_default();
+    if line.chars().count() <= TITLE_CHARS {
+        return line.to_string();
+    }
+    let mut title: String = line.chars().take(TITLE_CHARS - 1).collect();
+    title.push('…');
+    title
+}
+
+pub struct ConversationStore {
+    dir: PathBuf,
+    // Serialises the read-modify-write of a user's file
+    lock: Mutex<()>,
+}
+
+impl ConversationStore {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir, lock: Mutex::new(()) }
+    }
+
+    fn user_file(&self, owner: &str) -> PathBuf {
+        // Nostr pubkeys are hex; anything else is hashed so it can't leave the directory
+        let name = if !owner.is_empty() && owner.chars().all(|c| c.is_ascii_alphanumeric()) {
+            owner.to_string()
+        } else {
+            Sha256::digest(owner.as_bytes()).iter().map(|b| format!("{:02x}", b)).collect()
+        };
+        self.dir.join(format!("{}.json", name))
+    }
+
+    fn load(&self, owner: &str) -> Result<Vec<Conversation>, ConversationError> {
+        match fs::read_to_string(self.user_file(owner)) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| ConversationError::Storage(format!("Unreadable conversations: {}", e))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn save(&self, owner: &str, conversations: &[Conversation]) -> Result<(), ConversationError> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.user_file(owner);
+        let json = serde_json::to_string(conversations).map_err(io::Error::from)?;
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// The owner's conversations, most recently active first
+    pub async fn list(&self, owner: &str) -> Result<Vec<ConversationSummary>, ConversationError> {
+        let _guard = self.lock.lock().await;
+        let mut summaries: Vec<ConversationSummary> = self.load(owner)?.iter().map(ConversationSummary::from).collect();
+        summaries.sort_by_key(|summary| std::cmp::Reverse(summary.updated_at));
+        Ok(summaries)
+    }
+
+    pub async fn get(&self, owner: &str, id: &str) -> Result<Conversation, ConversationError> {
+        let _guard = self.lock.lock().await;
+        self.load(owner)?.into_iter()
+            .find(|conversation| conversation.id == id)
+            .ok_or_else(|| ConversationError::NotFound(format!("Conversation {} not found", id)))
+    }
+
+    /// Starts an empty conversation; without a title, the first question names it
+    pub async fn create(&self, owner: &str, title: Option<&str>) -> Result<Conversation, ConversationError> {
+        let _guard = self.lock.lock().await;
+        let mut conversations = self.load(owner)?;
+        if conversations.len() >= MAX_CONVERSATIONS_PER_USER {
+            return Err(ConversationError::LimitReached);
+        }
+        let now = Utc::now();
+        let conversation = Conversation {
+            id: Uuid::new_v4().to_string(),
+            title: title.map(title_from).unwrap_or_default(),
+            created_at: now,
+            updated_at: now,
+            session_id: None,
+            messages: Vec::new(),
+        };
+        conversations.push(conversation.clone());
+        self.save(owner, &conversations)?;
+        debug!("Created conversation {} for {}", conversation.id, owner);
+        Ok(conversation)
+    }
+
+    pub async fn delete(&self, owner: &str, id: &str) -> Result<(), ConversationError> {
+        let _guard = self.lock.lock().await;
+        let mut conversations = self.load(owner)?;
+        let before = conversations.len();
+        conversations.retain(|conversation| conversation.id != id);
+        if conversations.len() == before {
+            return Err(ConversationError::NotFound(format!("Conversation {} not found", id)));
+        }
+        self.save(owner, &conversations)
+    }
+
+    /// Appends a question and its answer, remembering the RAGFlow session that
+    /// answered it
+    pub async fn record_exchange(
+        &self,
+        owner: &str,
+        id: &str,
+        session_id: &str,
+        question: &str,
+        answer: &str,
+        citations: Vec<Citation>,
+    ) -> Result<Conversation, ConversationError> {
+        let _guard = self.lock.lock().await;
+        let mut conversations = self.load(owner)?;
+        let conversation = conversations.iter_mut()
+            .find(|conversation| conversation.id == id)
+            .ok_or_else(|| ConversationError::NotFound(format!("Conversation {} not found", id)))?;
+
+        let now = Utc::now();
+        if conversation.title.is_empty() {
+            conversation.title = title_from(question);
+        }
+        conversation.session_id = Some(session_id.to_string());
+        conversation.updated_at = now;
+        conversation.messages.push(ChatMessage { role: Role::User, content: question.to_string(), created_at: now, citations: Vec::new() });
+        conversation.messages.push(ChatMessage { role: Role::Assistant, content: answer.to_string(), created_at: now, citations });
+        let excess = conversation.messages.len().saturating_sub(MAX_MESSAGES);
+        conversation.messages.drain(..excess);
+
+        let updated = conversation.clone();
+        self.save(owner, &conversations)?;
+        Ok(updated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_conversation_store() {
+        let dir = std::env::temp_dir().join(format!("conversations_test_{}", Uuid::new_v4()));
+        let store = ConversationStore::new(dir.clone());
+        let alice = "a1b2c3";
+
+        let first = store.create(alice, None).await.unwrap();
+        let second = store.create(alice, Some("Planning")).await.unwrap();
+        assert!(store.list("d4e5f6").await.unwrap().is_empty());
+        assert!(matches!(store.get("d4e5f6", &first.id).await, Err(ConversationError::NotFound(_))));
+
+        let citations = vec![Citation { node_id: 7, title: "Graphs".to_string() }];
+        let updated = store.record_exchange(alice, &first.id, "session-1", "What links to graphs?\nAnd why?", "Trees do.", citations.clone()).await.unwrap();
+        assert_eq!(updated.title, "What links to graphs?");
+        assert_eq!(updated.session_id.as_deref(), Some("session-1"));
+        assert_eq!(updated.messages.len(), 2);
+        assert_eq!(updated.messages[1].role, Role::Assistant);
+        assert_eq!(updated.messages[1].citations, citations);
+
+        // Survives a restart, newest activity first
+        let store = ConversationStore::new(dir.clone());
+        let listed = store.list(alice).await.unwrap();
+        assert_eq!(listed.iter().map(|c| c.id.as_str()).collect::<Vec<_>>(), vec![first.id.as_str(), second.id.as_str()]);
+        assert_eq!(listed[0].message_count, 2);
+        assert_eq!(store.get(alice, &first.id).await.unwrap(), updated);
+
+        store.delete(alice, &first.id).await.unwrap();
+        assert!(matches!(store.delete(alice, &first.id).await, Err(ConversationError::NotFound(_))));
+        assert_eq!(store.list(alice).await.unwrap().len(), 1);
+
+        // Keys that aren't plain hex can't name a path outside the directory
+        assert!(store.user_file("../../etc/passwd").starts_with(&dir));
+        assert_eq!(title_from(&"x".repeat(100)).chars().count(), TITLE_CHARS);
+        let _ = fs::remove_dir_all(dir);
+    }
+}
@@ -0,0 +1,256 @@
+//! Public demo mode
+//!
+//! With `DEMO_MODE=true` the server needs no GitHub credentials. It starts on a
+//! synthetic vault of `DEMO_NODES` pages (500 by default), so people can try the
+//! server and load-test the WebSocket pipeline before pointing it at their own
+//! notes. Pages link to each other by preferential attachment: each new page links
+//! to existing ones with odds growing with how linked they already are, which gives
+//! the power-law degree distribution of real vaults, a few hubs and a long tail.
+//!
+//! Anyone may regenerate the demo graph, so regenerating is limited to
+//! `DEMO_REGENERATIONS_PER_HOUR` times an hour per client address and at most
+//! `DEMO_MAX_NODES` pages.
+
+use chrono::{DateTime, Duration, Utc};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use sha1::{Digest, Sha1};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::sync::Mutex;
+
+use crate::models::metadata::{Metadata, MetadataStore, PageStats};
+use crate::services::file_service::FileService;
+
+const DEFAULT_NODES: usize = 500;
+const DEFAULT_MAX_NODES: usize = 5000;
+const DEFAULT_REGENERATIONS_PER_HOUR: u32 = 10;
+/// Links each new page makes to existing ones
+const LINKS_PER_PAGE: usize = 2;
+// One page in ten is a journal page
+const JOURNAL_SHARE: f64 = 0.1;
+const WORDS_PER_LINK: usize = 40;
+
+const SUBJECTS: &[&str] = &[
+    "Graph", "Vector", "Neural", "Quantum", "Spatial", "Distributed", "Semantic", "Reactive",
+    "Embedded", "Functional", "Probabilistic", "Immersive", "Federated", "Linear", "Topological", "Generative",
+];
+const TOPICS: &[&str] = &[
+    "Databases", "Rendering", "Networks", "Algebra", "Computing", "Search", "Interfaces", "Physics",
+    "Compilers", "Storage", "Learning", "Audio", "Typography", "Robotics", "Cryptography", "Ethics",
+];
+const TAGS: &[&str] = &["research", "reading", "project", "idea", "reference", "meeting", "draft"];
+
+#[derive(Debug, Clone, Copy)]
+pub struct DemoConfig {
+    pub enabled: bool,
+    pub nodes: usize,
+    pub max_nodes: usize,
+    pub regenerations_per_hour: u32,
+}
+
+impl Default for DemoConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            nodes: DEFAULT_NODES,
+            max_nodes: DEFAULT_MAX_NODES,
+            regenerations_per_hour: DEFAULT_REGENERATIONS_PER_HOUR,
+        }
+    }
+}
+
+impl DemoConfig {
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        let number = |name: &str| std::env::var(name).ok().and_then(|v| v.trim().parse::<usize>().ok());
+        let max_nodes = number("DEMO_MAX_NODES").unwrap_or(default.max_nodes).max(1);
+        Self {
+            enabled: std::env::var("DEMO_MODE").is_ok_and(|v| v.trim().eq_ignore_ascii_case("true")),
+            nodes: number("DEMO_NODES").unwrap_or(default.nodes).clamp(1, max_nodes),
+            max_nodes,
+            regenerations_per_hour: number("DEMO_REGENERATIONS_PER_HOUR")
+                .map_or(default.regenerations_per_hour, |n| n as u32),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum DemoError {
+    Disabled,
+    Invalid(String),
+    /// Seconds until the client may regenerate again
+    RateLimited(u64),
+}
+
+impl fmt::Display for DemoError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DemoError::Disabled => write!(f, "Demo mode is off"),
+            DemoError::Invalid(message) => write!(f, "{}", message),
+            DemoError::RateLimited(seconds) => write!(f, "Too many regenerations; retry in {}s", seconds),
+        }
+    }
+}
+
+impl std::error::Error for DemoError {}
+
+/// A distinct, plausible page name for the `index`th page
+fn page_name(rng: &mut StdRng, index: usize, taken: &mut HashSet<String>, now: DateTime<Utc>) -> String {
+    if index > 0 && rng.gen_bool(JOURNAL_SHARE) {
+        let day = now - Duration::days(rng.gen_range(0..730));
+        let name = day.format("%Y_%m_%d").to_string();
+        if taken.insert(name.clone()) {
+            return name;
+        }
+    }
+    let base = format!("{} {}", SUBJECTS.choose(rng).unwrap(), TOPICS.choose(rng).unwrap());
+    let mut name = base.clone();
+    let mut n = 2;
+    while !taken.insert(name.clone()) {
+        name = format!("{} {}", base, n);
+        n += 1;
+    }
+    name
+}
+
+/// A synthetic vault of `nodes` linked pages. The same seed gives the same vault.
+pub fn generate(nodes: usize, seed: u64, now: DateTime<Utc>) -> MetadataStore {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let mut taken = HashSet::new();
+    let names: Vec<String> = (0..nodes).map(|i| page_name(&mut rng, i, &mut taken, now)).collect();
+
+    // Each page once per link end, so picking from it favours linked pages
+    let mut ends: Vec<usize> = Vec::new();
+    let mut links: Vec<HashMap<usize, usize>> = vec![HashMap::new(); nodes];
+    for (page, out) in links.iter_mut().enumerate().skip(1) {
+        // Ordered, so the same seed draws the same numbers
+        let mut targets = BTreeSet::new();
+        while targets.len() < LINKS_PER_PAGE.min(page) {
+            let target = if ends.is_empty() || rng.gen_bool(0.1) {
+                rng.gen_range(0..page)
+            } else {
+                ends[rng.gen_range(0..ends.len())]
+            };
+            targets.insert(target);
+        }
+        for target in targets {
+            // Repeated mentions make some links heavier
+            *out.entry(target).or_default() += rng.gen_range(1..=3);
+            ends.extend([page, target]);
+        }
+    }
+
+    names.iter().enumerate()
+        .map(|(i, name)| {
+            let topic_counts: HashMap<String, usize> = links[i].iter()
+                .map(|(target, count)| (names[*target].clone(), *count))
+                .collect();
+            let mentions: usize = topic_counts.values().sum();
+            let word_count = rng.gen_range(50..400) + WORDS_PER_LINK * mentions;
+            let file_size = word_count * 6;
+            let file_name = format!("{}.md", name);
+            let tag_count = rng.gen_range(0..3);
+            let metadata = Metadata {
+                file_name: file_name.clone(),
+                file_size,
+                node_size: FileService::calculate_node_size(file_size),
+                hyperlink_count: rng.gen_range(0..5),
+                sha1: format!("{:x}", Sha1::digest(format!("{}:{}", seed, name).as_bytes())),
+                node_id: i.to_string(),
+                last_modified: now - Duration::minutes(rng.gen_range(0..525_600)),
+                topic_counts,
+                tags: TAGS.choose_multiple(&mut rng, tag_count).map(|tag| tag.to_string()).collect(),
+                page_stats: PageStats {
+                    word_count,
+                    reading_time_minutes: word_count.div_ceil(200) as u32,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            (file_name, metadata)
+        })
+        .collect()
+}
+
+/// Demo mode settings and the regeneration rate limit
+pub struct DemoService {
+    config: DemoConfig,
+    /// Regenerations per client address in the current hour
+    rate: Mutex<(i64, HashMap<String, u32>)>,
+}
+
+impl DemoService {
+    pub fn new(config: DemoConfig) -> Self {
+        Self { config, rate: Mutex::new((0, HashMap::new())) }
+    }
+
+    pub fn config(&self) -> DemoConfig {
+        self.config
+    }
+
+    /// Counts a regeneration of `nodes` pages by `client` against its limit and
+    /// returns the number of pages to generate
+    pub fn allow_regenerate(&self, client: &str, nodes: Option<usize>) -> Result<usize, DemoError> {
+        self.allow_regenerate_at(client, nodes, Utc::now())
+    }
+
+    fn allow_regenerate_at(&self, client: &str, nodes: Option<usize>, now: DateTime<Utc>) -> Result<usize, DemoError> {
+        if !self.config.enabled {
+            return Err(DemoError::Disabled);
+        }
+        let nodes = nodes.unwrap_or(self.config.nodes);
+        if nodes == 0 || nodes > self.config.max_nodes {
+            return Err(DemoError::Invalid(format!("nodes must be 1 to {}", self.config.max_nodes)));
+        }
+        let hour = now.timestamp().div_euclid(3600);
+        let mut rate = self.rate.lock().unwrap_or_else(|e| e.into_inner());
+        if rate.0 != hour {
+            *rate = (hour, HashMap::new());
+        }
+        let done = rate.1.entry(client.to_string()).or_insert(0);
+        if *done >= self.config.regenerations_per_hour {
+            return Err(DemoError::RateLimited((3600 - now.timestamp().rem_euclid(3600)) as u64));
+        }
+        *done += 1;
+        Ok(nodes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_demo() {
+        let now = Utc::now();
+        let vault = generate(1000, 7, now);
+        assert_eq!(vault.len(), 1000);
+        assert_eq!(generate(1000, 7, now).keys().collect::<HashSet<_>>(), vault.keys().collect::<HashSet<_>>());
+        // Every link points at a page of the vault
+        assert!(vault.values().flat_map(|page| page.topic_counts.keys())
+            .all(|target| vault.contains_key(&format!("{}.md", target))));
+
+        let mut degree: HashMap<&str, usize> = HashMap::new();
+        for (file_name, page) in &vault {
+            for target in page.topic_counts.keys() {
+                *degree.entry(file_name.trim_end_matches(".md")).or_default() += 1;
+                *degree.entry(target.as_str()).or_default() += 1;
+            }
+        }
+        let max = degree.values().max().copied().unwrap_or(0);
+        let mean = degree.values().sum::<usize>() as f64 / degree.len() as f64;
+        // Hubs far above the mean, as in a power law
+        assert!(max as f64 > 8.0 * mean, "max degree {} against a mean of {}", max, mean);
+
+        let service = DemoService::new(DemoConfig { enabled: true, regenerations_per_hour: 1, ..Default::default() });
+        let at = Utc.with_ymd_and_hms(2024, 3, 1, 10, 30, 0).unwrap();
+        assert_eq!(service.allow_regenerate_at("10.0.0.1", Some(50), at), Ok(50));
+        assert_eq!(service.allow_regenerate_at("10.0.0.1", None, at), Err(DemoError::RateLimited(1800)));
+        assert_eq!(service.allow_regenerate_at("10.0.0.2", None, at), Ok(DEFAULT_NODES));
+        assert!(matches!(service.allow_regenerate_at("10.0.0.3", Some(DEFAULT_MAX_NODES + 1), at), Err(DemoError::Invalid(_))));
+        assert_eq!(DemoService::new(DemoConfig::default()).allow_regenerate("10.0.0.1", None), Err(DemoError::Disabled));
+    }
+}
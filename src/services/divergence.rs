@@ -0,0 +1,127 @@
+//! Catching a layout that has blown up
+//!
+//! A time step too large for the forces, or a pair of nodes landing on each other,
+//! can send nodes off to infinity or turn their velocities into NaN, after which
+//! every frame streamed to clients is garbage. The simulation loop checks each step
+//! for nodes outside `DIVERGENCE_FACTOR` times the bounds or with non-finite
+//! values. Runaway nodes are pulled back to the bounds and broken ones put back on
+//! the start sphere; if most of the graph has gone, the whole layout starts again.
+
+use serde::Serialize;
+
+use crate::models::graph::GraphData;
+use crate::models::simulation_params::SimulationParams;
+use crate::services::graph_validation;
+use crate::types::vec3::Vec3Data;
+use crate::utils::socket_flow_messages::BinaryNodeData;
+
+/// How far past the bounds a node may drift before it counts as diverged
+pub const DIVERGENCE_FACTOR: f32 = 100.0;
+// Share of diverged nodes above which the whole layout is reset
+const RESET_ALL_FRACTION: f32 = 0.5;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DivergenceReport {
+    /// Nodes that had diverged, all of them when `reset_all` is set
+    pub node_ids: Vec<u32>,
+    /// Nodes with NaN or infinite positions or velocities, among `node_ids`
+    pub non_finite: usize,
+    pub reset_all: bool,
+    /// Distance from the origin along any axis past which a node had diverged
+    pub limit: f32,
+}
+
+pub fn position_limit(params: &SimulationParams) -> f32 {
+    params.viewport_bounds.max(1.0) * DIVERGENCE_FACTOR
+}
+
+fn is_finite(data: &BinaryNodeData) -> bool {
+    [data.position, data.velocity].iter().all(|v| v.x.is_finite() && v.y.is_finite() && v.z.is_finite())
+}
+
+fn is_diverged(data: &BinaryNodeData, limit: f32) -> bool {
+    let p = data.position;
+    !is_finite(data) || p.x.abs() > limit || p.y.abs() > limit || p.z.abs() > limit
+}
+
+/// Brings diverged nodes in `graph` back, returning what was done, or `None` if
+/// every node was fine. Velocities of moved nodes are zeroed.
+pub fn recover(graph: &mut GraphData, params: &SimulationParams) -> Option<DivergenceReport> {
+    let limit = position_limit(params);
+    let diverged: Vec<usize> = graph.nodes.iter()
+        .enumerate()
+        .filter(|(_, node)| is_diverged(&node.data, limit))
+        .map(|(i, _)| i)
+        .collect();
+    if diverged.is_empty() {
+        return None;
+    }
+    let non_finite = diverged.iter().filter(|&&i| !is_finite(&graph.nodes[i].data)).count();
+
+    let reset_all = diverged.len() as f32 > graph.nodes.len() as f32 * RESET_ALL_FRACTION;
+    let node_ids = if reset_all {
+        for node in &mut graph.nodes {
+            node.data.position = graph_validation::reset_position(node.id);
+            node.data.velocity = Vec3Data::zero();
+            params.constrain(&mut node.data);
+        }
+        graph.nodes.iter().map(|node| node.id).collect()
+    } else {
+        let bound = params.viewport_bounds.max(1.0);
+        for &i in &diverged {
+            let node = &mut graph.nodes[i];
+            node.data.position = if is_finite(&node.data) {
+                let p = node.data.position;
+                Vec3Data::new(p.x.clamp(-bound, bound), p.y.clamp(-bound, bound), p.z.clamp(-bound, bound))
+            } else {
+                graph_validation::reset_position(node.id)
+            };
+            node.data.velocity = Vec3Data::zero();
+            params.constrain(&mut node.data);
+        }
+        diverged.iter().map(|&i| graph.nodes[i].id).collect()
+    };
+
+    Some(DivergenceReport { node_ids, non_finite, reset_all, limit })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::node::Node;
+
+    fn graph(positions: &[(f32, f32)]) -> GraphData {
+        let mut graph = GraphData::new();
+        graph.nodes = positions.iter().enumerate()
+            .map(|(i, &(x, vx))| Node::new_with_id(format!("page-{}", i), Some(i as u32 + 1))
+                .with_position(x, 0.0, 0.0)
+                .with_velocity(vx, 0.0, 0.0))
+            .collect();
+        graph
+    }
+
+    #[test]
+    fn test_recover() {
+        let params = SimulationParams { viewport_bounds: 10.0, ..SimulationParams::new() };
+        let mut settled = graph(&[(1.0, 0.1), (-5.0, 0.0), (900.0, 2.0)]);
+        assert_eq!(recover(&mut settled, &params), None);
+
+        // A runaway node is clamped to the bounds and a NaN one reinitialised
+        let mut broken = graph(&[(1.0, 0.1), (5e4, 3.0), (0.0, f32::NAN), (2.0, 0.0), (-3.0, 0.0)]);
+        let report = recover(&mut broken, &params).unwrap();
+        assert_eq!(report, DivergenceReport { node_ids: vec![2, 3], non_finite: 1, reset_all: false, limit: 1000.0 });
+        assert_eq!(broken.nodes[1].data.position.x, 10.0);
+        assert_eq!(broken.nodes[1].data.velocity, Vec3Data::zero());
+        assert_eq!(broken.nodes[2].data.position, graph_validation::reset_position(3));
+        assert_eq!(broken.nodes[0].data.velocity.x, 0.1);
+
+        // Most of the graph gone starts the whole layout again
+        let mut exploded = graph(&[(f32::INFINITY, 0.0), (-2e5, 0.0), (1.0, 0.5)]);
+        let report = recover(&mut exploded, &params).unwrap();
+        assert!(report.reset_all);
+        assert_eq!(report.node_ids, vec![1, 2, 3]);
+        assert_eq!(exploded.nodes[2].data.position, graph_validation::reset_position(3));
+        assert!(exploded.nodes.iter().all(|node| node.data.velocity == Vec3Data::zero()));
+    }
+}
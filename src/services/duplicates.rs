@@ -0,0 +1,191 @@
+//! Near-duplicate page detection
+//!
+//! Pages are compared by their sets of three-word shingles. MinHash signatures with
+//! locality-sensitive banding pick candidate pairs without comparing every page
+//! with every other, and candidates are then checked by exact Jaccard similarity,
+//! so only pairs at or above the threshold are reported.
+
+use log::{debug, info};
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::env;
+use std::hash::{Hash, Hasher};
+
+use crate::config::data_dirs::DataDirs;
+use crate::models::metadata::MetadataStore;
+use crate::services::vault_crypto;
+
+/// `edge_type` of the edges joining near-duplicate pages
+pub const DUPLICATE_EDGE_TYPE: &str = "duplicate";
+pub const DEFAULT_THRESHOLD: f32 = 0.8;
+
+const SHINGLE_WORDS: usize = 3;
+const BANDS: usize = 32;
+const ROWS_PER_BAND: usize = 4;
+const SIGNATURE_LEN: usize = BANDS * ROWS_PER_BAND;
+// Shorter pages share boilerplate too easily to call them duplicates
+const MIN_SHINGLES: usize = 8;
+
+/// `DUPLICATE_SIMILARITY_THRESHOLD`, or the default of 0.8
+pub fn threshold_from_env() -> f32 {
+    env::var("DUPLICATE_SIMILARITY_THRESHOLD").ok()
+        .and_then(|value| value.trim().parse::<f32>().ok())
+        .filter(|threshold| (0.0..=1.0).contains(threshold))
+        .unwrap_or(DEFAULT_THRESHOLD)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicatePair {
+    pub first: String,
+    pub second: String,
+    pub similarity: f32,
+}
+
+fn splitmix64(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+fn shingles(content: &str) -> HashSet<u64> {
+    let words: Vec<String> = content.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    words.windows(SHINGLE_WORDS)
+        .map(|window| {
+            let mut hasher = DefaultHasher::new();
+            window.hash(&mut hasher);
+            hasher.finish()
+        })
+        .collect()
+}
+
+fn signature(shingles: &HashSet<u64>) -> Vec<u64> {
+    (0..SIGNATURE_LEN as u64)
+        .map(|i| {
+            let seed = splitmix64(i);
+            shingles.iter().map(|shingle| splitmix64(shingle ^ seed)).min().unwrap_or(u64::MAX)
+        })
+        .collect()
+}
+
+fn jaccard(a: &HashSet<u64>, b: &HashSet<u64>) -> f32 {
+    let shared = a.intersection(b).count();
+    shared as f32 / (a.len() + b.len() - shared) as f32
+}
+
+/// Pairs of `pages` (name and content) at least `threshold` similar, most similar
+/// first
+pub fn find_duplicates(pages: &[(String, String)], threshold: f32) -> Vec<DuplicatePair> {
+    let sets: Vec<(usize, HashSet<u64>)> = pages.iter().enumerate()
+        .map(|(i, (_, content))| (i, shingles(content)))
+        .filter(|(_, set)| set.len() >= MIN_SHINGLES)
+        .collect();
+
+    let mut buckets: HashMap<(usize, u64), Vec<usize>> = HashMap::new();
+    for (position, (_, set)) in sets.iter().enumerate() {
+        let signature = signature(set);
+        for (band, rows) in signature.chunks(ROWS_PER_BAND).enumerate() {
+            let mut hasher = DefaultHasher::new();
+            rows.hash(&mut hasher);
+            buckets.entry((band, hasher.finish())).or_default().push(position);
+        }
+    }
+    let candidates: BTreeSet<(usize, usize)> = buckets.values()
+        .flat_map(|members| {
+            members.iter().enumerate()
+                .flat_map(move |(i, &a)| members[i + 1..].iter().map(move |&b| (a.min(b), a.max(b))))
+        })
+        .collect();
+    debug!("Checking {} candidate duplicate pairs among {} pages", candidates.len(), sets.len());
+
+    let mut pairs: Vec<DuplicatePair> = candidates.into_iter()
+        .filter_map(|(a, b)| {
+            let similarity = jaccard(&sets[a].1, &sets[b].1);
+            (similarity >= threshold).then(|| DuplicatePair {
+                first: pages[sets[a].0].0.clone(),
+                second: pages[sets[b].0].0.clone(),
+                similarity: (similarity * 100.0).round() / 100.0,
+            })
+        })
+        .collect();
+    pairs.sort_by(|a, b| b.similarity.total_cmp(&a.similarity).then_with(|| a.first.cmp(&b.first)));
+    pairs
+}
+
+/// Flags near duplicates among the files of `metadata_store`, replacing earlier
+/// results, and returns the pairs found
+pub fn detect(metadata_store: &mut MetadataStore, threshold: f32) -> Vec<DuplicatePair> {
+    let dirs = DataDirs::global();
+    let mut pages: Vec<(String, String)> = metadata_store.keys()
+        .filter_map(|file_name| {
+            let content = vault_crypto::read_to_string(dirs.markdown_file(file_name)).ok()?;
+            Some((file_name.clone(), content))
+        })
+        .collect();
+    pages.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+
+    let pairs = find_duplicates(&pages, threshold);
+    for metadata in metadata_store.values_mut() {
+        metadata.near_duplicates.clear();
+    }
+    for pair in &pairs {
+        if let Some(metadata) = metadata_store.get_mut(&pair.first) {
+            metadata.near_duplicates.insert(pair.second.clone(), pair.similarity);
+        }
+        if let Some(metadata) = metadata_store.get_mut(&pair.second) {
+            metadata.near_duplicates.insert(pair.first.clone(), pair.similarity);
+        }
+    }
+    if !pairs.is_empty() {
+        info!("Found {} pairs of near-duplicate pages", pairs.len());
+    }
+    pairs
+}
+
+/// The pairs recorded in `metadata_store`, most similar first
+pub fn recorded_pairs(metadata_store: &MetadataStore) -> Vec<DuplicatePair> {
+    let mut pairs: Vec<DuplicatePair> = metadata_store.iter()
+        .flat_map(|(file_name, metadata)| {
+            metadata.near_duplicates.iter()
+                .filter(move |(other, _)| file_name < *other)
+                .map(move |(other, similarity)| DuplicatePair {
+                    first: file_name.clone(),
+                    second: other.clone(),
+                    similarity: *similarity,
+                })
+        })
+        .collect();
+    pairs.sort_by(|a, b| b.similarity.total_cmp(&a.similarity).then_with(|| a.first.cmp(&b.first)));
+    pairs
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_duplicates() {
+        let original = "Rust is a systems programming language focused on safety, speed and concurrency. \
+            It prevents data races at compile time and has no garbage collector, which makes it a good fit \
+            for embedded work, web servers and game engines alike.";
+        let edited = original.replace("game engines", "game consoles");
+        let pages = vec![
+            ("Rust.md".to_string(), original.to_string()),
+            ("Rust copy.md".to_string(), edited),
+            ("Cooking.md".to_string(), "Bring the water to a boil, add salt, then cook the pasta for nine minutes before draining it and tossing it with the sauce.".to_string()),
+            ("Stub.md".to_string(), "Rust is a".to_string()),
+            ("Stub 2.md".to_string(), "Rust is a".to_string()),
+        ];
+
+        let pairs = find_duplicates(&pages, 0.8);
+        assert_eq!(pairs.len(), 1);
+        assert_eq!((pairs[0].first.as_str(), pairs[0].second.as_str()), ("Rust.md", "Rust copy.md"));
+        assert!(pairs[0].similarity >= 0.8 && pairs[0].similarity < 1.0);
+        assert!(find_duplicates(&pages, 0.99).is_empty());
+    }
+}
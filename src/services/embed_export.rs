@@ -0,0 +1,177 @@
+//! Static bundles for embedding a snapshot of the graph
+//!
+//! The bundle is a zip of `graph.json` (nodes at their current positions and the
+//! edges between them), `viewer.json` (title, colours and sizes from the
+//! visualisation settings) and an `index.html` that draws the two on a canvas.
+//! Unzipped onto any static host it can be put in an iframe, and it never talks
+//! to this server.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::io::{Cursor, Write};
+use zip::write::SimpleFileOptions;
+use zip::CompressionMethod;
+
+use crate::config::VisualisationSettings;
+use crate::models::graph::GraphData;
+
+const VIEWER_HTML: &str = include_str!("embed_viewer.html");
+pub const DEFAULT_TITLE: &str = "Knowledge graph";
+const MAX_TITLE_CHARS: usize = 200;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbedNode {
+    pub id: u32,
+    pub label: String,
+    /// Page the node stands for, without `.md`
+    pub page: String,
+    #[serde(rename = "type", skip_serializing_if = "Option::is_none")]
+    pub node_type: Option<String>,
+    pub position: [f32; 3],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbedEdge {
+    pub source: u32,
+    pub target: u32,
+    pub weight: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbedGraph {
+    pub nodes: Vec<EmbedNode>,
+    pub edges: Vec<EmbedEdge>,
+}
+
+impl EmbedGraph {
+    /// Only what a viewer draws: no page metadata leaves the server
+    pub fn of(graph: &GraphData) -> Self {
+        let nodes = graph.nodes.iter()
+            .map(|node| EmbedNode {
+                id: node.id,
+                label: node.label.clone(),
+                page: node.metadata_id.clone(),
+                node_type: node.node_type.clone(),
+                position: [node.data.position.x, node.data.position.y, node.data.position.z],
+                color: node.color.clone(),
+            })
+            .collect();
+        let edges = graph.edges.iter()
+            .map(|edge| EmbedEdge { source: edge.source, target: edge.target, weight: edge.weight })
+            .collect();
+        Self { nodes, edges }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ViewerConfig {
+    pub title: String,
+    pub background_color: String,
+    pub node_color: String,
+    /// Node radius in graph units
+    pub node_size: f32,
+    pub edge_color: String,
+    pub edge_opacity: f32,
+    pub show_labels: bool,
+    pub label_color: String,
+    pub exported_at: DateTime<Utc>,
+}
+
+impl Default for ViewerConfig {
+    fn default() -> Self {
+        Self {
+            title: DEFAULT_TITLE.to_string(),
+            background_color: "#000000".to_string(),
+            node_color: "#66d9ef".to_string(),
+            node_size: 0.1,
+            edge_color: "#56b6c2".to_string(),
+            edge_opacity: 0.25,
+            show_labels: false,
+            label_color: "#ffffff".to_string(),
+            exported_at: Utc::now(),
+        }
+    }
+}
+
+/// `value` if it is a `#rgb` or `#rrggbb` colour, so settings can't inject markup
+/// into the viewer
+fn hex_color(value: &str, default: String) -> String {
+    let digits = value.trim().strip_prefix('#').unwrap_or_default();
+    if matches!(digits.len(), 3 | 6) && digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        value.trim().to_string()
+    } else {
+        default
+    }
+}
+
+impl ViewerConfig {
+    pub fn from_settings(vis: &VisualisationSettings, title: Option<&str>) -> Self {
+        let default = Self::default();
+        let title = title.map(str::trim).filter(|title| !title.is_empty())
+            .map(|title| title.chars().take(MAX_TITLE_CHARS).collect())
+            .unwrap_or(default.title);
+        Self {
+            title,
+            background_color: hex_color(&vis.rendering.background_color, default.background_color),
+            node_color: hex_color(&vis.nodes.base_color, default.node_color),
+            node_size: if vis.nodes.node_size > 0.0 { vis.nodes.node_size } else { default.node_size },
+            edge_color: hex_color(&vis.edges.color, default.edge_color),
+            edge_opacity: if vis.edges.opacity > 0.0 { vis.edges.opacity.min(1.0) } else { default.edge_opacity },
+            show_labels: vis.labels.enable_labels,
+            label_color: hex_color(&vis.labels.text_color, default.label_color),
+            exported_at: default.exported_at,
+        }
+    }
+}
+
+/// Zips `graph` and `viewer` together with the viewer page
+pub fn export_bundle(graph: &GraphData, viewer: &ViewerConfig) -> Result<Vec<u8>, zip::result::ZipError> {
+    let options = SimpleFileOptions::default().compression_method(CompressionMethod::Deflated);
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    zip.start_file("index.html", options)?;
+    zip.write_all(VIEWER_HTML.as_bytes())?;
+    zip.start_file("viewer.json", options)?;
+    zip.write_all(&serde_json::to_vec_pretty(viewer).map_err(std::io::Error::from)?)?;
+    zip.start_file("graph.json", options)?;
+    zip.write_all(&serde_json::to_vec(&EmbedGraph::of(graph)).map_err(std::io::Error::from)?)?;
+    Ok(zip.finish()?.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::edge::Edge;
+    use crate::models::node::Node;
+    use std::io::Read;
+
+    #[test]
+    fn test_bundle_contents() {
+        let mut graph = GraphData::new();
+        let mut node = Node::new_with_id("Rust".to_string(), Some(1));
+        node.data.position.x = 2.0;
+        graph.nodes.push(node);
+        graph.nodes.push(Node::new_with_id("WebXR".to_string(), Some(2)));
+        graph.edges.push(Edge::new(1, 2, 0.5));
+        let viewer = ViewerConfig { background_color: hex_color("red\"><script>", "#000000".to_string()), ..Default::default() };
+        assert_eq!(viewer.background_color, "#000000");
+
+        let bundle = export_bundle(&graph, &viewer).unwrap();
+        let mut archive = zip::ZipArchive::new(Cursor::new(bundle)).unwrap();
+        let mut names: Vec<&str> = archive.file_names().collect();
+        names.sort();
+        assert_eq!(names, vec!["graph.json", "index.html", "viewer.json"]);
+
+        let mut json = String::new();
+        archive.by_name("graph.json").unwrap().read_to_string(&mut json).unwrap();
+        let exported: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(exported["nodes"][0]["position"], serde_json::json!([2.0, 0.0, 0.0]));
+        assert_eq!(exported["edges"][0], serde_json::json!({ "source": 1, "target": 2, "weight": 0.5 }));
+        assert!(exported["nodes"][0].get("metadata").is_none());
+    }
+}
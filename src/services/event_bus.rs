@@ -0,0 +1,209 @@
+//! Internal event bus
+//!
+//! A typed broadcast channel shared through `AppState`. Services publish lifecycle
+//! events (graph rebuilt, files processed, settings changed) without knowing who is
+//! listening; transports such as `SocketFlowServer` subscribe and forward them.
+
+use crate::services::activity::ActivityEntry;
+use crate::services::comments::Comment;
+use crate::services::job_queue::JobStatus;
+use crate::services::world_bounds::WorldBounds;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use tokio::sync::broadcast;
+use log::trace;
+
+// Enough headroom for a burst of file events during a full sync
+const DEFAULT_CAPACITY: usize = 256;
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum GraphEvent {
+    #[serde(rename_all = "camelCase")]
+    Rebuilt { node_count: usize, edge_count: usize },
+    #[serde(rename_all = "camelCase")]
+    Updated { node_count: usize, edge_count: usize },
+    /// Node colours by id from the colour mapper; nodes left out use the base colour
+    Restyle { colors: BTreeMap<u32, String> },
+    /// The world bounds changed; clients rescale their environment and, when
+    /// receiving quantized positions, decode later frames with the new extent
+    Bounds(WorldBounds),
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum FileEvent {
+    #[serde(rename_all = "camelCase")]
+    Processed { file_names: Vec<String> },
+    /// Outcome of a GitHub sync run, whether it changed anything or not
+    #[serde(rename_all = "camelCase")]
+    SyncReport {
+        scheduled: bool,
+        file_names: Vec<String>,
+        /// Files among `file_names` that weren't in the vault before
+        added_files: Vec<String>,
+        /// Files gone from the repository that were moved to the trash
+        removed_files: Vec<String>,
+        /// False when a scheduled run found nothing new and left the graph alone
+        graph_rebuilt: bool,
+        duration_ms: u64,
+        error: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum SettingsEvent {
+    /// Global (power user) settings changed
+    #[serde(rename_all = "camelCase")]
+    GlobalUpdated { pubkey: String },
+    /// A regular user's personal settings changed
+    #[serde(rename_all = "camelCase")]
+    UserUpdated { pubkey: String },
+}
+
+/// Progress of the Perplexity enrichment job
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum EnrichmentEvent {
+    #[serde(rename_all = "camelCase")]
+    Started { total: usize },
+    #[serde(rename_all = "camelCase")]
+    Progress { processed: usize, total: usize, file_name: String, success: bool },
+    #[serde(rename_all = "camelCase")]
+    Completed { enriched: usize, failed: usize },
+    /// Stopped early because the day's AI budget is spent
+    #[serde(rename_all = "camelCase")]
+    Paused { processed: usize, total: usize, reason: String },
+}
+
+/// Lifecycle and progress of jobs submitted to the `JobQueue`
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum JobEvent {
+    #[serde(rename_all = "camelCase")]
+    StatusChanged { id: String, job_kind: String, status: JobStatus, error: Option<String> },
+    #[serde(rename_all = "camelCase")]
+    Progress { id: String, current: usize, total: usize, message: Option<String> },
+}
+
+/// Changes to the comment threads on nodes
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum CommentEvent {
+    #[serde(rename_all = "camelCase")]
+    Added { comment: Comment },
+    #[serde(rename_all = "camelCase")]
+    Edited { comment: Comment },
+    #[serde(rename_all = "camelCase")]
+    Deleted { id: String, node: String },
+}
+
+/// Nodes entering or leaving a saved filter after the graph changed
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum FilterEvent {
+    #[serde(rename_all = "camelCase")]
+    MembershipChanged { filter_id: String, owner: String, entered: Vec<String>, left: Vec<String> },
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "topic", content = "event", rename_all = "camelCase")]
+pub enum AppEvent {
+    Graph(GraphEvent),
+    File(FileEvent),
+    Settings(SettingsEvent),
+    Enrichment(EnrichmentEvent),
+    Job(JobEvent),
+    /// A new entry in the activity feed
+    Activity(ActivityEntry),
+    Comment(CommentEvent),
+    Filter(FilterEvent),
+}
+
+impl From<GraphEvent> for AppEvent {
+    fn from(event: GraphEvent) -> Self {
+        AppEvent::Graph(event)
+    }
+}
+
+impl From<FileEvent> for AppEvent {
+    fn from(event: FileEvent) -> Self {
+        AppEvent::File(event)
+    }
+}
+
+impl From<SettingsEvent> for AppEvent {
+    fn from(event: SettingsEvent) -> Self {
+        AppEvent::Settings(event)
+    }
+}
+
+impl From<EnrichmentEvent> for AppEvent {
+    fn from(event: EnrichmentEvent) -> Self {
+        AppEvent::Enrichment(event)
+    }
+}
+
+impl From<ActivityEntry> for AppEvent {
+    fn from(entry: ActivityEntry) -> Self {
+        AppEvent::Activity(entry)
+    }
+}
+
+impl From<CommentEvent> for AppEvent {
+    fn from(event: CommentEvent) -> Self {
+        AppEvent::Comment(event)
+    }
+}
+
+impl From<FilterEvent> for AppEvent {
+    fn from(event: FilterEvent) -> Self {
+        AppEvent::Filter(event)
+    }
+}
+
+impl From<JobEvent> for AppEvent {
+    fn from(event: JobEvent) -> Self {
+        AppEvent::Job(event)
+    }
+}
+
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<AppEvent>,
+}
+
+impl EventBus {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+
+    /// Publish an event to all current subscribers. Returns the number of receivers
+    /// the event was delivered to; having none is not an error.
+    pub fn publish(&self, event: impl Into<AppEvent>) -> usize {
+        let event = event.into();
+        match self.sender.send(event) {
+            Ok(receivers) => receivers,
+            Err(broadcast::error::SendError(event)) => {
+                trace!("[EventBus] No subscribers for {:?}", event);
+                0
+            }
+        }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<AppEvent> {
+        self.sender.subscribe()
+    }
+
+    pub fn subscriber_count(&self) -> usize {
+        self.sender.receiver_count()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
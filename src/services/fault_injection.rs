@@ -0,0 +1,218 @@
+//! Fault injection for resilience testing
+//!
+//! Debug builds can make the GitHub and GPU dependencies fail at a given rate and
+//! respond after a given delay, so the fallbacks, retries and partial sync recovery
+//! get exercised without a flaky network or a broken driver:
+//!
+//! - `FAULT_GITHUB_ERROR_RATE` / `FAULT_GITHUB_LATENCY_MS` for every repository call
+//! - `FAULT_GPU_ERROR_RATE` / `FAULT_GPU_LATENCY_MS` for GPU initialisation and each
+//!   force computation
+//! - `FAULT_SEED` to make which calls fail repeatable
+//!
+//! Release builds ignore these variables. Tests wrap a repository in
+//! `FaultyGitHub` directly and change the rates while they run.
+
+use async_trait::async_trait;
+use log::{debug, warn};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::error::Error;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use crate::services::github::{ConditionalContent, GitHubError, GitHubFileMetadata, GitHubService};
+
+/// How one dependency misbehaves
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FaultConfig {
+    /// Share of calls that fail, from 0 to 1
+    pub error_rate: f64,
+    /// Delay added to every call
+    pub latency: Duration,
+}
+
+impl FaultConfig {
+    pub fn new(error_rate: f64, latency: Duration) -> Self {
+        Self { error_rate: error_rate.clamp(0.0, 1.0), latency }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.error_rate > 0.0 || !self.latency.is_zero()
+    }
+
+    fn from_env(prefix: &str) -> Self {
+        let error_rate = std::env::var(format!("{}_ERROR_RATE", prefix)).ok()
+            .and_then(|v| v.trim().parse::<f64>().ok())
+            .unwrap_or(0.0);
+        let latency = std::env::var(format!("{}_LATENCY_MS", prefix)).ok()
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .unwrap_or(0);
+        Self::new(error_rate, Duration::from_millis(latency))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FaultInjectionConfig {
+    pub github: FaultConfig,
+    pub gpu: FaultConfig,
+    /// Seed for deciding which calls fail; random when not given
+    pub seed: Option<u64>,
+}
+
+impl FaultInjectionConfig {
+    /// Faults from the `FAULT_*` variables; none at all in release builds
+    pub fn from_env() -> Self {
+        let config = Self {
+            github: FaultConfig::from_env("FAULT_GITHUB"),
+            gpu: FaultConfig::from_env("FAULT_GPU"),
+            seed: std::env::var("FAULT_SEED").ok().and_then(|v| v.trim().parse().ok()),
+        };
+        if !config.github.is_active() && !config.gpu.is_active() {
+            return Self::default();
+        }
+        if !cfg!(debug_assertions) {
+            warn!("Ignoring FAULT_* settings: fault injection is only available in debug builds");
+            return Self::default();
+        }
+        warn!("Fault injection is on: GitHub {:?}, GPU {:?}", config.github, config.gpu);
+        config
+    }
+}
+
+/// Decides which calls to a dependency fail, and delays them
+#[derive(Debug)]
+pub struct FaultInjector {
+    config: RwLock<FaultConfig>,
+    rng: Mutex<StdRng>,
+    injected: AtomicU64,
+}
+
+impl FaultInjector {
+    pub fn new(config: FaultConfig, seed: Option<u64>) -> Self {
+        Self {
+            config: RwLock::new(config),
+            rng: Mutex::new(seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64)),
+            injected: AtomicU64::new(0),
+        }
+    }
+
+    pub fn config(&self) -> FaultConfig {
+        *self.config.read().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn set_config(&self, config: FaultConfig) {
+        *self.config.write().unwrap_or_else(|e| e.into_inner()) = config;
+    }
+
+    /// Number of failures injected so far
+    pub fn injected(&self) -> u64 {
+        self.injected.load(Ordering::Relaxed)
+    }
+
+    fn roll(&self, operation: &str, error_rate: f64) -> Result<(), String> {
+        if error_rate <= 0.0 || !self.rng.lock().unwrap_or_else(|e| e.into_inner()).gen_bool(error_rate) {
+            return Ok(());
+        }
+        self.injected.fetch_add(1, Ordering::Relaxed);
+        debug!("Injecting a fault into {}", operation);
+        Err(format!("Injected fault in {}", operation))
+    }
+
+    /// Waits out the latency, then fails `operation` at the error rate
+    pub async fn inject(&self, operation: &str) -> Result<(), String> {
+        let config = self.config();
+        if !config.latency.is_zero() {
+            tokio::time::sleep(config.latency).await;
+        }
+        self.roll(operation, config.error_rate)
+    }
+
+    /// As `inject`, blocking the thread for the latency
+    pub fn inject_blocking(&self, operation: &str) -> Result<(), String> {
+        let config = self.config();
+        if !config.latency.is_zero() {
+            std::thread::sleep(config.latency);
+        }
+        self.roll(operation, config.error_rate)
+    }
+}
+
+/// A repository whose calls fail and lag as its injector says
+pub struct FaultyGitHub {
+    inner: Arc<dyn GitHubService>,
+    faults: Arc<FaultInjector>,
+}
+
+impl FaultyGitHub {
+    pub fn new(inner: Arc<dyn GitHubService>, faults: Arc<FaultInjector>) -> Self {
+        Self { inner, faults }
+    }
+
+    pub fn faults(&self) -> &Arc<FaultInjector> {
+        &self.faults
+    }
+
+    async fn inject(&self, operation: &str) -> Result<(), Box<dyn Error + Send + Sync>> {
+        self.faults.inject(operation).await.map_err(|e| Box::new(GitHubError::ApiError(e)) as _)
+    }
+}
+
+#[async_trait]
+impl GitHubService for FaultyGitHub {
+    fn max_concurrent_requests(&self) -> usize {
+        self.inner.max_concurrent_requests()
+    }
+
+    async fn list_markdown_files(&self, path: &str) -> Result<Vec<GitHubFileMetadata>, Box<dyn Error + Send + Sync>> {
+        self.inject("list_markdown_files").await?;
+        self.inner.list_markdown_files(path).await
+    }
+
+    async fn list_markdown_files_on(&self, path: &str, branch: Option<&str>) -> Result<Vec<GitHubFileMetadata>, Box<dyn Error + Send + Sync>> {
+        self.inject("list_markdown_files_on").await?;
+        self.inner.list_markdown_files_on(path, branch).await
+    }
+
+    async fn fetch_file_content(&self, download_url: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+        self.inject("fetch_file_content").await?;
+        self.inner.fetch_file_content(download_url).await
+    }
+
+    async fn fetch_file_content_if_modified(
+        &self,
+        download_url: &str,
+        etag: Option<&str>,
+    ) -> Result<ConditionalContent, Box<dyn Error + Send + Sync>> {
+        self.inject("fetch_file_content_if_modified").await?;
+        self.inner.fetch_file_content_if_modified(download_url, etag).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_fault_injector() {
+        let faults = FaultInjector::new(FaultConfig::default(), Some(3));
+        assert!(faults.inject("call").await.is_ok());
+
+        faults.set_config(FaultConfig::new(1.0, Duration::ZERO));
+        assert_eq!(faults.inject_blocking("call"), Err("Injected fault in call".to_string()));
+
+        faults.set_config(FaultConfig::new(0.25, Duration::from_millis(1)));
+        let started = std::time::Instant::now();
+        let failures = (0..400).filter(|_| faults.inject_blocking("call").is_err()).count();
+        assert!(started.elapsed() >= Duration::from_millis(400));
+        assert!((60..140).contains(&failures), "{} failures at a rate of 0.25", failures);
+        assert_eq!(faults.injected(), failures as u64 + 1);
+
+        // The same seed fails the same calls
+        let again = FaultInjector::new(FaultConfig::new(0.5, Duration::ZERO), Some(9));
+        let other = FaultInjector::new(FaultConfig::new(0.5, Duration::ZERO), Some(9));
+        let pattern = |faults: &FaultInjector| (0..50).map(|_| faults.inject_blocking("call").is_ok()).collect::<Vec<_>>();
+        assert_eq!(pattern(&again), pattern(&other));
+        assert_eq!(FaultConfig::new(7.0, Duration::ZERO).error_rate, 1.0);
+    }
+}
@@ -0,0 +1,119 @@
+//! Atom feed of recently changed pages
+//!
+//! Lets people follow the vault from a feed reader instead of the 3D view. Only
+//! published pages are in the metadata, so only they reach the feed. Entries are
+//! ordered by the page's last modification, and each carries the opening of the
+//! page rendered as HTML. `FEED_TITLE` names the feed, and `FEED_PAGE_URL` (e.g.
+//! `https://garden.example.com/{page}`) is where entries link to, the page API
+//! when not set.
+
+use chrono::{DateTime, Utc};
+use std::env;
+use std::fmt::Write;
+
+use crate::services::graphml_export::escape;
+use crate::services::pages::{render_html, PageSummary};
+
+const DEFAULT_TITLE: &str = "Knowledge garden";
+const SUMMARY_CHARS: usize = 500;
+
+pub struct FeedConfig {
+    pub title: String,
+    /// Entry link with `{page}` standing for the page name
+    pub page_url: Option<String>,
+}
+
+impl FeedConfig {
+    pub fn from_env() -> Self {
+        let non_empty = |name| env::var(name).ok().map(|value| value.trim().to_string()).filter(|value| !value.is_empty());
+        Self {
+            title: non_empty("FEED_TITLE").unwrap_or_else(|| DEFAULT_TITLE.to_string()),
+            page_url: non_empty("FEED_PAGE_URL").filter(|url| url.contains("{page}")),
+        }
+    }
+
+    fn page_url(&self, base_url: &str, page: &str) -> String {
+        let page = urlencoding::encode(page);
+        match &self.page_url {
+            Some(template) => template.replace("{page}", &page),
+            None => format!("{}/api/pages/{}", base_url, page),
+        }
+    }
+}
+
+pub struct FeedEntry {
+    pub page: PageSummary,
+    pub summary_html: String,
+}
+
+/// The opening of a page as HTML, leaving out Logseq properties (`key:: value`)
+pub fn summary(content: &str) -> String {
+    let mut excerpt = String::new();
+    for line in content.lines() {
+        let text = line.trim_start().trim_start_matches("- ");
+        let is_property = text.split_once("::").is_some_and(|(key, _)| {
+            !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        });
+        if is_property {
+            continue;
+        }
+        if excerpt.chars().count() + line.chars().count() > SUMMARY_CHARS && !excerpt.is_empty() {
+            excerpt.push_str("\n\n…");
+            break;
+        }
+        excerpt.push_str(line);
+        excerpt.push('\n');
+    }
+    render_html(excerpt.trim())
+}
+
+/// The Atom document for `entries`, newest first, served from `base_url`
+pub fn atom(config: &FeedConfig, base_url: &str, entries: &[FeedEntry]) -> String {
+    let updated = entries.iter().map(|entry| entry.page.modified).max().unwrap_or(DateTime::<Utc>::UNIX_EPOCH);
+    let self_url = format!("{}/api/pages/feed.atom", base_url);
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    let _ = writeln!(xml, "  <title>{}</title>", escape(&config.title));
+    let _ = writeln!(xml, "  <id>{}</id>", escape(&self_url));
+    let _ = writeln!(xml, "  <link rel=\"self\" href=\"{}\"/>", escape(&self_url));
+    let _ = writeln!(xml, "  <updated>{}</updated>", updated.to_rfc3339());
+    for entry in entries {
+        let url = config.page_url(base_url, &entry.page.id);
+        xml.push_str("  <entry>\n");
+        let _ = writeln!(xml, "    <title>{}</title>", escape(&entry.page.title));
+        let _ = writeln!(xml, "    <id>{}</id>", escape(&url));
+        let _ = writeln!(xml, "    <link href=\"{}\"/>", escape(&url));
+        let _ = writeln!(xml, "    <updated>{}</updated>", entry.page.modified.to_rfc3339());
+        if let Some(parent) = &entry.page.parent {
+            let _ = writeln!(xml, "    <category term=\"{}\"/>", escape(parent));
+        }
+        let _ = writeln!(xml, "    <summary type=\"html\">{}</summary>", escape(&entry.summary_html));
+        xml.push_str("  </entry>\n");
+    }
+    xml.push_str("</feed>\n");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::metadata::Metadata;
+
+    #[test]
+    fn test_atom_feed() {
+        let html = summary("public:: true\n- Notes on <b>WebXR</b> & friends");
+        assert!(!html.contains("public::"));
+        assert!(html.contains("&lt;b&gt;"));
+
+        let entry = FeedEntry {
+            page: PageSummary::of("projects___webxr.md", &Metadata::default()),
+            summary_html: html,
+        };
+        let config = FeedConfig { title: "Garden & notes".to_string(), page_url: Some("https://garden.example/{page}".to_string()) };
+        let xml = atom(&config, "https://vault.example", &[entry]);
+        assert!(xml.contains("<title>Garden &amp; notes</title>"));
+        assert!(xml.contains("<link href=\"https://garden.example/projects___webxr\"/>"));
+        assert!(xml.contains("<category term=\"projects\"/>"));
+        // The summary is escaped once more, as Atom carries HTML as text
+        assert!(xml.contains("&amp;lt;b&amp;gt;"));
+    }
+}
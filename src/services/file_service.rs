@@ -2,7 +2,7 @@ use crate::models::metadata::{Metadata, MetadataStore, MetadataOps};
 use crate::models::graph::GraphData;
 use crate::config::AppFullSettings; // Use AppFullSettings, ClientFacingSettings removed
 use serde::{Deserialize, Serialize};
-use log::{info, debug, error};
+use log::{info, debug, error, warn};
 use std::sync::atomic::{AtomicU32, Ordering};
 use regex::Regex;
 use std::fs;
@@ -11,18 +11,31 @@ use chrono::Utc;
 use std::sync::Arc;
 use tokio::sync::RwLock;
 use std::error::Error as StdError;
-use std::time::Duration;
-use tokio::time::sleep;
 use actix_web::web;
-use std::collections::HashMap;
+use futures::stream::{self, StreamExt};
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::Error;
-use super::github::{GitHubClient, ContentAPI, GitHubConfig};
+use super::github::{GitHubClient, ContentAPI, ConditionalContent, GitHubConfig, GitHubFileMetadata, GitHubService};
+use super::blob_cache::{BlobCache, git_blob_sha};
+use super::duplicates;
+use super::event_bus::{EventBus, FileEvent};
+use super::link_preview::LinkPreviewService;
+use super::storage_quota::StorageQuota;
+use super::sync_state::SyncState;
+use super::trash::{Trash, TrashPolicy};
+use super::reference_parser::{normalize_name, PageIndex, ParserProfile};
+use super::visibility::{VisibilityDecision, VisibilityPolicy};
+use super::vault_crypto::{self, VaultCipher};
 
-// Constants
-const METADATA_PATH: &str = "/app/data/metadata/metadata.json";
-pub const MARKDOWN_DIR: &str = "/app/data/markdown";
-const GITHUB_API_DELAY: Duration = Duration::from_millis(500);
+use crate::config::data_dirs::DataDirs;
+use webxr_core::page::{page_aliases, page_stats, page_tasks};
+
+const METADATA_FILE: &str = "metadata.json";
+// Metadata and sync state are persisted after this many files
+const SYNC_CHECKPOINT_INTERVAL: usize = 25;
+
+type FetchOutcome = Result<Option<String>, Box<dyn StdError + Send + Sync>>;
 
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ProcessedFile {
@@ -36,6 +49,13 @@ pub struct FileService {
     _settings: Arc<RwLock<AppFullSettings>>, // Changed to AppFullSettings, prefixed with underscore
     // Counter for assigning node IDs, initialized based on existing metadata
     node_id_counter: AtomicU32,
+    // Optional so callers that don't care about events (tests, one-off tools) can skip it
+    event_bus: Option<EventBus>,
+    parser_profile: ParserProfile,
+    visibility: VisibilityPolicy,
+    trash_policy: TrashPolicy,
+    // Lets a sync remove more files than `trash_policy` allows unconfirmed
+    confirm_deletions: bool,
 }
 
 impl FileService {
@@ -44,6 +64,11 @@ impl FileService {
         let service = Self {
             _settings, // Prefixed with underscore
             node_id_counter: AtomicU32::new(1),
+            event_bus: None,
+            parser_profile: ParserProfile::from_env(),
+            visibility: VisibilityPolicy::load(),
+            trash_policy: TrashPolicy::from_env(),
+            confirm_deletions: false,
         };
         
         // Try to initialize the counter based on existing metadata
@@ -58,21 +83,36 @@ impl FileService {
         
         service
     }
+
+    /// Publish FileEvents on the given bus once files have been processed
+    pub fn with_event_bus(mut self, event_bus: EventBus) -> Self {
+        self.event_bus = Some(event_bus);
+        self
+    }
+
+    /// Override the reference syntax taken from `MARKDOWN_PARSER_PROFILE`
+    pub fn with_parser_profile(mut self, parser_profile: ParserProfile) -> Self {
+        self.parser_profile = parser_profile;
+        self
+    }
+
+    /// Allow the next sync to remove files even past the trash policy's guardrail
+    pub fn with_confirmed_deletions(mut self, confirm_deletions: bool) -> Self {
+        self.confirm_deletions = confirm_deletions;
+        self
+    }
+
+    /// Override the visibility policy loaded from disk
+    pub fn with_visibility_policy(mut self, visibility: VisibilityPolicy) -> Self {
+        self.visibility = visibility;
+        self
+    }
     
     /// Get the next unique node ID
     fn get_next_node_id(&self) -> u32 {
         self.node_id_counter.fetch_add(1, Ordering::SeqCst)
     }
     
-    /// Update node IDs for processed files
-    fn update_node_ids(&self, processed_files: &mut Vec<ProcessedFile>) {
-        for processed_file in processed_files {
-            if processed_file.metadata.node_id == "0" {
-                processed_file.metadata.node_id = self.get_next_node_id().to_string();
-            }
-        }
-    }
-
     /// Process uploaded file and return graph data
     pub async fn process_file_upload(&self, payload: web::Bytes) -> Result<GraphData, Error> {
         let content = String::from_utf8(payload.to_vec())
@@ -83,18 +123,17 @@ impl FileService {
         
         // Create a temporary file to process
         let temp_filename = format!("temp_{}.md", Utc::now().timestamp());
-        let temp_path = format!("{}/{}", MARKDOWN_DIR, temp_filename);
-        if let Err(e) = fs::write(&temp_path, &content) {
+        let temp_path = DataDirs::global().markdown_file(&temp_filename);
+        if let Err(e) = vault_crypto::write(&temp_path, &content) {
             return Err(Error::new(std::io::ErrorKind::Other, e.to_string()));
         }
 
         // Extract references and create metadata
-        let valid_nodes: Vec<String> = metadata.keys()
-            .map(|name| name.trim_end_matches(".md").to_string())
-            .collect();
-
-        let references = Self::extract_references(&content, &valid_nodes);
+        let pages = PageIndex::from_metadata(&metadata);
+        let references = self.parser_profile.extract_references(&content, &pages);
         let topic_counts = Self::convert_references_to_topic_counts(references);
+        let mut unresolved_links = self.parser_profile.unresolved_links(&content, &pages);
+        let private_links = Self::take_private_links(&mut unresolved_links, &SyncState::load().private_pages());
 
         // Create metadata for the uploaded file
         let file_size = content.len();
@@ -109,7 +148,17 @@ impl FileService {
             last_modified: Utc::now(),
             perplexity_link: String::new(),
             last_perplexity_process: None,
+            perplexity_summary: String::new(),
             topic_counts,
+            unresolved_links,
+            private_links,
+            tags: self.parser_profile.tags(&content),
+            aliases: page_aliases(&content),
+            link_previews: Vec::new(),
+            page_stats: page_stats(&content),
+            tasks: page_tasks(&content),
+            near_duplicates: HashMap::new(),
+            link_checks: HashMap::new(),
         };
 
         // Assign a unique node ID
@@ -127,6 +176,52 @@ impl FileService {
         Ok(graph_data)
     }
 
+    /// Writes a new page into the local vault and adds it to `metadata_store`, which
+    /// is saved. References between it and existing pages are resolved again. The
+    /// page isn't in the repository, so unless it is also pushed there the next sync
+    /// moves it to the trash.
+    pub fn add_page(&self, file_name: &str, content: &str, metadata_store: &mut MetadataStore) -> Result<Metadata, Error> {
+        if metadata_store.contains_key(file_name) || DataDirs::global().markdown_file(file_name).exists() {
+            return Err(Error::new(std::io::ErrorKind::AlreadyExists, format!("Page {} already exists", file_name)));
+        }
+        self.write_page(file_name, content, metadata_store)
+    }
+
+    /// Writes a page into the local vault, creating or replacing it, and updates its
+    /// entry in `metadata_store`, which is saved. A replaced page keeps its node id
+    /// and enrichment, as it would through a sync.
+    pub fn write_page(&self, file_name: &str, content: &str, metadata_store: &mut MetadataStore) -> Result<Metadata, Error> {
+        vault_crypto::write(DataDirs::global().markdown_file(file_name), content)?;
+
+        let file_size = content.len();
+        let existing = metadata_store.get(file_name).cloned();
+        let node_id = match &existing {
+            Some(existing) => existing.node_id.clone(),
+            None => self.get_next_node_id().max(metadata_store.get_max_node_id() + 1).to_string(),
+        };
+        metadata_store.insert(file_name.to_string(), Metadata {
+            file_name: file_name.to_string(),
+            file_size,
+            node_size: Self::calculate_node_size(file_size),
+            node_id: node_id.clone(),
+            hyperlink_count: Self::count_hyperlinks(content),
+            sha1: Self::calculate_sha1(content),
+            last_modified: Utc::now(),
+            aliases: page_aliases(content),
+            page_stats: page_stats(content),
+            tasks: page_tasks(content),
+            ..existing.unwrap_or_default()
+        });
+        Self::update_topic_counts(metadata_store, self.parser_profile, &SyncState::load().private_pages())?;
+        Self::save_metadata(metadata_store)?;
+
+        if let Some(event_bus) = &self.event_bus {
+            event_bus.publish(FileEvent::Processed { file_names: vec![file_name.to_string()] });
+        }
+        info!("Wrote page {} as node {}", file_name, node_id);
+        Ok(metadata_store[file_name].clone())
+    }
+
     /// List available files
     pub async fn list_files(&self) -> Result<Vec<String>, Error> {
         let metadata = Self::load_or_create_metadata()
@@ -136,24 +231,23 @@ impl FileService {
 
     /// Load a specific file and return graph data
     pub async fn load_file(&self, filename: &str) -> Result<GraphData, Error> {
-        let file_path = format!("{}/{}", MARKDOWN_DIR, filename);
-        if !Path::new(&file_path).exists() {
+        let file_path = DataDirs::global().markdown_file(filename);
+        if !file_path.exists() {
             return Err(Error::new(std::io::ErrorKind::NotFound, format!("File not found: {}", filename)));
         }
 
-        let content = fs::read_to_string(&file_path)
+        let content = vault_crypto::read_to_string(&file_path)
             .map_err(|e| Error::new(std::io::ErrorKind::Other, e.to_string()))?;
         let metadata = Self::load_or_create_metadata()
             .map_err(|e| Error::new(std::io::ErrorKind::Other, e))?;
         let mut graph_data = GraphData::new();
 
         // Extract references and update metadata
-        let valid_nodes: Vec<String> = metadata.keys()
-            .map(|name| name.trim_end_matches(".md").to_string())
-            .collect();
-
-        let references = Self::extract_references(&content, &valid_nodes);
+        let pages = PageIndex::from_metadata(&metadata);
+        let references = self.parser_profile.extract_references(&content, &pages);
         let topic_counts = Self::convert_references_to_topic_counts(references);
+        let mut unresolved_links = self.parser_profile.unresolved_links(&content, &pages);
+        let private_links = Self::take_private_links(&mut unresolved_links, &SyncState::load().private_pages());
 
         // Update or create metadata for the file
         let file_size = content.len();
@@ -168,7 +262,17 @@ impl FileService {
             last_modified: Utc::now(),
             perplexity_link: String::new(),
             last_perplexity_process: None,
+            perplexity_summary: String::new(),
             topic_counts,
+            unresolved_links,
+            private_links,
+            tags: self.parser_profile.tags(&content),
+            aliases: page_aliases(&content),
+            link_previews: Vec::new(),
+            page_stats: page_stats(&content),
+            tasks: page_tasks(&content),
+            near_duplicates: HashMap::new(),
+            link_checks: HashMap::new(),
         };
 
         // Assign a unique node ID
@@ -184,19 +288,20 @@ impl FileService {
     /// Load metadata from file or create new if not exists
     pub fn load_or_create_metadata() -> Result<MetadataStore, String> {
         // Ensure metadata directory exists
-        std::fs::create_dir_all("/app/data/metadata")
+        let dirs = DataDirs::global();
+        std::fs::create_dir_all(&dirs.metadata)
             .map_err(|e| format!("Failed to create metadata directory: {}", e))?;
         
-        let metadata_path = "/app/data/metadata/metadata.json";
+        let metadata_path = dirs.metadata_file(METADATA_FILE);
         
-        if let Ok(file) = File::open(metadata_path) {
-            info!("Loading existing metadata from {}", metadata_path);
+        if let Ok(file) = File::open(&metadata_path) {
+            info!("Loading existing metadata from {}", metadata_path.display());
             serde_json::from_reader(file)
                 .map_err(|e| format!("Failed to parse metadata: {}", e))
         } else {
-            info!("Creating new metadata file at {}", metadata_path);
+            info!("Creating new metadata file at {}", metadata_path.display());
             let empty_store = MetadataStore::default();
-            let file = File::create(metadata_path)
+            let file = File::create(&metadata_path)
                 .map_err(|e| format!("Failed to create metadata file: {}", e))?;
                 
             serde_json::to_writer_pretty(file, &empty_store)
@@ -215,7 +320,7 @@ impl FileService {
     }
 
     /// Calculate node size based on file size
-    fn calculate_node_size(file_size: usize) -> f64 {
+    pub fn calculate_node_size(file_size: usize) -> f64 {
         const BASE_SIZE: f64 = 1000.0; // Base file size for scaling
         const MIN_SIZE: f64 = 5.0;  // Minimum node size
         const MAX_SIZE: f64 = 50.0; // Maximum node size
@@ -224,34 +329,6 @@ impl FileService {
         MIN_SIZE + (size * (MAX_SIZE - MIN_SIZE) / 5.0)
     }
 
-    /// Extract references to other files based on their names (case insensitive)
-    fn extract_references(content: &str, valid_nodes: &[String]) -> Vec<String> {
-        let mut references = Vec::new();
-        let content_lower = content.to_lowercase();
-        
-        for node_name in valid_nodes {
-            let node_name_lower = node_name.to_lowercase();
-            
-            // Create a regex pattern with word boundaries
-            let pattern = format!(r"\b{}\b", regex::escape(&node_name_lower));
-            if let Ok(re) = Regex::new(&pattern) {
-                // Count case-insensitive matches of the filename
-                let count = re.find_iter(&content_lower).count();
-                
-                // If we found any references, add them to the map
-                if count > 0 {
-                    debug!("Found {} references to {} in content", count, node_name);
-                    // Add the reference multiple times based on count
-                    for _ in 0..count {
-                        references.push(node_name.clone());
-                    }
-                }
-            }
-        }
-        
-        references
-    }
-
     fn convert_references_to_topic_counts(references: Vec<String>) -> HashMap<String, usize> {
         let mut topic_counts = HashMap::new();
         for reference in references {
@@ -287,88 +364,57 @@ impl FileService {
         info!("Found {} markdown files in GitHub", github_files.len());
 
         let mut metadata_store = MetadataStore::new();
+        let mut private_pages = HashSet::new();
 
-        // Process files in batches to prevent timeouts
-        const BATCH_SIZE: usize = 5;
-        for chunk in github_files.chunks(BATCH_SIZE) {
-            let mut futures = Vec::new();
-            
-            for file_meta in chunk {
-                let file_meta = file_meta.clone();
-                let content_api = content_api.clone();
-                
-                futures.push(async move {
-                    // First check if file is public
-                    match content_api.check_file_public(&file_meta.download_url).await {
-                        Ok(is_public) => {
-                            if !is_public {
-                                debug!("Skipping non-public file: {}", file_meta.name);
-                                return Ok(None);
-                            }
+        let visibility = VisibilityPolicy::load();
+        for (file_meta, result) in Self::fetch_public_files(&content_api, &visibility, github_files).await {
+            match result {
+                Ok(Some(content)) => {
+                    let file_size = content.len();
+                    let node_size = Self::calculate_node_size(file_size);
 
-                            // Only fetch full content for public files
-                            match content_api.fetch_file_content(&file_meta.download_url).await {
-                                Ok(content) => {
-                                    let file_path = format!("{}/{}", MARKDOWN_DIR, file_meta.name);
-                                    if let Err(e) = fs::write(&file_path, &content) {
-                                        error!("Failed to write file {}: {}", file_path, e);
-                                        return Err(e.into());
-                                    }
+                    // Create metadata entry
+                    let metadata = Metadata {
+                        file_name: file_meta.name.clone(),
+                        file_size,
+                        node_size,
+                        node_id: "0".to_string(), // Will be assigned properly later
+                        hyperlink_count: Self::count_hyperlinks(&content),
+                        sha1: Self::calculate_sha1(&content),
+                        last_modified: file_meta.last_modified.unwrap_or_else(Utc::now),
+                        perplexity_link: String::new(),
+                        last_perplexity_process: None,
+                        perplexity_summary: String::new(),
+                        topic_counts: HashMap::new(), // Will be updated later
+                        unresolved_links: HashMap::new(),
+                        private_links: HashMap::new(),
+                        tags: Vec::new(),
+                        aliases: page_aliases(&content),
+                        link_previews: Vec::new(),
+                        page_stats: page_stats(&content),
+                        tasks: page_tasks(&content),
+                        near_duplicates: HashMap::new(),
+                        link_checks: HashMap::new(),
+                    };
 
-                                    Ok(Some((file_meta, content)))
-                                }
-                                Err(e) => {
-                                    error!("Failed to fetch content for {}: {}", file_meta.name, e);
-                                    Err(e)
-                                }
-                            }
-                        }
-                        Err(e) => {
-                            error!("Failed to check public status for {}: {}", file_meta.name, e);
-                            Err(e)
-                        }
-                    }
-                });
-            }
-
-            // Wait for batch to complete
-            let results = futures::future::join_all(futures).await;
-            
-            for result in results {
-                match result {
-                    Ok(Some((file_meta, content))) => {
-                        let _node_name = file_meta.name.trim_end_matches(".md").to_string();
-                        let file_size = content.len();
-                        let node_size = Self::calculate_node_size(file_size);
-
-                        // Create metadata entry
-                        let metadata = Metadata {
-                            file_name: file_meta.name.clone(),
-                            file_size,
-                            node_size,
-                            node_id: "0".to_string(), // Will be assigned properly later
-                            hyperlink_count: Self::count_hyperlinks(&content),
-                            sha1: Self::calculate_sha1(&content),
-                            last_modified: file_meta.last_modified.unwrap_or_else(|| Utc::now()),
-                            perplexity_link: String::new(),
-                            last_perplexity_process: None,
-                            topic_counts: HashMap::new(), // Will be updated later
-                        };
-
-                        metadata_store.insert(file_meta.name, metadata);
-                    }
-                    Ok(None) => continue, // Skipped non-public file
-                    Err(e) => {
-                        error!("Failed to process file in batch: {}", e);
-                    }
+                    metadata_store.insert(file_meta.name, metadata);
+                }
+                Ok(None) => {
+                    // Skipped non-public file
+                    private_pages.insert(normalize_name(file_meta.name.trim_end_matches(".md")));
+                }
+                Err(e) => {
+                    error!("Failed to process file {}: {}", file_meta.name, e);
                 }
             }
-
-            sleep(GITHUB_API_DELAY).await;
         }
 
         // Update topic counts after all files are processed
-        Self::update_topic_counts(&mut metadata_store)?;
+        Self::update_topic_counts(&mut metadata_store, ParserProfile::from_env(), &private_pages)?;
+        if let Some(previews) = LinkPreviewService::from_env() {
+            previews.enrich(&mut metadata_store).await;
+        }
+        duplicates::detect(&mut metadata_store, duplicates::threshold_from_env());
 
         // Save metadata
         info!("Saving metadata for {} public files", metadata_store.len());
@@ -378,36 +424,344 @@ impl FileService {
         Ok(())
     }
 
-    /// Update topic counts for all files
-    fn update_topic_counts(metadata_store: &mut MetadataStore) -> Result<(), Error> {
-        let valid_nodes: Vec<String> = metadata_store.keys()
-            .map(|name| name.trim_end_matches(".md").to_string())
-            .collect();
+    /// Fetch the content of every public file, keeping at most
+    /// `content_api.max_concurrent_requests()` downloads in flight. Results keep the
+    /// input order; `Ok(None)` marks a file the visibility policy leaves out.
+    async fn fetch_public_files(
+        content_api: &dyn GitHubService,
+        visibility: &VisibilityPolicy,
+        files: Vec<GitHubFileMetadata>,
+    ) -> Vec<(GitHubFileMetadata, FetchOutcome)> {
+        let concurrency = content_api.max_concurrent_requests();
+        info!("Fetching {} files with up to {} concurrent requests", files.len(), concurrency);
 
-        for file_name in metadata_store.keys().cloned().collect::<Vec<_>>() {
-            let file_path = format!("{}/{}", MARKDOWN_DIR, file_name);
-            if let Ok(content) = fs::read_to_string(&file_path) {
-                let references = Self::extract_references(&content, &valid_nodes);
-                let topic_counts = Self::convert_references_to_topic_counts(references);
-                
-                if let Some(metadata) = metadata_store.get_mut(&file_name) {
-                    metadata.topic_counts = topic_counts;
+        let blobs = BlobCache::open();
+        let results = stream::iter(files)
+            .map(|file_meta| {
+                let blobs = &blobs;
+                async move {
+                    let outcome = Self::fetch_public_file(content_api, blobs, visibility, &file_meta).await;
+                    (file_meta, outcome)
+                }
+            })
+            .buffered(concurrency)
+            .collect()
+            .await;
+
+        if let Err(e) = blobs.save_index() {
+            error!("Failed to save blob cache ETag index: {}", e);
+        }
+        if let Err(e) = StorageQuota::from_env().enforce() {
+            error!("Failed to enforce storage limits: {}", e);
+        }
+        results
+    }
+
+    async fn fetch_public_file(
+        content_api: &dyn GitHubService,
+        blobs: &BlobCache,
+        visibility: &VisibilityPolicy,
+        file_meta: &GitHubFileMetadata,
+    ) -> FetchOutcome {
+        let content = Self::load_public_content(content_api, blobs, visibility, file_meta).await?;
+        if let Some(content) = &content {
+            Self::write_markdown(&DataDirs::global().markdown_file(&file_meta.name), content)?;
+        }
+        Ok(content)
+    }
+
+    /// Loads a file's content and applies the visibility policy. Only published
+    /// content is added to the blob cache; nothing in the markdown directory is modified.
+    async fn load_public_content(
+        content_api: &dyn GitHubService,
+        blobs: &BlobCache,
+        visibility: &VisibilityPolicy,
+        file_meta: &GitHubFileMetadata,
+    ) -> FetchOutcome {
+        // Files ruled out by name alone aren't downloaded at all
+        if let Some(decision) = visibility.check_path(&file_meta.name) {
+            debug!("Skipping {}: {}", file_meta.name, decision.reason);
+            return Ok(None);
+        }
+
+        let (content, etag) = Self::load_content(content_api, blobs, file_meta).await?;
+        let decision = visibility.evaluate(&file_meta.name, &content);
+        if !decision.included {
+            debug!("Skipping non-public file {}: {}", file_meta.name, decision.reason);
+            return Ok(None);
+        }
+
+        match blobs.put(&content) {
+            Ok(sha) => {
+                if let Some(etag) = etag {
+                    blobs.set_etag(&file_meta.name, etag, sha);
+                }
+            }
+            Err(e) => warn!("Failed to add {} to blob cache: {}", file_meta.name, e),
+        }
+        Ok(Some(content))
+    }
+
+    /// Resolves a file's content from the blob cache, an up-to-date local copy, or
+    /// GitHub, in that order. Returns the ETag when the content was downloaded.
+    async fn load_content(
+        content_api: &dyn GitHubService,
+        blobs: &BlobCache,
+        file_meta: &GitHubFileMetadata,
+    ) -> Result<(String, Option<String>), Box<dyn StdError + Send + Sync>> {
+        // Same blob SHA as the listing means we already have exactly this content
+        if let Some(content) = blobs.get(&file_meta.sha) {
+            debug!("Blob cache hit for {}", file_meta.name);
+            return Ok((content, None));
+        }
+
+        // Local copies written before the blob cache existed count as cached too
+        let file_path = DataDirs::global().markdown_file(&file_meta.name);
+        if let Ok(content) = vault_crypto::read_to_string(&file_path) {
+            if !file_meta.sha.is_empty() && git_blob_sha(&content) == file_meta.sha {
+                debug!("Reusing up-to-date local copy of {}", file_meta.name);
+                return Ok((content, None));
+            }
+        }
+
+        // Fetch conditionally if we've seen the file before
+        let previous = blobs.etag(&file_meta.name);
+        let fetched = content_api
+            .fetch_file_content_if_modified(&file_meta.download_url, previous.as_ref().map(|p| p.etag.as_str()))
+            .await
+            .map_err(|e| {
+                error!("Failed to fetch content for {}: {}", file_meta.name, e);
+                e
+            })?;
+
+        match fetched {
+            ConditionalContent::Modified { content, etag } => Ok((content, etag)),
+            ConditionalContent::NotModified => match previous.and_then(|p| blobs.get(&p.sha)) {
+                Some(content) => Ok((content, None)),
+                // The blob went missing; fall back to a full download
+                None => Ok((content_api.fetch_file_content(&file_meta.download_url).await?, None)),
+            },
+        }
+    }
+
+    /// Evaluates the visibility policy against every file in the repository without
+    /// writing anything, reporting why each file would or wouldn't be published
+    pub async fn dry_run_visibility(
+        content_api: &dyn GitHubService,
+        visibility: &VisibilityPolicy,
+    ) -> Result<Vec<(String, VisibilityDecision)>, Box<dyn StdError + Send + Sync>> {
+        let github_files = content_api.list_markdown_files("").await?;
+        info!("Evaluating visibility policy against {} markdown files", github_files.len());
+
+        let blobs = BlobCache::open();
+        let concurrency = content_api.max_concurrent_requests();
+        let decisions = stream::iter(github_files)
+            .map(|file_meta| {
+                let blobs = &blobs;
+                async move {
+                    let decision = match visibility.check_path(&file_meta.name) {
+                        Some(decision) => decision,
+                        None => match Self::load_content(content_api, blobs, &file_meta).await {
+                            Ok((content, _)) => visibility.evaluate(&file_meta.name, &content),
+                            Err(e) => VisibilityDecision {
+                                included: false,
+                                reason: format!("failed to fetch content: {}", e),
+                            },
+                        },
+                    };
+                    (file_meta.name, decision)
                 }
+            })
+            .buffered(concurrency)
+            .collect()
+            .await;
+        Ok(decisions)
+    }
+
+    /// Builds metadata for `branch` entirely in memory, for previewing how the graph
+    /// would look once a branch (typically a PR) is merged, or with `None` how the
+    /// next sync of the configured branch would leave it. The local markdown
+    /// directory and persisted metadata are left untouched. Files that also exist in
+    /// `current` keep their node ids so the preview can be compared with the live graph.
+    pub async fn build_preview_metadata(
+        content_api: &dyn GitHubService,
+        branch: Option<&str>,
+        current: &MetadataStore,
+    ) -> Result<MetadataStore, Box<dyn StdError + Send + Sync>> {
+        let github_files = content_api.list_markdown_files_on("", branch).await?;
+        let branch = branch.unwrap_or("(default)");
+        info!("Building preview of branch '{}' from {} markdown files", branch, github_files.len());
+        Ok(Self::build_metadata_from_listing(content_api, github_files, branch, current).await)
+    }
+
+    /// Builds metadata in memory for already-listed `github_files`, downloading what
+    /// the blob cache doesn't have. `label` names the source in log messages.
+    pub async fn build_metadata_from_listing(
+        content_api: &dyn GitHubService,
+        github_files: Vec<GitHubFileMetadata>,
+        label: &str,
+        current: &MetadataStore,
+    ) -> MetadataStore {
+        let blobs = BlobCache::open();
+        let visibility = VisibilityPolicy::load();
+        let concurrency = content_api.max_concurrent_requests();
+        let results: Vec<(GitHubFileMetadata, FetchOutcome)> = stream::iter(github_files)
+            .map(|file_meta| {
+                let blobs = &blobs;
+                let visibility = &visibility;
+                async move {
+                    let outcome = Self::load_public_content(content_api, blobs, visibility, &file_meta).await;
+                    (file_meta, outcome)
+                }
+            })
+            .buffered(concurrency)
+            .collect()
+            .await;
+        if let Err(e) = blobs.save_index() {
+            error!("Failed to save blob cache ETag index: {}", e);
+        }
+
+        let mut next_id = current.get_max_node_id() + 1;
+        let mut preview = MetadataStore::new();
+        let mut contents = HashMap::new();
+        for (file_meta, result) in results {
+            let content = match result {
+                Ok(Some(content)) => content,
+                Ok(None) => continue,
+                Err(e) => {
+                    warn!("Leaving {} out of the metadata for '{}': {}", file_meta.name, label, e);
+                    continue;
+                }
+            };
+            let existing = current.get(&file_meta.name);
+            let node_id = match existing {
+                Some(m) => m.node_id.clone(),
+                None => {
+                    let id = next_id;
+                    next_id += 1;
+                    id.to_string()
+                }
+            };
+            let file_size = content.len();
+            preview.insert(file_meta.name.clone(), Metadata {
+                file_name: file_meta.name.clone(),
+                file_size,
+                node_size: Self::calculate_node_size(file_size),
+                node_id,
+                hyperlink_count: Self::count_hyperlinks(&content),
+                sha1: Self::calculate_sha1(&content),
+                last_modified: file_meta.last_modified.unwrap_or_else(Utc::now),
+                perplexity_link: existing.map(|m| m.perplexity_link.clone()).unwrap_or_default(),
+                last_perplexity_process: existing.and_then(|m| m.last_perplexity_process),
+                perplexity_summary: existing.map(|m| m.perplexity_summary.clone()).unwrap_or_default(),
+                topic_counts: HashMap::new(),
+                unresolved_links: HashMap::new(),
+                private_links: HashMap::new(),
+                tags: Vec::new(),
+                aliases: page_aliases(&content),
+                link_previews: existing.map(|m| m.link_previews.clone()).unwrap_or_default(),
+                page_stats: page_stats(&content),
+                tasks: page_tasks(&content),
+                near_duplicates: existing.map(|m| m.near_duplicates.clone()).unwrap_or_default(),
+                link_checks: existing.map(|m| m.link_checks.clone()).unwrap_or_default(),
+            });
+            contents.insert(file_meta.name, content);
+        }
+
+        let pages = PageIndex::from_metadata(&preview);
+        let parser_profile = ParserProfile::from_env();
+        for (file_name, content) in contents {
+            let references = parser_profile.extract_references(&content, &pages);
+            if let Some(metadata) = preview.get_mut(&file_name) {
+                metadata.topic_counts = Self::convert_references_to_topic_counts(references);
+                metadata.unresolved_links = parser_profile.unresolved_links(&content, &pages);
+                metadata.tags = parser_profile.tags(&content);
+            }
+        }
+
+        preview
+    }
+
+    fn write_markdown(file_path: &Path, content: &str) -> Result<(), Box<dyn StdError + Send + Sync>> {
+        // Skip the write when the file is already up to date and stored the way the
+        // key says it should be
+        let cipher = VaultCipher::global()?;
+        if let Ok(existing) = fs::read(file_path) {
+            if !cipher.needs_sealing(&existing)
+                && cipher.decode_string(&existing).map(|existing| existing == content).unwrap_or(false)
+            {
+                return Ok(());
+            }
+        }
+        vault_crypto::write(file_path, content).map_err(|e| {
+            error!("Failed to write file {}: {}", file_path.display(), e);
+            e.into()
+        })
+    }
+
+    /// Update topic counts for all files
+    fn update_topic_counts(
+        metadata_store: &mut MetadataStore,
+        parser_profile: ParserProfile,
+        private_pages: &HashSet<String>,
+    ) -> Result<(), Error> {
+        // Aliases are refreshed first so references to them resolve in this pass
+        let mut contents = Vec::new();
+        for (file_name, metadata) in metadata_store.iter_mut() {
+            let file_path = DataDirs::global().markdown_file(file_name);
+            if let Ok(content) = vault_crypto::read_to_string(&file_path) {
+                metadata.aliases = page_aliases(&content);
+                contents.push((file_name.clone(), content));
+            }
+        }
+        let pages = PageIndex::from_metadata(metadata_store);
+
+        for (file_name, content) in contents {
+            let references = parser_profile.extract_references(&content, &pages);
+            let topic_counts = Self::convert_references_to_topic_counts(references);
+            let mut unresolved_links = parser_profile.unresolved_links(&content, &pages);
+            let private_links = Self::take_private_links(&mut unresolved_links, private_pages);
+
+            if let Some(metadata) = metadata_store.get_mut(&file_name) {
+                metadata.topic_counts = topic_counts;
+                metadata.unresolved_links = unresolved_links;
+                metadata.private_links = private_links;
+                metadata.tags = parser_profile.tags(&content);
+                metadata.page_stats = page_stats(&content);
+                metadata.tasks = page_tasks(&content);
             }
         }
 
         Ok(())
     }
 
+    /// Moves links to unpublished pages out of `unresolved`; `private_pages` holds
+    /// their normalised page names
+    fn take_private_links(
+        unresolved: &mut HashMap<String, usize>,
+        private_pages: &HashSet<String>,
+    ) -> HashMap<String, usize> {
+        let mut private_links = HashMap::new();
+        unresolved.retain(|target, count| {
+            let private = private_pages.contains(&normalize_name(target));
+            if private {
+                private_links.insert(target.clone(), *count);
+            }
+            !private
+        });
+        private_links
+    }
+
     /// Check if we have a valid local setup
     fn has_valid_local_setup() -> bool {
-        if let Ok(metadata_content) = fs::read_to_string(METADATA_PATH) {
+        let dirs = DataDirs::global();
+        if let Ok(metadata_content) = fs::read_to_string(dirs.metadata_file(METADATA_FILE)) {
             if metadata_content.trim().is_empty() {
                 return false;
             }
             
             if let Ok(metadata) = serde_json::from_str::<MetadataStore>(&metadata_content) {
-                return metadata.validate_files(MARKDOWN_DIR);
+                return metadata.validate_files(&dirs.markdown.to_string_lossy());
             }
         }
         false
@@ -415,8 +769,9 @@ impl FileService {
 
     /// Ensures all required directories exist with proper permissions
     fn ensure_directories() -> Result<(), Error> {
+        let dirs = DataDirs::global();
         // Create markdown directory
-        let markdown_dir = Path::new(MARKDOWN_DIR);
+        let markdown_dir = dirs.markdown.as_path();
         if !markdown_dir.exists() {
             info!("Creating markdown directory at {:?}", markdown_dir);
             fs::create_dir_all(markdown_dir)
@@ -431,7 +786,7 @@ impl FileService {
         }
 
         // Create metadata directory if it doesn't exist
-        let metadata_dir = Path::new(METADATA_PATH).parent().unwrap();
+        let metadata_dir = dirs.metadata.as_path();
         if !metadata_dir.exists() {
             info!("Creating metadata directory at {:?}", metadata_dir);
             fs::create_dir_all(metadata_dir)
@@ -445,10 +800,10 @@ impl FileService {
         }
 
         // Verify permissions by attempting to create a test file
-        let test_file = format!("{}/test_permissions", MARKDOWN_DIR);
+        let test_file = dirs.markdown_file("test_permissions");
         match fs::write(&test_file, "test") {
             Ok(_) => {
-                info!("Successfully wrote test file to {}", test_file);
+                info!("Successfully wrote test file to {}", test_file.display());
                 fs::remove_file(&test_file)
                     .map_err(|e| Error::new(std::io::ErrorKind::Other, format!("Failed to remove test file: {}", e)))?;
                 info!("Successfully removed test file");
@@ -460,7 +815,7 @@ impl FileService {
                 if let Ok(current_dir) = std::env::current_dir() {
                     error!("Current directory: {:?}", current_dir);
                 }
-                if let Ok(dir_contents) = fs::read_dir(MARKDOWN_DIR) {
+                if let Ok(dir_contents) = fs::read_dir(markdown_dir) {
                     error!("Directory contents: {:?}", dir_contents);
                 }
                 Err(Error::new(std::io::ErrorKind::PermissionDenied, format!("Failed to verify directory permissions: {}", e)))
@@ -472,7 +827,7 @@ impl FileService {
     pub fn save_metadata(metadata: &MetadataStore) -> Result<(), Error> {
         let json = serde_json::to_string_pretty(metadata)
             .map_err(|e| Error::new(std::io::ErrorKind::Other, e.to_string()))?;
-        fs::write(METADATA_PATH, json)
+        fs::write(DataDirs::global().metadata_file(METADATA_FILE), json)
             .map_err(|e| Error::new(std::io::ErrorKind::Other, e.to_string()))?;
         Ok(())
     }
@@ -491,106 +846,197 @@ impl FileService {
         re.find_iter(content).count()
     }
 
+    /// Targets of the markdown hyperlinks in `content` that are http(s) URLs, each
+    /// once and in order
+    pub fn external_hyperlinks(content: &str) -> Vec<String> {
+        let re = Regex::new(r"\[([^\]]+)\]\(([^)]+)\)").unwrap();
+        let mut seen = HashSet::new();
+        re.captures_iter(content)
+            // Drop a link title, as in [text](https://example.com "Title")
+            .filter_map(|link| link[2].split_whitespace().next().map(str::to_string))
+            .filter(|target| target.starts_with("http://") || target.starts_with("https://"))
+            .filter(|target| seen.insert(target.clone()))
+            .collect()
+    }
+
+    /// Moves the metadata of files gone from the repository to the trash, unless
+    /// that removes more of the vault than the trash policy allows unconfirmed, in
+    /// which case they are kept and recorded in `held_deletions`. Files that are
+    /// back are restored from the trash first, so they keep their node ids.
+    fn trash_removed_files(
+        &self,
+        github_files: &[GitHubFileMetadata],
+        metadata_store: &mut MetadataStore,
+        sync_state: &mut SyncState,
+    ) {
+        let now = Utc::now();
+        let mut trash = Trash::load();
+        trash.purge_expired(now, self.trash_policy.retention);
+
+        let listed: HashSet<&str> = github_files.iter().map(|f| f.name.as_str()).collect();
+        for name in &listed {
+            if !metadata_store.contains_key(*name) {
+                if let Some(metadata) = trash.restore(name) {
+                    info!("Restoring {} from the trash", name);
+                    metadata_store.insert(name.to_string(), metadata);
+                }
+            }
+        }
+
+        let mut removed: Vec<String> = metadata_store.keys()
+            .filter(|name| !listed.contains(name.as_str()))
+            .cloned()
+            .collect();
+        removed.sort();
+        if !self.confirm_deletions && self.trash_policy.needs_confirmation(removed.len(), metadata_store.len()) {
+            warn!(
+                "Holding back removal of {} of {} files; more than {}% of the vault needs confirmation",
+                removed.len(), metadata_store.len(), self.trash_policy.max_delete_percent
+            );
+            sync_state.held_deletions = removed;
+            removed = Vec::new();
+        } else {
+            sync_state.held_deletions.clear();
+            for name in &removed {
+                if let Some(metadata) = metadata_store.get(name) {
+                    trash.put(metadata.clone(), now);
+                }
+            }
+        }
+
+        // Only drop metadata once the trash holding it is on disk
+        if let Err(e) = trash.save() {
+            error!("Failed to save trash, keeping {} removed files: {}", removed.len(), e);
+            return;
+        }
+        for name in &removed {
+            metadata_store.remove(name);
+        }
+        if !removed.is_empty() {
+            info!("Moved {} files missing from the repository to the trash", removed.len());
+        }
+    }
+
     /// Fetch and process files from GitHub
     pub async fn fetch_and_process_files(
         &self,
-        content_api: Arc<ContentAPI>,
+        content_api: Arc<dyn GitHubService>,
         _settings: Arc<RwLock<AppFullSettings>>, // Changed to AppFullSettings (though unused)
         metadata_store: &mut MetadataStore,
     ) -> Result<Vec<ProcessedFile>, Box<dyn StdError + Send + Sync>> {
         let mut processed_files = Vec::new();
 
         // Get all markdown files from GitHub
-        let github_files = content_api.list_markdown_files("").await?;
+        let github_files = match content_api.list_markdown_files("").await {
+            Ok(files) => files,
+            Err(e) => {
+                let mut sync_state = SyncState::load();
+                sync_state.fail(format!("Failed to list files: {}", e));
+                sync_state.checkpoint();
+                return Err(e);
+            }
+        };
         info!("Found {} markdown files in GitHub", github_files.len());
 
-        // Process files in batches to prevent timeouts
-        const BATCH_SIZE: usize = 5;
-        for chunk in github_files.chunks(BATCH_SIZE) {
-            let mut futures = Vec::new();
-            
-            for file_meta in chunk {
-                let file_meta = file_meta.clone();
-                let content_api = content_api.clone();
-                
-                futures.push(async move {
-                    // First check if file is public
-                    match content_api.check_file_public(&file_meta.download_url).await {
-                        Ok(is_public) => {
-                            if !is_public {
-                                debug!("Skipping non-public file: {}", file_meta.name);
-                                return Ok(None);
-                            }
+        let mut sync_state = SyncState::load();
+        self.trash_removed_files(&github_files, metadata_store, &mut sync_state);
+        let to_fetch = sync_state.begin(&github_files, |name| metadata_store.contains_key(name));
+        info!("{} of {} files changed or pending since the last sync", to_fetch.len(), github_files.len());
+        sync_state.checkpoint();
 
-                            // Only fetch full content for public files
-                            match content_api.fetch_file_content(&file_meta.download_url).await {
-                                Ok(content) => {
-                                    let file_path = format!("{}/{}", MARKDOWN_DIR, file_meta.name);
-                                    if let Err(e) = fs::write(&file_path, &content) {
-                                        error!("Failed to write file {}: {}", file_path, e);
-                                        return Err(e.into());
-                                    }
-
-                                    let file_size = content.len();
-                                    let node_size = Self::calculate_node_size(file_size);
-
-                                    let metadata = Metadata {
-                                        file_name: file_meta.name.clone(),
-                                        file_size,
-                                        node_size,
-                                        node_id: "0".to_string(), // Will be assigned properly later
-                                        hyperlink_count: Self::count_hyperlinks(&content),
-                                        sha1: Self::calculate_sha1(&content),
-                                        last_modified: file_meta.last_modified.unwrap_or_else(|| Utc::now()),
-                                        perplexity_link: String::new(),
-                                        last_perplexity_process: None,
-                                        topic_counts: HashMap::new(), // Will be updated later
-                                    };
-
-                                    Ok(Some(ProcessedFile {
-                                        file_name: file_meta.name.clone(),
-                                        content,
-                                        is_public: true,
-                                        metadata,
-                                    }))
-                                }
-                                Err(e) => {
-                                    error!("Failed to fetch content for {}: {}", file_meta.name, e);
-                                    Err(e)
+        for chunk in to_fetch.chunks(SYNC_CHECKPOINT_INTERVAL) {
+            for (file_meta, result) in Self::fetch_public_files(content_api.as_ref(), &self.visibility, chunk.to_vec()).await {
+                match result {
+                    Ok(Some(content)) => {
+                        let file_size = content.len();
+                        let node_size = Self::calculate_node_size(file_size);
+                        let existing = metadata_store.get(&file_meta.name);
+
+                        // Keep the node id and enrichment of files we already know about
+                        let metadata = Metadata {
+                            file_name: file_meta.name.clone(),
+                            file_size,
+                            node_size,
+                            node_id: existing
+                                .map(|m| m.node_id.clone())
+                                .unwrap_or_else(|| self.get_next_node_id().to_string()),
+                            hyperlink_count: Self::count_hyperlinks(&content),
+                            sha1: Self::calculate_sha1(&content),
+                            last_modified: file_meta.last_modified.unwrap_or_else(Utc::now),
+                            perplexity_link: existing.map(|m| m.perplexity_link.clone()).unwrap_or_default(),
+                            last_perplexity_process: existing.and_then(|m| m.last_perplexity_process),
+                            perplexity_summary: existing.map(|m| m.perplexity_summary.clone()).unwrap_or_default(),
+                            topic_counts: HashMap::new(), // Will be updated later
+                            unresolved_links: HashMap::new(),
+                            private_links: HashMap::new(),
+                            tags: Vec::new(),
+                            aliases: page_aliases(&content),
+                            link_previews: existing.map(|m| m.link_previews.clone()).unwrap_or_default(),
+                            page_stats: page_stats(&content),
+                            tasks: page_tasks(&content),
+                            near_duplicates: existing.map(|m| m.near_duplicates.clone()).unwrap_or_default(),
+                            link_checks: existing.map(|m| m.link_checks.clone()).unwrap_or_default(),
+                        };
+
+                        metadata_store.insert(file_meta.name.clone(), metadata.clone());
+                        sync_state.mark_processed(&file_meta.name, &file_meta.sha, true);
+                        processed_files.push(ProcessedFile {
+                            file_name: file_meta.name.clone(),
+                            content,
+                            is_public: true,
+                            metadata,
+                        });
+                    }
+                    Ok(None) => {
+                        // Skipped non-public file. One that was public until now leaves
+                        // the graph and the local mirror.
+                        if metadata_store.remove(&file_meta.name).is_some() {
+                            info!("{} is no longer public, removing it", file_meta.name);
+                            let file_path = DataDirs::global().markdown_file(&file_meta.name);
+                            if let Err(e) = fs::remove_file(&file_path) {
+                                if e.kind() != std::io::ErrorKind::NotFound {
+                                    warn!("Failed to remove local copy of {}: {}", file_meta.name, e);
                                 }
                             }
                         }
-                        Err(e) => {
-                            error!("Failed to check public status for {}: {}", file_meta.name, e);
-                            Err(e)
-                        }
-                    }
-                });
-            }
-
-            // Wait for batch to complete
-            let results = futures::future::join_all(futures).await;
-            
-            for result in results {
-                match result {
-                    Ok(Some(processed_file)) => {
-                        processed_files.push(processed_file);
+                        sync_state.mark_processed(&file_meta.name, &file_meta.sha, false);
                     }
-                    Ok(None) => continue, // Skipped non-public file
                     Err(e) => {
-                        error!("Failed to process file in batch: {}", e);
+                        error!("Failed to process file {}: {}", file_meta.name, e);
+                        sync_state.mark_failed(&file_meta.name, e.to_string());
                     }
                 }
             }
 
-            sleep(GITHUB_API_DELAY).await;
+            // Checkpoint metadata before the sync state so a resumed run never skips
+            // a file whose metadata wasn't written
+            if let Err(e) = Self::save_metadata(metadata_store) {
+                error!("Failed to checkpoint metadata during sync: {}", e);
+            } else {
+                sync_state.checkpoint();
+            }
         }
 
-        // Assign node IDs to any new files
-        self.update_node_ids(&mut processed_files);
+        sync_state.finish();
+        sync_state.checkpoint();
+        if !sync_state.failed.is_empty() {
+            warn!("Sync finished with {} failed files; they will be retried on the next run", sync_state.failed.len());
+        }
 
         // Update topic counts after all files are processed
-        Self::update_topic_counts(metadata_store)?;
+        Self::update_topic_counts(metadata_store, self.parser_profile, &sync_state.private_pages())?;
+        if let Some(previews) = LinkPreviewService::from_env() {
+            previews.enrich(metadata_store).await;
+        }
+        duplicates::detect(metadata_store, duplicates::threshold_from_env());
+
+        if let Some(event_bus) = &self.event_bus {
+            if !processed_files.is_empty() {
+                event_bus.publish(FileEvent::Processed {
+                    file_names: processed_files.iter().map(|pf| pf.file_name.clone()).collect(),
+                });
+            }
+        }
 
         Ok(processed_files)
     }
@@ -0,0 +1,103 @@
+//! Camera suggestions for flying to a note
+//!
+//! The camera looks at the centre of the sphere bounding the node and its direct
+//! neighbours, from far enough away that the whole sphere fits the field of view.
+//! It approaches from outside the graph, along the line from the graph's centroid
+//! through the node, so the neighbourhood isn't hidden behind the rest of the graph.
+
+use glam::Vec3;
+use serde::Serialize;
+use std::collections::HashSet;
+
+use crate::models::graph::GraphData;
+use crate::types::vec3::Vec3Data;
+
+pub const DEFAULT_FOV_DEGREES: f32 = 60.0;
+// Keeps a node without neighbours from filling the whole view
+const MIN_RADIUS: f32 = 1.0;
+// Leaves a margin around the neighbourhood
+const FRAMING: f32 = 1.2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CameraFocus {
+    pub node_id: u32,
+    /// Point to look at
+    pub target: Vec3Data,
+    pub position: Vec3Data,
+    pub up: Vec3Data,
+    /// Radius of the neighbourhood's bounding sphere
+    pub radius: f32,
+    pub neighbour_count: usize,
+}
+
+/// Where to put a camera with a vertical field of view of `fov_degrees` to frame
+/// `node_id` and its neighbours, or `None` if the node isn't in the graph
+pub fn suggest(graph: &GraphData, node_id: u32, fov_degrees: f32) -> Option<CameraFocus> {
+    let position_of = |id: u32| graph.nodes.iter().find(|n| n.id == id).map(|n| Vec3::from(n.data.position));
+    let node = position_of(node_id)?;
+
+    let neighbours: HashSet<u32> = graph.edges.iter()
+        .filter_map(|e| match (e.source == node_id, e.target == node_id) {
+            (true, false) => Some(e.target),
+            (false, true) => Some(e.source),
+            _ => None,
+        })
+        .collect();
+    let points: Vec<Vec3> = std::iter::once(node)
+        .chain(neighbours.iter().filter_map(|&id| position_of(id)))
+        .collect();
+
+    let target = points.iter().copied().sum::<Vec3>() / points.len() as f32;
+    let radius = points.iter().map(|p| p.distance(target)).fold(MIN_RADIUS, f32::max);
+
+    let half_fov = (fov_degrees.clamp(10.0, 170.0) / 2.0).to_radians();
+    let distance = radius * FRAMING / half_fov.sin();
+
+    let centroid = graph.nodes.iter().map(|n| Vec3::from(n.data.position)).sum::<Vec3>()
+        / graph.nodes.len() as f32;
+    let direction = (target - centroid).try_normalize().unwrap_or(Vec3::Z);
+    // Looking straight down the y axis leaves "up" undefined, so fall back to z
+    let up = if direction.cross(Vec3::Y).length_squared() < 1e-6 { Vec3::Z } else { Vec3::Y };
+
+    Some(CameraFocus {
+        node_id,
+        target: target.into(),
+        position: (target + direction * distance).into(),
+        up: up.into(),
+        radius,
+        neighbour_count: neighbours.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::edge::Edge;
+    use crate::models::node::Node;
+
+    fn node(id: u32, x: f32, y: f32, z: f32) -> Node {
+        let mut node = Node::new_with_id(id.to_string(), Some(id));
+        node.data.position = Vec3Data::new(x, y, z);
+        node
+    }
+
+    #[test]
+    fn test_frames_neighbourhood_from_outside() {
+        let mut graph = GraphData::new();
+        graph.nodes = vec![node(1, 10.0, 0.0, 0.0), node(2, 10.0, 4.0, 0.0), node(3, -10.0, 0.0, 0.0)];
+        graph.edges = vec![Edge::new(1, 2, 1.0), Edge::new(2, 3, 1.0)];
+
+        let focus = suggest(&graph, 1, 60.0).unwrap();
+        assert_eq!(focus.neighbour_count, 1);
+        assert_eq!(focus.target, Vec3Data::new(10.0, 2.0, 0.0));
+        assert!((focus.radius - 2.0).abs() < 1e-5);
+        // sin(30°) = 0.5, so the sphere fits at twice its framed radius
+        let offset = Vec3::from(focus.position) - Vec3::from(focus.target);
+        assert!((offset.length() - 4.8).abs() < 1e-4);
+        assert!(offset.x > 0.0);
+        assert_eq!(focus.up, Vec3Data::new(0.0, 1.0, 0.0));
+
+        assert!(suggest(&graph, 9, 60.0).is_none());
+    }
+}
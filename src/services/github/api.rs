@@ -18,6 +18,8 @@ pub struct GitHubClient {
     owner: String,
     repo: String,
     base_path: String,
+    max_concurrent_requests: usize,
+    branch: Option<String>,
     settings: Arc<RwLock<AppFullSettings>>, // Changed from Settings to AppFullSettings
 }
 
@@ -76,6 +78,8 @@ impl GitHubClient {
             owner: config.owner,
             repo: config.repo,
             base_path,
+            max_concurrent_requests: config.max_concurrent_requests.max(1),
+            branch: config.branch,
             settings: Arc::clone(&settings),
         })
     }
@@ -214,6 +218,20 @@ impl GitHubClient {
         url
     }
 
+    /// The same repository accessed with another token, e.g. a user's OAuth token
+    pub fn with_token(&self, token: String) -> Self {
+        Self {
+            client: self.client.clone(),
+            token,
+            owner: self.owner.clone(),
+            repo: self.repo.clone(),
+            base_path: self.base_path.clone(),
+            max_concurrent_requests: self.max_concurrent_requests,
+            branch: self.branch.clone(),
+            settings: self.settings.clone(),
+        }
+    }
+
     /// Get the client for making requests
     pub(crate) fn client(&self) -> &Client {
         &self.client
@@ -239,6 +257,16 @@ impl GitHubClient {
         &self.base_path
     }
 
+    /// Get the configured limit on concurrent requests
+    pub(crate) fn max_concurrent_requests(&self) -> usize {
+        self.max_concurrent_requests
+    }
+
+    /// Get the configured branch, if any
+    pub(crate) fn branch(&self) -> Option<&str> {
+        self.branch.as_deref()
+    }
+
     /// Get settings
     pub(crate) fn settings(&self) -> &Arc<RwLock<AppFullSettings>> { // Changed from Settings to AppFullSettings
         &self.settings
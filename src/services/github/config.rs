@@ -2,6 +2,8 @@ use std::env;
 use std::error::Error;
 use std::fmt;
 
+const DEFAULT_MAX_CONCURRENT_REQUESTS: usize = 8;
+
 #[derive(Debug)]
 pub enum GitHubConfigError {
     MissingEnvVar(String),
@@ -27,6 +29,10 @@ pub struct GitHubConfig {
     pub base_path: String,
     pub rate_limit: bool,
     pub version: String,
+    /// Upper bound on in-flight requests when fetching many files at once
+    pub max_concurrent_requests: usize,
+    /// Branch to sync from; the repository's default branch when unset
+    pub branch: Option<String>,
 }
 
 impl GitHubConfig {
@@ -51,6 +57,16 @@ impl GitHubConfig {
         let version = env::var("GITHUB_API_VERSION")
             .unwrap_or_else(|_| "v3".to_string());
 
+        let max_concurrent_requests = env::var("GITHUB_MAX_CONCURRENT_REQUESTS")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(DEFAULT_MAX_CONCURRENT_REQUESTS);
+
+        let branch = env::var("GITHUB_BRANCH")
+            .ok()
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty());
+
         let config = Self {
             token,
             owner,
@@ -58,6 +74,8 @@ impl GitHubConfig {
             base_path,
             rate_limit,
             version,
+            max_concurrent_requests,
+            branch,
         };
 
         config.validate()?;
@@ -65,6 +83,44 @@ impl GitHubConfig {
         Ok(config)
     }
 
+    /// Config for a repository given at runtime rather than through the environment,
+    /// with the defaults `from_env` falls back to
+    pub fn for_repository(
+        token: String,
+        owner: String,
+        repo: String,
+        base_path: String,
+        branch: Option<String>,
+    ) -> Result<Self, GitHubConfigError> {
+        let config = Self {
+            token,
+            owner,
+            repo,
+            base_path,
+            rate_limit: true,
+            version: "v3".to_string(),
+            max_concurrent_requests: DEFAULT_MAX_CONCURRENT_REQUESTS,
+            branch: branch.map(|b| b.trim().to_string()).filter(|b| !b.is_empty()),
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Config without credentials or a repository, for running without GitHub.
+    /// Every request made with it fails.
+    pub fn unconfigured() -> Self {
+        Self {
+            token: String::new(),
+            owner: String::new(),
+            repo: String::new(),
+            base_path: String::new(),
+            rate_limit: false,
+            version: "v3".to_string(),
+            max_concurrent_requests: 1,
+            branch: None,
+        }
+    }
+
     fn validate(&self) -> Result<(), GitHubConfigError> {
         if self.token.is_empty() {
             return Err(GitHubConfigError::ValidationError(
@@ -90,6 +146,12 @@ impl GitHubConfig {
             ));
         }
 
+        if self.max_concurrent_requests == 0 {
+            return Err(GitHubConfigError::ValidationError(
+                "GitHub max concurrent requests must be at least 1".to_string(),
+            ));
+        }
+
         Ok(())
     }
 }
@@ -153,9 +215,13 @@ mod tests {
         env::set_var("GITHUB_BASE_PATH", "path");
         env::set_var("GITHUB_RATE_LIMIT", "false");
         env::set_var("GITHUB_API_VERSION", "v4");
+        env::set_var("GITHUB_MAX_CONCURRENT_REQUESTS", "4");
+        env::set_var("GITHUB_BRANCH", "drafts");
 
         let config = GitHubConfig::from_env().unwrap();
         assert!(!config.rate_limit);
         assert_eq!(config.version, "v4");
+        assert_eq!(config.max_concurrent_requests, 4);
+        assert_eq!(config.branch.as_deref(), Some("drafts"));
     }
 }
\ No newline at end of file
@@ -1,7 +1,10 @@
 use super::api::GitHubClient;
 use super::types::{GitHubFileMetadata, GitHubError, RateLimitInfo};
+use crate::services::resilience::{self, Dependency};
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
 use chrono::{DateTime, Utc};
-use log::{debug, error, info};
+use futures::stream::{self, StreamExt};
+use log::{debug, error, info, warn};
 use std::error::Error;
 use std::sync::Arc;
 use reqwest::header::HeaderMap;
@@ -11,14 +14,28 @@ use std::time::Duration;
 use std::pin::Pin;
 use std::future::Future;
 
-const BATCH_SIZE: usize = 5;
-const BATCH_DELAY: Duration = Duration::from_millis(500);
+const MAX_RETRIES: u32 = 3;
+// Below this many remaining requests, calls are spread out over the rest of the window
+const RATE_LIMIT_LOW_WATERMARK: u32 = 50;
+const MAX_PACING_DELAY: Duration = Duration::from_secs(5);
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+/// Result of a conditional content download
+#[derive(Debug)]
+pub enum ConditionalContent {
+    Modified { content: String, etag: Option<String> },
+    NotModified,
+}
 
 /// Handles GitHub content API operations
 #[derive(Clone)]
 pub struct ContentAPI {
     client: Arc<GitHubClient>,
     rate_limits: Arc<RwLock<HashMap<String, RateLimitInfo>>>,
+    // Set from a `Retry-After` header; no request is sent before this instant
+    backoff_until: Arc<RwLock<Option<DateTime<Utc>>>>,
+    // Retries failed connections and 5xx responses, and stops calling GitHub while it is down
+    dependency: Arc<Dependency>,
 }
 
 impl ContentAPI {
@@ -27,9 +44,16 @@ impl ContentAPI {
         Self {
             client,
             rate_limits: Arc::new(RwLock::new(HashMap::new())),
+            backoff_until: Arc::new(RwLock::new(None)),
+            dependency: resilience::dependency(resilience::GITHUB),
         }
     }
 
+    /// Number of requests callers may have in flight at once
+    pub fn max_concurrent_requests(&self) -> usize {
+        self.client.max_concurrent_requests()
+    }
+
     /*
     /// Ensure consistent URL encoding for paths
     async fn encode_path(&self, path: &str) -> String {
@@ -114,10 +138,62 @@ impl ContentAPI {
         } else if debug_enabled {
             debug!("No rate limit headers found in response");
         }
+
+        if let Some(wait) = Self::retry_after(headers) {
+            warn!("GitHub requested a {}s pause (Retry-After)", wait.as_secs());
+            let until = Utc::now() + chrono::Duration::from_std(wait).unwrap_or_else(|_| chrono::Duration::zero());
+            let mut backoff = self.backoff_until.write().await;
+            match *backoff {
+                Some(current) if current >= until => {}
+                _ => *backoff = Some(until),
+            }
+        }
+    }
+
+    /// Parse a `Retry-After` header given in seconds, capped to a sane maximum
+    fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+        headers.get("retry-after")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .map(|secs| Duration::from_secs(secs).min(MAX_RETRY_AFTER))
+    }
+
+    /// Send a request built by `build`, waiting out rate limits and retrying throttled
+    /// responses (429, or 403 with an exhausted quota) up to `MAX_RETRIES` times.
+    /// The last response is returned as-is so callers keep their own status handling.
+    async fn send_with_retry<F>(&self, build: F) -> Result<reqwest::Response, Box<dyn Error + Send + Sync>>
+    where
+        F: Fn() -> reqwest::RequestBuilder,
+    {
+        let mut attempt = 0;
+        loop {
+            self.check_rate_limit().await?;
+            let response = self.dependency.send(&build).await?;
+            self.update_rate_limits(response.headers()).await;
+
+            let status = response.status().as_u16();
+            let has_retry_after = response.headers().contains_key("retry-after");
+            let quota_exhausted = response.headers()
+                .get("x-ratelimit-remaining")
+                .and_then(|v| v.to_str().ok())
+                == Some("0");
+            let throttled = status == 429 || (status == 403 && (has_retry_after || quota_exhausted));
+            if !throttled || attempt >= MAX_RETRIES {
+                return Ok(response);
+            }
+
+            attempt += 1;
+            warn!("GitHub throttled request with status {}, retry {}/{}", status, attempt, MAX_RETRIES);
+            // Retry-After and an exhausted quota are waited out by check_rate_limit;
+            // otherwise back off exponentially
+            if !has_retry_after && !quota_exhausted {
+                tokio::time::sleep(Duration::from_secs(1 << attempt)).await;
+            }
+        }
     }
 
     /// Check rate limits and handle backoff if needed
-    fn check_rate_limit(&self) -> Pin<Box<dyn Future<Output = Result<(), GitHubError>> + '_>> {
+    fn check_rate_limit(&self) -> Pin<Box<dyn Future<Output = Result<(), GitHubError>> + Send + '_>> {
         Box::pin(async move {
             let settings = self.client.settings().read().await;
             let debug_enabled = settings.system.debug.enabled;
@@ -127,6 +203,18 @@ impl ContentAPI {
                 debug!("Checking rate limits...");
             }
 
+            let backoff_until = *self.backoff_until.read().await;
+            if let Some(until) = backoff_until {
+                let now = Utc::now();
+                if now < until {
+                    let wait = (until - now).to_std().unwrap_or_default();
+                    debug!("Waiting {}ms for GitHub Retry-After to elapse", wait.as_millis());
+                    tokio::time::sleep(wait).await;
+                }
+            }
+
+            let mut pacing_delay = None;
+
             let limits = self.rate_limits.read().await;
             if let Some(info) = limits.get("core") {
                 if debug_enabled {
@@ -169,6 +257,13 @@ impl ContentAPI {
                     return Err(GitHubError::RateLimitExceeded(info.clone()));
                 }
 
+                if info.remaining < RATE_LIMIT_LOW_WATERMARK {
+                    // Spread the remaining quota over the time left until reset instead of
+                    // bursting into a hard limit
+                    let window = (info.reset_time - Utc::now()).to_std().unwrap_or_default();
+                    pacing_delay = Some((window / info.remaining.max(1)).min(MAX_PACING_DELAY));
+                }
+
                 if debug_enabled {
                     debug!("Rate limit check passed. Remaining: {}/{}",
                         info.remaining, info.limit);
@@ -176,25 +271,25 @@ impl ContentAPI {
             } else if debug_enabled {
                 debug!("No rate limit information available");
             }
+            drop(limits);
+
+            if let Some(delay) = pacing_delay {
+                debug!("Rate limit running low, pacing next request by {}ms", delay.as_millis());
+                tokio::time::sleep(delay).await;
+            }
             Ok(())
         })
     }
 
     /// Check if a file is public by reading just the first line
     pub async fn check_file_public(&self, download_url: &str) -> Result<bool, Box<dyn Error + Send + Sync>> {
-        // Check rate limits before making request
-        self.check_rate_limit().await?;
-
         // First try a HEAD request to get content length
-        let head_response = self.client.client()
-            .head(download_url)
-            .header("Authorization", format!("Bearer {}", self.client.token()))
-            .header("Accept", "application/vnd.github+json")
-            .send()
-            .await?;
-
-        // Update rate limits from HEAD response
-        self.update_rate_limits(head_response.headers()).await;
+        let head_response = self.send_with_retry(|| {
+            self.client.client()
+                .head(download_url)
+                .header("Authorization", format!("Bearer {}", self.client.token()))
+                .header("Accept", "application/vnd.github+json")
+        }).await?;
 
         // Get content length, default to 1024 if not available
         let content_length: u64 = head_response
@@ -213,16 +308,13 @@ impl ContentAPI {
 
         debug!("Using range {} for file of size {}", range, content_length);
 
-        let response = self.client.client()
-            .get(download_url)
-            .header("Authorization", format!("Bearer {}", self.client.token()))
-            .header("Accept", "application/vnd.github+json")
-            .header("Range", range)
-            .send()
-            .await?;
-
-        // Update rate limits from response headers
-        self.update_rate_limits(response.headers()).await;
+        let response = self.send_with_retry(|| {
+            self.client.client()
+                .get(download_url)
+                .header("Authorization", format!("Bearer {}", self.client.token()))
+                .header("Accept", "application/vnd.github+json")
+                .header("Range", range.as_str())
+        }).await?;
 
         let status = response.status();
         match status.as_u16() {
@@ -257,24 +349,46 @@ impl ContentAPI {
 
     /// Fetch full content of a file
     pub async fn fetch_file_content(&self, download_url: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
-        // Check rate limits before making request
-        self.check_rate_limit().await?;
-
-        let response = self.client.client()
-            .get(download_url)
-            .header("Authorization", format!("Bearer {}", self.client.token()))
-            .header("Accept", "application/vnd.github+json")
-            .send()
-            .await?;
+        match self.fetch_file_content_if_modified(download_url, None).await? {
+            ConditionalContent::Modified { content, .. } => Ok(content),
+            // Can't happen without an ETag, but don't pretend the file is empty
+            ConditionalContent::NotModified => Err(Box::new(GitHubError::ApiError(
+                format!("Unexpected 304 Not Modified for {}", download_url)
+            ))),
+        }
+    }
 
-        // Update rate limits from response headers
-        self.update_rate_limits(response.headers()).await;
+    /// Fetch full content of a file, sending `If-None-Match` when an ETag from an
+    /// earlier download is known
+    pub async fn fetch_file_content_if_modified(
+        &self,
+        download_url: &str,
+        etag: Option<&str>,
+    ) -> Result<ConditionalContent, Box<dyn Error + Send + Sync>> {
+        let response = self.send_with_retry(|| {
+            let request = self.client.client()
+                .get(download_url)
+                .header("Authorization", format!("Bearer {}", self.client.token()))
+                .header("Accept", "application/vnd.github+json");
+            match etag {
+                Some(etag) => request.header("If-None-Match", etag),
+                None => request,
+            }
+        }).await?;
 
         let status = response.status();
         match status.as_u16() {
             200 => {
+                let etag = response.headers()
+                    .get("etag")
+                    .and_then(|v| v.to_str().ok())
+                    .map(|v| v.to_string());
                 let content = response.text().await?;
-                Ok(content)
+                Ok(ConditionalContent::Modified { content, etag })
+            },
+            304 => {
+                debug!("Not modified since last download: {}", download_url);
+                Ok(ConditionalContent::NotModified)
             },
             404 => {
                 error!("File not found: {}", download_url);
@@ -296,11 +410,91 @@ impl ContentAPI {
         }
     }
 
-    /// Get the last modified time for a file
+    /// A file on the configured branch with the sha of its blob, or `None` if it
+    /// isn't there
+    pub async fn get_file_with_sha(&self, file_name: &str) -> Result<Option<(String, String)>, Box<dyn Error + Send + Sync>> {
+        let url = self.client.get_contents_url(file_name).await;
+        let response = self.send_with_retry(|| {
+            let request = self.client.client()
+                .get(&url)
+                .header("Authorization", format!("Bearer {}", self.client.token()))
+                .header("Accept", "application/vnd.github+json");
+            match self.client.branch() {
+                Some(branch) => request.query(&[("ref", branch)]),
+                None => request,
+            }
+        }).await?;
+
+        let status = response.status();
+        match status.as_u16() {
+            200 => {
+                let body: serde_json::Value = response.json().await?;
+                let sha = body["sha"].as_str().ok_or("SHA not found in response")?.to_string();
+                // GitHub wraps the base64 content in lines
+                let encoded: String = body["content"].as_str().unwrap_or_default().split_whitespace().collect();
+                let content = String::from_utf8(BASE64.decode(encoded)?)?;
+                Ok(Some((content, sha)))
+            }
+            404 => Ok(None),
+            _ => {
+                let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+                error!("Failed to get {}. Status: {}, Error: {}", file_name, status, error_text);
+                Err(Box::new(GitHubError::ApiError(format!("{} - {}", status, error_text))))
+            }
+        }
+    }
+
+    /// Creates or replaces a file on the configured branch in a commit of its own,
+    /// returning the commit's sha. `sha` is the blob being replaced; if the file has
+    /// changed since, the write fails with `GitHubError::Conflict`.
+    pub async fn put_file(
+        &self,
+        file_name: &str,
+        content: &str,
+        sha: Option<&str>,
+        message: &str,
+    ) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let url = self.client.get_contents_url(file_name).await;
+        let mut body = serde_json::json!({ "message": message, "content": BASE64.encode(content) });
+        if let Some(sha) = sha {
+            body["sha"] = sha.into();
+        }
+        if let Some(branch) = self.client.branch() {
+            body["branch"] = branch.into();
+        }
+        let response = self.send_with_retry(|| {
+            self.client.client()
+                .put(&url)
+                .header("Authorization", format!("Bearer {}", self.client.token()))
+                .header("Accept", "application/vnd.github+json")
+                .json(&body)
+        }).await?;
+
+        let status = response.status();
+        match status.as_u16() {
+            200 | 201 => {
+                let body: serde_json::Value = response.json().await?;
+                let commit = body["commit"]["sha"].as_str().ok_or("Commit SHA not found in response")?;
+                info!("Committed {} as {}", file_name, commit);
+                Ok(commit.to_string())
+            }
+            // 409 for a stale sha, 422 when a sha was expected or the file appeared
+            409 | 422 => Err(Box::new(GitHubError::Conflict(file_name.to_string()))),
+            _ => {
+                let error_text = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+                error!("Failed to write {}. Status: {}, Error: {}", file_name, status, error_text);
+                Err(Box::new(GitHubError::ApiError(format!("{} - {}", status, error_text))))
+            }
+        }
+    }
+
+    /// Get the last modified time for a file on the configured branch
     pub async fn get_file_last_modified(&self, file_path: &str) -> Result<DateTime<Utc>, Box<dyn Error + Send + Sync>> {
-        // Check rate limits before making request
-        self.check_rate_limit().await?;
+        self.get_file_last_modified_on(file_path, self.client.branch()).await
+    }
 
+    /// Get the last modified time for a file on `branch`, or the default branch if `None`
+    pub async fn get_file_last_modified_on(&self, file_path: &str, branch: Option<&str>) -> Result<DateTime<Utc>, Box<dyn Error + Send + Sync>> {
         // Use GitHubClient's path handling
         let encoded_path = self.client.get_full_path(file_path).await;
         let url = format!(
@@ -313,16 +507,14 @@ impl ContentAPI {
         debug!("Getting last modified time - Original path: {}, Encoded path: {}",
             file_path, encoded_path);
 
-        let response = self.client.client()
-            .get(&url)
-            .header("Authorization", format!("Bearer {}", self.client.token()))
-            .header("Accept", "application/vnd.github+json")
-            .query(&[("path", encoded_path.as_str()), ("per_page", "1")])
-            .send()
-            .await?;
-
-        // Update rate limits from response headers
-        self.update_rate_limits(response.headers()).await;
+        let response = self.send_with_retry(|| {
+            self.client.client()
+                .get(&url)
+                .header("Authorization", format!("Bearer {}", self.client.token()))
+                .header("Accept", "application/vnd.github+json")
+                .query(&[("path", encoded_path.as_str()), ("per_page", "1")])
+                .query(&branch.map(|b| vec![("sha", b)]).unwrap_or_default())
+        }).await?;
 
         let status = response.status();
         if !status.is_success() {
@@ -372,20 +564,26 @@ impl ContentAPI {
         }
     }
 
-    /// List all markdown files in a directory
+    /// List all markdown files in a directory on the configured branch
     pub async fn list_markdown_files(&self, path: &str) -> Result<Vec<GitHubFileMetadata>, Box<dyn Error + Send + Sync>> {
+        self.list_markdown_files_on(path, self.client.branch()).await
+    }
+
+    /// List all markdown files in a directory on `branch`, or the default branch if `None`
+    pub async fn list_markdown_files_on(&self, path: &str, branch: Option<&str>) -> Result<Vec<GitHubFileMetadata>, Box<dyn Error + Send + Sync>> {
         // Use GitHubClient's contents URL construction
         let url = self.client.get_contents_url(path).await;
         
-        info!("GitHub API Request: URL={}, Original Path={}",
-            url, path);
+        info!("GitHub API Request: URL={}, Original Path={}, Branch={}",
+            url, path, branch.unwrap_or("(default)"));
 
-        let response = self.client.client()
-            .get(&url)
-            .header("Authorization", format!("Bearer {}", self.client.token()))
-            .header("Accept", "application/vnd.github+json")
-            .send()
-            .await?;
+        let response = self.send_with_retry(|| {
+            self.client.client()
+                .get(&url)
+                .header("Authorization", format!("Bearer {}", self.client.token()))
+                .header("Accept", "application/vnd.github+json")
+                .query(&branch.map(|b| vec![("ref", b)]).unwrap_or_default())
+        }).await?;
 
         let status = response.status();
         let headers = response.headers().clone();
@@ -415,11 +613,7 @@ impl ContentAPI {
 
         if debug_enabled {
             debug!("Found {} total items in directory", contents.len());
-            debug!("Batch size: {}, Expected batches: {}",
-                BATCH_SIZE,
-                (contents.len() + BATCH_SIZE - 1) / BATCH_SIZE
-            );
-            
+
             // Log file types distribution
             let file_count = contents.iter()
                 .filter(|item| item["type"].as_str().unwrap_or("") == "file")
@@ -433,64 +627,45 @@ impl ContentAPI {
             debug!("Content distribution - Total: {}, Files: {}, Markdown: {}",
                 contents.len(), file_count, md_count);
         }
-        
-        let mut markdown_files = Vec::new();
-        let mut current_idx = 0;
-        
-        // Process files in batches
-        while current_idx < contents.len() {
-            let end_idx = (current_idx + BATCH_SIZE).min(contents.len());
-            let batch_number = current_idx / BATCH_SIZE + 1;
-            let total_batches = (contents.len() + BATCH_SIZE - 1) / BATCH_SIZE;
-            
-            if debug_enabled {
-                debug!("Starting batch {}/{} (items {}-{} of {})",
-                    batch_number,
-                    total_batches,
-                    current_idx + 1,
-                    end_idx,
-                    contents.len()
-                );
-            }
-            
-            for item in &contents[current_idx..end_idx] {
+
+        let candidates: Vec<serde_json::Value> = contents.into_iter()
+            .filter(|item| {
                 let item_type = item["type"].as_str().unwrap_or("");
                 let item_name = item["name"].as_str().unwrap_or("");
-                
+
                 if debug_enabled {
                     debug!("Examining item: type='{}', name='{}'", item_type, item_name);
                 }
+                if item_type != "file" || !item_name.ends_with(".md") {
+                    return false;
+                }
+                if debug_enabled && !item_name.contains("Debug Test Page") && !item_name.contains("debug linked node") {
+                    debug!("Skipping non-debug file in debug mode: {}", item_name);
+                    return false;
+                }
+                true
+            })
+            .collect();
+
+        let concurrency = self.max_concurrent_requests();
+        info!("Fetching last modified times for {} markdown files ({} concurrent requests)",
+            candidates.len(), concurrency);
+
+        // Last-modified lookups are one commits query per file; run them concurrently,
+        // relying on send_with_retry for rate limiting rather than fixed sleeps
+        let markdown_files: Vec<GitHubFileMetadata> = stream::iter(candidates)
+            .map(|item| async move {
+                let name = item["name"].as_str().unwrap_or("").to_string();
+                debug!("Processing markdown file: {}", name);
 
-                if item_type == "file" && item_name.ends_with(".md") {
-                    let name = item_name.to_string();
-                    
-                    if debug_enabled {
-                        if !name.contains("Debug Test Page") && !name.contains("debug linked node") {
-                            debug!("Skipping non-debug file in debug mode: {}", name);
-                            continue;
-                        }
-                        debug!("Processing debug markdown file: {}", name);
-                    } else {
-                        debug!("Processing markdown file: {}", name);
-                    }
-                
-                // Use the file name directly since base path is already handled
-                debug!("Repository path for commits query: {}", name);
-                
                 // Combine with base path and get last modified time
                 let full_path = if path.is_empty() {
                     name.clone()
                 } else {
                     format!("{}/{}", path.trim_matches('/'), name)
                 };
-                // Add delay between API calls within batch
-                tokio::time::sleep(BATCH_DELAY).await;
-                
-                if debug_enabled {
-                    debug!("Fetching last modified time for: {}", full_path);
-                }
 
-                let last_modified = match self.get_file_last_modified(&full_path).await {
+                let last_modified = match self.get_file_last_modified_on(&full_path, branch).await {
                     Ok(time) => {
                         if debug_enabled {
                             debug!("Got last modified time for {}: {}", name, time);
@@ -508,67 +683,30 @@ impl ContentAPI {
 
                 let sha = item["sha"].as_str().unwrap_or("").to_string();
                 let download_url = item["download_url"].as_str().unwrap_or("").to_string();
-                
+
                 if debug_enabled {
                     debug!("Collecting metadata - Name: {}, SHA: {}, URL: {}",
                         name, sha, download_url);
                 }
-                
-                markdown_files.push(GitHubFileMetadata {
+
+                GitHubFileMetadata {
                     name,
                     sha,
                     download_url,
                     etag: None,
                     last_checked: Some(Utc::now()),
                     last_modified,
-                });
-                }
-            }
-            
-            // Move to next batch
-            current_idx = end_idx;
-            
-            let batch_number = current_idx / BATCH_SIZE;
-            let total_batches = (contents.len() + BATCH_SIZE - 1) / BATCH_SIZE;
-            let progress = (current_idx * 100) / contents.len();
-            
-            // Log batch completion with detailed stats
-            info!("Completed batch {}/{} - {}% complete ({} files processed)",
-                batch_number,
-                total_batches,
-                progress,
-                markdown_files.len()
-            );
-            
-            if debug_enabled {
-                let remaining_items = contents.len() - current_idx;
-                let est_remaining_batches = (remaining_items + BATCH_SIZE - 1) / BATCH_SIZE;
-                let est_remaining_time = est_remaining_batches as u64 * BATCH_DELAY.as_secs();
-                
-                debug!("Batch performance - Remaining items: {}, Est. remaining batches: {}, Est. time: {}s",
-                    remaining_items,
-                    est_remaining_batches,
-                    est_remaining_time
-                );
-            }
-            
-            // Add delay between batches if not the last batch
-            if current_idx < contents.len() {
-                if debug_enabled {
-                    debug!("Adding inter-batch delay of {}ms", BATCH_DELAY.as_millis());
                 }
-                tokio::time::sleep(BATCH_DELAY).await;
-            }
-        }
+            })
+            .buffered(concurrency)
+            .collect()
+            .await;
 
         if debug_enabled {
             info!("Debug mode: Processing only debug test files");
         }
 
-        info!("Found {} markdown files in {} batches",
-            markdown_files.len(),
-            (contents.len() + BATCH_SIZE - 1) / BATCH_SIZE
-        );
+        info!("Found {} markdown files", markdown_files.len());
         Ok(markdown_files)
     }
 }
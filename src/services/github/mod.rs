@@ -1,22 +1,29 @@
 //! GitHub service module providing API interactions for content and pull requests
 //!
 //! This module is split into:
-//! - Content API: Handles fetching and checking markdown files
+//! - Content API: Handles fetching and checking markdown files, and committing
+//!   single files straight to the branch
 //! - Pull Request API: Manages creation and updates of pull requests
 //! - Common types and error handling
 //! - Configuration: Environment-based configuration
+//! - OAuth: Device flow for per-user tokens
+//! - Service: The operations syncing needs, so tests can swap in a fake repository
 
 mod api;
 mod content;
 mod pr;
+mod oauth;
+mod service;
 pub mod types;
 pub mod config;
 
 pub use api::GitHubClient;
-pub use content::ContentAPI;
+pub use content::{ContentAPI, ConditionalContent};
 pub use pr::PullRequestAPI;
 pub use types::{GitHubError, GitHubFile, GitHubFileMetadata};
 pub use config::GitHubConfig;
+pub use oauth::{DeviceAuthorization, DevicePoll, GitHubOAuth};
+pub use service::GitHubService;
 
 // Re-export commonly used types for convenience
-pub use types::{ContentResponse, PullRequestResponse};
\ No newline at end of file
+pub use types::{ContentResponse, PullRequestResponse, PullRequestSummary, MergeMethod};
\ No newline at end of file
@@ -0,0 +1,219 @@
+//! GitHub OAuth device flow for per-user tokens
+//!
+//! A user starts the flow, enters the returned code at GitHub's verification page,
+//! and the client polls until GitHub hands over a token. Pending flows are held in
+//! memory per Nostr pubkey; the resulting tokens are stored by the caller.
+//! Requires an OAuth app with device flow enabled, named by `GITHUB_OAUTH_CLIENT_ID`.
+
+use chrono::{DateTime, Duration, Utc};
+use log::{info, warn};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use tokio::sync::Mutex;
+
+const DEVICE_CODE_URL: &str = "https://github.com/login/device/code";
+const ACCESS_TOKEN_URL: &str = "https://github.com/login/oauth/access_token";
+const USER_URL: &str = "https://api.github.com/user";
+const DEVICE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+const DEFAULT_SCOPES: &str = "repo";
+
+/// What the user needs to authorize the server
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceAuthorization {
+    pub user_code: String,
+    pub verification_uri: String,
+    pub expires_at: DateTime<Utc>,
+    /// Seconds to wait between polls
+    pub interval: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeviceCodeResponse {
+    device_code: String,
+    user_code: String,
+    verification_uri: String,
+    expires_in: i64,
+    interval: u64,
+}
+
+struct PendingDevice {
+    device_code: String,
+    expires_at: DateTime<Utc>,
+    interval: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DevicePoll {
+    /// The user hasn't entered the code yet; poll again after `interval` seconds
+    Pending { interval: u64 },
+    Authorized { token: String, scopes: Vec<String> },
+    Expired,
+    Denied,
+}
+
+pub struct GitHubOAuth {
+    http: Client,
+    client_id: String,
+    scopes: String,
+    pending: Mutex<HashMap<String, PendingDevice>>,
+}
+
+impl GitHubOAuth {
+    /// Reads `GITHUB_OAUTH_CLIENT_ID` and `GITHUB_OAUTH_SCOPES`; `None` without a client id
+    pub fn from_env() -> Option<Self> {
+        let client_id = std::env::var("GITHUB_OAUTH_CLIENT_ID").ok()
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())?;
+        let scopes = std::env::var("GITHUB_OAUTH_SCOPES").ok()
+            .map(|v| v.trim().to_string())
+            .filter(|v| !v.is_empty())
+            .unwrap_or_else(|| DEFAULT_SCOPES.to_string());
+        info!("GitHub OAuth device flow enabled with scopes '{}'", scopes);
+        Some(Self {
+            http: Client::builder()
+                .user_agent("github-api-client")
+                .timeout(std::time::Duration::from_secs(30))
+                .build()
+                .unwrap_or_default(),
+            client_id,
+            scopes,
+            pending: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Asks GitHub for a device code for `pubkey`, replacing any flow it had pending
+    pub async fn start_device_flow(&self, pubkey: &str) -> Result<DeviceAuthorization, String> {
+        let response = self.http.post(DEVICE_CODE_URL)
+            .header("Accept", "application/json")
+            .form(&[("client_id", self.client_id.as_str()), ("scope", self.scopes.as_str())])
+            .send()
+            .await
+            .map_err(|e| format!("Failed to reach GitHub: {}", e))?;
+        if !response.status().is_success() {
+            return Err(format!("GitHub refused the device code request: {}", response.status()));
+        }
+        let code: DeviceCodeResponse = response.json().await
+            .map_err(|e| format!("Unexpected device code response: {}", e))?;
+
+        let expires_at = Utc::now() + Duration::seconds(code.expires_in);
+        self.pending.lock().await.insert(pubkey.to_string(), PendingDevice {
+            device_code: code.device_code,
+            expires_at,
+            interval: code.interval,
+        });
+        Ok(DeviceAuthorization {
+            user_code: code.user_code,
+            verification_uri: code.verification_uri,
+            expires_at,
+            interval: code.interval,
+        })
+    }
+
+    /// Checks once whether the user has authorized the pending flow of `pubkey`.
+    /// The flow is forgotten once it finishes either way.
+    pub async fn poll(&self, pubkey: &str) -> Result<DevicePoll, String> {
+        let (device_code, interval) = {
+            let mut pending = self.pending.lock().await;
+            match pending.get(pubkey) {
+                None => return Err("No GitHub authorization is pending".to_string()),
+                Some(device) if device.expires_at <= Utc::now() => {
+                    pending.remove(pubkey);
+                    return Ok(DevicePoll::Expired);
+                }
+                Some(device) => (device.device_code.clone(), device.interval),
+            }
+        };
+
+        let response = self.http.post(ACCESS_TOKEN_URL)
+            .header("Accept", "application/json")
+            .form(&[
+                ("client_id", self.client_id.as_str()),
+                ("device_code", device_code.as_str()),
+                ("grant_type", DEVICE_GRANT_TYPE),
+            ])
+            .send()
+            .await
+            .map_err(|e| format!("Failed to reach GitHub: {}", e))?;
+        let body: Value = response.json().await
+            .map_err(|e| format!("Unexpected access token response: {}", e))?;
+
+        let outcome = interpret_token_response(&body, interval)?;
+        let mut pending = self.pending.lock().await;
+        match &outcome {
+            DevicePoll::Pending { interval } => {
+                if let Some(device) = pending.get_mut(pubkey) {
+                    device.interval = *interval;
+                }
+            }
+            _ => {
+                pending.remove(pubkey);
+            }
+        }
+        Ok(outcome)
+    }
+
+    /// The GitHub login a token belongs to, for showing which account is connected
+    pub async fn login_for(&self, token: &str) -> Option<String> {
+        let response = self.http.get(USER_URL)
+            .header("Authorization", format!("Bearer {}", token))
+            .header("Accept", "application/vnd.github+json")
+            .send()
+            .await
+            .ok()?;
+        if !response.status().is_success() {
+            warn!("Failed to look up GitHub user: {}", response.status());
+            return None;
+        }
+        let user: Value = response.json().await.ok()?;
+        user["login"].as_str().map(str::to_string)
+    }
+}
+
+/// Maps GitHub's access token response onto the state of the flow. GitHub answers
+/// with 200 and an `error` field while the flow is unfinished.
+fn interpret_token_response(body: &Value, interval: u64) -> Result<DevicePoll, String> {
+    if let Some(token) = body["access_token"].as_str() {
+        let scopes = body["scope"].as_str().unwrap_or("")
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+            .collect();
+        return Ok(DevicePoll::Authorized { token: token.to_string(), scopes });
+    }
+    match body["error"].as_str() {
+        Some("authorization_pending") => Ok(DevicePoll::Pending { interval }),
+        // GitHub sends the interval to use from now on
+        Some("slow_down") => Ok(DevicePoll::Pending {
+            interval: body["interval"].as_u64().unwrap_or(interval + 5),
+        }),
+        Some("expired_token") => Ok(DevicePoll::Expired),
+        Some("access_denied") => Ok(DevicePoll::Denied),
+        Some(error) => Err(format!(
+            "GitHub authorization failed: {}",
+            body["error_description"].as_str().unwrap_or(error)
+        )),
+        None => Err("Unexpected access token response".to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_interpret_token_response() {
+        assert_eq!(
+            interpret_token_response(&json!({"access_token": "gho_abc", "token_type": "bearer", "scope": "repo,read:user"}), 5),
+            Ok(DevicePoll::Authorized { token: "gho_abc".to_string(), scopes: vec!["repo".to_string(), "read:user".to_string()] })
+        );
+        assert_eq!(interpret_token_response(&json!({"error": "authorization_pending"}), 5), Ok(DevicePoll::Pending { interval: 5 }));
+        assert_eq!(interpret_token_response(&json!({"error": "slow_down", "interval": 10}), 5), Ok(DevicePoll::Pending { interval: 10 }));
+        assert_eq!(interpret_token_response(&json!({"error": "access_denied"}), 5), Ok(DevicePoll::Denied));
+        assert!(interpret_token_response(&json!({"error": "incorrect_client_credentials"}), 5).is_err());
+    }
+}
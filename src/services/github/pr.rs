@@ -1,7 +1,11 @@
 use super::api::GitHubClient;
-use super::types::{CreateBranchRequest, CreatePullRequest, UpdateFileRequest, PullRequestResponse};
+use super::types::{
+    CreateBranchRequest, CreatePullRequest, UpdateFileRequest, PullRequestResponse,
+    PullRequestInfo, PullRequestFile, PullRequestSummary, MergeMethod,
+};
 use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
 use log::{error, info};
+use serde_json::json;
 use std::error::Error;
 use chrono::Utc;
 
@@ -25,28 +29,53 @@ impl PullRequestAPI {
         content: &str,
         original_sha: &str,
     ) -> Result<String, Box<dyn Error + Send + Sync>> {
-        let timestamp = Utc::now().timestamp();
-        let branch_name = format!("update-{}-{}", file_name.replace(".md", ""), timestamp);
-        
+        let branch_name = format!("update-{}-{}", file_name.replace(".md", ""), Utc::now().timestamp());
+        self.open_file_pull_request(file_name, content, Some(original_sha), &branch_name, format!("Update: {}", file_name)).await
+    }
+
+    /// Create a pull request adding a new page
+    pub async fn create_page_pull_request(
+        &self,
+        file_name: &str,
+        content: &str,
+    ) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let branch_name = format!("add-{}-{}", file_name.replace(".md", ""), Utc::now().timestamp());
+        self.open_file_pull_request(file_name, content, None, &branch_name, format!("Add: {}", file_name)).await
+    }
+
+    /// Writes `content` to `file_name` on a new branch and opens a pull request for
+    /// it, returning its URL. Without `original_sha` the file is created.
+    async fn open_file_pull_request(
+        &self,
+        file_name: &str,
+        content: &str,
+        original_sha: Option<&str>,
+        branch_name: &str,
+        title: String,
+    ) -> Result<String, Box<dyn Error + Send + Sync>> {
         let main_sha = self.get_main_branch_sha().await?;
-        self.create_branch(&branch_name, &main_sha).await?;
+        self.create_branch(branch_name, &main_sha).await?;
         
         let file_path = format!("{}/{}", self.client.base_path(), file_name);
-        let new_sha = self.update_file(&file_path, content, &branch_name, original_sha).await?;
+        let new_sha = self.update_file(&file_path, content, branch_name, original_sha).await?;
         
         let url = format!(
             "https://api.github.com/repos/{}/{}/pulls",
             self.client.owner(), self.client.repo()
         );
 
-        let pr_body = CreatePullRequest {
-            title: format!("Update: {}", file_name),
-            head: branch_name,
-            base: "main".to_string(),
-            body: format!(
+        let body = match original_sha {
+            Some(original_sha) => format!(
                 "This PR updates content for {}.\n\nOriginal SHA: {}\nNew SHA: {}",
                 file_name, original_sha, new_sha
             ),
+            None => format!("This PR adds {}.\n\nNew SHA: {}", file_name, new_sha),
+        };
+        let pr_body = CreatePullRequest {
+            title,
+            head: branch_name.to_string(),
+            base: self.base_branch().to_string(),
+            body,
         };
 
         let response = self.client.client()
@@ -68,13 +97,167 @@ impl PullRequestAPI {
         Ok(pr_response.html_url)
     }
 
-    /// Get the SHA of the main branch
-    async fn get_main_branch_sha(&self) -> Result<String, Box<dyn Error + Send + Sync>> {
+    /// Branch PRs are opened against: the configured sync branch, or `main`
+    fn base_branch(&self) -> &str {
+        self.client.branch().unwrap_or("main")
+    }
+
+    /// List open pull requests against the base branch. Their files aren't looked
+    /// up, which would take a request per pull request.
+    pub async fn list_open_pull_requests(&self) -> Result<Vec<PullRequestSummary>, Box<dyn Error + Send + Sync>> {
         let url = format!(
-            "https://api.github.com/repos/{}/{}/git/ref/heads/main",
+            "https://api.github.com/repos/{}/{}/pulls",
             self.client.owner(), self.client.repo()
         );
 
+        let response = self.client.client()
+            .get(&url)
+            .header("Authorization", format!("Bearer {}", self.client.token()))
+            .header("Accept", "application/vnd.github+json")
+            .query(&[("state", "open"), ("base", self.base_branch()), ("per_page", "100")])
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            error!("Failed to list pull requests: {}", error_text);
+            return Err(format!("GitHub API error: {}", error_text).into());
+        }
+
+        let pulls: Vec<PullRequestInfo> = response.json().await?;
+        Ok(pulls.into_iter().map(|pr| PullRequestSummary::of(pr, None)).collect())
+    }
+
+    /// Look up a single open pull request
+    pub async fn get_pull_request(&self, number: u32) -> Result<PullRequestSummary, Box<dyn Error + Send + Sync>> {
+        let url = format!(
+            "https://api.github.com/repos/{}/{}/pulls/{}",
+            self.client.owner(), self.client.repo(), number
+        );
+
+        let response = self.client.client()
+            .get(&url)
+            .header("Authorization", format!("Bearer {}", self.client.token()))
+            .header("Accept", "application/vnd.github+json")
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            error!("Failed to get PR #{}: {}", number, error_text);
+            return Err(format!("GitHub API error: {}", error_text).into());
+        }
+
+        let pr: PullRequestInfo = response.json().await?;
+        let files = self.list_markdown_changes(pr.number).await?;
+        Ok(PullRequestSummary::of(pr, Some(files)))
+    }
+
+    /// Markdown files under the base path touched by a pull request, relative to the base path
+    async fn list_markdown_changes(&self, number: u32) -> Result<Vec<String>, Box<dyn Error + Send + Sync>> {
+        let url = format!(
+            "https://api.github.com/repos/{}/{}/pulls/{}/files",
+            self.client.owner(), self.client.repo(), number
+        );
+
+        let response = self.client.client()
+            .get(&url)
+            .header("Authorization", format!("Bearer {}", self.client.token()))
+            .header("Accept", "application/vnd.github+json")
+            .query(&[("per_page", "100")])
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            error!("Failed to list files of PR #{}: {}", number, error_text);
+            return Err(format!("GitHub API error: {}", error_text).into());
+        }
+
+        let files: Vec<PullRequestFile> = response.json().await?;
+        let prefix = format!("{}/", self.client.base_path().trim_matches('/'));
+        Ok(files.into_iter()
+            .filter(|f| f.filename.ends_with(".md"))
+            .filter_map(|f| f.filename.strip_prefix(&prefix).map(|name| name.to_string()))
+            // Only direct children of the base path become nodes
+            .filter(|name| !name.contains('/'))
+            .collect())
+    }
+
+    /// Submit an approving review
+    pub async fn approve_pull_request(&self, number: u32, comment: Option<&str>) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let url = format!(
+            "https://api.github.com/repos/{}/{}/pulls/{}/reviews",
+            self.client.owner(), self.client.repo(), number
+        );
+
+        let mut body = json!({ "event": "APPROVE" });
+        if let Some(comment) = comment.filter(|c| !c.is_empty()) {
+            body["body"] = json!(comment);
+        }
+
+        let response = self.client.client()
+            .post(&url)
+            .header("Authorization", format!("Bearer {}", self.client.token()))
+            .header("Accept", "application/vnd.github+json")
+            .json(&body)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            error!("Failed to approve PR #{}: {}", number, error_text);
+            return Err(format!("GitHub API error: {}", error_text).into());
+        }
+
+        info!("Approved PR #{}", number);
+        Ok(())
+    }
+
+    /// Merge a pull request. `expected_head_sha` guards against merging commits
+    /// pushed after the reviewer looked at the diff.
+    pub async fn merge_pull_request(
+        &self,
+        number: u32,
+        method: MergeMethod,
+        expected_head_sha: Option<&str>,
+    ) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let url = format!(
+            "https://api.github.com/repos/{}/{}/pulls/{}/merge",
+            self.client.owner(), self.client.repo(), number
+        );
+
+        let mut body = json!({ "merge_method": method });
+        if let Some(sha) = expected_head_sha {
+            body["sha"] = json!(sha);
+        }
+
+        let response = self.client.client()
+            .put(&url)
+            .header("Authorization", format!("Bearer {}", self.client.token()))
+            .header("Accept", "application/vnd.github+json")
+            .json(&body)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let error_text = response.text().await?;
+            error!("Failed to merge PR #{}: {}", number, error_text);
+            return Err(format!("GitHub API error: {}", error_text).into());
+        }
+
+        let response_json: serde_json::Value = response.json().await?;
+        info!("Merged PR #{}", number);
+        Ok(response_json["sha"].as_str().unwrap_or_default().to_string())
+    }
+
+    /// Get the SHA of the base branch
+    async fn get_main_branch_sha(&self) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let url = format!(
+            "https://api.github.com/repos/{}/{}/git/ref/heads/{}",
+            self.client.owner(), self.client.repo(), self.base_branch()
+        );
+
         let response = self.client.client()
             .get(&url)
             .header("Authorization", format!("Bearer {}", self.client.token()))
@@ -130,7 +313,7 @@ impl PullRequestAPI {
         file_path: &str,
         content: &str,
         branch_name: &str,
-        original_sha: &str,
+        original_sha: Option<&str>,
     ) -> Result<String, Box<dyn Error + Send + Sync>> {
         let url = format!(
             "https://api.github.com/repos/{}/{}/contents/{}",
@@ -140,9 +323,9 @@ impl PullRequestAPI {
         let encoded_content = BASE64.encode(content);
         
         let body = UpdateFileRequest {
-            message: format!("Update {}", file_path),
+            message: format!("{} {}", if original_sha.is_some() { "Update" } else { "Add" }, file_path),
             content: encoded_content,
-            sha: original_sha.to_string(),
+            sha: original_sha.map(str::to_string),
             branch: branch_name.to_string(),
         };
 
@@ -0,0 +1,54 @@
+use super::content::{ConditionalContent, ContentAPI};
+use super::types::GitHubFileMetadata;
+use async_trait::async_trait;
+use std::error::Error;
+
+/// The repository operations syncing needs. `ContentAPI` talks to GitHub; tests
+/// substitute an in-memory repository (see `crate::test_support`).
+#[async_trait]
+pub trait GitHubService: Send + Sync {
+    /// Number of requests callers may have in flight at once
+    fn max_concurrent_requests(&self) -> usize;
+
+    /// List all markdown files in a directory on the configured branch
+    async fn list_markdown_files(&self, path: &str) -> Result<Vec<GitHubFileMetadata>, Box<dyn Error + Send + Sync>>;
+
+    /// List all markdown files in a directory on `branch`, or the default branch if `None`
+    async fn list_markdown_files_on(&self, path: &str, branch: Option<&str>) -> Result<Vec<GitHubFileMetadata>, Box<dyn Error + Send + Sync>>;
+
+    async fn fetch_file_content(&self, download_url: &str) -> Result<String, Box<dyn Error + Send + Sync>>;
+
+    /// Fetch a file unless it still has the ETag of an earlier download
+    async fn fetch_file_content_if_modified(
+        &self,
+        download_url: &str,
+        etag: Option<&str>,
+    ) -> Result<ConditionalContent, Box<dyn Error + Send + Sync>>;
+}
+
+#[async_trait]
+impl GitHubService for ContentAPI {
+    fn max_concurrent_requests(&self) -> usize {
+        ContentAPI::max_concurrent_requests(self)
+    }
+
+    async fn list_markdown_files(&self, path: &str) -> Result<Vec<GitHubFileMetadata>, Box<dyn Error + Send + Sync>> {
+        ContentAPI::list_markdown_files(self, path).await
+    }
+
+    async fn list_markdown_files_on(&self, path: &str, branch: Option<&str>) -> Result<Vec<GitHubFileMetadata>, Box<dyn Error + Send + Sync>> {
+        ContentAPI::list_markdown_files_on(self, path, branch).await
+    }
+
+    async fn fetch_file_content(&self, download_url: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+        ContentAPI::fetch_file_content(self, download_url).await
+    }
+
+    async fn fetch_file_content_if_modified(
+        &self,
+        download_url: &str,
+        etag: Option<&str>,
+    ) -> Result<ConditionalContent, Box<dyn Error + Send + Sync>> {
+        ContentAPI::fetch_file_content_if_modified(self, download_url, etag).await
+    }
+}
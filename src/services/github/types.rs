@@ -28,6 +28,8 @@ pub enum GitHubError {
     RateLimitExceeded(RateLimitInfo),
     /// Resource not found
     NotFound(String),
+    /// A write was refused because the file changed since it was read
+    Conflict(String),
 }
 
 impl fmt::Display for GitHubError {
@@ -45,6 +47,9 @@ impl fmt::Display for GitHubError {
             GitHubError::NotFound(path) => {
                 write!(f, "Resource not found: {}", path)
             }
+            GitHubError::Conflict(path) => {
+                write!(f, "{} changed since it was read", path)
+            }
         }
     }
 }
@@ -119,6 +124,90 @@ pub struct PullRequestResponse {
     pub state: String,
 }
 
+/// Open pull request as listed by the GitHub pulls API
+#[derive(Debug, Deserialize, Clone)]
+pub struct PullRequestInfo {
+    pub number: u32,
+    pub title: String,
+    pub html_url: String,
+    pub state: String,
+    #[serde(default)]
+    pub draft: bool,
+    pub user: GitHubUser,
+    pub head: PullRequestRef,
+    pub base: PullRequestRef,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct GitHubUser {
+    pub login: String,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PullRequestRef {
+    #[serde(rename = "ref")]
+    pub ref_name: String,
+    pub sha: String,
+}
+
+/// File touched by a pull request
+#[derive(Debug, Deserialize, Clone)]
+pub struct PullRequestFile {
+    pub filename: String,
+    pub status: String,
+    #[serde(default)]
+    pub additions: u32,
+    #[serde(default)]
+    pub deletions: u32,
+}
+
+/// Open pull request against the sync branch
+#[derive(Debug, Serialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PullRequestSummary {
+    pub number: u32,
+    pub title: String,
+    pub url: String,
+    pub author: String,
+    pub draft: bool,
+    pub head_ref: String,
+    pub head_sha: String,
+    pub base_ref: String,
+    pub updated_at: DateTime<Utc>,
+    /// Markdown file names (relative to the base path) the PR adds, changes or removes.
+    /// Only looked up for a single pull request, not when listing them.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub files: Option<Vec<String>>,
+}
+
+impl PullRequestSummary {
+    pub fn of(pr: PullRequestInfo, files: Option<Vec<String>>) -> Self {
+        Self {
+            number: pr.number,
+            title: pr.title,
+            url: pr.html_url,
+            author: pr.user.login,
+            draft: pr.draft,
+            head_ref: pr.head.ref_name,
+            head_sha: pr.head.sha,
+            base_ref: pr.base.ref_name,
+            updated_at: pr.updated_at,
+            files,
+        }
+    }
+}
+
+/// How a pull request should be merged
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MergeMethod {
+    #[default]
+    Merge,
+    Squash,
+    Rebase,
+}
+
 /// Request to create a new branch
 #[derive(Debug, Serialize)]
 pub struct CreateBranchRequest {
@@ -140,6 +229,8 @@ pub struct CreatePullRequest {
 pub struct UpdateFileRequest {
     pub message: String,
     pub content: String,
-    pub sha: String,
+    /// Blob being replaced; left out when the file is created
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha: Option<String>,
     pub branch: String,
 }
\ No newline at end of file
@@ -0,0 +1,323 @@
+//! Binary glTF (GLB) export of the graph scene
+//!
+//! Bakes the current layout into a file Blender and most XR tools can open. Every
+//! node is a sphere at its current position, sharing one sphere mesh per colour,
+//! and the edges are a single line mesh. Each node keeps its graph id and metadata
+//! id in `extras`, so imported objects can be traced back to their notes.
+
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+use crate::config::VisualisationSettings;
+use crate::models::graph::GraphData;
+use crate::services::snapshot::parse_hex_color;
+
+const GLB_MAGIC: u32 = 0x4654_6c67; // "glTF"
+const GLB_VERSION: u32 = 2;
+const CHUNK_JSON: u32 = 0x4e4f_534a;
+const CHUNK_BIN: u32 = 0x004e_4942;
+
+// glTF enums
+const FLOAT: u32 = 5126;
+const UNSIGNED_SHORT: u32 = 5123;
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const MODE_LINES: u32 = 1;
+
+const SPHERE_SEGMENTS: u16 = 16;
+const SPHERE_RINGS: u16 = 12;
+
+type Rgb = [u8; 3];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportStyle {
+    pub node_color: Rgb,
+    /// Radius of the largest note; smaller notes shrink to half of it
+    pub node_radius: f32,
+    pub metalness: f32,
+    pub roughness: f32,
+    pub edge_color: Rgb,
+    pub edge_opacity: f32,
+}
+
+impl Default for ExportStyle {
+    fn default() -> Self {
+        Self {
+            node_color: [0x66, 0xd9, 0xef],
+            node_radius: 0.1,
+            metalness: 0.5,
+            roughness: 0.5,
+            edge_color: [0x56, 0xb6, 0xc2],
+            edge_opacity: 0.25,
+        }
+    }
+}
+
+impl ExportStyle {
+    pub fn from_settings(vis: &VisualisationSettings) -> Self {
+        let default = Self::default();
+        Self {
+            node_color: parse_hex_color(&vis.nodes.base_color).unwrap_or(default.node_color),
+            node_radius: if vis.nodes.node_size > 0.0 { vis.nodes.node_size } else { default.node_radius },
+            metalness: vis.nodes.metalness.clamp(0.0, 1.0),
+            roughness: vis.nodes.roughness.clamp(0.0, 1.0),
+            edge_color: parse_hex_color(&vis.edges.color).unwrap_or(default.edge_color),
+            edge_opacity: if vis.edges.opacity > 0.0 { vis.edges.opacity.min(1.0) } else { default.edge_opacity },
+        }
+    }
+}
+
+/// Builds a GLB of `graph` at its current positions
+pub fn export_glb(graph: &GraphData, style: &ExportStyle) -> Vec<u8> {
+    let mut bin = BinaryBuffer::default();
+    let mut accessors = Vec::new();
+
+    // One unit sphere, shared by every node mesh. On a unit sphere the normals are
+    // the positions.
+    let (sphere_positions, sphere_indices) = unit_sphere();
+    let positions = bin.push_f32(&sphere_positions, ARRAY_BUFFER);
+    accessors.push(json!({
+        "bufferView": positions, "componentType": FLOAT, "count": sphere_positions.len() / 3,
+        "type": "VEC3", "min": [-1.0, -1.0, -1.0], "max": [1.0, 1.0, 1.0],
+    }));
+    let normals = bin.push_f32(&sphere_positions, ARRAY_BUFFER);
+    accessors.push(json!({
+        "bufferView": normals, "componentType": FLOAT, "count": sphere_positions.len() / 3, "type": "VEC3",
+    }));
+    let indices = bin.push_u16(&sphere_indices);
+    accessors.push(json!({
+        "bufferView": indices, "componentType": UNSIGNED_SHORT, "count": sphere_indices.len(), "type": "SCALAR",
+    }));
+    let sphere_primitive = |material: usize| json!({
+        "attributes": { "POSITION": 0, "NORMAL": 1 }, "indices": 2, "material": material,
+    });
+
+    let mut materials = Vec::new();
+    let mut meshes = Vec::new();
+    // Colour -> mesh, in the order colours first appear
+    let mut node_meshes: HashMap<Rgb, usize> = HashMap::new();
+    let mut nodes = Vec::new();
+
+    let largest = graph.nodes.iter().filter_map(|n| n.size).fold(0.0, f32::max);
+    for node in &graph.nodes {
+        let color = node.color.as_deref().and_then(parse_hex_color).unwrap_or(style.node_color);
+        let mesh = *node_meshes.entry(color).or_insert_with(|| {
+            materials.push(json!({
+                "name": format!("Node #{:02x}{:02x}{:02x}", color[0], color[1], color[2]),
+                "pbrMetallicRoughness": {
+                    "baseColorFactor": linear_rgba(color, 1.0),
+                    "metallicFactor": style.metalness,
+                    "roughnessFactor": style.roughness,
+                },
+            }));
+            meshes.push(json!({ "name": "Node sphere", "primitives": [sphere_primitive(materials.len() - 1)] }));
+            meshes.len() - 1
+        });
+
+        let relative = match node.size {
+            Some(size) if largest > 0.0 => (size / largest).clamp(0.0, 1.0),
+            _ => 0.5,
+        };
+        let radius = style.node_radius * (0.5 + 0.5 * relative);
+        let p = node.data.position;
+        nodes.push(json!({
+            "name": if node.label.is_empty() { &node.metadata_id } else { &node.label },
+            "mesh": mesh,
+            "translation": [p.x, p.y, p.z],
+            "scale": [radius, radius, radius],
+            "extras": { "id": node.id, "metadataId": node.metadata_id },
+        }));
+    }
+
+    let positions_by_id: HashMap<u32, [f32; 3]> = graph.nodes.iter()
+        .map(|n| (n.id, [n.data.position.x, n.data.position.y, n.data.position.z]))
+        .collect();
+    let edge_vertices: Vec<f32> = graph.edges.iter()
+        .filter_map(|e| Some([*positions_by_id.get(&e.source)?, *positions_by_id.get(&e.target)?]))
+        .flatten()
+        .flatten()
+        .collect();
+    if !edge_vertices.is_empty() {
+        let (min, max) = bounds(&edge_vertices);
+        let view = bin.push_f32(&edge_vertices, ARRAY_BUFFER);
+        accessors.push(json!({
+            "bufferView": view, "componentType": FLOAT, "count": edge_vertices.len() / 3,
+            "type": "VEC3", "min": min, "max": max,
+        }));
+        materials.push(json!({
+            "name": "Edge",
+            "pbrMetallicRoughness": {
+                "baseColorFactor": linear_rgba(style.edge_color, style.edge_opacity),
+                "metallicFactor": 0.0,
+                "roughnessFactor": 1.0,
+            },
+            "alphaMode": "BLEND",
+        }));
+        meshes.push(json!({
+            "name": "Edges",
+            "primitives": [{ "attributes": { "POSITION": accessors.len() - 1 }, "mode": MODE_LINES, "material": materials.len() - 1 }],
+        }));
+        nodes.push(json!({ "name": "Edges", "mesh": meshes.len() - 1 }));
+    }
+
+    let root = nodes.len();
+    nodes.push(json!({ "name": "Knowledge graph", "children": (0..root).collect::<Vec<_>>() }));
+
+    let document = json!({
+        "asset": { "version": "2.0", "generator": "VisionFlow graph export" },
+        "scene": 0,
+        "scenes": [{ "name": "Knowledge graph", "nodes": [root] }],
+        "nodes": nodes,
+        "meshes": meshes,
+        "materials": materials,
+        "accessors": accessors,
+        "bufferViews": bin.views,
+        "buffers": [{ "byteLength": bin.data.len() }],
+    });
+    encode_glb(&document, &bin.data)
+}
+
+#[derive(Default)]
+struct BinaryBuffer {
+    data: Vec<u8>,
+    views: Vec<Value>,
+}
+
+impl BinaryBuffer {
+    fn push_f32(&mut self, values: &[f32], target: u32) -> usize {
+        let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        self.push_view(&bytes, target)
+    }
+
+    fn push_u16(&mut self, values: &[u16]) -> usize {
+        let bytes: Vec<u8> = values.iter().flat_map(|v| v.to_le_bytes()).collect();
+        self.push_view(&bytes, ELEMENT_ARRAY_BUFFER)
+    }
+
+    /// Appends a buffer view, keeping every view 4-byte aligned
+    fn push_view(&mut self, bytes: &[u8], target: u32) -> usize {
+        self.data.resize(self.data.len().next_multiple_of(4), 0);
+        self.views.push(json!({
+            "buffer": 0, "byteOffset": self.data.len(), "byteLength": bytes.len(), "target": target,
+        }));
+        self.data.extend_from_slice(bytes);
+        self.views.len() - 1
+    }
+}
+
+/// A UV sphere of radius 1 as (xyz positions, triangle indices)
+fn unit_sphere() -> (Vec<f32>, Vec<u16>) {
+    let mut positions = Vec::new();
+    for ring in 0..=SPHERE_RINGS {
+        let polar = std::f32::consts::PI * f32::from(ring) / f32::from(SPHERE_RINGS);
+        for segment in 0..=SPHERE_SEGMENTS {
+            let azimuth = std::f32::consts::TAU * f32::from(segment) / f32::from(SPHERE_SEGMENTS);
+            positions.extend_from_slice(&[polar.sin() * azimuth.cos(), polar.cos(), polar.sin() * azimuth.sin()]);
+        }
+    }
+
+    let row = SPHERE_SEGMENTS + 1;
+    let mut indices = Vec::new();
+    for ring in 0..SPHERE_RINGS {
+        for segment in 0..SPHERE_SEGMENTS {
+            let a = ring * row + segment;
+            let b = a + row;
+            // Counter-clockwise seen from outside
+            if ring != 0 {
+                indices.extend_from_slice(&[a, a + 1, b]);
+            }
+            if ring != SPHERE_RINGS - 1 {
+                indices.extend_from_slice(&[a + 1, b + 1, b]);
+            }
+        }
+    }
+    (positions, indices)
+}
+
+fn bounds(vertices: &[f32]) -> ([f32; 3], [f32; 3]) {
+    let (mut min, mut max) = ([f32::MAX; 3], [f32::MIN; 3]);
+    for vertex in vertices.chunks(3) {
+        for axis in 0..3 {
+            min[axis] = min[axis].min(vertex[axis]);
+            max[axis] = max[axis].max(vertex[axis]);
+        }
+    }
+    (min, max)
+}
+
+/// glTF colour factors are linear, while settings colours are sRGB
+fn linear_rgba(color: Rgb, alpha: f32) -> [f32; 4] {
+    let linear = |channel: u8| {
+        let c = f32::from(channel) / 255.0;
+        if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) }
+    };
+    [linear(color[0]), linear(color[1]), linear(color[2]), alpha.clamp(0.0, 1.0)]
+}
+
+/// Packs the document and binary buffer into a GLB container
+fn encode_glb(document: &Value, bin: &[u8]) -> Vec<u8> {
+    let mut json_chunk = document.to_string().into_bytes();
+    json_chunk.resize(json_chunk.len().next_multiple_of(4), b' ');
+    let mut bin_chunk = bin.to_vec();
+    bin_chunk.resize(bin_chunk.len().next_multiple_of(4), 0);
+
+    let total = 12 + 8 + json_chunk.len() + 8 + bin_chunk.len();
+    let mut glb = Vec::with_capacity(total);
+    for word in [GLB_MAGIC, GLB_VERSION, total as u32, json_chunk.len() as u32, CHUNK_JSON] {
+        glb.extend_from_slice(&word.to_le_bytes());
+    }
+    glb.extend_from_slice(&json_chunk);
+    glb.extend_from_slice(&(bin_chunk.len() as u32).to_le_bytes());
+    glb.extend_from_slice(&CHUNK_BIN.to_le_bytes());
+    glb.extend_from_slice(&bin_chunk);
+    glb
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::edge::Edge;
+    use crate::models::node::Node;
+    use crate::types::vec3::Vec3Data;
+
+    fn node(id: u32, x: f32, color: Option<&str>) -> Node {
+        let mut node = Node::new_with_id(id.to_string(), Some(id));
+        node.data.position = Vec3Data::new(x, 0.0, 0.0);
+        node.color = color.map(str::to_string);
+        node
+    }
+
+    #[test]
+    fn test_export_glb_layout() {
+        let mut graph = GraphData::new();
+        graph.nodes = vec![node(1, -1.0, None), node(2, 1.0, Some("#ff0000")), node(3, 2.0, None)];
+        graph.edges = vec![Edge::new(1, 2, 1.0), Edge::new(2, 99, 1.0)];
+        let glb = export_glb(&graph, &ExportStyle::default());
+
+        let word = |at: usize| u32::from_le_bytes(glb[at..at + 4].try_into().unwrap()) as usize;
+        assert_eq!(&glb[..4], b"glTF");
+        assert_eq!(word(4), 2);
+        assert_eq!(word(8), glb.len());
+        let json_length = word(12);
+        assert_eq!(word(16), CHUNK_JSON as usize);
+        assert_eq!(json_length % 4, 0);
+        let document: Value = serde_json::from_slice(&glb[20..20 + json_length]).unwrap();
+        let bin_length = word(20 + json_length);
+        assert_eq!(word(24 + json_length), CHUNK_BIN as usize);
+        assert_eq!(28 + json_length + bin_length, glb.len());
+        assert!(document["buffers"][0]["byteLength"].as_u64().unwrap() as usize <= bin_length);
+
+        // Two colours share two sphere meshes, plus one mesh for the edge that
+        // has both ends in the graph
+        assert_eq!(document["meshes"].as_array().unwrap().len(), 3);
+        let nodes = document["nodes"].as_array().unwrap();
+        assert_eq!(nodes.len(), 5);
+        assert_eq!(nodes[0]["mesh"], nodes[2]["mesh"]);
+        assert_ne!(nodes[0]["mesh"], nodes[1]["mesh"]);
+        assert_eq!(nodes[1]["extras"]["id"], 2);
+        let edge_accessor = &document["accessors"][3];
+        assert_eq!(edge_accessor["count"], 2);
+        assert_eq!(edge_accessor["min"][0], -1.0);
+        assert_eq!(document["scenes"][0]["nodes"][0], 4);
+    }
+}
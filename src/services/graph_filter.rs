@@ -0,0 +1,392 @@
+//! Filter expressions over graph nodes
+//!
+//! A small query language for picking nodes out of the graph, e.g.
+//! `tag:project AND modified<30d` or `(tag:rust OR tag:wasm) NOT tag:archived`.
+//! Terms are `field:value` or a comparison such as `degree>=5`; a bare word or
+//! quoted phrase matches node labels. Adjacent terms are joined with AND.
+//!
+//! Fields: `tag`, `modified` and `journal` (dates or ages like `30d`), `degree`,
+//! `text`, and `prop.<name>` for any node metadata property such as
+//! `prop.hyperlinkCount>10`.
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use crate::models::graph::GraphData;
+use crate::models::metadata::Metadata;
+use crate::models::node::Node;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    fn holds(self, ordering: std::cmp::Ordering) -> bool {
+        use std::cmp::Ordering::*;
+        match self {
+            Comparison::Eq => ordering == Equal,
+            Comparison::Lt => ordering == Less,
+            Comparison::Le => ordering != Greater,
+            Comparison::Gt => ordering == Greater,
+            Comparison::Ge => ordering != Less,
+        }
+    }
+}
+
+/// A point in time a date field is compared with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DateBound {
+    /// `30d`, `12h`, `2w`: that long before the evaluation time
+    Ago(Duration),
+    /// `2024-03-01`: compared by day
+    On(NaiveDate),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FilterExpr {
+    And(Box<FilterExpr>, Box<FilterExpr>),
+    Or(Box<FilterExpr>, Box<FilterExpr>),
+    Not(Box<FilterExpr>),
+    Tag(String),
+    Modified(Comparison, DateBound),
+    /// Journal pages by the date they are for
+    Journal(Comparison, DateBound),
+    /// Number of edges at the node
+    Degree(Comparison, usize),
+    /// Label or page name contains the text, ignoring case
+    Text(String),
+    /// A node metadata property, compared as numbers when both sides are numbers
+    Property(String, Comparison, String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FilterParseError(pub String);
+
+impl fmt::Display for FilterParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// A node with what filters look at besides the node itself
+pub struct Candidate<'a> {
+    pub node: &'a Node,
+    pub metadata: Option<&'a Metadata>,
+    pub degree: usize,
+}
+
+impl FilterExpr {
+    pub fn parse(input: &str) -> Result<Self, FilterParseError> {
+        let tokens = tokenize(input)?;
+        if tokens.is_empty() {
+            return Err(FilterParseError("Filter expression is empty".to_string()));
+        }
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.parse_or()?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(expr),
+            Some(token) => Err(FilterParseError(format!("Unexpected {}", token))),
+        }
+    }
+
+    pub fn matches(&self, candidate: &Candidate, now: DateTime<Utc>) -> bool {
+        match self {
+            FilterExpr::And(a, b) => a.matches(candidate, now) && b.matches(candidate, now),
+            FilterExpr::Or(a, b) => a.matches(candidate, now) || b.matches(candidate, now),
+            FilterExpr::Not(inner) => !inner.matches(candidate, now),
+            FilterExpr::Tag(tag) => candidate.metadata.is_some_and(|meta| {
+                meta.tags.iter().any(|t| t.trim_start_matches('#').eq_ignore_ascii_case(tag))
+            }),
+            FilterExpr::Modified(comparison, bound) => candidate.metadata
+                .is_some_and(|meta| compare_date(meta.last_modified, *comparison, *bound, now)),
+            FilterExpr::Journal(comparison, bound) => candidate.node.metadata.get("journalDate")
+                .and_then(|date| NaiveDate::parse_from_str(date, "%Y-%m-%d").ok())
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+                .is_some_and(|date| compare_date(date.and_utc(), *comparison, *bound, now)),
+            FilterExpr::Degree(comparison, degree) => comparison.holds(candidate.degree.cmp(degree)),
+            FilterExpr::Text(text) => {
+                let text = text.to_lowercase();
+                candidate.node.label.to_lowercase().contains(&text)
+                    || candidate.node.metadata_id.to_lowercase().contains(&text)
+            }
+            FilterExpr::Property(name, comparison, value) => candidate.node.metadata.get(name)
+                .is_some_and(|actual| compare_property(actual, *comparison, value)),
+        }
+    }
+
+    /// Metadata ids of the nodes of `graph` that match
+    pub fn select(&self, graph: &GraphData, now: DateTime<Utc>) -> HashSet<String> {
+        let mut degrees: HashMap<u32, usize> = HashMap::new();
+        for edge in &graph.edges {
+            *degrees.entry(edge.source).or_insert(0) += 1;
+            *degrees.entry(edge.target).or_insert(0) += 1;
+        }
+        graph.nodes.iter()
+            .filter(|node| {
+                let metadata = graph.metadata.get(&format!("{}.md", node.metadata_id));
+                let degree = degrees.get(&node.id).copied().unwrap_or(0);
+                self.matches(&Candidate { node, metadata, degree }, now)
+            })
+            .map(|node| node.metadata_id.clone())
+            .collect()
+    }
+}
+
+fn compare_property(actual: &str, comparison: Comparison, expected: &str) -> bool {
+    match (actual.trim().parse::<f64>(), expected.parse::<f64>()) {
+        (Ok(actual), Ok(expected)) => actual.partial_cmp(&expected).is_some_and(|ordering| comparison.holds(ordering)),
+        _ if comparison == Comparison::Eq => actual.eq_ignore_ascii_case(expected),
+        _ => comparison.holds(actual.to_lowercase().cmp(&expected.to_lowercase())),
+    }
+}
+
+fn compare_date(value: DateTime<Utc>, comparison: Comparison, bound: DateBound, now: DateTime<Utc>) -> bool {
+    match bound {
+        // `modified<30d` reads as "less than 30 days old", so ages compare the other way round
+        DateBound::Ago(age) => {
+            let comparison = if comparison == Comparison::Eq { Comparison::Le } else { comparison };
+            comparison.holds((now - value).cmp(&age))
+        }
+        DateBound::On(date) => comparison.holds(value.date_naive().cmp(&date)),
+    }
+}
+
+fn parse_date_bound(value: &str) -> Result<DateBound, FilterParseError> {
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(DateBound::On(date));
+    }
+    let split = value.find(|c: char| !c.is_ascii_digit()).unwrap_or(value.len());
+    let (amount, unit) = value.split_at(split);
+    let amount: i64 = amount.parse()
+        .map_err(|_| FilterParseError(format!("'{}' is neither a date nor an age like 30d", value)))?;
+    let age = match unit {
+        "h" => Duration::hours(amount),
+        "d" => Duration::days(amount),
+        "w" => Duration::weeks(amount),
+        "m" => Duration::days(amount * 30),
+        "y" => Duration::days(amount * 365),
+        _ => return Err(FilterParseError(format!("Unknown age unit in '{}'; use h, d, w, m or y", value))),
+    };
+    Ok(DateBound::Ago(age))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    And,
+    Or,
+    Not,
+    Term(String),
+    /// A quoted phrase, matched as text even if it looks like a term
+    Phrase(String),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Open => f.write_str("'('"),
+            Token::Close => f.write_str("')'"),
+            Token::And => f.write_str("AND"),
+            Token::Or => f.write_str("OR"),
+            Token::Not => f.write_str("NOT"),
+            Token::Term(term) | Token::Phrase(term) => write!(f, "'{}'", term),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<Token>, FilterParseError> {
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::Open);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::Close);
+            }
+            _ => {
+                let mut word = String::new();
+                let phrase = c == '"';
+                let mut quoted = false;
+                while let Some(&c) = chars.peek() {
+                    if c == '"' {
+                        quoted = !quoted;
+                    } else if !quoted && (c.is_whitespace() || c == '(' || c == ')') {
+                        break;
+                    } else {
+                        word.push(c);
+                    }
+                    chars.next();
+                }
+                if quoted {
+                    return Err(FilterParseError("Unterminated quote".to_string()));
+                }
+                tokens.push(match word.as_str() {
+                    _ if phrase => Token::Phrase(word),
+                    w if w.eq_ignore_ascii_case("and") => Token::And,
+                    w if w.eq_ignore_ascii_case("or") => Token::Or,
+                    w if w.eq_ignore_ascii_case("not") => Token::Not,
+                    _ => Token::Term(word),
+                });
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn parse_or(&mut self) -> Result<FilterExpr, FilterParseError> {
+        let mut expr = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            expr = FilterExpr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<FilterExpr, FilterParseError> {
+        let mut expr = self.parse_unary()?;
+        loop {
+            match self.peek() {
+                Some(Token::And) => self.pos += 1,
+                // Adjacent terms are implicitly joined with AND
+                Some(Token::Open | Token::Not | Token::Term(_) | Token::Phrase(_)) => {}
+                _ => return Ok(expr),
+            }
+            expr = FilterExpr::And(Box::new(expr), Box::new(self.parse_unary()?));
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<FilterExpr, FilterParseError> {
+        let token = self.tokens.get(self.pos).cloned()
+            .ok_or_else(|| FilterParseError("Filter expression ends too early".to_string()))?;
+        self.pos += 1;
+        match token {
+            Token::Not => Ok(FilterExpr::Not(Box::new(self.parse_unary()?))),
+            Token::Open => {
+                let expr = self.parse_or()?;
+                if self.peek() != Some(&Token::Close) {
+                    return Err(FilterParseError("Missing ')'".to_string()));
+                }
+                self.pos += 1;
+                Ok(expr)
+            }
+            Token::Term(term) => parse_term(&term),
+            Token::Phrase(text) => Ok(FilterExpr::Text(text)),
+            other => Err(FilterParseError(format!("Unexpected {}", other))),
+        }
+    }
+}
+
+fn parse_term(term: &str) -> Result<FilterExpr, FilterParseError> {
+    let Some(split) = term.find([':', '<', '>', '=']) else {
+        return Ok(FilterExpr::Text(term.to_string()));
+    };
+    let (field, rest) = term.split_at(split);
+    let (comparison, value) = if let Some(value) = rest.strip_prefix("<=") {
+        (Comparison::Le, value)
+    } else if let Some(value) = rest.strip_prefix(">=") {
+        (Comparison::Ge, value)
+    } else if let Some(value) = rest.strip_prefix('<') {
+        (Comparison::Lt, value)
+    } else if let Some(value) = rest.strip_prefix('>') {
+        (Comparison::Gt, value)
+    } else {
+        (Comparison::Eq, &rest[1..])
+    };
+    if value.is_empty() {
+        return Err(FilterParseError(format!("'{}' has no value", term)));
+    }
+
+    match field.to_ascii_lowercase().as_str() {
+        "tag" if comparison == Comparison::Eq => Ok(FilterExpr::Tag(value.trim_start_matches('#').to_string())),
+        "tag" => Err(FilterParseError("Tags can only be matched with tag:value".to_string())),
+        "modified" => Ok(FilterExpr::Modified(comparison, parse_date_bound(value)?)),
+        "journal" => Ok(FilterExpr::Journal(comparison, parse_date_bound(value)?)),
+        "degree" => value.parse()
+            .map(|degree| FilterExpr::Degree(comparison, degree))
+            .map_err(|_| FilterParseError(format!("Degree '{}' is not a whole number", value))),
+        "text" if comparison == Comparison::Eq => Ok(FilterExpr::Text(value.to_string())),
+        "text" => Err(FilterParseError("Text can only be matched with text:value".to_string())),
+        _ if field.len() > 5 && field[..5].eq_ignore_ascii_case("prop.") => {
+            Ok(FilterExpr::Property(field[5..].to_string(), comparison, value.to_string()))
+        }
+        _ => Err(FilterParseError(format!("Unknown filter field '{}'", field))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_and_match() {
+        let expr = FilterExpr::parse("tag:project AND modified<30d").unwrap();
+        assert_eq!(FilterExpr::parse("tag:project modified<30d").unwrap(), expr);
+
+        let now = Utc::now();
+        let node = Node::new("Roadmap".to_string());
+        let mut meta = Metadata {
+            tags: vec!["#Project".to_string()],
+            last_modified: now - Duration::days(3),
+            ..Default::default()
+        };
+        assert!(expr.matches(&Candidate { node: &node, metadata: Some(&meta), degree: 0 }, now));
+
+        meta.last_modified = now - Duration::days(45);
+        assert!(!expr.matches(&Candidate { node: &node, metadata: Some(&meta), degree: 0 }, now));
+        assert!(!expr.matches(&Candidate { node: &node, metadata: None, degree: 0 }, now));
+
+        let expr = FilterExpr::parse("(tag:rust OR tag:\"web assembly\") NOT modified>=2024-01-01").unwrap();
+        meta.tags = vec!["web assembly".to_string()];
+        meta.last_modified = "2023-12-31T12:00:00Z".parse().unwrap();
+        assert!(expr.matches(&Candidate { node: &node, metadata: Some(&meta), degree: 0 }, now));
+
+        assert!(FilterExpr::parse("").is_err());
+        assert!(FilterExpr::parse("(tag:rust").is_err());
+        assert!(FilterExpr::parse("colour:red").is_err());
+        assert!(FilterExpr::parse("modified<soon").is_err());
+        assert!(FilterExpr::parse("degree>many").is_err());
+    }
+
+    #[test]
+    fn test_degree_text_and_properties() {
+        let now = Utc::now();
+        let mut node = Node::new("2024_03_01".to_string());
+        node.label = "Weekly Review".to_string();
+        node.metadata.insert("hyperlinkCount".to_string(), "12".to_string());
+        node.metadata.insert("journalDate".to_string(), "2024-03-01".to_string());
+        let candidate = Candidate { node: &node, metadata: None, degree: 6 };
+
+        let matches = |input: &str| FilterExpr::parse(input).unwrap().matches(&candidate, now);
+        assert!(matches("degree>=6 \"weekly review\""));
+        assert!(!matches("degree>6"));
+        assert!(matches("review OR missing"));
+        assert!(matches("prop.hyperlinkCount>9 NOT prop.hyperlinkCount>100"));
+        assert!(matches("journal:2024-03-01 journal<2024-04-01"));
+        assert!(!matches("prop.missing:1"));
+        assert_eq!(FilterExpr::parse("\"a:b\"").unwrap(), FilterExpr::Text("a:b".to_string()));
+    }
+}
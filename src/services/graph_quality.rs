@@ -0,0 +1,151 @@
+//! Structural problems in the knowledge base worth cleaning up
+//!
+//! Reports pages nothing links to or from, links to pages that don't exist, and
+//! pages whose titles differ only in case or surrounding whitespace, which Logseq
+//! treats as the same page.
+
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+
+use crate::models::graph::GraphData;
+use crate::models::node::{Node, GHOST_NODE_TYPE};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PageRef {
+    pub id: u32,
+    pub metadata_id: String,
+    pub label: String,
+}
+
+impl From<&Node> for PageRef {
+    fn from(node: &Node) -> Self {
+        Self { id: node.id, metadata_id: node.metadata_id.clone(), label: node.label.clone() }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DanglingLink {
+    /// File containing the link
+    pub source: String,
+    /// Page the link points at
+    pub target: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DuplicateTitle {
+    pub title: String,
+    pub pages: Vec<PageRef>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GraphQualityReport {
+    pub page_count: usize,
+    /// Pages with no edges to other pages
+    pub orphans: Vec<PageRef>,
+    pub dangling_links: Vec<DanglingLink>,
+    pub duplicate_titles: Vec<DuplicateTitle>,
+    /// Placeholder nodes currently in the graph for dangling links
+    pub ghost_node_count: usize,
+}
+
+fn is_ghost(node: &Node) -> bool {
+    node.node_type.as_deref() == Some(GHOST_NODE_TYPE)
+}
+
+pub fn analyse(graph: &GraphData) -> GraphQualityReport {
+    let synthetic_ids: HashSet<u32> = graph.nodes.iter().filter(|n| !n.is_page()).map(|n| n.id).collect();
+    let pages: Vec<&Node> = graph.nodes.iter().filter(|n| n.is_page()).collect();
+
+    // Edges to ghosts and tags don't count: a page linking only to missing pages is
+    // still disconnected from the rest of the knowledge base
+    let connected: HashSet<u32> = graph.edges.iter()
+        .filter(|e| e.source != e.target && !synthetic_ids.contains(&e.source) && !synthetic_ids.contains(&e.target))
+        .flat_map(|e| [e.source, e.target])
+        .collect();
+    let mut orphans: Vec<PageRef> = pages.iter()
+        .filter(|node| !connected.contains(&node.id))
+        .map(|node| PageRef::from(*node))
+        .collect();
+    orphans.sort_by(|a, b| a.label.cmp(&b.label));
+
+    let mut dangling_links: Vec<DanglingLink> = graph.metadata.iter()
+        .flat_map(|(file_name, meta)| {
+            meta.unresolved_links.iter().map(move |(target, count)| DanglingLink {
+                source: file_name.clone(),
+                target: target.clone(),
+                count: *count,
+            })
+        })
+        .collect();
+    dangling_links.sort_by(|a, b| a.source.cmp(&b.source).then_with(|| a.target.cmp(&b.target)));
+
+    let mut by_title: BTreeMap<String, Vec<PageRef>> = BTreeMap::new();
+    for node in &pages {
+        by_title.entry(node.label.trim().to_lowercase()).or_default().push(PageRef::from(*node));
+    }
+    let duplicate_titles = by_title.into_iter()
+        .filter(|(_, pages)| pages.len() > 1)
+        .map(|(title, mut pages)| {
+            pages.sort_by(|a, b| a.metadata_id.cmp(&b.metadata_id));
+            DuplicateTitle { title, pages }
+        })
+        .collect();
+
+    GraphQualityReport {
+        page_count: pages.len(),
+        orphans,
+        dangling_links,
+        duplicate_titles,
+        ghost_node_count: graph.nodes.iter().filter(|n| is_ghost(n)).count(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::edge::Edge;
+    use crate::models::metadata::Metadata;
+    use crate::models::node::TAG_NODE_TYPE;
+    use std::collections::HashMap;
+
+    fn page(id: u32, label: &str) -> Node {
+        let mut node = Node::new_with_id(label.to_string(), Some(id));
+        node.label = label.to_string();
+        node
+    }
+
+    #[test]
+    fn test_report() {
+        let mut ghost = page(5, "Missing");
+        ghost.node_type = Some(GHOST_NODE_TYPE.to_string());
+        let mut tag = page(6, "rust");
+        tag.node_type = Some(TAG_NODE_TYPE.to_string());
+
+        let mut graph = GraphData::new();
+        graph.nodes = vec![page(1, "Rust"), page(2, "Graphs"), page(3, "rust "), page(4, "Lonely"), ghost, tag];
+        graph.edges = vec![Edge::new(1, 2, 1.0), Edge::new(4, 5, 1.0), Edge::new(3, 6, 1.0)];
+        graph.metadata.insert("Lonely.md".to_string(), Metadata {
+            unresolved_links: HashMap::from([("Missing".to_string(), 2)]),
+            ..Default::default()
+        });
+
+        let report = analyse(&graph);
+        assert_eq!(report.page_count, 4);
+        assert_eq!(report.ghost_node_count, 1);
+        let orphans: Vec<&str> = report.orphans.iter().map(|p| p.label.as_str()).collect();
+        assert_eq!(orphans, vec!["Lonely", "rust "]);
+        assert_eq!(report.dangling_links, vec![DanglingLink {
+            source: "Lonely.md".to_string(),
+            target: "Missing".to_string(),
+            count: 2,
+        }]);
+        assert_eq!(report.duplicate_titles.len(), 1);
+        assert_eq!(report.duplicate_titles[0].title, "rust");
+        assert_eq!(report.duplicate_titles[0].pages.len(), 2);
+    }
+}
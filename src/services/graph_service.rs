@@ -3,7 +3,6 @@ use std::sync::atomic::{AtomicBool, Ordering};
 use tokio::sync::RwLock;
 use std::collections::{HashMap, HashSet};
 use rand::distributions::{Alphanumeric, DistString};
-use rand::Rng;
 use std::io::{Error, ErrorKind};
 use serde_json;
 use std::pin::Pin;
@@ -13,20 +12,25 @@ use log::{info, warn, error, trace};
 use scopeguard;
 
 use tokio::fs::File as TokioFile;
+use crate::config::data_dirs::DataDirs;
 use crate::models::graph::GraphData;
 use crate::models::node::Node; // Corrected Node import
 use crate::models::edge::Edge;
 use crate::models::metadata::MetadataStore;
+use webxr_core::graph_builder;
 use crate::config::AppFullSettings; // Use AppFullSettings, ClientFacingSettings removed
 use crate::utils::gpu_compute::GPUCompute;
+use crate::utils::socket_flow_messages::BinaryNodeData;
 use crate::models::simulation_params::{SimulationParams, SimulationPhase, SimulationMode};
 use crate::models::pagination::PaginatedGraphData;
+use crate::services::compound_layout::CompoundLayout;
+use crate::services::divergence::{self, DivergenceReport};
+use crate::services::graph_validation::{self, RepairOptions};
 // Removed: use crate::handlers::socket_flow_handler::ClientManager;
 // ClientManagerActor is used instead
 use crate::actors::client_manager_actor::ClientManagerActor;
 use actix::Addr; // Added Addr import
-use crate::actors::messages::BroadcastNodePositions;
-use crate::utils::binary_protocol;
+use crate::actors::messages::BroadcastMessage;
 use tokio::sync::Mutex;
 use once_cell::sync::Lazy;
 
@@ -59,6 +63,13 @@ const METADATA_FILE_CHECK_INTERVAL_MS: u64 = 100; // Check every 100ms
 const MAX_GPU_CALCULATION_RETRIES: u32 = 3;
 const GPU_RETRY_DELAY_MS: u64 = 500; // 500ms delay between retries
 
+/// A frame applied by `advance_layout`
+pub struct LayoutFrame {
+    pub nodes: Vec<Node>,
+    /// Set when nodes had diverged and were brought back before the next step
+    pub divergence: Option<DivergenceReport>,
+}
+
 #[derive(Clone)]
 pub struct GraphService {
     graph_data: Arc<RwLock<GraphData>>,
@@ -180,10 +191,21 @@ impl GraphService {
                 mass_scale: physics_settings.mass_scale,
                 boundary_damping: physics_settings.boundary_damping,
                 enable_bounds: physics_settings.enable_bounds,
+                collision_radius: physics_settings.collision_radius,
+                collision_stiffness: physics_settings.collision_stiffness,
+                plane_z: physics_settings.layout_plane(),
                 time_step: 0.016,  // ~60fps
                 phase: SimulationPhase::Dynamic,
-                mode: SimulationMode::Remote,
+                mode: physics_settings.force_model,
+                gravity: physics_settings.gravity,
+                strong_gravity: physics_settings.strong_gravity,
+                edge_weight_normalization: physics_settings.edge_weight_normalization,
+                degree_gravity: physics_settings.attraction_strength,
+                cluster_separation: physics_settings.cluster_separation,
+                node_types: physics_settings.node_types.clone(),
             };
+            // Rebuilt whenever the graph changes shape
+            let mut compound: Option<CompoundLayout> = None;
             
             // Create a guard to reset the flag when the task exits
             let loop_guard = scopeguard::guard((), |_| { 
@@ -206,42 +228,55 @@ impl GraphService {
                 
                 // Update positions - using loop ID in logs to track which loop is running
                 trace!("[Graph:{}] Starting physics calculation iteration", loop_simulation_id);
-                let mut graph = graph_data.write().await;
-                let mut node_map = node_map.write().await;
-
                 let gpu_status = if gpu_compute.is_some() { "available" } else { "NOT available" };
                 trace!("[Graph:{}] GPU compute status: {}, physics enabled: {}",
                        loop_simulation_id, gpu_status, physics_settings.enabled);
-                       
+
                 if physics_settings.enabled {
                     if let Some(gpu) = &gpu_compute {
-                        if let Err(e) = Self::calculate_layout_with_retry(gpu, &mut graph, &mut node_map, &params).await {
-                            error!("[Graph:{}] Error updating positions: {}", loop_simulation_id, e);
-                        } else {
-                            trace!("[Graph:{}] GPU calculation completed successfully", loop_simulation_id);
-                            trace!("[Graph:{}] Successfully calculated layout for {} nodes", loop_simulation_id, graph.nodes.len());
-                            
-                            // Broadcast position updates to all clients
-                            Self::broadcast_positions(captured_client_manager.clone(), &graph.nodes).await;
+                        // The GPU step runs while the previous frame is broadcast and
+                        // the loop sleeps, so neither waits on the device
+                        match Self::advance_layout(gpu, &graph_data, &node_map, &params, &mut compound).await {
+                            Ok(Some(frame)) => {
+                                trace!("[Graph:{}] Applied GPU frame for {} nodes", loop_simulation_id, frame.nodes.len());
+                                if let Some(report) = &frame.divergence {
+                                    Self::notify_divergence(&captured_client_manager, report);
+                                }
+                            }
+                            Ok(None) => {}
+                            Err(e) => {
+                                warn!("[Graph:{}] Pipelined GPU step failed, retrying synchronously: {}", loop_simulation_id, e);
+                                let mut graph = graph_data.write().await;
+                                let mut node_map = node_map.write().await;
+                                if let Err(e) = Self::calculate_layout_with_retry(gpu, &mut graph, &mut node_map, &params).await {
+                                    error!("[Graph:{}] Error updating positions: {}", loop_simulation_id, e);
+                                } else {
+                                    Self::apply_compound_layout(&mut compound, &mut graph, &mut node_map, &params);
+                                    if let Some(report) = Self::recover_divergence(&mut graph, &mut node_map, &params) {
+                                        Self::notify_divergence(&captured_client_manager, &report);
+                                    }
+                                }
+                            }
                         }
                     } else {
+                        let mut graph = graph_data.write().await;
+                        let mut node_map = node_map.write().await;
                         // Use CPU fallback when GPU is not available
                         trace!("[Graph:{}] GPU compute not available - using CPU fallback for physics calculation", loop_simulation_id);
                         if let Err(e) = Self::calculate_layout_cpu(&mut graph, &mut node_map, &params) {
                             error!("[Graph:{}] Error updating positions with CPU fallback: {}", loop_simulation_id, e);
                         } else {
+                            Self::apply_compound_layout(&mut compound, &mut graph, &mut node_map, &params);
+                            if let Some(report) = Self::recover_divergence(&mut graph, &mut node_map, &params) {
+                                Self::notify_divergence(&captured_client_manager, &report);
+                            }
                             trace!("[Graph:{}] CPU calculation completed successfully", loop_simulation_id);
                             trace!("[Graph:{}] Successfully calculated layout with CPU fallback for {} nodes", loop_simulation_id, graph.nodes.len());
-                            
-                            // Broadcast position updates to all clients
-                            Self::broadcast_positions(captured_client_manager.clone(), &graph.nodes).await;
                         }
                     }
                 } else {
                     trace!("[Graph:{}] Physics disabled in settings - skipping physics calculation", loop_simulation_id);
                 }
-                drop(graph); // Release locks before sleep
-                drop(node_map);
                 tokio::time::sleep(tokio::time::Duration::from_millis(16)).await;
                 let mut cache = node_positions_cache.write().await;
                 *cache = None;
@@ -300,16 +335,16 @@ impl GraphService {
     //     });
     // }
  
-    // Helper method to broadcast position updates to all clients
-    async fn broadcast_positions(client_manager_addr: Addr<ClientManagerActor>, nodes: &[Node]) {
-        // Encode node data for broadcasting
-        // The binary_protocol::encode_node_data expects a slice of (u32, BinaryNodeData)
-        // We need to convert our Vec<Node> to this format.
-        let positions_to_encode: Vec<(u32, crate::utils::socket_flow_messages::BinaryNodeData)> = nodes.iter().map(|node| (node.id, node.data)).collect();
-
-        let binary_data = binary_protocol::encode_node_data(&positions_to_encode);
-        // Send BroadcastNodePositions message to ClientManagerActor
-        client_manager_addr.do_send(BroadcastNodePositions { positions: binary_data });
+    /// Tells every client which nodes were brought back after diverging, so they can
+    /// drop any interpolation towards the garbage positions
+    fn notify_divergence(client_manager_addr: &Addr<ClientManagerActor>, report: &DivergenceReport) {
+        let message = serde_json::json!({
+            "type": "simulationReset",
+            "nodeIds": report.node_ids,
+            "nonFinite": report.non_finite,
+            "resetAll": report.reset_all,
+        });
+        client_manager_addr.do_send(BroadcastMessage { message: message.to_string() });
     }
 
     /// Shutdown the simulation loop to allow creating a new instance
@@ -421,7 +456,7 @@ impl GraphService {
         info!("Checking for metadata file from Docker volume mount...");
         
         // Path to metadata file
-        let metadata_path = std::path::Path::new("/app/data/metadata/metadata.json");
+        let metadata_path = DataDirs::global().metadata_file("metadata.json");
         
         // Start timer
         let start_time = Instant::now();
@@ -465,7 +500,7 @@ impl GraphService {
         false
     }
 
-    pub async fn build_graph_from_metadata(metadata: &MetadataStore) -> Result<GraphData, Box<dyn std::error::Error + Send + Sync>> {
+    pub async fn build_graph_from_metadata(metadata: &MetadataStore, seed: Option<u64>) -> Result<GraphData, Box<dyn std::error::Error + Send + Sync>> {
         // Check if a rebuild is already in progress
         info!("Building graph from {} metadata entries", metadata.len());
         trace!("Building graph from {} metadata entries", metadata.len());
@@ -484,189 +519,25 @@ impl GraphService {
         }
         // This guard will reset the flag when it goes out of scope
         let _guard = RebuildGuard;
-        
-        let mut graph = GraphData::new();
-        let mut edge_map = HashMap::new();
-        let mut node_map = HashMap::new();
-
-        // First pass: Create nodes from files in metadata
-        let mut valid_nodes = HashSet::new();
-        trace!("Creating nodes from {} metadata entries", metadata.len());
-        for file_name in metadata.keys() {
-            let node_id = file_name.trim_end_matches(".md").to_string();
-            valid_nodes.insert(node_id);
-        }
-        trace!("Created valid_nodes set with {} nodes", valid_nodes.len());
-
-        // Create nodes for all valid node IDs
-        for node_id in &valid_nodes {
-            // Get metadata for this node, including the node_id if available
-            let metadata_entry = graph.metadata.get(&format!("{}.md", node_id));
-            let stored_node_id = metadata_entry.map(|m| m.node_id.clone());
-            
-            // Create node with stored ID or generate a new one if not available
-            let stored_node_id_u32 = stored_node_id.and_then(|s| s.parse::<u32>().ok());
-            let mut node = Node::new_with_id(node_id.clone(), stored_node_id_u32);
-            graph.id_to_metadata.insert(node.id.to_string(), node_id.clone());
-
-            // Get metadata for this node
-            if let Some(metadata) = metadata.get(&format!("{}.md", node_id)) {
-                // Set file size which also calculates mass
-                node.set_file_size(metadata.file_size as u64);  // This will update both file_size and mass
-                
-                // Set the node label to the file name without extension
-                // This will be used as the display name for the node
-                node.label = metadata.file_name.trim_end_matches(".md").to_string();
-                
-                // Set visual properties from metadata
-                node.size = Some(metadata.node_size as f32);
-                
-                // Add metadata fields to node's metadata map
-                // Add all relevant metadata fields to ensure consistency
-                node.metadata.insert("fileName".to_string(), metadata.file_name.clone());
-                
-                // Add name field (without .md extension) for client-side metadata ID mapping
-                if metadata.file_name.ends_with(".md") {
-                    let name = metadata.file_name[..metadata.file_name.len() - 3].to_string();
-                    node.metadata.insert("name".to_string(), name.clone());
-                    node.metadata.insert("metadataId".to_string(), name);
-                } else {
-                    node.metadata.insert("name".to_string(), metadata.file_name.clone());
-                    node.metadata.insert("metadataId".to_string(), metadata.file_name.clone());
-                }
-                
-                node.metadata.insert("fileSize".to_string(), metadata.file_size.to_string());
-                node.metadata.insert("nodeSize".to_string(), metadata.node_size.to_string());
-                node.metadata.insert("hyperlinkCount".to_string(), metadata.hyperlink_count.to_string());
-                node.metadata.insert("sha1".to_string(), metadata.sha1.clone());
-                node.metadata.insert("lastModified".to_string(), metadata.last_modified.to_string());
-                
-                if !metadata.perplexity_link.is_empty() {
-                    node.metadata.insert("perplexityLink".to_string(), metadata.perplexity_link.clone());
-                }
-                
-                if let Some(last_process) = metadata.last_perplexity_process {
-                    node.metadata.insert("lastPerplexityProcess".to_string(), last_process.to_string());
-                }
-                
-                // We don't add topic_counts to metadata as it would create circular references
-                // and is already used to create edges
-                
-                // Ensure flags is set to 1 (default active state)
-                node.data.flags = 1;
-            }
-
-            let node_clone = node.clone();
-            graph.nodes.push(node_clone);
-            // Store nodes in map by numeric ID for efficient lookups
-            node_map.insert(node.id, node);
-        }
-
-        // Store metadata in graph
-        trace!("Storing {} metadata entries in graph", metadata.len());
-        graph.metadata = metadata.clone();
-        trace!("Created {} nodes in graph", graph.nodes.len());
-        // Second pass: Create edges from topic counts
-        for (source_file, metadata) in metadata.iter() {
-            let source_id = source_file.trim_end_matches(".md").to_string();
-            // Find the node with this metadata_id to get its numeric ID
-            let source_node = graph.nodes.iter().find(|n| n.metadata_id == source_id);
-            if source_node.is_none() {
-                continue; // Skip if node not found
-            }
-            let source_numeric_id = source_node.unwrap().id;
-            
-            trace!("Processing edges for source: {} (ID: {})", source_id, source_numeric_id);
-            for (target_file, count) in &metadata.topic_counts {
-                let target_id = target_file.trim_end_matches(".md").to_string();
-                // Find the node with this metadata_id to get its numeric ID
-                let target_node = graph.nodes.iter().find(|n| n.metadata_id == target_id);
-                if target_node.is_none() {
-                    continue; // Skip if node not found
-                }
-                let target_numeric_id = target_node.unwrap().id;
-
-                trace!("  Edge: {} -> {} (weight: {})", source_numeric_id, target_numeric_id, count);
-
-                // Only create edge if both nodes exist and they're different
-                if source_numeric_id != target_numeric_id {
-                    let edge_key = if source_numeric_id < target_numeric_id {
-                        (source_numeric_id, target_numeric_id)
-                    } else {
-                        (target_numeric_id, source_numeric_id)
-                    };
-
-                    edge_map.entry(edge_key)
-                        .and_modify(|weight| *weight += *count as f32)
-                        .or_insert(*count as f32);
-                }
-            }
-        }
-
-        // Convert edge map to edges
-        trace!("Edge map contains {} unique connections", edge_map.len());
-        for ((source, target), weight) in &edge_map {
-            trace!("Edge map entry: {} -- {} (weight: {})", source, target, weight);
-        }
-
-        trace!("Converting edge map to {} edges", edge_map.len());
-        graph.edges = edge_map.into_iter()
-            .map(|((source, target), weight)| {
-                Edge::new(source, target, weight)
-            })
-            .collect();
-
-        // Initialize random positions
-        Self::initialize_random_positions(&mut graph);
 
-        info!("Built graph with {} nodes and {} edges", graph.nodes.len(), graph.edges.len());
-        trace!("Completed graph build: {} nodes, {} edges", graph.nodes.len(), graph.edges.len());
-        Ok(graph)
+        Ok(Self::assemble_graph(metadata, seed))
     }
 
+    /// Builds a graph from `metadata` without taking the rebuild lock, for graphs
+    /// that aren't the one the server simulates. It is repaired unless
+    /// `GRAPH_AUTO_REPAIR` is off.
+    pub fn assemble_graph(metadata: &MetadataStore, seed: Option<u64>) -> GraphData {
+        let mut graph = graph_builder::assemble_graph(metadata, Self::layout_seed(seed));
+        graph_validation::sanitize(&mut graph, &RepairOptions::from_env());
+        graph
+    }
 
-    fn initialize_random_positions(graph: &mut GraphData) {
-        let mut rng = rand::thread_rng();
-        let node_count = graph.nodes.len() as f32;
-        let initial_radius = 3.0; // Increasing radius for better visibility
-        let golden_ratio = (1.0 + 5.0_f32.sqrt()) / 2.0;
-        
-        // Log the initialization process
-        info!("Initializing random positions for {} nodes with radius {}", 
-             node_count, initial_radius);
-        info!("First 5 node numeric IDs: {}", graph.nodes.iter().take(5).map(|n| n.id.to_string()).collect::<Vec<_>>().join(", "));
-        info!("First 5 node metadata IDs: {}", graph.nodes.iter().take(5).map(|n| n.metadata_id.clone()).collect::<Vec<_>>().join(", "));
-        
-        // Use Fibonacci sphere distribution for more uniform initial positions
-        for (i, node) in graph.nodes.iter_mut().enumerate() {
-            let i_float: f32 = i as f32;
-            
-            // Calculate Fibonacci sphere coordinates
-            let theta = 2.0 * std::f32::consts::PI * i_float / golden_ratio;
-            let phi = (1.0 - 2.0 * (i_float + 0.5) / node_count).acos();
-            
-            // Add slight randomness to prevent exact overlaps
-            let r = initial_radius * (0.9 + rng.gen_range(0.0..0.2));
-            
-            node.set_x(r * phi.sin() * theta.cos());
-            node.set_y(r * phi.sin() * theta.sin());
-            node.set_z(r * phi.cos());
-            
-            // Initialize with zero velocity
-            node.set_vx(0.0);
-            node.set_vy(0.0);
-            node.set_vz(0.0);
-
-            // Log first 5 nodes for debugging
-            if i < 5 {
-                info!("Initialized node {}: id={}, pos=[{:.3},{:.3},{:.3}]", 
-                     i,
-                     node.id,
-                     node.data.position.x, 
-                     node.data.position.y, 
-                     node.data.position.z);
-            }
-        }
+    /// The seed to lay out a graph with, picking one if none was given. It is logged
+    /// so a layout worth keeping can be reproduced.
+    pub fn layout_seed(seed: Option<u64>) -> u64 {
+        let seed = seed.unwrap_or_else(rand::random);
+        info!("Using layout seed {}", seed);
+        seed
     }
 
     /// Helper function to retry GPU layout calculation with exponential backoff
@@ -720,6 +591,60 @@ impl GraphService {
         }
     }
 
+    /// One step of the simulation loop with double-buffered readback: applies the
+    /// frame read back after the previous step, then queues the next step and its
+    /// readback without waiting for them. Returns the updated nodes to broadcast,
+    /// or `None` while the first frame is still in flight. Nodes that diverged are
+    /// brought back before the next step is queued.
+    ///
+    /// The graph locks are only held to apply the frame and upload the graph, and
+    /// are taken before the GPU lock, as `calculate_layout` callers do.
+    pub async fn advance_layout(
+        gpu_compute: &Arc<RwLock<GPUCompute>>,
+        graph_data: &RwLock<GraphData>,
+        node_map: &RwLock<HashMap<u32, Node>>,
+        params: &SimulationParams,
+        compound: &mut Option<CompoundLayout>,
+    ) -> std::io::Result<Option<LayoutFrame>> {
+        let frame = {
+            let mut gpu = gpu_compute.write().await;
+            match gpu.collect_readback()? {
+                Some(_) => gpu.latest_positions(),
+                None => None,
+            }
+        };
+
+        let mut graph = graph_data.write().await;
+        let mut node_map = node_map.write().await;
+        let mut divergence = None;
+        if let Some((iteration, positions)) = &frame {
+            trace!("[advance_layout] Applying frame from iteration {} ({} nodes)", iteration, positions.len());
+            let positions: HashMap<u32, BinaryNodeData> = positions.iter().copied().collect();
+            for node in graph.nodes.iter_mut() {
+                // Nodes added since the step was queued keep their position until the next frame
+                let Some(data) = positions.get(&node.id) else { continue };
+                node.data.position = data.position;
+                node.data.velocity = data.velocity;
+                params.constrain(&mut node.data);
+                if let Some(map_node) = node_map.get_mut(&node.id) {
+                    map_node.data = node.data;
+                }
+            }
+            Self::apply_compound_layout(compound, &mut graph, &mut node_map, params);
+            divergence = Self::recover_divergence(&mut graph, &mut node_map, params);
+        }
+
+        {
+            let mut gpu = gpu_compute.write().await;
+            gpu.update_simulation_params(params)?;
+            gpu.update_graph_data(&graph)?;
+            gpu.step()?;
+            gpu.start_readback()?;
+        }
+
+        Ok(frame.map(|_| LayoutFrame { nodes: graph.nodes.clone(), divergence }))
+    }
+
     pub async fn calculate_layout(
         gpu_compute: &Arc<RwLock<GPUCompute>>,
         graph: &mut GraphData,
@@ -738,7 +663,12 @@ impl GraphService {
             trace!("[calculate_layout] params: iterations={}, spring_strength={:.3}, repulsion={:.3}, damping={:.3}",
                  params.iterations, params.spring_strength, params.repulsion, params.damping);
             
-            // Update data and parameters
+            // Parameters first, since the node data uploaded depends on them
+            if let Err(e) = gpu_compute.update_simulation_params(params) {
+                error!("[calculate_layout] Failed to update simulation parameters in GPU: {}", e);
+                return Err(e);
+            }
+
             if let Err(e) = gpu_compute.update_graph_data(graph) {
                 error!("[calculate_layout] Failed to update graph data in GPU: {}, node count: {}", 
                       e, graph.nodes.len());
@@ -749,11 +679,6 @@ impl GraphService {
                 return Err(e);
             }
             
-            if let Err(e) = gpu_compute.update_simulation_params(params) {
-                error!("[calculate_layout] Failed to update simulation parameters in GPU: {}", e);
-                return Err(e);
-            }
-            
             // Perform computation step
             if let Err(e) = gpu_compute.step() {
                 error!("[calculate_layout] Failed to execute physics step: {}, graph has {} nodes and {} edges", 
@@ -781,13 +706,16 @@ impl GraphService {
                     continue;
                 }
                 
-                // Update position and velocity from GPU data
-                node.data = updated_nodes[i];
+                // Update position and velocity from GPU data; the mass and padding on
+                // the GPU were adjusted for the node's type
+                node.data.position = updated_nodes[i].position;
+                node.data.velocity = updated_nodes[i].velocity;
+                params.constrain(&mut node.data);
                 nodes_updated += 1;
                 
                 // Update node_map as well
                 if let Some(map_node) = node_map.get_mut(&node.id) {
-                    map_node.data = updated_nodes[i];
+                    map_node.data = node.data;
                 } else {
                     warn!("[calculate_layout] Node {} not found in node_map", node.id);
                 }
@@ -811,6 +739,54 @@ impl GraphService {
         }
     }
 
+    /// Holds nodes near their community when `cluster_separation` is set, laying the
+    /// communities out again whenever nodes or edges are added or removed or the
+    /// separation changes. Does nothing, and drops any cached layout, when it is 0.
+    pub fn apply_compound_layout(
+        compound: &mut Option<CompoundLayout>,
+        graph: &mut GraphData,
+        node_map: &mut HashMap<u32, Node>,
+        params: &SimulationParams,
+    ) {
+        if params.cluster_separation <= 0.0 {
+            *compound = None;
+            return;
+        }
+        if !compound.as_ref().is_some_and(|layout| layout.is_current(graph, params.cluster_separation)) {
+            trace!("[apply_compound_layout] Laying out communities for {} nodes", graph.nodes.len());
+            *compound = Some(CompoundLayout::new(graph, params.cluster_separation));
+        }
+        let Some(layout) = compound.as_ref() else { return };
+        for node in graph.nodes.iter_mut() {
+            if layout.constrain(node) {
+                params.constrain(&mut node.data);
+                if let Some(map_node) = node_map.get_mut(&node.id) {
+                    map_node.data = node.data;
+                }
+            }
+        }
+    }
+
+    /// Brings back nodes that have run off or gone NaN, logging the parameters that
+    /// let it happen, and keeps `node_map` in step
+    pub fn recover_divergence(
+        graph: &mut GraphData,
+        node_map: &mut HashMap<u32, Node>,
+        params: &SimulationParams,
+    ) -> Option<DivergenceReport> {
+        let report = divergence::recover(graph, params)?;
+        warn!("[Graph] {} of {} nodes diverged ({} non-finite, limit {}), {}; mode {:?}, time step {}, damping {}, spring {}, repulsion {}, mass scale {}",
+              report.node_ids.len(), graph.nodes.len(), report.non_finite, report.limit,
+              if report.reset_all { "resetting the layout" } else { "bringing them back" },
+              params.mode, params.time_step, params.damping, params.spring_strength, params.repulsion, params.mass_scale);
+        for node in &graph.nodes {
+            if let Some(map_node) = node_map.get_mut(&node.id) {
+                map_node.data = node.data;
+            }
+        }
+        Some(report)
+    }
+
     /// CPU fallback implementation of force-directed graph layout
     pub fn calculate_layout_cpu(
         graph: &mut GraphData,
@@ -825,6 +801,38 @@ impl GraphService {
             return Ok(());
         }
         
+        let mut forces = match params.mode {
+            SimulationMode::SpringElectric => Self::spring_electric_forces(graph, params),
+            SimulationMode::ForceAtlas2 | SimulationMode::LinLog => Self::force_atlas2_forces(graph, params),
+        };
+        if params.degree_gravity > 0.0 {
+            Self::add_degree_gravity(graph, params, &mut forces);
+        }
+        
+        // Update velocities and positions for all nodes
+        for (i, node) in graph.nodes.iter_mut().enumerate() {            
+            // Apply force to velocity with damping
+            node.set_vx(node.data.velocity.x * params.damping + forces[i].0 * params.time_step);
+            node.set_vy(node.data.velocity.y * params.damping + forces[i].1 * params.time_step);
+            node.set_vz(node.data.velocity.z * params.damping + forces[i].2 * params.time_step);
+            
+            // Update position based on velocity
+            node.set_x(node.data.position.x + node.data.velocity.x * params.time_step);
+            node.set_y(node.data.position.y + node.data.velocity.y * params.time_step);
+            node.set_z(node.data.position.z + node.data.velocity.z * params.time_step);
+            params.constrain(&mut node.data);
+            
+            // Update node_map as well
+            if let Some(map_node) = node_map.get_mut(&node.id) {
+                map_node.data = node.data;
+            }
+        }
+        
+        Ok(())
+    }
+
+    fn spring_electric_forces(graph: &GraphData, params: &SimulationParams) -> Vec<(f32, f32, f32)> {
+        let nodes_len = graph.nodes.len();
         // Initialize force accumulators for each node
         let mut forces = vec![(0.0, 0.0, 0.0); nodes_len];
         
@@ -843,12 +851,23 @@ impl GraphService {
                 // Avoid division by zero and limit maximum repulsion distance
                 if distance_squared < 0.0001 { continue; }
                 let distance = distance_squared.sqrt();
-                if distance > params.max_repulsion_distance { continue; }
+                // Overlapping spheres are pushed apart in proportion to the overlap
+                let min_distance = params.collision_radius
+                    * (SimulationParams::radius_scale(node_i) + SimulationParams::radius_scale(node_j));
+                let collision_factor = params.collision_stiffness * (min_distance - distance).max(0.0);
+                if distance > params.max_repulsion_distance && collision_factor == 0.0 { continue; }
                 
-                // Calculate repulsion strength based on node masses (stored in data.mass) and distance
-                let mass_i = (node_i.data.mass as f32 / 255.0) * 10.0 * params.mass_scale;
-                let mass_j = (node_j.data.mass as f32 / 255.0) * 10.0 * params.mass_scale;
-                let repulsion_factor = params.repulsion * mass_i * mass_j / distance_squared;
+                // Calculate repulsion strength based on node masses (stored in data.mass),
+                // the type multipliers and distance
+                let physics_i = params.node_physics(node_i.node_type.as_deref());
+                let physics_j = params.node_physics(node_j.node_type.as_deref());
+                let mass_i = (node_i.data.mass as f32 / 255.0) * 10.0 * params.mass_scale * physics_i.mass;
+                let mass_j = (node_j.data.mass as f32 / 255.0) * 10.0 * params.mass_scale * physics_j.mass;
+                let repulsion_factor = if distance > params.max_repulsion_distance {
+                    0.0
+                } else {
+                    params.repulsion * physics_i.charge * physics_j.charge * mass_i * mass_j / distance_squared
+                };
                 
                 // Normalize direction
                 let nx = dx / distance;
@@ -856,9 +875,9 @@ impl GraphService {
                 let nz = dz / distance;
                 
                 // Calculate forces (nodes push each other away)
-                let fx = nx * repulsion_factor;
-                let fy = ny * repulsion_factor;
-                let fz = nz * repulsion_factor;
+                let fx = nx * (repulsion_factor + collision_factor);
+                let fy = ny * (repulsion_factor + collision_factor);
+                let fz = nz * (repulsion_factor + collision_factor);
                 
                 // Apply forces to both nodes (equal and opposite)
                 forces[i].0 -= fx;
@@ -871,7 +890,7 @@ impl GraphService {
         }
         
         // Calculate attractive forces for edges (spring forces)
-        for edge in &graph.edges {
+        for (edge, weight) in graph.edges.iter().zip(params.edge_weights(&graph.edges)) {
             let source_idx = graph.nodes.iter().position(|n| n.id == edge.source);
             let target_idx = graph.nodes.iter().position(|n| n.id == edge.target);
             
@@ -888,7 +907,7 @@ impl GraphService {
                 let distance = distance_squared.sqrt();
                 
                 // Spring force increases with distance and edge weight
-                let spring_factor = params.spring_strength * edge.weight * distance;
+                let spring_factor = params.spring_strength * weight * distance;
                 
                 // Normalize direction
                 let nx = dx / distance;
@@ -909,26 +928,114 @@ impl GraphService {
                 forces[j].2 -= fz;
             }
         }
-        
-        // Update velocities and positions for all nodes
-        for (i, node) in graph.nodes.iter_mut().enumerate() {            
-            // Apply force to velocity with damping
-            node.set_vx(node.data.velocity.x * params.damping + forces[i].0 * params.time_step);
-            node.set_vy(node.data.velocity.y * params.damping + forces[i].1 * params.time_step);
-            node.set_vz(node.data.velocity.z * params.damping + forces[i].2 * params.time_step);
-            
-            // Update position based on velocity
-            node.set_x(node.data.position.x + node.data.velocity.x * params.time_step);
-            node.set_y(node.data.position.y + node.data.velocity.y * params.time_step);
-            node.set_z(node.data.position.z + node.data.velocity.z * params.time_step);
-            
-            // Update node_map as well
-            if let Some(map_node) = node_map.get_mut(&node.id) {
-                map_node.data = node.data.clone();
+        forces
+    }
+
+    /// Number of edges at each node, in the order of `graph.nodes`
+    fn node_degrees(graph: &GraphData) -> Vec<f32> {
+        let index: HashMap<u32, usize> = graph.nodes.iter().enumerate().map(|(i, n)| (n.id, i)).collect();
+        let mut degree = vec![0.0f32; graph.nodes.len()];
+        for edge in &graph.edges {
+            if let (Some(&i), Some(&j)) = (index.get(&edge.source), index.get(&edge.target)) {
+                if i != j {
+                    degree[i] += 1.0;
+                    degree[j] += 1.0;
+                }
             }
         }
-        
-        Ok(())
+        degree
+    }
+
+    /// Degree gravity, as in the kernel: a spring to the origin whose stiffness is
+    /// `degree_gravity` for the busiest node and falls with degree, so hubs settle in
+    /// the centre and leaves stay free
+    fn add_degree_gravity(graph: &GraphData, params: &SimulationParams, forces: &mut [(f32, f32, f32)]) {
+        let degree = Self::node_degrees(graph);
+        let max_degree = degree.iter().copied().fold(0.0f32, f32::max);
+        if max_degree == 0.0 {
+            return;
+        }
+        for (i, node) in graph.nodes.iter().enumerate() {
+            let stiffness = params.degree_gravity * degree[i] / max_degree;
+            forces[i].0 -= node.data.position.x * stiffness;
+            forces[i].1 -= node.data.position.y * stiffness;
+            forces[i].2 -= node.data.position.z * stiffness;
+        }
+    }
+
+    /// ForceAtlas2 (Jacomy et al. 2014), as in the kernel: repulsion between every
+    /// pair scaled by (degree + 1) of both and falling off with 1/d, attraction along
+    /// edges that is linear in d, or log(1 + d) for LinLog, and gravity towards the
+    /// origin that is constant, or linear in d when strong
+    fn force_atlas2_forces(graph: &GraphData, params: &SimulationParams) -> Vec<(f32, f32, f32)> {
+        let nodes_len = graph.nodes.len();
+        let mut forces = vec![(0.0, 0.0, 0.0); nodes_len];
+        let index: HashMap<u32, usize> = graph.nodes.iter().enumerate().map(|(i, n)| (n.id, i)).collect();
+        let degree = Self::node_degrees(graph);
+
+        for i in 0..nodes_len {
+            for j in (i + 1)..nodes_len {
+                let node_i = &graph.nodes[i];
+                let node_j = &graph.nodes[j];
+                let dx = node_j.data.position.x - node_i.data.position.x;
+                let dy = node_j.data.position.y - node_i.data.position.y;
+                let dz = node_j.data.position.z - node_i.data.position.z;
+                let distance_squared = dx * dx + dy * dy + dz * dz;
+                if distance_squared < 0.0001 { continue; }
+                let distance = distance_squared.sqrt();
+
+                let min_distance = params.collision_radius
+                    * (SimulationParams::radius_scale(node_i) + SimulationParams::radius_scale(node_j));
+                let mut push = params.collision_stiffness * (min_distance - distance).max(0.0);
+                if distance < params.max_repulsion_distance {
+                    let charge = params.node_physics(node_i.node_type.as_deref()).charge
+                        * params.node_physics(node_j.node_type.as_deref()).charge;
+                    push += params.repulsion * (degree[i] + 1.0) * (degree[j] + 1.0) * charge / distance;
+                }
+
+                let (fx, fy, fz) = (dx / distance * push, dy / distance * push, dz / distance * push);
+                forces[i].0 -= fx;
+                forces[i].1 -= fy;
+                forces[i].2 -= fz;
+                forces[j].0 += fx;
+                forces[j].1 += fy;
+                forces[j].2 += fz;
+            }
+        }
+
+        for (edge, weight) in graph.edges.iter().zip(params.edge_weights(&graph.edges)) {
+            let (Some(&i), Some(&j)) = (index.get(&edge.source), index.get(&edge.target)) else { continue };
+            if i == j { continue; }
+            let dx = graph.nodes[j].data.position.x - graph.nodes[i].data.position.x;
+            let dy = graph.nodes[j].data.position.y - graph.nodes[i].data.position.y;
+            let dz = graph.nodes[j].data.position.z - graph.nodes[i].data.position.z;
+            let distance = (dx * dx + dy * dy + dz * dz).sqrt();
+            if distance < 0.01 { continue; }
+            let stretch = match params.mode {
+                SimulationMode::LinLog => distance.ln_1p(),
+                _ => distance,
+            };
+            let pull = params.spring_strength * weight * stretch;
+            let (fx, fy, fz) = (dx / distance * pull, dy / distance * pull, dz / distance * pull);
+            forces[i].0 += fx;
+            forces[i].1 += fy;
+            forces[i].2 += fz;
+            forces[j].0 -= fx;
+            forces[j].1 -= fy;
+            forces[j].2 -= fz;
+        }
+
+        for (i, node) in graph.nodes.iter().enumerate() {
+            let p = node.data.position;
+            let distance = (p.x * p.x + p.y * p.y + p.z * p.z).sqrt();
+            if distance < 0.01 { continue; }
+            let strength = if params.strong_gravity { distance } else { 1.0 };
+            let pull = params.gravity * (degree[i] + 1.0) * strength / distance;
+            forces[i].0 -= p.x * pull;
+            forces[i].1 -= p.y * pull;
+            forces[i].2 -= p.z * pull;
+        }
+        forces
     }
 
     pub async fn get_paginated_graph_data(
@@ -946,32 +1053,15 @@ impl GraphService {
         let start = page * page_size;
         let end = std::cmp::min((page + 1) * page_size, total_nodes);
 
-        let model_page_nodes: Vec<Node> = graph.nodes
+        let page_nodes: Vec<Node> = graph.nodes
             .iter()
             .skip(start)
             .take(end - start)
             .cloned()
             .collect();
 
-        let page_nodes: Vec<crate::utils::socket_flow_messages::Node> = model_page_nodes.iter().map(|model_node| {
-            crate::utils::socket_flow_messages::Node {
-                id: model_node.id.to_string(), // Convert u32 to String
-                metadata_id: model_node.metadata_id.clone(),
-                label: model_node.label.clone(),
-                data: model_node.data, // BinaryNodeData is the same
-                metadata: model_node.metadata.clone(),
-                file_size: model_node.file_size, // This field is present in socket_flow_messages::Node but marked #[serde(skip)]
-                node_type: model_node.node_type.clone(),
-                size: model_node.size,
-                color: model_node.color.clone(),
-                weight: model_node.weight,
-                group: model_node.group.clone(),
-                user_data: model_node.user_data.clone(),
-            }
-        }).collect();
-
         // Get edges that connect to these nodes
-        let node_ids: HashSet<u32> = model_page_nodes.iter()
+        let node_ids: HashSet<u32> = page_nodes.iter()
             .map(|n| n.id)
             .collect();
 
@@ -1057,7 +1147,7 @@ impl GraphService {
         self.gpu_compute.clone()
     }
  
-    pub async fn update_node_positions(&self, updates: Vec<(u32, Node)>, client_manager_addr: Addr<ClientManagerActor>) -> Result<(), Error> {
+    pub async fn update_node_positions(&self, updates: Vec<(u32, Node)>) -> Result<(), Error> {
         let mut graph = self.graph_data.write().await;
         let mut node_map = self.node_map.write().await;
         
@@ -1096,9 +1186,6 @@ impl GraphService {
             }
         });
         
-        // Broadcast all positions
-        Self::broadcast_positions(client_manager_addr, &graph.nodes).await;
-        
         Ok(())
     }
 
@@ -1197,7 +1284,7 @@ pub async fn initialize_gpu(&mut self, graph_data: &GraphData) -> Result<(), Err
     pub async fn test_metadata_transfer() -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
         use chrono::Utc;
         use std::collections::HashMap;
-        use crate::models::metadata::Metadata;
+        use crate::models::metadata::{Metadata, PageStats};
 
         // Create test metadata
         let mut metadata = crate::models::metadata::MetadataStore::new();
@@ -1214,13 +1301,23 @@ pub async fn initialize_gpu(&mut self, graph_data: &GraphData) -> Result<(), Err
             last_modified: Utc::now(),
             perplexity_link: "https://example.com".to_string(),
             last_perplexity_process: Some(Utc::now()),
+            perplexity_summary: String::new(),
             topic_counts: HashMap::new(),
+            unresolved_links: HashMap::new(),
+            private_links: HashMap::new(),
+            tags: Vec::new(),
+            aliases: Vec::new(),
+            link_previews: Vec::new(),
+            page_stats: PageStats::default(),
+            tasks: Vec::new(),
+            near_duplicates: HashMap::new(),
+            link_checks: HashMap::new(),
         };
         
         metadata.insert(file_name.to_string(), meta.clone());
         
         // Build graph from metadata
-        let graph = Self::build_graph_from_metadata(&metadata).await?;
+        let graph = Self::build_graph_from_metadata(&metadata, None).await?;
         
         // Check that the graph has one node with the correct metadata
         assert_eq!(graph.nodes.len(), 1);
@@ -1251,42 +1348,42 @@ pub async fn initialize_gpu(&mut self, graph_data: &GraphData) -> Result<(), Err
         println!("All metadata tests passed!");
         Ok(())
     }
-    
-    /// Start a separate broadcast loop to periodically push position updates to all clients
-    pub fn start_broadcast_loop(&self, client_manager_addr: Addr<ClientManagerActor>) {
-        info!("[GraphService] Starting position broadcast loop for client synchronization...");
- 
-        // Clone what we need for the async task
-        let service_clone = self.clone();
-        let simulation_id = self.simulation_id.clone();
-        let captured_client_manager_addr = client_manager_addr.clone(); // Capture ClientManagerActor Addr for the loop
- 
-        // Spawn a new task for the broadcast loop
-        tokio::spawn(async move {
-            info!("[GraphService:{}] Position broadcast loop starting", simulation_id);
- 
-            // Main broadcast loop
-            loop {
-                // Check if shutdown was requested
-                if service_clone.shutdown_requested.load(Ordering::SeqCst) {
-                    info!("[GraphService:{}] Broadcast loop shutting down due to shutdown request", simulation_id);
-                    break;
-                }
- 
-                // Get current node positions
-                let nodes = service_clone.get_node_positions().await;
- // Broadcast positions to all clients if we have any
- if !nodes.is_empty() {
-     GraphService::broadcast_positions(captured_client_manager_addr.clone(), &nodes).await;
- }
 
- 
-                // Sleep to avoid excessive updates
-                tokio::time::sleep(Duration::from_millis(100)).await;
-            }
- 
-            info!("[GraphService:{}] Position broadcast loop exited", simulation_id);
-        });
-        info!("[GraphService] Position broadcast loop started");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_force_atlas2_forces() {
+        let mut graph = GraphData::default();
+        graph.nodes.push(Node::new_with_id("a".to_string(), Some(1)).with_position(-10.0, 0.0, 0.0));
+        graph.nodes.push(Node::new_with_id("b".to_string(), Some(2)).with_position(10.0, 0.0, 0.0));
+        graph.edges.push(Edge::new(1, 2, 1.0));
+        let params = SimulationParams {
+            mode: SimulationMode::ForceAtlas2,
+            repulsion: 1.0,
+            spring_strength: 1.0,
+            max_repulsion_distance: 500.0,
+            gravity: 0.0,
+            ..SimulationParams::new()
+        };
+
+        // Attraction 20 against repulsion 2 * 2 / 20
+        let forces = GraphService::force_atlas2_forces(&graph, &params);
+        assert!((forces[0].0 - 19.8).abs() < 1e-4);
+        assert!((forces[0].0 + forces[1].0).abs() < 1e-4);
+
+        let lin_log = SimulationParams { mode: SimulationMode::LinLog, ..params.clone() };
+        let forces = GraphService::force_atlas2_forces(&graph, &lin_log);
+        assert!((forces[0].0 - (21.0f32.ln() - 0.2)).abs() < 1e-4);
+
+        // Constant gravity of (degree + 1) pulls towards the origin
+        let gravity = SimulationParams { gravity: 1.0, repulsion: 0.0, spring_strength: 0.0, ..params };
+        let forces = GraphService::force_atlas2_forces(&graph, &gravity);
+        assert!((forces[0].0 - 2.0).abs() < 1e-4);
+        let strong = SimulationParams { strong_gravity: true, ..gravity };
+        assert!((GraphService::force_atlas2_forces(&graph, &strong)[0].0 - 20.0).abs() < 1e-4);
     }
 }
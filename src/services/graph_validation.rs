@@ -0,0 +1,251 @@
+//! Checks a built graph is fit to simulate
+//!
+//! Bad metadata can produce nodes sharing an id, edges to nodes that were never
+//! added, positions that aren't finite or nodes with no mass. The solvers divide
+//! by mass and distance, so any of these turns into NaNs that spread through the
+//! whole layout. Every build is validated and, unless `GRAPH_AUTO_REPAIR` is off,
+//! repaired before it replaces the live graph.
+
+use log::warn;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use thiserror::Error;
+
+use crate::models::graph::GraphData;
+use crate::types::vec3::Vec3Data;
+
+/// Mass given to massless nodes, what a page with an empty file gets
+pub const DEFAULT_MASS: u8 = 2;
+// Repaired positions go on the sphere new graphs are laid out on
+const RESET_RADIUS: f32 = 3.0;
+
+#[derive(Debug, Clone, PartialEq, Error, Serialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum GraphIssue {
+    #[error("node id {id} is used by {count} nodes")]
+    DuplicateNodeId { id: u32, count: usize },
+    #[error("edge {edge_id} references missing node {node_id}")]
+    #[serde(rename_all = "camelCase")]
+    MissingEdgeEndpoint { edge_id: String, node_id: u32 },
+    #[error("node {id} has a non-finite position")]
+    NonFinitePosition { id: u32 },
+    #[error("node {id} has a non-finite velocity")]
+    NonFiniteVelocity { id: u32 },
+    #[error("node {id} has zero mass")]
+    ZeroMass { id: u32 },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationReport {
+    pub node_count: usize,
+    pub edge_count: usize,
+    pub issues: Vec<GraphIssue>,
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RepairReport {
+    /// Issues found and fixed
+    pub repaired: Vec<GraphIssue>,
+    /// The graph as left by the repair
+    pub validation: ValidationReport,
+}
+
+/// Which issues `repair` fixes; those left out are only reported
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RepairOptions {
+    /// Keep the first node with each id and drop the rest
+    pub drop_duplicate_nodes: bool,
+    /// Drop edges to or from nodes that aren't in the graph
+    pub drop_dangling_edges: bool,
+    /// Move nodes with a non-finite position back onto the start sphere and zero
+    /// non-finite velocities
+    pub reset_non_finite: bool,
+    /// Give massless nodes `DEFAULT_MASS`
+    pub fill_zero_mass: bool,
+}
+
+impl Default for RepairOptions {
+    fn default() -> Self {
+        Self { drop_duplicate_nodes: true, drop_dangling_edges: true, reset_non_finite: true, fill_zero_mass: true }
+    }
+}
+
+impl RepairOptions {
+    pub fn none() -> Self {
+        Self { drop_duplicate_nodes: false, drop_dangling_edges: false, reset_non_finite: false, fill_zero_mass: false }
+    }
+
+    /// Every repair unless `GRAPH_AUTO_REPAIR` is `false`, `0`, `no` or `off`
+    pub fn from_env() -> Self {
+        match std::env::var("GRAPH_AUTO_REPAIR") {
+            Ok(value) if matches!(value.trim().to_lowercase().as_str(), "0" | "false" | "no" | "off") => Self::none(),
+            _ => Self::default(),
+        }
+    }
+}
+
+pub fn validate(graph: &GraphData) -> ValidationReport {
+    let mut counts: HashMap<u32, usize> = HashMap::new();
+    for node in &graph.nodes {
+        *counts.entry(node.id).or_insert(0) += 1;
+    }
+    let mut duplicates: Vec<(u32, usize)> = counts.iter()
+        .filter(|(_, count)| **count > 1)
+        .map(|(id, count)| (*id, *count))
+        .collect();
+    duplicates.sort_unstable();
+
+    let mut issues: Vec<GraphIssue> = duplicates.into_iter()
+        .map(|(id, count)| GraphIssue::DuplicateNodeId { id, count })
+        .collect();
+    for edge in &graph.edges {
+        let mut missing = vec![edge.source];
+        if edge.target != edge.source {
+            missing.push(edge.target);
+        }
+        issues.extend(missing.into_iter()
+            .filter(|id| !counts.contains_key(id))
+            .map(|node_id| GraphIssue::MissingEdgeEndpoint { edge_id: edge.id.clone(), node_id }));
+    }
+    for node in &graph.nodes {
+        if !is_finite(&node.data.position) {
+            issues.push(GraphIssue::NonFinitePosition { id: node.id });
+        }
+        if !is_finite(&node.data.velocity) {
+            issues.push(GraphIssue::NonFiniteVelocity { id: node.id });
+        }
+        if node.data.mass == 0 {
+            issues.push(GraphIssue::ZeroMass { id: node.id });
+        }
+    }
+
+    ValidationReport { node_count: graph.nodes.len(), edge_count: graph.edges.len(), issues }
+}
+
+/// Fixes the issues `options` allows, returning the ones it fixed
+pub fn repair(graph: &mut GraphData, options: &RepairOptions) -> Vec<GraphIssue> {
+    let found = validate(graph).issues;
+    let mut repaired = Vec::new();
+
+    if options.drop_duplicate_nodes {
+        let mut seen = HashSet::new();
+        graph.nodes.retain(|node| seen.insert(node.id));
+    }
+    if options.drop_dangling_edges {
+        let ids: HashSet<u32> = graph.nodes.iter().map(|node| node.id).collect();
+        graph.edges.retain(|edge| ids.contains(&edge.source) && ids.contains(&edge.target));
+    }
+    for node in &mut graph.nodes {
+        if options.reset_non_finite {
+            if !is_finite(&node.data.position) {
+                node.data.position = reset_position(node.id);
+            }
+            if !is_finite(&node.data.velocity) {
+                node.data.velocity = Vec3Data::zero();
+            }
+        }
+        if options.fill_zero_mass && node.data.mass == 0 {
+            node.data.mass = DEFAULT_MASS;
+        }
+    }
+
+    for issue in found {
+        let fixed = match issue {
+            GraphIssue::DuplicateNodeId { .. } => options.drop_duplicate_nodes,
+            GraphIssue::MissingEdgeEndpoint { .. } => options.drop_dangling_edges,
+            GraphIssue::NonFinitePosition { .. } | GraphIssue::NonFiniteVelocity { .. } => options.reset_non_finite,
+            GraphIssue::ZeroMass { .. } => options.fill_zero_mass,
+        };
+        if fixed {
+            repaired.push(issue);
+        }
+    }
+    repaired
+}
+
+/// Repairs `graph` as far as `options` allow and logs what was wrong with it
+pub fn sanitize(graph: &mut GraphData, options: &RepairOptions) -> RepairReport {
+    let repaired = repair(graph, options);
+    let validation = validate(graph);
+    if !repaired.is_empty() {
+        warn!("[Validation] Repaired {} issues in the graph, first: {}", repaired.len(), repaired[0]);
+    }
+    if !validation.is_valid() {
+        warn!("[Validation] Graph has {} unrepaired issues, first: {}", validation.issues.len(), validation.issues[0]);
+    }
+    RepairReport { repaired, validation }
+}
+
+fn is_finite(v: &Vec3Data) -> bool {
+    v.x.is_finite() && v.y.is_finite() && v.z.is_finite()
+}
+
+/// A point on the start sphere picked by id, so a node put back lands in the same
+/// place every time
+pub fn reset_position(id: u32) -> Vec3Data {
+    let mut rng = StdRng::seed_from_u64(u64::from(id));
+    let z: f32 = rng.gen_range(-1.0..1.0);
+    let theta: f32 = rng.gen_range(0.0..std::f32::consts::TAU);
+    let ring = (1.0 - z * z).sqrt();
+    Vec3Data::new(RESET_RADIUS * ring * theta.cos(), RESET_RADIUS * ring * theta.sin(), RESET_RADIUS * z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::edge::Edge;
+    use crate::models::node::Node;
+
+    fn node(id: u32) -> Node {
+        let mut node = Node::new_with_id(format!("page-{}", id), Some(id)).with_position(1.0, 0.0, 0.0);
+        node.set_file_size(100);
+        node
+    }
+
+    #[test]
+    fn test_validate_and_repair() {
+        let mut graph = GraphData::new();
+        graph.nodes = vec![node(1), node(2), node(2).with_label("copy".to_string()), node(3)];
+        graph.nodes[0].data.position.y = f32::NAN;
+        graph.nodes[1].data.velocity.z = f32::INFINITY;
+        graph.nodes[3].data.mass = 0;
+        graph.edges = vec![Edge::new(1, 2, 1.0), Edge::new(2, 9, 1.0)];
+
+        let report = validate(&graph);
+        assert_eq!(report.issues, vec![
+            GraphIssue::DuplicateNodeId { id: 2, count: 2 },
+            GraphIssue::MissingEdgeEndpoint { edge_id: "2-9".to_string(), node_id: 9 },
+            GraphIssue::NonFinitePosition { id: 1 },
+            GraphIssue::NonFiniteVelocity { id: 2 },
+            GraphIssue::ZeroMass { id: 3 },
+        ]);
+
+        // Only what the options allow is fixed
+        let options = RepairOptions { fill_zero_mass: false, ..RepairOptions::default() };
+        let report = sanitize(&mut graph, &options);
+        assert_eq!(report.repaired.len(), 4);
+        assert_eq!(report.validation.issues, vec![GraphIssue::ZeroMass { id: 3 }]);
+        assert_eq!(graph.nodes.len(), 3);
+        assert!(graph.nodes.iter().all(|node| node.label != "copy"));
+        assert_eq!(graph.edges.len(), 1);
+        let reset = graph.nodes[0].data.position;
+        assert!(((reset.x * reset.x + reset.y * reset.y + reset.z * reset.z).sqrt() - RESET_RADIUS).abs() < 1e-4);
+        assert_eq!(graph.nodes[0].data.position, reset_position(1));
+        assert_eq!(graph.nodes[1].data.velocity, Vec3Data::zero());
+
+        assert_eq!(repair(&mut graph, &RepairOptions::default()), vec![GraphIssue::ZeroMass { id: 3 }]);
+        assert!(validate(&graph).is_valid());
+        assert_eq!(graph.nodes[2].data.mass, DEFAULT_MASS);
+    }
+}
@@ -0,0 +1,99 @@
+//! GraphML export of the graph
+//!
+//! Writes nodes and edges with their labels, types, weights and current positions
+//! so the vault can be opened in Gephi, yEd or networkx.
+
+use std::fmt::Write;
+
+use crate::models::graph::GraphData;
+
+/// `(id, for, attr.name, attr.type)` of every GraphML data key written
+const KEYS: &[(&str, &str, &str, &str)] = &[
+    ("label", "node", "label", "string"),
+    ("page", "node", "page", "string"),
+    ("type", "node", "type", "string"),
+    ("size", "node", "fileSize", "long"),
+    ("x", "node", "x", "float"),
+    ("y", "node", "y", "float"),
+    ("z", "node", "z", "float"),
+    ("weight", "edge", "weight", "float"),
+    ("edgeType", "edge", "type", "string"),
+];
+
+/// `value` with the five XML special characters replaced by entities
+pub fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Builds a directed GraphML document of `graph` at its current positions
+pub fn export_graphml(graph: &GraphData) -> String {
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n");
+    for (id, target, name, kind) in KEYS {
+        let _ = writeln!(out, "  <key id=\"{}\" for=\"{}\" attr.name=\"{}\" attr.type=\"{}\"/>", id, target, name, kind);
+    }
+    out.push_str("  <graph id=\"G\" edgedefault=\"directed\">\n");
+
+    for node in &graph.nodes {
+        let _ = writeln!(out, "    <node id=\"n{}\">", node.id);
+        let _ = writeln!(out, "      <data key=\"label\">{}</data>", escape(&node.label));
+        let _ = writeln!(out, "      <data key=\"page\">{}</data>", escape(&node.metadata_id));
+        if let Some(node_type) = &node.node_type {
+            let _ = writeln!(out, "      <data key=\"type\">{}</data>", escape(node_type));
+        }
+        let _ = writeln!(out, "      <data key=\"size\">{}</data>", node.file_size);
+        let position = node.data.position;
+        let _ = writeln!(out, "      <data key=\"x\">{}</data>", position.x);
+        let _ = writeln!(out, "      <data key=\"y\">{}</data>", position.y);
+        let _ = writeln!(out, "      <data key=\"z\">{}</data>", position.z);
+        out.push_str("    </node>\n");
+    }
+
+    for (index, edge) in graph.edges.iter().enumerate() {
+        let _ = writeln!(out, "    <edge id=\"e{}\" source=\"n{}\" target=\"n{}\">", index, edge.source, edge.target);
+        let _ = writeln!(out, "      <data key=\"weight\">{}</data>", edge.weight);
+        if let Some(edge_type) = &edge.edge_type {
+            let _ = writeln!(out, "      <data key=\"edgeType\">{}</data>", escape(edge_type));
+        }
+        out.push_str("    </edge>\n");
+    }
+
+    out.push_str("  </graph>\n</graphml>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::edge::Edge;
+    use crate::models::node::Node;
+
+    #[test]
+    fn test_export_graphml() {
+        let mut graph = GraphData::new();
+        let a = Node::new_with_id("Fish & Chips".to_string(), Some(1)).with_label("Fish & Chips".to_string());
+        let b = Node::new_with_id("<Cod>".to_string(), Some(2));
+        graph.nodes = vec![a, b];
+        graph.edges.push(Edge::new(1, 2, 2.5));
+
+        let xml = export_graphml(&graph);
+        assert!(xml.contains("<node id=\"n1\">"));
+        assert!(xml.contains("<data key=\"label\">Fish &amp; Chips</data>"));
+        assert!(xml.contains("<data key=\"page\">&lt;Cod&gt;</data>"));
+        assert!(xml.contains("<edge id=\"e0\" source=\"n1\" target=\"n2\">"));
+        assert!(xml.contains("<data key=\"weight\">2.5</data>"));
+        assert!(xml.trim_end().ends_with("</graphml>"));
+    }
+}
@@ -0,0 +1,422 @@
+//! In-process background job queue
+//!
+//! Long-running work (GitHub sync, enrichment, ...) is submitted here instead of running
+//! inside a request handler. Each job gets an id that clients can poll via `/api/jobs/{id}`
+//! or follow through `job` events on the event bus. Cancellation is cooperative: jobs
+//! should check `JobContext::is_cancelled` between units of work, and running jobs are
+//! additionally aborted at their next await point.
+
+use crate::services::event_bus::{EventBus, JobEvent};
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use log::{debug, info, warn};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock};
+use tokio::sync::{mpsc, Semaphore};
+use tokio::task::AbortHandle;
+use uuid::Uuid;
+
+const DEFAULT_MAX_CONCURRENT_JOBS: usize = 2;
+// Finished jobs are kept around so clients can still read the outcome
+const MAX_FINISHED_JOBS: usize = 200;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+impl JobStatus {
+    pub fn is_finished(&self) -> bool {
+        matches!(self, JobStatus::Completed | JobStatus::Failed | JobStatus::Cancelled)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobProgress {
+    pub current: usize,
+    pub total: usize,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct JobInfo {
+    pub id: String,
+    pub kind: String,
+    pub status: JobStatus,
+    pub progress: Option<JobProgress>,
+    pub created_at: DateTime<Utc>,
+    pub started_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub error: Option<String>,
+    pub result: Option<serde_json::Value>,
+}
+
+pub type JobResult = Result<serde_json::Value, String>;
+type JobFn = Box<dyn FnOnce(JobContext) -> BoxFuture<'static, JobResult> + Send>;
+
+struct QueuedJob {
+    id: String,
+    run: JobFn,
+}
+
+/// Handle given to a running job for progress reporting and cancellation checks
+#[derive(Clone)]
+pub struct JobContext {
+    id: String,
+    cancelled: Arc<AtomicBool>,
+    queue: JobQueue,
+}
+
+impl JobContext {
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    pub fn report_progress(&self, current: usize, total: usize, message: Option<String>) {
+        let progress = JobProgress { current, total, message };
+        self.queue.update(&self.id, |job| job.progress = Some(progress.clone()));
+        self.queue.event_bus.publish(JobEvent::Progress {
+            id: self.id.clone(),
+            current,
+            total,
+            message: progress.message,
+        });
+    }
+}
+
+#[derive(Clone)]
+pub struct JobQueue {
+    jobs: Arc<RwLock<HashMap<String, JobInfo>>>,
+    cancel_flags: Arc<RwLock<HashMap<String, Arc<AtomicBool>>>>,
+    abort_handles: Arc<RwLock<HashMap<String, AbortHandle>>>,
+    sender: mpsc::UnboundedSender<QueuedJob>,
+    event_bus: EventBus,
+}
+
+impl JobQueue {
+    /// Creates the queue and spawns its dispatcher; must be called inside a Tokio runtime
+    pub fn new(event_bus: EventBus) -> Self {
+        Self::with_concurrency(event_bus, DEFAULT_MAX_CONCURRENT_JOBS)
+    }
+
+    pub fn with_concurrency(event_bus: EventBus, max_concurrent: usize) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let queue = Self {
+            jobs: Arc::new(RwLock::new(HashMap::new())),
+            cancel_flags: Arc::new(RwLock::new(HashMap::new())),
+            abort_handles: Arc::new(RwLock::new(HashMap::new())),
+            sender,
+            event_bus,
+        };
+        queue.start(receiver, max_concurrent.max(1));
+        queue
+    }
+
+    fn start(&self, mut receiver: mpsc::UnboundedReceiver<QueuedJob>, max_concurrent: usize) {
+        let queue = self.clone();
+        let permits = Arc::new(Semaphore::new(max_concurrent));
+        tokio::spawn(async move {
+            while let Some(job) = receiver.recv().await {
+                let permit = match Arc::clone(&permits).acquire_owned().await {
+                    Ok(permit) => permit,
+                    Err(_) => break,
+                };
+                let cancelled = queue.cancel_flag(&job.id);
+                if cancelled.load(Ordering::SeqCst) || !queue.mark_running(&job.id) {
+                    // Cancelled while still queued; already marked as such by `cancel`
+                    queue.cancel_flags.write().unwrap().remove(&job.id);
+                    continue;
+                }
+                queue.publish_status(&job.id);
+
+                let ctx = JobContext {
+                    id: job.id.clone(),
+                    cancelled: Arc::clone(&cancelled),
+                    queue: queue.clone(),
+                };
+                let runner = queue.clone();
+                let id = job.id.clone();
+                let flag = Arc::clone(&cancelled);
+                let handle = tokio::spawn(async move {
+                    let outcome = (job.run)(ctx).await;
+                    runner.finish(&id, outcome, &flag);
+                    drop(permit);
+                });
+
+                queue.abort_handles.write().unwrap().insert(job.id.clone(), handle.abort_handle());
+                // A `cancel` that came before the handle was registered couldn't abort the job
+                if cancelled.load(Ordering::SeqCst) {
+                    handle.abort();
+                }
+                // A job that was aborted never reaches `finish`; watch for that separately.
+                // The handle and flag are dropped here, once the job can no longer run.
+                let watcher = queue.clone();
+                let id = job.id;
+                tokio::spawn(async move {
+                    if let Err(e) = handle.await {
+                        if e.is_cancelled() {
+                            watcher.finish(&id, Err("Cancelled".to_string()), &Arc::new(AtomicBool::new(true)));
+                        } else {
+                            watcher.finish(&id, Err(format!("Job panicked: {}", e)), &Arc::new(AtomicBool::new(false)));
+                        }
+                    }
+                    watcher.abort_handles.write().unwrap().remove(&id);
+                    watcher.cancel_flags.write().unwrap().remove(&id);
+                });
+            }
+        });
+    }
+
+    /// Queues a job and returns its id
+    pub fn submit<F>(&self, kind: &str, run: F) -> String
+    where
+        F: FnOnce(JobContext) -> BoxFuture<'static, JobResult> + Send + 'static,
+    {
+        let id = Uuid::new_v4().to_string();
+        let info = JobInfo {
+            id: id.clone(),
+            kind: kind.to_string(),
+            status: JobStatus::Queued,
+            progress: None,
+            created_at: Utc::now(),
+            started_at: None,
+            finished_at: None,
+            error: None,
+            result: None,
+        };
+        self.jobs.write().unwrap().insert(id.clone(), info);
+        self.cancel_flags.write().unwrap().insert(id.clone(), Arc::new(AtomicBool::new(false)));
+        self.prune();
+
+        info!("[JobQueue] Queued {} job {}", kind, id);
+        self.publish_status(&id);
+        if self.sender.send(QueuedJob { id: id.clone(), run: Box::new(run) }).is_err() {
+            warn!("[JobQueue] Dispatcher is not running; job {} will never start", id);
+        }
+        id
+    }
+
+    /// Like `submit`, but refuses if a job of the same kind is already queued or running.
+    /// Returns the id of the existing job as the error.
+    pub fn submit_unique<F>(&self, kind: &str, run: F) -> Result<String, String>
+    where
+        F: FnOnce(JobContext) -> BoxFuture<'static, JobResult> + Send + 'static,
+    {
+        if let Some(existing) = self.active_job_of_kind(kind) {
+            return Err(existing);
+        }
+        Ok(self.submit(kind, run))
+    }
+
+    pub fn active_job_of_kind(&self, kind: &str) -> Option<String> {
+        self.jobs.read().unwrap()
+            .values()
+            .find(|j| j.kind == kind && !j.status.is_finished())
+            .map(|j| j.id.clone())
+    }
+
+    pub fn get(&self, id: &str) -> Option<JobInfo> {
+        self.jobs.read().unwrap().get(id).cloned()
+    }
+
+    /// All known jobs, newest first
+    pub fn list(&self) -> Vec<JobInfo> {
+        let mut jobs: Vec<JobInfo> = self.jobs.read().unwrap().values().cloned().collect();
+        jobs.sort_by_key(|j| std::cmp::Reverse(j.created_at));
+        jobs
+    }
+
+    /// Requests cancellation. Returns false if the job is unknown or already finished.
+    pub fn cancel(&self, id: &str) -> bool {
+        let status = match self.get(id) {
+            Some(job) => job.status,
+            None => return false,
+        };
+        if status.is_finished() {
+            return false;
+        }
+
+        self.cancel_flag(id).store(true, Ordering::SeqCst);
+        match status {
+            JobStatus::Queued => {
+                self.finish(id, Err("Cancelled".to_string()), &Arc::new(AtomicBool::new(true)));
+            }
+            _ => {
+                if let Some(handle) = self.abort_handles.read().unwrap().get(id) {
+                    handle.abort();
+                }
+            }
+        }
+        info!("[JobQueue] Cancellation requested for job {}", id);
+        true
+    }
+
+    fn cancel_flag(&self, id: &str) -> Arc<AtomicBool> {
+        self.cancel_flags.write().unwrap()
+            .entry(id.to_string())
+            .or_insert_with(|| Arc::new(AtomicBool::new(false)))
+            .clone()
+    }
+
+    /// Moves a queued job to running. Returns false if it has already finished,
+    /// which a job cancelled while queued has.
+    fn mark_running(&self, id: &str) -> bool {
+        let mut jobs = self.jobs.write().unwrap();
+        match jobs.get_mut(id) {
+            Some(job) if !job.status.is_finished() => {
+                job.status = JobStatus::Running;
+                job.started_at = Some(Utc::now());
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn update<F: FnOnce(&mut JobInfo)>(&self, id: &str, f: F) {
+        if let Some(job) = self.jobs.write().unwrap().get_mut(id) {
+            f(job);
+        }
+    }
+
+    fn finish(&self, id: &str, outcome: JobResult, cancelled: &AtomicBool) {
+        let mut already_finished = false;
+        self.update(id, |job| {
+            if job.status.is_finished() {
+                already_finished = true;
+                return;
+            }
+            job.finished_at = Some(Utc::now());
+            match outcome {
+                _ if cancelled.load(Ordering::SeqCst) => job.status = JobStatus::Cancelled,
+                Ok(result) => {
+                    job.status = JobStatus::Completed;
+                    job.result = Some(result);
+                }
+                Err(e) => {
+                    job.status = JobStatus::Failed;
+                    job.error = Some(e);
+                }
+            }
+        });
+        if already_finished {
+            return;
+        }
+        debug!("[JobQueue] Job {} finished", id);
+        self.publish_status(id);
+    }
+
+    fn publish_status(&self, id: &str) {
+        if let Some(job) = self.get(id) {
+            self.event_bus.publish(JobEvent::StatusChanged {
+                id: job.id,
+                job_kind: job.kind,
+                status: job.status,
+                error: job.error,
+            });
+        }
+    }
+
+    fn prune(&self) {
+        let mut jobs = self.jobs.write().unwrap();
+        let mut finished: Vec<(DateTime<Utc>, String)> = jobs.values()
+            .filter(|j| j.status.is_finished())
+            .map(|j| (j.finished_at.unwrap_or(j.created_at), j.id.clone()))
+            .collect();
+        if finished.len() <= MAX_FINISHED_JOBS {
+            return;
+        }
+        finished.sort();
+        let excess = finished.len() - MAX_FINISHED_JOBS;
+        for (_, id) in finished.into_iter().take(excess) {
+            jobs.remove(&id);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+    use std::time::Duration;
+
+    async fn wait_for_finish(queue: &JobQueue, id: &str) -> JobInfo {
+        for _ in 0..100 {
+            if let Some(job) = queue.get(id) {
+                if job.status.is_finished() {
+                    return job;
+                }
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("job {} did not finish", id);
+    }
+
+    #[tokio::test]
+    async fn test_job_completes_with_result() {
+        let queue = JobQueue::new(EventBus::default());
+        let id = queue.submit("test", |ctx| async move {
+            ctx.report_progress(1, 1, None);
+            Ok(serde_json::json!({ "done": true }))
+        }.boxed());
+
+        let job = wait_for_finish(&queue, &id).await;
+        assert_eq!(job.status, JobStatus::Completed);
+        assert_eq!(job.result, Some(serde_json::json!({ "done": true })));
+        assert_eq!(job.progress.map(|p| p.total), Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_running_job_can_be_cancelled() {
+        let queue = JobQueue::new(EventBus::default());
+        let id = queue.submit("slow", |_ctx| async move {
+            tokio::time::sleep(Duration::from_secs(30)).await;
+            Ok(serde_json::Value::Null)
+        }.boxed());
+
+        // Let the dispatcher start it
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(queue.submit_unique("slow", |_ctx| async { Ok(serde_json::Value::Null) }.boxed()).is_err());
+        assert!(queue.cancel(&id));
+
+        let job = wait_for_finish(&queue, &id).await;
+        assert_eq!(job.status, JobStatus::Cancelled);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_queued_job_never_runs() {
+        let queue = JobQueue::with_concurrency(EventBus::default(), 1);
+        let blocking = queue.submit("blocking", |_ctx| futures::future::pending().boxed());
+        let ran = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&ran);
+        let queued = queue.submit("queued", move |_ctx| async move {
+            flag.store(true, Ordering::SeqCst);
+            Ok(serde_json::Value::Null)
+        }.boxed());
+
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert_eq!(queue.get(&queued).unwrap().status, JobStatus::Queued);
+        assert!(queue.cancel(&queued));
+        // Freeing the only slot lets the dispatcher reach the cancelled job
+        assert!(queue.cancel(&blocking));
+        wait_for_finish(&queue, &blocking).await;
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        assert!(!ran.load(Ordering::SeqCst));
+        assert_eq!(queue.get(&queued).unwrap().status, JobStatus::Cancelled);
+        assert!(queue.cancel_flags.read().unwrap().is_empty());
+        assert!(queue.abort_handles.read().unwrap().is_empty());
+    }
+}
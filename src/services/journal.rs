@@ -0,0 +1,53 @@
+//! Capturing notes into the Logseq journal
+//!
+//! Each day has a journal page named like `2024_03_01.md`. A note becomes a
+//! bullet starting with the time it was taken (`- 14:30 Call the venue`), added
+//! at the end of the day's page. Notes spanning several lines stay in the one
+//! bullet, with the following lines indented under it.
+
+use chrono::{NaiveDate, NaiveTime};
+
+/// The journal page of `date`, the way Logseq names it
+pub fn journal_file_name(date: NaiveDate) -> String {
+    date.format("%Y_%m_%d.md").to_string()
+}
+
+/// A bullet holding `text`, stamped with `time`
+pub fn bullet(time: NaiveTime, text: &str) -> String {
+    let mut lines = text.trim().lines().map(str::trim_end);
+    let mut bullet = format!("- {} {}", time.format("%H:%M"), lines.next().unwrap_or_default());
+    for line in lines {
+        bullet.push_str("\n  ");
+        bullet.push_str(line);
+    }
+    bullet
+}
+
+/// `page` with `bullet` added at the end. A new page, or one holding only the
+/// empty bullet Logseq starts journals with, is replaced by the bullet.
+pub fn append(page: &str, bullet: &str) -> String {
+    let page = page.trim_end();
+    if page.is_empty() || page == "-" {
+        format!("{}\n", bullet)
+    } else {
+        format!("{}\n{}\n", page, bullet)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_bullet() {
+        let date = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        assert_eq!(journal_file_name(date), "2024_03_01.md");
+
+        let time = NaiveTime::from_hms_opt(14, 30, 5).unwrap();
+        let note = bullet(time, " Call the venue\nabout [[Catering]] \n");
+        assert_eq!(note, "- 14:30 Call the venue\n  about [[Catering]]");
+        assert_eq!(append("", &note), format!("{}\n", note));
+        assert_eq!(append("-\n", &note), format!("{}\n", note));
+        assert_eq!(append("- 09:00 Standup\n\n", &note), format!("- 09:00 Standup\n{}\n", note));
+    }
+}
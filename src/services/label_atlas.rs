@@ -0,0 +1,353 @@
+//! Texture atlases of node labels
+//!
+//! Every label is rendered once on the server and packed into a single greyscale
+//! texture, so standalone headsets can draw labels as textured quads instead of
+//! rasterising text on a canvas per node. The atlas is either plain coverage or a
+//! signed distance field, which stays sharp when labels are scaled and lets the
+//! client draw outlines by thresholding.
+
+use ab_glyph::{point, Font, FontVec, PxScale, ScaleFont};
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+
+use crate::config::LabelSettings;
+
+/// Tried in order when neither the settings nor `LABEL_FONT_PATH` name a font
+const DEFAULT_FONT_PATHS: &[&str] = &[
+    "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf",
+    "/usr/share/fonts/TTF/DejaVuSans.ttf",
+    "/usr/share/fonts/dejavu/DejaVuSans.ttf",
+    "/System/Library/Fonts/Supplemental/Arial.ttf",
+];
+const ATLAS_WIDTH: u32 = 2048;
+const MAX_ATLAS_HEIGHT: u32 = 4096;
+const MAX_LABEL_CHARS: usize = 64;
+const MIN_FONT_PX: f32 = 8.0;
+const MAX_FONT_PX: f32 = 128.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AtlasFormat {
+    #[default]
+    Sdf,
+    Bitmap,
+}
+
+#[derive(Debug, Clone, PartialEq, Hash)]
+pub struct LabelAtlasStyle {
+    pub font_path: Option<String>,
+    pub font_px: u32,
+    /// Distance in pixels the SDF covers on each side of a glyph edge
+    pub spread: u32,
+}
+
+impl LabelAtlasStyle {
+    pub fn from_settings(labels: &LabelSettings) -> Self {
+        let font_px = (labels.text_resolution as f32).clamp(MIN_FONT_PX, MAX_FONT_PX).round() as u32;
+        Self {
+            font_path: labels.font_path.clone(),
+            font_px,
+            spread: (font_px / 8).max(2),
+        }
+    }
+}
+
+/// Where one label sits in the atlas, in pixels and normalised texture coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AtlasRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub u0: f32,
+    pub v0: f32,
+    pub u1: f32,
+    pub v1: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LabelAtlas {
+    /// Changes whenever the labels or style do, for matching image and metadata
+    pub version: String,
+    pub format: AtlasFormat,
+    pub width: u32,
+    pub height: u32,
+    pub font_size: u32,
+    /// Padding around each label; for SDF atlases also the distance field's range
+    pub spread: u32,
+    pub labels: BTreeMap<u32, AtlasRegion>,
+    /// Nodes whose labels didn't fit
+    pub skipped: Vec<u32>,
+    #[serde(skip)]
+    pub pixels: Vec<u8>,
+}
+
+/// Loads the configured font, then `LABEL_FONT_PATH`, then a common system font
+pub fn load_font(path: Option<&str>) -> Result<FontVec, String> {
+    let env_path = env::var("LABEL_FONT_PATH").ok();
+    let candidates = path.into_iter()
+        .chain(env_path.as_deref())
+        .chain(DEFAULT_FONT_PATHS.iter().copied());
+    for candidate in candidates {
+        if let Ok(data) = fs::read(candidate) {
+            let font = FontVec::try_from_vec(data).map_err(|e| format!("Invalid font {}: {}", candidate, e))?;
+            debug!("Using label font {}", candidate);
+            return Ok(font);
+        }
+    }
+    Err("No label font found; set labels.font_path or LABEL_FONT_PATH".to_string())
+}
+
+/// Identifies an atlas by its inputs, so unchanged labels reuse the last build
+pub fn atlas_version(labels: &[(u32, String)], style: &LabelAtlasStyle, format: AtlasFormat) -> String {
+    let mut hasher = DefaultHasher::new();
+    labels.hash(&mut hasher);
+    style.hash(&mut hasher);
+    format.hash(&mut hasher);
+    format!("{:016x}", hasher.finish())
+}
+
+/// Coverage of one label, `width` x `height`, with `margin` blank pixels all round
+struct LabelBitmap {
+    width: u32,
+    height: u32,
+    coverage: Vec<f32>,
+}
+
+fn rasterise(font: &FontVec, text: &str, font_px: u32, margin: u32) -> LabelBitmap {
+    let scaled = font.as_scaled(PxScale::from(font_px as f32));
+    let mut glyphs = Vec::new();
+    let mut caret = 0.0;
+    let mut previous = None;
+    for c in text.chars().take(MAX_LABEL_CHARS) {
+        let id = scaled.glyph_id(c);
+        if let Some(previous) = previous {
+            caret += scaled.kern(previous, id);
+        }
+        glyphs.push(id.with_scale_and_position(font_px as f32, point(caret, scaled.ascent())));
+        caret += scaled.h_advance(id);
+        previous = Some(id);
+    }
+
+    let max_text_width = ATLAS_WIDTH - 2 * margin;
+    let width = (caret.ceil() as u32).clamp(1, max_text_width) + 2 * margin;
+    let height = ((scaled.ascent() - scaled.descent()).ceil() as u32).max(1) + 2 * margin;
+    let mut coverage = vec![0.0f32; (width * height) as usize];
+    for glyph in glyphs {
+        let Some(outlined) = scaled.outline_glyph(glyph) else { continue };
+        let bounds = outlined.px_bounds();
+        outlined.draw(|x, y, c| {
+            let px = bounds.min.x as i64 + x as i64 + margin as i64;
+            let py = bounds.min.y as i64 + y as i64 + margin as i64;
+            if px >= 0 && py >= 0 && (px as u32) < width && (py as u32) < height {
+                let cell = &mut coverage[(py as u32 * width + px as u32) as usize];
+                *cell = cell.max(c);
+            }
+        });
+    }
+    LabelBitmap { width, height, coverage }
+}
+
+/// Squared Euclidean distance transform along one line (Felzenszwalb and
+/// Huttenlocher): `f` holds 0 at features and a large value elsewhere
+fn edt_1d(f: &[f64], d: &mut [f64], v: &mut [usize], z: &mut [f64]) {
+    let mut k = 0;
+    v[0] = 0;
+    z[0] = f64::NEG_INFINITY;
+    z[1] = f64::INFINITY;
+    for q in 1..f.len() {
+        let parabola_meet = |p: usize| ((f[q] + (q * q) as f64) - (f[p] + (p * p) as f64)) / (2 * (q - p)) as f64;
+        let mut s = parabola_meet(v[k]);
+        while s <= z[k] {
+            k -= 1;
+            s = parabola_meet(v[k]);
+        }
+        k += 1;
+        v[k] = q;
+        z[k] = s;
+        z[k + 1] = f64::INFINITY;
+    }
+    k = 0;
+    for (q, out) in d.iter_mut().enumerate() {
+        while z[k + 1] < q as f64 {
+            k += 1;
+        }
+        let offset = q as f64 - v[k] as f64;
+        *out = offset * offset + f[v[k]];
+    }
+}
+
+/// Distance in pixels from every cell to the nearest cell where `feature` holds
+fn distance_to(width: usize, height: usize, feature: impl Fn(usize) -> bool) -> Vec<f32> {
+    const FAR: f64 = 1e20;
+    let mut grid: Vec<f64> = (0..width * height).map(|i| if feature(i) { 0.0 } else { FAR }).collect();
+    let longest = width.max(height);
+    let (mut f, mut d) = (vec![0.0; longest], vec![0.0; longest]);
+    let (mut v, mut z) = (vec![0; longest], vec![0.0; longest + 1]);
+    for x in 0..width {
+        for y in 0..height {
+            f[y] = grid[y * width + x];
+        }
+        edt_1d(&f[..height], &mut d[..height], &mut v, &mut z);
+        for y in 0..height {
+            grid[y * width + x] = d[y];
+        }
+    }
+    for y in 0..height {
+        let row = &mut grid[y * width..(y + 1) * width];
+        f[..width].copy_from_slice(row);
+        edt_1d(&f[..width], &mut d[..width], &mut v, &mut z);
+        row.copy_from_slice(&d[..width]);
+    }
+    grid.iter().map(|squared| squared.sqrt() as f32).collect()
+}
+
+/// Encodes the signed distance to the glyph edge as 0..=255, 128 at the edge and
+/// brighter inside, reaching the extremes `spread` pixels away
+fn signed_distance_field(bitmap: &LabelBitmap, spread: u32) -> Vec<u8> {
+    let (width, height) = (bitmap.width as usize, bitmap.height as usize);
+    let inside = |i: usize| bitmap.coverage[i] >= 0.5;
+    let to_inside = distance_to(width, height, inside);
+    let to_outside = distance_to(width, height, |i| !inside(i));
+    to_inside.iter().zip(&to_outside)
+        .map(|(outside_distance, inside_distance)| {
+            let signed = if *outside_distance > 0.0 { -outside_distance } else { *inside_distance };
+            (128.0 + signed / spread as f32 * 127.0).clamp(0.0, 255.0).round() as u8
+        })
+        .collect()
+}
+
+/// Renders `labels` (node id and text) and packs them row by row into an atlas
+/// `ATLAS_WIDTH` wide, with the height rounded up to a power of two
+pub fn build_atlas(font: &FontVec, labels: &[(u32, String)], style: &LabelAtlasStyle, format: AtlasFormat) -> LabelAtlas {
+    let margin = match format {
+        AtlasFormat::Sdf => style.spread,
+        AtlasFormat::Bitmap => 1,
+    };
+
+    let mut placed = Vec::new();
+    let mut skipped = Vec::new();
+    let (mut x, mut y, mut row_height) = (0u32, 0u32, 0u32);
+    for (id, text) in labels {
+        let bitmap = rasterise(font, text, style.font_px, margin);
+        if x + bitmap.width > ATLAS_WIDTH {
+            x = 0;
+            y += row_height;
+            row_height = 0;
+        }
+        if y + bitmap.height > MAX_ATLAS_HEIGHT {
+            skipped.push(*id);
+            continue;
+        }
+        placed.push((*id, x, y, bitmap));
+        x += placed.last().map(|(_, _, _, b)| b.width).unwrap_or(0);
+        row_height = row_height.max(placed.last().map(|(_, _, _, b)| b.height).unwrap_or(0));
+    }
+
+    let height = (y + row_height).max(1).next_power_of_two().min(MAX_ATLAS_HEIGHT);
+    let mut pixels = vec![0u8; (ATLAS_WIDTH * height) as usize];
+    let mut regions = BTreeMap::new();
+    for (id, left, top, bitmap) in placed {
+        let values: Vec<u8> = match format {
+            AtlasFormat::Sdf => signed_distance_field(&bitmap, style.spread),
+            AtlasFormat::Bitmap => bitmap.coverage.iter().map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8).collect(),
+        };
+        for row in 0..bitmap.height {
+            let source = (row * bitmap.width) as usize;
+            let target = ((top + row) * ATLAS_WIDTH + left) as usize;
+            pixels[target..target + bitmap.width as usize].copy_from_slice(&values[source..source + bitmap.width as usize]);
+        }
+        regions.insert(id, AtlasRegion {
+            x: left,
+            y: top,
+            width: bitmap.width,
+            height: bitmap.height,
+            u0: left as f32 / ATLAS_WIDTH as f32,
+            v0: top as f32 / height as f32,
+            u1: (left + bitmap.width) as f32 / ATLAS_WIDTH as f32,
+            v1: (top + bitmap.height) as f32 / height as f32,
+        });
+    }
+
+    if !skipped.is_empty() {
+        info!("Label atlas is full; {} labels left out", skipped.len());
+    }
+    LabelAtlas {
+        version: atlas_version(labels, style, format),
+        format,
+        width: ATLAS_WIDTH,
+        height,
+        font_size: style.font_px,
+        spread: margin,
+        labels: regions,
+        skipped,
+        pixels,
+    }
+}
+
+/// The most recently built atlas of each format
+#[derive(Default)]
+pub struct LabelAtlasCache {
+    atlases: Mutex<Vec<Arc<LabelAtlas>>>,
+}
+
+impl LabelAtlasCache {
+    /// Returns the cached atlas for these inputs, building it if they changed
+    pub fn get_or_build(&self, labels: &[(u32, String)], style: &LabelAtlasStyle, format: AtlasFormat) -> Result<Arc<LabelAtlas>, String> {
+        let version = atlas_version(labels, style, format);
+        if let Some(atlas) = self.atlases.lock().unwrap().iter().find(|atlas| atlas.version == version) {
+            return Ok(Arc::clone(atlas));
+        }
+
+        let font = load_font(style.font_path.as_deref())?;
+        let atlas = Arc::new(build_atlas(&font, labels, style, format));
+        info!("Built {:?} label atlas {} of {} labels ({}x{})",
+            format, atlas.version, atlas.labels.len(), atlas.width, atlas.height);
+        let mut atlases = self.atlases.lock().unwrap();
+        atlases.retain(|cached| cached.format != format);
+        atlases.push(Arc::clone(&atlas));
+        Ok(atlas)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distance_to() {
+        // One feature in the middle of a 5x3 grid
+        let distances = distance_to(5, 3, |i| i == 7);
+        assert_eq!(distances[7], 0.0);
+        assert_eq!(distances[5], 2.0);
+        assert!((distances[0] - 5f32.sqrt()).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_build_atlas() {
+        let Ok(font) = load_font(None) else {
+            eprintln!("No system font available; skipping");
+            return;
+        };
+        let style = LabelAtlasStyle { font_path: None, font_px: 24, spread: 3 };
+        let labels = vec![(1, "Alpha".to_string()), (2, "Beta".to_string())];
+        let atlas = build_atlas(&font, &labels, &style, AtlasFormat::Sdf);
+
+        assert_eq!(atlas.pixels.len(), (atlas.width * atlas.height) as usize);
+        let (a, b) = (atlas.labels[&1], atlas.labels[&2]);
+        assert_eq!((a.x, a.y), (0, 0));
+        assert_eq!(b.x, a.width);
+        assert!(a.u1 > a.u0 && a.v1 <= 1.0);
+        // Inside some glyph there must be pixels past the edge value
+        assert!(atlas.pixels.iter().any(|&p| p > 128));
+        assert_eq!(atlas.version, atlas_version(&labels, &style, AtlasFormat::Sdf));
+    }
+}
@@ -0,0 +1,356 @@
+//! Deterministic layouts computed on demand
+//!
+//! The force-directed simulation settles differently on every run. These layouts
+//! place nodes by structure alone, so the same graph always gets the same picture:
+//! a radial tree around a chosen root, rows following edge direction, or a circle
+//! per cluster. The positions are targets for the client to tween towards and leave
+//! the simulation untouched.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::f32::consts::{PI, TAU};
+
+use crate::models::graph::GraphData;
+use crate::types::vec3::Vec3Data;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LayoutAlgorithm {
+    /// A ring per hop from the root, each subtree getting a wedge sized by its leaves
+    Radial,
+    /// Rows following edge direction, with sources at the top
+    Layered,
+    /// A circle per connected component, the circles arranged around a larger one
+    Circular,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TargetPosition {
+    pub id: u32,
+    pub position: Vec3Data,
+}
+
+/// Nodes by index in id order, so traversals don't depend on how the graph was built
+struct Adjacency {
+    ids: Vec<u32>,
+    neighbours: Vec<Vec<usize>>,
+    successors: Vec<Vec<usize>>,
+}
+
+impl Adjacency {
+    fn new(graph: &GraphData) -> Self {
+        let mut ids: Vec<u32> = graph.nodes.iter().map(|node| node.id).collect();
+        ids.sort_unstable();
+        ids.dedup();
+        let index: HashMap<u32, usize> = ids.iter().enumerate().map(|(i, &id)| (id, i)).collect();
+
+        let mut neighbours = vec![Vec::new(); ids.len()];
+        let mut successors = vec![Vec::new(); ids.len()];
+        for edge in &graph.edges {
+            if let (Some(&source), Some(&target)) = (index.get(&edge.source), index.get(&edge.target)) {
+                if source != target {
+                    successors[source].push(target);
+                    neighbours[source].push(target);
+                    neighbours[target].push(source);
+                }
+            }
+        }
+        for list in neighbours.iter_mut().chain(successors.iter_mut()) {
+            list.sort_unstable();
+            list.dedup();
+        }
+        Self { ids, neighbours, successors }
+    }
+
+    fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    fn index_of(&self, id: u32) -> Option<usize> {
+        self.ids.binary_search(&id).ok()
+    }
+
+    /// The best-connected node, lowest id first on ties
+    fn hub(&self) -> usize {
+        (0..self.len()).max_by_key(|&i| (self.neighbours[i].len(), std::cmp::Reverse(i))).unwrap_or(0)
+    }
+}
+
+/// Target positions for every node, `spacing` apart between rings, rows or
+/// neighbours. `root` picks the centre of a radial layout and defaults to the
+/// best-connected node.
+pub fn compute(
+    graph: &GraphData,
+    algorithm: LayoutAlgorithm,
+    root: Option<u32>,
+    spacing: f32,
+) -> Result<Vec<TargetPosition>, String> {
+    if !(spacing.is_finite() && spacing > 0.0) {
+        return Err(format!("spacing must be a positive number, got {}", spacing));
+    }
+    let adjacency = Adjacency::new(graph);
+    if adjacency.len() == 0 {
+        return Ok(Vec::new());
+    }
+
+    let positions = match algorithm {
+        LayoutAlgorithm::Radial => {
+            let root = match root {
+                Some(id) => adjacency.index_of(id).ok_or_else(|| format!("Unknown root node {}", id))?,
+                None => adjacency.hub(),
+            };
+            radial(&adjacency, root, spacing)
+        }
+        LayoutAlgorithm::Layered => layered(&adjacency, spacing),
+        LayoutAlgorithm::Circular => circular(&adjacency, spacing),
+    };
+
+    Ok(adjacency.ids.iter()
+        .zip(positions)
+        .map(|(&id, position)| TargetPosition { id, position })
+        .collect())
+}
+
+fn radial(adjacency: &Adjacency, root: usize, spacing: f32) -> Vec<Vec3Data> {
+    let n = adjacency.len();
+    let mut depth = vec![usize::MAX; n];
+    let mut children = vec![Vec::new(); n];
+    let mut order = Vec::with_capacity(n);
+    let mut queue = VecDeque::from([root]);
+    depth[root] = 0;
+    while let Some(i) = queue.pop_front() {
+        order.push(i);
+        for &j in &adjacency.neighbours[i] {
+            if depth[j] == usize::MAX {
+                depth[j] = depth[i] + 1;
+                children[i].push(j);
+                queue.push_back(j);
+            }
+        }
+    }
+
+    let mut leaves = vec![1usize; n];
+    for &i in order.iter().rev() {
+        if !children[i].is_empty() {
+            leaves[i] = children[i].iter().map(|&c| leaves[c]).sum();
+        }
+    }
+
+    let mut positions = vec![Vec3Data::zero(); n];
+    let mut wedges = vec![(0.0f32, TAU); n];
+    for &i in &order {
+        let (start, width) = wedges[i];
+        positions[i] = polar(depth[i] as f32 * spacing, start + width / 2.0);
+        let mut child_start = start;
+        for &c in &children[i] {
+            let child_width = width * leaves[c] as f32 / leaves[i] as f32;
+            wedges[c] = (child_start, child_width);
+            child_start += child_width;
+        }
+    }
+
+    // Nodes not connected to the root go on a ring outside the tree
+    let unreached: Vec<usize> = (0..n).filter(|&i| depth[i] == usize::MAX).collect();
+    let outer_depth = order.iter().map(|&i| depth[i]).max().unwrap_or(0) + 1;
+    place_on_ring(&mut positions, &unreached, outer_depth as f32 * spacing, Vec3Data::zero());
+    positions
+}
+
+fn layered(adjacency: &Adjacency, spacing: f32) -> Vec<Vec3Data> {
+    let n = adjacency.len();
+
+    // Depth-first search dropping edges that close a cycle, so the rest is a DAG
+    const UNSEEN: u8 = 0;
+    const ACTIVE: u8 = 1;
+    const DONE: u8 = 2;
+    let mut state = vec![UNSEEN; n];
+    let mut dag: Vec<Vec<usize>> = vec![Vec::new(); n];
+    let mut finished = Vec::with_capacity(n);
+    for start in 0..n {
+        if state[start] != UNSEEN {
+            continue;
+        }
+        state[start] = ACTIVE;
+        let mut stack = vec![(start, 0usize)];
+        while let Some(top) = stack.last_mut() {
+            let (i, next) = *top;
+            if let Some(&j) = adjacency.successors[i].get(next) {
+                top.1 += 1;
+                match state[j] {
+                    UNSEEN => {
+                        dag[i].push(j);
+                        state[j] = ACTIVE;
+                        stack.push((j, 0));
+                    }
+                    DONE => dag[i].push(j),
+                    _ => {}
+                }
+            } else {
+                state[i] = DONE;
+                finished.push(i);
+                stack.pop();
+            }
+        }
+    }
+
+    // Longest-path layering in topological order
+    let mut layer = vec![0usize; n];
+    let mut parents = vec![Vec::new(); n];
+    for &i in finished.iter().rev() {
+        for &j in &dag[i] {
+            layer[j] = layer[j].max(layer[i] + 1);
+            parents[j].push(i);
+        }
+    }
+    let mut rows: Vec<Vec<usize>> = vec![Vec::new(); layer.iter().max().map_or(0, |&l| l + 1)];
+    for i in 0..n {
+        rows[layer[i]].push(i);
+    }
+
+    // Each row is ordered by the mean x of its parents to cut down on crossings
+    let mut positions = vec![Vec3Data::zero(); n];
+    for (depth, row) in rows.iter_mut().enumerate() {
+        let barycentre = |i: usize| {
+            let xs: Vec<f32> = parents[i].iter().map(|&p| positions[p].x).collect();
+            if xs.is_empty() { 0.0 } else { xs.iter().sum::<f32>() / xs.len() as f32 }
+        };
+        let mut keyed: Vec<(f32, usize)> = row.iter().map(|&i| (barycentre(i), i)).collect();
+        keyed.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+        *row = keyed.into_iter().map(|(_, i)| i).collect();
+
+        let offset = (row.len() as f32 - 1.0) / 2.0;
+        for (k, &i) in row.iter().enumerate() {
+            positions[i] = Vec3Data::new((k as f32 - offset) * spacing, -(depth as f32) * spacing, 0.0);
+        }
+    }
+    positions
+}
+
+fn circular(adjacency: &Adjacency, spacing: f32) -> Vec<Vec3Data> {
+    let n = adjacency.len();
+    let mut seen = vec![false; n];
+    let mut clusters: Vec<Vec<usize>> = Vec::new();
+    for start in 0..n {
+        if seen[start] {
+            continue;
+        }
+        seen[start] = true;
+        let mut members = Vec::new();
+        let mut queue = VecDeque::from([start]);
+        while let Some(i) = queue.pop_front() {
+            members.push(i);
+            for &j in &adjacency.neighbours[i] {
+                if !seen[j] {
+                    seen[j] = true;
+                    queue.push_back(j);
+                }
+            }
+        }
+        members.sort_unstable();
+        clusters.push(members);
+    }
+    // Largest clusters first; ties keep id order
+    clusters.sort_by(|a, b| b.len().cmp(&a.len()).then(a[0].cmp(&b[0])));
+
+    let mut positions = vec![Vec3Data::zero(); n];
+    if let [cluster] = clusters.as_slice() {
+        place_on_ring(&mut positions, cluster, ring_radius(cluster.len(), spacing), Vec3Data::zero());
+        return positions;
+    }
+
+    // Each cluster gets an arc of the outer circle as wide as the cluster plus a gap
+    let radii: Vec<f32> = clusters.iter().map(|c| ring_radius(c.len(), spacing)).collect();
+    let arcs: Vec<f32> = radii.iter().map(|r| 2.0 * r + spacing).collect();
+    let circumference: f32 = arcs.iter().sum();
+    let outer_radius = circumference / TAU;
+    let mut angle = 0.0;
+    for ((cluster, radius), arc) in clusters.iter().zip(&radii).zip(&arcs) {
+        let width = TAU * arc / circumference;
+        let centre = polar(outer_radius, angle + width / 2.0);
+        place_on_ring(&mut positions, cluster, *radius, centre);
+        angle += width;
+    }
+    positions
+}
+
+/// Radius of a ring holding `count` nodes `spacing` apart
+fn ring_radius(count: usize, spacing: f32) -> f32 {
+    match count {
+        0 | 1 => 0.0,
+        _ => spacing / (2.0 * (PI / count as f32).sin()),
+    }
+}
+
+fn place_on_ring(positions: &mut [Vec3Data], members: &[usize], radius: f32, centre: Vec3Data) {
+    for (k, &i) in members.iter().enumerate() {
+        let offset = polar(radius, TAU * k as f32 / members.len() as f32);
+        positions[i] = Vec3Data::new(centre.x + offset.x, centre.y + offset.y, centre.z);
+    }
+}
+
+fn polar(radius: f32, angle: f32) -> Vec3Data {
+    Vec3Data::new(radius * angle.cos(), radius * angle.sin(), 0.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::edge::Edge;
+    use crate::models::node::Node;
+
+    fn graph(ids: &[u32], edges: &[(u32, u32)]) -> GraphData {
+        let mut graph = GraphData::new();
+        graph.nodes = ids.iter().map(|&id| Node::new_with_id(id.to_string(), Some(id))).collect();
+        graph.edges = edges.iter().map(|&(s, t)| Edge::new(s, t, 1.0)).collect();
+        graph
+    }
+
+    fn by_id(positions: &[TargetPosition]) -> HashMap<u32, Vec3Data> {
+        positions.iter().map(|p| (p.id, p.position)).collect()
+    }
+
+    fn distance(a: Vec3Data, b: Vec3Data) -> f32 {
+        ((a.x - b.x).powi(2) + (a.y - b.y).powi(2) + (a.z - b.z).powi(2)).sqrt()
+    }
+
+    #[test]
+    fn test_radial_rings() {
+        let g = graph(&[1, 2, 3, 4, 9], &[(1, 2), (1, 3), (3, 4)]);
+        let positions = by_id(&compute(&g, LayoutAlgorithm::Radial, None, 2.0).unwrap());
+        let origin = Vec3Data::zero();
+        assert_eq!(positions[&1], origin);
+        assert!((distance(positions[&2], origin) - 2.0).abs() < 1e-5);
+        assert!((distance(positions[&4], origin) - 4.0).abs() < 1e-5);
+        // Unconnected nodes sit outside the deepest ring
+        assert!((distance(positions[&9], origin) - 6.0).abs() < 1e-5);
+
+        assert!(compute(&g, LayoutAlgorithm::Radial, Some(42), 2.0).is_err());
+        assert_eq!(compute(&g, LayoutAlgorithm::Radial, Some(3), 2.0).unwrap()
+            .iter().find(|p| p.id == 3).unwrap().position, origin);
+    }
+
+    #[test]
+    fn test_layered_follows_edges_and_breaks_cycles() {
+        let g = graph(&[1, 2, 3, 4], &[(1, 2), (2, 3), (3, 1), (1, 4)]);
+        let positions = by_id(&compute(&g, LayoutAlgorithm::Layered, None, 1.0).unwrap());
+        assert_eq!(positions[&1].y, 0.0);
+        assert_eq!(positions[&2].y, -1.0);
+        assert_eq!(positions[&4].y, -1.0);
+        assert_eq!(positions[&3].y, -2.0);
+        assert_ne!(positions[&2].x, positions[&4].x);
+    }
+
+    #[test]
+    fn test_circular_is_deterministic_and_separates_clusters() {
+        let g = graph(&[5, 1, 2, 3, 4], &[(1, 2), (2, 3), (4, 5)]);
+        let mut shuffled = g.clone();
+        shuffled.nodes.reverse();
+        let positions = compute(&g, LayoutAlgorithm::Circular, None, 1.0).unwrap();
+        assert_eq!(positions, compute(&shuffled, LayoutAlgorithm::Circular, None, 1.0).unwrap());
+
+        let positions = by_id(&positions);
+        assert!((distance(positions[&4], positions[&5]) - 1.0).abs() < 1e-5);
+        assert!(distance(positions[&1], positions[&4]) > 1.0);
+    }
+}
@@ -0,0 +1,220 @@
+//! Dead external link detection
+//!
+//! Checks the http(s) hyperlinks in every page and records the outcome in the
+//! page's `link_checks`. A link is broken when it can't be reached at all or
+//! answers 404, 410 or a server error. Other client errors, such as the 403s many
+//! sites give to non-browser clients, don't count as broken.
+//!
+//! `LINK_CHECK_CONCURRENCY` (default 8) bounds the requests in flight, and links
+//! checked within `LINK_CHECK_INTERVAL_HOURS` (default 24) aren't checked again.
+
+use chrono::{Duration, Utc};
+use futures::stream::{self, StreamExt};
+use log::{debug, info};
+use reqwest::{Client, StatusCode, Url};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::env;
+
+use crate::config::data_dirs::DataDirs;
+use crate::models::metadata::{LinkCheck, MetadataStore};
+use crate::services::file_service::FileService;
+use crate::services::job_queue::JobContext;
+use crate::services::link_preview::is_fetchable;
+use crate::services::vault_crypto;
+
+const DEFAULT_CONCURRENCY: usize = 8;
+const DEFAULT_INTERVAL_HOURS: i64 = 24;
+const REQUEST_TIMEOUT_SECS: u64 = 15;
+
+/// Whether a final response with `status` means the link is dead
+fn is_broken_status(status: StatusCode) -> bool {
+    matches!(status, StatusCode::NOT_FOUND | StatusCode::GONE) || status.is_server_error()
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BrokenLink {
+    pub url: String,
+    #[serde(flatten)]
+    pub check: LinkCheck,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BrokenLinkReport {
+    pub checked_links: usize,
+    pub broken_links: usize,
+    /// Files with broken links and what's wrong with each
+    pub files: BTreeMap<String, Vec<BrokenLink>>,
+    /// Broken link count by node id, for badges
+    pub badges: BTreeMap<String, usize>,
+}
+
+/// Collects the broken links recorded in `metadata_store`
+pub fn report(metadata_store: &MetadataStore) -> BrokenLinkReport {
+    let mut files = BTreeMap::new();
+    let mut badges = BTreeMap::new();
+    let mut checked_links = 0;
+    for (file_name, metadata) in metadata_store {
+        checked_links += metadata.link_checks.len();
+        let mut broken: Vec<BrokenLink> = metadata.link_checks.iter()
+            .filter(|(_, check)| check.broken)
+            .map(|(url, check)| BrokenLink { url: url.clone(), check: check.clone() })
+            .collect();
+        if broken.is_empty() {
+            continue;
+        }
+        broken.sort_by(|a, b| a.url.cmp(&b.url));
+        badges.insert(metadata.node_id.clone(), broken.len());
+        files.insert(file_name.clone(), broken);
+    }
+    let broken_links = files.values().map(Vec::len).sum();
+    BrokenLinkReport { checked_links, broken_links, files, badges }
+}
+
+pub struct LinkChecker {
+    client: Client,
+    concurrency: usize,
+    interval: Duration,
+}
+
+impl LinkChecker {
+    pub fn from_env() -> Result<Self, String> {
+        let concurrency = env::var("LINK_CHECK_CONCURRENCY").ok()
+            .and_then(|value| value.trim().parse().ok())
+            .filter(|concurrency| *concurrency > 0)
+            .unwrap_or(DEFAULT_CONCURRENCY);
+        let interval_hours = env::var("LINK_CHECK_INTERVAL_HOURS").ok()
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(DEFAULT_INTERVAL_HOURS);
+        let client = Client::builder()
+            .timeout(std::time::Duration::from_secs(REQUEST_TIMEOUT_SECS))
+            .redirect(reqwest::redirect::Policy::custom(|attempt| {
+                if attempt.previous().len() >= 10 || !is_fetchable(attempt.url()) {
+                    attempt.stop()
+                } else {
+                    attempt.follow()
+                }
+            }))
+            .user_agent(concat!("logseq-xr-link-checker/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
+        Ok(Self { client, concurrency, interval: Duration::hours(interval_hours) })
+    }
+
+    /// Tries HEAD first, falling back to GET for servers that don't support it
+    async fn check(&self, url: Url) -> LinkCheck {
+        let checked_at = Utc::now();
+        let mut result = self.client.head(url.clone()).send().await;
+        let head_refused = matches!(&result, Ok(response)
+            if matches!(response.status(), StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED | StatusCode::FORBIDDEN));
+        if head_refused {
+            result = self.client.get(url).send().await;
+        }
+        match result {
+            Ok(response) => LinkCheck {
+                checked_at,
+                status: Some(response.status().as_u16()),
+                error: None,
+                broken: is_broken_status(response.status()),
+            },
+            Err(e) => LinkCheck {
+                checked_at,
+                status: None,
+                error: Some(if e.is_timeout() { "Timed out".to_string() } else { e.to_string() }),
+                broken: true,
+            },
+        }
+    }
+
+    /// Checks the external links of every file in `metadata_store` that weren't
+    /// checked recently, replacing each file's `link_checks` with its current links
+    pub async fn run(&self, metadata_store: &mut MetadataStore, ctx: Option<&JobContext>) {
+        let dirs = DataDirs::global();
+        let mut links_by_file: HashMap<String, Vec<String>> = HashMap::new();
+        for file_name in metadata_store.keys() {
+            if let Ok(content) = vault_crypto::read_to_string(dirs.markdown_file(file_name)) {
+                links_by_file.insert(file_name.clone(), FileService::external_hyperlinks(&content));
+            }
+        }
+
+        // Reuse recent results wherever the same link appears
+        let now = Utc::now();
+        let mut results: HashMap<String, LinkCheck> = HashMap::new();
+        for metadata in metadata_store.values() {
+            for (url, check) in &metadata.link_checks {
+                if now - check.checked_at < self.interval {
+                    results.entry(url.clone()).or_insert_with(|| check.clone());
+                }
+            }
+        }
+        let stale: HashSet<&String> = links_by_file.values().flatten()
+            .filter(|url| !results.contains_key(*url))
+            .collect();
+        let to_check: Vec<(String, Url)> = stale.into_iter()
+            .filter_map(|url| Url::parse(url).ok().filter(is_fetchable).map(|parsed| (url.clone(), parsed)))
+            .collect();
+
+        let total = to_check.len();
+        info!("Checking {} external links with up to {} concurrent requests", total, self.concurrency);
+        let mut checks = stream::iter(to_check)
+            .map(|(url, parsed)| async move { (url, self.check(parsed).await) })
+            .buffer_unordered(self.concurrency);
+        let mut done = 0;
+        while let Some((url, check)) = checks.next().await {
+            if check.broken {
+                debug!("Broken link {}: {:?} {:?}", url, check.status, check.error);
+            }
+            results.insert(url, check);
+            done += 1;
+            if let Some(ctx) = ctx {
+                if ctx.is_cancelled() {
+                    break;
+                }
+                ctx.report_progress(done, total, None);
+            }
+        }
+
+        for (file_name, links) in links_by_file {
+            if let Some(metadata) = metadata_store.get_mut(&file_name) {
+                metadata.link_checks = links.into_iter()
+                    .filter_map(|url| results.get(&url).map(|check| (url, check.clone())))
+                    .collect();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::metadata::Metadata;
+
+    #[test]
+    fn test_report_and_statuses() {
+        assert!(is_broken_status(StatusCode::NOT_FOUND));
+        assert!(is_broken_status(StatusCode::BAD_GATEWAY));
+        assert!(!is_broken_status(StatusCode::FORBIDDEN));
+        assert!(!is_broken_status(StatusCode::OK));
+
+        let links = FileService::external_hyperlinks(
+            "[a](https://a.example \"A\") [b](Page.md) [a again](https://a.example) [c](http://c.example/x)");
+        assert_eq!(links, vec!["https://a.example", "http://c.example/x"]);
+
+        let check = |status: u16, broken: bool| LinkCheck { checked_at: Utc::now(), status: Some(status), error: None, broken };
+        let mut store = MetadataStore::new();
+        store.insert("Rust.md".to_string(), Metadata {
+            node_id: "7".to_string(),
+            link_checks: HashMap::from([
+                ("https://a.example".to_string(), check(200, false)),
+                ("http://c.example/x".to_string(), check(404, true)),
+            ]),
+            ..Default::default()
+        });
+        let report = report(&store);
+        assert_eq!((report.checked_links, report.broken_links), (2, 1));
+        assert_eq!(report.badges.get("7"), Some(&1));
+        assert_eq!(report.files["Rust.md"][0].url, "http://c.example/x");
+    }
+}
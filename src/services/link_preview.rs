@@ -0,0 +1,318 @@
+//! Previews of external pages linked from notes
+//!
+//! After a sync, every `http(s)` link in a published page is fetched once and its
+//! Open Graph title, description and image are stored in the page's metadata, so
+//! the client can show thumbnails of linked resources beside the node. Results,
+//! failures included, are cached in `link_previews.json` for `LINK_PREVIEW_TTL_HOURS`
+//! (a week by default); `LINK_PREVIEW_CONCURRENCY` bounds the requests in flight,
+//! and `0` turns previews off.
+//!
+//! Links to loopback, private and link-local addresses are never fetched, so a note
+//! can't make the server probe its own network.
+
+use chrono::{DateTime, Duration, Utc};
+use futures::stream::{self, StreamExt};
+use log::{debug, info, warn};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use reqwest::{Client, Url};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::io;
+use std::net::IpAddr;
+use std::path::PathBuf;
+
+use crate::config::data_dirs::DataDirs;
+use crate::models::metadata::{LinkPreview, MetadataStore};
+use crate::services::vault_crypto;
+
+const DEFAULT_CONCURRENCY: usize = 4;
+const DEFAULT_TTL_HOURS: i64 = 24 * 7;
+const MAX_LINKS_PER_PAGE: usize = 10;
+// Open Graph tags are in the head, so there's no need to read whole pages
+const MAX_BODY_BYTES: usize = 256 * 1024;
+const REQUEST_TIMEOUT_SECS: u64 = 10;
+const MAX_TEXT_CHARS: usize = 300;
+
+static LINK_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r#"https?://[^\s<>()\[\]"'`]+"#).unwrap());
+static META_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?is)<meta\s[^>]*>").unwrap());
+static ATTR_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r#"(?s)([\w:-]+)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).unwrap());
+static TITLE_RE: Lazy<Regex> = Lazy::new(|| Regex::new(r"(?is)<title[^>]*>(.*?)</title>").unwrap());
+
+fn preview_cache_path() -> PathBuf {
+    DataDirs::global().metadata_file("link_previews.json")
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CachedPreview {
+    fetched_at: DateTime<Utc>,
+    /// `None` when the page couldn't be fetched or had nothing to show
+    preview: Option<LinkPreview>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct PreviewCache {
+    entries: HashMap<String, CachedPreview>,
+}
+
+impl PreviewCache {
+    fn load() -> Self {
+        match fs::read_to_string(preview_cache_path()) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable link preview cache: {}", e);
+                PreviewCache::default()
+            }),
+            Err(_) => PreviewCache::default(),
+        }
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let path = preview_cache_path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_string(self).map_err(io::Error::from)?;
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, path)
+    }
+}
+
+/// The distinct external links in `content`, in order of first appearance
+pub fn external_links(content: &str) -> Vec<String> {
+    let mut seen = HashSet::new();
+    LINK_RE.find_iter(content)
+        .map(|m| m.as_str().trim_end_matches(['.', ',', ';', ':', '!', '?']).to_string())
+        .filter(|url| seen.insert(url.clone()))
+        .take(MAX_LINKS_PER_PAGE)
+        .collect()
+}
+
+/// Whether `url` is an http(s) URL on a public host
+pub(crate) fn is_fetchable(url: &Url) -> bool {
+    if !matches!(url.scheme(), "http" | "https") {
+        return false;
+    }
+    match url.host() {
+        Some(url::Host::Domain(domain)) => {
+            let domain = domain.to_ascii_lowercase();
+            domain != "localhost" && !domain.ends_with(".localhost") && !domain.ends_with(".local")
+        }
+        Some(url::Host::Ipv4(ip)) => is_public(IpAddr::V4(ip)),
+        Some(url::Host::Ipv6(ip)) => is_public(IpAddr::V6(ip)),
+        None => false,
+    }
+}
+
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => !(ip.is_loopback() || ip.is_private() || ip.is_link_local()
+            || ip.is_unspecified() || ip.is_broadcast() || ip.is_documentation()),
+        IpAddr::V6(ip) => {
+            let first = ip.segments()[0];
+            // Unique local (fc00::/7) and link-local (fe80::/10) addresses
+            !(ip.is_loopback() || ip.is_unspecified() || (first & 0xfe00) == 0xfc00 || (first & 0xffc0) == 0xfe80)
+        }
+    }
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&#x27;", "'")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+fn clean_text(text: &str) -> Option<String> {
+    let text = decode_entities(text.split_whitespace().collect::<Vec<_>>().join(" ").as_str());
+    if text.is_empty() {
+        return None;
+    }
+    Some(text.chars().take(MAX_TEXT_CHARS).collect())
+}
+
+/// Reads the preview fields from a page's HTML, resolving the image against `url`.
+/// Returns `None` if the page has neither a title nor an image.
+pub fn parse_preview(url: &Url, html: &str) -> Option<LinkPreview> {
+    let mut tags: HashMap<String, String> = HashMap::new();
+    for meta in META_RE.find_iter(html) {
+        let mut key = None;
+        let mut content = None;
+        for attr in ATTR_RE.captures_iter(meta.as_str()) {
+            let value = attr.get(2).or_else(|| attr.get(3)).map(|v| v.as_str()).unwrap_or_default();
+            match attr[1].to_ascii_lowercase().as_str() {
+                "property" | "name" => key = Some(value.to_ascii_lowercase()),
+                "content" => content = Some(value),
+                _ => {}
+            }
+        }
+        if let (Some(key), Some(content)) = (key, content) {
+            tags.entry(key).or_insert_with(|| content.to_string());
+        }
+    }
+
+    let tag = |names: &[&str]| names.iter().find_map(|name| tags.get(*name).and_then(|value| clean_text(value)));
+    let title = tag(&["og:title", "twitter:title"])
+        .or_else(|| TITLE_RE.captures(html).and_then(|c| clean_text(&c[1])));
+    let image = tag(&["og:image", "og:image:url", "twitter:image"])
+        .and_then(|image| url.join(&image).ok())
+        .filter(|image| matches!(image.scheme(), "http" | "https"))
+        .map(String::from);
+    if title.is_none() && image.is_none() {
+        return None;
+    }
+    Some(LinkPreview {
+        url: url.to_string(),
+        title,
+        description: tag(&["og:description", "twitter:description", "description"]),
+        image,
+        site_name: tag(&["og:site_name"]),
+    })
+}
+
+pub struct LinkPreviewService {
+    client: Client,
+    concurrency: usize,
+    ttl: Duration,
+}
+
+impl LinkPreviewService {
+    /// The service configured from the environment, or `None` if previews are off
+    pub fn from_env() -> Option<Self> {
+        let concurrency = env::var("LINK_PREVIEW_CONCURRENCY").ok()
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(DEFAULT_CONCURRENCY);
+        if concurrency == 0 {
+            return None;
+        }
+        let ttl_hours = env::var("LINK_PREVIEW_TTL_HOURS").ok()
+            .and_then(|value| value.trim().parse().ok())
+            .unwrap_or(DEFAULT_TTL_HOURS);
+        let client = Client::builder()
+            .timeout(std::time::Duration::from_secs(REQUEST_TIMEOUT_SECS))
+            .redirect(reqwest::redirect::Policy::custom(|attempt| {
+                if attempt.previous().len() >= 5 || !is_fetchable(attempt.url()) {
+                    attempt.stop()
+                } else {
+                    attempt.follow()
+                }
+            }))
+            .user_agent(concat!("logseq-xr-link-preview/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .ok()?;
+        Some(Self { client, concurrency, ttl: Duration::hours(ttl_hours) })
+    }
+
+    async fn fetch(&self, url: &Url) -> Result<Option<LinkPreview>, reqwest::Error> {
+        let mut response = self.client.get(url.clone()).send().await?.error_for_status()?;
+        let is_html = response.headers().get(reqwest::header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.contains("html"));
+        if !is_html {
+            return Ok(None);
+        }
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await? {
+            body.extend_from_slice(&chunk);
+            if body.len() >= MAX_BODY_BYTES {
+                break;
+            }
+        }
+        let final_url = response.url().clone();
+        Ok(parse_preview(&final_url, &String::from_utf8_lossy(&body)).map(|mut preview| {
+            // Keep the link as written so the client can match it
+            preview.url = url.to_string();
+            preview
+        }))
+    }
+
+    /// Sets `link_previews` on every file in `metadata_store` from its markdown,
+    /// fetching links that aren't cached or whose cache entry has expired
+    pub async fn enrich(&self, metadata_store: &mut MetadataStore) {
+        let markdown = &DataDirs::global().markdown;
+        let mut links_by_file = HashMap::new();
+        for file_name in metadata_store.keys() {
+            if let Ok(content) = vault_crypto::read_to_string(markdown.join(file_name)) {
+                let links: Vec<Url> = external_links(&content).iter()
+                    .filter_map(|link| Url::parse(link).ok())
+                    .filter(is_fetchable)
+                    .collect();
+                links_by_file.insert(file_name.clone(), links);
+            }
+        }
+
+        let now = Utc::now();
+        let mut cache = PreviewCache::load();
+        let stale: HashSet<Url> = links_by_file.values().flatten()
+            .filter(|url| cache.entries.get(url.as_str()).is_none_or(|cached| now - cached.fetched_at > self.ttl))
+            .cloned()
+            .collect();
+        if !stale.is_empty() {
+            info!("Fetching previews of {} linked pages with up to {} concurrent requests", stale.len(), self.concurrency);
+        }
+        let fetched: Vec<(Url, Option<LinkPreview>)> = stream::iter(stale)
+            .map(|url| async move {
+                let preview = match self.fetch(&url).await {
+                    Ok(preview) => preview,
+                    Err(e) => {
+                        debug!("No preview for {}: {}", url, e);
+                        None
+                    }
+                };
+                (url, preview)
+            })
+            .buffer_unordered(self.concurrency)
+            .collect()
+            .await;
+        for (url, preview) in fetched {
+            cache.entries.insert(url.to_string(), CachedPreview { fetched_at: now, preview });
+        }
+
+        let linked: HashSet<&str> = links_by_file.values().flatten().map(Url::as_str).collect();
+        cache.entries.retain(|url, _| linked.contains(url.as_str()));
+        for (file_name, links) in &links_by_file {
+            if let Some(metadata) = metadata_store.get_mut(file_name) {
+                metadata.link_previews = links.iter()
+                    .filter_map(|url| cache.entries.get(url.as_str())?.preview.clone())
+                    .collect();
+            }
+        }
+        if let Err(e) = cache.save() {
+            warn!("Failed to save link preview cache: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_preview() {
+        let url = Url::parse("https://example.com/posts/1").unwrap();
+        let html = r#"<html><head>
+            <title>Fallback</title>
+            <meta property="og:title" content="Rust &amp; WebXR">
+            <meta content='/img/cover.png' property='og:image' />
+            <meta name="description" content="A   post">
+        </head></html>"#;
+        let preview = parse_preview(&url, html).unwrap();
+        assert_eq!(preview.title.as_deref(), Some("Rust & WebXR"));
+        assert_eq!(preview.image.as_deref(), Some("https://example.com/img/cover.png"));
+        assert_eq!(preview.description.as_deref(), Some("A post"));
+        assert!(parse_preview(&url, "<p>no head</p>").is_none());
+
+        let links = external_links("See [docs](https://docs.rs/regex). Also https://docs.rs/regex, and http://127.0.0.1:8080/admin.");
+        assert_eq!(links, vec!["https://docs.rs/regex", "http://127.0.0.1:8080/admin"]);
+        assert!(!is_fetchable(&Url::parse(&links[1]).unwrap()));
+        assert!(!is_fetchable(&Url::parse("http://[fd00::1]/").unwrap()));
+        assert!(is_fetchable(&Url::parse(&links[0]).unwrap()));
+    }
+}
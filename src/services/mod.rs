@@ -1,7 +1,65 @@
 pub mod github;
+pub mod activity;
+pub mod agenda;
+pub mod ai_cache;
+pub mod ai_usage;
+pub mod audio_cache;
+pub mod blob_cache;
+pub mod chat_context;
+pub mod comments;
+pub mod compound_layout;
+pub mod conversations;
+pub mod demo;
+pub mod divergence;
+pub mod duplicates;
+pub mod embed_export;
+pub mod event_bus;
+pub mod fault_injection;
+pub mod feed;
 pub mod file_service;
+pub mod focus;
+pub mod gltf_export;
+pub mod graph_filter;
+pub mod graphml_export;
+pub mod graph_quality;
 pub mod graph_service;
+pub mod graph_validation;
+pub mod job_queue;
+pub mod journal;
+pub mod label_atlas;
+pub mod layout;
+pub mod link_checker;
+pub mod link_preview;
+pub mod node_colors;
+pub mod nostr_ingest;
+pub mod nostr_publisher;
 pub mod nostr_service;
+pub mod pages;
+pub mod page_templates;
 pub mod perplexity_service;
+pub mod position_broadcaster;
 pub mod ragflow_service;
+pub mod recommendations;
+pub mod replay;
+pub mod resilience;
+pub use webxr_core::reference_parser;
+pub mod saved_filters;
+pub mod shares;
+pub mod scheduler;
+pub mod snapshot;
 pub mod speech_service;
+pub mod stats_history;
+pub mod storage_quota;
+pub mod sync_state;
+pub mod telemetry;
+pub mod tenants;
+pub mod timeline;
+pub mod trash;
+pub mod transcripts;
+pub mod tts_provider;
+pub mod vault_crypto;
+pub mod view_links;
+pub mod visit_heatmap;
+pub mod visibility;
+pub mod webhook_service;
+pub mod world_bounds;
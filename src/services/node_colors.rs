@@ -0,0 +1,183 @@
+//! Server-side node colouring
+//!
+//! Assigns `Node::color` from the `color_mode` in the node settings: by how long
+//! ago a page was modified, by node type, or by connected cluster. Nodes the mode
+//! says nothing about keep `None` and render in the client's base colour.
+
+use chrono::{DateTime, Utc};
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+
+use crate::config::{AgeColor, NodeColorMode, NodeSettings};
+use crate::models::graph::GraphData;
+
+#[derive(Debug, Clone, Default)]
+pub struct NodeColorMapper {
+    mode: NodeColorMode,
+    age_colors: Vec<AgeColor>,
+    type_colors: BTreeMap<String, String>,
+    cluster_palette: Vec<String>,
+}
+
+impl NodeColorMapper {
+    pub fn from_settings(nodes: &NodeSettings) -> Self {
+        let mut age_colors = nodes.age_colors.clone();
+        age_colors.sort_by_key(|bucket| bucket.max_age_days);
+        Self {
+            mode: nodes.color_mode,
+            age_colors,
+            type_colors: nodes.type_colors.clone(),
+            cluster_palette: nodes.cluster_palette.clone(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.mode != NodeColorMode::Base
+    }
+
+    /// Sets the colour of every node in `graph` as of `now` and returns the
+    /// coloured nodes by id
+    pub fn apply(&self, graph: &mut GraphData, now: DateTime<Utc>) -> BTreeMap<u32, String> {
+        let colors = match self.mode {
+            NodeColorMode::Base => HashMap::new(),
+            NodeColorMode::Age => self.age_colors(graph, now),
+            NodeColorMode::Type => graph.nodes.iter()
+                .filter_map(|node| {
+                    let color = self.type_colors.get(node.node_type.as_deref()?)?;
+                    Some((node.id, color.clone()))
+                })
+                .collect(),
+            NodeColorMode::Cluster => self.cluster_colors(graph),
+        };
+        for node in &mut graph.nodes {
+            node.color = colors.get(&node.id).cloned();
+        }
+        colors.into_iter().collect()
+    }
+
+    fn age_colors(&self, graph: &GraphData, now: DateTime<Utc>) -> HashMap<u32, String> {
+        let Some(oldest) = self.age_colors.last() else { return HashMap::new() };
+        graph.nodes.iter()
+            .filter_map(|node| {
+                let metadata = graph.metadata.get(&format!("{}.md", node.metadata_id))?;
+                let age_days = (now - metadata.last_modified).num_days().max(0);
+                let bucket = self.age_colors.iter()
+                    .find(|bucket| age_days <= bucket.max_age_days as i64)
+                    .unwrap_or(oldest);
+                Some((node.id, bucket.color.clone()))
+            })
+            .collect()
+    }
+
+    /// Colours connected components from the palette, largest first and cycling
+    /// when there are more clusters than colours. Unlinked nodes stay uncoloured.
+    fn cluster_colors(&self, graph: &GraphData) -> HashMap<u32, String> {
+        if self.cluster_palette.is_empty() {
+            return HashMap::new();
+        }
+        let mut neighbours: HashMap<u32, Vec<u32>> = HashMap::new();
+        for edge in &graph.edges {
+            neighbours.entry(edge.source).or_default().push(edge.target);
+            neighbours.entry(edge.target).or_default().push(edge.source);
+        }
+
+        let mut ids: Vec<u32> = graph.nodes.iter().map(|node| node.id).collect();
+        ids.sort_unstable();
+        let mut cluster_of: HashMap<u32, usize> = HashMap::new();
+        let mut clusters: Vec<Vec<u32>> = Vec::new();
+        for start in ids {
+            if cluster_of.contains_key(&start) || !neighbours.contains_key(&start) {
+                continue;
+            }
+            let mut members = Vec::new();
+            let mut queue = VecDeque::from([start]);
+            cluster_of.insert(start, clusters.len());
+            while let Some(id) = queue.pop_front() {
+                members.push(id);
+                for &next in neighbours.get(&id).into_iter().flatten() {
+                    if let Entry::Vacant(slot) = cluster_of.entry(next) {
+                        slot.insert(clusters.len());
+                        queue.push_back(next);
+                    }
+                }
+            }
+            clusters.push(members);
+        }
+
+        // Largest clusters first; ties keep the order they were found in, by lowest id
+        let mut order: Vec<usize> = (0..clusters.len()).collect();
+        order.sort_by_key(|&i| std::cmp::Reverse(clusters[i].len()));
+        let mut colors = HashMap::new();
+        for (rank, &i) in order.iter().enumerate() {
+            let color = &self.cluster_palette[rank % self.cluster_palette.len()];
+            for &id in &clusters[i] {
+                colors.insert(id, color.clone());
+            }
+        }
+        colors
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::edge::Edge;
+    use crate::models::metadata::Metadata;
+    use crate::models::node::Node;
+    use chrono::Duration;
+
+    fn mapper(mode: NodeColorMode) -> NodeColorMapper {
+        NodeColorMapper {
+            mode,
+            age_colors: vec![
+                AgeColor { max_age_days: 7, color: "#000007".to_string() },
+                AgeColor { max_age_days: 30, color: "#000030".to_string() },
+            ],
+            type_colors: BTreeMap::from([("tag".to_string(), "#7a9000".to_string())]),
+            cluster_palette: vec!["#c00001".to_string(), "#c00002".to_string()],
+        }
+    }
+
+    fn graph(now: DateTime<Utc>) -> GraphData {
+        let mut graph = GraphData::new();
+        for (id, name, age_days) in [(1, "New", 1), (2, "Month", 20), (3, "Old", 400), (4, "Lone", 3)] {
+            graph.nodes.push(Node::new_with_id(name.to_string(), Some(id)));
+            graph.metadata.insert(format!("{}.md", name), Metadata {
+                file_name: format!("{}.md", name),
+                last_modified: now - Duration::days(age_days),
+                ..Default::default()
+            });
+        }
+        let mut tag = Node::new_with_id("tag:rust".to_string(), Some(5));
+        tag.node_type = Some("tag".to_string());
+        graph.nodes.push(tag);
+        graph.edges.push(Edge::new(1, 2, 1.0));
+        graph.edges.push(Edge::new(2, 3, 1.0));
+        graph.edges.push(Edge::new(4, 5, 1.0));
+        graph
+    }
+
+    #[test]
+    fn test_color_modes() {
+        let now = Utc::now();
+        let mut g = graph(now);
+
+        let by_age = mapper(NodeColorMode::Age).apply(&mut g, now);
+        assert_eq!(by_age.get(&1).map(String::as_str), Some("#000007"));
+        assert_eq!(by_age.get(&2).map(String::as_str), Some("#000030"));
+        assert_eq!(by_age.get(&3).map(String::as_str), Some("#000030"));
+        assert!(!by_age.contains_key(&5));
+        assert_eq!(g.nodes[0].color.as_deref(), Some("#000007"));
+
+        let by_type = mapper(NodeColorMode::Type).apply(&mut g, now);
+        assert_eq!(by_type.into_iter().collect::<Vec<_>>(), vec![(5, "#7a9000".to_string())]);
+        assert_eq!(g.nodes[0].color, None);
+
+        let by_cluster = mapper(NodeColorMode::Cluster).apply(&mut g, now);
+        assert_eq!(by_cluster.get(&3).map(String::as_str), Some("#c00001"));
+        assert_eq!(by_cluster.get(&4).map(String::as_str), Some("#c00002"));
+
+        assert!(mapper(NodeColorMode::Base).apply(&mut g, now).is_empty());
+        assert!(g.nodes.iter().all(|node| node.color.is_none()));
+    }
+}
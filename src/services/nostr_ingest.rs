@@ -0,0 +1,307 @@
+//! Notes pulled in from Nostr relays
+//!
+//! With `nostrIngest.enabled` set in the protected settings, events matching the
+//! configured authors, kinds and hashtags (by default long-form posts) are fetched
+//! from the configured relays and shown in the graph as `nostr` nodes. A note is
+//! linked to the pages it `[[links]]` to and to pages named like its hashtags.
+//! Notes are cached in `/app/data/metadata/nostr_notes.json`, so the graph keeps
+//! them when the relays can't be reached, and relays that drop are reconnected and
+//! asked only for what came after the newest cached note.
+
+use log::{debug, info, warn};
+use nostr_sdk::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::PathBuf;
+use tokio::sync::broadcast;
+use tokio::time::Duration;
+
+use actix::Addr;
+use crate::actors::graph_actor::GraphServiceActor;
+use crate::actors::messages::SetExternalNotes;
+use crate::config::data_dirs::DataDirs;
+use crate::models::node::NOSTR_NODE_TYPE;
+use crate::models::protected_settings::NostrIngestSettings;
+use webxr_core::reference_parser::{PageIndex, ParserProfile};
+
+fn cache_path() -> PathBuf {
+    DataDirs::global().metadata_file("nostr_notes.json")
+}
+
+// Only once the relay pool itself has shut down; dropped relays reconnect on their own
+const RESTART_DELAY: Duration = Duration::from_secs(30);
+const MAX_TITLE_CHARS: usize = 80;
+const UNTITLED: &str = "Nostr note";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExternalNote {
+    /// Stays the same across edits of a replaceable event such as a long-form post
+    pub key: String,
+    pub event_id: String,
+    /// Hex public key of the author
+    pub author: String,
+    pub kind: u16,
+    pub title: String,
+    pub content: String,
+    #[serde(default)]
+    pub hashtags: Vec<String>,
+    pub created_at: i64,
+}
+
+impl ExternalNote {
+    pub fn from_event(event: &Event) -> Self {
+        let author = event.pubkey.to_string();
+        let kind = event.kind.as_u64() as u16;
+        let key = if event.is_parameterized_replaceable() {
+            format!("{}:{}:{}", author, kind, event.identifier().unwrap_or_default())
+        } else if event.is_replaceable() {
+            format!("{}:{}", author, kind)
+        } else {
+            event.id.to_hex()
+        };
+        let mut title = None;
+        let mut hashtags = Vec::new();
+        for tag in &event.tags {
+            match tag {
+                Tag::Title(value) if !value.trim().is_empty() => title = Some(value.trim().to_string()),
+                Tag::Hashtag(value) => hashtags.push(value.clone()),
+                _ => {}
+            }
+        }
+        let title = title
+            .or_else(|| event.content.lines()
+                .map(|line| line.trim().trim_start_matches('#').trim())
+                .find(|line| !line.is_empty())
+                .map(|line| line.chars().take(MAX_TITLE_CHARS).collect()))
+            .unwrap_or_else(|| UNTITLED.to_string());
+        Self {
+            key,
+            event_id: event.id.to_hex(),
+            author,
+            kind,
+            title,
+            content: event.content.clone(),
+            hashtags,
+            created_at: event.created_at.as_i64(),
+        }
+    }
+
+    /// `metadata_id` of the note's node
+    pub fn node_id(&self) -> String {
+        format!("{}:{}", NOSTR_NODE_TYPE, self.key)
+    }
+
+    /// Pages the note links to, as named in `pages`. Hashtags count as links to
+    /// the page of the same name.
+    pub fn linked_pages(&self, pages: &PageIndex) -> Vec<String> {
+        let mut text = self.content.clone();
+        for hashtag in &self.hashtags {
+            text.push_str(&format!("\n[[{}]]", hashtag));
+        }
+        let mut linked = ParserProfile::Obsidian.extract_references(&text, pages);
+        linked.sort();
+        linked.dedup();
+        linked
+    }
+}
+
+/// The newest `max_notes` notes, one per key
+pub struct NoteCache {
+    notes: Vec<ExternalNote>,
+    max_notes: usize,
+}
+
+impl NoteCache {
+    pub fn new(max_notes: usize) -> Self {
+        Self { notes: Vec::new(), max_notes }
+    }
+
+    pub fn load(max_notes: usize) -> Self {
+        let mut cache = Self::new(max_notes);
+        match fs::read_to_string(cache_path()).map_err(|e| e.to_string())
+            .and_then(|content| serde_json::from_str::<Vec<ExternalNote>>(&content).map_err(|e| e.to_string()))
+        {
+            Ok(notes) => {
+                for note in notes {
+                    cache.insert(note);
+                }
+            }
+            Err(e) => debug!("[NostrIngest] No cached notes loaded: {}", e),
+        }
+        cache
+    }
+
+    pub fn notes(&self) -> &[ExternalNote] {
+        &self.notes
+    }
+
+    /// `created_at` of the newest note
+    pub fn newest(&self) -> Option<i64> {
+        self.notes.first().map(|note| note.created_at)
+    }
+
+    /// Adds `note`, replacing an older version of it. False when the cache already
+    /// had this version or a newer one, or the note is too old to keep.
+    pub fn insert(&mut self, note: ExternalNote) -> bool {
+        if let Some(existing) = self.notes.iter_mut().find(|existing| existing.key == note.key) {
+            if existing.created_at >= note.created_at {
+                return false;
+            }
+            *existing = note.clone();
+        } else {
+            self.notes.push(note.clone());
+        }
+        self.notes.sort_by(|a, b| b.created_at.cmp(&a.created_at).then_with(|| a.key.cmp(&b.key)));
+        self.notes.truncate(self.max_notes);
+        self.notes.iter().any(|kept| kept.key == note.key)
+    }
+
+    pub fn save(&self) -> std::io::Result<()> {
+        if let Some(parent) = cache_path().parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(cache_path(), serde_json::to_string_pretty(&self.notes)?)
+    }
+}
+
+pub struct NostrIngest {
+    relays: Vec<String>,
+    filter: Filter,
+    max_notes: usize,
+}
+
+impl NostrIngest {
+    /// `None` unless ingesting is enabled and has relays
+    pub fn from_settings(settings: &NostrIngestSettings) -> Option<Self> {
+        if !settings.enabled {
+            return None;
+        }
+        if settings.relays.is_empty() {
+            warn!("[NostrIngest] Ingesting is enabled but no relays are configured");
+            return None;
+        }
+        let authors: Vec<XOnlyPublicKey> = settings.authors.iter()
+            .filter_map(|author| match Keys::from_pk_str(author.trim()) {
+                Ok(keys) => Some(keys.public_key()),
+                Err(e) => {
+                    warn!("[NostrIngest] Skipping author {}: {}", author, e);
+                    None
+                }
+            })
+            .collect();
+        if authors.is_empty() && !settings.authors.is_empty() {
+            warn!("[NostrIngest] None of the configured authors is a valid public key");
+            return None;
+        }
+        let mut filter = Filter::new()
+            .kinds(settings.kinds.iter().map(|kind| Kind::from(u64::from(*kind))))
+            .limit(settings.max_notes);
+        if !authors.is_empty() {
+            filter = filter.authors(authors);
+        }
+        if !settings.hashtags.is_empty() {
+            filter = filter.hashtags(settings.hashtags.iter().map(|hashtag| hashtag.trim_start_matches('#').to_string()));
+        }
+        Some(Self { relays: settings.relays.clone(), filter, max_notes: settings.max_notes })
+    }
+
+    pub fn start(self, graph_service_addr: Addr<GraphServiceActor>) {
+        tokio::spawn(async move {
+            let mut cache = NoteCache::load(self.max_notes);
+            if !cache.notes().is_empty() {
+                info!("[NostrIngest] Showing {} cached notes", cache.notes().len());
+                graph_service_addr.do_send(SetExternalNotes { notes: cache.notes().to_vec() });
+            }
+            loop {
+                self.listen(&mut cache, &graph_service_addr).await;
+                warn!("[NostrIngest] Relay pool shut down, restarting in {}s", RESTART_DELAY.as_secs());
+                tokio::time::sleep(RESTART_DELAY).await;
+            }
+        });
+    }
+
+    async fn listen(&self, cache: &mut NoteCache, graph_service_addr: &Addr<GraphServiceActor>) {
+        let client = Client::new(&Keys::generate());
+        for relay in &self.relays {
+            if let Err(e) = client.add_relay(relay.as_str()).await {
+                warn!("[NostrIngest] Skipping relay {}: {}", relay, e);
+            }
+        }
+        let mut notifications = client.notifications();
+        client.connect().await;
+        let filter = match cache.newest() {
+            Some(newest) => self.filter.clone().since(Timestamp::from(newest.max(0) as u64)),
+            None => self.filter.clone(),
+        };
+        client.subscribe(vec![filter]).await;
+        info!("[NostrIngest] Subscribed to {} relays", self.relays.len());
+
+        loop {
+            match notifications.recv().await {
+                Ok(RelayPoolNotification::Event { relay_url, event }) => {
+                    if let Err(e) = event.verify() {
+                        warn!("[NostrIngest] Dropping event from {} with a bad signature: {}", relay_url, e);
+                        continue;
+                    }
+                    let note = ExternalNote::from_event(&event);
+                    let title = note.title.clone();
+                    if cache.insert(note) {
+                        debug!("[NostrIngest] Note '{}' from {}", title, relay_url);
+                        if let Err(e) = cache.save() {
+                            warn!("[NostrIngest] Failed to cache notes: {}", e);
+                        }
+                        graph_service_addr.do_send(SetExternalNotes { notes: cache.notes().to_vec() });
+                    }
+                }
+                Ok(RelayPoolNotification::RelayStatus { relay_url, status }) => {
+                    debug!("[NostrIngest] Relay {} is {}", relay_url, status);
+                }
+                Ok(RelayPoolNotification::Shutdown) | Err(broadcast::error::RecvError::Closed) => break,
+                Ok(_) => {}
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("[NostrIngest] Lagged, skipped {} notifications", skipped);
+                }
+            }
+        }
+        if let Err(e) = client.shutdown().await {
+            debug!("[NostrIngest] Failed to shut the client down: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::metadata::{Metadata, MetadataStore};
+
+    #[test]
+    fn test_notes_from_events() {
+        let keys = Keys::generate();
+        let post = |content: &str, created_at: i64| {
+            let event = EventBuilder::new(Kind::LongFormTextNote, content, [
+                Tag::Identifier("webxr-notes".to_string()),
+                Tag::Hashtag("Rust".to_string()),
+            ]).to_event(&keys).unwrap();
+            ExternalNote { created_at, ..ExternalNote::from_event(&event) }
+        };
+
+        let first = post("# WebXR notes\nSee [[WebXR]]", 100);
+        assert_eq!(first.title, "WebXR notes");
+        assert_eq!(first.key, format!("{}:30023:webxr-notes", keys.public_key()));
+
+        let mut metadata = MetadataStore::new();
+        for name in ["WebXR.md", "rust.md", "Other.md"] {
+            metadata.insert(name.to_string(), Metadata { file_name: name.to_string(), ..Default::default() });
+        }
+        assert_eq!(first.linked_pages(&PageIndex::from_metadata(&metadata)), vec!["WebXR", "rust"]);
+
+        let mut cache = NoteCache::new(1);
+        assert!(cache.insert(first.clone()));
+        assert!(!cache.insert(first));
+        // An edit of the post replaces it rather than adding a second note
+        assert!(cache.insert(post("Edited", 200)));
+        assert_eq!(cache.notes().len(), 1);
+        assert_eq!(cache.newest(), Some(200));
+    }
+}
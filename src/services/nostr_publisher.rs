@@ -0,0 +1,142 @@
+//! Publishing page changes to Nostr relays
+//!
+//! With `nostrPublishing.enabled` set in the protected settings, every page a sync
+//! or the journal adds or updates is announced as an event signed with the key in
+//! `NOSTR_PUBLISH_SECRET_KEY` (hex or `nsec`) and sent to the configured relays.
+//! Followers subscribe to the key's events of the configured kind (a text note
+//! unless set otherwise) to see the vault's public activity. Only pages already
+//! published to the graph produce activity, so nothing private is announced.
+
+use log::{info, warn};
+use nostr_sdk::prelude::*;
+use std::env;
+use tokio::sync::broadcast;
+
+use crate::models::protected_settings::NostrPublishingSettings;
+use crate::services::activity::{ActivityEntry, ActivityKind};
+use crate::services::event_bus::{AppEvent, EventBus};
+use crate::services::pages::page_title;
+
+const SECRET_KEY_VAR: &str = "NOSTR_PUBLISH_SECRET_KEY";
+const HASHTAG: &str = "logseq";
+
+pub struct NostrPublisher {
+    keys: Keys,
+    kind: Kind,
+    relays: Vec<String>,
+}
+
+impl NostrPublisher {
+    /// `None` unless publishing is enabled, has relays and a signing key
+    pub fn from_settings(settings: &NostrPublishingSettings) -> Option<Self> {
+        if !settings.enabled {
+            return None;
+        }
+        if settings.relays.is_empty() {
+            warn!("[NostrPublisher] Publishing is enabled but no relays are configured");
+            return None;
+        }
+        let secret_key = match env::var(SECRET_KEY_VAR) {
+            Ok(secret_key) if !secret_key.trim().is_empty() => secret_key,
+            _ => {
+                warn!("[NostrPublisher] Publishing is enabled but {} is not set", SECRET_KEY_VAR);
+                return None;
+            }
+        };
+        let keys = match Keys::from_sk_str(secret_key.trim()) {
+            Ok(keys) => keys,
+            Err(e) => {
+                warn!("[NostrPublisher] Invalid {}: {}", SECRET_KEY_VAR, e);
+                return None;
+            }
+        };
+        Some(Self { keys, kind: Kind::from(u64::from(settings.kind)), relays: settings.relays.clone() })
+    }
+
+    pub fn start(self, event_bus: &EventBus) {
+        let mut events = event_bus.subscribe();
+        tokio::spawn(async move {
+            let client = Client::new(&self.keys);
+            for relay in &self.relays {
+                if let Err(e) = client.add_relay(relay.as_str()).await {
+                    warn!("[NostrPublisher] Skipping relay {}: {}", relay, e);
+                }
+            }
+            client.connect().await;
+            info!("[NostrPublisher] Publishing page changes as {}", self.keys.public_key());
+
+            loop {
+                match events.recv().await {
+                    Ok(AppEvent::Activity(entry)) => {
+                        let Some(event) = page_event(&entry, self.kind, &self.keys) else { continue };
+                        if let Err(e) = client.send_event(event).await {
+                            warn!("[NostrPublisher] Failed to publish {}: {}", entry.subject, e);
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("[NostrPublisher] Lagged, skipped {} events", skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+}
+
+/// The signed announcement of an added or updated page; other activity isn't published
+fn page_event(entry: &ActivityEntry, kind: Kind, keys: &Keys) -> Option<Event> {
+    let (action, verb) = match entry.kind {
+        ActivityKind::NodeAdded => ("added", "Added"),
+        ActivityKind::FileUpdated => ("updated", "Updated"),
+        _ => return None,
+    };
+    let title = page_title(&entry.subject);
+    let mut tags = vec![
+        Tag::Title(title.clone()),
+        Tag::Generic(TagKind::Custom("action".to_string()), vec![action.to_string()]),
+        Tag::Hashtag(HASHTAG.to_string()),
+    ];
+    // Replaceable kinds such as long-form posts (30023) need a `d` tag, and naming
+    // the page there makes each update replace the page's previous event
+    if kind.is_parameterized_replaceable() {
+        tags.push(Tag::Identifier(title.clone()));
+    }
+    EventBuilder::new(kind, format!("{} page: {}", verb, title), tags)
+        .to_event(keys)
+        .map_err(|e| warn!("[NostrPublisher] Failed to sign event for {}: {}", entry.subject, e))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Utc;
+
+    #[test]
+    fn test_page_event() {
+        let keys = Keys::generate();
+        let entry = |kind| ActivityEntry {
+            id: 1,
+            at: Utc::now(),
+            kind,
+            subject: "projects___webxr.md".to_string(),
+            actor: None,
+            detail: serde_json::Value::Null,
+        };
+
+        let event = page_event(&entry(ActivityKind::NodeAdded), Kind::from(30023), &keys).unwrap();
+        assert!(event.verify().is_ok());
+        assert_eq!(event.kind, Kind::from(30023));
+        assert_eq!(event.pubkey, keys.public_key());
+        assert_eq!(event.content, "Added page: projects/webxr");
+        let tags: Vec<Vec<String>> = event.tags.iter().map(Tag::as_vec).collect();
+        assert!(tags.contains(&vec!["action".to_string(), "added".to_string()]));
+        assert!(tags.contains(&vec!["t".to_string(), HASHTAG.to_string()]));
+        assert!(tags.contains(&vec!["d".to_string(), "projects/webxr".to_string()]));
+
+        let note = page_event(&entry(ActivityKind::FileUpdated), Kind::TextNote, &keys).unwrap();
+        assert!(note.identifier().is_none());
+        assert!(page_event(&entry(ActivityKind::NodeRemoved), Kind::TextNote, &keys).is_none());
+    }
+}
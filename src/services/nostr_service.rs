@@ -203,6 +203,22 @@ impl NostrService {
         });
     }
 
+    /// Signs `pubkey` in without an auth event and returns the session token. Only
+    /// `test_support` uses this; the server opens sessions in `verify_auth_event`.
+    pub(crate) async fn open_session(&self, pubkey: &str, is_power_user: bool) -> String {
+        let session_token = Uuid::new_v4().to_string();
+        let user = NostrUser {
+            pubkey: pubkey.to_string(),
+            npub: String::new(),
+            is_power_user,
+            api_keys: ApiKeys::default(),
+            last_seen: Utc::now().timestamp(),
+            session_token: Some(session_token.clone()),
+        };
+        self.users.write().await.insert(pubkey.to_string(), user);
+        session_token
+    }
+
     pub async fn is_power_user(&self, pubkey: &str) -> bool {
         if let Some(user) = self.get_user(pubkey).await {
             user.is_power_user
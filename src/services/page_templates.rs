@@ -0,0 +1,103 @@
+//! Creating pages from templates
+//!
+//! Templates are markdown files in the `templates` data directory, written with
+//! Handlebars placeholders: `meeting.md` holding `# {{title}}` and
+//! `- Attendees: {{attendees}}` is the `meeting` template. Besides the caller's
+//! variables every template gets `title`, `date` (`2024-03-01`) and `time`
+//! (`14:30`). Rendering is strict, so a placeholder with no value is an error
+//! rather than a blank in the new page, and nothing is HTML-escaped.
+
+use chrono::Local;
+use handlebars::Handlebars;
+use serde_json::{Map, Value};
+use std::path::PathBuf;
+use thiserror::Error;
+
+const TEMPLATE_EXTENSION: &str = "md";
+
+#[derive(Debug, Error, PartialEq)]
+pub enum TemplateError {
+    #[error("Template {0} not found")]
+    NotFound(String),
+    #[error("Invalid template name {0}")]
+    InvalidName(String),
+    #[error("Template {name} could not be rendered: {message}")]
+    Render { name: String, message: String },
+}
+
+pub struct PageTemplates {
+    dir: PathBuf,
+}
+
+fn is_valid_name(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | ' '))
+}
+
+impl PageTemplates {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Names of the available templates, sorted
+    pub fn list(&self) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(&self.dir)
+            .map(|entries| entries
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == TEMPLATE_EXTENSION))
+                .filter_map(|path| path.file_stem().and_then(|stem| stem.to_str()).map(str::to_string))
+                .filter(|name| is_valid_name(name))
+                .collect())
+            .unwrap_or_default();
+        names.sort();
+        names
+    }
+
+    /// The markdown of a page titled `title` from template `name`. `variables`
+    /// may override `date` and `time`, but not `title`.
+    pub fn render(&self, name: &str, title: &str, variables: &Map<String, Value>) -> Result<String, TemplateError> {
+        if !is_valid_name(name) {
+            return Err(TemplateError::InvalidName(name.to_string()));
+        }
+        let path = self.dir.join(format!("{}.{}", name, TEMPLATE_EXTENSION));
+        let source = std::fs::read_to_string(&path).map_err(|_| TemplateError::NotFound(name.to_string()))?;
+
+        let now = Local::now();
+        let mut data = Map::new();
+        data.insert("date".to_string(), Value::String(now.format("%Y-%m-%d").to_string()));
+        data.insert("time".to_string(), Value::String(now.format("%H:%M").to_string()));
+        data.extend(variables.iter().map(|(key, value)| (key.clone(), value.clone())));
+        data.insert("title".to_string(), Value::String(title.to_string()));
+
+        let mut handlebars = Handlebars::new();
+        handlebars.set_strict_mode(true);
+        handlebars.register_escape_fn(handlebars::no_escape);
+        handlebars.render_template(&source, &data)
+            .map_err(|e| TemplateError::Render { name: name.to_string(), message: e.to_string() })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_render() {
+        let dir = std::env::temp_dir().join(format!("page_templates_test_{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("meeting.md"), "# {{title}}\n- date:: {{date}}\n- Attendees: {{#each attendees}}[[{{this}}]] {{/each}}\n").unwrap();
+        std::fs::write(dir.join("notes.txt"), "not a template").unwrap();
+        let templates = PageTemplates::new(&dir);
+        assert_eq!(templates.list(), vec!["meeting".to_string()]);
+
+        let variables = json!({"date": "2024-03-01", "attendees": ["Ana", "B & C"], "title": "ignored"});
+        let page = templates.render("meeting", "Standup", variables.as_object().unwrap()).unwrap();
+        assert_eq!(page, "# Standup\n- date:: 2024-03-01\n- Attendees: [[Ana]] [[B & C]] \n");
+
+        let missing = templates.render("meeting", "Standup", &Map::new());
+        assert!(matches!(missing, Err(TemplateError::Render { .. })));
+        assert_eq!(templates.render("../secret", "x", &Map::new()), Err(TemplateError::InvalidName("../secret".to_string())));
+        assert_eq!(templates.render("retro", "x", &Map::new()), Err(TemplateError::NotFound("retro".to_string())));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,274 @@
+//! Browsing pages without the graph: namespace tree, rendered content,
+//! backlinks, recent changes and search
+//!
+//! Logseq keeps namespaced pages (`projects/webxr`) in flat files, writing the
+//! `/` as `___` in newer vaults and as `%2F` in older ones. Titles are recovered
+//! from the file name, and the tree is built from the `/`-separated parts.
+
+use chrono::{DateTime, Utc};
+use pulldown_cmark::{html, Event, Options, Parser};
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use crate::models::metadata::{Metadata, MetadataStore};
+use crate::services::reference_parser::normalize_name;
+
+const SNIPPET_CHARS: usize = 80;
+
+/// The page title of `file_name`, with namespaces separated by `/`
+pub fn page_title(file_name: &str) -> String {
+    let stem = file_name.trim_end_matches(".md");
+    let stem = stem.replace("___", "/");
+    urlencoding::decode(&stem).map(|title| title.into_owned()).unwrap_or(stem)
+}
+
+/// The namespace `title` belongs to, if any
+pub fn parent_title(title: &str) -> Option<&str> {
+    title.rsplit_once('/').map(|(parent, _)| parent).filter(|parent| !parent.is_empty())
+}
+
+/// The file a new page titled `title` is stored in, the way newer Logseq vaults
+/// name namespaced pages, or `None` if the title can't be a page
+pub fn page_file_name(title: &str) -> Option<String> {
+    let title = title.trim();
+    let valid = !title.is_empty()
+        && title.split('/').all(|part| !part.trim().is_empty() && !part.starts_with('.'))
+        && !title.chars().any(|c| c.is_control() || matches!(c, '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|'));
+    valid.then(|| format!("{}.md", title.replace('/', "___")))
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PageSummary {
+    /// Page name used in the pages API, the file name without `.md`
+    pub id: String,
+    pub title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub parent: Option<String>,
+    pub node_id: String,
+    pub modified: DateTime<Utc>,
+    pub word_count: usize,
+}
+
+impl PageSummary {
+    pub fn of(file_name: &str, metadata: &Metadata) -> Self {
+        let title = page_title(file_name);
+        Self {
+            id: file_name.trim_end_matches(".md").to_string(),
+            parent: parent_title(&title).map(str::to_string),
+            title,
+            node_id: metadata.node_id.clone(),
+            modified: metadata.last_modified,
+            word_count: metadata.page_stats.word_count,
+        }
+    }
+}
+
+/// Every page, sorted by title
+pub fn list(metadata_store: &MetadataStore) -> Vec<PageSummary> {
+    let mut pages: Vec<PageSummary> = metadata_store.iter()
+        .map(|(file_name, metadata)| PageSummary::of(file_name, metadata))
+        .collect();
+    pages.sort_by_cached_key(|page| normalize_name(&page.title));
+    pages
+}
+
+/// A namespace, which may also be a page of its own
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TreeNode {
+    /// Last part of the title
+    pub name: String,
+    pub title: String,
+    /// The page with this title, if there is one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<PageSummary>,
+    pub children: Vec<TreeNode>,
+}
+
+#[derive(Default)]
+struct Branch {
+    page: Option<PageSummary>,
+    children: BTreeMap<String, Branch>,
+}
+
+fn into_nodes(children: BTreeMap<String, Branch>, prefix: &str) -> Vec<TreeNode> {
+    let mut nodes: Vec<TreeNode> = children.into_iter()
+        .map(|(name, branch)| {
+            let title = if prefix.is_empty() { name.clone() } else { format!("{}/{}", prefix, name) };
+            TreeNode { children: into_nodes(branch.children, &title), page: branch.page, name, title }
+        })
+        .collect();
+    nodes.sort_by_cached_key(|node| normalize_name(&node.name));
+    nodes
+}
+
+/// Pages arranged by namespace, each level sorted by name
+pub fn tree(metadata_store: &MetadataStore) -> Vec<TreeNode> {
+    let mut root = Branch::default();
+    for page in list(metadata_store) {
+        let mut branch = &mut root;
+        for part in page.title.split('/').filter(|part| !part.is_empty()) {
+            branch = branch.children.entry(part.to_string()).or_default();
+        }
+        branch.page = Some(page);
+    }
+    into_nodes(root.children, "")
+}
+
+/// Renders page markdown as HTML. Raw HTML in the page is escaped rather than
+/// passed through, so pages can't inject markup into the client.
+pub fn render_html(content: &str) -> String {
+    let options = Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    let events = Parser::new_ext(content, options).map(|event| match event {
+        Event::Html(raw) => Event::Text(raw),
+        event => event,
+    });
+    let mut rendered = String::with_capacity(content.len() * 3 / 2);
+    html::push_html(&mut rendered, events);
+    rendered
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Backlink {
+    #[serde(flatten)]
+    pub page: PageSummary,
+    /// References from that page to this one
+    pub count: usize,
+}
+
+/// Pages referencing `file_name`, most references first
+pub fn backlinks(metadata_store: &MetadataStore, file_name: &str) -> Vec<Backlink> {
+    let target = file_name.trim_end_matches(".md");
+    let mut links: Vec<Backlink> = metadata_store.iter()
+        .filter(|(source, _)| source.as_str() != file_name)
+        .filter_map(|(source, metadata)| {
+            let count = *metadata.topic_counts.get(target)?;
+            Some(Backlink { page: PageSummary::of(source, metadata), count })
+        })
+        .collect();
+    links.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.page.title.cmp(&b.page.title)));
+    links
+}
+
+/// Pages by last modification, newest first
+pub fn recent(metadata_store: &MetadataStore) -> Vec<PageSummary> {
+    let mut pages = list(metadata_store);
+    pages.sort_by(|a, b| b.modified.cmp(&a.modified).then_with(|| a.title.cmp(&b.title)));
+    pages
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchHit {
+    #[serde(flatten)]
+    pub page: PageSummary,
+    /// Text around the first match in the page body, when the body matched
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snippet: Option<String>,
+    pub score: u32,
+}
+
+/// A query matched against page titles, aliases and bodies, ignoring case
+pub struct Search {
+    query: String,
+    pattern: Regex,
+}
+
+impl Search {
+    /// `None` for a blank query
+    pub fn new(query: &str) -> Option<Self> {
+        let query = normalize_name(query);
+        if query.is_empty() {
+            return None;
+        }
+        let pattern = RegexBuilder::new(&regex::escape(&query)).case_insensitive(true).build().ok()?;
+        Some(Self { query, pattern })
+    }
+
+    /// Ranks a page: title matches beat alias matches, which beat matches in
+    /// the body. `None` when nothing matched.
+    pub fn score(&self, file_name: &str, metadata: &Metadata, content: Option<&str>) -> Option<SearchHit> {
+        let query = self.query.as_str();
+        let title = normalize_name(&page_title(file_name));
+        let mut score = if title == query {
+            100
+        } else if title.starts_with(query) || title.rsplit('/').next().is_some_and(|name| name.starts_with(query)) {
+            60
+        } else if title.contains(query) {
+            40
+        } else if metadata.aliases.iter().any(|alias| normalize_name(alias).contains(query)) {
+            30
+        } else {
+            0
+        };
+
+        let mut snippet = None;
+        if let Some(content) = content {
+            let mut matches = self.pattern.find_iter(content);
+            if let Some(first) = matches.next() {
+                score += 11 + matches.take(9).count() as u32;
+                snippet = Some(make_snippet(content, first.start(), first.end()));
+            }
+        }
+        (score > 0).then(|| SearchHit { page: PageSummary::of(file_name, metadata), snippet, score })
+    }
+}
+
+fn make_snippet(text: &str, match_start: usize, match_end: usize) -> String {
+    let start = text[..match_start].char_indices().rev().nth(SNIPPET_CHARS / 2).map_or(0, |(i, _)| i);
+    let end = text[match_end..].char_indices().nth(SNIPPET_CHARS / 2).map_or(text.len(), |(i, _)| match_end + i);
+    let mut snippet = text[start..end].split_whitespace().collect::<Vec<_>>().join(" ");
+    if start > 0 {
+        snippet.insert(0, '…');
+    }
+    if end < text.len() {
+        snippet.push('…');
+    }
+    snippet
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_namespaces_and_tree() {
+        assert_eq!(page_title("projects___webxr.md"), "projects/webxr");
+        assert_eq!(page_title("projects%2Fold.md"), "projects/old");
+        assert_eq!(parent_title("projects/webxr/server"), Some("projects/webxr"));
+        assert_eq!(parent_title("Rust"), None);
+        assert_eq!(page_file_name("projects/webxr").as_deref(), Some("projects___webxr.md"));
+        assert_eq!(page_file_name("projects/../secrets"), None);
+        assert_eq!(page_file_name("a//b"), None);
+
+        let store: MetadataStore = ["projects___webxr___server.md", "projects___webxr.md", "Rust.md"].iter()
+            .map(|name| (name.to_string(), Metadata { file_name: name.to_string(), ..Default::default() }))
+            .collect();
+        let tree = tree(&store);
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree[0].title, "projects");
+        assert!(tree[0].page.is_none());
+        let webxr = &tree[0].children[0];
+        assert_eq!(webxr.page.as_ref().map(|page| page.id.as_str()), Some("projects___webxr"));
+        assert_eq!(webxr.children[0].title, "projects/webxr/server");
+        assert_eq!(tree[1].name, "Rust");
+    }
+
+    #[test]
+    fn test_render_escapes_html_and_search_ranks() {
+        let html = render_html("# Title\n\n<script>alert(1)</script>\n\n- [ ] task");
+        assert!(html.contains("<h1>Title</h1>"));
+        assert!(!html.contains("<script>"));
+
+        let metadata = Metadata { aliases: vec!["rustlang".to_string()], ..Default::default() };
+        let title_hit = Search::new("Rust").unwrap().score("Rust.md", &metadata, None).unwrap();
+        let body_hit = Search::new("BORROW").unwrap().score("Rust.md", &metadata, Some("Ownership and the borrow checker")).unwrap();
+        assert!(title_hit.score > body_hit.score);
+        assert_eq!(body_hit.snippet.as_deref(), Some("Ownership and the borrow checker"));
+        assert!(Search::new("python").unwrap().score("Rust.md", &metadata, Some("nothing here")).is_none());
+        assert!(Search::new("  ").is_none());
+    }
+}
@@ -1,18 +1,39 @@
+use crate::config::data_dirs::DataDirs;
 use crate::config::AppFullSettings; // Use AppFullSettings, ConfigPerplexitySettings removed
-use crate::models::metadata::Metadata;
+use crate::models::metadata::{Metadata, MetadataStore};
 use crate::services::file_service::ProcessedFile;
+use crate::services::event_bus::{EventBus, EnrichmentEvent};
+use crate::services::ai_cache::{AiCache, CacheMode};
+use crate::services::ai_usage;
+use crate::services::job_queue::JobContext;
+use crate::services::resilience::{self, Dependency};
+use crate::services::vault_crypto::{self, VaultCipher};
 use chrono::Utc;
-use log::{error, info};
+use log::{debug, error, info, warn};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::error::Error as StdError;
-use std::fs;
-use std::path::Path;
 use std::sync::Arc;
 use tokio::sync::RwLock;
+use webxr_core::page::{page_stats, page_tasks};
 use std::collections::HashMap;
+use std::time::Duration;
 
-const MARKDOWN_DIR: &str = "data/markdown";
+// Enrichment is batched to stay well inside Perplexity's rate limits
+const ENRICHMENT_BATCH_SIZE: usize = 3;
+const ENRICHMENT_BATCH_DELAY: Duration = Duration::from_secs(1);
+// Long pages are truncated before being sent; the summary only needs the gist
+const MAX_ENRICHMENT_CHARS: usize = 12_000;
+const ENRICHMENT_PROMPT: &str = "You summarise notes from a personal knowledge graph. \
+Reply with a concise two or three sentence summary of the note's topic, \
+citing one authoritative web source for further reading.";
+
+/// Summary and reference link generated for one file
+#[derive(Debug, Clone)]
+pub struct Enrichment {
+    pub summary: String,
+    pub link: String,
+}
 
 #[derive(Debug, Serialize, Deserialize)]
 struct PerplexityResponse {
@@ -35,6 +56,7 @@ struct QueryRequest {
 pub struct PerplexityService {
     client: Client,
     settings: Arc<RwLock<AppFullSettings>>, // Changed to AppFullSettings
+    dependency: Arc<Dependency>,
 }
 
 impl PerplexityService {
@@ -51,11 +73,68 @@ impl PerplexityService {
 
         Ok(Self { 
             client,
-            settings: Arc::clone(&settings)
+            settings: Arc::clone(&settings),
+            dependency: resilience::dependency(resilience::PERPLEXITY),
         })
     }
 
-    pub async fn query(&self, query: &str, conversation_id: &str) -> Result<String, Box<dyn StdError + Send + Sync>> {
+    /// Tokens used by one request: Perplexity reports them under `usage`, otherwise
+    /// they are estimated from the text
+    fn token_usage(request: &str, body: &str) -> (u64, u64) {
+        let usage = serde_json::from_str::<serde_json::Value>(body).ok()
+            .map(|body| body["usage"].clone())
+            .unwrap_or_default();
+        (
+            usage["prompt_tokens"].as_u64().unwrap_or_else(|| ai_usage::estimate_tokens(request)),
+            usage["completion_tokens"].as_u64().unwrap_or_else(|| ai_usage::estimate_tokens(body)),
+        )
+    }
+
+    /// Posts `request` and returns the response body, from the AI cache when it
+    /// holds an answer to the same request. Fails with `BudgetExceeded` when the
+    /// request would go to Perplexity after today's budget is spent.
+    async fn post_cached(
+        &self,
+        api_url: &str,
+        api_key: &str,
+        model: &str,
+        request: &(impl Serialize + Sync),
+        cache: Option<AiCache>,
+        mode: CacheMode,
+    ) -> Result<String, Box<dyn StdError + Send + Sync>> {
+        let prompt = serde_json::to_string(request)?;
+        let key = AiCache::key(resilience::PERPLEXITY, model, &prompt);
+        if let Some(body) = cache.as_ref().and_then(|cache| cache.get(&key, mode)) {
+            return Ok(body);
+        }
+        ai_usage::check(resilience::PERPLEXITY)?;
+
+        let response = self.dependency.send(|| {
+            self.client
+                .post(api_url)
+                .header("Authorization", format!("Bearer {}", api_key))
+                .json(request)
+        }).await?;
+
+        let status = response.status();
+        if !status.is_success() {
+            let error_text = response.text().await?;
+            error!("Perplexity API error: Status: {}, Error: {}", status, error_text);
+            return Err(format!("Perplexity API error: {}", error_text).into());
+        }
+
+        let body = response.text().await?;
+        let (input_tokens, output_tokens) = Self::token_usage(&prompt, &body);
+        ai_usage::record(resilience::PERPLEXITY, input_tokens, output_tokens);
+        if let Some(cache) = &cache {
+            if let Err(e) = cache.put(&key, resilience::PERPLEXITY, model, &body, mode) {
+                warn!("Failed to cache Perplexity response: {}", e);
+            }
+        }
+        Ok(body)
+    }
+
+    pub async fn query(&self, query: &str, conversation_id: &str, mode: CacheMode) -> Result<String, Box<dyn StdError + Send + Sync>> {
         let settings_read = self.settings.read().await;
         
         // Get perplexity settings or return error if not configured
@@ -83,31 +162,19 @@ impl PerplexityService {
             frequency_penalty: perplexity_config.frequency_penalty.unwrap_or(0.0),
         };
 
-        let response = self.client
-            .post(api_url)
-            .header("Authorization", format!("Bearer {}", api_key))
-            .json(&request)
-            .send()
-            .await?;
-
-        let status = response.status();
-        if !status.is_success() {
-            let error_text = response.text().await?;
-            error!("Perplexity API error: Status: {}, Error: {}", status, error_text);
-            return Err(format!("Perplexity API error: {}", error_text).into());
-        }
-
-        let perplexity_response: PerplexityResponse = response.json().await?;
+        let cache = AiCache::from_settings(settings_read.ai_cache.as_ref());
+        let body = self.post_cached(api_url, api_key, model, &request, cache, mode).await?;
+        let perplexity_response: PerplexityResponse = serde_json::from_str(&body)?;
         Ok(perplexity_response.content)
     }
 
     pub async fn process_file(&self, file_name: &str) -> Result<ProcessedFile, Box<dyn StdError + Send + Sync>> {
-        let file_path = format!("{}/{}", MARKDOWN_DIR, file_name);
-        if !Path::new(&file_path).exists() {
+        let file_path = DataDirs::global().markdown_file(file_name);
+        if !file_path.exists() {
             return Err(format!("File not found: {}", file_name).into());
         }
 
-        let content = fs::read_to_string(&file_path)?;
+        let content = vault_crypto::read_to_string(&file_path)?;
         let settings_read = self.settings.read().await;
 
         // Get perplexity settings or return error if not configured
@@ -121,14 +188,15 @@ impl PerplexityService {
         let api_key = perplexity_config.api_key.as_deref().ok_or("Perplexity API Key not configured")?;
 
         info!("Sending request to Perplexity API: {}", api_url);
+        ai_usage::check(resilience::PERPLEXITY)?;
 
         // Assuming the API takes the raw content as JSON string body? If not, adjust .json(&content)
-        let response = self.client
-            .post(api_url)
-            .header("Authorization", format!("Bearer {}", api_key))
-            .json(&content)
-            .send()
-            .await?;
+        let response = self.dependency.send(|| {
+            self.client
+                .post(api_url)
+                .header("Authorization", format!("Bearer {}", api_key))
+                .json(&content)
+        }).await?;
 
         let status = response.status();
         if !status.is_success() {
@@ -138,6 +206,11 @@ impl PerplexityService {
         }
 
         let perplexity_response: PerplexityResponse = response.json().await?;
+        ai_usage::record(
+            resilience::PERPLEXITY,
+            ai_usage::estimate_tokens(&content),
+            ai_usage::estimate_tokens(&perplexity_response.content),
+        );
         
         // Create metadata for processed file
         let metadata = Metadata {
@@ -150,7 +223,17 @@ impl PerplexityService {
             last_modified: Utc::now(),
             perplexity_link: perplexity_response.link,
             last_perplexity_process: Some(Utc::now()),
+            perplexity_summary: String::new(),
             topic_counts: HashMap::new(),
+            unresolved_links: HashMap::new(),
+            private_links: HashMap::new(),
+            tags: Vec::new(),
+            aliases: Vec::new(),
+            link_previews: Vec::new(),
+            page_stats: page_stats(&perplexity_response.content),
+            tasks: page_tasks(&perplexity_response.content),
+            near_duplicates: HashMap::new(),
+            link_checks: HashMap::new(),
         };
 
         Ok(ProcessedFile {
@@ -160,4 +243,124 @@ impl PerplexityService {
             metadata,
         })
     }
+
+    /// Ask Perplexity for a short summary and a reference link for one note
+    pub async fn enrich_file(&self, file_name: &str, content: &str, mode: CacheMode) -> Result<Enrichment, Box<dyn StdError + Send + Sync>> {
+        // Copied out so the settings aren't locked while the request is in flight
+        let (perplexity_config, cache) = {
+            let settings_read = self.settings.read().await;
+            let perplexity_config = settings_read.perplexity.clone()
+                .ok_or("Perplexity settings not configured")?;
+            (perplexity_config, AiCache::from_settings(settings_read.ai_cache.as_ref()))
+        };
+        let api_url = perplexity_config.api_url.as_deref()
+            .filter(|u| !u.is_empty())
+            .ok_or("Perplexity API URL not configured")?;
+        let api_key = perplexity_config.api_key.as_deref()
+            .filter(|k| !k.is_empty())
+            .ok_or("Perplexity API Key not configured")?;
+        let model = perplexity_config.model.as_deref().ok_or("Perplexity model not configured")?;
+
+        let title = file_name.trim_end_matches(".md");
+        let excerpt: String = content.chars().take(MAX_ENRICHMENT_CHARS).collect();
+        let request = serde_json::json!({
+            "model": model,
+            "messages": [
+                { "role": "system", "content": ENRICHMENT_PROMPT },
+                { "role": "user", "content": format!("Title: {}\n\n{}", title, excerpt) }
+            ],
+            "max_tokens": perplexity_config.max_tokens.unwrap_or(4096).min(512),
+            "temperature": perplexity_config.temperature.unwrap_or(0.5),
+            "top_p": perplexity_config.top_p.unwrap_or(0.9),
+        });
+
+        let body = self.post_cached(api_url, api_key, model, &request, cache, mode).await?;
+
+        // Chat-completions shape: choices[0].message.content plus a top-level citations array
+        let body: serde_json::Value = serde_json::from_str(&body)?;
+        let summary = body["choices"][0]["message"]["content"].as_str()
+            .ok_or("Perplexity response missing message content")?
+            .trim()
+            .to_string();
+        let link = body["citations"][0].as_str().unwrap_or_default().to_string();
+
+        Ok(Enrichment { summary, link })
+    }
+
+    /// Files that need enrichment: never processed, or modified since the last run.
+    /// `requested` restricts the selection to the given file names.
+    pub fn select_for_enrichment(metadata: &MetadataStore, requested: Option<&[String]>, force: bool) -> Vec<String> {
+        let mut selected: Vec<String> = metadata.iter()
+            .filter(|(name, _)| match requested {
+                Some(requested) => requested.contains(name),
+                None => true,
+            })
+            .filter(|(_, meta)| force || match meta.last_perplexity_process {
+                Some(processed) => processed < meta.last_modified,
+                None => true,
+            })
+            .map(|(name, _)| name.clone())
+            .collect();
+        selected.sort();
+        selected
+    }
+
+    /// Enrich the given files in small batches, reporting progress on the event bus.
+    /// Returns the successful enrichments; failures are logged and counted. Notes
+    /// enriched before with the same content and settings come from the AI cache.
+    /// The run pauses before the next batch once the Perplexity budget is spent.
+    pub async fn run_enrichment(&self, file_names: Vec<String>, event_bus: &EventBus, job: &JobContext, mode: CacheMode) -> Vec<(String, Enrichment)> {
+        let total = file_names.len();
+        event_bus.publish(EnrichmentEvent::Started { total });
+        info!("Starting Perplexity enrichment of {} files", total);
+
+        let mut results = Vec::new();
+        let mut processed = 0;
+        let mut failed = 0;
+
+        for batch in file_names.chunks(ENRICHMENT_BATCH_SIZE) {
+            if job.is_cancelled() {
+                info!("Perplexity enrichment cancelled after {} of {} files", processed, total);
+                break;
+            }
+            if let Err(e) = ai_usage::check(resilience::PERPLEXITY) {
+                info!("Perplexity enrichment paused after {} of {} files: {}", processed, total, e);
+                event_bus.publish(EnrichmentEvent::Paused { processed, total, reason: e.to_string() });
+                break;
+            }
+            let futures = batch.iter().map(|file_name| async move {
+                let path = DataDirs::global().markdown_file(file_name);
+                let outcome = match tokio::fs::read(&path).await.and_then(|data| VaultCipher::global()?.decode_string(&data)) {
+                    Ok(content) => self.enrich_file(file_name, &content, mode).await,
+                    Err(e) => Err(format!("Failed to read {}: {}", path.display(), e).into()),
+                };
+                (file_name.clone(), outcome)
+            });
+
+            for (file_name, outcome) in futures::future::join_all(futures).await {
+                processed += 1;
+                let success = outcome.is_ok();
+                match outcome {
+                    Ok(enrichment) => {
+                        debug!("Enriched {} ({} chars)", file_name, enrichment.summary.len());
+                        results.push((file_name.clone(), enrichment));
+                    }
+                    Err(e) => {
+                        warn!("Failed to enrich {}: {}", file_name, e);
+                        failed += 1;
+                    }
+                }
+                job.report_progress(processed, total, Some(file_name.clone()));
+                event_bus.publish(EnrichmentEvent::Progress { processed, total, file_name, success });
+            }
+
+            if processed < total {
+                tokio::time::sleep(ENRICHMENT_BATCH_DELAY).await;
+            }
+        }
+
+        info!("Perplexity enrichment finished: {} enriched, {} failed", results.len(), failed);
+        event_bus.publish(EnrichmentEvent::Completed { enriched: results.len(), failed });
+        results
+    }
 }
@@ -0,0 +1,112 @@
+//! Central position broadcaster
+//!
+//! A single task fetches the graph's position frame once per tick and hands it to
+//! `ClientManagerActor`, which fans it out to every WebSocket session. Sessions only
+//! filter the shared frame against what they last sent, so the cost of reading and
+//! encoding the graph no longer grows with the number of connected clients.
+//!
+//! When recording is on, each new frame is also appended to the replay ring buffer,
+//! even while no one is connected.
+
+use actix::Addr;
+use chrono::Utc;
+use log::{debug, error, info, warn};
+use std::time::Duration;
+use tokio::time::MissedTickBehavior;
+
+use crate::actors::client_manager_actor::ClientManagerActor;
+use crate::actors::graph_actor::GraphServiceActor;
+use crate::actors::messages::{BroadcastPositionFrame, GetClientCount, GetPositionFrame, GetSettingByPath};
+use crate::actors::settings_actor::SettingsActor;
+use crate::services::replay::ReplayRecorder;
+use crate::utils::binary_protocol::EncodedFrame;
+
+/// Spawn the broadcast loop, ticking `update_rate` times per second and passing
+/// frames to `recorder` if given
+pub fn start(
+    graph_service_addr: Addr<GraphServiceActor>,
+    settings_addr: Addr<SettingsActor>,
+    client_manager_addr: Addr<ClientManagerActor>,
+    update_rate: u32,
+    mut recorder: Option<ReplayRecorder>,
+) {
+    let tick = Duration::from_millis(1000 / u64::from(update_rate.max(1)));
+    info!("[Broadcaster] Broadcasting positions every {:?}", tick);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(tick);
+        // A slow tick shouldn't be followed by a burst of catch-up frames
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+
+        loop {
+            interval.tick().await;
+
+            let has_clients = match client_manager_addr.send(GetClientCount).await {
+                Ok(Ok(count)) => count > 0,
+                Ok(Err(_)) => true,
+                Err(e) => {
+                    error!("[Broadcaster] ClientManagerActor unavailable, stopping: {}", e);
+                    break;
+                }
+            };
+            if !has_clients && recorder.is_none() {
+                continue;
+            }
+
+            let frame = match graph_service_addr.send(GetPositionFrame).await {
+                Ok(Ok(frame)) => frame,
+                Ok(Err(e)) => {
+                    error!("[Broadcaster] Failed to get position frame: {}", e);
+                    continue;
+                }
+                Err(e) => {
+                    error!("[Broadcaster] GraphServiceActor unavailable, stopping: {}", e);
+                    break;
+                }
+            };
+            if frame.is_empty() {
+                continue;
+            }
+            if let Some(recorder) = recorder.as_mut() {
+                if let Err(e) = recorder.record(&frame, Utc::now().timestamp_millis().max(0) as u64) {
+                    warn!("[Broadcaster] Failed to record frame {}: {}", frame.sequence(), e);
+                }
+            }
+            if !has_clients {
+                continue;
+            }
+
+            let detailed_debug = setting_enabled(&settings_addr, "system.debug.enabled").await
+                && setting_enabled(&settings_addr, "system.debug.enable_websocket_debug").await;
+            let kinetic_energy = mean_kinetic_energy(&frame);
+            if detailed_debug {
+                debug!("[Broadcaster] Frame {}: {} nodes, kinetic energy {:.5}",
+                    frame.sequence(), frame.node_count(), kinetic_energy);
+            }
+
+            client_manager_addr.do_send(BroadcastPositionFrame { frame, kinetic_energy, detailed_debug });
+        }
+    });
+}
+
+/// Mean of ½|v|² over the frame's nodes, treating every node as unit mass
+fn mean_kinetic_energy(frame: &EncodedFrame) -> f32 {
+    let count = frame.node_count();
+    if count == 0 {
+        return 0.0;
+    }
+    let total: f32 = frame.items()
+        .map(|item| {
+            let v = item.velocity;
+            0.5 * (v.x * v.x + v.y * v.y + v.z * v.z)
+        })
+        .sum();
+    total / count as f32
+}
+
+async fn setting_enabled(settings_addr: &Addr<SettingsActor>, path: &str) -> bool {
+    match settings_addr.send(GetSettingByPath { path: path.to_string() }).await {
+        Ok(Ok(value)) => value.as_bool().unwrap_or(false),
+        _ => false,
+    }
+}
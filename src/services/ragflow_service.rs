@@ -1,6 +1,9 @@
 use reqwest::{Client, StatusCode};
 use log::{error, info};
 use crate::config::AppFullSettings; // Use AppFullSettings, ConfigRagFlowSettings removed
+use crate::services::ai_cache::{AiCache, CacheMode};
+use crate::services::ai_usage::{self, BudgetExceeded};
+use crate::services::resilience::{self, Dependency, ResilienceError};
 use std::fmt;
 use futures::stream::{Stream, StreamExt};
 use std::pin::Pin;
@@ -14,6 +17,10 @@ pub enum RAGFlowError {
     StatusError(StatusCode, String),
     ParseError(String),
     IoError(std::io::Error),
+    /// The circuit is open or the timeout budget ran out
+    Unavailable(String),
+    /// Today's AI budget is spent
+    OverBudget(BudgetExceeded),
 }
 
 impl fmt::Display for RAGFlowError {
@@ -23,6 +30,8 @@ impl fmt::Display for RAGFlowError {
             RAGFlowError::StatusError(status, msg) => write!(f, "Status error ({}): {}", status, msg),
             RAGFlowError::ParseError(msg) => write!(f, "Parse error: {}", msg),
             RAGFlowError::IoError(e) => write!(f, "IO error: {}", e),
+            RAGFlowError::Unavailable(msg) => write!(f, "RAGFlow unavailable: {}", msg),
+            RAGFlowError::OverBudget(e) => write!(f, "{}", e),
         }
     }
 }
@@ -35,6 +44,21 @@ impl From<reqwest::Error> for RAGFlowError {
     }
 }
 
+impl From<ResilienceError> for RAGFlowError {
+    fn from(err: ResilienceError) -> Self {
+        match err {
+            ResilienceError::Request(e) => RAGFlowError::ReqwestError(e),
+            other => RAGFlowError::Unavailable(other.to_string()),
+        }
+    }
+}
+
+impl From<BudgetExceeded> for RAGFlowError {
+    fn from(err: BudgetExceeded) -> Self {
+        RAGFlowError::OverBudget(err)
+    }
+}
+
 impl From<std::io::Error> for RAGFlowError {
     fn from(err: std::io::Error) -> Self {
         RAGFlowError::IoError(err)
@@ -92,6 +116,7 @@ pub struct RAGFlowService {
     api_key: String,
     base_url: String,
     agent_id: String,
+    dependency: Arc<Dependency>,
 }
 
 impl RAGFlowService {
@@ -145,6 +170,7 @@ impl RAGFlowService {
             api_key,
             base_url,
             agent_id,
+            dependency: resilience::dependency(resilience::RAGFLOW),
         })
     }
 
@@ -158,12 +184,12 @@ impl RAGFlowService {
         );
         info!("Full URL for create_session: {}", url);
         
-        let response = self.client.post(&url)
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
-            .body("{}")  // Empty JSON body as we don't have any Begin parameters
-            .send()
-            .await?;
+        let response = self.dependency.send(|| {
+            self.client.post(&url)
+                .header("Authorization", format!("Bearer {}", self.api_key))
+                .header("Content-Type", "application/json")
+                .body("{}")  // Empty JSON body as we don't have any Begin parameters
+        }).await?;
 
         let status = response.status();
         info!("Response status: {}", status);
@@ -202,6 +228,9 @@ impl RAGFlowService {
             self.agent_id
         );
         info!("Full URL for send_message: {}", url);
+        ai_usage::check(resilience::RAGFLOW)?;
+        // Streamed answers go straight to the client, so only the question is counted
+        let question_tokens = ai_usage::estimate_tokens(&message);
         
         let request_body = CompletionRequest {
             question: message,
@@ -213,17 +242,18 @@ impl RAGFlowService {
 
         info!("Request body: {:?}", serde_json::to_string(&request_body).unwrap_or_default());
 
-        let response = self.client.post(&url)
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
-            .json(&request_body)
-            .send()
-            .await?;
+        let response = self.dependency.send(|| {
+            self.client.post(&url)
+                .header("Authorization", format!("Bearer {}", self.api_key))
+                .header("Content-Type", "application/json")
+                .json(&request_body)
+        }).await?;
 
         let status = response.status();
         info!("Response status: {}", status);
        
         if status.is_success() {
+            ai_usage::record(resilience::RAGFLOW, question_tokens, 0);
             if stream {
                 let stream = response.bytes_stream().map(move |chunk_result| {
                     match chunk_result {
@@ -283,10 +313,10 @@ impl RAGFlowService {
             session_id
         );
         
-        let response = self.client.get(&url)
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .send()
-            .await?;
+        let response = self.dependency.send(|| {
+            self.client.get(&url)
+                .header("Authorization", format!("Bearer {}", self.api_key))
+        }).await?;
 
         let status = response.status();
         if status.is_success() {
@@ -320,12 +350,12 @@ impl RAGFlowService {
             sync_dsl: Some(false),
         };
 
-        let response = self.client.post(&url)
-            .header("Authorization", format!("Bearer {}", self.api_key))
-            .header("Content-Type", "application/json")
-            .json(&request_body)
-            .send()
-            .await?;
+        let response = self.dependency.send(|| {
+            self.client.post(&url)
+                .header("Authorization", format!("Bearer {}", self.api_key))
+                .header("Content-Type", "application/json")
+                .json(&request_body)
+        }).await?;
 
         let status = response.status();
         if !status.is_success() {
@@ -404,6 +434,34 @@ impl RAGFlowService {
             Ok((full_answer, session_id))
         }
     }
+
+    /// `send_chat_message`, answering from `cache` when the agent was asked the same
+    /// question before. The answer keeps the caller's session. Questions that reach
+    /// the agent count towards the AI budget and are refused once it is spent.
+    pub async fn send_cached_chat_message(
+        &self,
+        session_id: String,
+        message: String,
+        stream_preference: bool,
+        cache: Option<&AiCache>,
+        mode: CacheMode,
+    ) -> Result<(String, String), RAGFlowError> {
+        let key = AiCache::key(resilience::RAGFLOW, &self.agent_id, &message);
+        if let Some(answer) = cache.and_then(|cache| cache.get(&key, mode)) {
+            info!("Answering RAGFlow session {} from the AI cache", session_id);
+            return Ok((answer, session_id));
+        }
+        ai_usage::check(resilience::RAGFLOW)?;
+        let question_tokens = ai_usage::estimate_tokens(&message);
+        let (answer, session_id) = self.send_chat_message(session_id, message, stream_preference).await?;
+        ai_usage::record(resilience::RAGFLOW, question_tokens, ai_usage::estimate_tokens(&answer));
+        if let Some(cache) = cache {
+            if let Err(e) = cache.put(&key, resilience::RAGFLOW, &self.agent_id, &answer, mode) {
+                log::warn!("Failed to cache RAGFlow answer: {}", e);
+            }
+        }
+        Ok((answer, session_id))
+    }
 } // This closing brace now correctly closes impl RAGFlowService
 
 impl Clone for RAGFlowService {
@@ -413,6 +471,7 @@ impl Clone for RAGFlowService {
             api_key: self.api_key.clone(),
             base_url: self.base_url.clone(),
             agent_id: self.agent_id.clone(),
+            dependency: self.dependency.clone(),
         }
     }
 }
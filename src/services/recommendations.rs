@@ -0,0 +1,278 @@
+//! "What to read next" suggestions
+//!
+//! Pages are ranked by a mix of three scores, each between 0 and 1: PageRank over
+//! the graph's edges (how central the page is), recency of its last change (halving
+//! every 30 days) and the cosine similarity of its text to the pages the reader
+//! started from. Text is embedded by hashing its words into a fixed-size
+//! term-frequency vector, which needs no model and is cheap enough to keep per
+//! page, recomputed only when a page's content changes.
+//!
+//! The starting pages are either one given node or the reader's recent selections,
+//! which clients report under a session id of their choosing.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
+
+use crate::config::data_dirs::DataDirs;
+use crate::models::graph::GraphData;
+use crate::models::metadata::MetadataStore;
+use crate::services::vault_crypto;
+
+const EMBEDDING_DIMS: usize = 256;
+const DAMPING: f32 = 0.85;
+const ITERATIONS: usize = 30;
+const RECENCY_HALF_LIFE_DAYS: f32 = 30.0;
+const RANK_WEIGHT: f32 = 0.3;
+const RECENCY_WEIGHT: f32 = 0.2;
+const SIMILARITY_WEIGHT: f32 = 0.5;
+/// Selections remembered per session
+pub const MAX_SELECTIONS: usize = 20;
+// Oldest sessions are forgotten beyond this
+const MAX_SESSIONS: usize = 10_000;
+
+/// PageRank of every node, scaled so the most central node has 1. Edges count in
+/// both directions, weighted by their weight.
+pub fn page_rank(graph: &GraphData) -> HashMap<u32, f32> {
+    let ids: Vec<u32> = graph.nodes.iter().map(|node| node.id).collect();
+    let index: HashMap<u32, usize> = ids.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+    let n = ids.len();
+    if n == 0 {
+        return HashMap::new();
+    }
+    let mut links: Vec<Vec<(usize, f32)>> = vec![Vec::new(); n];
+    for edge in &graph.edges {
+        if let (Some(&source), Some(&target)) = (index.get(&edge.source), index.get(&edge.target)) {
+            let weight = edge.weight.max(0.0);
+            if source != target && weight > 0.0 {
+                links[source].push((target, weight));
+                links[target].push((source, weight));
+            }
+        }
+    }
+    let out_weight: Vec<f32> = links.iter().map(|out| out.iter().map(|(_, weight)| weight).sum()).collect();
+
+    let mut rank = vec![1.0 / n as f32; n];
+    for _ in 0..ITERATIONS {
+        // Nodes without edges share their rank with everyone
+        let dangling: f32 = (0..n).filter(|&i| out_weight[i] == 0.0).map(|i| rank[i]).sum();
+        let mut next = vec![(1.0 - DAMPING) / n as f32 + DAMPING * dangling / n as f32; n];
+        for (i, out) in links.iter().enumerate() {
+            for (target, weight) in out {
+                next[*target] += DAMPING * rank[i] * weight / out_weight[i];
+            }
+        }
+        rank = next;
+    }
+    let max = rank.iter().cloned().fold(f32::MIN_POSITIVE, f32::max);
+    ids.into_iter().zip(rank).map(|(id, rank)| (id, rank / max)).collect()
+}
+
+/// 1 for a page changed now, halving every 30 days
+pub fn recency(modified: DateTime<Utc>, now: DateTime<Utc>) -> f32 {
+    let age_days = (now - modified).num_seconds().max(0) as f32 / 86_400.0;
+    0.5f32.powf(age_days / RECENCY_HALF_LIFE_DAYS)
+}
+
+/// Hashed term frequencies of the words in `content`, scaled to unit length
+pub fn embed(content: &str) -> Vec<f32> {
+    let mut vector = vec![0.0f32; EMBEDDING_DIMS];
+    for word in content.split(|c: char| !c.is_alphanumeric()).filter(|word| word.chars().count() > 2) {
+        let mut hasher = DefaultHasher::new();
+        word.to_lowercase().hash(&mut hasher);
+        vector[(hasher.finish() % EMBEDDING_DIMS as u64) as usize] += 1.0;
+    }
+    // Damped, so a word repeated many times doesn't drown out the rest
+    vector.iter_mut().for_each(|count| *count = count.ln_1p());
+    normalize(&mut vector);
+    vector
+}
+
+fn normalize(vector: &mut [f32]) {
+    let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|x| *x /= norm);
+    }
+}
+
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>().max(0.0)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Recommendation {
+    pub node_id: u32,
+    pub score: f32,
+    pub page_rank: f32,
+    pub recency: f32,
+    /// 0 when there were no starting pages
+    pub similarity: f32,
+}
+
+/// Ranks the pages in `graph` other than `seeds`, best first
+pub fn rank(
+    graph: &GraphData,
+    ranks: &HashMap<u32, f32>,
+    embeddings: &HashMap<String, Vec<f32>>,
+    seeds: &[u32],
+    now: DateTime<Utc>,
+    limit: usize,
+) -> Vec<Recommendation> {
+    let file_name = |metadata_id: &str| format!("{}.md", metadata_id);
+    let seed_set: HashSet<u32> = seeds.iter().copied().collect();
+    let mut profile = vec![0.0f32; EMBEDDING_DIMS];
+    for node in graph.nodes.iter().filter(|node| seed_set.contains(&node.id)) {
+        if let Some(embedding) = embeddings.get(&file_name(&node.metadata_id)) {
+            profile.iter_mut().zip(embedding).for_each(|(sum, x)| *sum += x);
+        }
+    }
+    normalize(&mut profile);
+
+    let mut recommendations: Vec<Recommendation> = graph.nodes.iter()
+        .filter(|node| node.is_page() && !seed_set.contains(&node.id))
+        .filter_map(|node| {
+            let file_name = file_name(&node.metadata_id);
+            let metadata = graph.metadata.get(&file_name)?;
+            let page_rank = ranks.get(&node.id).copied().unwrap_or_default();
+            let recency = recency(metadata.last_modified, now);
+            let similarity = embeddings.get(&file_name).map_or(0.0, |embedding| cosine(&profile, embedding));
+            Some(Recommendation {
+                node_id: node.id,
+                score: RANK_WEIGHT * page_rank + RECENCY_WEIGHT * recency + SIMILARITY_WEIGHT * similarity,
+                page_rank,
+                recency,
+                similarity,
+            })
+        })
+        .collect();
+    recommendations.sort_by(|a, b| b.score.total_cmp(&a.score).then_with(|| a.node_id.cmp(&b.node_id)));
+    recommendations.truncate(limit);
+    recommendations
+}
+
+fn edge_fingerprint(graph: &GraphData) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    graph.nodes.len().hash(&mut hasher);
+    for edge in &graph.edges {
+        (edge.source, edge.target, edge.weight.to_bits()).hash(&mut hasher);
+    }
+    hasher.finish()
+}
+
+type Ranks = Arc<HashMap<u32, f32>>;
+// When the session last selected something, and its selections newest first
+type SessionSelections = (DateTime<Utc>, VecDeque<u32>);
+
+/// Caches what recommendations are computed from and the sessions' selections
+#[derive(Default)]
+pub struct Recommender {
+    // Recomputed only when the edges change, not on every position update
+    ranks: Mutex<Option<(u64, Ranks)>>,
+    // File name to the sha1 the embedding was made from, and the embedding
+    embeddings: Mutex<HashMap<String, (String, Vec<f32>)>>,
+    selections: Mutex<HashMap<String, SessionSelections>>,
+}
+
+impl Recommender {
+    pub fn ranks(&self, graph: &GraphData) -> Ranks {
+        let fingerprint = edge_fingerprint(graph);
+        let mut cached = self.ranks.lock().unwrap();
+        match cached.as_ref() {
+            Some((cached_fingerprint, ranks)) if *cached_fingerprint == fingerprint => Arc::clone(ranks),
+            _ => {
+                let ranks = Arc::new(page_rank(graph));
+                *cached = Some((fingerprint, Arc::clone(&ranks)));
+                ranks
+            }
+        }
+    }
+
+    /// Embeddings of every page in `metadata`, reading only pages that changed since
+    /// they were last embedded. Blocks on file reads.
+    pub fn embeddings(&self, metadata: &MetadataStore) -> HashMap<String, Vec<f32>> {
+        let dirs = DataDirs::global();
+        let mut cache = self.embeddings.lock().unwrap();
+        cache.retain(|file_name, _| metadata.contains_key(file_name));
+        for (file_name, page) in metadata {
+            if cache.get(file_name).is_some_and(|(sha1, _)| *sha1 == page.sha1) {
+                continue;
+            }
+            if let Ok(content) = vault_crypto::read_to_string(dirs.markdown_file(file_name)) {
+                cache.insert(file_name.clone(), (page.sha1.clone(), embed(&content)));
+            }
+        }
+        cache.iter().map(|(file_name, (_, embedding))| (file_name.clone(), embedding.clone())).collect()
+    }
+
+    /// Remembers that `session` selected `node_id` and returns its selections, newest first
+    pub fn select(&self, session: &str, node_id: u32) -> Vec<u32> {
+        let mut sessions = self.selections.lock().unwrap();
+        if !sessions.contains_key(session) && sessions.len() >= MAX_SESSIONS {
+            if let Some(oldest) = sessions.iter().min_by_key(|(_, (used, _))| *used).map(|(key, _)| key.clone()) {
+                sessions.remove(&oldest);
+            }
+        }
+        let (used, selected) = sessions.entry(session.to_string()).or_default();
+        *used = Utc::now();
+        selected.retain(|id| *id != node_id);
+        selected.push_front(node_id);
+        selected.truncate(MAX_SELECTIONS);
+        selected.iter().copied().collect()
+    }
+
+    pub fn selections(&self, session: &str) -> Vec<u32> {
+        self.selections.lock().unwrap().get(session)
+            .map(|(_, selected)| selected.iter().copied().collect())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::edge::Edge;
+    use crate::models::metadata::Metadata;
+    use crate::models::node::Node;
+    use chrono::Duration;
+
+    #[test]
+    fn test_recommendations() {
+        let now = Utc::now();
+        let mut graph = GraphData::new();
+        let mut embeddings = HashMap::new();
+        for (id, name, age_days, content) in [
+            (1, "Rust", 0, "ownership borrowing lifetimes traits"),
+            (2, "Borrowing", 60, "borrowing lifetimes references ownership traits"),
+            (3, "Gardening", 0, "tomatoes compost soil watering"),
+            (4, "Hub", 0, "index of everything"),
+        ] {
+            let file_name = format!("{}.md", name);
+            graph.nodes.push(Node::new_with_id(name.to_string(), Some(id)));
+            graph.metadata.insert(file_name.clone(), Metadata { last_modified: now - Duration::days(age_days), ..Default::default() });
+            embeddings.insert(file_name, embed(content));
+        }
+        for source in [1, 2, 3] {
+            graph.edges.push(Edge::new(source, 4, 1.0));
+        }
+
+        let ranks = page_rank(&graph);
+        assert_eq!(ranks[&4], 1.0);
+        assert!(ranks[&1] < 1.0);
+
+        // Similar text outweighs recent changes, and the hub outranks the other leaf
+        let from_rust = rank(&graph, &ranks, &embeddings, &[1], now, 3);
+        assert_eq!(from_rust.iter().map(|r| r.node_id).collect::<Vec<_>>(), vec![2, 4, 3]);
+        assert!(from_rust[0].similarity > 0.5 && from_rust[2].similarity == 0.0);
+        assert!(from_rust[0].recency < from_rust[2].recency);
+
+        let recommender = Recommender::default();
+        recommender.select("session", 1);
+        assert_eq!(recommender.select("session", 3), vec![3, 1]);
+        assert_eq!(recommender.select("session", 1), vec![1, 3]);
+        assert!(recommender.selections("other").is_empty());
+    }
+}
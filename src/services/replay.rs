@@ -0,0 +1,269 @@
+//! Recording and replaying position frames
+//!
+//! With `REPLAY_RECORDING_FRAMES` set, the position broadcaster also appends every
+//! new frame to a ring buffer in `/app/data/replays`, keeping about that many of
+//! the latest frames. The buffer is a run of segment files of `SEGMENT_FRAMES`
+//! frames each; starting a segment deletes the oldest ones past the limit, so
+//! nothing already on disk is rewritten. A recording can be downloaded from
+//! `GET /api/graph/replay.bin` or streamed to a WebSocket client at its recorded
+//! pace, which makes a settling sequence repeatable for demos, layout regression
+//! hunts and client tests without a GPU.
+
+use bytes::Bytes;
+use log::{debug, info};
+use serde::Serialize;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::utils::binary_protocol::{EncodedFrame, WireNodeDataItem};
+
+/// Frames per segment file, about five seconds at 60 frames a second
+pub const SEGMENT_FRAMES: usize = 300;
+// Each record is a u64 timestamp in ms and a u32 length, little endian, then the frame
+const RECORD_HEADER: usize = 12;
+// Gaps longer than this, e.g. across a restart, are shortened when playing back
+const MAX_FRAME_GAP: Duration = Duration::from_secs(1);
+
+/// A frame as recorded: its float wire records and when it was broadcast
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedFrame {
+    pub timestamp_ms: u64,
+    pub bytes: Bytes,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplaySummary {
+    pub frame_count: usize,
+    pub started_at: Option<u64>,
+    pub ended_at: Option<u64>,
+    /// Playback time at normal speed, with long gaps shortened
+    pub duration_ms: u64,
+    pub max_node_count: usize,
+}
+
+impl ReplaySummary {
+    pub fn new(frames: &[RecordedFrame]) -> Self {
+        Self {
+            frame_count: frames.len(),
+            started_at: frames.first().map(|frame| frame.timestamp_ms),
+            ended_at: frames.last().map(|frame| frame.timestamp_ms),
+            duration_ms: frames.windows(2).map(|pair| frame_delay(&pair[0], &pair[1], 1.0).as_millis() as u64).sum(),
+            max_node_count: frames.iter()
+                .map(|frame| frame.bytes.len() / std::mem::size_of::<WireNodeDataItem>())
+                .max()
+                .unwrap_or(0),
+        }
+    }
+}
+
+/// How long to wait between playing `previous` and `next` at `speed` times the
+/// recorded pace
+pub fn frame_delay(previous: &RecordedFrame, next: &RecordedFrame, speed: f32) -> Duration {
+    let gap = Duration::from_millis(next.timestamp_ms.saturating_sub(previous.timestamp_ms)).min(MAX_FRAME_GAP);
+    Duration::from_micros((gap.as_micros() as f64 / f64::from(speed)).round() as u64)
+}
+
+/// Appends broadcast frames to the on-disk ring buffer
+#[derive(Debug)]
+pub struct ReplayRecorder {
+    dir: PathBuf,
+    max_segments: usize,
+    // Open segment, its index and the frames written to it
+    segment: Option<(u64, File, usize)>,
+    last_sequence: Option<u64>,
+}
+
+impl ReplayRecorder {
+    /// Keeps about `capacity` frames in `dir`, in whole segments. Nothing is written
+    /// until the first frame.
+    pub fn new(dir: impl Into<PathBuf>, capacity: usize) -> Self {
+        Self {
+            dir: dir.into(),
+            max_segments: capacity.div_ceil(SEGMENT_FRAMES).max(1),
+            segment: None,
+            last_sequence: None,
+        }
+    }
+
+    /// A recorder for the data directory's replays if `REPLAY_RECORDING_FRAMES` is
+    /// set above zero
+    pub fn from_env() -> Option<Self> {
+        let capacity: usize = std::env::var("REPLAY_RECORDING_FRAMES").ok()?.trim().parse().ok()?;
+        if capacity == 0 {
+            return None;
+        }
+        let dir = crate::config::data_dirs::DataDirs::global().replays();
+        info!("[Replay] Recording the latest {} position frames to {}", capacity, dir.display());
+        Some(Self::new(dir, capacity))
+    }
+
+    /// Appends `frame` unless it is empty or was the last one recorded. Returns
+    /// whether it was written.
+    pub fn record(&mut self, frame: &EncodedFrame, timestamp_ms: u64) -> io::Result<bool> {
+        if frame.is_empty() || self.last_sequence == Some(frame.sequence()) {
+            return Ok(false);
+        }
+        let full = self.segment.as_ref().is_none_or(|(_, _, frames)| *frames >= SEGMENT_FRAMES);
+        if full {
+            self.start_segment()?;
+        }
+        let Some((_, file, frames)) = self.segment.as_mut() else {
+            return Ok(false);
+        };
+
+        let bytes = frame.bytes();
+        let mut record = Vec::with_capacity(RECORD_HEADER + bytes.len());
+        record.extend_from_slice(&timestamp_ms.to_le_bytes());
+        record.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+        record.extend_from_slice(&bytes);
+        file.write_all(&record)?;
+        *frames += 1;
+        self.last_sequence = Some(frame.sequence());
+        Ok(true)
+    }
+
+    /// Opens the segment after the newest on disk and deletes the oldest past the limit
+    fn start_segment(&mut self) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let mut segments = segment_files(&self.dir)?;
+        let index = match (&self.segment, segments.last()) {
+            (Some((current, _, _)), _) => current + 1,
+            (None, Some((newest, _))) => newest + 1,
+            (None, None) => 0,
+        };
+        let path = self.dir.join(segment_name(index));
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        debug!("[Replay] Recording to {}", path.display());
+        self.segment = Some((index, file, 0));
+
+        segments.push((index, path));
+        let excess = segments.len().saturating_sub(self.max_segments);
+        for (_, old) in &segments[..excess] {
+            fs::remove_file(old)?;
+        }
+        Ok(())
+    }
+}
+
+fn segment_name(index: u64) -> String {
+    format!("frames-{:010}.bin", index)
+}
+
+/// Segment files in `dir`, oldest first
+fn segment_files(dir: &Path) -> io::Result<Vec<(u64, PathBuf)>> {
+    let mut segments: Vec<(u64, PathBuf)> = fs::read_dir(dir)?
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            let name = path.file_name()?.to_str()?;
+            let index = name.strip_prefix("frames-")?.strip_suffix(".bin")?.parse().ok()?;
+            Some((index, path))
+        })
+        .collect();
+    segments.sort();
+    Ok(segments)
+}
+
+/// Every frame in `dir`, oldest first. A record cut short, e.g. one still being
+/// written, ends its segment. A missing directory is an empty recording.
+pub fn read_recording(dir: &Path) -> io::Result<Vec<RecordedFrame>> {
+    let segments = match segment_files(dir) {
+        Ok(segments) => segments,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut frames = Vec::new();
+    for (_, path) in segments {
+        // The recorder may have deleted the segment since it was listed
+        let data = match fs::read(&path) {
+            Ok(data) => Bytes::from(data),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        let mut offset = 0;
+        while data.len() - offset >= RECORD_HEADER {
+            let timestamp_ms = u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap_or_default());
+            let len = u32::from_le_bytes(data[offset + 8..offset + 12].try_into().unwrap_or_default()) as usize;
+            let start = offset + RECORD_HEADER;
+            if data.len() - start < len {
+                break;
+            }
+            frames.push(RecordedFrame { timestamp_ms, bytes: data.slice(start..start + len) });
+            offset = start + len;
+        }
+    }
+    Ok(frames)
+}
+
+/// Frames laid out as in the segment files, for download
+pub fn encode_recording(frames: &[RecordedFrame]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(frames.iter().map(|frame| RECORD_HEADER + frame.bytes.len()).sum());
+    for frame in frames {
+        out.extend_from_slice(&frame.timestamp_ms.to_le_bytes());
+        out.extend_from_slice(&(frame.bytes.len() as u32).to_le_bytes());
+        out.extend_from_slice(&frame.bytes);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::vec3::Vec3Data;
+    use crate::utils::binary_protocol::FrameEncoder;
+    use crate::utils::socket_flow_messages::BinaryNodeData;
+    use bytemuck::Zeroable;
+
+    fn frame(encoder: &mut FrameEncoder, x: f32) -> EncodedFrame {
+        let mut data = BinaryNodeData::zeroed();
+        data.position = Vec3Data::new(x, 0.0, 0.0);
+        encoder.encode(vec![(1, data), (2, data)])
+    }
+
+    #[test]
+    fn test_recording_ring_buffer() {
+        let dir = std::env::temp_dir().join(format!("replay_test_{}", uuid::Uuid::new_v4()));
+        let mut encoder = FrameEncoder::new();
+        let mut recorder = ReplayRecorder::new(&dir, SEGMENT_FRAMES * 2);
+
+        let first = frame(&mut encoder, 0.0);
+        assert!(recorder.record(&first, 1_000).unwrap());
+        // The same frame fetched again on the next tick is skipped
+        assert!(!recorder.record(&first, 1_016).unwrap());
+        for i in 1..SEGMENT_FRAMES * 3 {
+            recorder.record(&frame(&mut encoder, i as f32), 1_000 + i as u64 * 16).unwrap();
+        }
+
+        // Only the two newest segments are kept
+        let frames = read_recording(&dir).unwrap();
+        assert_eq!(frames.len(), SEGMENT_FRAMES * 2);
+        assert_eq!(frames[0].timestamp_ms, 1_000 + SEGMENT_FRAMES as u64 * 16);
+        let replayed = EncodedFrame::from_wire(frames[0].bytes.clone(), 1).unwrap();
+        assert_eq!(replayed.item(0).position.x, SEGMENT_FRAMES as f32);
+
+        // A record cut short is dropped rather than failing the read
+        let (_, newest) = segment_files(&dir).unwrap().pop().unwrap();
+        let mut file = OpenOptions::new().append(true).open(newest).unwrap();
+        file.write_all(&[1, 2, 3, 4, 5, 6, 7, 8, 200, 0, 0, 0, 9]).unwrap();
+        assert_eq!(read_recording(&dir).unwrap(), frames);
+
+        let summary = ReplaySummary::new(&frames);
+        assert_eq!(summary.max_node_count, 2);
+        assert_eq!(summary.duration_ms, (SEGMENT_FRAMES as u64 * 2 - 1) * 16);
+        assert_eq!(encode_recording(&frames).len(), frames.len() * (RECORD_HEADER + 56));
+
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(read_recording(&dir).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_frame_delay() {
+        let at = |timestamp_ms| RecordedFrame { timestamp_ms, bytes: Bytes::new() };
+        assert_eq!(frame_delay(&at(100), &at(116), 1.0), Duration::from_millis(16));
+        assert_eq!(frame_delay(&at(100), &at(120), 2.0), Duration::from_millis(10));
+        assert_eq!(frame_delay(&at(100), &at(60_000), 1.0), MAX_FRAME_GAP);
+        assert_eq!(frame_delay(&at(100), &at(50), 1.0), Duration::ZERO);
+    }
+}
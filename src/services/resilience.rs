@@ -0,0 +1,413 @@
+//! Retries and circuit breaking for external HTTP services
+//!
+//! Calls to GitHub, Perplexity, RAGFlow and OpenAI go through a [`Dependency`]
+//! named after the service. A call that fails to connect, times out or gets a 5xx
+//! is retried with exponential backoff and full jitter, as long as the timeout
+//! budget allows. After `FAILURE_THRESHOLD` failed calls in a row the circuit
+//! opens and calls fail at once for `OPEN_FOR`; then one trial call is let through,
+//! and its outcome closes or reopens the circuit. Any other response, 4xx
+//! included, shows the service is up and is returned to the caller as it is.
+//!
+//! Budgets and retry counts come from the settings (see [`configure`]); the state
+//! of every dependency is reported on `/api/health`.
+
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use rand::Rng;
+use reqwest::{RequestBuilder, Response};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use crate::config::AppFullSettings;
+
+pub const GITHUB: &str = "github";
+pub const PERPLEXITY: &str = "perplexity";
+pub const RAGFLOW: &str = "ragflow";
+pub const OPENAI: &str = "openai";
+
+/// Failed calls in a row that open the circuit
+const FAILURE_THRESHOLD: u32 = 5;
+const OPEN_FOR: Duration = Duration::from_secs(30);
+const MAX_DELAY: Duration = Duration::from_secs(10);
+
+static DEPENDENCIES: OnceLock<Mutex<BTreeMap<&'static str, Arc<Dependency>>>> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Attempts after the first
+    pub max_retries: u32,
+    /// Upper bound of the first backoff, doubled for each retry
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+    /// Time all attempts of one call may take together
+    pub budget: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay: Duration::from_millis(500),
+            max_delay: MAX_DELAY,
+            budget: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The network settings' retries, with the service's own timeout as the budget
+    /// when it has one
+    fn from_settings(settings: &AppFullSettings, timeout_secs: Option<u64>, max_retries: Option<u32>) -> Self {
+        let network = &settings.system.network;
+        Self {
+            max_retries: max_retries.unwrap_or(network.max_retries),
+            base_delay: Duration::from_secs(network.retry_delay.max(1) as u64).min(MAX_DELAY),
+            max_delay: MAX_DELAY,
+            budget: Duration::from_secs(timeout_secs.unwrap_or(network.api_client_timeout).max(1)),
+        }
+    }
+
+    /// Full jitter: anywhere up to the doubled delay, so clients don't retry in step
+    fn backoff(&self, retry: u32) -> Duration {
+        let ceiling = self.base_delay.saturating_mul(1 << retry.min(16)).min(self.max_delay);
+        ceiling.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+    }
+}
+
+#[derive(Debug)]
+pub enum ResilienceError {
+    /// The circuit is open; no request was sent
+    Open { dependency: &'static str, retry_in: Duration },
+    /// The timeout budget ran out
+    Timeout { dependency: &'static str, budget: Duration },
+    Request(reqwest::Error),
+}
+
+impl fmt::Display for ResilienceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ResilienceError::Open { dependency, retry_in } =>
+                write!(f, "{} is unavailable; retry in {}s", dependency, retry_in.as_secs().max(1)),
+            ResilienceError::Timeout { dependency, budget } =>
+                write!(f, "{} did not respond within {}s", dependency, budget.as_secs()),
+            ResilienceError::Request(e) => write!(f, "Request failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ResilienceError {}
+
+impl From<reqwest::Error> for ResilienceError {
+    fn from(err: reqwest::Error) -> Self {
+        ResilienceError::Request(err)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+#[derive(Debug)]
+struct Breaker {
+    consecutive_failures: u32,
+    opened_at: Option<Instant>,
+    successes: u64,
+    failures: u64,
+    rejected: u64,
+    last_error: Option<String>,
+    last_failure: Option<DateTime<Utc>>,
+}
+
+/// State of one dependency for `/api/health`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DependencyHealth {
+    pub name: &'static str,
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+    pub successes: u64,
+    pub failures: u64,
+    /// Calls failed at once while the circuit was open
+    pub rejected: u64,
+    pub last_error: Option<String>,
+    pub last_failure: Option<DateTime<Utc>>,
+    /// Seconds until an open circuit lets a trial call through
+    pub retry_in_secs: Option<u64>,
+}
+
+/// An external service with its retry policy and circuit breaker
+#[derive(Debug)]
+pub struct Dependency {
+    name: &'static str,
+    policy: Mutex<RetryPolicy>,
+    breaker: Mutex<Breaker>,
+}
+
+impl Dependency {
+    pub fn new(name: &'static str, policy: RetryPolicy) -> Self {
+        Self {
+            name,
+            policy: Mutex::new(policy),
+            breaker: Mutex::new(Breaker {
+                consecutive_failures: 0,
+                opened_at: None,
+                successes: 0,
+                failures: 0,
+                rejected: 0,
+                last_error: None,
+                last_failure: None,
+            }),
+        }
+    }
+
+    pub fn policy(&self) -> RetryPolicy {
+        *self.policy.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn set_policy(&self, policy: RetryPolicy) {
+        *self.policy.lock().unwrap_or_else(|e| e.into_inner()) = policy;
+    }
+
+    fn breaker(&self) -> std::sync::MutexGuard<'_, Breaker> {
+        self.breaker.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn state(breaker: &Breaker) -> CircuitState {
+        match breaker.opened_at {
+            None => CircuitState::Closed,
+            Some(at) if at.elapsed() < OPEN_FOR => CircuitState::Open,
+            Some(_) => CircuitState::HalfOpen,
+        }
+    }
+
+    /// Lets a call through unless the circuit is open. The trial call of a half
+    /// open circuit restarts the open period, so the calls behind it wait for its
+    /// outcome, or for the next trial if it never finishes.
+    fn admit(&self) -> Result<(), ResilienceError> {
+        let mut breaker = self.breaker();
+        match (Self::state(&breaker), breaker.opened_at) {
+            (CircuitState::Closed, _) => Ok(()),
+            (CircuitState::HalfOpen, _) => {
+                breaker.opened_at = Some(Instant::now());
+                Ok(())
+            }
+            (CircuitState::Open, at) => {
+                breaker.rejected += 1;
+                let retry_in = at.map_or(Duration::ZERO, |at| OPEN_FOR.saturating_sub(at.elapsed()));
+                Err(ResilienceError::Open { dependency: self.name, retry_in })
+            }
+        }
+    }
+
+    fn succeeded(&self) {
+        let mut breaker = self.breaker();
+        if breaker.opened_at.is_some() {
+            info!("{} is reachable again; closing its circuit", self.name);
+        }
+        breaker.consecutive_failures = 0;
+        breaker.opened_at = None;
+        breaker.successes += 1;
+    }
+
+    fn failed(&self, error: String) {
+        let mut breaker = self.breaker();
+        breaker.consecutive_failures += 1;
+        breaker.failures += 1;
+        breaker.last_failure = Some(Utc::now());
+        // A failed trial reopens the circuit
+        if breaker.opened_at.is_some() || breaker.consecutive_failures == FAILURE_THRESHOLD {
+            warn!("Opening the circuit for {} for {}s after {} failures: {}",
+                self.name, OPEN_FOR.as_secs(), breaker.consecutive_failures, error);
+            breaker.opened_at = Some(Instant::now());
+        }
+        breaker.last_error = Some(error);
+    }
+
+    /// Runs `attempt` until it succeeds, the retries or the budget run out, or the
+    /// circuit opens. `is_failure` tells whether an outcome counts against the
+    /// dependency and may be retried; the last outcome is returned either way.
+    pub async fn call<T, E, F, Fut>(&self, mut attempt: F, is_failure: impl Fn(&Result<T, E>) -> Option<String>) -> Result<T, E>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: From<ResilienceError>,
+    {
+        let policy = self.policy();
+        let deadline = Instant::now() + policy.budget;
+        let mut retry = 0;
+        loop {
+            self.admit()?;
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let outcome = match tokio::time::timeout(remaining, attempt()).await {
+                Ok(outcome) => outcome,
+                Err(_) => {
+                    self.failed(format!("No response within {}s", policy.budget.as_secs()));
+                    return Err(ResilienceError::Timeout { dependency: self.name, budget: policy.budget }.into());
+                }
+            };
+            let Some(error) = is_failure(&outcome) else {
+                self.succeeded();
+                return outcome;
+            };
+            self.failed(error.clone());
+
+            let delay = policy.backoff(retry);
+            if retry >= policy.max_retries || Instant::now() + delay >= deadline {
+                return outcome;
+            }
+            retry += 1;
+            warn!("{} call failed ({}), retry {}/{} in {}ms", self.name, error, retry, policy.max_retries, delay.as_millis());
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Sends the request `build` makes. Connection errors and 5xx responses are
+    /// retried; the last response is returned whatever its status.
+    pub async fn send<F>(&self, build: F) -> Result<Response, ResilienceError>
+    where
+        F: Fn() -> RequestBuilder,
+    {
+        self.call(
+            || async { build().send().await.map_err(ResilienceError::from) },
+            |outcome| match outcome {
+                Ok(response) if response.status().is_server_error() => Some(format!("HTTP {}", response.status())),
+                Ok(_) => None,
+                Err(e) => Some(e.to_string()),
+            },
+        ).await
+    }
+
+    pub fn health(&self) -> DependencyHealth {
+        let breaker = self.breaker();
+        let state = Self::state(&breaker);
+        DependencyHealth {
+            name: self.name,
+            state,
+            consecutive_failures: breaker.consecutive_failures,
+            successes: breaker.successes,
+            failures: breaker.failures,
+            rejected: breaker.rejected,
+            last_error: breaker.last_error.clone(),
+            last_failure: breaker.last_failure,
+            retry_in_secs: breaker.opened_at
+                .filter(|_| state == CircuitState::Open)
+                .map(|at| OPEN_FOR.saturating_sub(at.elapsed()).as_secs()),
+        }
+    }
+}
+
+/// The process-wide dependency called `name`, created with the default policy
+/// until `configure` sets one
+pub fn dependency(name: &'static str) -> Arc<Dependency> {
+    DEPENDENCIES.get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .entry(name)
+        .or_insert_with(|| Arc::new(Dependency::new(name, RetryPolicy::default())))
+        .clone()
+}
+
+/// Sets each dependency's retry policy from the settings
+pub fn configure(settings: &AppFullSettings) {
+    let policies = [
+        (GITHUB, RetryPolicy::from_settings(settings, None, None)),
+        (PERPLEXITY, RetryPolicy::from_settings(settings, settings.perplexity.as_ref().and_then(|p| p.timeout), None)),
+        (RAGFLOW, RetryPolicy::from_settings(
+            settings,
+            settings.ragflow.as_ref().and_then(|r| r.timeout),
+            settings.ragflow.as_ref().and_then(|r| r.max_retries),
+        )),
+        (OPENAI, RetryPolicy::from_settings(settings, settings.openai.as_ref().and_then(|o| o.timeout), None)),
+    ];
+    for (name, policy) in policies {
+        dependency(name).set_policy(policy);
+    }
+}
+
+/// Every dependency called so far, by name
+pub fn health() -> Vec<DependencyHealth> {
+    DEPENDENCIES.get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .values()
+        .map(|dependency| dependency.health())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[derive(Debug)]
+    enum TestError {
+        Down,
+        Resilience(ResilienceError),
+    }
+
+    impl From<ResilienceError> for TestError {
+        fn from(err: ResilienceError) -> Self {
+            TestError::Resilience(err)
+        }
+    }
+
+    fn down(outcome: &Result<u32, TestError>) -> Option<String> {
+        matches!(outcome, Err(TestError::Down)).then(|| "down".to_string())
+    }
+
+    #[tokio::test]
+    async fn test_dependency() {
+        let policy = RetryPolicy {
+            max_retries: 2,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+            budget: Duration::from_secs(5),
+        };
+        let service = Dependency::new("test", policy);
+
+        // Fails twice, then recovers within the retries
+        let calls = AtomicU32::new(0);
+        let outcome = service.call(|| async {
+            if calls.fetch_add(1, Ordering::SeqCst) < 2 { Err(TestError::Down) } else { Ok(7) }
+        }, down).await;
+        assert!(matches!(outcome, Ok(7)));
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+        assert_eq!(service.health().consecutive_failures, 0);
+
+        // Retries stop after max_retries and the circuit opens at the threshold
+        let outcome = service.call(|| async { Err(TestError::Down) }, down).await;
+        assert!(matches!(outcome, Err(TestError::Down)));
+        assert_eq!(service.health().consecutive_failures, 3);
+        let _ = service.call(|| async { Err(TestError::Down) }, down).await;
+        let health = service.health();
+        assert_eq!(health.state, CircuitState::Open);
+        assert_eq!(health.consecutive_failures, FAILURE_THRESHOLD);
+
+        let rejected = health.rejected;
+        let outcome = service.call(|| async { Ok(1) }, down).await;
+        assert!(matches!(outcome, Err(TestError::Resilience(ResilienceError::Open { .. }))));
+        assert_eq!(service.health().rejected, rejected + 1);
+
+        // Past the open period one trial call closes the circuit again
+        service.breaker().opened_at = Some(Instant::now() - OPEN_FOR);
+        assert_eq!(service.health().state, CircuitState::HalfOpen);
+        assert!(matches!(service.call(|| async { Ok(2) }, down).await, Ok(2)));
+        assert_eq!(service.health().state, CircuitState::Closed);
+
+        // Calls running past the budget time out
+        service.set_policy(RetryPolicy { budget: Duration::from_millis(20), ..policy });
+        let outcome = service.call(|| async {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            Ok(3)
+        }, down).await;
+        assert!(matches!(outcome, Err(TestError::Resilience(ResilienceError::Timeout { .. }))));
+    }
+}
@@ -0,0 +1,236 @@
+//! Saved filters with live membership
+//!
+//! Users save filter expressions (see `graph_filter`) under a name and fetch the
+//! matching part of the graph with `/api/graph/data?filter_id=`. After every graph
+//! rebuild or update the members of each filter are recomputed, and nodes entering
+//! or leaving a filter are published on the event bus. Filters are kept in
+//! `/app/data/metadata/saved_filters.json`; memberships are only held in memory.
+
+use actix::Addr;
+use chrono::{DateTime, Utc};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use crate::config::data_dirs::DataDirs;
+use crate::actors::graph_actor::GraphServiceActor;
+use crate::actors::messages::GetGraphData;
+use crate::models::graph::GraphData;
+use crate::services::event_bus::{AppEvent, EventBus, FilterEvent};
+use crate::services::graph_filter::FilterExpr;
+
+fn filters_path() -> PathBuf {
+    DataDirs::global().metadata_file("saved_filters.json")
+}
+
+const MAX_FILTERS_PER_USER: usize = 50;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedFilter {
+    pub id: String,
+    /// Pubkey of the user who saved it
+    pub owner: String,
+    pub name: String,
+    pub expression: String,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub enum SavedFilterError {
+    Invalid(String),
+    NotFound(String),
+    LimitReached,
+    Storage(String),
+}
+
+impl std::fmt::Display for SavedFilterError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SavedFilterError::Invalid(message)
+            | SavedFilterError::NotFound(message)
+            | SavedFilterError::Storage(message) => f.write_str(message),
+            SavedFilterError::LimitReached => write!(f, "At most {} filters can be saved per user", MAX_FILTERS_PER_USER),
+        }
+    }
+}
+
+struct Entry {
+    filter: SavedFilter,
+    expr: FilterExpr,
+    members: HashSet<String>,
+}
+
+pub struct SavedFilterService {
+    filters: RwLock<HashMap<String, Entry>>,
+    event_bus: EventBus,
+}
+
+impl SavedFilterService {
+    pub fn new(event_bus: EventBus) -> Self {
+        let stored = Self::load_filters().unwrap_or_else(|e| {
+            debug!("[SavedFilters] No stored filters loaded: {}", e);
+            Vec::new()
+        });
+        let filters: HashMap<String, Entry> = stored.into_iter()
+            .filter_map(|filter| match FilterExpr::parse(&filter.expression) {
+                Ok(expr) => Some((filter.id.clone(), Entry { filter, expr, members: HashSet::new() })),
+                Err(e) => {
+                    warn!("[SavedFilters] Dropping filter {} that no longer parses: {}", filter.id, e);
+                    None
+                }
+            })
+            .collect();
+        info!("[SavedFilters] Loaded {} saved filters", filters.len());
+        Self { filters: RwLock::new(filters), event_bus }
+    }
+
+    /// Recomputes memberships whenever the graph is rebuilt or updated
+    pub fn start(self: &Arc<Self>, event_bus: &EventBus, graph_service_addr: Addr<GraphServiceActor>) {
+        let service = self.clone();
+        let mut events = event_bus.subscribe();
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(AppEvent::Graph(_)) => match graph_service_addr.send(GetGraphData).await {
+                        Ok(Ok(graph)) => service.refresh(&graph).await,
+                        _ => warn!("[SavedFilters] Could not read the graph to refresh filters"),
+                    },
+                    Ok(_) => {}
+                    Err(tokio::sync::broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("[SavedFilters] Lagged behind event bus, skipped {} events", skipped);
+                    }
+                    Err(tokio::sync::broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    async fn refresh(&self, graph: &GraphData) {
+        let now = Utc::now();
+        let mut filters = self.filters.write().await;
+        for entry in filters.values_mut() {
+            let members = entry.expr.select(graph, now);
+            let (entered, left) = membership_changes(&entry.members, &members);
+            entry.members = members;
+            if !entered.is_empty() || !left.is_empty() {
+                self.event_bus.publish(FilterEvent::MembershipChanged {
+                    filter_id: entry.filter.id.clone(),
+                    owner: entry.filter.owner.clone(),
+                    entered,
+                    left,
+                });
+            }
+        }
+    }
+
+    pub async fn list(&self, owner: &str) -> Vec<SavedFilter> {
+        let filters = self.filters.read().await;
+        let mut owned: Vec<SavedFilter> = filters.values()
+            .filter(|entry| entry.filter.owner == owner)
+            .map(|entry| entry.filter.clone())
+            .collect();
+        owned.sort_by_key(|filter| filter.created_at);
+        owned
+    }
+
+    pub async fn get(&self, id: &str) -> Option<SavedFilter> {
+        self.filters.read().await.get(id).map(|entry| entry.filter.clone())
+    }
+
+    /// Metadata ids of the nodes currently in the filter, sorted
+    pub async fn members(&self, id: &str) -> Option<Vec<String>> {
+        self.filters.read().await.get(id).map(|entry| {
+            let mut members: Vec<String> = entry.members.iter().cloned().collect();
+            members.sort();
+            members
+        })
+    }
+
+    /// Metadata ids of the nodes of `graph` in the filter, evaluated now rather than
+    /// taken from the last refresh
+    pub async fn select(&self, id: &str, graph: &GraphData) -> Option<HashSet<String>> {
+        self.filters.read().await.get(id).map(|entry| entry.expr.select(graph, Utc::now()))
+    }
+
+    /// Saves a filter and computes its members against `graph`
+    pub async fn create(&self, owner: &str, name: &str, expression: &str, graph: &GraphData) -> Result<SavedFilter, SavedFilterError> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(SavedFilterError::Invalid("Filter name is empty".to_string()));
+        }
+        let expr = FilterExpr::parse(expression).map_err(|e| SavedFilterError::Invalid(e.to_string()))?;
+
+        let mut filters = self.filters.write().await;
+        if filters.values().filter(|entry| entry.filter.owner == owner).count() >= MAX_FILTERS_PER_USER {
+            return Err(SavedFilterError::LimitReached);
+        }
+        let filter = SavedFilter {
+            id: Uuid::new_v4().simple().to_string(),
+            owner: owner.to_string(),
+            name: name.to_string(),
+            expression: expression.trim().to_string(),
+            created_at: Utc::now(),
+        };
+        let members = expr.select(graph, Utc::now());
+        filters.insert(filter.id.clone(), Entry { filter: filter.clone(), expr, members });
+        Self::save_filters(&filters)?;
+        Ok(filter)
+    }
+
+    /// Deletes a filter owned by `owner`
+    pub async fn delete(&self, id: &str, owner: &str) -> Result<(), SavedFilterError> {
+        let mut filters = self.filters.write().await;
+        match filters.get(id) {
+            Some(entry) if entry.filter.owner == owner => {}
+            _ => return Err(SavedFilterError::NotFound(format!("Filter {} not found", id))),
+        }
+        filters.remove(id);
+        Self::save_filters(&filters)
+    }
+
+    fn load_filters() -> Result<Vec<SavedFilter>, Box<dyn std::error::Error>> {
+        let content = fs::read_to_string(filters_path())?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    fn save_filters(filters: &HashMap<String, Entry>) -> Result<(), SavedFilterError> {
+        let save = || -> std::io::Result<()> {
+            if let Some(parent) = filters_path().parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut sorted: Vec<&SavedFilter> = filters.values().map(|entry| &entry.filter).collect();
+            sorted.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)));
+            fs::write(filters_path(), serde_json::to_string_pretty(&sorted)?)
+        };
+        save().map_err(|e| SavedFilterError::Storage(format!("Failed to persist saved filters: {}", e)))
+    }
+}
+
+/// Members that entered and left between two memberships, each sorted
+fn membership_changes(before: &HashSet<String>, after: &HashSet<String>) -> (Vec<String>, Vec<String>) {
+    let mut entered: Vec<String> = after.difference(before).cloned().collect();
+    let mut left: Vec<String> = before.difference(after).cloned().collect();
+    entered.sort();
+    left.sort();
+    (entered, left)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_membership_changes() {
+        let set = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<HashSet<_>>();
+        let (entered, left) = membership_changes(&set(&["A", "B", "C"]), &set(&["B", "D", "C"]));
+        assert_eq!(entered, vec!["D".to_string()]);
+        assert_eq!(left, vec!["A".to_string()]);
+        assert_eq!(membership_changes(&set(&["A"]), &set(&["A"])), (vec![], vec![]));
+    }
+}
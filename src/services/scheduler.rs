@@ -0,0 +1,67 @@
+//! Cron-style scheduling of background tasks
+//!
+//! Schedules are cron expressions evaluated in UTC. The usual five fields (minute,
+//! hour, day of month, month, day of week) are accepted as well as the six-field
+//! form with seconds first. Times that pass while a task is still running are
+//! skipped rather than run late.
+
+use chrono::{DateTime, Utc};
+use cron::Schedule;
+use log::{debug, info};
+use std::future::Future;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Parses a five- or six-field cron expression
+pub fn parse_schedule(expression: &str) -> Result<Schedule, String> {
+    let fields = expression.split_whitespace().count();
+    let expression = match fields {
+        5 => format!("0 {}", expression.trim()),
+        6 => expression.trim().to_string(),
+        _ => return Err(format!("Expected 5 or 6 cron fields, got {}: '{}'", fields, expression)),
+    };
+    Schedule::from_str(&expression).map_err(|e| format!("Invalid cron expression '{}': {}", expression, e))
+}
+
+/// Time from `now` until the schedule next fires, or `None` if it never will
+pub fn next_delay(schedule: &Schedule, now: DateTime<Utc>) -> Option<Duration> {
+    let next = schedule.after(&now).next()?;
+    Some((next - now).to_std().unwrap_or_default())
+}
+
+/// Spawns a loop that awaits `task` each time `schedule` fires
+pub fn start<F, Fut>(name: &'static str, schedule: Schedule, task: F)
+where
+    F: Fn() -> Fut + Send + 'static,
+    Fut: Future<Output = ()> + Send,
+{
+    info!("[Scheduler] Scheduled {} for '{}'", name, schedule);
+    tokio::spawn(async move {
+        while let Some(delay) = next_delay(&schedule, Utc::now()) {
+            debug!("[Scheduler] Next {} in {:?}", name, delay);
+            tokio::time::sleep(delay).await;
+            task().await;
+        }
+        info!("[Scheduler] Schedule for {} has no further runs", name);
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_parse_and_next_delay() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 1, 10, 20, 15).unwrap();
+
+        let every_half_hour = parse_schedule("*/30 * * * *").unwrap();
+        assert_eq!(next_delay(&every_half_hour, now), Some(Duration::from_secs(9 * 60 + 45)));
+
+        let with_seconds = parse_schedule("30 * * * * *").unwrap();
+        assert_eq!(next_delay(&with_seconds, now), Some(Duration::from_secs(15)));
+
+        assert!(parse_schedule("* * *").is_err());
+        assert!(parse_schedule("61 * * * *").is_err());
+    }
+}
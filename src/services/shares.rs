@@ -0,0 +1,311 @@
+//! Read-only shares of part of the graph
+//!
+//! A power user picks tags and namespaces and gets a token for a `/share/<token>`
+//! link. Whoever holds the link sees only the pages carrying one of the tags or
+//! sitting in one of the namespaces, and the edges between them, and can't change
+//! anything. Unlike view links the token is random, as it is what keeps the rest
+//! of the vault out of reach. Shares are kept in `/app/data/metadata/shares.json`.
+
+use chrono::Utc;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use thiserror::Error;
+use tokio::sync::RwLock;
+
+use crate::config::data_dirs::DataDirs;
+use crate::models::graph::GraphData;
+use crate::models::metadata::Metadata;
+use crate::services::pages::page_title;
+
+fn shares_path() -> PathBuf {
+    DataDirs::global().metadata_file("shares.json")
+}
+
+const MAX_SCOPE_ENTRIES: usize = 50;
+const MAX_LABEL_CHARS: usize = 100;
+
+/// The pages a share shows: those tagged with any of `tags`, or in any of
+/// `namespaces` (including the namespace's own page)
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShareScope {
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub namespaces: Vec<String>,
+}
+
+impl ShareScope {
+    fn normalized(self) -> Self {
+        let clean = |values: Vec<String>, trim: &[char]| -> Vec<String> {
+            let mut values: Vec<String> = values.iter()
+                .map(|value| value.trim().trim_matches(trim).to_string())
+                .filter(|value| !value.is_empty())
+                .collect();
+            values.sort();
+            values.dedup();
+            values
+        };
+        Self { tags: clean(self.tags, &['#']), namespaces: clean(self.namespaces, &['/']) }
+    }
+
+    /// Whether the page stored in `file_name` is shared
+    pub fn includes(&self, file_name: &str, metadata: Option<&Metadata>) -> bool {
+        let title = page_title(file_name);
+        let in_namespace = self.namespaces.iter().any(|namespace| {
+            title.get(..namespace.len()).is_some_and(|prefix| prefix.eq_ignore_ascii_case(namespace))
+                && matches!(title.as_bytes().get(namespace.len()), None | Some(b'/'))
+        });
+        in_namespace || metadata.is_some_and(|meta| meta.tags.iter().any(|tag| {
+            let tag = tag.trim_start_matches('#');
+            self.tags.iter().any(|wanted| wanted.eq_ignore_ascii_case(tag))
+        }))
+    }
+
+    /// Ids of the nodes in `graph` that are shared
+    pub fn node_ids(&self, graph: &GraphData) -> HashSet<u32> {
+        graph.nodes.iter()
+            .filter(|node| {
+                let file_name = format!("{}.md", node.metadata_id);
+                self.includes(&file_name, graph.metadata.get(&file_name))
+            })
+            .map(|node| node.id)
+            .collect()
+    }
+
+    /// The shared part of `graph`: its nodes, the edges between them and their
+    /// metadata, with every mention of a page outside the share taken out
+    pub fn apply(&self, graph: &GraphData) -> GraphData {
+        let ids = self.node_ids(graph);
+        let mut shared = GraphData::new();
+        shared.nodes = graph.nodes.iter().filter(|node| ids.contains(&node.id)).cloned().collect();
+        shared.edges = graph.edges.iter()
+            .filter(|edge| ids.contains(&edge.source) && ids.contains(&edge.target))
+            .cloned()
+            .collect();
+        let file_names: HashSet<String> = shared.nodes.iter().map(|node| format!("{}.md", node.metadata_id)).collect();
+        let titles: HashSet<String> = file_names.iter().map(|file_name| page_title(file_name).to_lowercase()).collect();
+        for node in &shared.nodes {
+            let file_name = format!("{}.md", node.metadata_id);
+            if let Some(metadata) = graph.metadata.get(&file_name) {
+                shared.metadata.insert(file_name.clone(), scoped_metadata(metadata, &file_names, &titles));
+            }
+            shared.id_to_metadata.insert(node.id.to_string(), file_name);
+        }
+        shared
+    }
+}
+
+/// `metadata` without the links, duplicates and tasks that name pages outside the
+/// share. Links to private or missing pages name nothing in the share, so they all go.
+fn scoped_metadata(metadata: &Metadata, file_names: &HashSet<String>, titles: &HashSet<String>) -> Metadata {
+    let mut metadata = metadata.clone();
+    metadata.topic_counts.retain(|page, _| file_names.contains(&format!("{}.md", page)));
+    metadata.near_duplicates.retain(|file_name, _| file_names.contains(file_name));
+    metadata.unresolved_links.clear();
+    metadata.private_links.clear();
+    // A task's text names the pages it links, so the whole task goes
+    metadata.tasks.retain(|task| {
+        task.references.iter().all(|page| titles.contains(&page.trim().to_lowercase()))
+    });
+    metadata
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Share {
+    pub token: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub label: Option<String>,
+    pub scope: ShareScope,
+    /// Pubkey of the power user who created it
+    pub owner: String,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Error)]
+pub enum ShareError {
+    #[error("{0}")]
+    Invalid(String),
+    #[error("Share {0} not found")]
+    NotFound(String),
+    #[error("Failed to persist shares: {0}")]
+    Storage(String),
+}
+
+pub struct ShareService {
+    shares: RwLock<HashMap<String, Share>>,
+}
+
+impl Default for ShareService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ShareService {
+    pub fn new() -> Self {
+        let shares = Self::load_shares().unwrap_or_else(|e| {
+            debug!("[Shares] No stored shares loaded: {}", e);
+            HashMap::new()
+        });
+        info!("[Shares] Loaded {} shares", shares.len());
+        Self { shares: RwLock::new(shares) }
+    }
+
+    pub async fn create(&self, owner: &str, label: Option<String>, scope: ShareScope) -> Result<Share, ShareError> {
+        let share = new_share(owner, label, scope)?;
+        let mut shares = self.shares.write().await;
+        shares.insert(share.token.clone(), share.clone());
+        Self::save_shares(&shares).map_err(|e| ShareError::Storage(e.to_string()))?;
+        info!("[Shares] {} shared tags {:?} and namespaces {:?}", owner, share.scope.tags, share.scope.namespaces);
+        Ok(share)
+    }
+
+    pub async fn resolve(&self, token: &str) -> Option<Share> {
+        self.shares.read().await.get(token).cloned()
+    }
+
+    /// Shares created by `owner`, newest first
+    pub async fn list(&self, owner: &str) -> Vec<Share> {
+        let mut shares: Vec<Share> = self.shares.read().await.values()
+            .filter(|share| share.owner == owner)
+            .cloned()
+            .collect();
+        shares.sort_by(|a, b| b.created_at.cmp(&a.created_at).then_with(|| a.token.cmp(&b.token)));
+        shares
+    }
+
+    /// Stops the share's link from working. Only its owner can revoke it.
+    pub async fn revoke(&self, owner: &str, token: &str) -> Result<(), ShareError> {
+        let mut shares = self.shares.write().await;
+        if shares.get(token).is_none_or(|share| share.owner != owner) {
+            return Err(ShareError::NotFound(token.to_string()));
+        }
+        shares.remove(token);
+        Self::save_shares(&shares).map_err(|e| ShareError::Storage(e.to_string()))?;
+        info!("[Shares] {} revoked a share", owner);
+        Ok(())
+    }
+
+    fn load_shares() -> Result<HashMap<String, Share>, Box<dyn std::error::Error>> {
+        let content = fs::read_to_string(shares_path())?;
+        let shares: Vec<Share> = serde_json::from_str(&content)?;
+        Ok(shares.into_iter().map(|share| (share.token.clone(), share)).collect())
+    }
+
+    fn save_shares(shares: &HashMap<String, Share>) -> std::io::Result<()> {
+        if let Some(parent) = shares_path().parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut sorted: Vec<&Share> = shares.values().collect();
+        sorted.sort_by(|a, b| a.token.cmp(&b.token));
+        let json = serde_json::to_string_pretty(&sorted)?;
+        fs::write(shares_path(), json)
+    }
+}
+
+fn new_share(owner: &str, label: Option<String>, scope: ShareScope) -> Result<Share, ShareError> {
+    let scope = scope.normalized();
+    if scope.tags.is_empty() && scope.namespaces.is_empty() {
+        return Err(ShareError::Invalid("A share needs at least one tag or namespace".to_string()));
+    }
+    if scope.tags.len() + scope.namespaces.len() > MAX_SCOPE_ENTRIES {
+        return Err(ShareError::Invalid(format!("A share can have at most {} tags and namespaces", MAX_SCOPE_ENTRIES)));
+    }
+    let label = label.map(|label| label.trim().to_string()).filter(|label| !label.is_empty());
+    if label.as_ref().is_some_and(|label| label.chars().count() > MAX_LABEL_CHARS) {
+        return Err(ShareError::Invalid(format!("Labels are limited to {} characters", MAX_LABEL_CHARS)));
+    }
+    Ok(Share {
+        token: uuid::Uuid::new_v4().simple().to_string(),
+        label,
+        scope,
+        owner: owner.to_string(),
+        created_at: Utc::now().timestamp(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::edge::Edge;
+    use crate::models::metadata::Task;
+    use crate::models::node::Node;
+
+    #[test]
+    fn test_scope_selects_tagged_and_namespaced_pages() {
+        let scope = ShareScope { tags: vec!["#Public".to_string()], namespaces: vec!["projects/webxr/".to_string()] };
+        let share = new_share("npub1", Some("  ".to_string()), scope).unwrap();
+        assert_eq!(share.scope.namespaces, vec!["projects/webxr".to_string()]);
+        assert_eq!(share.token.len(), 32);
+        assert!(share.label.is_none());
+        assert!(new_share("npub1", None, ShareScope::default()).is_err());
+
+        let mut graph = GraphData::new();
+        for (id, name, tags) in [(1, "projects___webxr", vec![]), (2, "projects___webxr2", vec![]), (3, "Notes", vec!["public"])] {
+            let file_name = format!("{}.md", name);
+            let tags = tags.into_iter().map(str::to_string).collect();
+            graph.metadata.insert(file_name.clone(), Metadata { file_name, tags, ..Default::default() });
+            graph.nodes.push(Node::new_with_id(name.to_string(), Some(id)));
+        }
+        for (source, target) in [(1, 2), (1, 3)] {
+            graph.edges.push(Edge::new(source, target, 1.0));
+        }
+
+        let shared = share.scope.apply(&graph);
+        let mut ids: Vec<u32> = shared.nodes.iter().map(|node| node.id).collect();
+        ids.sort();
+        assert_eq!(ids, vec![1, 3]);
+        assert_eq!(shared.edges.len(), 1);
+        assert!(!shared.metadata.contains_key("projects___webxr2.md"));
+    }
+
+    #[test]
+    fn test_shared_metadata_names_no_outside_page() {
+        let task = |text: &str, references: &[&str]| Task {
+            marker: "TODO".to_string(),
+            text: text.to_string(),
+            priority: None,
+            scheduled: None,
+            deadline: None,
+            tags: Vec::new(),
+            references: references.iter().map(|page| page.to_string()).collect(),
+            line: 1,
+        };
+        let counts = |pages: &[&str]| pages.iter().map(|page| (page.to_string(), 1)).collect::<HashMap<_, _>>();
+
+        let mut graph = GraphData::new();
+        let alpha = Metadata {
+            file_name: "Alpha.md".to_string(),
+            tags: vec!["public".to_string()],
+            topic_counts: counts(&["Beta", "Outside"]),
+            unresolved_links: counts(&["Missing"]),
+            private_links: counts(&["Secret"]),
+            near_duplicates: [("Beta.md".to_string(), 0.9), ("Outside.md".to_string(), 0.8)].into_iter().collect(),
+            tasks: vec![task("Ask [[beta]]", &["beta"]), task("Ask [[Outside]]", &["Outside"])],
+            ..Default::default()
+        };
+        let beta = Metadata { file_name: "Beta.md".to_string(), tags: vec!["public".to_string()], ..Default::default() };
+        let outside = Metadata { file_name: "Outside.md".to_string(), ..Default::default() };
+        for (id, metadata) in [(1, alpha), (2, beta), (3, outside)] {
+            graph.nodes.push(Node::new_with_id(metadata.file_name.trim_end_matches(".md").to_string(), Some(id)));
+            graph.metadata.insert(metadata.file_name.clone(), metadata);
+        }
+
+        let scope = ShareScope { tags: vec!["public".to_string()], namespaces: Vec::new() };
+        let shared = scope.apply(&graph);
+        let alpha = &shared.metadata["Alpha.md"];
+        assert_eq!(alpha.topic_counts, counts(&["Beta"]));
+        assert_eq!(alpha.near_duplicates.keys().collect::<Vec<_>>(), vec!["Beta.md"]);
+        assert_eq!(alpha.tasks.len(), 1);
+
+        let json = serde_json::to_string(&shared).unwrap();
+        for name in ["Outside", "Missing", "Secret"] {
+            assert!(!json.contains(name), "shared graph names {}", name);
+        }
+    }
+}
@@ -0,0 +1,368 @@
+//! PNG snapshots of the graph
+//!
+//! Renders the current node positions without a GPU: an orthographic view down the
+//! z axis, fitted to the graph's bounds, with edges drawn as thin translucent lines
+//! and nodes as discs painted far to near, so nearer nodes overlap and come out
+//! brighter. Colours come from the visualisation settings. Used for link previews
+//! and, when `GRAPH_SNAPSHOT_INTERVAL_MINUTES` is set, a history of snapshots kept
+//! in `/app/data/snapshots`.
+
+use actix::Addr;
+use chrono::Utc;
+use flate2::write::ZlibEncoder;
+use flate2::{Compression, Crc};
+use log::{debug, error, info, warn};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::time::Duration;
+
+use crate::actors::graph_actor::GraphServiceActor;
+use crate::actors::messages::{GetGraphData, GetSettings};
+use crate::actors::settings_actor::SettingsActor;
+use crate::config::data_dirs::DataDirs;
+use crate::config::VisualisationSettings;
+use crate::models::graph::GraphData;
+
+/// Open Graph's preferred preview size
+pub const DEFAULT_WIDTH: u32 = 1200;
+pub const DEFAULT_HEIGHT: u32 = 630;
+pub const MIN_DIMENSION: u32 = 16;
+pub const MAX_DIMENSION: u32 = 4096;
+
+const DEFAULT_HISTORY: usize = 48;
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+type Rgb = [u8; 3];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SnapshotStyle {
+    pub background: Rgb,
+    pub node_color: Rgb,
+    pub node_opacity: f32,
+    pub edge_color: Rgb,
+    pub edge_opacity: f32,
+}
+
+impl Default for SnapshotStyle {
+    fn default() -> Self {
+        Self {
+            background: [0x18, 0x1c, 0x28],
+            node_color: [0x66, 0xd9, 0xef],
+            node_opacity: 1.0,
+            edge_color: [0x56, 0xb6, 0xc2],
+            edge_opacity: 0.25,
+        }
+    }
+}
+
+impl SnapshotStyle {
+    /// Takes the colours the clients use, keeping the defaults for any that don't parse
+    pub fn from_settings(vis: &VisualisationSettings) -> Self {
+        let default = Self::default();
+        Self {
+            background: parse_hex_color(&vis.rendering.background_color).unwrap_or(default.background),
+            node_color: parse_hex_color(&vis.nodes.base_color).unwrap_or(default.node_color),
+            // Translucent nodes would show the edges behind them, which reads as noise
+            // at thumbnail size
+            node_opacity: default.node_opacity,
+            edge_color: parse_hex_color(&vis.edges.color).unwrap_or(default.edge_color),
+            edge_opacity: if vis.edges.opacity > 0.0 { vis.edges.opacity.min(1.0) } else { default.edge_opacity },
+        }
+    }
+}
+
+/// Parses `#rrggbb` or `#rgb`
+pub fn parse_hex_color(value: &str) -> Option<Rgb> {
+    let hex = value.trim().strip_prefix('#')?;
+    let channel = |s: &str| u8::from_str_radix(s, 16).ok();
+    match hex.len() {
+        6 => Some([channel(&hex[0..2])?, channel(&hex[2..4])?, channel(&hex[4..6])?]),
+        3 => {
+            let [r, g, b] = [channel(&hex[0..1])?, channel(&hex[1..2])?, channel(&hex[2..3])?];
+            Some([r * 17, g * 17, b * 17])
+        }
+        _ => None,
+    }
+}
+
+/// Renders `graph` as a `width` x `height` PNG
+pub fn render_png(graph: &GraphData, width: u32, height: u32, style: &SnapshotStyle) -> std::io::Result<Vec<u8>> {
+    let width = width.clamp(MIN_DIMENSION, MAX_DIMENSION);
+    let height = height.clamp(MIN_DIMENSION, MAX_DIMENSION);
+    let canvas = render(graph, width, height, style);
+    encode_png(&canvas.pixels, width, height)
+}
+
+struct Canvas {
+    width: u32,
+    height: u32,
+    /// RGB, row by row
+    pixels: Vec<u8>,
+}
+
+impl Canvas {
+    fn new(width: u32, height: u32, background: Rgb) -> Self {
+        let pixels = background.repeat((width * height) as usize);
+        Self { width, height, pixels }
+    }
+
+    fn blend(&mut self, x: i64, y: i64, color: Rgb, alpha: f32) {
+        if x < 0 || y < 0 || x >= i64::from(self.width) || y >= i64::from(self.height) || alpha <= 0.0 {
+            return;
+        }
+        let offset = ((y as u32 * self.width + x as u32) * 3) as usize;
+        let alpha = alpha.min(1.0);
+        for (pixel, channel) in self.pixels[offset..offset + 3].iter_mut().zip(color) {
+            *pixel = (f32::from(*pixel) * (1.0 - alpha) + f32::from(channel) * alpha).round() as u8;
+        }
+    }
+
+    fn line(&mut self, from: (f32, f32), to: (f32, f32), color: Rgb, alpha: f32) {
+        let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+        let steps = dx.abs().max(dy.abs()).ceil().max(1.0) as u32;
+        for step in 0..=steps {
+            let t = step as f32 / steps as f32;
+            let x = (from.0 + dx * t).round() as i64;
+            let y = (from.1 + dy * t).round() as i64;
+            self.blend(x, y, color, alpha);
+        }
+    }
+
+    /// A filled disc whose rim is antialiased by pixel coverage
+    fn disc(&mut self, centre: (f32, f32), radius: f32, color: Rgb, alpha: f32) {
+        let reach = radius + 1.0;
+        let (x0, x1) = ((centre.0 - reach).floor() as i64, (centre.0 + reach).ceil() as i64);
+        let (y0, y1) = ((centre.1 - reach).floor() as i64, (centre.1 + reach).ceil() as i64);
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                let distance = (x as f32 - centre.0).hypot(y as f32 - centre.1);
+                let coverage = (radius + 0.5 - distance).clamp(0.0, 1.0);
+                self.blend(x, y, color, coverage * alpha);
+            }
+        }
+    }
+}
+
+fn render(graph: &GraphData, width: u32, height: u32, style: &SnapshotStyle) -> Canvas {
+    let mut canvas = Canvas::new(width, height, style.background);
+    if graph.nodes.is_empty() {
+        return canvas;
+    }
+
+    let short_side = width.min(height) as f32;
+    let base_radius = (short_side / 150.0).max(1.0);
+    // Sizes follow file size, so draw them relative to the largest note, up to
+    // twice the base radius
+    let largest = graph.nodes.iter().filter_map(|n| n.size).fold(0.0, f32::max);
+    let radius_of = |size: Option<f32>| {
+        let relative = match size {
+            Some(size) if largest > 0.0 => (size / largest).clamp(0.0, 1.0),
+            _ => 0.5,
+        };
+        base_radius * (1.0 + relative)
+    };
+
+    let (mut min, mut max) = ([f32::MAX; 3], [f32::MIN; 3]);
+    for node in &graph.nodes {
+        let p = node.data.position;
+        for (axis, value) in [p.x, p.y, p.z].into_iter().enumerate() {
+            min[axis] = min[axis].min(value);
+            max[axis] = max[axis].max(value);
+        }
+    }
+    let margin = short_side * 0.05 + 2.0 * base_radius;
+    let span_x = (max[0] - min[0]).max(f32::EPSILON);
+    let span_y = (max[1] - min[1]).max(f32::EPSILON);
+    let scale = ((width as f32 - 2.0 * margin) / span_x).min((height as f32 - 2.0 * margin) / span_y).max(0.0);
+    let (centre_x, centre_y) = ((min[0] + max[0]) / 2.0, (min[1] + max[1]) / 2.0);
+    // Screen y grows downwards
+    let project = |x: f32, y: f32| {
+        (width as f32 / 2.0 + (x - centre_x) * scale, height as f32 / 2.0 - (y - centre_y) * scale)
+    };
+
+    let screen: HashMap<u32, (f32, f32)> = graph.nodes.iter()
+        .map(|n| (n.id, project(n.data.position.x, n.data.position.y)))
+        .collect();
+    for edge in &graph.edges {
+        if let (Some(&from), Some(&to)) = (screen.get(&edge.source), screen.get(&edge.target)) {
+            canvas.line(from, to, style.edge_color, style.edge_opacity);
+        }
+    }
+
+    let span_z = max[2] - min[2];
+    let mut nodes: Vec<_> = graph.nodes.iter().collect();
+    nodes.sort_by(|a, b| a.data.position.z.total_cmp(&b.data.position.z));
+    for node in nodes {
+        let depth = if span_z > f32::EPSILON { (node.data.position.z - min[2]) / span_z } else { 1.0 };
+        let base = node.color.as_deref().and_then(parse_hex_color).unwrap_or(style.node_color);
+        let shade = 0.55 + 0.45 * depth;
+        let color = base.map(|channel| (f32::from(channel) * shade).round() as u8);
+        canvas.disc(screen[&node.id], radius_of(node.size), color, style.node_opacity);
+    }
+    canvas
+}
+
+/// 8-bit RGB, no interlacing, every row unfiltered
+fn encode_png(pixels: &[u8], width: u32, height: u32) -> std::io::Result<Vec<u8>> {
+    encode_png_rows(pixels, width, height, 3)
+}
+
+/// Encodes 8-bit greyscale pixels, one byte each
+pub(crate) fn encode_grayscale_png(pixels: &[u8], width: u32, height: u32) -> std::io::Result<Vec<u8>> {
+    encode_png_rows(pixels, width, height, 1)
+}
+
+fn encode_png_rows(pixels: &[u8], width: u32, height: u32, channels: usize) -> std::io::Result<Vec<u8>> {
+    let color_type = if channels == 1 { 0 } else { 2 };
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    header.extend_from_slice(&[8, color_type, 0, 0, 0]);
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    for row in pixels.chunks(width as usize * channels) {
+        encoder.write_all(&[0])?;
+        encoder.write_all(row)?;
+    }
+    let data = encoder.finish()?;
+
+    let mut png = PNG_SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &data);
+    write_chunk(&mut png, b"IEND", &[]);
+    Ok(png)
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let mut crc = Crc::new();
+    crc.update(kind);
+    crc.update(data);
+    png.extend_from_slice(&crc.sum().to_be_bytes());
+}
+
+/// Starts saving a snapshot every `GRAPH_SNAPSHOT_INTERVAL_MINUTES`, keeping the
+/// latest `GRAPH_SNAPSHOT_HISTORY` (48 by default). Does nothing if the interval
+/// is unset or zero.
+pub fn start_history(graph_service_addr: Addr<GraphServiceActor>, settings_addr: Addr<SettingsActor>) {
+    let minutes: u64 = env_number("GRAPH_SNAPSHOT_INTERVAL_MINUTES").unwrap_or(0);
+    if minutes == 0 {
+        debug!("[Snapshots] Snapshot history disabled");
+        return;
+    }
+    let keep = env_number("GRAPH_SNAPSHOT_HISTORY").unwrap_or(DEFAULT_HISTORY).max(1);
+    info!("[Snapshots] Saving a snapshot every {} minutes, keeping {}", minutes, keep);
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(minutes * 60));
+        loop {
+            interval.tick().await;
+
+            let graph = match graph_service_addr.send(GetGraphData).await {
+                Ok(Ok(graph)) => graph,
+                Ok(Err(e)) => {
+                    error!("[Snapshots] Failed to get graph data: {}", e);
+                    continue;
+                }
+                Err(e) => {
+                    error!("[Snapshots] GraphServiceActor unavailable, stopping: {}", e);
+                    break;
+                }
+            };
+            if graph.nodes.is_empty() {
+                continue;
+            }
+            let style = match settings_addr.send(GetSettings).await {
+                Ok(Ok(settings)) => SnapshotStyle::from_settings(&settings.visualisation),
+                _ => SnapshotStyle::default(),
+            };
+
+            match render_png(&graph, DEFAULT_WIDTH, DEFAULT_HEIGHT, &style).and_then(|png| save_snapshot(&png, keep)) {
+                Ok(path) => debug!("[Snapshots] Saved {}", path),
+                Err(e) => warn!("[Snapshots] Failed to save snapshot: {}", e),
+            }
+        }
+    });
+}
+
+fn env_number<T: std::str::FromStr>(name: &str) -> Option<T> {
+    std::env::var(name).ok().and_then(|value| value.trim().parse().ok())
+}
+
+/// Writes `png` to the snapshot directory and removes the oldest beyond `keep`
+fn save_snapshot(png: &[u8], keep: usize) -> std::io::Result<String> {
+    let dir = DataDirs::global().snapshots();
+    fs::create_dir_all(&dir)?;
+    let name = format!("graph-{}.png", Utc::now().format("%Y%m%dT%H%M%SZ"));
+    let path = dir.join(&name);
+    fs::write(&path, png)?;
+
+    // Timestamped names sort oldest first
+    let mut snapshots: Vec<String> = fs::read_dir(&dir)?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|file| file.starts_with("graph-") && file.ends_with(".png"))
+        .collect();
+    snapshots.sort();
+    let excess = snapshots.len().saturating_sub(keep);
+    for old in &snapshots[..excess] {
+        fs::remove_file(dir.join(old))?;
+    }
+    Ok(path.display().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::edge::Edge;
+    use crate::models::node::Node;
+    use crate::types::vec3::Vec3Data;
+    use flate2::read::ZlibDecoder;
+    use std::io::Read;
+
+    fn node(id: u32, x: f32, y: f32) -> Node {
+        let mut node = Node::new_with_id(id.to_string(), Some(id));
+        node.data.position = Vec3Data::new(x, y, 0.0);
+        node
+    }
+
+    #[test]
+    fn test_parse_hex_color() {
+        assert_eq!(parse_hex_color("#66d9ef"), Some([0x66, 0xd9, 0xef]));
+        assert_eq!(parse_hex_color("#fff"), Some([255, 255, 255]));
+        assert_eq!(parse_hex_color("red"), None);
+    }
+
+    #[test]
+    fn test_render_png_round_trip() {
+        let mut graph = GraphData::new();
+        graph.nodes = vec![node(1, -10.0, 0.0), node(2, 10.0, 0.0)];
+        graph.edges = vec![Edge::new(1, 2, 1.0)];
+        let style = SnapshotStyle::default();
+        let png = render_png(&graph, 64, 32, &style).unwrap();
+
+        assert_eq!(png[..8], PNG_SIGNATURE);
+        assert_eq!(&png[12..16], b"IHDR");
+        assert_eq!(u32::from_be_bytes(png[16..20].try_into().unwrap()), 64);
+        assert_eq!(u32::from_be_bytes(png[20..24].try_into().unwrap()), 32);
+        let mut crc = Crc::new();
+        crc.update(&png[12..29]);
+        assert_eq!(u32::from_be_bytes(png[29..33].try_into().unwrap()), crc.sum());
+
+        let idat_length = u32::from_be_bytes(png[33..37].try_into().unwrap()) as usize;
+        assert_eq!(&png[37..41], b"IDAT");
+        let mut raw = Vec::new();
+        ZlibDecoder::new(&png[41..41 + idat_length]).read_to_end(&mut raw).unwrap();
+        assert_eq!(raw.len(), 32 * (1 + 64 * 3));
+        let pixel = |x: usize, y: usize| {
+            let offset = y * (1 + 64 * 3) + 1 + x * 3;
+            [raw[offset], raw[offset + 1], raw[offset + 2]]
+        };
+        assert_eq!(pixel(0, 0), style.background);
+        // Both nodes sit on the middle row; the edge between them is tinted
+        assert_ne!(pixel(32, 16), style.background);
+        assert!(png.ends_with(&[0, 0, 0, 0, b'I', b'E', b'N', b'D', 0xae, 0x42, 0x60, 0x82]));
+    }
+}
@@ -7,7 +7,8 @@ use tokio::task;
 use tokio::sync::broadcast;
 use crate::config::AppFullSettings;
 // use crate::config::Settings; // AppFullSettings is used from self.settings
-use log::{info, error, debug};
+use log::{info, error, debug, warn};
+use std::collections::HashMap;
 use futures::{SinkExt, StreamExt};
 use std::error::Error;
 use tokio::net::TcpStream;
@@ -16,18 +17,54 @@ use base64::Engine as _;
 use base64::engine::general_purpose::{STANDARD as BASE64};
 use crate::types::speech::{SpeechError, SpeechCommand, TTSProvider, STTProvider, SpeechOptions, TranscriptionOptions};
 use reqwest::Client;
+use crate::services::tts_provider::{tts_backend, describe_providers, TtsProviderInfo};
+use crate::services::audio_cache::AudioCache;
+use crate::services::transcripts::{Speaker, TranscriptLine, MAX_TRANSCRIPT_LINES};
 
+// Per-session channel sizes are smaller than the global ones since only one client listens
+const SESSION_CHANNEL_CAPACITY: usize = 32;
+
+/// State kept for a single speech WebSocket connection
+struct SpeechSession {
+    audio_tx: broadcast::Sender<Vec<u8>>,
+    transcription_tx: broadcast::Sender<String>,
+    /// Everything said while the session is recording a transcript
+    transcript: Option<Vec<TranscriptLine>>,
+    /// In-flight TTS request; aborted when a newer request supersedes it
+    active_tts: Option<task::JoinHandle<()>>,
+}
+
+impl SpeechSession {
+    /// Adds `line` to the transcript if one is being recorded
+    fn record(&mut self, speaker: Speaker, line: &str) {
+        if let Some(transcript) = self.transcript.as_mut().filter(|t| t.len() < MAX_TRANSCRIPT_LINES) {
+            transcript.push(TranscriptLine { at: chrono::Local::now(), speaker, text: line.to_string() });
+        }
+    }
+}
+
+type SessionMap = Arc<std::sync::Mutex<HashMap<String, SpeechSession>>>;
+
+/// Receivers handed to a WebSocket connection when it opens a speech session
+pub struct SpeechSessionHandle {
+    pub session_id: String,
+    pub audio_rx: broadcast::Receiver<Vec<u8>>,
+    pub transcription_rx: broadcast::Receiver<String>,
+}
 
 /// Centralized speech service managing both Text-to-Speech (TTS) and Speech-to-Text (STT) operations
 ///
 /// This service orchestrates real-time voice interactions by:
-/// - Managing TTS via Kokoro API for generating speech from text
+/// - Managing TTS via pluggable backends (Kokoro, OpenAI, Sonata, ElevenLabs)
 /// - Managing STT via Whisper API for transcribing audio to text
 /// - Broadcasting audio and transcription data to multiple WebSocket clients
 /// - Handling provider switching and configuration management
 ///
 /// The service uses async channels for command processing and broadcast channels
-/// for distributing results to multiple subscribers simultaneously.
+/// for distributing results to multiple subscribers simultaneously. WebSocket clients
+/// open a session so their audio and transcripts are routed only to them; requests
+/// without a session (e.g. RAGFlow answers) still go to the global channels. The
+/// OpenAI realtime connection is a single one shared by every session.
 pub struct SpeechService {
     /// Command sender for internal message passing to the service task
     sender: Arc<Mutex<mpsc::Sender<SpeechCommand>>>,
@@ -46,6 +83,8 @@ pub struct SpeechService {
     /// Shared HTTP client for making API requests to external services (Kokoro, Whisper)
     /// Reused across all requests for connection pooling and efficiency
     http_client: Arc<Client>,
+    /// Per-connection sessions keyed by socket id
+    sessions: SessionMap,
 }
 
 impl SpeechService {
@@ -93,6 +132,7 @@ impl SpeechService {
             audio_tx,
             transcription_tx,
             http_client,
+            sessions: Arc::new(std::sync::Mutex::new(HashMap::new())),
         };
 
         // Start the internal service task for async command processing
@@ -107,6 +147,7 @@ impl SpeechService {
         let stt_provider = Arc::clone(&self.stt_provider);
         let audio_tx = self.audio_tx.clone();
         let transcription_tx = self.transcription_tx.clone();
+        let sessions = Arc::clone(&self.sessions);
 
         task::spawn(async move {
             let mut ws_stream: Option<WebSocketStream<MaybeTlsStream<TcpStream>>> = None;
@@ -263,104 +304,118 @@ impl SpeechService {
                         *current_provider = provider.clone();
                         info!("TTS provider updated to: {:?}", provider);
                     },
-                    SpeechCommand::TextToSpeech(text, options) => {
+                    SpeechCommand::TextToSpeech(text, options, session_id) => {
                         let provider = tts_provider.read().await.clone();
+                        info!("Processing TextToSpeech command with {} provider", provider.id());
+
+                        // Route audio to the requesting session, or to everyone if there is none
+                        let (audio_out, superseded) = match &session_id {
+                            Some(id) => {
+                                let mut sessions = sessions.lock().unwrap();
+                                match sessions.get_mut(id) {
+                                    Some(session) => {
+                                        session.record(Speaker::Assistant, &text);
+                                        (session.audio_tx.clone(), session.active_tts.take())
+                                    }
+                                    None => {
+                                        warn!("TextToSpeech for unknown speech session {}", id);
+                                        continue;
+                                    }
+                                }
+                            }
+                            None => (audio_tx.clone(), None),
+                        };
+                        if let Some(handle) = superseded {
+                            handle.abort();
+                            debug!("Cancelled superseded TTS request for session {:?}", session_id);
+                        }
 
-                        match provider {
-                            TTSProvider::OpenAI => {
-                                info!("TextToSpeech command with OpenAI provider not implemented");
-                            },
-                            TTSProvider::Kokoro => {
-                                info!("Processing TextToSpeech command with Kokoro provider");
-                                let kokoro_config = {
-                                    let s = settings.read().await;
-                                    s.kokoro.clone()
-                                };
+                        // Snapshot settings so the lock isn't held across the HTTP request
+                        let settings_snapshot = settings.read().await.clone();
+                        let http_client = Arc::clone(&http_client);
+
+                        // Synthesis runs in its own task so it can be cancelled and doesn't
+                        // block the command loop while waiting on the provider
+                        let handle = task::spawn(async move {
+                            let backend = tts_backend(&provider);
+                            let cache = AudioCache::from_settings(settings_snapshot.audio_cache.as_ref());
+                            let fingerprint = backend.audio_fingerprint(&settings_snapshot);
+                            let cache_key = AudioCache::key(&provider, &fingerprint, &text, &options);
+                            if let Some(cache) = &cache {
+                                let lookup = cache.clone();
+                                let key = cache_key.clone();
+                                if let Ok(Some(audio)) = task::spawn_blocking(move || lookup.get(&key)).await {
+                                    debug!("Serving {} bytes of cached TTS audio", audio.len());
+                                    if let Err(e) = audio_out.send(audio) {
+                                        error!("Failed to send cached audio data: {}", e);
+                                    }
+                                    return;
+                                }
+                            }
 
-                                if let Some(config) = kokoro_config {
-                                    let api_url_base = match config.api_url.as_deref() {
-                                        Some(url) if !url.is_empty() => url,
-                                        _ => {
-                                            error!("Kokoro API URL not configured or empty.");
-                                            continue;
-                                        }
-                                    };
-                                    let api_url = format!("{}/v1/audio/speech", api_url_base.trim_end_matches('/'));
-                                    info!("Sending TTS request to Kokoro API: {}", api_url);
-
-                                    let response_format = config.default_format.as_deref().unwrap_or("mp3");
-
-                                    let request_body = json!({
-                                        "model": "kokoro",
-                                        "input": text,
-                                        "voice": options.voice.clone(),
-                                        "response_format": response_format,
-                                        "speed": options.speed,
-                                        "stream": options.stream
-                                    });
+                            let response = match backend.synthesize(&http_client, &settings_snapshot, &text, &options).await {
+                                Ok(response) => response,
+                                Err(e) => {
+                                    error!("{}", e);
+                                    return;
+                                }
+                            };
 
-                                    let response = match http_client
-                                        .post(&api_url)
-                                        .header("Content-Type", "application/json")
-                                        .body(request_body.to_string())
-                                        .send()
-                                        .await
-                                    {
-                                        Ok(response) => {
-                                            if !response.status().is_success() {
-                                                let status = response.status();
-                                                let error_text = response.text().await.unwrap_or_default();
-                                                error!("Kokoro API error {}: {}", status, error_text);
-                                                continue;
+                            // Collected alongside streaming so a complete response can be cached
+                            let mut complete_audio: Option<Vec<u8>> = None;
+                            if options.stream {
+                                let mut stream = Box::pin(response.bytes_stream());
+                                let mut collected = cache.as_ref().map(|_| Vec::new());
+                                let mut stream_ok = true;
+                                while let Some(item) = stream.next().await {
+                                    match item {
+                                        Ok(bytes) => {
+                                            if let Some(buf) = collected.as_mut() {
+                                                buf.extend_from_slice(&bytes);
+                                            }
+                                            if let Err(e) = audio_out.send(bytes.to_vec()) {
+                                                error!("Failed to broadcast audio chunk: {}", e);
                                             }
-                                            response
                                         }
                                         Err(e) => {
-                                            error!("Failed to connect to Kokoro API: {}", e);
-                                            continue;
+                                            error!("Error receiving audio stream: {}", e);
+                                            stream_ok = false;
+                                            break;
                                         }
-                                    };
-
-                                    if options.stream {
-                                        let stream = response.bytes_stream();
-                                        let audio_broadcaster = audio_tx.clone();
-
-                                        tokio::spawn(async move {
-                                            let mut stream = Box::pin(stream);
-
-                                            while let Some(item) = stream.next().await {
-                                                match item {
-                                                    Ok(bytes) => {
-                                                        if let Err(e) = audio_broadcaster.send(bytes.to_vec()) {
-                                                            error!("Failed to broadcast audio chunk: {}", e);
-                                                        }
-                                                    }
-                                                    Err(e) => {
-                                                        error!("Error receiving audio stream: {}", e);
-                                                        break;
-                                                    }
-                                                }
-                                            }
-                                            debug!("Finished streaming audio from Kokoro");
-                                        });
-                                    } else {
-                                        match response.bytes().await {
-                                            Ok(bytes) => {
-                                                if let Err(e) = audio_tx.send(bytes.to_vec()) {
-                                                    error!("Failed to send audio data: {}", e);
-                                                } else {
-                                                    debug!("Sent {} bytes of audio data", bytes.len());
-                                                }
-                                            }
-                                            Err(e) => {
-                                                error!("Failed to get audio bytes: {}", e);
-                                            }
+                                    }
+                                }
+                                debug!("Finished streaming audio from {}", provider.id());
+                                if stream_ok {
+                                    complete_audio = collected;
+                                }
+                            } else {
+                                match response.bytes().await {
+                                    Ok(bytes) => {
+                                        if let Err(e) = audio_out.send(bytes.to_vec()) {
+                                            error!("Failed to send audio data: {}", e);
+                                        } else {
+                                            debug!("Sent {} bytes of audio data", bytes.len());
                                         }
+                                        complete_audio = Some(bytes.to_vec());
+                                    }
+                                    Err(e) => {
+                                        error!("Failed to get audio bytes: {}", e);
                                     }
-                                } else {
-                                    error!("Kokoro configuration not found");
                                 }
                             }
+
+                            if let (Some(cache), Some(audio)) = (cache, complete_audio) {
+                                let stored = task::spawn_blocking(move || cache.put(&cache_key, &audio)).await;
+                                if let Ok(Err(e)) = stored {
+                                    error!("Failed to store TTS audio in cache: {}", e);
+                                }
+                            }
+                        });
+
+                        if let Some(id) = &session_id {
+                            if let Some(session) = sessions.lock().unwrap().get_mut(id) {
+                                session.active_tts = Some(handle);
+                            }
                         }
                     },
                     SpeechCommand::SetSTTProvider(provider) => {
@@ -400,7 +455,7 @@ impl SpeechService {
                         info!("Stopping transcription");
                         // TODO: Implement stop logic
                     },
-                    SpeechCommand::ProcessAudioChunk(audio_data) => {
+                    SpeechCommand::ProcessAudioChunk(audio_data, session_id) => {
                         debug!("Processing audio chunk of size: {} bytes", audio_data.len());
 
                         let provider = stt_provider.read().await.clone();
@@ -442,7 +497,17 @@ impl SpeechService {
                                     }
 
                                     let http_client_clone = Arc::clone(&http_client);
-                                    let transcription_broadcaster = transcription_tx.clone();
+                                    let transcription_broadcaster = match &session_id {
+                                        Some(id) => match sessions.lock().unwrap().get(id) {
+                                            Some(session) => session.transcription_tx.clone(),
+                                            None => {
+                                                warn!("Audio chunk for unknown speech session {}", id);
+                                                continue;
+                                            }
+                                        },
+                                        None => transcription_tx.clone(),
+                                    };
+                                    let sessions_for_transcript = Arc::clone(&sessions);
 
                                     tokio::spawn(async move {
                                         match http_client_clone
@@ -458,6 +523,11 @@ impl SpeechService {
                                                             if let Some(text) = json.get("text").and_then(|t| t.as_str()) {
                                                                 if !text.trim().is_empty() {
                                                                     debug!("Whisper transcription: {}", text);
+                                                                    if let Some(id) = &session_id {
+                                                                        if let Some(session) = sessions_for_transcript.lock().unwrap().get_mut(id) {
+                                                                            session.record(Speaker::User, text);
+                                                                        }
+                                                                    }
                                                                     let _ = transcription_broadcaster.send(text.to_string());
                                                                 }
                                                             } else {
@@ -520,9 +590,9 @@ impl SpeechService {
     /// - Queues the TTS request for async processing by the service task
     /// - Audio output is broadcast to all subscribers via the audio channel
     /// - Supports both streaming and non-streaming audio generation
-    /// - Uses Kokoro API by default with fallback error handling
+    /// - Uses the active provider's backend (Kokoro by default, see `tts_provider`)
     pub async fn text_to_speech(&self, text: String, options: SpeechOptions) -> Result<(), Box<dyn Error>> {
-        let command = SpeechCommand::TextToSpeech(text, options);
+        let command = SpeechCommand::TextToSpeech(text, options, None);
         self.sender.lock().await.send(command).await.map_err(|e| Box::new(SpeechError::from(e)))?;
         Ok(())
     }
@@ -557,6 +627,20 @@ impl SpeechService {
         self.tts_provider.read().await.clone()
     }
 
+    /// Lists every TTS backend with whether it is configured and which one is active
+    pub async fn list_tts_providers(&self) -> Vec<TtsProviderInfo> {
+        let active = self.tts_provider.read().await.clone();
+        let settings = self.settings.read().await;
+        describe_providers(&settings, &active)
+    }
+
+    /// Default voice/speed/stream options for the active TTS provider
+    pub async fn default_speech_options(&self) -> SpeechOptions {
+        let active = self.tts_provider.read().await.clone();
+        let settings = self.settings.read().await;
+        tts_backend(&active).default_options(&settings)
+    }
+
     pub async fn set_stt_provider(&self, provider: STTProvider) -> Result<(), Box<dyn Error>> {
         let command = SpeechCommand::SetSTTProvider(provider);
         self.sender.lock().await.send(command).await.map_err(|e| Box::new(SpeechError::from(e)))?;
@@ -591,7 +675,69 @@ impl SpeechService {
     /// - Supports configurable Whisper parameters (model, language, temperature, etc.)
     /// - Handles multipart form upload format required by Whisper-WebUI-Backend
     pub async fn process_audio_chunk(&self, audio_data: Vec<u8>) -> Result<(), Box<dyn Error>> {
-        let command = SpeechCommand::ProcessAudioChunk(audio_data);
+        let command = SpeechCommand::ProcessAudioChunk(audio_data, None);
+        self.sender.lock().await.send(command).await.map_err(|e| Box::new(SpeechError::from(e)))?;
+        Ok(())
+    }
+
+    /// Registers a session for one WebSocket connection and returns its private receivers
+    pub fn open_session(&self, session_id: &str) -> SpeechSessionHandle {
+        let (audio_tx, audio_rx) = broadcast::channel(SESSION_CHANNEL_CAPACITY);
+        let (transcription_tx, transcription_rx) = broadcast::channel(SESSION_CHANNEL_CAPACITY);
+        let session = SpeechSession {
+            audio_tx,
+            transcription_tx,
+            transcript: None,
+            active_tts: None,
+        };
+        self.sessions.lock().unwrap().insert(session_id.to_string(), session);
+        debug!("Opened speech session {}", session_id);
+
+        SpeechSessionHandle {
+            session_id: session_id.to_string(),
+            audio_rx,
+            transcription_rx,
+        }
+    }
+
+    /// Drops a session, cancelling any TTS request still in flight for it
+    pub fn close_session(&self, session_id: &str) {
+        if let Some(session) = self.sessions.lock().unwrap().remove(session_id) {
+            if let Some(handle) = session.active_tts {
+                handle.abort();
+            }
+            debug!("Closed speech session {}", session_id);
+        }
+    }
+
+    /// Starts recording what is said in a session, returning false if there is no
+    /// such session. A recording already under way carries on.
+    pub fn start_transcript(&self, session_id: &str) -> bool {
+        match self.sessions.lock().unwrap().get_mut(session_id) {
+            Some(session) => {
+                session.transcript.get_or_insert_with(Vec::new);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Stops a session's recording and hands back what was said, if it was recording
+    pub fn take_transcript(&self, session_id: &str) -> Option<Vec<TranscriptLine>> {
+        self.sessions.lock().unwrap().get_mut(session_id)?.transcript.take()
+    }
+
+    /// Like `text_to_speech`, but audio only goes to the given session and replaces
+    /// any request that session still has in flight
+    pub async fn session_text_to_speech(&self, session_id: &str, text: String, options: SpeechOptions) -> Result<(), Box<dyn Error>> {
+        let command = SpeechCommand::TextToSpeech(text, options, Some(session_id.to_string()));
+        self.sender.lock().await.send(command).await.map_err(|e| Box::new(SpeechError::from(e)))?;
+        Ok(())
+    }
+
+    /// Like `process_audio_chunk`, but transcripts only go to the given session
+    pub async fn session_process_audio_chunk(&self, session_id: &str, audio_data: Vec<u8>) -> Result<(), Box<dyn Error>> {
+        let command = SpeechCommand::ProcessAudioChunk(audio_data, Some(session_id.to_string()));
         self.sender.lock().await.send(command).await.map_err(|e| Box::new(SpeechError::from(e)))?;
         Ok(())
     }
@@ -0,0 +1,165 @@
+//! How the knowledge base grows over time
+//!
+//! Each successful sync appends a sample of graph size, word count and orphan
+//! count to `stats_history.json`. Samples from the last `FULL_RESOLUTION_DAYS` are
+//! all kept; older ones are thinned to the last sample of each day, so years of
+//! hourly syncs stay a few hundred kilobytes.
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::config::data_dirs::DataDirs;
+use crate::models::graph::GraphData;
+use crate::models::metadata::MetadataStore;
+use crate::services::graph_quality;
+use crate::services::timeline::Granularity;
+use crate::services::vault_crypto;
+
+const FULL_RESOLUTION_DAYS: i64 = 30;
+
+fn stats_history_path() -> PathBuf {
+    DataDirs::global().metadata_file("stats_history.json")
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatsSample {
+    pub recorded_at: DateTime<Utc>,
+    pub node_count: usize,
+    pub edge_count: usize,
+    pub page_count: usize,
+    pub word_count: usize,
+    pub orphan_count: usize,
+}
+
+impl StatsSample {
+    /// Measures `graph`; `word_count` comes from the markdown, which the graph
+    /// doesn't hold
+    pub fn of(graph: &GraphData, word_count: usize, recorded_at: DateTime<Utc>) -> Self {
+        let quality = graph_quality::analyse(graph);
+        Self {
+            recorded_at,
+            node_count: graph.nodes.len(),
+            edge_count: graph.edges.len(),
+            page_count: quality.page_count,
+            word_count,
+            orphan_count: quality.orphans.len(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct StatsHistory {
+    pub samples: Vec<StatsSample>,
+}
+
+impl StatsHistory {
+    pub fn load() -> Self {
+        match fs::read_to_string(stats_history_path()) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable stats history: {}", e);
+                StatsHistory::default()
+            }),
+            Err(_) => StatsHistory::default(),
+        }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = stats_history_path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_string(self).map_err(io::Error::from)?;
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, path)
+    }
+
+    /// Adds `sample` and thins samples that have aged out of full resolution
+    pub fn record(&mut self, sample: StatsSample) {
+        let now = sample.recorded_at;
+        self.samples.push(sample);
+        self.samples.sort_by_key(|sample| sample.recorded_at);
+
+        let cutoff = now - Duration::days(FULL_RESOLUTION_DAYS);
+        let mut last_of_day: BTreeMap<NaiveDate, StatsSample> = BTreeMap::new();
+        let mut recent = Vec::new();
+        for sample in self.samples.drain(..) {
+            if sample.recorded_at >= cutoff {
+                recent.push(sample);
+            } else {
+                last_of_day.insert(sample.recorded_at.date_naive(), sample);
+            }
+        }
+        self.samples = last_of_day.into_values().chain(recent).collect();
+    }
+
+    /// The last sample of each bucket since `since`, oldest first
+    pub fn trend(&self, since: Option<DateTime<Utc>>, granularity: Granularity) -> Vec<StatsSample> {
+        let mut buckets: BTreeMap<NaiveDate, &StatsSample> = BTreeMap::new();
+        for sample in &self.samples {
+            if since.is_some_and(|since| sample.recorded_at < since) {
+                continue;
+            }
+            buckets.insert(granularity.bucket(sample.recorded_at.date_naive()), sample);
+        }
+        buckets.into_values().cloned().collect()
+    }
+}
+
+/// Words across the markdown of every file in `metadata`
+pub fn word_count(metadata: &MetadataStore) -> usize {
+    let dirs = DataDirs::global();
+    metadata.keys()
+        .filter_map(|file_name| vault_crypto::read_to_string(dirs.markdown_file(file_name)).ok())
+        .map(|content| content.split_whitespace().count())
+        .sum()
+}
+
+/// Samples `graph` now and appends it to the stored history
+pub fn record_sync(graph: &GraphData, metadata: &MetadataStore) -> io::Result<StatsSample> {
+    let sample = StatsSample::of(graph, word_count(metadata), Utc::now());
+    let mut history = StatsHistory::load();
+    history.record(sample.clone());
+    history.save()?;
+    Ok(sample)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn sample(recorded_at: DateTime<Utc>, node_count: usize) -> StatsSample {
+        StatsSample { recorded_at, node_count, edge_count: 0, page_count: 0, word_count: 0, orphan_count: 0 }
+    }
+
+    #[test]
+    fn test_record_thins_old_samples() {
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let mut history = StatsHistory::default();
+        // Every six hours for 60 days
+        for i in 0..240 {
+            history.record(sample(start + Duration::hours(6 * i), i as usize));
+        }
+        let now = history.samples.last().unwrap().recorded_at;
+        let cutoff = now - Duration::days(FULL_RESOLUTION_DAYS);
+        let old: Vec<&StatsSample> = history.samples.iter().filter(|s| s.recorded_at < cutoff).collect();
+        assert_eq!(old.len(), 30);
+        // The last sample of the first day was taken at 18:00
+        assert_eq!(old[0].node_count, 3);
+        assert!(history.samples.len() < 240);
+
+        let monthly = history.trend(None, Granularity::Month);
+        assert_eq!(monthly.len(), 2);
+        assert_eq!(monthly[0].recorded_at.date_naive().to_string(), "2024-01-31");
+        assert_eq!(monthly[1].node_count, 239);
+        let since = history.trend(Some(start + Duration::days(59)), Granularity::Day);
+        assert_eq!(since.len(), 1);
+    }
+}
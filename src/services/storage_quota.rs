@@ -0,0 +1,285 @@
+//! Disk usage limits for the data directory
+//!
+//! The markdown mirror is what the graph is built from, so it is only counted. The
+//! GitHub blob cache and the speech audio cache hold content that can be fetched or
+//! synthesized again, and are evicted least recently used first when they go over
+//! their limits.
+//!
+//! `STORAGE_LIMIT_MB` caps markdown, blobs and audio together; `BLOB_CACHE_LIMIT_MB`
+//! caps the blob cache on its own. Both are unlimited when unset.
+
+use log::{debug, info, warn};
+use serde::Serialize;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use crate::config::data_dirs::DataDirs;
+
+const BYTES_PER_MB: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StorageLimits {
+    pub total_bytes: Option<u64>,
+    pub blob_cache_bytes: Option<u64>,
+}
+
+impl StorageLimits {
+    pub fn from_env() -> Self {
+        Self {
+            total_bytes: env_megabytes("STORAGE_LIMIT_MB"),
+            blob_cache_bytes: env_megabytes("BLOB_CACHE_LIMIT_MB"),
+        }
+    }
+}
+
+fn env_megabytes(name: &str) -> Option<u64> {
+    env::var(name).ok()
+        .and_then(|value| value.trim().parse::<u64>().ok())
+        .filter(|mb| *mb > 0)
+        .map(|mb| mb * BYTES_PER_MB)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AreaKind {
+    Markdown,
+    Blobs,
+    Audio,
+}
+
+impl AreaKind {
+    fn name(&self) -> &'static str {
+        match self {
+            AreaKind::Markdown => "markdown",
+            AreaKind::Blobs => "blobCache",
+            AreaKind::Audio => "audioCache",
+        }
+    }
+
+    fn evictable(&self) -> bool {
+        *self != AreaKind::Markdown
+    }
+
+    /// Whether `path` is an entry of this area rather than an index or temp file
+    fn holds(&self, path: &Path) -> bool {
+        let extension = path.extension().and_then(|e| e.to_str());
+        match self {
+            AreaKind::Markdown => extension == Some("md"),
+            AreaKind::Blobs => extension.is_none(),
+            AreaKind::Audio => extension == Some("audio"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AreaUsage {
+    pub name: &'static str,
+    pub path: String,
+    pub files: usize,
+    pub bytes: u64,
+    pub limit_bytes: Option<u64>,
+    pub evictable: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageStats {
+    pub areas: Vec<AreaUsage>,
+    pub total_bytes: u64,
+    pub limit_bytes: Option<u64>,
+    /// Set when the limit can't be met by eviction alone
+    pub over_quota: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Eviction {
+    pub files: usize,
+    pub bytes: u64,
+}
+
+struct Entry {
+    used: SystemTime,
+    len: u64,
+    path: PathBuf,
+}
+
+pub struct StorageQuota {
+    limits: StorageLimits,
+    areas: Vec<(AreaKind, PathBuf)>,
+}
+
+impl StorageQuota {
+    pub fn new(limits: StorageLimits, markdown: PathBuf, blobs: PathBuf, audio: PathBuf) -> Self {
+        Self {
+            limits,
+            areas: vec![(AreaKind::Markdown, markdown), (AreaKind::Blobs, blobs), (AreaKind::Audio, audio)],
+        }
+    }
+
+    /// Limits from the environment over the configured data directories
+    pub fn from_env() -> Self {
+        let dirs = DataDirs::global();
+        Self::new(StorageLimits::from_env(), dirs.markdown.clone(), dirs.blobs(), dirs.audio_cache())
+    }
+
+    /// Use `dir` for the audio cache, for when the speech settings move it
+    pub fn with_audio_dir(mut self, dir: PathBuf) -> Self {
+        for (kind, path) in &mut self.areas {
+            if *kind == AreaKind::Audio {
+                *path = dir.clone();
+            }
+        }
+        self
+    }
+
+    fn limit_for(&self, kind: AreaKind) -> Option<u64> {
+        match kind {
+            AreaKind::Blobs => self.limits.blob_cache_bytes,
+            _ => None,
+        }
+    }
+
+    pub fn stats(&self) -> StorageStats {
+        let areas: Vec<AreaUsage> = self.areas.iter()
+            .map(|(kind, dir)| {
+                let entries = entries(dir, *kind);
+                AreaUsage {
+                    name: kind.name(),
+                    path: dir.display().to_string(),
+                    files: entries.len(),
+                    bytes: entries.iter().map(|entry| entry.len).sum(),
+                    limit_bytes: self.limit_for(*kind),
+                    evictable: kind.evictable(),
+                }
+            })
+            .collect();
+        let total_bytes = areas.iter().map(|area| area.bytes).sum();
+        let over_quota = self.limits.total_bytes.is_some_and(|limit| total_bytes > limit);
+        StorageStats { areas, total_bytes, limit_bytes: self.limits.total_bytes, over_quota }
+    }
+
+    /// Evicts cached content until the blob cache and the total fit their limits
+    pub fn enforce(&self) -> io::Result<Eviction> {
+        let mut eviction = Eviction::default();
+        let mut evictable = Vec::new();
+        let mut total: u64 = 0;
+
+        for (kind, dir) in &self.areas {
+            let mut entries = entries(dir, *kind);
+            let mut bytes: u64 = entries.iter().map(|entry| entry.len).sum();
+            if let Some(limit) = self.limit_for(*kind) {
+                entries.sort_by_key(|entry| entry.used);
+                entries.reverse();
+                while bytes > limit {
+                    let Some(entry) = entries.pop() else { break };
+                    if remove(&entry, &mut eviction) {
+                        bytes -= entry.len;
+                    }
+                }
+            }
+            total += bytes;
+            if kind.evictable() {
+                evictable.extend(entries);
+            }
+        }
+
+        if let Some(limit) = self.limits.total_bytes {
+            evictable.sort_by_key(|entry| entry.used);
+            let mut oldest = evictable.into_iter();
+            while total > limit {
+                let Some(entry) = oldest.next() else { break };
+                if remove(&entry, &mut eviction) {
+                    total -= entry.len;
+                }
+            }
+            if total > limit {
+                warn!("Stored data uses {} bytes, over the {} byte limit, with no cached content left to evict", total, limit);
+            }
+        }
+
+        if eviction.files > 0 {
+            info!("Evicted {} cached files ({} bytes) to stay within storage limits", eviction.files, eviction.bytes);
+        }
+        Ok(eviction)
+    }
+}
+
+fn remove(entry: &Entry, eviction: &mut Eviction) -> bool {
+    match fs::remove_file(&entry.path) {
+        Ok(()) => {
+            debug!("Evicted {:?}", entry.path);
+            eviction.files += 1;
+            eviction.bytes += entry.len;
+            true
+        }
+        Err(e) => {
+            warn!("Failed to evict {:?}: {}", entry.path, e);
+            false
+        }
+    }
+}
+
+/// Files of `kind` under `dir`, recursively; a missing directory is empty
+fn entries(dir: &Path, kind: AreaKind) -> Vec<Entry> {
+    let mut found = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(read_dir) = fs::read_dir(&dir) else { continue };
+        for entry in read_dir.flatten() {
+            let Ok(meta) = entry.metadata() else { continue };
+            let path = entry.path();
+            if meta.is_dir() {
+                pending.push(path);
+            } else if kind.holds(&path) {
+                let used = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                found.push(Entry { used, len: meta.len(), path });
+            }
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn write_aged(path: &Path, len: usize, age_secs: u64) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, vec![b'x'; len]).unwrap();
+        let used = SystemTime::now() - Duration::from_secs(age_secs);
+        fs::File::options().write(true).open(path).unwrap().set_modified(used).unwrap();
+    }
+
+    #[test]
+    fn test_enforce_evicts_oldest_cached_content() {
+        let root = std::env::temp_dir().join(format!("storage_quota_test_{}", uuid::Uuid::new_v4()));
+        let dirs = DataDirs::under(&root);
+        write_aged(&dirs.markdown_file("Page.md"), 100, 300);
+        write_aged(&dirs.blobs().join("ab").join("abcd"), 100, 200);
+        write_aged(&dirs.blobs().join("cd").join("cdef"), 100, 10);
+        write_aged(&dirs.blobs().join("etags.json"), 10, 400);
+        write_aged(&dirs.audio_cache().join("key.audio"), 100, 100);
+
+        let limits = StorageLimits { total_bytes: Some(250), blob_cache_bytes: None };
+        let quota = StorageQuota::new(limits, dirs.markdown.clone(), dirs.blobs(), dirs.audio_cache());
+        assert_eq!(quota.stats().total_bytes, 400);
+
+        // Markdown is never evicted, so the two oldest cache entries go
+        assert_eq!(quota.enforce().unwrap(), Eviction { files: 2, bytes: 200 });
+        assert!(dirs.markdown_file("Page.md").exists());
+        assert!(!dirs.blobs().join("ab").join("abcd").exists());
+        assert!(!dirs.audio_cache().join("key.audio").exists());
+        assert!(dirs.blobs().join("cd").join("cdef").exists());
+        assert!(!quota.stats().over_quota);
+
+        let tight = StorageQuota::new(StorageLimits { total_bytes: Some(50), blob_cache_bytes: None },
+            dirs.markdown.clone(), dirs.blobs(), dirs.audio_cache());
+        tight.enforce().unwrap();
+        assert!(tight.stats().over_quota);
+        let _ = fs::remove_dir_all(root);
+    }
+}
@@ -0,0 +1,226 @@
+//! Persistent GitHub sync progress
+//!
+//! `FileService::fetch_and_process_files` records which blob SHA of each file it has
+//! processed and which files are still pending. The state is checkpointed to disk as
+//! the sync goes, so a crash or a rate-limit abort resumes with the remaining files
+//! on the next run instead of downloading the whole vault again.
+
+use crate::services::github::GitHubFileMetadata;
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use crate::config::data_dirs::DataDirs;
+use crate::services::reference_parser::normalize_name;
+
+fn sync_state_path() -> PathBuf {
+    DataDirs::global().metadata_file("sync_state.json")
+}
+
+// Set while a sync is running in this process; a state file saying "running"
+// without this flag was left behind by a process that died mid-sync
+static SYNC_ACTIVE: AtomicBool = AtomicBool::new(false);
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub enum SyncStatus {
+    #[default]
+    Idle,
+    Running,
+    /// Every file was processed
+    Completed,
+    /// The run finished but some files failed and are still pending
+    Partial,
+    /// The process stopped mid-sync
+    Interrupted,
+    /// The run could not start or aborted, e.g. listing the repository failed
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProcessedEntry {
+    /// Git blob SHA reported by GitHub when the file was processed
+    pub sha: String,
+    pub public: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncState {
+    pub status: SyncStatus,
+    pub started_at: Option<DateTime<Utc>>,
+    pub updated_at: Option<DateTime<Utc>>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub total: usize,
+    #[serde(default)]
+    pub processed: HashMap<String, ProcessedEntry>,
+    #[serde(default)]
+    pub pending: Vec<String>,
+    /// File name to the error from its last attempt
+    #[serde(default)]
+    pub failed: HashMap<String, String>,
+    pub last_error: Option<String>,
+    /// Files gone from the repository whose removal was held back for confirmation
+    #[serde(default)]
+    pub held_deletions: Vec<String>,
+}
+
+impl SyncState {
+    /// Loads the persisted state, or a fresh one if none exists or it can't be read
+    pub fn load() -> Self {
+        let mut state: SyncState = match fs::read_to_string(sync_state_path()) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable sync state: {}", e);
+                SyncState::default()
+            }),
+            Err(_) => SyncState::default(),
+        };
+        if state.status == SyncStatus::Running && !SYNC_ACTIVE.load(Ordering::SeqCst) {
+            state.status = SyncStatus::Interrupted;
+        }
+        state
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        if let Some(parent) = sync_state_path().parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::from)?;
+        let tmp = sync_state_path().with_extension("json.tmp");
+        fs::write(&tmp, json)?;
+        fs::rename(&tmp, sync_state_path())
+    }
+
+    /// Saves the state, logging instead of failing; losing a checkpoint only costs
+    /// re-downloading some files on the next run
+    pub fn checkpoint(&self) {
+        if let Err(e) = self.save() {
+            error!("Failed to save sync state: {}", e);
+        }
+    }
+
+    /// Starts a run over `files` and returns the ones that still need fetching.
+    /// A file is skipped when it was already processed at the same blob SHA and, if
+    /// public, `is_known` confirms its metadata is present.
+    pub fn begin<F>(&mut self, files: &[GitHubFileMetadata], is_known: F) -> Vec<GitHubFileMetadata>
+    where
+        F: Fn(&str) -> bool,
+    {
+        if matches!(self.status, SyncStatus::Interrupted | SyncStatus::Partial | SyncStatus::Failed)
+            && !self.pending.is_empty()
+        {
+            info!("Resuming previous sync with {} pending files", self.pending.len());
+        }
+
+        // Forget files that have been removed from the repository
+        let listed: HashSet<&str> = files.iter().map(|f| f.name.as_str()).collect();
+        self.processed.retain(|name, _| listed.contains(name.as_str()));
+
+        let to_fetch: Vec<GitHubFileMetadata> = files.iter()
+            .filter(|file| match self.processed.get(&file.name) {
+                Some(entry) => entry.sha != file.sha || file.sha.is_empty() || (entry.public && !is_known(&file.name)),
+                None => true,
+            })
+            .cloned()
+            .collect();
+
+        let now = Utc::now();
+        SYNC_ACTIVE.store(true, Ordering::SeqCst);
+        self.status = SyncStatus::Running;
+        self.started_at = Some(now);
+        self.updated_at = Some(now);
+        self.finished_at = None;
+        self.total = files.len();
+        self.pending = to_fetch.iter().map(|f| f.name.clone()).collect();
+        self.failed.clear();
+        self.last_error = None;
+        to_fetch
+    }
+
+    pub fn mark_processed(&mut self, file_name: &str, sha: &str, public: bool) {
+        self.processed.insert(file_name.to_string(), ProcessedEntry { sha: sha.to_string(), public });
+        self.pending.retain(|name| name != file_name);
+        self.failed.remove(file_name);
+        self.updated_at = Some(Utc::now());
+    }
+
+    /// Normalised page names of files skipped because they aren't public
+    pub fn private_pages(&self) -> HashSet<String> {
+        self.processed.iter()
+            .filter(|(_, entry)| !entry.public)
+            .map(|(name, _)| normalize_name(name.trim_end_matches(".md")))
+            .collect()
+    }
+
+    /// Records a failure; the file stays pending so the next run retries it
+    pub fn mark_failed(&mut self, file_name: &str, error: String) {
+        self.failed.insert(file_name.to_string(), error);
+        self.updated_at = Some(Utc::now());
+    }
+
+    pub fn finish(&mut self) {
+        let now = Utc::now();
+        self.status = if self.failed.is_empty() { SyncStatus::Completed } else { SyncStatus::Partial };
+        self.updated_at = Some(now);
+        self.finished_at = Some(now);
+        SYNC_ACTIVE.store(false, Ordering::SeqCst);
+    }
+
+    pub fn fail(&mut self, error: String) {
+        let now = Utc::now();
+        self.status = SyncStatus::Failed;
+        self.last_error = Some(error);
+        self.updated_at = Some(now);
+        self.finished_at = Some(now);
+        SYNC_ACTIVE.store(false, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(name: &str, sha: &str) -> GitHubFileMetadata {
+        GitHubFileMetadata {
+            name: name.to_string(),
+            sha: sha.to_string(),
+            download_url: String::new(),
+            etag: None,
+            last_checked: None,
+            last_modified: None,
+        }
+    }
+
+    #[test]
+    fn test_begin_skips_files_processed_at_same_sha() {
+        let mut state = SyncState::default();
+        state.mark_processed("a.md", "1", true);
+        state.mark_processed("b.md", "2", false);
+        state.mark_processed("gone.md", "3", true);
+
+        let files = vec![file("a.md", "1"), file("b.md", "2"), file("c.md", "4"), file("d.md", "5")];
+        state.mark_processed("d.md", "old", true);
+
+        let to_fetch: Vec<String> = state.begin(&files, |name| name == "a.md")
+            .into_iter()
+            .map(|f| f.name)
+            .collect();
+
+        assert_eq!(to_fetch, vec!["c.md".to_string(), "d.md".to_string()]);
+        assert_eq!(state.pending, to_fetch);
+        assert!(!state.processed.contains_key("gone.md"));
+
+        state.mark_processed("c.md", "4", true);
+        state.mark_failed("d.md", "rate limited".to_string());
+        state.finish();
+        assert_eq!(state.status, SyncStatus::Partial);
+        assert_eq!(state.pending, vec!["d.md".to_string()]);
+        assert_eq!(state.private_pages(), HashSet::from(["b".to_string()]));
+    }
+}
@@ -0,0 +1,384 @@
+//! Client telemetry: frame rate, dropped frames, WebSocket reconnects and
+//! JavaScript errors
+//!
+//! Clients post batches of events to `/api/telemetry`. Batches are rate limited
+//! per client address, error text is scrubbed of URL queries, email addresses,
+//! keys and tokens, and events are folded into per-minute buckets kept in memory
+//! for the last hour. Each minute's summary is logged once the minute is over, so
+//! client jank can be lined up with server log lines from the same minute.
+//! Nothing is written to disk and client addresses are never logged.
+
+use chrono::{DateTime, TimeZone, Utc};
+use log::{info, warn};
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const DEFAULT_BATCHES_PER_MINUTE: u32 = 30;
+const DEFAULT_MAX_EVENTS: usize = 200;
+const RETAINED_MINUTES: i64 = 60;
+const MAX_MESSAGE_CHARS: usize = 300;
+const MAX_STACK_CHARS: usize = 2000;
+const TOP_ERRORS: usize = 10;
+// Frame rates above this are treated as a client bug rather than a sample
+const MAX_FPS: f32 = 1000.0;
+
+static URL_QUERY: Lazy<Regex> = Lazy::new(|| Regex::new(r"(https?://[^\s?#]+)[?#][^\s)]*").unwrap());
+static EMAIL: Lazy<Regex> = Lazy::new(|| Regex::new(r"[\w.+-]+@[\w-]+(\.[\w-]+)+").unwrap());
+static NOSTR_KEY: Lazy<Regex> = Lazy::new(|| Regex::new(r"\b(npub|nsec)1[0-9a-z]{20,}\b").unwrap());
+static TOKEN: Lazy<Regex> = Lazy::new(|| Regex::new(r"\b[A-Za-z0-9_\-]{32,}\b").unwrap());
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TelemetryLimits {
+    pub enabled: bool,
+    /// Batches one client address may post per minute
+    pub batches_per_minute: u32,
+    /// Most events one batch may carry
+    pub max_events: usize,
+}
+
+impl Default for TelemetryLimits {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            batches_per_minute: DEFAULT_BATCHES_PER_MINUTE,
+            max_events: DEFAULT_MAX_EVENTS,
+        }
+    }
+}
+
+impl TelemetryLimits {
+    /// Reads `TELEMETRY_ENABLED`, `TELEMETRY_BATCHES_PER_MINUTE` and
+    /// `TELEMETRY_MAX_EVENTS`
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        Self {
+            enabled: std::env::var("TELEMETRY_ENABLED")
+                .map(|v| !v.trim().eq_ignore_ascii_case("false"))
+                .unwrap_or(default.enabled),
+            batches_per_minute: std::env::var("TELEMETRY_BATCHES_PER_MINUTE").ok()
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(default.batches_per_minute),
+            max_events: std::env::var("TELEMETRY_MAX_EVENTS").ok()
+                .and_then(|v| v.trim().parse().ok())
+                .unwrap_or(default.max_events),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum ClientEvent {
+    Fps { fps: f32 },
+    DroppedFrames { count: u32 },
+    WsReconnect {
+        #[serde(default)]
+        reason: Option<String>,
+    },
+    Error {
+        message: String,
+        /// Script URL the error came from
+        #[serde(default)]
+        source: Option<String>,
+        #[serde(default)]
+        stack: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimedEvent {
+    /// When the client saw the event, in milliseconds since the epoch. Events
+    /// without one, or with one outside the retained hour, count at arrival.
+    #[serde(default)]
+    pub at: Option<i64>,
+    #[serde(flatten)]
+    pub event: ClientEvent,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TelemetryBatch {
+    pub events: Vec<TimedEvent>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TelemetryError {
+    Disabled,
+    /// The batch can't be accepted as sent
+    Invalid(String),
+    /// The client posted too often; it may retry after this many seconds
+    RateLimited(u64),
+}
+
+impl std::fmt::Display for TelemetryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TelemetryError::Disabled => f.write_str("Telemetry is disabled"),
+            TelemetryError::Invalid(message) => f.write_str(message),
+            TelemetryError::RateLimited(seconds) => write!(f, "Too many telemetry batches; retry in {}s", seconds),
+        }
+    }
+}
+
+/// Removes what could identify a user from client error text, then truncates it
+pub fn scrub(text: &str, max_chars: usize) -> String {
+    let text = URL_QUERY.replace_all(text, "$1");
+    let text = EMAIL.replace_all(&text, "<email>");
+    let text = NOSTR_KEY.replace_all(&text, "<key>");
+    let text = TOKEN.replace_all(&text, "<token>");
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}…", &text[..end]),
+        None => text.into_owned(),
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorCount {
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// Stack of the first occurrence in the minute
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stack: Option<String>,
+    pub count: u64,
+}
+
+#[derive(Debug, Default)]
+struct Bucket {
+    batches: u64,
+    fps_sum: f64,
+    fps_samples: u64,
+    fps_min: Option<f32>,
+    dropped_frames: u64,
+    ws_reconnects: u64,
+    errors: HashMap<(String, Option<String>), ErrorCount>,
+    logged: bool,
+}
+
+/// What clients reported in one minute
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MinuteSummary {
+    pub minute: DateTime<Utc>,
+    pub batches: u64,
+    pub fps_samples: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fps_mean: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fps_min: Option<f32>,
+    pub dropped_frames: u64,
+    pub ws_reconnects: u64,
+    pub errors: u64,
+    /// Most frequent errors, most first
+    pub top_errors: Vec<ErrorCount>,
+}
+
+impl Bucket {
+    fn summary(&self, minute: i64) -> MinuteSummary {
+        let mut top_errors: Vec<ErrorCount> = self.errors.values().cloned().collect();
+        top_errors.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.message.cmp(&b.message)));
+        top_errors.truncate(TOP_ERRORS);
+        MinuteSummary {
+            minute: Utc.timestamp_opt(minute * 60, 0).single().unwrap_or_default(),
+            batches: self.batches,
+            fps_samples: self.fps_samples,
+            fps_mean: (self.fps_samples > 0).then(|| (self.fps_sum / self.fps_samples as f64) as f32),
+            fps_min: self.fps_min,
+            dropped_frames: self.dropped_frames,
+            ws_reconnects: self.ws_reconnects,
+            errors: self.errors.values().map(|error| error.count).sum(),
+            top_errors,
+        }
+    }
+
+    fn add(&mut self, event: ClientEvent) {
+        match event {
+            ClientEvent::Fps { fps } => {
+                if fps.is_finite() && (0.0..=MAX_FPS).contains(&fps) {
+                    self.fps_sum += fps as f64;
+                    self.fps_samples += 1;
+                    self.fps_min = Some(self.fps_min.map_or(fps, |min| min.min(fps)));
+                }
+            }
+            ClientEvent::DroppedFrames { count } => self.dropped_frames += count as u64,
+            ClientEvent::WsReconnect { .. } => self.ws_reconnects += 1,
+            ClientEvent::Error { message, source, stack } => {
+                let message = scrub(&message, MAX_MESSAGE_CHARS);
+                let source = source.map(|source| scrub(&source, MAX_MESSAGE_CHARS));
+                let error = self.errors.entry((message.clone(), source.clone())).or_insert_with(|| {
+                    warn!("[Telemetry] Client error: {}{}", message, source.as_deref().map(|s| format!(" ({})", s)).unwrap_or_default());
+                    ErrorCount { message, source, stack: stack.map(|stack| scrub(&stack, MAX_STACK_CHARS)), count: 0 }
+                });
+                error.count += 1;
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct TelemetryState {
+    /// Keyed by minutes since the epoch
+    buckets: BTreeMap<i64, Bucket>,
+    /// Batches per client address in the current minute
+    rate: HashMap<String, u32>,
+    rate_minute: i64,
+}
+
+pub struct TelemetryService {
+    limits: TelemetryLimits,
+    state: Mutex<TelemetryState>,
+}
+
+impl TelemetryService {
+    pub fn new(limits: TelemetryLimits) -> Self {
+        Self { limits, state: Mutex::new(TelemetryState::default()) }
+    }
+
+    pub fn limits(&self) -> TelemetryLimits {
+        self.limits
+    }
+
+    /// Counts a batch from `client` against its rate limit and adds its events
+    /// to the summaries. Returns how many events were accepted.
+    pub fn ingest(&self, client: &str, batch: TelemetryBatch) -> Result<usize, TelemetryError> {
+        self.ingest_at(client, batch, Utc::now())
+    }
+
+    fn ingest_at(&self, client: &str, batch: TelemetryBatch, now: DateTime<Utc>) -> Result<usize, TelemetryError> {
+        if !self.limits.enabled {
+            return Err(TelemetryError::Disabled);
+        }
+        if batch.events.len() > self.limits.max_events {
+            return Err(TelemetryError::Invalid(format!(
+                "Batch has {} events; the limit is {}", batch.events.len(), self.limits.max_events
+            )));
+        }
+
+        let now_ms = now.timestamp_millis();
+        let minute = now.timestamp().div_euclid(60);
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+
+        if state.rate_minute != minute {
+            state.rate.clear();
+            state.rate_minute = minute;
+        }
+        let sent = state.rate.entry(client.to_string()).or_insert(0);
+        if *sent >= self.limits.batches_per_minute {
+            return Err(TelemetryError::RateLimited((60 - now.timestamp().rem_euclid(60)) as u64));
+        }
+        *sent += 1;
+
+        let oldest = minute - RETAINED_MINUTES + 1;
+        state.buckets.entry(minute).or_default().batches += 1;
+        let accepted = batch.events.len();
+        for TimedEvent { at, event } in batch.events {
+            let event_minute = at
+                .filter(|&at| at <= now_ms)
+                .map(|at| at.div_euclid(60_000))
+                .filter(|&event_minute| event_minute >= oldest)
+                .unwrap_or(minute);
+            state.buckets.entry(event_minute).or_default().add(event);
+        }
+        state.buckets.retain(|&bucket_minute, _| bucket_minute >= oldest);
+        Ok(accepted)
+    }
+
+    /// Summaries of the retained minutes, oldest first
+    pub fn summaries(&self) -> Vec<MinuteSummary> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.buckets.iter().map(|(&minute, bucket)| bucket.summary(minute)).collect()
+    }
+
+    /// Summaries of finished minutes that haven't been logged yet, marking them logged
+    fn take_unlogged(&self, now: DateTime<Utc>) -> Vec<MinuteSummary> {
+        let current = now.timestamp().div_euclid(60);
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.buckets.range_mut(..current)
+            .filter(|(_, bucket)| !bucket.logged)
+            .map(|(&minute, bucket)| {
+                bucket.logged = true;
+                bucket.summary(minute)
+            })
+            .collect()
+    }
+
+    /// Logs each minute's summary once the minute is over
+    pub fn start(self: &Arc<Self>) {
+        if !self.limits.enabled {
+            return;
+        }
+        let telemetry = self.clone();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(Duration::from_secs(15));
+            loop {
+                interval.tick().await;
+                for summary in telemetry.take_unlogged(Utc::now()) {
+                    info!(
+                        "[Telemetry] {}: {} batches, fps mean {} min {}, {} dropped frames, {} reconnects, {} errors",
+                        summary.minute.format("%H:%M"),
+                        summary.batches,
+                        summary.fps_mean.map_or("-".to_string(), |fps| format!("{:.1}", fps)),
+                        summary.fps_min.map_or("-".to_string(), |fps| format!("{:.1}", fps)),
+                        summary.dropped_frames,
+                        summary.ws_reconnects,
+                        summary.errors,
+                    );
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn batch(json: serde_json::Value) -> TelemetryBatch {
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn test_scrub_removes_identifying_text() {
+        let scrubbed = scrub(
+            "Failed https://example.com/app?token=abc#x for alice@example.org npub1qqqqqqqqqqqqqqqqqqqqqqqqqqqqqq key 0123456789abcdef0123456789abcdef01",
+            MAX_MESSAGE_CHARS,
+        );
+        assert_eq!(scrubbed, "Failed https://example.com/app for <email> <key> key <token>");
+        assert_eq!(scrub("abcdef", 3), "abc…");
+    }
+
+    #[test]
+    fn test_ingest_aggregates_and_rate_limits() {
+        let service = TelemetryService::new(TelemetryLimits { enabled: true, batches_per_minute: 2, max_events: 4 });
+        let now = Utc.with_ymd_and_hms(2024, 3, 1, 10, 0, 30).unwrap();
+        let earlier = now.timestamp_millis() - 60_000;
+        let events = batch(serde_json::json!({ "events": [
+            { "type": "fps", "fps": 30.0 },
+            { "type": "fps", "fps": 60.0 },
+            { "type": "droppedFrames", "count": 3, "at": earlier },
+            { "type": "error", "message": "boom for bob@example.com" },
+        ]}));
+        assert_eq!(service.ingest_at("10.0.0.1", events.clone(), now), Ok(4));
+        assert_eq!(service.ingest_at("10.0.0.1", batch(serde_json::json!({ "events": [{ "type": "wsReconnect" }] })), now), Ok(1));
+        assert_eq!(service.ingest_at("10.0.0.1", events.clone(), now), Err(TelemetryError::RateLimited(30)));
+        assert!(service.ingest_at("10.0.0.2", events, now).is_ok());
+
+        let summaries = service.summaries();
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(summaries[0].dropped_frames, 6);
+        let current = &summaries[1];
+        assert_eq!(current.batches, 3);
+        assert_eq!(current.fps_mean, Some(45.0));
+        assert_eq!(current.fps_min, Some(30.0));
+        assert_eq!(current.ws_reconnects, 1);
+        assert_eq!(current.top_errors[0].message, "boom for <email>");
+        assert_eq!(current.top_errors[0].count, 2);
+
+        assert_eq!(service.take_unlogged(now).len(), 1);
+        assert!(service.take_unlogged(now).is_empty());
+    }
+}
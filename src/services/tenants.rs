@@ -0,0 +1,457 @@
+//! Per-user vaults for multi-tenant mode
+//!
+//! With `MULTI_TENANT_ENABLED=true`, each authenticated Nostr user can connect their
+//! own GitHub repository next to the server's vault. A tenant's vault gets its own
+//! GitHub client, metadata and graph, kept under `/app/data/tenants/<pubkey>` and
+//! never mixed with the server's markdown mirror or the simulated graph. Tenant
+//! graphs are laid out once per sync rather than simulated. Quotas cap how many
+//! vaults the server hosts and how large each may be; a sync that would exceed
+//! them leaves the previous graph in place. A vault registered without a token uses
+//! the GitHub account the user connected through OAuth; one registered with a token
+//! needs `VAULT_ENCRYPTION_KEY` to seal it.
+//!
+//! Tenants reuse the server's listing and graph assembly but not its pipeline: there
+//! is no background sync, change feed or position stream for a tenant vault. It is
+//! synced when the user asks, and its graph is only served from
+//! `/api/tenants/vault/graph`.
+
+use chrono::{DateTime, Utc};
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+use crate::config::data_dirs::DataDirs;
+use crate::config::AppFullSettings;
+use crate::models::graph::GraphData;
+use crate::models::metadata::MetadataStore;
+use crate::services::file_service::FileService;
+use crate::services::github::{ContentAPI, GitHubClient, GitHubConfig};
+use crate::services::graph_service::GraphService;
+use crate::services::vault_crypto::VaultCipher;
+
+const DEFAULT_MAX_TENANTS: usize = 50;
+const DEFAULT_MAX_FILES: usize = 2000;
+const DEFAULT_MAX_MEGABYTES: usize = 50;
+const MISSING_TOKEN: &str = "No GitHub token: register the vault with a token or connect a GitHub account";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TenantQuota {
+    pub enabled: bool,
+    /// Most vaults the server will host
+    pub max_tenants: usize,
+    /// Most markdown files one vault may list
+    pub max_files: usize,
+    /// Largest total size of one vault's published markdown
+    pub max_bytes: usize,
+}
+
+impl Default for TenantQuota {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_tenants: DEFAULT_MAX_TENANTS,
+            max_files: DEFAULT_MAX_FILES,
+            max_bytes: DEFAULT_MAX_MEGABYTES * 1024 * 1024,
+        }
+    }
+}
+
+impl TenantQuota {
+    /// Reads `MULTI_TENANT_ENABLED`, `TENANT_MAX_VAULTS`, `TENANT_MAX_FILES` and
+    /// `TENANT_MAX_MB`
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        let number = |name: &str| std::env::var(name).ok().and_then(|v| v.trim().parse::<usize>().ok());
+        Self {
+            enabled: std::env::var("MULTI_TENANT_ENABLED")
+                .map(|v| v.trim().eq_ignore_ascii_case("true"))
+                .unwrap_or(false),
+            max_tenants: number("TENANT_MAX_VAULTS").unwrap_or(default.max_tenants),
+            max_files: number("TENANT_MAX_FILES").unwrap_or(default.max_files),
+            max_bytes: number("TENANT_MAX_MB").map(|mb| mb * 1024 * 1024).unwrap_or(default.max_bytes),
+        }
+    }
+
+    fn check_files(&self, files: usize) -> Result<(), TenantError> {
+        if files > self.max_files {
+            return Err(TenantError::QuotaExceeded(format!(
+                "Vault lists {} markdown files; the limit is {}", files, self.max_files
+            )));
+        }
+        Ok(())
+    }
+
+    fn check_bytes(&self, bytes: usize) -> Result<(), TenantError> {
+        if bytes > self.max_bytes {
+            return Err(TenantError::QuotaExceeded(format!(
+                "Vault holds {} bytes of published markdown; the limit is {}", bytes, self.max_bytes
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub enum TenantError {
+    Disabled,
+    NotFound,
+    Invalid(String),
+    QuotaExceeded(String),
+    /// GitHub couldn't be reached or refused the request
+    Upstream(String),
+    Storage(String),
+}
+
+impl std::fmt::Display for TenantError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TenantError::Disabled => f.write_str("Multi-tenant mode is disabled"),
+            TenantError::NotFound => f.write_str("No vault is registered for this user"),
+            TenantError::Invalid(message)
+            | TenantError::QuotaExceeded(message)
+            | TenantError::Upstream(message)
+            | TenantError::Storage(message) => f.write_str(message),
+        }
+    }
+}
+
+/// Which repository a vault mirrors
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VaultConnection {
+    pub owner: String,
+    pub repo: String,
+    pub base_path: String,
+    #[serde(default)]
+    pub branch: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterVault {
+    #[serde(flatten)]
+    pub connection: VaultConnection,
+    /// Personal access token; the user's OAuth token is used when omitted
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TenantSyncStatus {
+    pub synced_at: DateTime<Utc>,
+    pub file_count: usize,
+    pub total_bytes: usize,
+    pub node_count: usize,
+    pub edge_count: usize,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TenantVault {
+    pub pubkey: String,
+    #[serde(flatten)]
+    pub connection: VaultConnection,
+    /// GitHub token, sealed with `VAULT_ENCRYPTION_KEY`; a vault can't be registered
+    /// with a token when no key is set
+    #[serde(default)]
+    sealed_token: Option<String>,
+    pub registered_at: DateTime<Utc>,
+    pub last_sync: Option<TenantSyncStatus>,
+}
+
+impl TenantVault {
+    /// Whether the vault was registered with its own token
+    pub fn has_token(&self) -> bool {
+        self.sealed_token.is_some()
+    }
+
+    fn github_config(&self, oauth_token: Option<String>) -> Result<GitHubConfig, TenantError> {
+        let token = match &self.sealed_token {
+            Some(sealed_token) => {
+                let sealed = BASE64.decode(sealed_token)
+                    .map_err(|e| TenantError::Storage(format!("Stored token is corrupt: {}", e)))?;
+                VaultCipher::global()
+                    .and_then(|cipher| cipher.decode_string(&sealed))
+                    .map_err(|e| TenantError::Storage(format!("Failed to read stored token: {}", e)))?
+            }
+            None => oauth_token.ok_or_else(|| TenantError::Invalid(MISSING_TOKEN.to_string()))?,
+        };
+        let connection = self.connection.clone();
+        GitHubConfig::for_repository(token, connection.owner, connection.repo, connection.base_path, connection.branch)
+            .map_err(|e| TenantError::Invalid(e.to_string()))
+    }
+}
+
+/// Nostr pubkeys are hex, which also keeps them safe to use as directory names
+fn validate_pubkey(pubkey: &str) -> Result<(), TenantError> {
+    if pubkey.is_empty() || pubkey.len() > 64 || !pubkey.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(TenantError::Invalid(format!("Invalid pubkey '{}'", pubkey)));
+    }
+    Ok(())
+}
+
+fn tenant_dir(pubkey: &str) -> PathBuf {
+    DataDirs::global().tenants().join(pubkey)
+}
+
+pub struct TenantRegistry {
+    quota: TenantQuota,
+    settings: Arc<RwLock<AppFullSettings>>,
+    vaults: RwLock<HashMap<String, TenantVault>>,
+    graphs: RwLock<HashMap<String, Arc<GraphData>>>,
+}
+
+impl TenantRegistry {
+    pub fn new(quota: TenantQuota, settings: Arc<RwLock<AppFullSettings>>) -> Self {
+        let vaults = if quota.enabled {
+            Self::load_vaults().unwrap_or_else(|e| {
+                debug!("[Tenants] No stored vaults loaded: {}", e);
+                HashMap::new()
+            })
+        } else {
+            HashMap::new()
+        };
+        if quota.enabled {
+            info!("[Tenants] Multi-tenant mode enabled with {} registered vaults", vaults.len());
+        }
+        Self {
+            quota,
+            settings,
+            vaults: RwLock::new(vaults),
+            graphs: RwLock::new(HashMap::new()),
+        }
+    }
+
+    pub fn quota(&self) -> TenantQuota {
+        self.quota
+    }
+
+    fn ensure_enabled(&self) -> Result<(), TenantError> {
+        if self.quota.enabled { Ok(()) } else { Err(TenantError::Disabled) }
+    }
+
+    /// Connects `pubkey` to a repository, replacing any earlier connection. Moving
+    /// to another repository drops the graph built from the old one. Without a token
+    /// in the request, `oauth_token` must be given and is used from then on.
+    pub async fn register(
+        &self,
+        pubkey: &str,
+        request: RegisterVault,
+        oauth_token: Option<String>,
+    ) -> Result<TenantVault, TenantError> {
+        self.ensure_enabled()?;
+        validate_pubkey(pubkey)?;
+        let RegisterVault { connection, token } = request;
+        let token = token.map(|t| t.trim().to_string()).filter(|t| !t.is_empty());
+        let config = GitHubConfig::for_repository(
+            token.clone().or(oauth_token).ok_or_else(|| TenantError::Invalid(MISSING_TOKEN.to_string()))?,
+            connection.owner.clone(),
+            connection.repo.clone(),
+            connection.base_path.clone(),
+            connection.branch.clone(),
+        ).map_err(|e| TenantError::Invalid(e.to_string()))?;
+        let sealed = match token {
+            Some(token) => Some(VaultCipher::global()
+                .and_then(|cipher| cipher.seal_secret(token.as_bytes()))
+                .map_err(|e| TenantError::Storage(format!("Failed to seal token: {}", e)))?),
+            None => None,
+        };
+        let connection = VaultConnection { branch: config.branch.clone(), ..connection };
+
+        let mut vaults = self.vaults.write().await;
+        let previous = vaults.get(pubkey);
+        if previous.is_none() && vaults.len() >= self.quota.max_tenants {
+            return Err(TenantError::QuotaExceeded(format!(
+                "The server already hosts its limit of {} vaults", self.quota.max_tenants
+            )));
+        }
+        let moved = previous.is_some_and(|p| p.connection != connection);
+        let vault = TenantVault {
+            pubkey: pubkey.to_string(),
+            registered_at: previous.map(|p| p.registered_at).unwrap_or_else(Utc::now),
+            last_sync: previous.filter(|_| !moved).and_then(|p| p.last_sync.clone()),
+            connection,
+            sealed_token: sealed.map(|sealed| BASE64.encode(sealed)),
+        };
+        vaults.insert(pubkey.to_string(), vault.clone());
+        Self::save_vaults(&vaults).map_err(|e| TenantError::Storage(format!("Failed to persist vaults: {}", e)))?;
+        drop(vaults);
+
+        if moved {
+            self.graphs.write().await.remove(pubkey);
+            if let Err(e) = fs::remove_file(tenant_dir(pubkey).join("metadata.json")) {
+                debug!("[Tenants] No metadata to drop for {}: {}", pubkey, e);
+            }
+        }
+        info!("[Tenants] Registered {}/{} for {}", vault.connection.owner, vault.connection.repo, pubkey);
+        Ok(vault)
+    }
+
+    pub async fn get(&self, pubkey: &str) -> Option<TenantVault> {
+        self.vaults.read().await.get(pubkey).cloned()
+    }
+
+    /// Registered vaults, oldest first
+    pub async fn list(&self) -> Vec<TenantVault> {
+        let mut vaults: Vec<TenantVault> = self.vaults.read().await.values().cloned().collect();
+        vaults.sort_by(|a, b| a.registered_at.cmp(&b.registered_at).then_with(|| a.pubkey.cmp(&b.pubkey)));
+        vaults
+    }
+
+    /// Disconnects `pubkey` and deletes everything stored for its vault
+    pub async fn remove(&self, pubkey: &str) -> Result<(), TenantError> {
+        self.ensure_enabled()?;
+        let mut vaults = self.vaults.write().await;
+        if vaults.remove(pubkey).is_none() {
+            return Err(TenantError::NotFound);
+        }
+        Self::save_vaults(&vaults).map_err(|e| TenantError::Storage(format!("Failed to persist vaults: {}", e)))?;
+        drop(vaults);
+
+        self.graphs.write().await.remove(pubkey);
+        let dir = tenant_dir(pubkey);
+        if dir.exists() {
+            fs::remove_dir_all(&dir).map_err(|e| TenantError::Storage(format!("Failed to delete vault data: {}", e)))?;
+        }
+        info!("[Tenants] Removed vault of {}", pubkey);
+        Ok(())
+    }
+
+    /// Fetches the tenant's repository and rebuilds its graph, with `oauth_token`
+    /// for vaults registered without a token. The outcome, failed or not, is
+    /// recorded as the vault's last sync.
+    pub async fn sync(&self, pubkey: &str, oauth_token: Option<String>) -> Result<TenantSyncStatus, TenantError> {
+        self.ensure_enabled()?;
+        let vault = self.get(pubkey).await.ok_or(TenantError::NotFound)?;
+        let result = self.fetch_graph(&vault, oauth_token).await;
+        let status = match &result {
+            Ok((metadata, graph)) => TenantSyncStatus {
+                synced_at: Utc::now(),
+                file_count: metadata.len(),
+                total_bytes: metadata.values().map(|m| m.file_size).sum(),
+                node_count: graph.nodes.len(),
+                edge_count: graph.edges.len(),
+                error: None,
+            },
+            Err(e) => {
+                warn!("[Tenants] Sync of {} failed: {}", pubkey, e);
+                // The counts still describe the graph being served
+                let previous = vault.last_sync.as_ref();
+                TenantSyncStatus {
+                    synced_at: Utc::now(),
+                    file_count: previous.map_or(0, |p| p.file_count),
+                    total_bytes: previous.map_or(0, |p| p.total_bytes),
+                    node_count: previous.map_or(0, |p| p.node_count),
+                    edge_count: previous.map_or(0, |p| p.edge_count),
+                    error: Some(e.to_string()),
+                }
+            }
+        };
+
+        if let Ok((metadata, graph)) = result.as_ref() {
+            Self::save_metadata(pubkey, metadata)
+                .map_err(|e| TenantError::Storage(format!("Failed to persist vault metadata: {}", e)))?;
+            self.graphs.write().await.insert(pubkey.to_string(), Arc::new(graph.clone()));
+        }
+
+        let mut vaults = self.vaults.write().await;
+        if let Some(stored) = vaults.get_mut(pubkey) {
+            stored.last_sync = Some(status.clone());
+            if let Err(e) = Self::save_vaults(&vaults) {
+                warn!("[Tenants] Failed to persist sync status of {}: {}", pubkey, e);
+            }
+        }
+        drop(vaults);
+
+        result.map(|_| status)
+    }
+
+    async fn fetch_graph(&self, vault: &TenantVault, oauth_token: Option<String>) -> Result<(MetadataStore, GraphData), TenantError> {
+        let config = vault.github_config(oauth_token)?;
+        let client = GitHubClient::new(config, self.settings.clone()).await
+            .map_err(|e| TenantError::Upstream(format!("Failed to create GitHub client: {}", e)))?;
+        let content_api = ContentAPI::new(Arc::new(client));
+
+        let files = content_api.list_markdown_files("").await
+            .map_err(|e| TenantError::Upstream(format!("Failed to list markdown files: {}", e)))?;
+        self.quota.check_files(files.len())?;
+
+        let label = format!("{}/{}", vault.connection.owner, vault.connection.repo);
+        let current = Self::load_metadata(&vault.pubkey).unwrap_or_default();
+        let metadata = FileService::build_metadata_from_listing(&content_api, files, &label, &current).await;
+        self.quota.check_bytes(metadata.values().map(|m| m.file_size).sum())?;
+
+        let graph = GraphService::assemble_graph(&metadata, None);
+        info!("[Tenants] Synced {} for {}: {} pages", label, vault.pubkey, metadata.len());
+        Ok((metadata, graph))
+    }
+
+    /// The tenant's graph from its last successful sync
+    pub async fn graph(&self, pubkey: &str) -> Result<Arc<GraphData>, TenantError> {
+        self.ensure_enabled()?;
+        if let Some(graph) = self.graphs.read().await.get(pubkey) {
+            return Ok(graph.clone());
+        }
+        if !self.vaults.read().await.contains_key(pubkey) {
+            return Err(TenantError::NotFound);
+        }
+        // Not built since the server started; rebuild from the stored metadata
+        let metadata = Self::load_metadata(pubkey)
+            .map_err(|_| TenantError::Invalid("The vault hasn't been synced yet".to_string()))?;
+        let graph = Arc::new(GraphService::assemble_graph(&metadata, None));
+        self.graphs.write().await.insert(pubkey.to_string(), graph.clone());
+        Ok(graph)
+    }
+
+    fn load_vaults() -> Result<HashMap<String, TenantVault>, Box<dyn std::error::Error>> {
+        let content = fs::read_to_string(DataDirs::global().tenants().join("vaults.json"))?;
+        let vaults: Vec<TenantVault> = serde_json::from_str(&content)?;
+        Ok(vaults.into_iter().map(|vault| (vault.pubkey.clone(), vault)).collect())
+    }
+
+    fn save_vaults(vaults: &HashMap<String, TenantVault>) -> io::Result<()> {
+        fs::create_dir_all(DataDirs::global().tenants())?;
+        let mut sorted: Vec<&TenantVault> = vaults.values().collect();
+        sorted.sort_by(|a, b| a.pubkey.cmp(&b.pubkey));
+        let json = serde_json::to_string_pretty(&sorted)?;
+        fs::write(DataDirs::global().tenants().join("vaults.json"), json)
+    }
+
+    fn load_metadata(pubkey: &str) -> Result<MetadataStore, Box<dyn std::error::Error>> {
+        let content = fs::read_to_string(tenant_dir(pubkey).join("metadata.json"))?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    fn save_metadata(pubkey: &str, metadata: &MetadataStore) -> io::Result<()> {
+        let dir = tenant_dir(pubkey);
+        fs::create_dir_all(&dir)?;
+        let json = serde_json::to_string_pretty(metadata)?;
+        fs::write(dir.join("metadata.json"), json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quota_and_pubkey_checks() {
+        let quota = TenantQuota { enabled: true, max_tenants: 1, max_files: 10, max_bytes: 1000 };
+        assert!(quota.check_files(10).is_ok());
+        assert!(matches!(quota.check_files(11), Err(TenantError::QuotaExceeded(_))));
+        assert!(quota.check_bytes(1000).is_ok());
+        assert!(matches!(quota.check_bytes(1001), Err(TenantError::QuotaExceeded(_))));
+
+        assert!(validate_pubkey("3bf0c63fcb93463407af97a5e5ee64fa883d107ef9e558472c4eb9aaaefa459d").is_ok());
+        assert!(validate_pubkey("../metadata").is_err());
+        assert!(validate_pubkey("").is_err());
+    }
+}
@@ -0,0 +1,146 @@
+//! Chronological grouping of pages for the timeline view
+//!
+//! Logseq names journal pages after their day (`2024-01-15`, stored on disk as
+//! `2024_01_15.md`), which gives them a reliable creation date. Other pages only
+//! have the modification time GitHub reports, so that stands in for their creation
+//! date as well.
+
+use chrono::{Datelike, Days, NaiveDate};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+use crate::models::graph::GraphData;
+use crate::models::node::Node;
+
+/// `edge_type` of the edges joining each journal page to the next one
+pub const JOURNAL_EDGE_TYPE: &str = "journal";
+
+/// The day a journal page is for, if `page_name` is a `YYYY-MM-DD` or `YYYY_MM_DD`
+/// journal name. Directories and a `.md` extension are ignored.
+pub fn journal_date(page_name: &str) -> Option<NaiveDate> {
+    let name = page_name.rsplit('/').next().unwrap_or_default().trim_end_matches(".md");
+    if name.len() != 10 {
+        return None;
+    }
+    NaiveDate::parse_from_str(&name.replace('_', "-"), "%Y-%m-%d").ok()
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TimelineDate {
+    /// The journal day, or the modification date for other pages
+    #[default]
+    Created,
+    Modified,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    #[default]
+    Day,
+    /// Weeks start on Monday
+    Week,
+    Month,
+}
+
+impl Granularity {
+    /// First day of the bucket containing `date`
+    pub fn bucket(self, date: NaiveDate) -> NaiveDate {
+        match self {
+            Granularity::Day => date,
+            Granularity::Week => date - Days::new(u64::from(date.weekday().num_days_from_monday())),
+            Granularity::Month => date.with_day(1).unwrap_or(date),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimelineEntry {
+    pub id: u32,
+    pub metadata_id: String,
+    pub label: String,
+    pub date: NaiveDate,
+    pub journal: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimelineBucket {
+    /// First day covered by the bucket
+    pub start: NaiveDate,
+    pub nodes: Vec<TimelineEntry>,
+}
+
+/// Groups the pages of `graph` into buckets in date order. Pages without file
+/// metadata are left out unless they are journals.
+pub fn build(graph: &GraphData, by: TimelineDate, granularity: Granularity) -> Vec<TimelineBucket> {
+    let mut buckets: BTreeMap<NaiveDate, Vec<TimelineEntry>> = BTreeMap::new();
+    for node in graph.nodes.iter().filter(|node| node.is_page()) {
+        let journal = journal_date(&node.metadata_id);
+        let modified = graph.metadata.get(&format!("{}.md", node.metadata_id))
+            .map(|meta| meta.last_modified.date_naive());
+        let date = match by {
+            TimelineDate::Created => journal.or(modified),
+            TimelineDate::Modified => modified,
+        };
+        if let Some(date) = date {
+            buckets.entry(granularity.bucket(date)).or_default().push(entry(node, date, journal.is_some()));
+        }
+    }
+
+    buckets.into_iter()
+        .map(|(start, mut nodes)| {
+            nodes.sort_by(|a, b| a.date.cmp(&b.date).then_with(|| a.label.cmp(&b.label)));
+            TimelineBucket { start, nodes }
+        })
+        .collect()
+}
+
+fn entry(node: &Node, date: NaiveDate, journal: bool) -> TimelineEntry {
+    TimelineEntry { id: node.id, metadata_id: node.metadata_id.clone(), label: node.label.clone(), date, journal }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::metadata::Metadata;
+    use chrono::{TimeZone, Utc};
+
+    fn page(id: u32, name: &str) -> Node {
+        let mut node = Node::new_with_id(name.to_string(), Some(id));
+        node.label = name.to_string();
+        node
+    }
+
+    #[test]
+    fn test_journal_date() {
+        assert_eq!(journal_date("2024_01_15"), NaiveDate::from_ymd_opt(2024, 1, 15));
+        assert_eq!(journal_date("journals/2024-02-29.md"), NaiveDate::from_ymd_opt(2024, 2, 29));
+        assert_eq!(journal_date("2023-02-29"), None);
+        assert_eq!(journal_date("2024-01-15 notes"), None);
+        assert_eq!(journal_date("Rust"), None);
+    }
+
+    #[test]
+    fn test_build_groups_by_week() {
+        let mut graph = GraphData::new();
+        graph.nodes = vec![page(1, "2024_01_15"), page(2, "2024_01_21"), page(3, "Rust"), page(4, "2024_01_22")];
+        graph.metadata.insert("Rust.md".to_string(), Metadata {
+            last_modified: Utc.with_ymd_and_hms(2024, 1, 17, 12, 0, 0).unwrap(),
+            ..Default::default()
+        });
+
+        let timeline = build(&graph, TimelineDate::Created, Granularity::Week);
+        let starts: Vec<String> = timeline.iter().map(|b| b.start.to_string()).collect();
+        assert_eq!(starts, vec!["2024-01-15", "2024-01-22"]);
+        let labels: Vec<&str> = timeline[0].nodes.iter().map(|n| n.label.as_str()).collect();
+        assert_eq!(labels, vec!["2024_01_15", "Rust", "2024_01_21"]);
+
+        let modified = build(&graph, TimelineDate::Modified, Granularity::Month);
+        assert_eq!(modified.len(), 1);
+        assert_eq!(modified[0].nodes.len(), 1);
+        assert!(!modified[0].nodes[0].journal);
+    }
+}
@@ -0,0 +1,101 @@
+//! Spoken sessions kept as pages
+//!
+//! A speech session can record what is said in it: the user's transcribed speech
+//! and the replies spoken back. Saved, the recording becomes a page named after
+//! when it started (`Transcripts/2024-03-01 14-30`), dated and linking the nodes
+//! under discussion, with one journal-style bullet per utterance. A dictation,
+//! where only the user speaks, leaves out the speaker names.
+
+use chrono::{DateTime, Local};
+
+use crate::services::journal;
+
+// Longest recording kept for one session, in utterances
+pub const MAX_TRANSCRIPT_LINES: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Speaker {
+    User,
+    Assistant,
+}
+
+impl Speaker {
+    fn label(self) -> &'static str {
+        match self {
+            Speaker::User => "User",
+            Speaker::Assistant => "Assistant",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranscriptLine {
+    pub at: DateTime<Local>,
+    pub speaker: Speaker,
+    pub text: String,
+}
+
+/// Title for a transcript started at `started_at`, numbered if `exists` says
+/// the plain one is taken
+pub fn page_title(started_at: DateTime<Local>, exists: impl Fn(&str) -> bool) -> String {
+    let base = format!("Transcripts/{}", started_at.format("%Y-%m-%d %H-%M"));
+    let mut title = base.clone();
+    let mut n = 1;
+    while exists(&title) {
+        n += 1;
+        title = format!("{} ({})", base, n);
+    }
+    title
+}
+
+/// The page holding `lines`, linking `related` page titles
+pub fn render(lines: &[TranscriptLine], related: &[String]) -> String {
+    let date = lines.first().map(|line| line.at).unwrap_or_else(Local::now);
+    let mut page = format!("type:: transcript\ndate:: {}\n", date.format("%Y-%m-%d"));
+    if !related.is_empty() {
+        let links: Vec<String> = related.iter().map(|title| format!("[[{}]]", title)).collect();
+        page.push_str(&format!("related:: {}\n", links.join(", ")));
+    }
+
+    let dictation = lines.iter().all(|line| line.speaker == Speaker::User);
+    for line in lines {
+        let text = if dictation {
+            line.text.clone()
+        } else {
+            format!("**{}:** {}", line.speaker.label(), line.text.trim())
+        };
+        page = journal::append(&page, &journal::bullet(line.at.time(), &text));
+    }
+    page
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_render_transcript() {
+        let at = |minute| Local.with_ymd_and_hms(2024, 3, 1, 14, minute, 0).unwrap();
+        let line = |minute, speaker, text: &str| TranscriptLine { at: at(minute), speaker, text: text.to_string() };
+
+        assert_eq!(page_title(at(30), |_| false), "Transcripts/2024-03-01 14-30");
+        assert_eq!(
+            page_title(at(30), |title| !title.ends_with("(3)")),
+            "Transcripts/2024-03-01 14-30 (3)"
+        );
+
+        let chat = vec![
+            line(30, Speaker::User, "What links to graphs?"),
+            line(31, Speaker::Assistant, "Trees do."),
+        ];
+        assert_eq!(
+            render(&chat, &["Graphs".to_string(), "Trees".to_string()]),
+            "type:: transcript\ndate:: 2024-03-01\nrelated:: [[Graphs]], [[Trees]]\n\
+             - 14:30 **User:** What links to graphs?\n- 14:31 **Assistant:** Trees do.\n"
+        );
+
+        let dictation = vec![line(30, Speaker::User, "Buy milk\nand eggs")];
+        assert_eq!(render(&dictation, &[]), "type:: transcript\ndate:: 2024-03-01\n- 14:30 Buy milk\n  and eggs\n");
+    }
+}
@@ -0,0 +1,162 @@
+//! Soft-deleted file metadata
+//!
+//! When a sync finds that files have gone from the repository, their metadata is
+//! moved here instead of being dropped, so their node ids and enrichment come back
+//! if the files reappear. Entries are purged after a retention window. A sync that
+//! would remove a large share of the vault at once, which is more likely an API
+//! hiccup than a real clean-up, holds the removal back until it is confirmed.
+
+use chrono::{DateTime, Duration, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use crate::config::data_dirs::DataDirs;
+use crate::models::metadata::Metadata;
+
+fn trash_path() -> PathBuf {
+    DataDirs::global().metadata_file("trash.json")
+}
+
+const DEFAULT_RETENTION_DAYS: i64 = 30;
+const DEFAULT_MAX_DELETE_PERCENT: f64 = 20.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TrashPolicy {
+    pub retention: Duration,
+    /// Largest share of the vault, in percent, one sync may remove unconfirmed
+    pub max_delete_percent: f64,
+}
+
+impl Default for TrashPolicy {
+    fn default() -> Self {
+        Self {
+            retention: Duration::days(DEFAULT_RETENTION_DAYS),
+            max_delete_percent: DEFAULT_MAX_DELETE_PERCENT,
+        }
+    }
+}
+
+impl TrashPolicy {
+    /// Reads `SYNC_TRASH_RETENTION_DAYS` and `SYNC_MAX_DELETE_PERCENT`
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        let number = |name: &str| std::env::var(name).ok().and_then(|v| v.trim().parse::<f64>().ok());
+        Self {
+            retention: number("SYNC_TRASH_RETENTION_DAYS")
+                .map(|days| Duration::days(days.max(0.0) as i64))
+                .unwrap_or(default.retention),
+            max_delete_percent: number("SYNC_MAX_DELETE_PERCENT")
+                .map(|percent| percent.clamp(0.0, 100.0))
+                .unwrap_or(default.max_delete_percent),
+        }
+    }
+
+    /// Whether removing `removing` of `total` files needs confirmation. Removing a
+    /// single file never does, so small vaults can still lose a page.
+    pub fn needs_confirmation(&self, removing: usize, total: usize) -> bool {
+        removing > 1 && removing as f64 * 100.0 > self.max_delete_percent * total as f64
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrashedFile {
+    pub metadata: Metadata,
+    pub trashed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Trash {
+    files: HashMap<String, TrashedFile>,
+}
+
+impl Trash {
+    /// Loads the persisted trash, or an empty one if none exists or it can't be read
+    pub fn load() -> Self {
+        match fs::read_to_string(trash_path()) {
+            Ok(content) => serde_json::from_str(&content).unwrap_or_else(|e| {
+                warn!("Ignoring unreadable trash: {}", e);
+                Trash::default()
+            }),
+            Err(_) => Trash::default(),
+        }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        if let Some(parent) = trash_path().parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let json = serde_json::to_string_pretty(self).map_err(io::Error::from)?;
+        fs::write(trash_path(), json)
+    }
+
+    pub fn put(&mut self, metadata: Metadata, now: DateTime<Utc>) {
+        self.files.insert(metadata.file_name.clone(), TrashedFile { metadata, trashed_at: now });
+    }
+
+    /// Takes a file back out of the trash
+    pub fn restore(&mut self, file_name: &str) -> Option<Metadata> {
+        self.files.remove(file_name).map(|trashed| trashed.metadata)
+    }
+
+    /// Drops entries trashed longer than `retention` ago and returns their names
+    pub fn purge_expired(&mut self, now: DateTime<Utc>, retention: Duration) -> Vec<String> {
+        let mut purged: Vec<String> = self.files.iter()
+            .filter(|(_, trashed)| now - trashed.trashed_at >= retention)
+            .map(|(name, _)| name.clone())
+            .collect();
+        purged.sort();
+        for name in &purged {
+            self.files.remove(name);
+        }
+        if !purged.is_empty() {
+            info!("Purged {} files from the trash", purged.len());
+        }
+        purged
+    }
+
+    /// Trashed files, oldest first
+    pub fn list(&self) -> Vec<&TrashedFile> {
+        let mut files: Vec<&TrashedFile> = self.files.values().collect();
+        files.sort_by(|a, b| a.trashed_at.cmp(&b.trashed_at).then_with(|| a.metadata.file_name.cmp(&b.metadata.file_name)));
+        files
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn metadata(file_name: &str) -> Metadata {
+        Metadata { file_name: file_name.to_string(), node_id: "7".to_string(), ..Default::default() }
+    }
+
+    #[test]
+    fn test_guardrail() {
+        let policy = TrashPolicy { max_delete_percent: 20.0, ..TrashPolicy::default() };
+        assert!(!policy.needs_confirmation(1, 2));
+        assert!(!policy.needs_confirmation(2, 10));
+        assert!(policy.needs_confirmation(3, 10));
+        assert!(policy.needs_confirmation(5, 0));
+    }
+
+    #[test]
+    fn test_restore_and_purge() {
+        let now = Utc::now();
+        let mut trash = Trash::default();
+        trash.put(metadata("Old.md"), now - Duration::days(31));
+        trash.put(metadata("Recent.md"), now - Duration::days(1));
+
+        assert_eq!(trash.purge_expired(now, Duration::days(30)), vec!["Old.md".to_string()]);
+        assert_eq!(trash.restore("Recent.md").map(|m| m.node_id), Some("7".to_string()));
+        assert!(trash.is_empty());
+    }
+}
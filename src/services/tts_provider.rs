@@ -0,0 +1,325 @@
+//! Text-to-Speech backends
+//!
+//! Each backend implements [`TtsProvider`]; `SpeechService` picks one based on the
+//! currently selected [`TTSProvider`] and hands the HTTP response back to its own
+//! streaming/broadcast logic, so backends only need to know how to build a request.
+
+use async_trait::async_trait;
+use reqwest::{Client, Response};
+use serde::Serialize;
+use serde_json::json;
+use std::time::Duration;
+use crate::config::AppFullSettings;
+use crate::services::ai_usage;
+use crate::services::resilience;
+use crate::types::speech::{SpeechError, SpeechOptions, TTSProvider};
+
+const OPENAI_DEFAULT_BASE_URL: &str = "https://api.openai.com";
+const ELEVENLABS_DEFAULT_BASE_URL: &str = "https://api.elevenlabs.io";
+const DEFAULT_TIMEOUT_SECS: u64 = 30;
+const KOKORO_DEFAULT_FORMAT: &str = "mp3";
+const OPENAI_DEFAULT_MODEL: &str = "tts-1";
+const SONATA_DEFAULT_FORMAT: &str = "wav";
+const ELEVENLABS_DEFAULT_MODEL: &str = "eleven_multilingual_v2";
+const ELEVENLABS_DEFAULT_STABILITY: f32 = 0.5;
+const ELEVENLABS_DEFAULT_SIMILARITY: f32 = 0.75;
+
+#[async_trait]
+pub trait TtsProvider: Send + Sync {
+    fn kind(&self) -> TTSProvider;
+
+    /// Whether the settings contain enough configuration to attempt synthesis
+    fn is_available(&self, settings: &AppFullSettings) -> bool;
+
+    /// Voice/speed/stream defaults used when a request doesn't specify them
+    fn default_options(&self, settings: &AppFullSettings) -> SpeechOptions;
+
+    /// The settings other than voice and speed that shape the audio, such as the
+    /// model and output format, so cached audio is only reused while they hold
+    fn audio_fingerprint(&self, settings: &AppFullSettings) -> String;
+
+    async fn synthesize(
+        &self,
+        client: &Client,
+        settings: &AppFullSettings,
+        text: &str,
+        options: &SpeechOptions,
+    ) -> Result<Response, SpeechError>;
+}
+
+/// Availability summary returned by `/api/speech/providers`
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TtsProviderInfo {
+    pub id: &'static str,
+    pub available: bool,
+    pub active: bool,
+    pub default_voice: String,
+}
+
+pub fn tts_backend(kind: &TTSProvider) -> Box<dyn TtsProvider> {
+    match kind {
+        TTSProvider::OpenAI => Box::new(OpenAITts),
+        TTSProvider::Kokoro => Box::new(KokoroTts),
+        TTSProvider::Sonata => Box::new(SonataTts),
+        TTSProvider::ElevenLabs => Box::new(ElevenLabsTts),
+    }
+}
+
+pub fn describe_providers(settings: &AppFullSettings, active: &TTSProvider) -> Vec<TtsProviderInfo> {
+    TTSProvider::ALL.iter()
+        .map(|kind| {
+            let backend = tts_backend(kind);
+            TtsProviderInfo {
+                id: kind.id(),
+                available: backend.is_available(settings),
+                active: kind == active,
+                default_voice: backend.default_options(settings).voice,
+            }
+        })
+        .collect()
+}
+
+fn non_empty(value: Option<&String>) -> Option<&str> {
+    value.map(|s| s.as_str()).filter(|s| !s.is_empty())
+}
+
+async fn check_response(provider: &str, response: Result<Response, impl std::fmt::Display>) -> Result<Response, SpeechError> {
+    let response = response
+        .map_err(|e| SpeechError::TTSError(format!("Failed to connect to {} API: {}", provider, e)))?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let error_text = response.text().await.unwrap_or_default();
+        return Err(SpeechError::TTSError(format!("{} API error {}: {}", provider, status, error_text)));
+    }
+    Ok(response)
+}
+
+pub struct KokoroTts;
+
+#[async_trait]
+impl TtsProvider for KokoroTts {
+    fn kind(&self) -> TTSProvider {
+        TTSProvider::Kokoro
+    }
+
+    fn is_available(&self, settings: &AppFullSettings) -> bool {
+        settings.kokoro.as_ref().and_then(|k| non_empty(k.api_url.as_ref())).is_some()
+    }
+
+    fn default_options(&self, settings: &AppFullSettings) -> SpeechOptions {
+        let kokoro = settings.kokoro.as_ref();
+        let defaults = SpeechOptions::default();
+        SpeechOptions {
+            voice: kokoro.and_then(|k| k.default_voice.clone()).unwrap_or(defaults.voice),
+            speed: kokoro.and_then(|k| k.default_speed).unwrap_or(defaults.speed),
+            stream: kokoro.and_then(|k| k.stream).unwrap_or(defaults.stream),
+        }
+    }
+
+    fn audio_fingerprint(&self, settings: &AppFullSettings) -> String {
+        settings.kokoro.as_ref()
+            .and_then(|k| k.default_format.clone())
+            .unwrap_or_else(|| KOKORO_DEFAULT_FORMAT.to_string())
+    }
+
+    async fn synthesize(&self, client: &Client, settings: &AppFullSettings, text: &str, options: &SpeechOptions) -> Result<Response, SpeechError> {
+        let config = settings.kokoro.as_ref()
+            .ok_or_else(|| SpeechError::TTSError("Kokoro configuration not found".to_string()))?;
+        let api_url_base = non_empty(config.api_url.as_ref())
+            .ok_or_else(|| SpeechError::TTSError("Kokoro API URL not configured or empty".to_string()))?;
+        let api_url = format!("{}/v1/audio/speech", api_url_base.trim_end_matches('/'));
+
+        let request_body = json!({
+            "model": "kokoro",
+            "input": text,
+            "voice": options.voice,
+            "response_format": config.default_format.as_deref().unwrap_or(KOKORO_DEFAULT_FORMAT),
+            "speed": options.speed,
+            "stream": options.stream
+        });
+
+        let response = client.post(&api_url)
+            .timeout(Duration::from_secs(config.timeout.unwrap_or(DEFAULT_TIMEOUT_SECS)))
+            .json(&request_body)
+            .send()
+            .await;
+        check_response("Kokoro", response).await
+    }
+}
+
+pub struct OpenAITts;
+
+#[async_trait]
+impl TtsProvider for OpenAITts {
+    fn kind(&self) -> TTSProvider {
+        TTSProvider::OpenAI
+    }
+
+    fn is_available(&self, settings: &AppFullSettings) -> bool {
+        settings.openai.as_ref().and_then(|o| non_empty(o.api_key.as_ref())).is_some()
+    }
+
+    fn default_options(&self, settings: &AppFullSettings) -> SpeechOptions {
+        let openai = settings.openai.as_ref();
+        SpeechOptions {
+            voice: openai.and_then(|o| o.tts_voice.clone()).unwrap_or_else(|| "alloy".to_string()),
+            speed: openai.and_then(|o| o.tts_speed).unwrap_or(1.0),
+            // The speech endpoint always returns chunked audio; treat it as a stream
+            stream: true,
+        }
+    }
+
+    fn audio_fingerprint(&self, settings: &AppFullSettings) -> String {
+        settings.openai.as_ref()
+            .and_then(|o| o.tts_model.clone())
+            .unwrap_or_else(|| OPENAI_DEFAULT_MODEL.to_string())
+    }
+
+    async fn synthesize(&self, client: &Client, settings: &AppFullSettings, text: &str, options: &SpeechOptions) -> Result<Response, SpeechError> {
+        let config = settings.openai.as_ref()
+            .ok_or_else(|| SpeechError::TTSError("OpenAI configuration not found".to_string()))?;
+        let api_key = non_empty(config.api_key.as_ref())
+            .ok_or_else(|| SpeechError::TTSError("OpenAI API key not configured or empty".to_string()))?;
+        let base_url = non_empty(config.base_url.as_ref()).unwrap_or(OPENAI_DEFAULT_BASE_URL);
+        let api_url = format!("{}/v1/audio/speech", base_url.trim_end_matches('/'));
+
+        let request_body = json!({
+            "model": config.tts_model.as_deref().unwrap_or(OPENAI_DEFAULT_MODEL),
+            "input": text,
+            "voice": options.voice,
+            "speed": options.speed,
+            "response_format": "mp3"
+        });
+
+        ai_usage::check(resilience::OPENAI).map_err(|e| SpeechError::TTSError(e.to_string()))?;
+        let response = resilience::dependency(resilience::OPENAI).send(|| {
+            client.post(&api_url)
+                .bearer_auth(api_key)
+                .timeout(Duration::from_secs(config.timeout.unwrap_or(DEFAULT_TIMEOUT_SECS)))
+                .json(&request_body)
+        }).await;
+        let response = check_response("OpenAI", response).await?;
+        // Speech is billed per input character
+        ai_usage::record(resilience::OPENAI, text.chars().count() as u64, 0);
+        Ok(response)
+    }
+}
+
+/// Local Sonata (Piper voices) server exposing an OpenAI-compatible speech endpoint
+pub struct SonataTts;
+
+#[async_trait]
+impl TtsProvider for SonataTts {
+    fn kind(&self) -> TTSProvider {
+        TTSProvider::Sonata
+    }
+
+    fn is_available(&self, settings: &AppFullSettings) -> bool {
+        settings.sonata.as_ref().and_then(|s| non_empty(s.api_url.as_ref())).is_some()
+    }
+
+    fn default_options(&self, settings: &AppFullSettings) -> SpeechOptions {
+        let sonata = settings.sonata.as_ref();
+        SpeechOptions {
+            voice: sonata.and_then(|s| s.default_voice.clone()).unwrap_or_else(|| "en_US-lessac-medium".to_string()),
+            speed: sonata.and_then(|s| s.default_speed).unwrap_or(1.0),
+            stream: false,
+        }
+    }
+
+    fn audio_fingerprint(&self, settings: &AppFullSettings) -> String {
+        settings.sonata.as_ref()
+            .and_then(|s| s.default_format.clone())
+            .unwrap_or_else(|| SONATA_DEFAULT_FORMAT.to_string())
+    }
+
+    async fn synthesize(&self, client: &Client, settings: &AppFullSettings, text: &str, options: &SpeechOptions) -> Result<Response, SpeechError> {
+        let config = settings.sonata.as_ref()
+            .ok_or_else(|| SpeechError::TTSError("Sonata configuration not found".to_string()))?;
+        let api_url_base = non_empty(config.api_url.as_ref())
+            .ok_or_else(|| SpeechError::TTSError("Sonata API URL not configured or empty".to_string()))?;
+        let api_url = format!("{}/v1/audio/speech", api_url_base.trim_end_matches('/'));
+
+        let request_body = json!({
+            "model": "sonata",
+            "input": text,
+            "voice": options.voice,
+            "speed": options.speed,
+            "response_format": config.default_format.as_deref().unwrap_or(SONATA_DEFAULT_FORMAT)
+        });
+
+        let response = client.post(&api_url)
+            .timeout(Duration::from_secs(config.timeout.unwrap_or(DEFAULT_TIMEOUT_SECS)))
+            .json(&request_body)
+            .send()
+            .await;
+        check_response("Sonata", response).await
+    }
+}
+
+pub struct ElevenLabsTts;
+
+#[async_trait]
+impl TtsProvider for ElevenLabsTts {
+    fn kind(&self) -> TTSProvider {
+        TTSProvider::ElevenLabs
+    }
+
+    fn is_available(&self, settings: &AppFullSettings) -> bool {
+        settings.elevenlabs.as_ref()
+            .map(|e| non_empty(e.api_key.as_ref()).is_some() && non_empty(e.default_voice_id.as_ref()).is_some())
+            .unwrap_or(false)
+    }
+
+    fn default_options(&self, settings: &AppFullSettings) -> SpeechOptions {
+        let elevenlabs = settings.elevenlabs.as_ref();
+        SpeechOptions {
+            voice: elevenlabs.and_then(|e| e.default_voice_id.clone()).unwrap_or_default(),
+            speed: elevenlabs.and_then(|e| e.default_speed).unwrap_or(1.0),
+            stream: true,
+        }
+    }
+
+    fn audio_fingerprint(&self, settings: &AppFullSettings) -> String {
+        let elevenlabs = settings.elevenlabs.as_ref();
+        format!(
+            "{}/{}/{}",
+            elevenlabs.and_then(|e| e.model_id.as_deref()).unwrap_or(ELEVENLABS_DEFAULT_MODEL),
+            elevenlabs.and_then(|e| e.stability).unwrap_or(ELEVENLABS_DEFAULT_STABILITY),
+            elevenlabs.and_then(|e| e.similarity_boost).unwrap_or(ELEVENLABS_DEFAULT_SIMILARITY),
+        )
+    }
+
+    async fn synthesize(&self, client: &Client, settings: &AppFullSettings, text: &str, options: &SpeechOptions) -> Result<Response, SpeechError> {
+        let config = settings.elevenlabs.as_ref()
+            .ok_or_else(|| SpeechError::TTSError("ElevenLabs configuration not found".to_string()))?;
+        let api_key = non_empty(config.api_key.as_ref())
+            .ok_or_else(|| SpeechError::TTSError("ElevenLabs API key not configured or empty".to_string()))?;
+        if options.voice.is_empty() {
+            return Err(SpeechError::TTSError("ElevenLabs requires a voice id".to_string()));
+        }
+        let base_url = non_empty(config.api_url.as_ref()).unwrap_or(ELEVENLABS_DEFAULT_BASE_URL);
+        let suffix = if options.stream { "/stream" } else { "" };
+        let api_url = format!("{}/v1/text-to-speech/{}{}", base_url.trim_end_matches('/'), options.voice, suffix);
+
+        let request_body = json!({
+            "text": text,
+            "model_id": config.model_id.as_deref().unwrap_or(ELEVENLABS_DEFAULT_MODEL),
+            "voice_settings": {
+                "stability": config.stability.unwrap_or(ELEVENLABS_DEFAULT_STABILITY),
+                "similarity_boost": config.similarity_boost.unwrap_or(ELEVENLABS_DEFAULT_SIMILARITY),
+                "speed": options.speed
+            }
+        });
+
+        let response = client.post(&api_url)
+            .header("xi-api-key", api_key)
+            .header("Accept", "audio/mpeg")
+            .timeout(Duration::from_secs(config.timeout.unwrap_or(DEFAULT_TIMEOUT_SECS)))
+            .json(&request_body)
+            .send()
+            .await;
+        check_response("ElevenLabs", response).await
+    }
+}
@@ -0,0 +1,192 @@
+//! Optional encryption of notes stored on disk
+//!
+//! With `VAULT_ENCRYPTION_KEY` set (32 bytes, base64), the markdown mirror under
+//! `/app/data/markdown` and the blob cache are written with AES-256-GCM, so a shared
+//! host never sees the notes in plaintext. Each file is the `MAGIC` header, a random
+//! 96-bit nonce and the ciphertext. Files without the header are read as plaintext,
+//! so an existing mirror keeps working and is encrypted as files are rewritten.
+//! Without a key notes are read and written as plain text, but access tokens
+//! aren't stored at all.
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::{Engine as _, engine::general_purpose::STANDARD as BASE64};
+use log::info;
+use once_cell::sync::Lazy;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::config::data_dirs::DataDirs;
+
+const MAGIC: &[u8] = b"VFENC1\0";
+const NONCE_LEN: usize = 12;
+pub const MISSING_KEY: &str = "Set VAULT_ENCRYPTION_KEY to store access tokens";
+
+static VAULT_CIPHER: Lazy<Result<VaultCipher, String>> = Lazy::new(VaultCipher::from_env);
+
+pub struct VaultCipher {
+    cipher: Option<Aes256Gcm>,
+}
+
+impl VaultCipher {
+    /// Reads the key from `VAULT_ENCRYPTION_KEY`; an unset or empty key disables
+    /// encryption, a malformed one is an error
+    pub fn from_env() -> Result<Self, String> {
+        match std::env::var("VAULT_ENCRYPTION_KEY") {
+            Ok(key) if !key.trim().is_empty() => {
+                let bytes = BASE64.decode(key.trim())
+                    .map_err(|e| format!("VAULT_ENCRYPTION_KEY is not valid base64: {}", e))?;
+                let key: [u8; 32] = bytes.try_into()
+                    .map_err(|bytes: Vec<u8>| format!("VAULT_ENCRYPTION_KEY must be 32 bytes, got {}", bytes.len()))?;
+                Ok(Self::with_key(key))
+            }
+            _ => Ok(Self { cipher: None }),
+        }
+    }
+
+    pub fn with_key(key: [u8; 32]) -> Self {
+        Self { cipher: Some(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&key))) }
+    }
+
+    /// The process-wide cipher configured from the environment
+    pub fn global() -> io::Result<&'static VaultCipher> {
+        VAULT_CIPHER.as_ref().map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e.clone()))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    /// Encrypts `plaintext`, or returns it unchanged without a key
+    pub fn seal(&self, plaintext: &[u8]) -> io::Result<Vec<u8>> {
+        let Some(cipher) = &self.cipher else {
+            return Ok(plaintext.to_vec());
+        };
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = cipher.encrypt(&nonce, plaintext)
+            .map_err(|_| io::Error::other("Failed to encrypt content"))?;
+        let mut sealed = Vec::with_capacity(MAGIC.len() + NONCE_LEN + ciphertext.len());
+        sealed.extend_from_slice(MAGIC);
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+        Ok(sealed)
+    }
+
+    /// Encrypts a credential. Unlike notes, credentials are never stored in
+    /// plaintext, so this fails without a key.
+    pub fn seal_secret(&self, secret: &[u8]) -> io::Result<Vec<u8>> {
+        if !self.is_enabled() {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, MISSING_KEY));
+        }
+        self.seal(secret)
+    }
+
+    /// Decrypts content written by `seal`. Content without the header is returned
+    /// as it is.
+    pub fn open(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let Some(sealed) = data.strip_prefix(MAGIC) else {
+            return Ok(data.to_vec());
+        };
+        let Some(cipher) = &self.cipher else {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, "Content is encrypted but VAULT_ENCRYPTION_KEY is not set"));
+        };
+        if sealed.len() < NONCE_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Encrypted content is truncated"));
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        cipher.decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Failed to decrypt content; wrong key or corrupt file"))
+    }
+
+    pub fn decode_string(&self, data: &[u8]) -> io::Result<String> {
+        String::from_utf8(self.open(data)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Whether stored `data` is plaintext while a key is set
+    pub fn needs_sealing(&self, data: &[u8]) -> bool {
+        self.is_enabled() && !data.starts_with(MAGIC)
+    }
+}
+
+/// Checks the configured key, so a bad one stops startup instead of failing reads
+/// later, and encrypts any plaintext notes left from before the key was set
+pub fn init() -> io::Result<()> {
+    let cipher = VaultCipher::global()?;
+    if !cipher.is_enabled() {
+        return Ok(());
+    }
+    info!("Encrypting stored markdown with VAULT_ENCRYPTION_KEY");
+    let dirs = DataDirs::global();
+    let sealed = seal_plaintext_files(cipher, &dirs.markdown, |path| {
+        path.extension().is_some_and(|ext| ext == "md")
+    })? + seal_plaintext_files(cipher, &dirs.blobs(), |path| path.extension().is_none())?;
+    if sealed > 0 {
+        info!("Encrypted {} plaintext files", sealed);
+    }
+    Ok(())
+}
+
+/// Encrypts the plaintext files under `dir` that `include` accepts, returning how
+/// many were rewritten
+fn seal_plaintext_files(cipher: &VaultCipher, dir: &Path, include: fn(&Path) -> bool) -> io::Result<usize> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    let mut sealed = 0;
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_dir() {
+            sealed += seal_plaintext_files(cipher, &path, include)?;
+            continue;
+        }
+        if !include(&path) {
+            continue;
+        }
+        let data = fs::read(&path)?;
+        if cipher.needs_sealing(&data) {
+            fs::write(&path, cipher.seal(&data)?)?;
+            sealed += 1;
+        }
+    }
+    Ok(sealed)
+}
+
+/// `fs::read_to_string` for files that may be encrypted
+pub fn read_to_string(path: impl AsRef<Path>) -> io::Result<String> {
+    VaultCipher::global()?.decode_string(&fs::read(path)?)
+}
+
+/// `fs::write`, encrypting when a key is configured
+pub fn write(path: impl AsRef<Path>, content: &str) -> io::Result<()> {
+    fs::write(path, VaultCipher::global()?.seal(content.as_bytes())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_open() {
+        let cipher = VaultCipher::with_key([7; 32]);
+        let sealed = cipher.seal(b"public:: true\n# Page").unwrap();
+        assert!(sealed.starts_with(MAGIC));
+        assert!(!sealed.windows(4).any(|w| w == b"Page"));
+        assert_eq!(cipher.decode_string(&sealed).unwrap(), "public:: true\n# Page");
+        // A fresh nonce each time
+        assert_ne!(cipher.seal(b"same").unwrap(), cipher.seal(b"same").unwrap());
+
+        // Plaintext left over from before encryption still reads
+        assert_eq!(cipher.decode_string(b"# Old page").unwrap(), "# Old page");
+        assert!(VaultCipher::with_key([8; 32]).open(&sealed).is_err());
+        assert!(VaultCipher { cipher: None }.open(&sealed).is_err());
+        assert!(cipher.needs_sealing(b"# Old page"));
+        assert!(!cipher.needs_sealing(&sealed));
+
+        // Credentials are never stored in plaintext
+        assert!(cipher.seal_secret(b"ghp_token").unwrap().starts_with(MAGIC));
+        assert!(VaultCipher { cipher: None }.seal_secret(b"ghp_token").is_err());
+    }
+}
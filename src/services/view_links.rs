@@ -0,0 +1,201 @@
+//! Short links to a shared view of the graph
+//!
+//! A client posts what it is showing (the selected node, its filters, a layout
+//! preset and the camera) and gets back a short token for a `/g/<token>` link.
+//! Anyone opening the link resolves the token and restores the same view. Tokens
+//! are derived from the view itself, so sharing the same view twice gives the same
+//! link. Links are kept in `/app/data/metadata/view_links.json`.
+
+use chrono::Utc;
+use log::{debug, info};
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use tokio::sync::RwLock;
+
+use crate::config::data_dirs::DataDirs;
+use crate::types::vec3::Vec3Data;
+
+fn view_links_path() -> PathBuf {
+    DataDirs::global().metadata_file("view_links.json")
+}
+
+const TOKEN_LENGTH: usize = 6;
+// Filters are opaque to the server, so cap what a client can make it store
+const MAX_VIEW_BYTES: usize = 16 * 1024;
+const TOKEN_ALPHABET: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CameraView {
+    pub position: Vec3Data,
+    pub target: Vec3Data,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ViewState {
+    pub selected_node: Option<u32>,
+    /// Client filter settings, stored as given
+    #[serde(default)]
+    pub filters: serde_json::Value,
+    /// Layout preset, e.g. `force` or one of the `/api/graph/layout` algorithms
+    pub layout: Option<String>,
+    pub camera: Option<CameraView>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ViewLink {
+    pub token: String,
+    pub view: ViewState,
+    pub created_at: i64,
+}
+
+#[derive(Debug)]
+pub enum ViewLinkError {
+    /// The view can't be stored as given
+    Invalid(String),
+    Storage(String),
+}
+
+impl std::fmt::Display for ViewLinkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ViewLinkError::Invalid(message) | ViewLinkError::Storage(message) => f.write_str(message),
+        }
+    }
+}
+
+pub struct ViewLinkService {
+    links: RwLock<HashMap<String, ViewLink>>,
+}
+
+impl Default for ViewLinkService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ViewLinkService {
+    pub fn new() -> Self {
+        let links = Self::load_links().unwrap_or_else(|e| {
+            debug!("[ViewLinks] No stored view links loaded: {}", e);
+            HashMap::new()
+        });
+        info!("[ViewLinks] Loaded {} view links", links.len());
+        Self { links: RwLock::new(links) }
+    }
+
+    /// Stores `view` and returns its link, reusing the existing one if the same
+    /// view was shared before
+    pub async fn create(&self, view: ViewState) -> Result<ViewLink, ViewLinkError> {
+        let mut links = self.links.write().await;
+        let (link, created) = claim_token(&mut links, view).map_err(ViewLinkError::Invalid)?;
+        if created {
+            Self::save_links(&links)
+                .map_err(|e| ViewLinkError::Storage(format!("Failed to persist view links: {}", e)))?;
+            info!("[ViewLinks] Created view link {}", link.token);
+        }
+        Ok(link)
+    }
+
+    pub async fn resolve(&self, token: &str) -> Option<ViewLink> {
+        self.links.read().await.get(token).cloned()
+    }
+
+    fn load_links() -> Result<HashMap<String, ViewLink>, Box<dyn std::error::Error>> {
+        let content = fs::read_to_string(view_links_path())?;
+        let links: Vec<ViewLink> = serde_json::from_str(&content)?;
+        Ok(links.into_iter().map(|link| (link.token.clone(), link)).collect())
+    }
+
+    fn save_links(links: &HashMap<String, ViewLink>) -> std::io::Result<()> {
+        if let Some(parent) = view_links_path().parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut sorted: Vec<&ViewLink> = links.values().collect();
+        sorted.sort_by(|a, b| a.token.cmp(&b.token));
+        let json = serde_json::to_string_pretty(&sorted)?;
+        fs::write(view_links_path(), json)
+    }
+}
+
+/// Finds the token for `view`, adding a link if there isn't one yet. The token is
+/// the start of the view's digest, lengthened until it doesn't clash with another
+/// view. Returns whether a link was added.
+fn claim_token(links: &mut HashMap<String, ViewLink>, view: ViewState) -> Result<(ViewLink, bool), String> {
+    let encoded = serde_json::to_vec(&view).map_err(|e| format!("Invalid view: {}", e))?;
+    if encoded.len() > MAX_VIEW_BYTES {
+        return Err(format!("View state is {} bytes; the limit is {}", encoded.len(), MAX_VIEW_BYTES));
+    }
+
+    let digest = token_digest(&encoded);
+    for length in TOKEN_LENGTH..=digest.len() {
+        match links.get(&digest[..length]) {
+            Some(existing) if existing.view == view => return Ok((existing.clone(), false)),
+            Some(_) => continue,
+            None => {
+                let link = ViewLink { token: digest[..length].to_string(), view, created_at: Utc::now().timestamp() };
+                links.insert(link.token.clone(), link.clone());
+                return Ok((link, true));
+            }
+        }
+    }
+    Err("No free token for this view".to_string())
+}
+
+/// The first 128 bits of the view's SHA-1 in base 62
+fn token_digest(encoded: &[u8]) -> String {
+    let digest = Sha1::digest(encoded);
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&digest[..16]);
+    let mut value = u128::from_be_bytes(bytes);
+    (0..22)
+        .map(|_| {
+            let digit = (value % 62) as usize;
+            value /= 62;
+            TOKEN_ALPHABET[digit] as char
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn view(selected_node: u32) -> ViewState {
+        ViewState {
+            selected_node: Some(selected_node),
+            filters: serde_json::json!({ "tags": ["rust"] }),
+            layout: Some("radial".to_string()),
+            camera: None,
+        }
+    }
+
+    #[test]
+    fn test_tokens_are_short_and_stable() {
+        let mut links = HashMap::new();
+        let (first, created) = claim_token(&mut links, view(1)).unwrap();
+        assert!(created);
+        assert_eq!(first.token.len(), TOKEN_LENGTH);
+        assert!(first.token.chars().all(|c| c.is_ascii_alphanumeric()));
+
+        let (again, created) = claim_token(&mut links, view(1)).unwrap();
+        assert!(!created);
+        assert_eq!(again.token, first.token);
+
+        // A different view under the same short token gets a longer one
+        let clashing = links.remove(&first.token).unwrap();
+        links.insert(first.token.clone(), ViewLink { view: view(2), ..clashing });
+        let (longer, created) = claim_token(&mut links, view(1)).unwrap();
+        assert!(created);
+        assert_eq!(longer.token.len(), TOKEN_LENGTH + 1);
+        assert!(longer.token.starts_with(&first.token));
+
+        let huge = ViewState { filters: serde_json::json!("x".repeat(MAX_VIEW_BYTES)), ..view(3) };
+        assert!(claim_token(&mut links, huge).is_err());
+    }
+}
@@ -0,0 +1,239 @@
+//! Rules deciding which markdown files are published to the graph
+//!
+//! The policy is read from `VISIBILITY_POLICY_PATH` (default `visibility.json` in
+//! the metadata directory). Without a policy file only pages whose
+//! properties contain `public:: true` are published, as before.
+//!
+//! Exclusions always win. A file that passes the `include`/`exclude` globs is then
+//! published if it has any of `includeTags` or any of `includeProperties`; when both
+//! are empty every file passing the globs is published.
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use webxr_core::page::{inline_tags, page_properties};
+
+use crate::config::data_dirs::DataDirs;
+
+const DEFAULT_POLICY_FILE: &str = "visibility.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct VisibilityPolicy {
+    /// Globs on the file name; an empty list allows every file
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub include_tags: Vec<String>,
+    pub exclude_tags: Vec<String>,
+    /// Page property name to required value, compared case-insensitively
+    pub include_properties: HashMap<String, String>,
+    pub exclude_properties: HashMap<String, String>,
+}
+
+impl Default for VisibilityPolicy {
+    fn default() -> Self {
+        Self {
+            include: Vec::new(),
+            exclude: Vec::new(),
+            include_tags: Vec::new(),
+            exclude_tags: Vec::new(),
+            include_properties: HashMap::from([("public".to_string(), "true".to_string())]),
+            exclude_properties: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VisibilityDecision {
+    pub included: bool,
+    pub reason: String,
+}
+
+impl VisibilityDecision {
+    fn include(reason: String) -> Self {
+        Self { included: true, reason }
+    }
+
+    fn exclude(reason: String) -> Self {
+        Self { included: false, reason }
+    }
+}
+
+impl VisibilityPolicy {
+    /// Loads the configured policy. A missing file means the default policy; an
+    /// unreadable one is logged and also falls back to the default, which only
+    /// publishes pages explicitly marked public.
+    pub fn load() -> Self {
+        let path = env::var("VISIBILITY_POLICY_PATH")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| DataDirs::global().metadata_file(DEFAULT_POLICY_FILE));
+        match fs::read_to_string(&path) {
+            Ok(content) => match serde_json::from_str(&content) {
+                Ok(policy) => {
+                    info!("Loaded visibility policy from {}", path.display());
+                    policy
+                }
+                Err(e) => {
+                    warn!("Ignoring invalid visibility policy {}: {}", path.display(), e);
+                    Self::default()
+                }
+            },
+            Err(_) => Self::default(),
+        }
+    }
+
+    /// Applies only the file name globs, so excluded files can be skipped before
+    /// downloading them
+    pub fn check_path(&self, file_name: &str) -> Option<VisibilityDecision> {
+        if let Some(pattern) = self.exclude.iter().find(|p| glob_match(p, file_name)) {
+            return Some(VisibilityDecision::exclude(format!("matches exclude pattern '{}'", pattern)));
+        }
+        if !self.include.is_empty() && !self.include.iter().any(|p| glob_match(p, file_name)) {
+            return Some(VisibilityDecision::exclude("matches no include pattern".to_string()));
+        }
+        None
+    }
+
+    pub fn evaluate(&self, file_name: &str, content: &str) -> VisibilityDecision {
+        if let Some(decision) = self.check_path(file_name) {
+            return decision;
+        }
+
+        let properties = page_properties(content);
+        let tags = page_tags(content, &properties);
+
+        if let Some(tag) = self.exclude_tags.iter().find(|t| tags.contains(&normalise_tag(t))) {
+            return VisibilityDecision::exclude(format!("has excluded tag '{}'", tag));
+        }
+        if let Some((key, value)) = find_property(&self.exclude_properties, &properties) {
+            return VisibilityDecision::exclude(format!("has excluded property {}:: {}", key, value));
+        }
+
+        if self.include_tags.is_empty() && self.include_properties.is_empty() {
+            return VisibilityDecision::include("allowed by path rules".to_string());
+        }
+        if let Some(tag) = self.include_tags.iter().find(|t| tags.contains(&normalise_tag(t))) {
+            return VisibilityDecision::include(format!("has tag '{}'", tag));
+        }
+        if let Some((key, value)) = find_property(&self.include_properties, &properties) {
+            return VisibilityDecision::include(format!("has property {}:: {}", key, value));
+        }
+        VisibilityDecision::exclude("matches no include tag or property".to_string())
+    }
+
+    pub fn is_visible(&self, file_name: &str, content: &str) -> bool {
+        self.evaluate(file_name, content).included
+    }
+}
+
+fn find_property<'a>(rules: &'a HashMap<String, String>, properties: &HashMap<String, String>) -> Option<(&'a String, &'a String)> {
+    rules.iter().find(|(key, value)| {
+        properties.get(&key.to_lowercase())
+            .map(|actual| actual.eq_ignore_ascii_case(value.trim()))
+            .unwrap_or(false)
+    })
+}
+
+/// Tags from the `tags` property plus inline `#tag`s, normalised for comparison
+fn page_tags(content: &str, properties: &HashMap<String, String>) -> HashSet<String> {
+    let mut tags: HashSet<String> = properties.get("tags")
+        .map(|value| {
+            value.trim_matches(|c| c == '[' || c == ']')
+                .split(',')
+                .map(normalise_tag)
+                .filter(|tag| !tag.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    tags.extend(inline_tags(content).map(normalise_tag));
+    tags
+}
+
+fn normalise_tag(tag: &str) -> String {
+    tag.trim()
+        .trim_start_matches('#')
+        .trim_start_matches("[[")
+        .trim_end_matches("]]")
+        .trim()
+        .to_lowercase()
+}
+
+/// Matches `*` (any run of characters) and `?` (one character)
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position of the last `*` and the text index it was tried against
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star, matched)) = backtrack {
+            p = star + 1;
+            t = matched + 1;
+            backtrack = Some((star, matched + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_policy_requires_public_property() {
+        let policy = VisibilityPolicy::default();
+        assert!(policy.is_visible("Page.md", "public:: true\n- content"));
+        assert!(policy.is_visible("Page.md", "title:: Page\npublic:: TRUE\n- content"));
+        assert!(!policy.is_visible("Page.md", "- public:: true appears in a block"));
+        assert!(!policy.is_visible("Page.md", "public:: false\n"));
+    }
+
+    #[test]
+    fn test_exclusions_win_over_inclusions() {
+        let policy = VisibilityPolicy {
+            exclude: vec!["draft-*".to_string()],
+            include_tags: vec!["Published".to_string()],
+            exclude_tags: vec!["secret".to_string()],
+            ..Default::default()
+        };
+        assert!(policy.is_visible("Notes.md", "tags:: [[published]], rust\n- text"));
+        assert!(policy.is_visible("Notes.md", "- shared #published"));
+        assert!(policy.is_visible("Notes.md", "public:: true\n"));
+        assert!(!policy.is_visible("Notes.md", "public:: true\n- #secret"));
+        assert!(!policy.is_visible("draft-Notes.md", "public:: true\n"));
+        assert!(!policy.is_visible("Notes.md", "- nothing to see"));
+    }
+
+    #[test]
+    fn test_path_only_policy_and_front_matter() {
+        let policy: VisibilityPolicy = serde_json::from_str(
+            r#"{"include": ["*.md"], "includeProperties": {}, "excludeProperties": {"status": "private"}}"#
+        ).unwrap();
+        assert!(policy.is_visible("Any page.md", "no properties at all"));
+        assert!(!policy.is_visible("Any page.md", "---\nstatus: \"private\"\n---\n# Page"));
+        assert!(policy.check_path("image.png").is_some());
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("*.md", "Page.md"));
+        assert!(glob_match("journal_????_*", "journal_2024_01_01.md"));
+        assert!(glob_match("a*b*c", "aXXbYYc"));
+        assert!(!glob_match("a*b*c", "aXXbYY"));
+        assert!(!glob_match("*.md", "Page.org"));
+    }
+}
@@ -0,0 +1,92 @@
+//! Heat of graph nodes from a user's visits
+//!
+//! Each visited node's heat mixes how often it was visited with how long it was
+//! looked at, both relative to the user's most visited node, so the hottest node
+//! is 1. Heat then spreads one hop along edges at half strength, so clients tinting
+//! by heat light up the areas a user keeps returning to rather than lone nodes.
+
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+use crate::models::graph::GraphData;
+use crate::models::user_settings::NodeVisits;
+
+const SPREAD: f32 = 0.5;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HeatCell {
+    pub node_id: u32,
+    /// Between 0 and 1
+    pub heat: f32,
+    /// Visits of the node itself, 0 for nodes only warmed by a neighbour
+    pub visits: u64,
+    pub dwell_ms: u64,
+}
+
+/// Nodes of `graph` with any heat, hottest first
+pub fn heatmap(graph: &GraphData, visits: &BTreeMap<String, NodeVisits>) -> Vec<HeatCell> {
+    let own: HashMap<u32, &NodeVisits> = graph.nodes.iter()
+        .filter_map(|node| visits.get(&node.metadata_id).map(|v| (node.id, v)))
+        .collect();
+    let max_count = own.values().map(|v| v.count).max().unwrap_or(0).max(1) as f32;
+    let max_dwell = own.values().map(|v| v.dwell_ms).max().unwrap_or(0).max(1) as f32;
+    let own_heat = |v: &NodeVisits| 0.5 * v.count as f32 / max_count + 0.5 * v.dwell_ms as f32 / max_dwell;
+
+    let mut heat: HashMap<u32, f32> = own.iter().map(|(id, v)| (*id, own_heat(v))).collect();
+    for edge in &graph.edges {
+        for (from, to) in [(edge.source, edge.target), (edge.target, edge.source)] {
+            if let Some(v) = own.get(&from) {
+                let spread = SPREAD * own_heat(v);
+                let cell = heat.entry(to).or_default();
+                *cell = cell.max(spread);
+            }
+        }
+    }
+
+    let mut cells: Vec<HeatCell> = heat.into_iter()
+        .filter(|(_, heat)| *heat > 0.0)
+        .map(|(node_id, heat)| {
+            let v = own.get(&node_id);
+            HeatCell {
+                node_id,
+                heat,
+                visits: v.map_or(0, |v| v.count),
+                dwell_ms: v.map_or(0, |v| v.dwell_ms),
+            }
+        })
+        .collect();
+    cells.sort_by(|a, b| b.heat.total_cmp(&a.heat).then_with(|| a.node_id.cmp(&b.node_id)));
+    cells
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::edge::Edge;
+    use crate::models::node::Node;
+
+    #[test]
+    fn test_heatmap() {
+        let mut graph = GraphData::new();
+        for (id, name) in [(1, "Rust"), (2, "Borrowing"), (3, "Gardening")] {
+            graph.nodes.push(Node::new_with_id(name.to_string(), Some(id)));
+        }
+        graph.edges.push(Edge::new(1, 2, 1.0));
+        let mut visits = BTreeMap::new();
+        visits.insert("Rust".to_string(), NodeVisits { count: 4, dwell_ms: 8000, last_visited: 0 });
+        visits.insert("Gardening".to_string(), NodeVisits { count: 2, dwell_ms: 2000, last_visited: 0 });
+        // Nodes no longer in the graph are left out, and don't cool the rest
+        visits.insert("Deleted".to_string(), NodeVisits { count: 9, dwell_ms: 90000, last_visited: 0 });
+
+        let cells = heatmap(&graph, &visits);
+        let by_node: HashMap<u32, &HeatCell> = cells.iter().map(|cell| (cell.node_id, cell)).collect();
+        assert_eq!(cells.len(), 3);
+        assert_eq!(cells[0].node_id, 1);
+        assert_eq!(by_node[&1].heat, 1.0);
+        // Borrowing is never visited but warmed by Rust
+        assert_eq!(by_node[&2].visits, 0);
+        assert_eq!(by_node[&2].heat, SPREAD);
+        assert!(by_node[&3].heat < by_node[&1].heat);
+    }
+}
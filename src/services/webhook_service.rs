@@ -0,0 +1,272 @@
+use crate::services::event_bus::{AppEvent, EventBus, FileEvent, GraphEvent};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use log::{debug, error, info, warn};
+use reqwest::Client;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::error::Error as StdError;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, RwLock};
+use uuid::Uuid;
+
+use crate::config::data_dirs::DataDirs;
+
+fn webhooks_path() -> PathBuf {
+    DataDirs::global().metadata_file("webhooks.json")
+}
+
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+// A rebuild that loses more than this fraction of nodes is reported as an anomaly
+const NODE_DROP_THRESHOLD: f64 = 0.5;
+
+pub const SIGNATURE_HEADER: &str = "X-Webhook-Signature";
+
+/// Event names a webhook can subscribe to
+pub const EVENT_SYNC_COMPLETED: &str = "sync.completed";
+pub const EVENT_GRAPH_REBUILT: &str = "graph.rebuilt";
+pub const EVENT_NODE_COUNT_ANOMALY: &str = "graph.nodeCountAnomaly";
+
+pub const SUPPORTED_EVENTS: [&str; 3] = [
+    EVENT_SYNC_COMPLETED,
+    EVENT_GRAPH_REBUILT,
+    EVENT_NODE_COUNT_ANOMALY,
+];
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Webhook {
+    pub id: String,
+    pub url: String,
+    pub secret: String,
+    // Empty means "all events"
+    #[serde(default)]
+    pub events: Vec<String>,
+    pub created_by: String,
+    pub created_at: i64,
+}
+
+impl Webhook {
+    fn wants(&self, event: &str) -> bool {
+        self.events.is_empty() || self.events.iter().any(|e| e == event)
+    }
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WebhookPayload {
+    pub event: String,
+    pub timestamp: i64,
+    pub data: serde_json::Value,
+}
+
+pub struct WebhookService {
+    client: Client,
+    hooks: RwLock<Vec<Webhook>>,
+    last_node_count: RwLock<Option<usize>>,
+}
+
+impl WebhookService {
+    pub fn new() -> Self {
+        let hooks = Self::load_hooks().unwrap_or_else(|e| {
+            debug!("[Webhooks] No stored webhooks loaded: {}", e);
+            Vec::new()
+        });
+        info!("[Webhooks] Loaded {} webhook registrations", hooks.len());
+
+        Self {
+            client: Client::builder()
+                .timeout(DELIVERY_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            hooks: RwLock::new(hooks),
+            last_node_count: RwLock::new(None),
+        }
+    }
+
+    /// Spawn a task that forwards relevant bus events to registered webhooks
+    pub fn start(self: &Arc<Self>, event_bus: &EventBus) {
+        let service = Arc::clone(self);
+        let mut events = event_bus.subscribe();
+        tokio::spawn(async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => service.handle_event(event).await,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("[Webhooks] Dispatcher lagged, skipped {} events", skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    pub async fn list(&self) -> Vec<Webhook> {
+        self.hooks.read().await.clone()
+    }
+
+    pub async fn register(&self, url: String, secret: String, events: Vec<String>, created_by: &str) -> Result<Webhook, String> {
+        let parsed = url::Url::parse(&url).map_err(|e| format!("Invalid webhook URL: {}", e))?;
+        if parsed.scheme() != "http" && parsed.scheme() != "https" {
+            return Err("Webhook URL must use http or https".to_string());
+        }
+        if secret.is_empty() {
+            return Err("Webhook secret must not be empty".to_string());
+        }
+        if let Some(unknown) = events.iter().find(|e| !SUPPORTED_EVENTS.contains(&e.as_str())) {
+            return Err(format!("Unsupported webhook event: {}", unknown));
+        }
+
+        let hook = Webhook {
+            id: Uuid::new_v4().to_string(),
+            url,
+            secret,
+            events,
+            created_by: created_by.to_string(),
+            created_at: Utc::now().timestamp(),
+        };
+
+        let mut hooks = self.hooks.write().await;
+        hooks.push(hook.clone());
+        Self::save_hooks(&hooks).map_err(|e| format!("Failed to persist webhooks: {}", e))?;
+        info!("[Webhooks] Registered webhook {} -> {}", hook.id, hook.url);
+        Ok(hook)
+    }
+
+    pub async fn remove(&self, id: &str) -> Result<bool, String> {
+        let mut hooks = self.hooks.write().await;
+        let before = hooks.len();
+        hooks.retain(|h| h.id != id);
+        if hooks.len() == before {
+            return Ok(false);
+        }
+        Self::save_hooks(&hooks).map_err(|e| format!("Failed to persist webhooks: {}", e))?;
+        info!("[Webhooks] Removed webhook {}", id);
+        Ok(true)
+    }
+
+    async fn handle_event(&self, event: AppEvent) {
+        match event {
+            AppEvent::File(FileEvent::Processed { file_names }) => {
+                self.dispatch(EVENT_SYNC_COMPLETED, serde_json::json!({
+                    "fileCount": file_names.len(),
+                    "files": file_names,
+                })).await;
+            }
+            AppEvent::Graph(GraphEvent::Rebuilt { node_count, edge_count }) => {
+                let previous = self.last_node_count.write().await.replace(node_count);
+                if let Some(previous) = previous {
+                    if previous > 0 && (node_count as f64) < previous as f64 * (1.0 - NODE_DROP_THRESHOLD) {
+                        warn!("[Webhooks] Node count dropped from {} to {}", previous, node_count);
+                        self.dispatch(EVENT_NODE_COUNT_ANOMALY, serde_json::json!({
+                            "previousNodeCount": previous,
+                            "nodeCount": node_count,
+                        })).await;
+                    }
+                }
+                self.dispatch(EVENT_GRAPH_REBUILT, serde_json::json!({
+                    "nodeCount": node_count,
+                    "edgeCount": edge_count,
+                })).await;
+            }
+            _ => {}
+        }
+    }
+
+    async fn dispatch(&self, event: &str, data: serde_json::Value) {
+        let targets: Vec<Webhook> = self.hooks.read().await
+            .iter()
+            .filter(|h| h.wants(event))
+            .cloned()
+            .collect();
+        if targets.is_empty() {
+            return;
+        }
+
+        let payload = WebhookPayload {
+            event: event.to_string(),
+            timestamp: Utc::now().timestamp(),
+            data,
+        };
+        let body = match serde_json::to_vec(&payload) {
+            Ok(body) => body,
+            Err(e) => {
+                error!("[Webhooks] Failed to serialize {} payload: {}", event, e);
+                return;
+            }
+        };
+
+        for hook in targets {
+            let client = self.client.clone();
+            let body = body.clone();
+            let event = event.to_string();
+            // Deliveries are fire-and-forget so a slow endpoint can't stall the dispatcher
+            tokio::spawn(async move {
+                let signature = sign_payload(&hook.secret, &body);
+                let result = client.post(&hook.url)
+                    .header("Content-Type", "application/json")
+                    .header("X-Webhook-Event", &event)
+                    .header(SIGNATURE_HEADER, format!("sha256={}", signature))
+                    .body(body)
+                    .send()
+                    .await;
+                match result {
+                    Ok(resp) if resp.status().is_success() => {
+                        debug!("[Webhooks] Delivered {} to {}", event, hook.url);
+                    }
+                    Ok(resp) => {
+                        warn!("[Webhooks] {} responded with {} for {}", hook.url, resp.status(), event);
+                    }
+                    Err(e) => {
+                        warn!("[Webhooks] Failed to deliver {} to {}: {}", event, hook.url, e);
+                    }
+                }
+            });
+        }
+    }
+
+    fn load_hooks() -> Result<Vec<Webhook>, Box<dyn StdError + Send + Sync>> {
+        let content = fs::read_to_string(webhooks_path())?;
+        Ok(serde_json::from_str(&content)?)
+    }
+
+    fn save_hooks(hooks: &[Webhook]) -> Result<(), Box<dyn StdError + Send + Sync>> {
+        if let Some(parent) = webhooks_path().parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(webhooks_path(), serde_json::to_string_pretty(hooks)?)?;
+        Ok(())
+    }
+}
+
+impl Default for WebhookService {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Hex-encoded HMAC-SHA256 of the request body, keyed with the webhook secret
+pub fn sign_payload(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(body);
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_payload_matches_known_vector() {
+        let signature = sign_payload("key", b"The quick brown fox jumps over the lazy dog");
+        assert_eq!(signature, "f7bc83f430538424b13298e6aa6fb143ef4d59a14946175997479dbc2d1a3cd8");
+    }
+}
@@ -0,0 +1,118 @@
+//! Size of the simulated world
+//!
+//! Nodes are held inside a cube of half extent `size` around the origin (a square
+//! in 2D layouts). With auto-fit the cube grows with the graph so the density of
+//! nodes stays about the same: a graph of `REFERENCE_NODES` nodes or fewer gets
+//! `size`, and larger ones grow by the cube root (square root in 2D) of their
+//! node count, up to `max_size`. Small changes in node count don't move the
+//! bounds, so clients aren't asked to rescale after every edit.
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::PhysicsSettings;
+
+const REFERENCE_NODES: f32 = 100.0;
+// Auto-fit leaves the bounds alone until they would change by more than this fraction
+const REFIT_TOLERANCE: f32 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BoundsConfig {
+    pub enabled: bool,
+    /// Half extent, or the smallest half extent when auto-fitting
+    pub size: f32,
+    pub auto_fit: bool,
+    /// Largest half extent auto-fit will pick
+    pub max_size: f32,
+    /// Nodes lie on a plane, so the world grows in two dimensions
+    #[serde(skip)]
+    pub planar: bool,
+}
+
+impl Default for BoundsConfig {
+    fn default() -> Self {
+        Self { enabled: true, size: 15.0, auto_fit: false, max_size: 250.0, planar: false }
+    }
+}
+
+impl BoundsConfig {
+    pub fn from_settings(physics: &PhysicsSettings) -> Self {
+        Self {
+            enabled: physics.enable_bounds,
+            size: physics.bounds_size,
+            auto_fit: physics.auto_fit_bounds,
+            max_size: physics.max_bounds_size,
+            planar: physics.layout_plane().is_some(),
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if !(self.size.is_finite() && self.size > 0.0) {
+            return Err(format!("size must be positive, got {}", self.size));
+        }
+        if self.auto_fit && !(self.max_size.is_finite() && self.max_size >= self.size) {
+            return Err(format!("maxSize must be at least size ({}), got {}", self.size, self.max_size));
+        }
+        Ok(())
+    }
+
+    /// Half extent for a graph of `node_count` nodes
+    pub fn fit(&self, node_count: usize) -> f32 {
+        if !self.auto_fit {
+            return self.size;
+        }
+        let dimensions = if self.planar { 2.0 } else { 3.0 };
+        let scale = (node_count as f32 / REFERENCE_NODES).max(1.0).powf(1.0 / dimensions);
+        (self.size * scale).min(self.max_size).max(self.size)
+    }
+
+    /// The half extent to use now that the graph has `node_count` nodes, or
+    /// `None` to keep `current`
+    pub fn refit(&self, current: f32, node_count: usize) -> Option<f32> {
+        let fitted = self.fit(node_count);
+        let changed = if self.auto_fit {
+            (fitted - current).abs() > current * REFIT_TOLERANCE
+        } else {
+            fitted != current
+        };
+        changed.then_some(fitted)
+    }
+}
+
+/// The bounds in effect, as reported to clients
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorldBounds {
+    pub half_extent: f32,
+    #[serde(flatten)]
+    pub config: BoundsConfig,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fit_grows_with_node_count() {
+        let fixed = BoundsConfig::default();
+        assert_eq!(fixed.fit(100_000), 15.0);
+
+        let auto = BoundsConfig { auto_fit: true, ..fixed };
+        assert_eq!(auto.fit(10), 15.0);
+        assert!((auto.fit(800) - 30.0).abs() < 1e-3);
+        assert_eq!(auto.fit(10_000_000), 250.0);
+        let planar = BoundsConfig { planar: true, ..auto };
+        assert!((planar.fit(400) - 30.0).abs() < 1e-3);
+
+        assert_eq!(auto.refit(30.0, 850), None);
+        assert!(auto.refit(30.0, 2000).is_some());
+        assert_eq!(fixed.refit(30.0, 10), Some(15.0));
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(BoundsConfig::default().validate().is_ok());
+        assert!(BoundsConfig { size: 0.0, ..Default::default() }.validate().is_err());
+        assert!(BoundsConfig { auto_fit: true, max_size: 10.0, ..Default::default() }.validate().is_err());
+    }
+}
@@ -0,0 +1,185 @@
+//! Fakes for handler and service tests
+//!
+//! `test_app_state` builds an `AppState` that needs no GitHub credentials, GPU or
+//! Docker volume: the repository is an `InMemoryGitHub`, the GPU actor and
+//! background loops are left out, and physics is off so positions hold still.
+//! Anything the services persist goes to a per-process temporary data directory.
+
+use async_trait::async_trait;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::sync::RwLock;
+
+use crate::actors::graph_actor::PhysicsBackend;
+use crate::app_state::{AppState, AppStateBuilder};
+use crate::config::data_dirs::DataDirs;
+use crate::config::feature_access::FeatureAccess;
+use crate::config::AppFullSettings;
+use crate::models::metadata::MetadataStore;
+use crate::services::blob_cache::git_blob_sha;
+use crate::services::nostr_service::NostrService;
+use crate::services::github::{ConditionalContent, GitHubError, GitHubFileMetadata, GitHubService};
+
+const DOWNLOAD_URL_PREFIX: &str = "memory://";
+
+/// The settings shipped in `data/settings.yaml`
+pub fn test_settings() -> AppFullSettings {
+    serde_yaml::from_str(include_str!("../data/settings.yaml")).expect("data/settings.yaml is valid")
+}
+
+/// Points the data directories at a directory under the system temp dir, so tests
+/// never touch `/app/data`. Has no effect once the directories have been read.
+pub fn use_temp_data_dirs() -> &'static DataDirs {
+    let root = std::env::temp_dir().join(format!("webxr-test-{}", std::process::id()));
+    let _ = DataDirs::init(DataDirs::under(root));
+    DataDirs::global()
+}
+
+/// An `AppState` over `github` with `metadata` loaded and no GPU, background loops
+/// or physics. `power_users` are the only users with any feature access, and
+/// nobody is signed in until `sign_in` is called.
+pub async fn test_app_state(github: InMemoryGitHub, metadata: MetadataStore, power_users: &[&str]) -> AppState {
+    use_temp_data_dirs();
+    let power_users: Vec<String> = power_users.iter().map(|pubkey| pubkey.to_string()).collect();
+    let mut state = AppStateBuilder::new(test_settings())
+        .with_content_api(std::sync::Arc::new(github))
+        .with_metadata(metadata)
+        .with_feature_access(FeatureAccess {
+            approved_pubkeys: power_users.clone(),
+            perplexity_enabled: Vec::new(),
+            openai_enabled: Vec::new(),
+            ragflow_enabled: Vec::new(),
+            power_users: power_users.clone(),
+            settings_sync_enabled: power_users,
+        })
+        .with_physics(PhysicsBackend::Noop)
+        .without_gpu()
+        .without_background_tasks()
+        .build()
+        .await
+        .expect("test app state builds");
+    state.set_nostr_service(NostrService::default());
+    state
+}
+
+/// Opens a session for `pubkey` and returns its token, to send as `X-Nostr-Token`
+/// or `Authorization: Bearer`. Power users are those `test_app_state` was given.
+pub async fn sign_in(state: &AppState, pubkey: &str) -> String {
+    let nostr_service = state.nostr_service.as_ref().expect("test app state has a Nostr service");
+    nostr_service.open_session(pubkey, state.is_power_user(pubkey)).await
+}
+
+/// A repository held in memory. Files are listed in name order with their git blob
+/// SHA, which also serves as the ETag.
+#[derive(Debug, Default)]
+pub struct InMemoryGitHub {
+    branches: RwLock<BTreeMap<Option<String>, BTreeMap<String, String>>>,
+}
+
+impl InMemoryGitHub {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_file(self, name: &str, content: &str) -> Self {
+        self.put_file(None, name, content);
+        self
+    }
+
+    /// Adds or replaces a file on `branch`, or the default branch if `None`
+    pub fn put_file(&self, branch: Option<&str>, name: &str, content: &str) {
+        self.branches.write().unwrap()
+            .entry(branch.map(str::to_string))
+            .or_default()
+            .insert(name.to_string(), content.to_string());
+    }
+
+    pub fn remove_file(&self, branch: Option<&str>, name: &str) {
+        if let Some(files) = self.branches.write().unwrap().get_mut(&branch.map(str::to_string)) {
+            files.remove(name);
+        }
+    }
+
+    /// Content behind a download URL, with the branch encoded as `memory://branch/name`
+    fn content(&self, download_url: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+        let not_found = || -> Box<dyn Error + Send + Sync> { Box::new(GitHubError::NotFound(download_url.to_string())) };
+        let path = download_url.strip_prefix(DOWNLOAD_URL_PREFIX).ok_or_else(not_found)?;
+        let (branch, name) = path.split_once('/').ok_or_else(not_found)?;
+        let branch = (!branch.is_empty()).then(|| branch.to_string());
+        self.branches.read().unwrap()
+            .get(&branch)
+            .and_then(|files| files.get(name).cloned())
+            .ok_or_else(not_found)
+    }
+}
+
+#[async_trait]
+impl GitHubService for InMemoryGitHub {
+    fn max_concurrent_requests(&self) -> usize {
+        4
+    }
+
+    async fn list_markdown_files(&self, path: &str) -> Result<Vec<GitHubFileMetadata>, Box<dyn Error + Send + Sync>> {
+        self.list_markdown_files_on(path, None).await
+    }
+
+    async fn list_markdown_files_on(&self, path: &str, branch: Option<&str>) -> Result<Vec<GitHubFileMetadata>, Box<dyn Error + Send + Sync>> {
+        let branches = self.branches.read().unwrap();
+        let Some(files) = branches.get(&branch.map(str::to_string)) else {
+            return Ok(Vec::new());
+        };
+        Ok(files.iter()
+            .filter(|(name, _)| name.starts_with(path) && name.ends_with(".md"))
+            .map(|(name, content)| GitHubFileMetadata {
+                name: name.clone(),
+                sha: git_blob_sha(content),
+                download_url: format!("{}{}/{}", DOWNLOAD_URL_PREFIX, branch.unwrap_or(""), name),
+                etag: Some(git_blob_sha(content)),
+                last_checked: None,
+                last_modified: None,
+            })
+            .collect())
+    }
+
+    async fn fetch_file_content(&self, download_url: &str) -> Result<String, Box<dyn Error + Send + Sync>> {
+        self.content(download_url)
+    }
+
+    async fn fetch_file_content_if_modified(
+        &self,
+        download_url: &str,
+        etag: Option<&str>,
+    ) -> Result<ConditionalContent, Box<dyn Error + Send + Sync>> {
+        let content = self.content(download_url)?;
+        let current = git_blob_sha(&content);
+        if etag == Some(current.as_str()) {
+            return Ok(ConditionalContent::NotModified);
+        }
+        Ok(ConditionalContent::Modified { content, etag: Some(current) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_in_memory_github() {
+        let github = InMemoryGitHub::new().with_file("A.md", "# A").with_file("notes.txt", "skip");
+        github.put_file(Some("draft"), "B.md", "# B");
+
+        let files = github.list_markdown_files("").await.unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!(github.fetch_file_content(&files[0].download_url).await.unwrap(), "# A");
+        assert!(matches!(
+            github.fetch_file_content_if_modified(&files[0].download_url, files[0].etag.as_deref()).await.unwrap(),
+            ConditionalContent::NotModified
+        ));
+
+        let draft = github.list_markdown_files_on("", Some("draft")).await.unwrap();
+        assert_eq!(github.fetch_file_content(&draft[0].download_url).await.unwrap(), "# B");
+
+        github.remove_file(None, "A.md");
+        assert!(github.fetch_file_content(&files[0].download_url).await.is_err());
+    }
+}
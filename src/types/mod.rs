@@ -1,4 +1,4 @@
 pub mod speech;
-pub mod vec3;
+pub use webxr_core::vec3;
 
 pub use vec3::Vec3Data;
@@ -61,10 +61,35 @@ impl From<base64::DecodeError> for SpeechError {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum TTSProvider {
     OpenAI,
     Kokoro,
+    Sonata,
+    ElevenLabs,
+}
+
+impl TTSProvider {
+    pub const ALL: [TTSProvider; 4] = [
+        TTSProvider::Kokoro,
+        TTSProvider::OpenAI,
+        TTSProvider::Sonata,
+        TTSProvider::ElevenLabs,
+    ];
+
+    /// Stable identifier used in settings and client messages
+    pub fn id(&self) -> &'static str {
+        match self {
+            TTSProvider::OpenAI => "openai",
+            TTSProvider::Kokoro => "kokoro",
+            TTSProvider::Sonata => "sonata",
+            TTSProvider::ElevenLabs => "elevenlabs",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<Self> {
+        Self::ALL.iter().find(|p| p.id().eq_ignore_ascii_case(id)).cloned()
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -77,13 +102,15 @@ pub enum STTProvider {
 pub enum SpeechCommand {
     Initialize,
     SendMessage(String),
-    TextToSpeech(String, SpeechOptions),
+    /// Text, options and the optional session the audio should be routed to
+    TextToSpeech(String, SpeechOptions, Option<String>),
     Close,
     SetTTSProvider(TTSProvider),
     SetSTTProvider(STTProvider),
     StartTranscription(TranscriptionOptions),
     StopTranscription,
-    ProcessAudioChunk(Vec<u8>),
+    /// Audio data and the optional session the transcript should be routed to
+    ProcessAudioChunk(Vec<u8>, Option<String>),
 }
 
 #[derive(Debug, Clone)]
@@ -124,15 +124,13 @@ mod tests {
     use tokio::runtime::Runtime;
 
     fn create_test_settings() -> Arc<RwLock<Settings>> {
-        let settings = Settings {
-            debug_mode: false,
-            debug: crate::config::DebugSettings {
-                enable_websocket_debug: false,
-                enable_data_debug: false,
-                log_binary_headers: false,
-                log_full_json: false,
-            },
-            // Add other required fields with default values
+        let mut settings = Settings::default();
+        settings.system.debug = crate::config::DebugSettings {
+            enabled: false,
+            enable_websocket_debug: false,
+            enable_data_debug: false,
+            log_binary_headers: false,
+            log_full_json: false,
             ..Default::default()
         };
         Arc::new(RwLock::new(settings))
@@ -1,5 +1,7 @@
 use actix_web::{HttpRequest, HttpResponse};
-use log::{warn, error};
+use log::warn;
+use serde_json::json;
+use crate::app_state::AppState;
 use crate::services::nostr_service::NostrService;
 
 pub enum AccessLevel {
@@ -67,4 +69,22 @@ pub async fn verify_authenticated(
     nostr_service: &NostrService,
 ) -> Result<String, HttpResponse> {
     verify_access(req, nostr_service, AccessLevel::Authenticated).await
-}
\ No newline at end of file
+}
+
+// For requests acting as the user, such as with their GitHub token or on their own
+// pages: a live Nostr session sent as `Authorization: Bearer`, not just a pubkey header
+pub async fn require_session(req: &HttpRequest, state: &AppState) -> Result<String, HttpResponse> {
+    let pubkey = match req.headers().get("X-Nostr-Pubkey").and_then(|v| v.to_str().ok()) {
+        Some(pk) => pk.to_string(),
+        None => return Err(HttpResponse::Unauthorized().json(json!({"error": "Missing X-Nostr-Pubkey header"}))),
+    };
+    let token = match req.headers().get("Authorization").and_then(|v| v.to_str().ok().map(|s| s.trim_start_matches("Bearer "))) {
+        Some(t) => t.to_string(),
+        None => return Err(HttpResponse::Unauthorized().json(json!({"error": "Missing Authorization token"}))),
+    };
+    if !state.validate_nostr_session(&pubkey, &token).await {
+        warn!("Rejected request with an invalid session for {}", pubkey);
+        return Err(HttpResponse::Unauthorized().json(json!({"error": "Invalid session token"})));
+    }
+    Ok(pubkey)
+}
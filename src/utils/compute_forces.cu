@@ -21,9 +21,117 @@ extern "C" {
 
         unsigned char mass;   // 1 byte  - matches Rust u8
         unsigned char flags;  // 1 byte  - matches Rust u8
-        unsigned char padding[2]; // 2 bytes - matches Rust padding
+        unsigned char padding[2]; // 2 bytes - padding[0] holds the charge multiplier
     };
 
+    // Multipliers packed by the host in sixteenths; 0 means 1.0
+    __device__ float unpack_multiplier(unsigned char packed) {
+        return packed == 0 ? 1.0f : packed / 16.0f;
+    }
+
+    // Charge multiplier, set per node type
+    __device__ float node_charge(const BinaryNodeData& node) {
+        return unpack_multiplier(node.padding[0]);
+    }
+
+    // Collision radius multiplier, from the node's size
+    __device__ float node_radius_scale(const BinaryNodeData& node) {
+        return unpack_multiplier(node.padding[1]);
+    }
+
+    // Values of LayoutParams::force_model, matching SimulationMode on the host
+    const int FORCE_MODEL_SPRING_ELECTRIC = 0;
+    const int FORCE_MODEL_FORCE_ATLAS2 = 1;
+    const int FORCE_MODEL_LIN_LOG = 2;
+
+    // Arguments after iteration_count, in one struct since a launch takes at most
+    // 12 arguments. Must match GPULayoutParams in gpu_compute.rs.
+    struct LayoutParams {
+        float collision_radius;
+        float collision_stiffness;
+        float plane_z;        // NaN lays out in 3D, otherwise nodes are held to z = plane_z
+        int force_model;
+        float gravity;
+        int strong_gravity;
+        // Adjacency in CSR form, read by the ForceAtlas2 models only: the neighbours
+        // of node i are edge_targets[edge_offsets[i]..edge_offsets[i + 1]]
+        const int* edge_offsets;
+        const int* edge_targets;
+        const float* edge_weights;
+        // Stiffness of a spring to the origin per unit of degree; 0 disables it
+        float degree_gravity;
+    };
+
+    __device__ int node_degree(const LayoutParams& layout, int idx) {
+        return layout.edge_offsets[idx + 1] - layout.edge_offsets[idx];
+    }
+
+    // ForceAtlas2 (Jacomy et al. 2014): repulsion between every pair scaled by
+    // (degree + 1) of both and falling off with 1/d, attraction along edges that is
+    // linear in d, or log(1 + d) for LinLog, and gravity towards the origin that is
+    // constant, or linear in d when strong
+    __device__ float3 force_atlas2(
+        const BinaryNodeData* nodes, int num_nodes, int idx, float3 pos,
+        float repel_k, float spring_k, float max_repulsion_dist,
+        const LayoutParams& layout, float ramp_up_factor
+    ) {
+        const float MIN_DISTANCE = 0.15f;
+        float3 force = make_float3(0.0f, 0.0f, 0.0f);
+        float deg_mass = node_degree(layout, idx) + 1.0f;
+        float charge = node_charge(nodes[idx]);
+        float radius = layout.collision_radius * node_radius_scale(nodes[idx]);
+
+        for (int j = 0; j < num_nodes; j++) {
+            if (j == idx) continue;
+            float3 diff = make_float3(
+                nodes[j].position.x - pos.x,
+                nodes[j].position.y - pos.y,
+                nodes[j].position.z - pos.z
+            );
+            float dist = sqrtf(diff.x * diff.x + diff.y * diff.y + diff.z * diff.z);
+            if (dist <= MIN_DISTANCE) continue;
+            float3 dir = make_float3(diff.x / dist, diff.y / dist, diff.z / dist);
+
+            float push = 0.0f;
+            if (dist < max_repulsion_dist) {
+                float other_mass = node_degree(layout, j) + 1.0f;
+                push += repel_k * ramp_up_factor * deg_mass * other_mass * charge * node_charge(nodes[j]) / dist;
+            }
+            float min_dist = radius + layout.collision_radius * node_radius_scale(nodes[j]);
+            if (layout.collision_stiffness > 0.0f && dist < min_dist) {
+                push += layout.collision_stiffness * (min_dist - dist);
+            }
+            force.x -= dir.x * push;
+            force.y -= dir.y * push;
+            force.z -= dir.z * push;
+        }
+
+        for (int e = layout.edge_offsets[idx]; e < layout.edge_offsets[idx + 1]; e++) {
+            int j = layout.edge_targets[e];
+            float3 diff = make_float3(
+                nodes[j].position.x - pos.x,
+                nodes[j].position.y - pos.y,
+                nodes[j].position.z - pos.z
+            );
+            float dist = sqrtf(diff.x * diff.x + diff.y * diff.y + diff.z * diff.z);
+            if (dist <= MIN_DISTANCE) continue;
+            float stretch = layout.force_model == FORCE_MODEL_LIN_LOG ? logf(1.0f + dist) : dist;
+            float pull = spring_k * ramp_up_factor * layout.edge_weights[e] * stretch;
+            force.x += diff.x / dist * pull;
+            force.y += diff.y / dist * pull;
+            force.z += diff.z / dist * pull;
+        }
+
+        float center_dist = sqrtf(pos.x * pos.x + pos.y * pos.y + pos.z * pos.z);
+        if (center_dist > MIN_DISTANCE) {
+            float pull = layout.gravity * deg_mass * (layout.strong_gravity ? center_dist : 1.0f);
+            force.x -= pos.x / center_dist * pull;
+            force.y -= pos.y / center_dist * pull;
+            force.z -= pos.z / center_dist * pull;
+        }
+        return force;
+    }
+
     __global__ void compute_forces_kernel(
         BinaryNodeData* nodes,
         int num_nodes,
@@ -33,11 +141,18 @@ extern "C" {
         float dt,
         float max_repulsion_dist,
         float viewport_bounds,
-        int iteration_count
+        int iteration_count,
+        // Appended last so an older PTX without it still launches
+        const LayoutParams layout
     ) {
         int idx = blockIdx.x * blockDim.x + threadIdx.x;
         if (idx >= num_nodes) return;
 
+        float collision_radius = layout.collision_radius;
+        float collision_stiffness = layout.collision_stiffness;
+        float plane_z = layout.plane_z;
+        bool spring_electric = layout.force_model == FORCE_MODEL_SPRING_ELECTRIC;
+
         const float MAX_FORCE = 3.0f; // Reduced maximum force magnitude
         const float MAX_VELOCITY = 0.02f; // Stricter velocity cap to prevent momentum buildup
         const float MIN_DISTANCE = 0.15f; // Slightly increased minimum distance
@@ -72,12 +187,20 @@ extern "C" {
             mass = (nodes[idx].mass + 1.0f) / 256.0f; // Add 1 to avoid zero mass
         }
 
+        float charge = node_charge(nodes[idx]);
+        float radius = collision_radius * node_radius_scale(nodes[idx]);
+
         bool is_active = true; // All nodes are active by default
 
         if (!is_active) return; // Skip inactive nodes
 
+        if (!spring_electric) {
+            total_force = force_atlas2(nodes, num_nodes, idx, pos, repel_k, spring_k,
+                                       max_repulsion_dist, layout, ramp_up_factor);
+        }
+
         // Process all node interactions
-        for (int j = 0; j < num_nodes; j++) {
+        for (int j = 0; spring_electric && j < num_nodes; j++) {
             if (j == idx) continue;
 
             // All nodes are considered active by default
@@ -127,7 +250,7 @@ extern "C" {
 
                     // Repulsion forces - only apply at close distances
                     if (dist < max_repulsion_dist) {
-                        float repel_scale = repel_k * mass * other_mass;
+                        float repel_scale = repel_k * mass * other_mass * charge * node_charge(nodes[j]);
                         // Apply the ramp_up_factor to gradually increase repulsion forces
                         float dist_sq = fmaxf(dist * dist, MIN_DISTANCE);
                         // Cap maximum repulsion force to prevent explosion
@@ -143,19 +266,44 @@ extern "C" {
                         total_force.z -= dir.z * force_magnitude;
                     }
                 }
+
+                // Sphere collision: push overlapping nodes apart in proportion to
+                // the overlap so dense clusters stay readable
+                float min_dist = radius + collision_radius * node_radius_scale(nodes[j]);
+                if (collision_stiffness > 0.0f && dist < min_dist) {
+                    float push = collision_stiffness * (min_dist - dist);
+                    total_force.x -= dir.x * push;
+                    total_force.y -= dir.y * push;
+                    total_force.z -= dir.z * push;
+                }
             }
         }
 
         // Stronger center gravity to prevent nodes from drifting too far
         float center_strength = 0.015f * mass * ramp_up_factor; // Apply ramp_up to center gravity too
         float center_dist = sqrtf(pos.x*pos.x + pos.y*pos.y + pos.z*pos.z);
-        if (center_dist > 3.0f) { // Apply at shorter distances
+        if (spring_electric && center_dist > 3.0f) { // Apply at shorter distances
             float center_factor = center_strength * (center_dist - 3.0f) / center_dist;
             total_force.x -= pos.x * center_factor;
             total_force.y -= pos.y * center_factor;
             total_force.z -= pos.z * center_factor;
         }
 
+        // Degree gravity: hubs are pulled towards the centre harder than leaves
+        if (layout.degree_gravity > 0.0f) {
+            float stiffness = layout.degree_gravity * node_degree(layout, idx);
+            total_force.x -= pos.x * stiffness;
+            total_force.y -= pos.y * stiffness;
+            total_force.z -= pos.z * stiffness;
+        }
+
+        // 2D layouts: no motion out of the plane
+        bool planar = !isnan(plane_z);
+        if (planar) {
+            total_force.z = 0.0f;
+            vel.z = 0.0f;
+        }
+
         // Calculate total force magnitude
         float force_magnitude = sqrtf(
             total_force.x*total_force.x +
@@ -214,6 +362,11 @@ extern "C" {
             }
         }
 
+        if (planar) {
+            pos.z = plane_z;
+            vel.z = 0.0f;
+        }
+
         // Store results back
         nodes[idx].position.x = pos.x;
         nodes[idx].position.y = pos.y;
@@ -1,6 +1,8 @@
 use cudarc::driver::{CudaDevice, CudaFunction, CudaSlice, LaunchConfig, LaunchAsync};
 use cudarc::nvrtc::Ptx;
 use cudarc::driver::sys::CUdevice_attribute_enum;
+use cudarc::driver::{result, sys, DevicePtr, DeviceRepr};
+use bytemuck::Zeroable;
 
 use std::io::{Error, ErrorKind};
 use std::sync::Arc;
@@ -40,6 +42,224 @@ pub struct GPUCompute {
     pub node_indices: HashMap<u32, usize>,
     pub simulation_params: SimulationParams,
     pub iteration_count: u32,
+    pub readback: ReadbackFrames,
+    pub edges: GpuEdges,
+}
+
+/// Adjacency in compressed sparse row form: the neighbours of the node at index
+/// `i` are `targets[offsets[i]..offsets[i + 1]]`, each edge listed from both ends
+/// with its weight normalized as `params` asks. Edges to nodes that aren't in
+/// `node_indices` are dropped.
+#[derive(Debug, Default, PartialEq)]
+pub struct EdgeCsr {
+    pub offsets: Vec<i32>,
+    pub targets: Vec<i32>,
+    pub weights: Vec<f32>,
+}
+
+impl EdgeCsr {
+    pub fn new(graph: &GraphData, node_indices: &HashMap<u32, usize>, params: &SimulationParams) -> Self {
+        let mut neighbours: Vec<Vec<(i32, f32)>> = vec![Vec::new(); graph.nodes.len()];
+        for (edge, weight) in graph.edges.iter().zip(params.edge_weights(&graph.edges)) {
+            let (Some(&a), Some(&b)) = (node_indices.get(&edge.source), node_indices.get(&edge.target)) else {
+                continue;
+            };
+            if a == b || a >= neighbours.len() || b >= neighbours.len() {
+                continue;
+            }
+            neighbours[a].push((b as i32, weight));
+            neighbours[b].push((a as i32, weight));
+        }
+        let mut csr = Self { offsets: Vec::with_capacity(neighbours.len() + 1), ..Default::default() };
+        csr.offsets.push(0);
+        for list in neighbours {
+            for (target, weight) in list {
+                csr.targets.push(target);
+                csr.weights.push(weight);
+            }
+            csr.offsets.push(csr.targets.len() as i32);
+        }
+        csr
+    }
+
+    pub fn max_degree(&self) -> i32 {
+        self.offsets.windows(2).map(|w| w[1] - w[0]).max().unwrap_or(0)
+    }
+}
+
+/// `EdgeCsr` on the device, with the host copy it was uploaded from. Only the
+/// ForceAtlas2 models read it. The buffers hold at least one element, since CUDA
+/// won't allocate zero bytes.
+#[derive(Debug)]
+pub struct GpuEdges {
+    pub csr: EdgeCsr,
+    pub offsets: CudaSlice<i32>,
+    pub targets: CudaSlice<i32>,
+    pub weights: CudaSlice<f32>,
+}
+
+impl GpuEdges {
+    pub fn upload(device: &Arc<CudaDevice>, csr: EdgeCsr) -> Result<Self, Error> {
+        fn copy<T: DeviceRepr + Clone + Default + Unpin>(device: &Arc<CudaDevice>, data: &[T]) -> Result<CudaSlice<T>, Error> {
+            let padded;
+            let data = if data.is_empty() {
+                padded = [T::default()];
+                &padded[..]
+            } else {
+                data
+            };
+            device.htod_sync_copy(data)
+                .map_err(|e| Error::other(format!("Failed to copy edges to GPU: {}", e)))
+        }
+        Ok(Self {
+            offsets: copy(device, &csr.offsets)?,
+            targets: copy(device, &csr.targets)?,
+            weights: copy(device, &csr.weights)?,
+            csr,
+        })
+    }
+}
+
+/// Kernel arguments after `iteration_count`, passed as one struct because a launch
+/// takes at most 12 arguments. Must match `LayoutParams` in compute_forces.cu.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct GPULayoutParams {
+    pub collision_radius: f32,
+    pub collision_stiffness: f32,
+    pub plane_z: f32,
+    pub force_model: i32,
+    pub gravity: f32,
+    pub strong_gravity: i32,
+    pub edge_offsets: sys::CUdeviceptr,
+    pub edge_targets: sys::CUdeviceptr,
+    pub edge_weights: sys::CUdeviceptr,
+    /// Stiffness of the spring to the origin per unit of degree
+    pub degree_gravity: f32,
+}
+
+unsafe impl DeviceRepr for GPULayoutParams {}
+
+impl GPULayoutParams {
+    pub fn new(params: &SimulationParams, edges: &GpuEdges) -> Self {
+        Self {
+            collision_radius: params.collision_radius,
+            collision_stiffness: params.collision_stiffness,
+            plane_z: params.plane_z.unwrap_or(f32::NAN),
+            force_model: params.mode as i32,
+            gravity: params.gravity,
+            strong_gravity: params.strong_gravity as i32,
+            edge_offsets: *edges.offsets.device_ptr(),
+            edge_targets: *edges.targets.device_ptr(),
+            edge_weights: *edges.weights.device_ptr(),
+            degree_gravity: match edges.csr.max_degree() {
+                0 => 0.0,
+                max_degree => params.degree_gravity / max_degree as f32,
+            },
+        }
+    }
+}
+
+/// Host side of the position readback, double buffered so the GPU can copy a
+/// finished step into `back` while the step before it, in `front`, is applied
+/// and broadcast. Both buffers are page-locked once CUDA is up, which is what
+/// lets the copy run without blocking the host.
+#[derive(Debug, Default)]
+pub struct ReadbackFrames {
+    front: Vec<BinaryNodeData>,
+    back: Vec<BinaryNodeData>,
+    front_iteration: Option<u32>,
+    in_flight: Option<u32>,
+    pinned: bool,
+}
+
+impl ReadbackFrames {
+    /// Sizes both buffers for `len` nodes, returning whether they were reallocated.
+    /// Must not be called while a copy is in flight.
+    fn resize(&mut self, len: usize) -> bool {
+        if self.back.len() == len {
+            return false;
+        }
+        self.unpin();
+        self.front = vec![BinaryNodeData::zeroed(); len];
+        self.back = vec![BinaryNodeData::zeroed(); len];
+        self.front_iteration = None;
+        true
+    }
+
+    /// Marks a copy of `iteration` as queued into the back buffer
+    fn begin(&mut self, iteration: u32) -> &mut [BinaryNodeData] {
+        self.in_flight = Some(iteration);
+        &mut self.back
+    }
+
+    /// Hands a landed copy over to the front buffer
+    fn finish(&mut self) -> Option<u32> {
+        let iteration = self.in_flight.take()?;
+        std::mem::swap(&mut self.front, &mut self.back);
+        self.front_iteration = Some(iteration);
+        Some(iteration)
+    }
+
+    /// Forgets every frame, e.g. once node indices no longer match them
+    fn invalidate(&mut self) {
+        self.in_flight = None;
+        self.front_iteration = None;
+    }
+
+    pub fn in_flight(&self) -> bool {
+        self.in_flight.is_some()
+    }
+
+    /// The most recent complete frame and the iteration it was taken after
+    pub fn latest(&self) -> Option<(u32, &[BinaryNodeData])> {
+        self.front_iteration.map(|iteration| (iteration, self.front.as_slice()))
+    }
+
+    fn pin(&mut self) {
+        if self.pinned || self.back.is_empty() {
+            return;
+        }
+        let register = |buf: &mut Vec<BinaryNodeData>| unsafe {
+            sys::lib().cuMemHostRegister_v2(buf.as_mut_ptr().cast(), std::mem::size_of_val(buf.as_slice()), 0).result()
+        };
+        match register(&mut self.front) {
+            Ok(()) => match register(&mut self.back) {
+                Ok(()) => self.pinned = true,
+                Err(e) => {
+                    unsafe { let _ = sys::lib().cuMemHostUnregister(self.front.as_mut_ptr().cast()); }
+                    warn!("Could not page-lock readback buffer, copies will block: {}", e);
+                }
+            },
+            Err(e) => warn!("Could not page-lock readback buffer, copies will block: {}", e),
+        }
+    }
+
+    fn unpin(&mut self) {
+        if !self.pinned {
+            return;
+        }
+        unsafe {
+            let _ = sys::lib().cuMemHostUnregister(self.front.as_mut_ptr().cast());
+            let _ = sys::lib().cuMemHostUnregister(self.back.as_mut_ptr().cast());
+        }
+        self.pinned = false;
+    }
+}
+
+impl Drop for ReadbackFrames {
+    fn drop(&mut self) {
+        self.unpin();
+    }
+}
+
+impl Drop for GPUCompute {
+    fn drop(&mut self) {
+        // A queued readback still writes into host memory owned by `readback`
+        if self.readback.in_flight() {
+            let _ = self.device.synchronize();
+        }
+    }
 }
 
 impl GPUCompute {
@@ -287,6 +507,7 @@ impl GPUCompute {
             node_indices.insert(node.id, idx);
         }
 
+        let edges = GpuEdges::upload(&device, EdgeCsr::default())?;
         let mut instance = Self {
             device: Arc::clone(&device),
             force_kernel,
@@ -295,6 +516,8 @@ impl GPUCompute {
             node_indices,
             simulation_params: SimulationParams::default(),
             iteration_count: 0,
+            readback: ReadbackFrames::default(),
+            edges,
         };
 
         info!("Copying initial graph data to device memory");
@@ -305,6 +528,13 @@ impl GPUCompute {
 
     pub fn update_graph_data(&mut self, graph: &GraphData) -> Result<(), Error> {
         trace!("Updating graph data for {} nodes", graph.nodes.len());
+        let reordered = graph.nodes.len() != self.node_indices.len()
+            || graph.nodes.iter().enumerate().any(|(idx, node)| self.node_indices.get(&node.id) != Some(&idx));
+        if reordered {
+            // Frames read back so far are laid out by the old indices
+            self.collect_readback()?;
+            self.readback.invalidate();
+        }
         self.node_indices.clear();
         for (idx, node) in graph.nodes.iter().enumerate() {
             self.node_indices.insert(node.id, idx);
@@ -331,13 +561,7 @@ impl GPUCompute {
             }
         }
         for node in &graph.nodes {
-            node_data.push(BinaryNodeData {
-                position: node.data.position.clone(),
-                velocity: node.data.velocity.clone(),
-                mass: node.data.mass,
-                flags: node.data.flags,
-                padding: node.data.padding,
-            });
+            node_data.push(self.simulation_params.gpu_node_data(node));
             if node.id == 0 || node.id == 1 {
                 trace!(
                     "Node {} data prepared for GPU: pos=[{:.3},{:.3},{:.3}], vel=[{:.3},{:.3},{:.3}]",
@@ -350,6 +574,13 @@ impl GPUCompute {
         trace!("Copying {} nodes to GPU", graph.nodes.len());
         self.device.htod_sync_copy_into(&node_data, &mut self.node_data)
             .map_err(|e| Error::new(ErrorKind::Other, format!("Failed to copy node data to GPU: {}", e)))?;
+        if self.simulation_params.uses_edges() {
+            let csr = EdgeCsr::new(graph, &self.node_indices, &self.simulation_params);
+            if csr != self.edges.csr {
+                trace!("Copying {} edges to GPU", csr.targets.len() / 2);
+                self.edges = GpuEdges::upload(&self.device, csr)?;
+            }
+        }
         Ok(())
     }
 
@@ -389,6 +620,7 @@ impl GPUCompute {
                     f32::MAX // disable bounds
                 },
                 self.iteration_count as i32,
+                GPULayoutParams::new(&self.simulation_params, &self.edges),
             )).map_err(|e| {
                 error!("Kernel launch failed: {}", e);
                 Error::new(ErrorKind::Other, e.to_string())
@@ -430,6 +662,50 @@ impl GPUCompute {
         Ok(gpu_raw_data)
     }
 
+    /// Queues a copy of the node data as it stands after the steps queued so far.
+    /// The copy follows the kernel on the device's stream, so this returns without
+    /// waiting for either; `collect_readback` picks the frame up later.
+    pub fn start_readback(&mut self) -> Result<(), Error> {
+        // The back buffer can't be reused until the previous copy has landed
+        self.collect_readback()?;
+        if self.readback.resize(self.num_nodes as usize) {
+            self.readback.pin();
+        }
+        if self.num_nodes == 0 {
+            return Ok(());
+        }
+        let src = *self.node_data.device_ptr();
+        let stream = *self.device.cu_stream();
+        let dst = self.readback.begin(self.iteration_count);
+        unsafe { result::memcpy_dtoh_async(dst, src, stream) }.map_err(|e| {
+            self.readback.invalidate();
+            Error::other(format!("Failed to queue readback from GPU: {}", e))
+        })
+    }
+
+    /// Waits for the queued readback, if any, and makes it the latest frame,
+    /// returning the iteration it was taken after. Collected a frame after it was
+    /// queued, the copy has normally long finished and this doesn't block.
+    pub fn collect_readback(&mut self) -> Result<Option<u32>, Error> {
+        if !self.readback.in_flight() {
+            return Ok(None);
+        }
+        if let Err(e) = self.device.synchronize() {
+            self.readback.invalidate();
+            return Err(Error::other(format!("Failed waiting for GPU readback: {}", e)));
+        }
+        Ok(self.readback.finish())
+    }
+
+    /// The latest collected frame as (node id, data) pairs
+    pub fn latest_positions(&self) -> Option<(u32, Vec<(u32, BinaryNodeData)>)> {
+        let (iteration, frame) = self.readback.latest()?;
+        let positions = self.node_indices.iter()
+            .filter_map(|(&id, &idx)| frame.get(idx).map(|data| (id, *data)))
+            .collect();
+        Some((iteration, positions))
+    }
+
     /// Advances one simulation step.
     pub fn step(&mut self) -> Result<(), Error> {
         trace!("Executing physics step (iteration {})", self.iteration_count);
@@ -484,6 +760,53 @@ mod tests {
         assert_eq!(node_data.len(), graph.nodes.len());
     }
 
+    #[test]
+    fn test_edge_csr() {
+        use crate::models::edge::Edge;
+        use crate::models::node::Node;
+
+        let mut graph = GraphData::default();
+        for (id, name) in [(1, "a"), (2, "b"), (3, "c")] {
+            graph.nodes.push(Node::new_with_id(name.to_string(), Some(id)));
+        }
+        graph.edges = vec![Edge::new(1, 2, 2.0), Edge::new(2, 3, 1.0), Edge::new(3, 99, 1.0)];
+        let indices = graph.nodes.iter().enumerate().map(|(i, n)| (n.id, i)).collect();
+
+        let mut params = SimulationParams::new();
+        let csr = EdgeCsr::new(&graph, &indices, &params);
+        assert_eq!(csr.offsets, vec![0, 1, 3, 4]);
+        assert_eq!(csr.targets, vec![1, 0, 2, 1]);
+        assert_eq!(csr.weights, vec![2.0, 2.0, 1.0, 1.0]);
+        assert_eq!(csr.max_degree(), 2);
+
+        params.edge_weight_normalization = crate::config::EdgeWeightNormalization::Max;
+        assert_eq!(EdgeCsr::new(&graph, &indices, &params).weights, vec![1.0, 1.0, 0.5, 0.5]);
+    }
+
+    #[test]
+    fn test_readback_frames_swap() {
+        let mut frames = ReadbackFrames::default();
+        assert!(frames.resize(2));
+        assert!(!frames.resize(2));
+        assert!(frames.latest().is_none());
+
+        frames.begin(7)[1].position.x = 1.5;
+        assert!(frames.in_flight());
+        assert!(frames.latest().is_none());
+        assert_eq!(frames.finish(), Some(7));
+        assert_eq!(frames.finish(), None);
+        let (iteration, front) = frames.latest().unwrap();
+        assert_eq!(iteration, 7);
+        assert_eq!(front[1].position.x, 1.5);
+
+        // The next copy goes into the other buffer, leaving the front frame intact
+        frames.begin(8)[1].position.x = 2.5;
+        assert_eq!(frames.latest().unwrap().1[1].position.x, 1.5);
+        frames.invalidate();
+        assert!(!frames.in_flight());
+        assert!(frames.latest().is_none());
+    }
+
     #[test]
     fn test_node_data_memory_layout() {
         info!("Checking BinaryNodeData memory layout");
@@ -0,0 +1,107 @@
+//! JSON Merge Patch (RFC 7386) for settings documents
+//!
+//! Patch keys are matched against the document's existing keys after converting
+//! both to snake_case, so a camelCase patch applies to snake_case settings and the
+//! other way round. Keys that don't exist in the document are rejected rather than
+//! added, since they would be silently dropped when deserializing the settings.
+
+use serde_json::{Map, Value};
+
+use crate::utils::case_conversion::to_snake_case;
+
+/// Applies `patch` to `target`. Fails with the dotted path of the first patch key
+/// that doesn't name an existing setting.
+pub fn apply(target: &mut Value, patch: &Value) -> Result<(), String> {
+    apply_at(target, patch, "")
+}
+
+fn apply_at(target: &mut Value, patch: &Value, path: &str) -> Result<(), String> {
+    let Value::Object(patch_map) = patch else {
+        *target = patch.clone();
+        return Ok(());
+    };
+    let Value::Object(target_map) = target else {
+        return Err(format!("Setting '{}' is not an object", path));
+    };
+
+    for (key, value) in patch_map {
+        let existing = find_key(target_map, key)
+            .ok_or_else(|| format!("Unknown setting '{}'", join(path, key)))?;
+        let child_path = join(path, &existing);
+        if value.is_null() {
+            target_map.remove(&existing);
+        } else if let Some(child) = target_map.get_mut(&existing) {
+            if child.is_null() && value.is_object() {
+                // An unset optional section can be filled in as a whole
+                *child = value.clone();
+            } else {
+                apply_at(child, value, &child_path)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn find_key(map: &Map<String, Value>, key: &str) -> Option<String> {
+    if map.contains_key(key) {
+        return Some(key.to_string());
+    }
+    let wanted = to_snake_case(key);
+    map.keys().find(|existing| to_snake_case(existing) == wanted).cloned()
+}
+
+fn join(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+/// The merge patch taking `before` to `after`: only changed values, with removed
+/// keys as `null`. `None` when nothing changed.
+pub fn diff(before: &Value, after: &Value) -> Option<Value> {
+    match (before, after) {
+        (Value::Object(old), Value::Object(new)) => {
+            let mut changed = Map::new();
+            for (key, new_value) in new {
+                let delta = match old.get(key) {
+                    Some(old_value) => diff(old_value, new_value),
+                    None => Some(new_value.clone()),
+                };
+                if let Some(delta) = delta {
+                    changed.insert(key.clone(), delta);
+                }
+            }
+            for key in old.keys().filter(|key| !new.contains_key(*key)) {
+                changed.insert(key.clone(), Value::Null);
+            }
+            (!changed.is_empty()).then_some(Value::Object(changed))
+        }
+        _ => (before != after).then(|| after.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_apply_matches_keys_across_case() {
+        let mut doc = json!({"physics": {"spring_strength": 0.2, "damping": 0.9}, "teleport_ray_color": "#fff"});
+        apply(&mut doc, &json!({"physics": {"springStrength": 0.5}, "teleportRayColor": null})).unwrap();
+        assert_eq!(doc, json!({"physics": {"spring_strength": 0.5, "damping": 0.9}}));
+
+        let err = apply(&mut doc, &json!({"physics": {"gravity": 1}})).unwrap_err();
+        assert_eq!(err, "Unknown setting 'physics.gravity'");
+    }
+
+    #[test]
+    fn test_diff_returns_changed_subtree() {
+        let before = json!({"a": {"b": 1, "c": 2}, "d": true, "e": "x"});
+        let after = json!({"a": {"b": 1, "c": 3}, "d": true});
+        assert_eq!(diff(&before, &after), Some(json!({"a": {"c": 3}, "e": null})));
+        assert_eq!(diff(&before, &before), None);
+    }
+}
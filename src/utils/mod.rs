@@ -1,7 +1,10 @@
 pub mod audio_processor;
-pub mod binary_protocol;
+pub mod auth;
+pub use webxr_core::{binary_protocol, case_conversion, protocol};
 pub mod edge_data;
 pub mod gpu_compute;
 pub mod logging;
+pub mod merge_patch;
 pub mod socket_flow_constants;
 pub mod socket_flow_messages;
+pub mod view_culling;
@@ -0,0 +1,189 @@
+//! Server-side culling of streamed nodes against a client's camera
+//!
+//! Clients that report a camera pose only receive nodes inside a slightly widened
+//! view frustum, plus any node close enough to the camera to matter whichever way
+//! it is facing. When more nodes than the client's per-frame budget have changed,
+//! the nearest ones are sent first and the rest follow on later frames.
+
+use glam::Vec3;
+use serde::Deserialize;
+
+use crate::types::vec3::Vec3Data;
+use crate::utils::binary_protocol::EncodedFrame;
+
+// Widens the frustum so nodes just off screen already have fresh positions when
+// the camera turns towards them
+const FRUSTUM_MARGIN: f32 = 1.25;
+const DEFAULT_FOV_Y_DEGREES: f32 = 75.0;
+const DEFAULT_FAR: f32 = 1000.0;
+const DEFAULT_VIEW_RADIUS: f32 = 5.0;
+const DEFAULT_NODE_BUDGET: usize = 10_000;
+
+/// Camera pose sent by the client in a `cameraPose` message
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CameraPose {
+    pub position: Vec3Data,
+    /// View direction; need not be normalised
+    pub forward: Vec3Data,
+    #[serde(default = "default_up")]
+    pub up: Vec3Data,
+    /// Vertical field of view in degrees
+    #[serde(default = "default_fov_y")]
+    pub fov_y: f32,
+    /// Viewport width divided by height
+    #[serde(default = "default_aspect")]
+    pub aspect: f32,
+    /// Nodes further away than this are never streamed
+    pub far: Option<f32>,
+    /// Nodes within this distance are streamed even when outside the frustum
+    pub radius: Option<f32>,
+    /// Maximum nodes sent per frame
+    pub node_budget: Option<usize>,
+}
+
+fn default_up() -> Vec3Data {
+    Vec3Data::new(0.0, 1.0, 0.0)
+}
+
+fn default_fov_y() -> f32 {
+    DEFAULT_FOV_Y_DEGREES
+}
+
+fn default_aspect() -> f32 {
+    1.0
+}
+
+#[derive(Debug, Clone)]
+pub struct ViewCuller {
+    eye: Vec3,
+    forward: Vec3,
+    right: Vec3,
+    up: Vec3,
+    tan_half_x: f32,
+    tan_half_y: f32,
+    far_sq: f32,
+    radius_sq: f32,
+    node_budget: usize,
+}
+
+impl ViewCuller {
+    pub fn new(pose: &CameraPose) -> Result<Self, String> {
+        let eye = Vec3::from(pose.position);
+        let forward = Vec3::from(pose.forward).try_normalize()
+            .ok_or_else(|| "Camera forward vector must be non-zero".to_string())?;
+        let right = forward.cross(Vec3::from(pose.up)).try_normalize()
+            .ok_or_else(|| "Camera up vector must not be parallel to forward".to_string())?;
+        let up = right.cross(forward);
+
+        if !eye.is_finite() {
+            return Err("Camera position must be finite".to_string());
+        }
+        if !(pose.fov_y > 0.0 && pose.fov_y < 180.0) {
+            return Err(format!("Field of view {} must be between 0 and 180 degrees", pose.fov_y));
+        }
+        if !(pose.aspect.is_finite() && pose.aspect > 0.0) {
+            return Err(format!("Aspect ratio {} must be positive", pose.aspect));
+        }
+
+        let tan_half_y = (pose.fov_y.to_radians() / 2.0).tan() * FRUSTUM_MARGIN;
+        let far = pose.far.unwrap_or(DEFAULT_FAR).max(0.0);
+        let radius = pose.radius.unwrap_or(DEFAULT_VIEW_RADIUS).max(0.0);
+
+        Ok(Self {
+            eye,
+            forward,
+            right,
+            up,
+            tan_half_x: tan_half_y * pose.aspect,
+            tan_half_y,
+            far_sq: far * far,
+            radius_sq: radius * radius,
+            node_budget: pose.node_budget.unwrap_or(DEFAULT_NODE_BUDGET).max(1),
+        })
+    }
+
+    fn distance_sq(&self, position: Vec3Data) -> f32 {
+        Vec3::from(position).distance_squared(self.eye)
+    }
+
+    pub fn is_visible(&self, position: Vec3Data) -> bool {
+        let offset = Vec3::from(position) - self.eye;
+        let distance_sq = offset.length_squared();
+        if distance_sq <= self.radius_sq {
+            return true;
+        }
+        if distance_sq > self.far_sq {
+            return false;
+        }
+
+        let depth = offset.dot(self.forward);
+        depth > 0.0
+            && offset.dot(self.right).abs() <= depth * self.tan_half_x
+            && offset.dot(self.up).abs() <= depth * self.tan_half_y
+    }
+
+    /// Trims frame `indices` to the node budget, keeping the nodes nearest the
+    /// camera. The survivors stay in frame order.
+    pub fn apply_budget(&self, frame: &EncodedFrame, indices: &mut Vec<usize>) {
+        if indices.len() <= self.node_budget {
+            return;
+        }
+        indices.select_nth_unstable_by(self.node_budget, |&a, &b| {
+            self.distance_sq(frame.item(a).position)
+                .total_cmp(&self.distance_sq(frame.item(b).position))
+        });
+        indices.truncate(self.node_budget);
+        indices.sort_unstable();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::binary_protocol::FrameEncoder;
+    use crate::utils::socket_flow_messages::BinaryNodeData;
+
+    fn pose_json(extra: &str) -> CameraPose {
+        serde_json::from_str(&format!(
+            r#"{{"position": {{"x": 0, "y": 0, "z": 0}}, "forward": {{"x": 0, "y": 0, "z": -2}}{}}}"#,
+            extra
+        )).unwrap()
+    }
+
+    #[test]
+    fn test_frustum_and_radius() {
+        let culler = ViewCuller::new(&pose_json(r#", "fovY": 90, "far": 50, "radius": 2"#)).unwrap();
+        assert!(culler.is_visible(Vec3Data::new(0.0, 0.0, -10.0)));
+        assert!(culler.is_visible(Vec3Data::new(9.0, 0.0, -10.0)));
+        assert!(!culler.is_visible(Vec3Data::new(20.0, 0.0, -10.0)));
+        assert!(!culler.is_visible(Vec3Data::new(0.0, 0.0, 10.0)));
+        assert!(!culler.is_visible(Vec3Data::new(0.0, 0.0, -60.0)));
+        // Behind the camera but within the radius
+        assert!(culler.is_visible(Vec3Data::new(0.0, 0.0, 1.5)));
+    }
+
+    #[test]
+    fn test_budget_keeps_nearest_in_frame_order() {
+        let culler = ViewCuller::new(&pose_json(r#", "nodeBudget": 2"#)).unwrap();
+        let frame = FrameEncoder::new().encode([30.0f32, 5.0, 20.0, 10.0].into_iter().enumerate().map(|(i, z)| {
+            (i as u32, BinaryNodeData {
+                position: Vec3Data::new(0.0, 0.0, -z),
+                velocity: Vec3Data::zero(),
+                mass: 100,
+                flags: 1,
+                padding: [0, 0],
+            })
+        }));
+
+        let mut indices = vec![0, 1, 2, 3];
+        culler.apply_budget(&frame, &mut indices);
+        assert_eq!(indices, vec![1, 3]);
+    }
+
+    #[test]
+    fn test_rejects_degenerate_pose() {
+        assert!(ViewCuller::new(&pose_json(r#", "up": {"x": 0, "y": 0, "z": 1}"#)).is_err());
+        assert!(ViewCuller::new(&pose_json(r#", "fovY": 0"#)).is_err());
+    }
+}
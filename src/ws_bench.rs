@@ -0,0 +1,376 @@
+//! Load test for the WebSocket pipeline
+//!
+//! `ws-bench` opens `--clients` connections to `/wss`, each going through the
+//! client's `requestInitialData` handshake and then reading binary frames and
+//! pinging for `--duration` seconds, as a browser tab with the graph open would.
+//! The report gives throughput and the spread of connect time, time to the first
+//! position frame, ping round trips and gaps between frames, so a change to the
+//! protocol or the broadcaster can be measured against the one before it.
+
+use clap::{Parser, ValueEnum};
+use futures_util::{SinkExt, StreamExt};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+use tokio_tungstenite::connect_async;
+use tokio_tungstenite::tungstenite::Message;
+
+use webxr_core::binary_protocol::{decode_node_data, decode_quantized_node_data, QuantizationBounds, WireEncoding};
+use webxr_core::protocol::{split_frame, FrameKind, PROTOCOL_VERSION};
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BenchEncoding {
+    Float32,
+    Quantized16,
+}
+
+impl From<BenchEncoding> for WireEncoding {
+    fn from(encoding: BenchEncoding) -> Self {
+        match encoding {
+            BenchEncoding::Float32 => WireEncoding::Float32,
+            BenchEncoding::Quantized16 => WireEncoding::Quantized16,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Parser)]
+#[command(name = "ws-bench", about = "Load test the WebSocket pipeline with simulated clients")]
+pub struct BenchArgs {
+    /// WebSocket endpoint of the server
+    #[arg(long, default_value = "ws://localhost:3001/wss")]
+    pub url: String,
+    /// Simulated clients
+    #[arg(short, long, default_value_t = 50)]
+    pub clients: usize,
+    /// Seconds each client stays connected
+    #[arg(short, long, default_value_t = 30)]
+    pub duration: u64,
+    /// Milliseconds between starting one client and the next
+    #[arg(long, default_value_t = 20)]
+    pub ramp_ms: u64,
+    /// Position encoding the clients request
+    #[arg(long, value_enum, default_value_t = BenchEncoding::Float32)]
+    pub encoding: BenchEncoding,
+    /// Milliseconds between pings from each client
+    #[arg(long, default_value_t = 1000)]
+    pub ping_ms: u64,
+    /// Print the report as JSON
+    #[arg(long)]
+    pub json: bool,
+}
+
+/// What one simulated client saw
+#[derive(Debug, Default)]
+struct ClientStats {
+    error: Option<String>,
+    connect: Option<Duration>,
+    initial_data: Option<Duration>,
+    first_frame: Option<Duration>,
+    frames: u64,
+    edge_frames: u64,
+    bytes: u64,
+    node_records: u64,
+    decode_errors: u64,
+    rtts: Vec<Duration>,
+    gaps: Vec<Duration>,
+    last_frame: Option<Instant>,
+    bounds: Option<QuantizationBounds>,
+}
+
+impl ClientStats {
+    /// Counts a binary message received `since_request` after asking for data
+    fn frame(&mut self, data: &[u8], since_request: Duration, now: Instant) {
+        self.bytes += data.len() as u64;
+        let Ok((header, payload)) = split_frame(data) else {
+            self.decode_errors += 1;
+            return;
+        };
+        let decoded = match (header.kind(), header.encoding()) {
+            (Some(FrameKind::Edges), _) => {
+                self.edge_frames += 1;
+                return;
+            }
+            (Some(FrameKind::Positions), Some(WireEncoding::Float32)) => decode_node_data(payload).ok(),
+            (Some(FrameKind::Positions), Some(WireEncoding::Quantized16)) => self.bounds
+                .and_then(|bounds| decode_quantized_node_data(payload, &bounds).ok()),
+            _ => None,
+        };
+        let Some(records) = decoded else {
+            self.decode_errors += 1;
+            return;
+        };
+        self.frames += 1;
+        self.node_records += records.len() as u64;
+        self.first_frame.get_or_insert(since_request);
+        if let Some(last) = self.last_frame.replace(now) {
+            self.gaps.push(now - last);
+        }
+    }
+
+    fn text(&mut self, text: &str, since_request: Duration, epoch: Instant) {
+        let Ok(message) = serde_json::from_str::<serde_json::Value>(text) else {
+            return;
+        };
+        match message["type"].as_str() {
+            Some("updatesStarted") => {
+                self.initial_data.get_or_insert(since_request);
+                let bounds = &message["bounds"];
+                if let (Some(half_extent), Some(max_velocity)) = (bounds["halfExtent"].as_f64(), bounds["maxVelocity"].as_f64()) {
+                    self.bounds = Some(QuantizationBounds { half_extent: half_extent as f32, max_velocity: max_velocity as f32 });
+                }
+            }
+            // Pings carry microseconds since the bench started, echoed back as is
+            Some("pong") => {
+                if let Some(sent) = message["timestamp"].as_u64() {
+                    self.rtts.push(epoch.elapsed().saturating_sub(Duration::from_micros(sent)));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+async fn run_client(args: BenchArgs, epoch: Instant) -> ClientStats {
+    let mut stats = ClientStats::default();
+    let started = Instant::now();
+    let socket = match tokio::time::timeout(CONNECT_TIMEOUT, connect_async(args.url.as_str())).await {
+        Ok(Ok((socket, _))) => socket,
+        Ok(Err(e)) => {
+            stats.error = Some(e.to_string());
+            return stats;
+        }
+        Err(_) => {
+            stats.error = Some("connect timed out".to_string());
+            return stats;
+        }
+    };
+    stats.connect = Some(started.elapsed());
+    let (mut sink, mut stream) = socket.split();
+
+    let requested = Instant::now();
+    let request = json!({
+        "type": "requestInitialData",
+        "encoding": WireEncoding::from(args.encoding),
+        "protocolVersion": PROTOCOL_VERSION,
+    });
+    if let Err(e) = sink.send(Message::Text(request.to_string())).await {
+        stats.error = Some(e.to_string());
+        return stats;
+    }
+
+    let deadline = tokio::time::sleep(Duration::from_secs(args.duration));
+    tokio::pin!(deadline);
+    let mut ping = tokio::time::interval(Duration::from_millis(args.ping_ms.max(1)));
+    loop {
+        tokio::select! {
+            _ = &mut deadline => break,
+            _ = ping.tick() => {
+                let ping = json!({ "type": "ping", "timestamp": epoch.elapsed().as_micros() as u64 });
+                if let Err(e) = sink.send(Message::Text(ping.to_string())).await {
+                    stats.error = Some(e.to_string());
+                    break;
+                }
+            }
+            message = stream.next() => match message {
+                Some(Ok(Message::Binary(data))) => stats.frame(&data, requested.elapsed(), Instant::now()),
+                Some(Ok(Message::Text(text))) => stats.text(&text, requested.elapsed(), epoch),
+                Some(Ok(Message::Close(_))) | None => {
+                    stats.error = Some("closed by the server".to_string());
+                    break;
+                }
+                Some(Ok(_)) => {}
+                Some(Err(e)) => {
+                    stats.error = Some(e.to_string());
+                    break;
+                }
+            },
+        }
+    }
+    let _ = sink.send(Message::Close(None)).await;
+    stats
+}
+
+/// Percentiles of a set of durations, in milliseconds
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Percentiles {
+    pub samples: usize,
+    pub p50: f64,
+    pub p90: f64,
+    pub p99: f64,
+    pub max: f64,
+}
+
+impl Percentiles {
+    /// Nearest-rank percentiles; `None` without samples
+    pub fn of(samples: &mut [Duration]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort_unstable();
+        let at = |p: f64| {
+            let rank = ((p * samples.len() as f64).ceil() as usize).clamp(1, samples.len());
+            samples[rank - 1].as_secs_f64() * 1000.0
+        };
+        Some(Self { samples: samples.len(), p50: at(0.5), p90: at(0.9), p99: at(0.99), max: at(1.0) })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    pub clients: usize,
+    pub connected: usize,
+    /// Clients that failed or were dropped, by error
+    pub errors: BTreeMap<String, usize>,
+    pub duration_secs: u64,
+    pub frames: u64,
+    pub edge_frames: u64,
+    pub node_records: u64,
+    pub bytes: u64,
+    pub decode_errors: u64,
+    pub frames_per_sec: f64,
+    pub node_records_per_sec: f64,
+    pub megabytes_per_sec: f64,
+    pub connect: Option<Percentiles>,
+    /// From `requestInitialData` to `updatesStarted`
+    pub initial_data: Option<Percentiles>,
+    /// From `requestInitialData` to the first position frame
+    pub first_frame: Option<Percentiles>,
+    pub ping_rtt: Option<Percentiles>,
+    /// Between position frames on one connection
+    pub frame_interval: Option<Percentiles>,
+}
+
+impl Report {
+    fn from_clients(clients: Vec<ClientStats>, duration_secs: u64) -> Self {
+        let mut errors = BTreeMap::new();
+        let (mut connect, mut initial_data, mut first_frame, mut rtts, mut gaps) = (vec![], vec![], vec![], vec![], vec![]);
+        let mut report = Report {
+            clients: clients.len(),
+            connected: 0,
+            errors: BTreeMap::new(),
+            duration_secs,
+            frames: 0,
+            edge_frames: 0,
+            node_records: 0,
+            bytes: 0,
+            decode_errors: 0,
+            frames_per_sec: 0.0,
+            node_records_per_sec: 0.0,
+            megabytes_per_sec: 0.0,
+            connect: None,
+            initial_data: None,
+            first_frame: None,
+            ping_rtt: None,
+            frame_interval: None,
+        };
+        for client in clients {
+            if let Some(error) = client.error {
+                *errors.entry(error).or_insert(0) += 1;
+            }
+            report.connected += usize::from(client.connect.is_some());
+            report.frames += client.frames;
+            report.edge_frames += client.edge_frames;
+            report.node_records += client.node_records;
+            report.bytes += client.bytes;
+            report.decode_errors += client.decode_errors;
+            connect.extend(client.connect);
+            initial_data.extend(client.initial_data);
+            first_frame.extend(client.first_frame);
+            rtts.extend(client.rtts);
+            gaps.extend(client.gaps);
+        }
+        let seconds = duration_secs.max(1) as f64;
+        report.errors = errors;
+        report.frames_per_sec = report.frames as f64 / seconds;
+        report.node_records_per_sec = report.node_records as f64 / seconds;
+        report.megabytes_per_sec = report.bytes as f64 / seconds / 1_000_000.0;
+        report.connect = Percentiles::of(&mut connect);
+        report.initial_data = Percentiles::of(&mut initial_data);
+        report.first_frame = Percentiles::of(&mut first_frame);
+        report.ping_rtt = Percentiles::of(&mut rtts);
+        report.frame_interval = Percentiles::of(&mut gaps);
+        report
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Clients:        {} connected of {}", self.connected, self.clients)?;
+        for (error, count) in &self.errors {
+            writeln!(f, "  {} x {}", count, error)?;
+        }
+        writeln!(f, "Duration:       {}s per client", self.duration_secs)?;
+        writeln!(f, "Frames:         {} ({:.1}/s), {} edge frames, {} undecodable",
+            self.frames, self.frames_per_sec, self.edge_frames, self.decode_errors)?;
+        writeln!(f, "Node records:   {} ({:.0}/s)", self.node_records, self.node_records_per_sec)?;
+        writeln!(f, "Received:       {} bytes ({:.2} MB/s)", self.bytes, self.megabytes_per_sec)?;
+        writeln!(f)?;
+        writeln!(f, "{:<16}{:>9}{:>10}{:>10}{:>10}{:>10}", "ms", "samples", "p50", "p90", "p99", "max")?;
+        for (name, percentiles) in [
+            ("connect", &self.connect),
+            ("initial data", &self.initial_data),
+            ("first frame", &self.first_frame),
+            ("ping rtt", &self.ping_rtt),
+            ("frame interval", &self.frame_interval),
+        ] {
+            match percentiles {
+                Some(p) => writeln!(f, "{:<16}{:>9}{:>10.2}{:>10.2}{:>10.2}{:>10.2}", name, p.samples, p.p50, p.p90, p.p99, p.max)?,
+                None => writeln!(f, "{:<16}{:>9}", name, 0)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Runs the simulated clients and gathers what they saw
+pub async fn run(args: BenchArgs) -> Report {
+    let epoch = Instant::now();
+    let mut handles = Vec::with_capacity(args.clients);
+    for i in 0..args.clients {
+        if i > 0 && args.ramp_ms > 0 {
+            tokio::time::sleep(Duration::from_millis(args.ramp_ms)).await;
+        }
+        handles.push(tokio::spawn(run_client(args.clone(), epoch)));
+    }
+    let mut clients = Vec::with_capacity(handles.len());
+    for handle in handles {
+        clients.push(handle.await.unwrap_or_else(|e| ClientStats { error: Some(e.to_string()), ..Default::default() }));
+    }
+    Report::from_clients(clients, args.duration)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use webxr_core::binary_protocol::encode_node_data;
+    use webxr_core::models::node::Node;
+    use webxr_core::protocol::frame;
+
+    #[test]
+    fn test_bench_stats() {
+        let mut samples: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+        let p = Percentiles::of(&mut samples).unwrap();
+        assert_eq!((p.samples, p.p50, p.p90, p.p99, p.max), (100, 50.0, 90.0, 99.0, 100.0));
+        assert!(Percentiles::of(&mut []).is_none());
+
+        let nodes: Vec<_> = (1..=3).map(|id| (id, Node::new_with_id(id.to_string(), Some(id)).data)).collect();
+        let positions = frame(FrameKind::Positions, WireEncoding::Float32, &encode_node_data(&nodes));
+        let start = Instant::now();
+        let mut client = ClientStats::default();
+        client.frame(&positions, Duration::from_millis(5), start);
+        client.frame(&positions, Duration::from_millis(25), start + Duration::from_millis(20));
+        client.frame(&encode_node_data(&nodes), Duration::from_millis(30), start);
+        assert_eq!((client.frames, client.node_records, client.decode_errors), (2, 6, 1));
+        assert_eq!(client.first_frame, Some(Duration::from_millis(5)));
+        assert_eq!(client.gaps, vec![Duration::from_millis(20)]);
+
+        let report = Report::from_clients(vec![client, ClientStats { error: Some("refused".to_string()), ..Default::default() }], 2);
+        assert_eq!(report.frames_per_sec, 1.0);
+        assert_eq!(report.errors.get("refused"), Some(&1));
+    }
+}
@@ -0,0 +1,283 @@
+//! Handler tests against an in-memory app state
+
+use actix_web::{test, web, App};
+use futures::FutureExt;
+use glam::Vec3;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use webxr::actors::messages::{BuildGraphFromMetadata, UpdateNodePosition};
+use webxr::handlers::api_handler;
+use webxr::models::metadata::{Metadata, MetadataStore};
+use webxr::test_support::{sign_in, test_app_state, InMemoryGitHub};
+
+fn page(name: &str, node_id: &str, links: &[&str]) -> (String, Metadata) {
+    let file_name = format!("{}.md", name);
+    let metadata = Metadata {
+        file_name: file_name.clone(),
+        node_id: node_id.to_string(),
+        file_size: 100,
+        topic_counts: links.iter().map(|link| (link.to_string(), 1)).collect::<HashMap<_, _>>(),
+        ..Default::default()
+    };
+    (file_name, metadata)
+}
+
+#[actix_web::test]
+async fn graph_data_returns_the_built_graph() {
+    let metadata: MetadataStore = [page("Alpha", "1", &["Beta"]), page("Beta", "2", &["Alpha"])].into_iter().collect();
+    let state = test_app_state(InMemoryGitHub::new(), metadata.clone(), &[]).await;
+    state.graph_service_addr
+        .send(BuildGraphFromMetadata { metadata, seed: Some(1) })
+        .await
+        .unwrap()
+        .unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .service(web::scope("/api").configure(api_handler::config)),
+    ).await;
+    let resp = test::call_service(&app, test::TestRequest::get().uri("/api/graph/data").to_request()).await;
+    assert!(resp.status().is_success());
+
+    let body: Value = test::read_body_json(resp).await;
+    let mut pages: Vec<&str> = body["nodes"].as_array().unwrap().iter()
+        .filter_map(|node| node["metadataId"].as_str())
+        .collect();
+    pages.sort();
+    assert_eq!(pages, vec!["Alpha", "Beta"]);
+    assert!(!body["edges"].as_array().unwrap().is_empty());
+}
+
+#[actix_web::test]
+async fn graph_data_is_not_modified_while_nodes_only_move() {
+    let metadata: MetadataStore = [page("Alpha", "1", &["Beta"]), page("Beta", "2", &["Alpha"])].into_iter().collect();
+    let state = test_app_state(InMemoryGitHub::new(), metadata.clone(), &[]).await;
+    let graph_service_addr = state.graph_service_addr.clone();
+    graph_service_addr
+        .send(BuildGraphFromMetadata { metadata: metadata.clone(), seed: Some(1) })
+        .await
+        .unwrap()
+        .unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .service(web::scope("/api").configure(api_handler::config)),
+    ).await;
+    let get = |etag: Option<&str>| {
+        let mut req = test::TestRequest::get().uri("/api/graph/data");
+        if let Some(etag) = etag {
+            req = req.insert_header(("If-None-Match", etag));
+        }
+        req.to_request()
+    };
+
+    let resp = test::call_service(&app, get(None)).await;
+    let etag = resp.headers().get("ETag").unwrap().to_str().unwrap().to_string();
+    let body: Value = test::read_body_json(resp).await;
+    let node_id = body["nodes"][0]["id"].as_u64().unwrap() as u32;
+    assert_eq!(test::call_service(&app, get(Some(&etag))).await.status(), 304);
+
+    // Moving a node leaves the graph's tag alone
+    graph_service_addr
+        .send(UpdateNodePosition { node_id, position: Vec3::new(5.0, 5.0, 5.0), velocity: Vec3::ZERO })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(test::call_service(&app, get(Some(&etag))).await.status(), 304);
+
+    // Rebuilding it doesn't
+    graph_service_addr
+        .send(BuildGraphFromMetadata { metadata, seed: Some(1) })
+        .await
+        .unwrap()
+        .unwrap();
+    let resp = test::call_service(&app, get(Some(&etag))).await;
+    assert_eq!(resp.status(), 200);
+    assert_ne!(resp.headers().get("ETag").unwrap().to_str().unwrap(), etag);
+}
+
+#[actix_web::test]
+async fn visibility_dry_run_reads_the_repository() {
+    let github = InMemoryGitHub::new()
+        .with_file("Published.md", "public:: true\n\n- Hello")
+        .with_file("Private.md", "- Secret");
+    let state = test_app_state(github, MetadataStore::new(), &["admin"]).await;
+    let admin_token = sign_in(&state, "admin").await;
+    let member_token = sign_in(&state, "member").await;
+    let nostr_service = state.nostr_service.clone().unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .app_data(nostr_service)
+            .service(web::scope("/api").configure(api_handler::config)),
+    ).await;
+
+    let policy = json!({ "includeProperties": { "public": "true" } });
+    let dry_run = |pubkey: &str, token: Option<&str>| {
+        let mut req = test::TestRequest::post()
+            .uri("/api/files/visibility/dry-run")
+            .insert_header(("X-Nostr-Pubkey", pubkey))
+            .set_json(&policy);
+        if let Some(token) = token {
+            req = req.insert_header(("X-Nostr-Token", token));
+        }
+        req.to_request()
+    };
+    // The report names private files, so a power user's pubkey alone isn't enough
+    assert_eq!(test::call_service(&app, dry_run("admin", None)).await.status(), 403);
+    assert_eq!(test::call_service(&app, dry_run("admin", Some(&member_token))).await.status(), 401);
+    assert_eq!(test::call_service(&app, dry_run("member", Some(&member_token))).await.status(), 403);
+
+    let body: Value = test::call_and_read_body_json(&app, dry_run("admin", Some(&admin_token))).await;
+    assert_eq!(body["includedCount"], 1);
+    assert_eq!(body["included"][0]["fileName"], "Published.md");
+    assert_eq!(body["excluded"][0]["fileName"], "Private.md");
+}
+
+#[actix_web::test]
+async fn enrichment_needs_a_power_user_session() {
+    let state = test_app_state(InMemoryGitHub::new(), MetadataStore::new(), &["admin"]).await;
+    let admin_token = sign_in(&state, "admin").await;
+    let member_token = sign_in(&state, "member").await;
+    let nostr_service = state.nostr_service.clone().unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .app_data(nostr_service)
+            .service(web::scope("/api").configure(api_handler::config)),
+    ).await;
+    let enrich = |pubkey: Option<&str>, token: Option<&str>| {
+        let mut req = test::TestRequest::post().uri("/api/files/enrich").set_json(json!({ "force": true }));
+        if let Some(pubkey) = pubkey {
+            req = req.insert_header(("X-Nostr-Pubkey", pubkey));
+        }
+        if let Some(token) = token {
+            req = req.insert_header(("X-Nostr-Token", token));
+        }
+        req.to_request()
+    };
+
+    assert_eq!(test::call_service(&app, enrich(None, None)).await.status(), 403);
+    assert_eq!(test::call_service(&app, enrich(Some("admin"), Some(&member_token))).await.status(), 401);
+    assert_eq!(test::call_service(&app, enrich(Some("member"), Some(&member_token))).await.status(), 403);
+    // The vault is empty, so a power user is told there's nothing to enrich
+    let body: Value = test::call_and_read_body_json(&app, enrich(Some("admin"), Some(&admin_token))).await;
+    assert_eq!(body["total"], 0);
+}
+
+#[actix_web::test]
+async fn link_checks_need_a_power_user_session() {
+    let state = test_app_state(InMemoryGitHub::new(), MetadataStore::new(), &["admin"]).await;
+    let admin_token = sign_in(&state, "admin").await;
+    let member_token = sign_in(&state, "member").await;
+    let nostr_service = state.nostr_service.clone().unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .app_data(nostr_service)
+            .service(web::scope("/api").configure(api_handler::config)),
+    ).await;
+    let check = |pubkey: &str, token: Option<&str>| {
+        let mut req = test::TestRequest::post().uri("/api/files/link-check").insert_header(("X-Nostr-Pubkey", pubkey));
+        if let Some(token) = token {
+            req = req.insert_header(("X-Nostr-Token", token));
+        }
+        req.to_request()
+    };
+
+    assert_eq!(test::call_service(&app, check("admin", None)).await.status(), 403);
+    assert_eq!(test::call_service(&app, check("member", Some(&member_token))).await.status(), 403);
+    assert_eq!(test::call_service(&app, check("admin", Some(&admin_token))).await.status(), 202);
+}
+
+#[actix_web::test]
+async fn pull_request_review_needs_a_power_user_session() {
+    let state = test_app_state(InMemoryGitHub::new(), MetadataStore::new(), &["admin"]).await;
+    let admin_token = sign_in(&state, "admin").await;
+    let member_token = sign_in(&state, "member").await;
+    let nostr_service = state.nostr_service.clone().unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .app_data(nostr_service)
+            .service(web::scope("/api").configure(api_handler::config)),
+    ).await;
+    let merge = |pubkey: &str, token: Option<&str>| {
+        let mut req = test::TestRequest::post().uri("/api/prs/1/merge").insert_header(("X-Nostr-Pubkey", pubkey));
+        if let Some(token) = token {
+            req = req.insert_header(("X-Nostr-Token", token));
+        }
+        req.to_request()
+    };
+
+    // Power-user pubkeys are public, so the header alone proves nothing
+    assert_eq!(test::call_service(&app, merge("admin", None)).await.status(), 403);
+    assert_eq!(test::call_service(&app, merge("admin", Some(&member_token))).await.status(), 401);
+    assert_eq!(test::call_service(&app, merge("member", Some(&member_token))).await.status(), 403);
+
+    // A power user's session gets through to GitHub, which the test can't reach
+    let list = test::TestRequest::get()
+        .uri("/api/prs")
+        .insert_header(("X-Nostr-Pubkey", "admin"))
+        .insert_header(("X-Nostr-Token", admin_token.as_str()))
+        .to_request();
+    let status = test::call_service(&app, list).await.status();
+    assert!(status != 401 && status != 403, "power user was refused with {}", status);
+}
+
+#[actix_web::test]
+async fn webhooks_need_a_power_user_session() {
+    let state = test_app_state(InMemoryGitHub::new(), MetadataStore::new(), &["admin"]).await;
+    let admin_token = sign_in(&state, "admin").await;
+    let nostr_service = state.nostr_service.clone().unwrap();
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .app_data(nostr_service)
+            .service(web::scope("/api").configure(api_handler::config)),
+    ).await;
+    let list = |token: Option<&str>| {
+        let mut req = test::TestRequest::get().uri("/api/admin/webhooks").insert_header(("X-Nostr-Pubkey", "admin"));
+        if let Some(token) = token {
+            req = req.insert_header(("X-Nostr-Token", token));
+        }
+        req.to_request()
+    };
+
+    assert_eq!(test::call_service(&app, list(None)).await.status(), 403);
+    assert_eq!(test::call_service(&app, list(Some("forged"))).await.status(), 401);
+    assert_eq!(test::call_service(&app, list(Some(&admin_token))).await.status(), 200);
+}
+
+#[actix_web::test]
+async fn cancelling_a_job_needs_a_power_user_session() {
+    let state = test_app_state(InMemoryGitHub::new(), MetadataStore::new(), &["admin"]).await;
+    let admin_token = sign_in(&state, "admin").await;
+    let member_token = sign_in(&state, "member").await;
+    let job_id = state.job_queue.submit("github_sync", |_ctx| async { futures::future::pending().await }.boxed());
+
+    let app = test::init_service(
+        App::new()
+            .app_data(web::Data::new(state))
+            .service(web::scope("/api").configure(api_handler::config)),
+    ).await;
+    let cancel = |session: Option<(&str, &str)>| {
+        let mut req = test::TestRequest::delete().uri(&format!("/api/jobs/{}", job_id));
+        if let Some((pubkey, token)) = session {
+            req = req.insert_header(("X-Nostr-Pubkey", pubkey))
+                .insert_header(("Authorization", format!("Bearer {}", token)));
+        }
+        req.to_request()
+    };
+
+    assert_eq!(test::call_service(&app, cancel(None)).await.status(), 401);
+    assert_eq!(test::call_service(&app, cancel(Some(("member", &member_token)))).await.status(), 403);
+    assert_eq!(test::call_service(&app, cancel(Some(("admin", &admin_token)))).await.status(), 202);
+}
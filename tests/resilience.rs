@@ -0,0 +1,54 @@
+//! Syncing against a repository that fails and lags on purpose
+
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use webxr::models::metadata::MetadataStore;
+use webxr::services::fault_injection::{FaultConfig, FaultInjector, FaultyGitHub};
+use webxr::services::file_service::FileService;
+use webxr::services::github::GitHubService;
+use webxr::services::sync_state::SyncState;
+use webxr::test_support::{test_settings, use_temp_data_dirs, InMemoryGitHub};
+
+const PAGES: usize = 30;
+
+#[tokio::test]
+async fn sync_recovers_from_repository_faults() {
+    // The Docker volume provides these directories in production
+    let dirs = use_temp_data_dirs();
+    std::fs::create_dir_all(dirs.markdown_file("")).unwrap();
+    std::fs::create_dir_all(dirs.metadata_file("")).unwrap();
+    let github = (0..PAGES).fold(InMemoryGitHub::new(), |github, i| {
+        github.with_file(&format!("Page {}.md", i), &format!("public:: true\n\n- Page {} links [[Page {}]]", i, (i + 1) % PAGES))
+    });
+    let faults = Arc::new(FaultInjector::new(FaultConfig::new(1.0, Duration::ZERO), Some(11)));
+    let content_api: Arc<dyn GitHubService> = Arc::new(FaultyGitHub::new(Arc::new(github), faults.clone()));
+    let settings = Arc::new(RwLock::new(test_settings()));
+    let file_service = FileService::new(settings.clone());
+    let mut metadata = MetadataStore::new();
+
+    // Listing fails, so the sync fails without touching the metadata
+    assert!(file_service.fetch_and_process_files(content_api.clone(), settings.clone(), &mut metadata).await.is_err());
+    assert!(metadata.is_empty());
+
+    // Some downloads fail; the sync keeps the rest and records the failures
+    faults.set_config(FaultConfig::new(0.3, Duration::from_millis(1)));
+    let mut partial = false;
+    for _ in 0..20 {
+        if file_service.fetch_and_process_files(content_api.clone(), settings.clone(), &mut metadata).await.is_ok() {
+            let failed = SyncState::load().failed.len();
+            partial |= failed > 0 && metadata.len() + failed == PAGES;
+        }
+        if metadata.len() == PAGES {
+            break;
+        }
+    }
+    assert!(partial, "no sync finished with only some files fetched");
+
+    // Once the repository behaves, the next sync retries what is still missing
+    faults.set_config(FaultConfig::default());
+    file_service.fetch_and_process_files(content_api, settings, &mut metadata).await.unwrap();
+    assert_eq!(metadata.len(), PAGES);
+    assert!(SyncState::load().failed.is_empty());
+    assert!(faults.injected() > 1);
+}
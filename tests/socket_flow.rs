@@ -0,0 +1,153 @@
+//! What WebSocket sessions are sent over a real connection
+
+use actix_web::{web, App, HttpServer};
+use futures_util::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+use webxr::actors::messages::{BroadcastPositionFrame, BuildGraphFromMetadata, GetPositionFrame, StartSimulation};
+use webxr::app_state::AppState;
+use webxr::handlers::socket_flow_handler::{socket_flow_handler, PreReadSocketSettings};
+use webxr::handlers::speech_socket_handler::speech_socket_handler;
+use webxr::models::metadata::{Metadata, MetadataStore};
+use webxr::test_support::{sign_in, test_app_state, InMemoryGitHub};
+use webxr_core::protocol::{split_frame, FrameKind, PROTOCOL_VERSION};
+
+type Socket = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+fn page(name: &str, node_id: &str, links: &[&str]) -> (String, Metadata) {
+    let file_name = format!("{}.md", name);
+    let metadata = Metadata {
+        file_name: file_name.clone(),
+        node_id: node_id.to_string(),
+        file_size: 100,
+        topic_counts: links.iter().map(|link| (link.to_string(), 1)).collect::<HashMap<_, _>>(),
+        ..Default::default()
+    };
+    (file_name, metadata)
+}
+
+/// Serves the graph and speech sockets over `state` on a free local port
+fn serve(state: web::Data<AppState>) -> SocketAddr {
+    let socket_settings = PreReadSocketSettings {
+        min_update_rate: 60,
+        max_update_rate: 60,
+        motion_threshold: 0.05,
+        motion_damping: 0.9,
+        heartbeat_interval_ms: 5000,
+        heartbeat_timeout_ms: 10000,
+        bounds_size: 1000.0,
+        max_velocity: 10.0,
+    };
+    let server = HttpServer::new(move || {
+        App::new()
+            .app_data(state.clone())
+            .app_data(web::Data::new(socket_settings.clone()))
+            .route("/wss", web::get().to(socket_flow_handler))
+            .route("/ws/speech", web::get().to(speech_socket_handler))
+    })
+    .workers(1)
+    .bind(("127.0.0.1", 0))
+    .unwrap();
+    let address = server.addrs()[0];
+    actix_web::rt::spawn(server.run());
+    address
+}
+
+/// Binary messages received within `window`
+async fn binary_messages(socket: &mut Socket, window: Duration) -> Vec<Vec<u8>> {
+    let mut received = Vec::new();
+    let deadline = tokio::time::sleep(window);
+    tokio::pin!(deadline);
+    loop {
+        tokio::select! {
+            _ = &mut deadline => return received,
+            message = socket.next() => match message {
+                Some(Ok(Message::Binary(data))) => received.push(data),
+                Some(Ok(_)) => {}
+                _ => return received,
+            },
+        }
+    }
+}
+
+#[actix_web::test]
+async fn sessions_only_receive_broadcaster_frames() {
+    let metadata: MetadataStore = [page("Alpha", "1", &["Beta"]), page("Beta", "2", &["Alpha"])].into_iter().collect();
+    let state = test_app_state(InMemoryGitHub::new(), metadata.clone(), &[]).await;
+    state.graph_service_addr
+        .send(BuildGraphFromMetadata { metadata, seed: Some(1) })
+        .await
+        .unwrap()
+        .unwrap();
+    // The simulation moves nodes every 16 ms from here on
+    state.graph_service_addr.send(StartSimulation).await.unwrap().unwrap();
+
+    let state = web::Data::new(state);
+    let address = serve(state.clone());
+
+    let (mut socket, _) = connect_async(format!("ws://{}/wss", address)).await.unwrap();
+    let request = json!({ "type": "requestInitialData", "protocolVersion": PROTOCOL_VERSION });
+    socket.send(Message::Text(request.to_string())).await.unwrap();
+
+    // Simulation steps alone send nothing
+    assert!(binary_messages(&mut socket, Duration::from_millis(300)).await.is_empty());
+
+    let frame = state.graph_service_addr.send(GetPositionFrame).await.unwrap().unwrap();
+    state.client_manager_addr
+        .send(BroadcastPositionFrame { frame, kinetic_energy: 0.0, detailed_debug: false })
+        .await
+        .unwrap();
+    let received = binary_messages(&mut socket, Duration::from_millis(300)).await;
+    assert_eq!(received.len(), 1);
+    let (header, _) = split_frame(&received[0]).unwrap();
+    assert_eq!(header.kind(), Some(FrameKind::Positions));
+}
+
+/// The speech socket's reply to a `setProvider` sent with `session`, a pubkey and
+/// its session token
+async fn set_provider(address: SocketAddr, session: Option<(&str, &str)>) -> Value {
+    let mut request = format!("ws://{}/ws/speech", address).into_client_request().unwrap();
+    if let Some((pubkey, token)) = session {
+        request.headers_mut().insert("X-Nostr-Pubkey", pubkey.parse().unwrap());
+        request.headers_mut().insert("Authorization", format!("Bearer {}", token).parse().unwrap());
+    }
+    let (mut socket, _) = connect_async(request).await.unwrap();
+    let message = json!({ "type": "setProvider", "provider": "kokoro" });
+    socket.send(Message::Text(message.to_string())).await.unwrap();
+    loop {
+        match socket.next().await {
+            Some(Ok(Message::Text(text))) => {
+                let reply: Value = serde_json::from_str(&text).unwrap();
+                // Every session opens with a greeting
+                if reply["type"] != "connected" {
+                    return reply;
+                }
+            }
+            Some(Ok(_)) => {}
+            other => panic!("speech socket closed without a reply: {:?}", other),
+        }
+    }
+}
+
+#[actix_web::test]
+async fn only_power_users_change_the_tts_provider() {
+    let state = test_app_state(InMemoryGitHub::new(), MetadataStore::new(), &["admin"]).await;
+    let admin_token = sign_in(&state, "admin").await;
+    let member_token = sign_in(&state, "member").await;
+    let address = serve(web::Data::new(state));
+
+    let refused = json!({ "type": "error", "message": "Only power users can change the TTS provider" });
+    assert_eq!(set_provider(address, None).await, refused);
+    assert_eq!(set_provider(address, Some(("member", &member_token))).await, refused);
+    assert_eq!(set_provider(address, Some(("admin", &member_token))).await, refused);
+
+    // The test server has no speech service, so a power user gets as far as that
+    let reply = set_provider(address, Some(("admin", &admin_token))).await;
+    assert_eq!(reply["message"], "Speech service is not available");
+}
@@ -0,0 +1,34 @@
+//! Syncing pages whose visibility changes
+
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use webxr::models::metadata::MetadataStore;
+use webxr::services::file_service::FileService;
+use webxr::services::github::GitHubService;
+use webxr::test_support::{test_settings, use_temp_data_dirs, InMemoryGitHub};
+
+#[tokio::test]
+async fn pages_made_private_leave_the_graph() {
+    // The Docker volume provides these directories in production
+    let dirs = use_temp_data_dirs();
+    std::fs::create_dir_all(dirs.markdown_file("")).unwrap();
+    std::fs::create_dir_all(dirs.metadata_file("")).unwrap();
+    let github = Arc::new(InMemoryGitHub::new()
+        .with_file("Published.md", "public:: true\n\n- Hello")
+        .with_file("Draft.md", "public:: true\n\n- Soon private"));
+    let content_api: Arc<dyn GitHubService> = github.clone();
+    let settings = Arc::new(RwLock::new(test_settings()));
+    let file_service = FileService::new(settings.clone());
+    let mut metadata = MetadataStore::new();
+
+    file_service.fetch_and_process_files(content_api.clone(), settings.clone(), &mut metadata).await.unwrap();
+    assert!(metadata.contains_key("Draft.md"));
+    assert!(dirs.markdown_file("Draft.md").exists());
+
+    github.put_file(None, "Draft.md", "- Private again");
+    file_service.fetch_and_process_files(content_api, settings, &mut metadata).await.unwrap();
+    let mut pages: Vec<&String> = metadata.keys().collect();
+    pages.sort();
+    assert_eq!(pages, vec!["Published.md"]);
+    assert!(!dirs.markdown_file("Draft.md").exists());
+}